@@ -48,6 +48,10 @@ pub enum Error {
     /// The request is not authorized to perform the requested operation.
     #[error("Forbidden")]
     Forbidden,
+    /// The request was well-formed, but there are not enough resources
+    /// available to the caller to satisfy it.
+    #[error("Insufficient Capacity: {message}")]
+    InsufficientCapacity { message: String },
 
     /// The system encountered an unhandled operational error.
     #[error("Internal Error: {internal_message}")]
@@ -116,6 +120,7 @@ impl Error {
             | Error::InvalidRequest { .. }
             | Error::InvalidValue { .. }
             | Error::Forbidden
+            | Error::InsufficientCapacity { .. }
             | Error::MethodNotAllowed { .. }
             | Error::InternalError { .. }
             | Error::TypeVersionMismatch { .. } => false,
@@ -151,6 +156,18 @@ impl Error {
         Error::InvalidRequest { message: message.to_owned() }
     }
 
+    /// Generates an [`Error::InsufficientCapacity`] error with the specific
+    /// message
+    ///
+    /// This should be used when a request cannot be satisfied because it would
+    /// exceed some limit on the resources available to the caller, such as a
+    /// quota.  Unlike [`Error::ServiceUnavailable`], retrying the request is
+    /// not expected to help until those resources are freed up or the limit
+    /// is raised.
+    pub fn insufficient_capacity(message: &str) -> Error {
+        Error::InsufficientCapacity { message: message.to_owned() }
+    }
+
     /// Generates an [`Error::ServiceUnavailable`] error with the specific
     /// message
     ///
@@ -187,7 +204,8 @@ impl Error {
             | Error::ObjectAlreadyExists { .. }
             | Error::InvalidRequest { .. }
             | Error::InvalidValue { .. }
-            | Error::Forbidden => self,
+            | Error::Forbidden
+            | Error::InsufficientCapacity { .. } => self,
             Error::Unauthenticated { internal_message } => {
                 Error::Unauthenticated {
                     internal_message: format!(
@@ -303,6 +321,13 @@ impl From<Error> for HttpError {
                 String::from("Forbidden"),
             ),
 
+            Error::InsufficientCapacity { message } => HttpError {
+                status_code: http::StatusCode::INSUFFICIENT_STORAGE,
+                error_code: Some(String::from("InsufficientCapacity")),
+                external_message: message.clone(),
+                internal_message: message,
+            },
+
             Error::InternalError { internal_message } => {
                 HttpError::for_internal_error(internal_message)
             }
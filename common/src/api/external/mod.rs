@@ -670,6 +670,7 @@ pub enum ResourceType {
     SamlIdentityProvider,
     SshKey,
     Certificate,
+    AlertRule,
     ConsoleSession,
     DeviceAuthRequest,
     DeviceAccessToken,
@@ -678,9 +679,12 @@ pub enum ResourceType {
     Project,
     Dataset,
     Disk,
+    FloatingIp,
+    AffinityGroup,
     Image,
     Instance,
     IpPool,
+    Migration,
     NetworkInterface,
     PhysicalDisk,
     Rack,
@@ -166,6 +166,35 @@ pub struct UpdatesConfig {
     pub default_base_url: String,
 }
 
+/// Configuration for the external DNS zone maintained by Nexus.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ExternalDnsConfig {
+    /// Zone in which silo and instance names are published, e.g.
+    /// "oxide.example".
+    pub zone: String,
+    /// Dropshot addresses of the DNS servers that serve the zone.
+    pub servers: Vec<SocketAddr>,
+}
+
+/// Configuration for evaluating alert rules.
+///
+/// Rules are evaluated against the timeseries database, so this should only be
+/// configured where Nexus has one.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AlertsConfig {
+    /// How often Nexus evaluates every alert rule, in seconds.
+    #[serde(default = "default_alert_evaluation_period_secs")]
+    pub evaluation_period_secs: u64,
+    /// URL to which a notification is POSTed whenever an alert fires or is
+    /// resolved.  No notifications are sent when this is unconfigured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+}
+
+fn default_alert_evaluation_period_secs() -> u64 {
+    60
+}
+
 /// Optional configuration for the timeseries database.
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct TimeseriesDbConfig {
@@ -275,6 +304,19 @@ pub struct PackageConfig {
     /// unconfigured.
     #[serde(default)]
     pub updates: Option<UpdatesConfig>,
+    /// External DNS configuration. Nexus does not publish any external names
+    /// when this is unconfigured.
+    #[serde(default)]
+    pub external_dns: Option<ExternalDnsConfig>,
+    /// Alerting configuration. Nexus does not evaluate any alert rules when
+    /// this is unconfigured.
+    #[serde(default)]
+    pub alerts: Option<AlertsConfig>,
+    /// Whether to also serve Nexus's own metrics at `/metrics` on the internal
+    /// API, in the Prometheus text exposition format, for scraping during
+    /// development.
+    #[serde(default)]
+    pub prometheus: bool,
     /// Tunable configuration for testing and experimentation
     #[serde(default)]
     pub tunables: Tunables,
@@ -346,8 +388,9 @@ impl std::fmt::Display for SchemeName {
 mod test {
     use super::Tunables;
     use super::{
-        AuthnConfig, Config, ConsoleConfig, LoadError, PackageConfig,
-        SchemeName, TimeseriesDbConfig, UpdatesConfig,
+        AlertsConfig, AuthnConfig, Config, ConsoleConfig, ExternalDnsConfig,
+        LoadError, PackageConfig, SchemeName, TimeseriesDbConfig,
+        UpdatesConfig,
     };
     use crate::address::{Ipv6Subnet, RACK_PREFIX};
     use crate::nexus_config::{Database, DeploymentConfig, LoadErrorKind};
@@ -447,6 +490,7 @@ mod test {
         let config = read_config(
             "valid",
             r##"
+            prometheus = true
             [console]
             static_dir = "tests/static"
             cache_control_max_age_minutes = 10
@@ -464,6 +508,12 @@ mod test {
             [updates]
             trusted_root = "/path/to/root.json"
             default_base_url = "http://example.invalid/"
+            [external_dns]
+            zone = "oxide.example"
+            servers = [ "[::1]:5353" ]
+            [alerts]
+            evaluation_period_secs = 30
+            webhook_url = "http://example.invalid/alerts"
             [tunables]
             max_vpc_ipv4_subnet_prefix = 27
             [deployment]
@@ -527,6 +577,17 @@ mod test {
                         trusted_root: PathBuf::from("/path/to/root.json"),
                         default_base_url: "http://example.invalid/".into(),
                     }),
+                    external_dns: Some(ExternalDnsConfig {
+                        zone: "oxide.example".into(),
+                        servers: vec!["[::1]:5353".parse().unwrap()],
+                    }),
+                    alerts: Some(AlertsConfig {
+                        evaluation_period_secs: 30,
+                        webhook_url: Some(
+                            "http://example.invalid/alerts".into()
+                        ),
+                    }),
+                    prometheus: true,
                     tunables: Tunables { max_vpc_ipv4_subnet_prefix: 27 },
                 },
             }
@@ -60,13 +60,30 @@ CREATE TABLE omicron.public.rack (
     initialized BOOL NOT NULL,
 
     /* Used to configure the updates service URL */
-    tuf_base_url STRING(512)
+    tuf_base_url STRING(512),
+
+    /*
+     * Generation of the external DNS zone contents most recently computed by
+     * any Nexus.  Each Nexus bumps this before reading the state it publishes,
+     * so that DNS servers always keep the newest contents.
+     */
+    external_dns_generation INT8 NOT NULL
 );
 
 /*
  * Sleds
  */
 
+/*
+ * Whether new resources may be placed on a sled.  Cordoned sleds keep running
+ * what they already have; draining sleds also have their Instances moved off.
+ */
+CREATE TYPE omicron.public.sled_provision_state AS ENUM (
+    'active',
+    'cordoned',
+    'draining'
+);
+
 CREATE TABLE omicron.public.sled (
     /* Identity metadata (asset) */
     id UUID PRIMARY KEY,
@@ -91,7 +108,10 @@ CREATE TABLE omicron.public.sled (
     port INT4 CHECK (port BETWEEN 0 AND 65535) NOT NULL,
 
     /* The last address allocated to an Oxide service on this sled. */
-    last_used_address INET NOT NULL
+    last_used_address INET NOT NULL,
+
+    /* Whether new Instances and regions may be placed on this sled. */
+    provision_state omicron.public.sled_provision_state NOT NULL
 );
 
 /* Add an index which lets us look up sleds on a rack */
@@ -242,7 +262,14 @@ CREATE TABLE omicron.public.virtual_provisioning_collection (
     cpus_provisioned INT8 NOT NULL,
 
     -- The amount of RAM provisioned by VMs.
-    ram_provisioned INT8 NOT NULL
+    ram_provisioned INT8 NOT NULL,
+
+    -- Quotas on the values above. Provisioning which would exceed the quota
+    -- of any collection containing the resource is refused. NULL means that
+    -- the collection is unlimited.
+    virtual_disk_bytes_limit INT8,
+    cpus_limit INT8,
+    ram_limit INT8
 );
 
 -- A table describing a single virtual resource which has been provisioned.
@@ -779,6 +806,106 @@ CREATE UNIQUE INDEX ON omicron.public.instance (
 ) WHERE
     time_deleted IS NULL;
 
+/*
+ * Live migrations of Instances between sleds
+ */
+
+CREATE TYPE omicron.public.migration_state AS ENUM (
+    'pending',
+    'in_progress',
+    'completed',
+    'failed',
+    'cancelled'
+);
+
+/*
+ * How far a migration has progressed, as reported by the sled agents running
+ * its source and target.
+ */
+CREATE TYPE omicron.public.migration_phase AS ENUM (
+    'preparing',
+    'source_ready',
+    'transferring',
+    'target_running'
+);
+
+CREATE TABLE omicron.public.migration (
+    id UUID PRIMARY KEY,
+    time_created TIMESTAMPTZ NOT NULL,
+    time_modified TIMESTAMPTZ NOT NULL,
+
+    /* The Instance being migrated. */
+    instance_id UUID NOT NULL,
+
+    state omicron.public.migration_state NOT NULL,
+    phase omicron.public.migration_phase NOT NULL,
+
+    /* The sled and propolis-server the Instance is migrating away from. */
+    source_sled_id UUID NOT NULL,
+    source_propolis_id UUID NOT NULL,
+
+    /* The sled and propolis-server the Instance is migrating to. */
+    target_sled_id UUID NOT NULL,
+    target_propolis_id UUID NOT NULL,
+
+    /* Set when the migration comes to rest in a terminal state. */
+    time_finished TIMESTAMPTZ
+);
+
+CREATE INDEX ON omicron.public.migration (
+    instance_id,
+    time_created
+);
+
+/*
+ * Affinity groups, which constrain where their member Instances are placed
+ */
+
+CREATE TYPE omicron.public.affinity_group_kind AS ENUM (
+    'affinity',
+    'anti_affinity'
+);
+
+CREATE TYPE omicron.public.affinity_policy AS ENUM (
+    'hard',
+    'soft'
+);
+
+CREATE TABLE omicron.public.affinity_group (
+    /* Identity metadata (resource) */
+    id UUID PRIMARY KEY,
+    name STRING(63) NOT NULL,
+    description STRING(512) NOT NULL,
+    time_created TIMESTAMPTZ NOT NULL,
+    time_modified TIMESTAMPTZ NOT NULL,
+    /* Indicates that the object has been deleted */
+    time_deleted TIMESTAMPTZ,
+
+    /* Every affinity group is in exactly one Project at a time. */
+    project_id UUID NOT NULL,
+
+    kind omicron.public.affinity_group_kind NOT NULL,
+    policy omicron.public.affinity_policy NOT NULL
+);
+
+CREATE UNIQUE INDEX ON omicron.public.affinity_group (
+    project_id,
+    name
+) WHERE
+    time_deleted IS NULL;
+
+CREATE TABLE omicron.public.affinity_group_instance_membership (
+    group_id UUID NOT NULL,
+    instance_id UUID NOT NULL,
+
+    PRIMARY KEY (group_id, instance_id)
+);
+
+CREATE INDEX ON omicron.public.affinity_group_instance_membership (
+    instance_id,
+    group_id
+);
+
 
 /*
  * Guest-Visible, Virtual Disks
@@ -1377,6 +1504,12 @@ CREATE TABLE omicron.public.external_ip (
     /* The last port in the allowed range, also inclusive. */
     last_port INT4 NOT NULL,
 
+    /*
+     * FK to the `project` table, for floating IPs. See the constraints
+     * below.
+     */
+    project_id UUID,
+
     /* The name must be non-NULL iff this is a floating IP. */
     CONSTRAINT null_fip_name CHECK (
         (kind != 'floating' AND name IS NULL) OR
@@ -1397,6 +1530,12 @@ CREATE TABLE omicron.public.external_ip (
         (kind != 'floating' AND instance_id IS NOT NULL) OR
         (kind = 'floating') OR
         (kind = 'service')
+    ),
+
+    /* Floating IPs, and only floating IPs, belong to a project. */
+    CONSTRAINT null_fip_project_id CHECK (
+        (kind != 'floating' AND project_id IS NULL) OR
+        (kind = 'floating' AND project_id IS NOT NULL)
     )
 );
 
@@ -1429,6 +1568,39 @@ CREATE UNIQUE INDEX ON omicron.public.external_ip (
 )
     WHERE instance_id IS NOT NULL AND time_deleted IS NULL;
 
+/*
+ * Index used to enforce uniqueness of floating IP names within a project, and
+ * to list a project's floating IPs by name.
+ */
+CREATE UNIQUE INDEX ON omicron.public.external_ip (
+    project_id,
+    name
+)
+    WHERE kind = 'floating' AND time_deleted IS NULL;
+
+/*
+ * Floating IPs are stored in the `external_ip` table, alongside all other
+ * external addresses. This view exposes them as a standalone, project-scoped
+ * resource.
+ */
+CREATE VIEW omicron.public.floating_ip AS
+SELECT
+    id,
+    name,
+    description,
+    time_created,
+    time_modified,
+    time_deleted,
+    ip_pool_id,
+    ip_pool_range_id,
+    instance_id,
+    ip,
+    project_id
+FROM
+    omicron.public.external_ip
+WHERE
+    kind = 'floating';
+
 /*******************************************************************/
 
 /*
@@ -1920,6 +2092,134 @@ CREATE TABLE omicron.public.role_assignment (
 
 /*******************************************************************/
 
+/*
+ * Audit log
+ *
+ * Each mutating request to the external API is recorded here before it's
+ * carried out, and the entry is updated with the outcome once the request
+ * completes.  An entry which is never completed describes a request whose
+ * outcome isn't known (e.g., because Nexus crashed while handling it).
+ */
+CREATE TABLE omicron.public.audit_log (
+    id UUID PRIMARY KEY,
+    time_started TIMESTAMPTZ NOT NULL,
+    request_id STRING(63) NOT NULL,
+    operation_id STRING(512) NOT NULL,
+    http_method STRING(16) NOT NULL,
+    request_uri STRING(4096) NOT NULL,
+
+    /*
+     * The actor is unset for requests which were not authenticated.  Which
+     * table "actor_id" refers to is determined by "actor_kind".
+     */
+    actor_id UUID,
+    actor_kind omicron.public.identity_type,
+    actor_silo_id UUID,
+
+    /* Set when the request completes. */
+    time_completed TIMESTAMPTZ,
+    http_status_code INT4,
+    error_message STRING
+);
+
+/* Entries are listed in the order in which the requests were received. */
+CREATE INDEX ON omicron.public.audit_log (
+    time_started,
+    id
+);
+
+/* Silo viewers may list the entries for requests made by their Silo's users. */
+CREATE INDEX ON omicron.public.audit_log (
+    actor_silo_id,
+    time_started,
+    id
+) WHERE actor_silo_id IS NOT NULL;
+
+/*******************************************************************/
+
+/*
+ * Alerts
+ *
+ * An alert rule fires when an aggregation of a timeseries in the timeseries
+ * database has crossed a threshold for some duration, and is resolved once it
+ * no longer has.  Nexus evaluates every rule periodically, recording the
+ * outcome on the rule, and each time it fires in the "alert" table.
+ */
+CREATE TYPE omicron.public.alert_comparison AS ENUM (
+    'above',
+    'below'
+);
+
+CREATE TYPE omicron.public.alert_rule_state AS ENUM (
+    'ok',
+    'firing'
+);
+
+CREATE TABLE omicron.public.alert_rule (
+    /* Identity metadata (resource) */
+    id UUID PRIMARY KEY,
+    name STRING(63) NOT NULL,
+    description STRING(512) NOT NULL,
+    time_created TIMESTAMPTZ NOT NULL,
+    time_modified TIMESTAMPTZ NOT NULL,
+    time_deleted TIMESTAMPTZ,
+
+    /* The timeseries evaluated, and the filters applied to its fields. */
+    timeseries_name STRING(128) NOT NULL,
+    criteria STRING(512)[] NOT NULL,
+
+    /* The aggregation applied to the matching timeseries, as JSON. */
+    aggregation JSONB NOT NULL,
+
+    comparison omicron.public.alert_comparison NOT NULL,
+    threshold FLOAT NOT NULL,
+    duration_secs INT8 NOT NULL,
+
+    /* The outcome of the most recent evaluation of the rule. */
+    state omicron.public.alert_rule_state NOT NULL,
+    time_state_changed TIMESTAMPTZ NOT NULL,
+    time_evaluated TIMESTAMPTZ,
+    last_value FLOAT,
+
+    /*
+     * When the rule was first found past its threshold, if it still is.  The
+     * rule fires once it has been past it for "duration_secs".
+     */
+    time_breach_started TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX ON omicron.public.alert_rule (
+    name
+) WHERE
+    time_deleted IS NULL;
+
+CREATE TABLE omicron.public.alert (
+    id UUID PRIMARY KEY,
+    alert_rule_id UUID NOT NULL,
+    time_fired TIMESTAMPTZ NOT NULL,
+
+    /* The value which crossed the threshold when the alert fired. */
+    value FLOAT NOT NULL,
+
+    /* Set when the alert is resolved. */
+    time_resolved TIMESTAMPTZ
+);
+
+/* Alerts are listed by rule, in the order in which they fired. */
+CREATE INDEX ON omicron.public.alert (
+    alert_rule_id,
+    time_fired,
+    id
+);
+
+/* Each rule has at most one alert which hasn't been resolved. */
+CREATE UNIQUE INDEX ON omicron.public.alert (
+    alert_rule_id
+) WHERE
+    time_resolved IS NULL;
+
+/*******************************************************************/
+
 /*
  * Metadata for the schema itself.  This version number isn't great, as there's
  * nothing to ensure it gets bumped when it should be, but it's a start.
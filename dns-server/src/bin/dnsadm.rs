@@ -2,14 +2,14 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::{Args, Parser, Subcommand};
 use dns_service_client::{
     types::{DnsKv, DnsRecord, DnsRecordKey, Srv},
     Client,
 };
 use slog::{Drain, Logger};
-use std::net::Ipv6Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 #[derive(Debug, Parser)]
 #[clap(name = "dnsadm", about = "Administer DNS records")]
@@ -20,18 +20,38 @@ struct Opt {
     #[clap(short, long, action)]
     port: Option<usize>,
 
+    /// The zone whose records to list or change
+    #[clap(short, long, action)]
+    zone: Option<String>,
+
     #[clap(subcommand)]
     subcommand: SubCommand,
 }
 
 #[derive(Debug, Subcommand)]
 enum SubCommand {
+    ListZones,
+    AddZone,
+    DeleteZone,
     ListRecords,
+    AddA(AddACommand),
     AddAAAA(AddAAAACommand),
+    AddCNAME(AddNameCommand),
+    AddNS(AddNameCommand),
+    AddPTR(AddNameCommand),
     AddSRV(AddSRVCommand),
+    AddTXT(AddTXTCommand),
     DeleteRecord(DeleteRecordCommand),
 }
 
+#[derive(Debug, Args)]
+struct AddACommand {
+    #[clap(action)]
+    name: String,
+    #[clap(action)]
+    addr: Ipv4Addr,
+}
+
 #[derive(Debug, Args)]
 struct AddAAAACommand {
     #[clap(action)]
@@ -54,6 +74,23 @@ struct AddSRVCommand {
     target: String,
 }
 
+/// Adds a record whose data is another name (e.g., a CNAME record)
+#[derive(Debug, Args)]
+struct AddNameCommand {
+    #[clap(action)]
+    name: String,
+    #[clap(action)]
+    target: String,
+}
+
+#[derive(Debug, Args)]
+struct AddTXTCommand {
+    #[clap(action)]
+    name: String,
+    #[clap(action)]
+    text: String,
+}
+
 #[derive(Debug, Args)]
 struct DeleteRecordCommand {
     #[clap(action)]
@@ -74,35 +111,74 @@ async fn main() -> Result<()> {
     let endpoint = format!("http://{}:{}", addr, port);
     let client = Client::new(&endpoint, log.clone());
 
+    // Every subcommand but listing the zones operates on a zone.
+    let zone = match (&opt.subcommand, opt.zone) {
+        (SubCommand::ListZones, _) => String::new(),
+        (_, Some(zone)) => zone,
+        (_, None) => return Err(anyhow!("--zone is required")),
+    };
+    let zone = zone.as_str();
+
     match opt.subcommand {
+        SubCommand::ListZones => {
+            let zones = client.dns_zones_list().await?;
+            println!("{:#?}", zones);
+        }
+        SubCommand::AddZone => {
+            client.dns_zone_create(zone).await?;
+        }
+        SubCommand::DeleteZone => {
+            client.dns_zone_delete(zone).await?;
+        }
         SubCommand::ListRecords => {
-            let records = client.dns_records_list().await?;
+            let records = client.dns_records_list(zone).await?;
             println!("{:#?}", records);
         }
+        SubCommand::AddA(cmd) => {
+            add_record(&client, zone, cmd.name, DnsRecord::A(cmd.addr)).await?;
+        }
         SubCommand::AddAAAA(cmd) => {
-            client
-                .dns_records_create(&vec![DnsKv {
-                    key: DnsRecordKey { name: cmd.name },
-                    records: vec![DnsRecord::Aaaa(cmd.addr)],
-                }])
+            add_record(&client, zone, cmd.name, DnsRecord::Aaaa(cmd.addr))
+                .await?;
+        }
+        SubCommand::AddCNAME(cmd) => {
+            add_record(&client, zone, cmd.name, DnsRecord::Cname(cmd.target))
+                .await?;
+        }
+        SubCommand::AddNS(cmd) => {
+            add_record(&client, zone, cmd.name, DnsRecord::Ns(cmd.target))
+                .await?;
+        }
+        SubCommand::AddPTR(cmd) => {
+            add_record(&client, zone, cmd.name, DnsRecord::Ptr(cmd.target))
                 .await?;
         }
         SubCommand::AddSRV(cmd) => {
             client
-                .dns_records_create(&vec![DnsKv {
-                    key: DnsRecordKey { name: cmd.name },
-                    records: vec![DnsRecord::Srv(Srv {
-                        prio: cmd.prio,
-                        weight: cmd.weight,
-                        port: cmd.port,
-                        target: cmd.target,
-                    })],
-                }])
+                .dns_records_create(
+                    zone,
+                    &vec![DnsKv {
+                        key: DnsRecordKey { name: cmd.name },
+                        records: vec![DnsRecord::Srv(Srv {
+                            prio: cmd.prio,
+                            weight: cmd.weight,
+                            port: cmd.port,
+                            target: cmd.target,
+                        })],
+                    }],
+                )
+                .await?;
+        }
+        SubCommand::AddTXT(cmd) => {
+            add_record(&client, zone, cmd.name, DnsRecord::Txt(cmd.text))
                 .await?;
         }
         SubCommand::DeleteRecord(cmd) => {
             client
-                .dns_records_delete(&vec![DnsRecordKey { name: cmd.name }])
+                .dns_records_delete(
+                    zone,
+                    &vec![DnsRecordKey { name: cmd.name }],
+                )
                 .await?;
         }
     }
@@ -110,6 +186,22 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Replaces the records for `name` (in `zone`) with `record`
+async fn add_record(
+    client: &Client,
+    zone: &str,
+    name: String,
+    record: DnsRecord,
+) -> Result<()> {
+    client
+        .dns_records_create(
+            zone,
+            &vec![DnsKv { key: DnsRecordKey { name }, records: vec![record] }],
+        )
+        .await?;
+    Ok(())
+}
+
 fn init_logger() -> Logger {
     let decorator = slog_term::TermDecorator::new().build();
     let drain = slog_term::FullFormat::new(decorator).build().fuse();
@@ -7,8 +7,13 @@
 use anyhow::Context;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use sled::transaction::TransactionError;
+use sled::Transactional;
 use slog::{error, info, o, trace};
-use std::net::Ipv6Addr;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
 use std::sync::Arc;
 
 /// Configuration related to data model
@@ -24,6 +29,231 @@ pub struct Config {
 /// default maximum number of messages to buffer
 const NMAX_MESSAGES_DEFAULT: usize = 16;
 
+/// sled tree listing the zones, each of which maps to the generation of its
+/// records
+const ZONES_TREE: &str = "zones";
+
+/// sled tree holding bookkeeping about the other trees
+const META_TREE: &str = "meta";
+
+/// Key in [`META_TREE`] of the version of the set of zones, which changes
+/// whenever a zone is created or removed
+const ZONES_VERSION_KEY: &str = "zones_version";
+
+/// sled tree in which servers that served a single zone kept its generation
+const LEGACY_METADATA_TREE: &str = "metadata";
+
+/// Key in [`LEGACY_METADATA_TREE`] of the generation of the records
+const LEGACY_GENERATION_KEY: &str = "generation";
+
+/// Returns the name of the sled tree holding the records of `zone`
+fn records_tree_name(zone: &str) -> String {
+    format!("records/{}", zone)
+}
+
+/// Returns the canonical form of the zone name `zone`: lowercase, and without
+/// a trailing dot (like the names of records)
+fn zone_name(zone: &str) -> Result<String, DataError> {
+    let zone = zone.trim_end_matches('.').to_ascii_lowercase();
+    if zone.is_empty() || trust_dns_proto::rr::Name::from_str(&zone).is_err() {
+        return Err(DataError::InvalidZoneName(zone));
+    }
+    Ok(zone)
+}
+
+/// Returns whether the record name `name` falls within `zone` (which must be
+/// in canonical form)
+fn name_in_zone(name: &str, zone: &str) -> bool {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    name == zone
+        || name
+            .strip_suffix(zone)
+            .map(|prefix| prefix.ends_with('.'))
+            .unwrap_or(false)
+}
+
+/// Parses a generation number stored in [`ZONES_TREE`]
+fn parse_generation(bits: &[u8]) -> Result<u64, anyhow::Error> {
+    let bytes = <[u8; 8]>::try_from(bits).context("generation is not a u64")?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn zones_tree(db: &sled::Db) -> Result<sled::Tree, anyhow::Error> {
+    db.open_tree(ZONES_TREE).context("open zones tree")
+}
+
+/// Returns the names of all of the zones, in canonical form
+pub fn zones(db: &sled::Db) -> Result<Vec<String>, anyhow::Error> {
+    zones_tree(db)?
+        .iter()
+        .keys()
+        .map(|key| {
+            let key = key.context("zones iteration")?;
+            let zone = std::str::from_utf8(key.as_ref())
+                .context("zone name encoding")?;
+            Ok(zone.to_string())
+        })
+        .collect()
+}
+
+/// Returns the version of the set of zones, which changes whenever a zone is
+/// created or removed
+///
+/// This lets readers cache what they know about the zones until it changes.
+pub fn zones_version(db: &sled::Db) -> Result<u64, anyhow::Error> {
+    db.open_tree(META_TREE)
+        .context("open meta tree")?
+        .get(ZONES_VERSION_KEY)
+        .context("get zones version")?
+        .map(|bits| parse_generation(bits.as_ref()))
+        .transpose()
+        .map(|version| version.unwrap_or(0))
+}
+
+/// Moves to a new version of the set of zones
+///
+/// This must be done after the set of zones changes, so that readers who see
+/// the new version also see the change.
+fn bump_zones_version(db: &sled::Db) -> Result<(), anyhow::Error> {
+    db.open_tree(META_TREE)
+        .context("open meta tree")?
+        .update_and_fetch(ZONES_VERSION_KEY, |old| {
+            let old =
+                old.and_then(|bits| parse_generation(bits).ok()).unwrap_or(0);
+            Some(old.wrapping_add(1).to_be_bytes().to_vec())
+        })
+        .context("bump zones version")?;
+    Ok(())
+}
+
+/// Returns the generation of the records of `zone`, which changes whenever a
+/// new configuration is put, or `None` if there's no such zone
+pub fn zone_generation(
+    db: &sled::Db,
+    zone: &str,
+) -> Result<Option<u64>, anyhow::Error> {
+    zones_tree(db)?
+        .get(zone.as_bytes())
+        .context("get generation")?
+        .map(|bits| parse_generation(bits.as_ref()))
+        .transpose()
+}
+
+/// Returns the tree holding the records of `zone`, which maps each name in
+/// the zone to a JSON array of its records
+///
+/// Opening the tree creates it, so this should only be used for zones that
+/// are known to exist.  Readers which may race with the zone's removal should
+/// use [`zone_records_if_exists`] instead.
+pub fn zone_records(
+    db: &sled::Db,
+    zone: &str,
+) -> Result<sled::Tree, anyhow::Error> {
+    db.open_tree(records_tree_name(zone)).context("open records tree")
+}
+
+/// Returns the tree holding the records of `zone`, or `None` if there's no
+/// such zone
+///
+/// The zone may still be removed after this checks for it, which leaves an
+/// empty records tree behind.  That's harmless: it's never read, and it's
+/// cleared if a zone of the same name is created again.
+pub fn zone_records_if_exists(
+    db: &sled::Db,
+    zone: &str,
+) -> Result<Option<sled::Tree>, anyhow::Error> {
+    if !zones_tree(db)?.contains_key(zone.as_bytes()).context("get zone")? {
+        return Ok(None);
+    }
+    zone_records(db, zone).map(Some)
+}
+
+/// Creates `zone` with no records, if it doesn't already exist
+///
+/// This is normally done by the data model server, but it's also done before
+/// that's started for the zone that the server is configured with.
+pub(crate) fn create_zone(db: &sled::Db, zone: &str) -> Result<(), DataError> {
+    let zone = zone_name(zone)?;
+    let zones = zones_tree(db)?;
+    if zones.contains_key(zone.as_bytes()).context("get zone")? {
+        return Ok(());
+    }
+
+    // Clear out any records left behind by an earlier zone of the same name
+    // whose removal didn't finish.
+    zone_records(db, &zone)?.clear().context("clear records")?;
+    zones
+        .insert(zone.as_bytes(), 0u64.to_be_bytes().to_vec())
+        .context("insert zone")?;
+    bump_zones_version(db)?;
+    Ok(())
+}
+
+/// Moves any records left in the default tree by a server that served only a
+/// single zone into the tree of `zone`, along with their generation
+///
+/// This must be done before the servers are started.  Once it's been done,
+/// there's nothing left to move, so it's cheap to do every time.
+pub(crate) fn migrate_legacy_records(
+    db: &sled::Db,
+    zone: &str,
+) -> Result<(), DataError> {
+    let zone = zone_name(zone)?;
+    let legacy_generation = if db
+        .tree_names()
+        .iter()
+        .any(|name| name.as_ref() == LEGACY_METADATA_TREE.as_bytes())
+    {
+        db.open_tree(LEGACY_METADATA_TREE)
+            .context("open legacy metadata tree")?
+            .get(LEGACY_GENERATION_KEY)
+            .context("get legacy generation")?
+            .map(|bits| parse_generation(bits.as_ref()))
+            .transpose()?
+    } else {
+        None
+    };
+    if db.is_empty() && legacy_generation.is_none() {
+        return Ok(());
+    }
+
+    let mut moved = sled::Batch::default();
+    let mut removed = sled::Batch::default();
+    for entry in db.iter() {
+        let (name, bits) = entry.context("legacy records iteration")?;
+        moved.insert(name.clone(), bits);
+        removed.remove(name);
+    }
+    let Some(current) = zone_generation(db, &zone)? else {
+        return Err(DataError::NoSuchZone(zone));
+    };
+    let generation = current.max(legacy_generation.unwrap_or(0));
+
+    let default_tree: &sled::Tree = db;
+    let records = zone_records(db, &zone)?;
+    let zones = zones_tree(db)?;
+    let result: Result<(), TransactionError<Infallible>> = (
+        default_tree,
+        &records,
+        &zones,
+    )
+        .transaction(|(default_tree, records, zones)| {
+            records.apply_batch(&moved)?;
+            default_tree.apply_batch(&removed)?;
+            zones.insert(zone.as_bytes(), generation.to_be_bytes().to_vec())?;
+            Ok(())
+        });
+    match result {
+        Ok(()) => (),
+        Err(TransactionError::Abort(never)) => match never {},
+        Err(TransactionError::Storage(e)) => {
+            return Err(anyhow::Error::new(e).context("db transaction").into())
+        }
+    }
+    db.drop_tree(LEGACY_METADATA_TREE).context("drop legacy metadata tree")?;
+    Ok(())
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
@@ -46,8 +276,19 @@ pub struct SRV {
 #[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
 #[serde(tag = "type", content = "data")]
 pub enum DnsRecord {
+    A(Ipv4Addr),
     AAAA(Ipv6Addr),
+    /// The name is an alias for the given (canonical) name
+    CNAME(String),
+    /// The given name is an authoritative name server for this name's zone
+    NS(String),
+    /// The given name is the canonical name for this one, which is usually
+    /// an address in a reverse-lookup zone
+    PTR(String),
     SRV(SRV),
+    /// Arbitrary text.  Strings longer than 255 bytes are split into several
+    /// character-strings on the wire.
+    TXT(String),
 }
 #[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct DnsRecordKey {
@@ -65,20 +306,80 @@ pub struct DnsKV {
     records: Vec<DnsRecord>,
 }
 
-// XXX some refactors to help
-// - each variant should have its own struct containing the data.  This way we
-//   can pass it to functions as a bundle without them having to consume the
-//   whole enum (which might in principle be a different variant)
-// - each variant's data should include some generic responder<T> so that we can
-//   have common functions for logging and sending the T
+/// The complete set of records in a zone, as of some generation
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DnsConfig {
+    /// Generation number of this configuration
+    ///
+    /// Each new configuration must have a higher generation than the last, so
+    /// that servers can tell whether they're up to date and reject updates
+    /// which are out of date.
+    pub generation: u64,
+    pub records: Vec<DnsKV>,
+}
+
+/// Describes why a request of the data model server failed
+#[derive(Debug)]
+pub enum DataError {
+    /// The named zone doesn't exist
+    NoSuchZone(String),
+    /// The name given for a zone isn't a valid DNS name
+    InvalidZoneName(String),
+    /// A record was given for a name outside of the zone it was given for
+    NameOutsideZone {
+        name: String,
+        zone: String,
+    },
+    /// The server already has a newer configuration for the zone
+    StaleGeneration {
+        current: u64,
+        requested: u64,
+    },
+    Internal(anyhow::Error),
+}
+
+impl std::fmt::Display for DataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataError::NoSuchZone(zone) => {
+                write!(f, "no such zone: '{}'", zone)
+            }
+            DataError::InvalidZoneName(zone) => {
+                write!(f, "invalid zone name: '{}'", zone)
+            }
+            DataError::NameOutsideZone { name, zone } => {
+                write!(f, "name '{}' is not in zone '{}'", name, zone)
+            }
+            DataError::StaleGeneration { current, requested } => write!(
+                f,
+                "requested generation {} is older than current generation {}",
+                requested, current
+            ),
+            DataError::Internal(error) => write!(f, "{:#}", error),
+        }
+    }
+}
+
+impl From<anyhow::Error> for DataError {
+    fn from(error: anyhow::Error) -> Self {
+        DataError::Internal(error)
+    }
+}
+
 #[derive(Debug)]
 pub enum DnsCmd {
-    // XXX
-    // MakeExist(DnsRecord, DnsResponse<()>),
-    // MakeGone(DnsRecordKey, DnsResponse<()>),
-    Get(Option<DnsRecordKey>, DnsResponse<Vec<DnsKV>>),
-    Set(Vec<DnsKV>, DnsResponse<()>),
-    Delete(Vec<DnsRecordKey>, DnsResponse<()>),
+    ListZones(DnsResponse<Result<Vec<String>, DataError>>),
+    CreateZone(String, DnsResponse<Result<(), DataError>>),
+    DeleteZone(String, DnsResponse<Result<(), DataError>>),
+    Get(
+        String,
+        Option<DnsRecordKey>,
+        DnsResponse<Result<Vec<DnsKV>, DataError>>,
+    ),
+    Set(String, Vec<DnsKV>, DnsResponse<Result<(), DataError>>),
+    Delete(String, Vec<DnsRecordKey>, DnsResponse<Result<(), DataError>>),
+    GetConfig(String, DnsResponse<Result<DnsConfig, DataError>>),
+    PutConfig(String, DnsConfig, DnsResponse<Result<(), DataError>>),
 }
 
 /// Data model client
@@ -107,43 +408,102 @@ impl Client {
         Client { log, sender }
     }
 
-    // XXX error type needs to be rich enough for appropriate HTTP response
-    pub async fn get_records(
+    /// Sends the command made by `make_cmd` to the data model server and
+    /// waits for its response
+    async fn request<T>(
         &self,
-        key: Option<DnsRecordKey>,
-    ) -> Result<Vec<DnsKV>, anyhow::Error> {
-        slog::trace!(&self.log, "get_records"; "key" => ?key);
+        make_cmd: impl FnOnce(DnsResponse<Result<T, DataError>>) -> DnsCmd,
+    ) -> Result<T, DataError> {
         let (tx, rx) = tokio::sync::oneshot::channel();
         self.sender
-            .try_send(DnsCmd::Get(key, DnsResponse { tx }))
+            .try_send(make_cmd(DnsResponse { tx }))
             .context("send message")?;
-        rx.await.context("recv response")
+        rx.await.context("recv response")?
+    }
+
+    /// Returns the names of all of the zones
+    pub async fn list_zones(&self) -> Result<Vec<String>, DataError> {
+        slog::trace!(&self.log, "list_zones");
+        self.request(DnsCmd::ListZones).await
+    }
+
+    /// Creates `zone` with no records, if it doesn't already exist
+    pub async fn create_zone(&self, zone: String) -> Result<(), DataError> {
+        slog::trace!(&self.log, "create_zone"; "zone" => &zone);
+        self.request(|response| DnsCmd::CreateZone(zone, response)).await
+    }
+
+    /// Removes `zone` along with all of its records
+    pub async fn delete_zone(&self, zone: String) -> Result<(), DataError> {
+        slog::trace!(&self.log, "delete_zone"; "zone" => &zone);
+        self.request(|response| DnsCmd::DeleteZone(zone, response)).await
+    }
+
+    pub async fn get_records(
+        &self,
+        zone: String,
+        key: Option<DnsRecordKey>,
+    ) -> Result<Vec<DnsKV>, DataError> {
+        slog::trace!(&self.log, "get_records"; "zone" => &zone, "key" => ?key);
+        self.request(|response| DnsCmd::Get(zone, key, response)).await
     }
 
-    // XXX error type needs to be rich enough for appropriate HTTP response
     pub async fn set_records(
         &self,
+        zone: String,
         records: Vec<DnsKV>,
-    ) -> Result<(), anyhow::Error> {
-        slog::trace!(&self.log, "set_records"; "records" => ?records);
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        self.sender
-            .try_send(DnsCmd::Set(records, DnsResponse { tx }))
-            .context("send message")?;
-        rx.await.context("recv response")
+    ) -> Result<(), DataError> {
+        slog::trace!(
+            &self.log,
+            "set_records";
+            "zone" => &zone,
+            "records" => ?records
+        );
+        self.request(|response| DnsCmd::Set(zone, records, response)).await
     }
 
-    // XXX error type needs to be rich enough for appropriate HTTP response
     pub async fn delete_records(
         &self,
+        zone: String,
         records: Vec<DnsRecordKey>,
-    ) -> Result<(), anyhow::Error> {
-        slog::trace!(&self.log, "delete_records"; "records" => ?records);
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        self.sender
-            .try_send(DnsCmd::Delete(records, DnsResponse { tx }))
-            .context("send message")?;
-        rx.await.context("recv response")
+    ) -> Result<(), DataError> {
+        slog::trace!(
+            &self.log,
+            "delete_records";
+            "zone" => &zone,
+            "records" => ?records
+        );
+        self.request(|response| DnsCmd::Delete(zone, records, response)).await
+    }
+
+    /// Returns the current configuration of `zone`, including its generation
+    pub async fn get_config(
+        &self,
+        zone: String,
+    ) -> Result<DnsConfig, DataError> {
+        slog::trace!(&self.log, "get_config"; "zone" => &zone);
+        self.request(|response| DnsCmd::GetConfig(zone, response)).await
+    }
+
+    /// Atomically replaces the current configuration of `zone` with `config`
+    ///
+    /// `config` must be at least as new as the current configuration.  If it
+    /// has the same generation and the zone already has exactly its records,
+    /// nothing changes, which makes it safe to retry.  If the records differ
+    /// (because individual records were changed since, which leaves the
+    /// generation alone), `config` replaces them.
+    pub async fn put_config(
+        &self,
+        zone: String,
+        config: DnsConfig,
+    ) -> Result<(), DataError> {
+        slog::trace!(
+            &self.log,
+            "put_config";
+            "zone" => &zone,
+            "config" => ?config
+        );
+        self.request(|response| DnsCmd::PutConfig(zone, config, response)).await
     }
 }
 
@@ -162,20 +522,38 @@ async fn data_server(mut server: Server) {
 
         trace!(log, "rx message"; "message" => ?msg);
         match msg {
-            DnsCmd::Get(key, response) => {
-                server.cmd_get_records(key, response).await;
+            DnsCmd::ListZones(response) => {
+                server.respond(response, server.list_zones());
+            }
+            DnsCmd::CreateZone(zone, response) => {
+                server.respond(response, create_zone(&server.db, &zone));
             }
-            DnsCmd::Set(records, response) => {
-                server.cmd_set_records(records, response).await;
+            DnsCmd::DeleteZone(zone, response) => {
+                server.respond(response, server.delete_zone(&zone));
             }
-            DnsCmd::Delete(records, response) => {
-                server.cmd_delete_records(records, response).await;
+            DnsCmd::Get(zone, key, response) => {
+                server.respond(response, server.get_records(&zone, key));
+            }
+            DnsCmd::Set(zone, records, response) => {
+                server.respond(response, server.set_records(&zone, records));
+            }
+            DnsCmd::Delete(zone, records, response) => {
+                server.respond(response, server.delete_records(&zone, records));
+            }
+            DnsCmd::GetConfig(zone, response) => {
+                server.respond(response, server.get_config(&zone));
+            }
+            DnsCmd::PutConfig(zone, config, response) => {
+                server.respond(response, server.put_config(&zone, config));
             }
         }
     }
 }
 
 /// Data model server
+///
+/// This is the only thing that modifies the records once it's running, so it
+/// can rely on them not changing between reading and writing them.
 pub struct Server {
     log: slog::Logger,
     receiver: tokio::sync::mpsc::Receiver<DnsCmd>,
@@ -183,175 +561,215 @@ pub struct Server {
 }
 
 impl Server {
-    async fn cmd_get_records(
+    fn respond<T: std::fmt::Debug>(&self, response: DnsResponse<T>, value: T) {
+        if let Err(e) = response.tx.send(value) {
+            error!(self.log, "response tx: {:?}", e);
+        }
+    }
+
+    fn list_zones(&self) -> Result<Vec<String>, DataError> {
+        Ok(zones(&self.db)?)
+    }
+
+    fn delete_zone(&self, zone: &str) -> Result<(), DataError> {
+        let zone = zone_name(zone)?;
+        let removed = zones_tree(&self.db)?
+            .remove(zone.as_bytes())
+            .context("remove zone")?;
+        if removed.is_none() {
+            return Err(DataError::NoSuchZone(zone));
+        }
+        self.db
+            .drop_tree(records_tree_name(&zone))
+            .context("drop records tree")?;
+        bump_zones_version(&self.db)?;
+        Ok(())
+    }
+
+    /// Returns the canonical name of `zone`, its current generation, and the
+    /// tree holding its records
+    fn zone(&self, zone: &str) -> Result<(String, u64, sled::Tree), DataError> {
+        let zone = zone_name(zone)?;
+        let Some(generation) = zone_generation(&self.db, &zone)? else {
+            return Err(DataError::NoSuchZone(zone));
+        };
+        let records = zone_records(&self.db, &zone)?;
+        Ok((zone, generation, records))
+    }
+
+    /// Applies `batch` to the records of `zone` and moves the zone to
+    /// `generation`, all at once
+    fn apply_batch(
+        &self,
+        zone: &str,
+        records: &sled::Tree,
+        batch: sled::Batch,
+        generation: u64,
+    ) -> Result<(), DataError> {
+        let zones = zones_tree(&self.db)?;
+        let result: Result<(), TransactionError<Infallible>> =
+            (records, &zones).transaction(|(records, zones)| {
+                records.apply_batch(&batch)?;
+                zones.insert(
+                    zone.as_bytes(),
+                    generation.to_be_bytes().to_vec(),
+                )?;
+                Ok(())
+            });
+        match result {
+            Ok(()) => Ok(()),
+            Err(TransactionError::Abort(never)) => match never {},
+            Err(TransactionError::Storage(e)) => {
+                Err(anyhow::Error::new(e).context("db transaction").into())
+            }
+        }
+    }
+
+    fn get_records(
         &self,
+        zone: &str,
         key: Option<DnsRecordKey>,
-        response: DnsResponse<Vec<DnsKV>>,
-    ) {
+    ) -> Result<Vec<DnsKV>, DataError> {
+        let (_, _, records) = self.zone(zone)?;
+
         // If a key is provided search just for that key. Otherwise return all
         // the db entries.
-        if let Some(key) = key {
-            let bits = match self.db.get(key.name.as_bytes()) {
-                Ok(Some(bits)) => bits,
-                _ => {
-                    match response.tx.send(Vec::new()) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            error!(self.log, "response tx: {:?}", e);
-                        }
-                    }
-                    return;
-                }
-            };
-            let records: Vec<DnsRecord> =
-                match serde_json::from_slice(bits.as_ref()) {
-                    Ok(r) => r,
-                    Err(e) => {
-                        error!(self.log, "deserialize record: {}", e);
-                        match response.tx.send(Vec::new()) {
-                            Ok(_) => {}
-                            Err(e) => {
-                                error!(self.log, "response tx: {:?}", e);
-                            }
-                        }
-                        return;
-                    }
-                };
-            match response.tx.send(vec![DnsKV { key, records }]) {
-                Ok(_) => {}
-                Err(e) => {
-                    error!(self.log, "response tx: {:?}", e);
-                }
-            }
-        } else {
-            let mut result = Vec::new();
-            let mut iter = self.db.iter();
-            loop {
-                match iter.next() {
-                    Some(Ok((k, v))) => {
-                        let records: Vec<DnsRecord> =
-                            match serde_json::from_slice(v.as_ref()) {
-                                Ok(r) => r,
-                                Err(e) => {
-                                    error!(
-                                        self.log,
-                                        "deserialize record: {}", e
-                                    );
-                                    match response.tx.send(Vec::new()) {
-                                        Ok(_) => {}
-                                        Err(e) => {
-                                            error!(
-                                                self.log,
-                                                "response tx: {:?}", e
-                                            );
-                                        }
-                                    }
-                                    return;
-                                }
-                            };
-                        let key = match std::str::from_utf8(k.as_ref()) {
-                            Ok(s) => s.to_string(),
-                            Err(e) => {
-                                error!(self.log, "key encoding: {}", e);
-                                match response.tx.send(Vec::new()) {
-                                    Ok(_) => {}
-                                    Err(e) => {
-                                        error!(
-                                            self.log,
-                                            "response tx: {:?}", e
-                                        );
-                                    }
-                                }
-                                return;
-                            }
-                        };
-                        result.push(DnsKV {
-                            key: DnsRecordKey { name: key },
-                            records,
-                        });
-                    }
-                    Some(Err(e)) => {
-                        error!(self.log, "db iteration error: {}", e);
-                        break;
-                    }
-                    None => break,
-                }
-            }
-            match response.tx.send(result) {
-                Ok(_) => {}
-                Err(e) => {
-                    error!(self.log, "response tx: {:?}", e);
-                }
-            }
-        }
+        let Some(key) = key else {
+            return read_records(&records);
+        };
+        let Some(bits) = records.get(key.name.as_bytes()).context("db get")?
+        else {
+            return Ok(Vec::new());
+        };
+        let records = serde_json::from_slice(bits.as_ref())
+            .context("deserialize record")?;
+        Ok(vec![DnsKV { key, records }])
     }
 
-    async fn cmd_set_records(
+    // Changes to individual records leave the zone's generation alone.
+    // Generations are chosen by whoever puts whole configurations, and any
+    // number we picked here could collide with one of theirs for different
+    // contents.
+
+    fn set_records(
         &self,
+        zone: &str,
         records: Vec<DnsKV>,
-        response: DnsResponse<()>,
-    ) {
-        for kv in records {
-            let bits = match serde_json::to_string(&kv.records) {
-                Ok(bits) => bits,
-                Err(e) => {
-                    error!(self.log, "serialize record: {}", e);
-                    match response.tx.send(()) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            error!(self.log, "response tx: {:?}", e);
-                        }
-                    }
-                    return;
-                }
-            };
-            match self.db.insert(kv.key.name.as_bytes(), bits.as_bytes()) {
-                Ok(_) => {}
-                Err(e) => {
-                    error!(self.log, "db insert: {}", e);
-                    match response.tx.send(()) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            error!(self.log, "response tx: {:?}", e);
-                        }
-                    }
-                    return;
-                }
-            }
-        }
-        match response.tx.send(()) {
-            Ok(_) => {}
-            Err(e) => {
-                error!(self.log, "response tx: {:?}", e);
-            }
-        }
+    ) -> Result<(), DataError> {
+        let (zone, _, tree) = self.zone(zone)?;
+        let batch = records_batch(&zone, &records)?;
+        tree.apply_batch(batch).context("apply batch")?;
+        Ok(())
     }
 
-    async fn cmd_delete_records(
+    fn delete_records(
         &self,
+        zone: &str,
         records: Vec<DnsRecordKey>,
-        response: DnsResponse<()>,
-    ) {
-        for k in records {
-            match self.db.remove(k.name.as_bytes()) {
-                Ok(_) => {}
-                Err(e) => {
-                    error!(self.log, "db delete: {}", e);
-                    match response.tx.send(()) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            error!(self.log, "response tx: {:?}", e);
-                        }
-                    }
-                    return;
-                }
-            }
+    ) -> Result<(), DataError> {
+        let (_, _, tree) = self.zone(zone)?;
+        let mut batch = sled::Batch::default();
+        for key in records {
+            batch.remove(key.name.as_bytes());
         }
-        match response.tx.send(()) {
-            Ok(_) => {}
-            Err(e) => {
-                error!(self.log, "response tx: {:?}", e);
+        tree.apply_batch(batch).context("apply batch")?;
+        Ok(())
+    }
+
+    fn get_config(&self, zone: &str) -> Result<DnsConfig, DataError> {
+        let (_, generation, records) = self.zone(zone)?;
+        let records = read_records(&records)?;
+        Ok(DnsConfig { generation, records })
+    }
+
+    fn put_config(
+        &self,
+        zone: &str,
+        config: DnsConfig,
+    ) -> Result<(), DataError> {
+        let (zone, current, tree) = self.zone(zone)?;
+        if config.generation < current {
+            return Err(DataError::StaleGeneration {
+                current,
+                requested: config.generation,
+            });
+        }
+
+        // Changes to individual records don't move the zone to a new
+        // generation, so the zone may be at this generation without having
+        // these contents.  Only if it has them is there nothing to do.
+        let entries = records_batch_entries(&zone, &config.records)?;
+        if config.generation == current {
+            let wanted: BTreeMap<Vec<u8>, Vec<u8>> =
+                entries.iter().cloned().collect();
+            let stored = tree
+                .iter()
+                .map(|entry| entry.map(|(k, v)| (k.to_vec(), v.to_vec())))
+                .collect::<Result<BTreeMap<_, _>, _>>()
+                .context("db iteration")?;
+            if wanted == stored {
+                return Ok(());
             }
         }
+
+        // Remove every name that's there now before (re)adding those in the
+        // new configuration.  Later operations in a batch win.
+        let mut batch = sled::Batch::default();
+        for name in tree.iter().keys() {
+            batch.remove(name.context("list names")?);
+        }
+        for (name, bits) in entries {
+            batch.insert(name, bits);
+        }
+        self.apply_batch(&zone, &tree, batch, config.generation)
+    }
+}
+
+/// Reads all of the records in `tree`
+fn read_records(tree: &sled::Tree) -> Result<Vec<DnsKV>, DataError> {
+    tree.iter()
+        .map(|entry| {
+            let (k, v) = entry.context("db iteration")?;
+            let name = std::str::from_utf8(k.as_ref())
+                .context("key encoding")?
+                .to_string();
+            let records = serde_json::from_slice(v.as_ref())
+                .context("deserialize record")?;
+            Ok(DnsKV { key: DnsRecordKey { name }, records })
+        })
+        .collect()
+}
+
+/// Returns the keys and values to store for each of `records`, which must all
+/// be in `zone`
+fn records_batch_entries(
+    zone: &str,
+    records: &[DnsKV],
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>, DataError> {
+    records
+        .iter()
+        .map(|kv| {
+            if !name_in_zone(&kv.key.name, zone) {
+                return Err(DataError::NameOutsideZone {
+                    name: kv.key.name.clone(),
+                    zone: zone.to_string(),
+                });
+            }
+            let bits =
+                serde_json::to_vec(&kv.records).context("serialize record")?;
+            Ok((kv.key.name.as_bytes().to_vec(), bits))
+        })
+        .collect()
+}
+
+/// Returns a batch which stores each of `records`, which must all be in `zone`
+fn records_batch(
+    zone: &str,
+    records: &[DnsKV],
+) -> Result<sled::Batch, DataError> {
+    let mut batch = sled::Batch::default();
+    for (name, bits) in records_batch_entries(zone, records)? {
+        batch.insert(name, bits);
     }
+    Ok(batch)
 }
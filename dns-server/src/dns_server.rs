@@ -5,256 +5,727 @@
 use std::io::Result;
 use std::net::SocketAddr;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::dns_data::DnsRecord;
+use crate::metrics::{Metrics, QueryOutcome};
+use anyhow::Context;
 use pretty_hex::*;
 use serde::Deserialize;
 use slog::{error, Logger};
-use tokio::net::UdpSocket;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
 use trust_dns_client::rr::LowerName;
+use trust_dns_proto::error::ProtoResult;
 use trust_dns_proto::op::header::Header;
 use trust_dns_proto::op::response_code::ResponseCode;
-use trust_dns_proto::rr::rdata::SRV;
+use trust_dns_proto::op::Edns;
+use trust_dns_proto::rr::rdata::{SOA, SRV, TXT};
 use trust_dns_proto::rr::record_data::RData;
 use trust_dns_proto::rr::record_type::RecordType;
 use trust_dns_proto::rr::{Name, Record};
 use trust_dns_proto::serialize::binary::{
     BinDecodable, BinDecoder, BinEncoder,
 };
-use trust_dns_server::authority::{MessageRequest, MessageResponseBuilder};
+use trust_dns_server::authority::{
+    MessageRequest, MessageResponse, MessageResponseBuilder,
+};
+
+/// The largest UDP payload we accept, which is advertised to clients that use
+/// EDNS.  This is also the most we'll send them, whatever they advertise.
+const MAX_UDP_PAYLOAD: u16 = 4096;
+
+/// The largest UDP payload that every client must accept (RFC 1035).  This is
+/// the most we'll send to clients that don't use EDNS.
+const MIN_UDP_PAYLOAD: u16 = 512;
+
+/// How long a TCP connection may sit idle before we close it
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most CNAME records we'll follow when answering a query, which keeps
+/// loops of them from being a problem
+const MAX_CNAME_CHAIN: usize = 8;
+
+// Timers (in seconds) for the zone's SOA record.  Our records have a TTL of
+// zero, and so does the SOA record's minimum, which is used as the TTL for
+// negative responses.
+const SOA_REFRESH: i32 = 3600;
+const SOA_RETRY: i32 = 600;
+const SOA_EXPIRE: i32 = 18000;
+const SOA_MINIMUM: u32 = 0;
 
 /// Configuration related to the DNS server
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
-    /// The address to listen for DNS requests on
+    /// The address to listen for DNS requests on (over both UDP and TCP)
     pub bind_address: String,
-
-    /// The DNS zone this server presides over. This must be a valid DNS name
-    pub zone: String,
 }
 
 pub struct Server {
     pub address: SocketAddr,
+    pub metrics: Metrics,
     pub handle: tokio::task::JoinHandle<Result<()>>,
+    pub tcp_handle: tokio::task::JoinHandle<Result<()>>,
 }
 
 impl Drop for Server {
     fn drop(&mut self) {
-        self.handle.abort()
+        self.handle.abort();
+        self.tcp_handle.abort();
     }
 }
 
+/// The transport over which a request was received
+#[derive(Clone, Copy, Debug)]
+enum Transport {
+    Udp,
+    Tcp,
+}
+
+impl Transport {
+    fn label(self) -> &'static str {
+        match self {
+            Transport::Udp => "udp",
+            Transport::Tcp => "tcp",
+        }
+    }
+}
+
+/// An encoded response, along with its response code
+struct Response {
+    data: Vec<u8>,
+    code: ResponseCode,
+}
+
 pub async fn run(
     log: Logger,
     db: Arc<sled::Db>,
     config: Config,
+    metrics: Metrics,
 ) -> Result<Server> {
     let socket = Arc::new(UdpSocket::bind(config.bind_address).await?);
     let address = socket.local_addr()?;
 
-    let handle = tokio::task::spawn(async move {
-        loop {
-            let mut buf = vec![0u8; 16384];
-            let (n, src) = socket.recv_from(&mut buf).await?;
-            buf.resize(n, 0);
+    // Clients retry truncated UDP responses over TCP on the same port, so
+    // listen there even if we were asked to pick any port.
+    let listener = TcpListener::bind(address).await?;
 
-            let socket = socket.clone();
-            let log = log.clone();
-            let db = db.clone();
-            let zone = config.zone.clone();
+    let zones = Arc::new(Zones::new(db));
 
-            tokio::spawn(async move {
-                handle_req(log, db, socket, src, buf, zone).await
-            });
-        }
-    });
+    let handle = {
+        let log = log.clone();
+        let zones = zones.clone();
+        let metrics = metrics.clone();
+        tokio::task::spawn(async move {
+            loop {
+                let mut buf = vec![0u8; usize::from(MAX_UDP_PAYLOAD)];
+                let (n, src) = socket.recv_from(&mut buf).await?;
+                buf.resize(n, 0);
 
-    Ok(Server { address, handle })
+                let socket = socket.clone();
+                let log = log.clone();
+                let zones = zones.clone();
+                let metrics = metrics.clone();
+
+                tokio::spawn(async move {
+                    let Some(resp_data) = handle_req(
+                        &log,
+                        &zones,
+                        &metrics,
+                        &buf,
+                        Transport::Udp,
+                    )
+                    else {
+                        return;
+                    };
+                    if let Err(e) = socket.send_to(&resp_data, &src).await {
+                        error!(log, "send: {}", e);
+                    }
+                });
+            }
+        })
+    };
+
+    let tcp_handle = {
+        let metrics = metrics.clone();
+        tokio::task::spawn(async move {
+            loop {
+                let (stream, src) = listener.accept().await?;
+
+                let log = log.clone();
+                let zones = zones.clone();
+                let metrics = metrics.clone();
+
+                tokio::spawn(async move {
+                    if let Err(e) =
+                        handle_tcp_conn(&log, &zones, &metrics, stream).await
+                    {
+                        error!(log, "tcp connection from {}: {}", src, e);
+                    }
+                });
+            }
+        })
+    };
+
+    Ok(Server { address, metrics, handle, tcp_handle })
 }
 
-async fn respond_nxdomain(
+/// Handles the requests made over a TCP connection until the client closes it
+/// or leaves it idle for too long
+async fn handle_tcp_conn(
     log: &Logger,
-    socket: Arc<UdpSocket>,
-    src: SocketAddr,
-    rb: MessageResponseBuilder<'_>,
-    header: Header,
-    mr: &MessageRequest,
-) {
-    let mresp = rb.error_msg(&header, ResponseCode::NXDomain);
+    zones: &Zones,
+    metrics: &Metrics,
+    mut stream: TcpStream,
+) -> Result<()> {
+    loop {
+        // Each message is preceded by its length (RFC 1035 section 4.2.2).
+        let len =
+            match tokio::time::timeout(TCP_IDLE_TIMEOUT, stream.read_u16())
+                .await
+            {
+                Err(_) => return Ok(()),
+                Ok(Err(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return Ok(());
+                }
+                Ok(result) => result?,
+            };
+        // A client that stalls partway through a message is treated like an
+        // idle one, rather than holding the connection open indefinitely.
+        let mut buf = vec![0u8; usize::from(len)];
+        tokio::time::timeout(TCP_IDLE_TIMEOUT, stream.read_exact(&mut buf))
+            .await
+            .map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "timed out reading message",
+                )
+            })??;
+
+        let Some(resp_data) =
+            handle_req(log, zones, metrics, &buf, Transport::Tcp)
+        else {
+            continue;
+        };
+        // Responses are encoded with a maximum size of `u16::MAX` when sent
+        // over TCP, so the length always fits.
+        let resp_len = u16::try_from(resp_data.len()).unwrap();
+        stream.write_u16(resp_len).await?;
+        stream.write_all(&resp_data).await?;
+    }
+}
+
+/// Encodes a response, truncating it (and setting the TC bit) if it's larger
+/// than `max_size` bytes
+fn encode_response<'q, 'a, A, N, S, D>(
+    mresp: MessageResponse<'q, 'a, A, N, S, D>,
+    max_size: u16,
+) -> ProtoResult<Vec<u8>>
+where
+    A: Iterator<Item = &'a Record> + Send + 'a,
+    N: Iterator<Item = &'a Record> + Send + 'a,
+    S: Iterator<Item = &'a Record> + Send + 'a,
+    D: Iterator<Item = &'a Record> + Send + 'a,
+{
     let mut resp_data = Vec::new();
     let mut enc = BinEncoder::new(&mut resp_data);
-    match mresp.destructive_emit(&mut enc) {
-        Ok(_) => {}
+    enc.set_max_size(max_size);
+    mresp.destructive_emit(&mut enc)?;
+    Ok(resp_data)
+}
+
+fn respond_nxdomain(
+    log: &Logger,
+    rb: MessageResponseBuilder<'_>,
+    mut header: Header,
+    soa: &Record,
+    mr: &MessageRequest,
+    max_size: u16,
+) -> Option<Response> {
+    header.set_response_code(ResponseCode::NXDomain);
+    let mresp = rb.build(header, vec![], vec![], vec![soa], vec![]);
+    match encode_response(mresp, max_size) {
+        Ok(data) => Some(Response { data, code: ResponseCode::NXDomain }),
         Err(e) => {
             error!(log, "NXDOMAIN destructive emit: {}", e);
-            nack(&log, &mr, &socket, &header, &src).await;
-            return;
-        }
-    }
-    match socket.send_to(&resp_data, &src).await {
-        Ok(_) => {}
-        Err(e) => {
-            error!(log, "NXDOMAIN send: {}", e);
+            nack(log, mr, &header)
         }
     }
 }
 
-async fn handle_req<'a, 'b, 'c>(
-    log: Logger,
-    db: Arc<sled::Db>,
-    socket: Arc<UdpSocket>,
-    src: SocketAddr,
-    buf: Vec<u8>,
-    zone: String,
-) {
+/// Handles a single request, returning the response to send back (if any)
+///
+/// The outcome of each request that can be parsed is recorded in `metrics`.
+fn handle_req(
+    log: &Logger,
+    zones: &Zones,
+    metrics: &Metrics,
+    buf: &[u8],
+    transport: Transport,
+) -> Option<Vec<u8>> {
+    let start = Instant::now();
     println!("{:?}", buf.hex_dump());
 
-    let mut dec = BinDecoder::new(&buf);
+    let mut dec = BinDecoder::new(buf);
     let mr = match MessageRequest::read(&mut dec) {
         Ok(mr) => mr,
         Err(e) => {
             error!(log, "read message: {}", e);
-            return;
+            return None;
         }
     };
 
     println!("{:#?}", mr);
 
-    let header = Header::response_from_request(mr.header());
-    let zone = LowerName::from(Name::from_str(&zone).unwrap());
+    let mut zone = None;
+    let response = answer_req(log, zones, &mr, transport, &mut zone);
+    metrics.record(QueryOutcome {
+        record_type: mr.query().query_type().to_string(),
+        zone: zone.as_deref(),
+        response_code: response.as_ref().map(|r| format!("{:?}", r.code)),
+        transport: transport.label(),
+        latency: start.elapsed(),
+    });
+    response.map(|r| r.data)
+}
 
-    // Ensure the query is for this zone, otherwise bail with servfail. This
-    // will cause resolvers to look to other DNS servers for this query.
-    let name = mr.query().name();
-    if !zone.zone_of(name) {
-        nack(&log, &mr, &socket, &header, &src).await;
-        return;
+/// Answers a parsed request, returning the response to send back (if any)
+///
+/// `zone_found` is set to the zone that the query is for, once that's known.
+fn answer_req(
+    log: &Logger,
+    zones: &Zones,
+    mr: &MessageRequest,
+    transport: Transport,
+    zone_found: &mut Option<String>,
+) -> Option<Response> {
+    let mut header = Header::response_from_request(mr.header());
+    let mut rb = MessageResponseBuilder::from_message_request(mr);
+
+    // Work out how big a response the client will accept.  Over UDP, that's
+    // 512 bytes unless the client tells us otherwise using EDNS.  Responses
+    // which don't fit are truncated, and the client is expected to retry over
+    // TCP.
+    let max_size = match (transport, mr.edns()) {
+        (Transport::Tcp, _) => u16::MAX,
+        (Transport::Udp, None) => MIN_UDP_PAYLOAD,
+        (Transport::Udp, Some(req_edns)) => {
+            req_edns.max_payload().clamp(MIN_UDP_PAYLOAD, MAX_UDP_PAYLOAD)
+        }
+    };
+    if let Some(req_edns) = mr.edns() {
+        let mut resp_edns = Edns::new();
+        resp_edns.set_max_payload(MAX_UDP_PAYLOAD);
+        resp_edns.set_version(0);
+
+        // We only support EDNS version 0 (RFC 6891 section 6.1.3).
+        if req_edns.version() > 0 {
+            header.set_response_code(ResponseCode::BADVERS);
+            resp_edns.set_rcode_high(ResponseCode::BADVERS.high());
+            rb.edns(resp_edns);
+            return match encode_response(rb.build_no_records(header), max_size)
+            {
+                Ok(data) => {
+                    Some(Response { data, code: ResponseCode::BADVERS })
+                }
+                Err(e) => {
+                    error!(log, "BADVERS destructive emit: {}", e);
+                    nack(log, mr, &header)
+                }
+            };
+        }
+
+        rb.edns(resp_edns);
     }
 
+    // Find the zone that the query is for, otherwise bail with servfail. This
+    // will cause resolvers to look to other DNS servers for this query.
+    let ZoneEntry { key: zone_key, name: zone_name, records: tree } =
+        match zones.find(mr.query().name()) {
+            Ok(Some(zone)) => zone,
+            Ok(None) => return nack(log, mr, &header),
+            Err(e) => {
+                error!(log, "{:#}", e);
+                return nack(log, mr, &header);
+            }
+        };
+    *zone_found = Some(zone_key.clone());
+    let zone = LowerName::from(zone_name.clone());
+    header.set_authoritative(true);
+
     let name = mr.query().original().name().clone();
     let key = name.to_string();
     let key = key.trim_end_matches('.');
+    let is_apex = LowerName::from(&name) == zone;
 
-    let rb = MessageResponseBuilder::from_message_request(&mr);
-
-    let bits = match db.get(key.as_bytes()) {
-        Ok(Some(bits)) => bits,
-
-        // If no record is found bail with NXDOMAIN.
-        Ok(None) => {
-            respond_nxdomain(&log, socket, src, rb, header, &mr).await;
-            return;
+    // The zone's SOA record isn't stored, but synthesized from what is.  It's
+    // included in negative responses so that resolvers know how long they can
+    // cache them for (RFC 2308).
+    let soa = match soa_record(&zones.db, &tree, &zone_key, &zone_name) {
+        Ok(soa) => soa,
+        Err(e) => {
+            error!(log, "{:#}", e);
+            return nack(log, mr, &header);
         }
+    };
+
+    let records = match get_records(&tree, key) {
+        Ok(Some(records)) => records,
+        Ok(None) => vec![],
 
         // If we encountered an error bail with SERVFAIL.
         Err(e) => {
-            error!(log, "db get: {}", e);
-            nack(&log, &mr, &socket, &header, &src).await;
-            return;
+            error!(log, "{:#}", e);
+            return nack(log, mr, &header);
         }
     };
 
-    let records: Vec<crate::dns_data::DnsRecord> =
-        match serde_json::from_slice(bits.as_ref()) {
-            Ok(r) => r,
-            Err(e) => {
-                error!(log, "deserialize record: {}", e);
-                nack(&log, &mr, &socket, &header, &src).await;
-                return;
-            }
-        };
-
-    if records.is_empty() {
-        error!(log, "No records found for {}", key);
-        respond_nxdomain(&log, socket, src, rb, header, &mr).await;
-        return;
+    // If no record is found bail with NXDOMAIN.  The apex of the zone always
+    // exists, though, since that's where the SOA record is.
+    if records.is_empty() && !is_apex {
+        return respond_nxdomain(log, rb, header, &soa, mr, max_size);
     }
 
+    // Answer with only the records of the type that was asked for.  If the
+    // name exists but has no records of that type, the answer is empty but the
+    // response code is still NOERROR ("NODATA"), which tells the resolver not
+    // to bother asking for this type again but that other types may exist.
+    let query_type = mr.query().query_type();
     let mut response_records: Vec<Record> = vec![];
-    for record in &records {
-        let resp = match record {
-            DnsRecord::AAAA(addr) => {
-                let mut aaaa = Record::new();
-                aaaa.set_name(name.clone())
-                    .set_rr_type(RecordType::AAAA)
-                    .set_data(Some(RData::AAAA(*addr)));
-                aaaa
-            }
-            DnsRecord::SRV(crate::dns_data::SRV {
-                prio,
-                weight,
-                port,
-                target,
-            }) => {
-                let mut srv = Record::new();
-                let tgt = match Name::from_str(&target) {
-                    Ok(tgt) => tgt,
-                    Err(e) => {
-                        error!(log, "srv target: '{}' {}", target, e);
-                        nack(&log, &mr, &socket, &header, &src).await;
-                        return;
-                    }
-                };
-                srv.set_name(name.clone())
-                    .set_rr_type(RecordType::SRV)
-                    .set_data(Some(RData::SRV(SRV::new(
-                        *prio, *weight, *port, tgt,
-                    ))));
-                srv
-            }
+    if is_apex && matches!(query_type, RecordType::SOA | RecordType::ANY) {
+        let mut soa = soa.clone();
+        soa.set_name(name.clone());
+        response_records.push(soa);
+    }
+    match answer_records(&tree, &zone, name, records, query_type) {
+        Ok(records) => response_records.extend(records),
+        Err(e) => {
+            error!(log, "{:#}", e);
+            return nack(log, mr, &header);
+        }
+    }
+
+    // Include the addresses of the targets of any SRV or NS records in the
+    // additional section so that clients don't need to look them up
+    // separately.
+    let mut glue_targets: Vec<Name> = vec![];
+    let mut additional_records: Vec<Record> = vec![];
+    for record in &response_records {
+        let target = match record.data() {
+            Some(RData::SRV(srv)) => srv.target(),
+            Some(RData::NS(ns)) => ns,
+            _ => continue,
         };
-        response_records.push(resp);
+        if glue_targets.contains(target)
+            || !zone.zone_of(&LowerName::from(target))
+        {
+            continue;
+        }
+        glue_targets.push(target.clone());
+        additional_records.extend(glue_records(log, &tree, target));
     }
 
+    let authority_records =
+        if response_records.is_empty() { vec![&soa] } else { vec![] };
     let mresp = rb.build(
         header,
         response_records.iter().collect::<Vec<&Record>>(),
         vec![],
-        vec![],
-        vec![],
+        authority_records,
+        additional_records.iter().collect::<Vec<&Record>>(),
     );
 
-    let mut resp_data = Vec::new();
-    let mut enc = BinEncoder::new(&mut resp_data);
-    match mresp.destructive_emit(&mut enc) {
-        Ok(_) => {}
+    match encode_response(mresp, max_size) {
+        Ok(data) => Some(Response { data, code: ResponseCode::NoError }),
         Err(e) => {
             error!(log, "destructive emit: {}", e);
-            nack(&log, &mr, &socket, &header, &src).await;
-            return;
+            nack(log, mr, &header)
         }
     }
-    match socket.send_to(&resp_data, &src).await {
-        Ok(_) => {}
-        Err(e) => {
-            error!(log, "send: {}", e);
+}
+
+/// A zone served by this server
+#[derive(Clone)]
+struct ZoneEntry {
+    /// The zone's key in the database
+    key: String,
+    /// The zone's name
+    name: Name,
+    /// The tree holding the zone's records
+    records: sled::Tree,
+}
+
+/// The zones served by this server, as of some version of the set of zones
+struct ZoneSet {
+    version: u64,
+    zones: Vec<ZoneEntry>,
+}
+
+/// Looks up the zones served by this server
+///
+/// Parsing the zones and opening their trees is done once for each version of
+/// the set of zones, rather than for each query.  The data model server moves
+/// to a new version whenever it creates or removes a zone.
+struct Zones {
+    db: Arc<sled::Db>,
+    cache: Mutex<Option<Arc<ZoneSet>>>,
+}
+
+impl Zones {
+    fn new(db: Arc<sled::Db>) -> Zones {
+        Zones { db, cache: Mutex::new(None) }
+    }
+
+    /// Returns the current set of zones, reading it from the database if it's
+    /// changed since it was last read
+    fn current(&self) -> Result<Arc<ZoneSet>, anyhow::Error> {
+        let version = crate::dns_data::zones_version(&self.db)?;
+        if let Some(cached) = &*self.cache.lock().unwrap() {
+            if cached.version == version {
+                return Ok(cached.clone());
+            }
+        }
+
+        let mut zones = Vec::new();
+        for key in crate::dns_data::zones(&self.db)? {
+            // The zone may have been removed since it was listed.
+            let Some(records) =
+                crate::dns_data::zone_records_if_exists(&self.db, &key)?
+            else {
+                continue;
+            };
+            let name = parse_name(&key)?;
+            zones.push(ZoneEntry { key, name, records });
+        }
+        let current = Arc::new(ZoneSet { version, zones });
+        *self.cache.lock().unwrap() = Some(current.clone());
+        Ok(current)
+    }
+
+    /// Returns the most specific of the zones that `name` is in, if it's in
+    /// any
+    fn find(
+        &self,
+        name: &LowerName,
+    ) -> Result<Option<ZoneEntry>, anyhow::Error> {
+        let current = self.current()?;
+        let found = current
+            .zones
+            .iter()
+            .filter(|zone| LowerName::from(&zone.name).zone_of(name))
+            .max_by_key(|zone| zone.name.num_labels());
+        Ok(found.cloned())
+    }
+}
+
+/// Fetches the records stored for `key` in a zone's `tree`, if there are any
+fn get_records(
+    tree: &sled::Tree,
+    key: &str,
+) -> Result<Option<Vec<DnsRecord>>, anyhow::Error> {
+    let bits = match tree.get(key.as_bytes()).context("db get")? {
+        Some(bits) => bits,
+        None => return Ok(None),
+    };
+    let records =
+        serde_json::from_slice(bits.as_ref()).context("deserialize record")?;
+    Ok(Some(records))
+}
+
+/// Returns the records for `name` (whose stored records are `records`) which
+/// answer a query for `query_type`
+///
+/// If `name` has no such records but is an alias for another name in the
+/// zone, the CNAME record is returned along with the records for the name
+/// it points to (RFC 1034 section 4.3.2).
+fn answer_records(
+    tree: &sled::Tree,
+    zone: &LowerName,
+    mut name: Name,
+    mut records: Vec<DnsRecord>,
+    query_type: RecordType,
+) -> Result<Vec<Record>, anyhow::Error> {
+    let mut answers = vec![];
+    for _ in 0..MAX_CNAME_CHAIN {
+        let mut matched = false;
+        for record in records.iter().filter(|record| {
+            query_type == RecordType::ANY
+                || dns_record_type(record) == query_type
+        }) {
+            answers.push(dns_record_to_record(&name, record)?);
+            matched = true;
+        }
+        if matched {
+            break;
         }
+
+        let cname = records
+            .iter()
+            .find(|record| matches!(record, DnsRecord::CNAME(_)))
+            .map(|cname| dns_record_to_record(&name, cname))
+            .transpose()?;
+        let Some(cname) = cname else {
+            break;
+        };
+        let Some(RData::CNAME(target)) = cname.data().cloned() else {
+            break;
+        };
+        answers.push(cname);
+
+        // The client will have to look up names outside the zone itself.
+        if !zone.zone_of(&LowerName::from(&target)) {
+            break;
+        }
+        let key = target.to_string();
+        records =
+            get_records(tree, key.trim_end_matches('.'))?.unwrap_or_default();
+        name = target;
+    }
+    Ok(answers)
+}
+
+/// Returns the SOA record of `zone`, whose key in the database is `zone_key`
+/// and whose records are in `tree`
+///
+/// The primary name server it names is the first of the zone's NS records, if
+/// there are any.  Its serial number is the generation of the zone's records,
+/// so that it changes whenever they do.
+fn soa_record(
+    db: &sled::Db,
+    tree: &sled::Tree,
+    zone_key: &str,
+    zone: &Name,
+) -> Result<Record, anyhow::Error> {
+    let apex_key = zone.to_string();
+    let apex_records =
+        get_records(tree, apex_key.trim_end_matches('.'))?.unwrap_or_default();
+    let mname = match apex_records.iter().find_map(|record| match record {
+        DnsRecord::NS(ns) => Some(ns),
+        _ => None,
+    }) {
+        Some(ns) => parse_name(ns)?,
+        None => zone.clone(),
+    };
+    let rname = parse_name(&format!("hostmaster.{}", apex_key))?;
+
+    // Serial numbers are compared using sequence space arithmetic (RFC 1982),
+    // so it's fine for them to wrap around.
+    let serial =
+        crate::dns_data::zone_generation(db, zone_key)?.unwrap_or(0) as u32;
+
+    let mut soa = Record::new();
+    soa.set_name(zone.clone()).set_rr_type(RecordType::SOA).set_data(Some(
+        RData::SOA(SOA::new(
+            mname,
+            rname,
+            serial,
+            SOA_REFRESH,
+            SOA_RETRY,
+            SOA_EXPIRE,
+            SOA_MINIMUM,
+        )),
+    ));
+    Ok(soa)
+}
+
+/// Returns the type of record that a query must ask for to get `record` back
+fn dns_record_type(record: &DnsRecord) -> RecordType {
+    match record {
+        DnsRecord::A(_) => RecordType::A,
+        DnsRecord::AAAA(_) => RecordType::AAAA,
+        DnsRecord::CNAME(_) => RecordType::CNAME,
+        DnsRecord::NS(_) => RecordType::NS,
+        DnsRecord::PTR(_) => RecordType::PTR,
+        DnsRecord::SRV(_) => RecordType::SRV,
+        DnsRecord::TXT(_) => RecordType::TXT,
+    }
+}
+
+fn parse_name(name: &str) -> Result<Name, anyhow::Error> {
+    Name::from_str(name).with_context(|| format!("invalid name: '{}'", name))
+}
+
+/// Splits `text` into the character-strings of a TXT record, each of which
+/// can be at most 255 bytes long
+fn txt_strings(text: &str) -> Vec<String> {
+    let mut strings = vec![];
+    let mut current = String::new();
+    for c in text.chars() {
+        if current.len() + c.len_utf8() > 255 {
+            strings.push(std::mem::take(&mut current));
+        }
+        current.push(c);
     }
+    strings.push(current);
+    strings
 }
 
-async fn nack(
+/// Converts a record stored for `name` into its DNS representation
+fn dns_record_to_record(
+    name: &Name,
+    record: &DnsRecord,
+) -> Result<Record, anyhow::Error> {
+    let mut resp = Record::new();
+    resp.set_name(name.clone()).set_rr_type(dns_record_type(record));
+    let data = match record {
+        DnsRecord::A(addr) => RData::A(*addr),
+        DnsRecord::AAAA(addr) => RData::AAAA(*addr),
+        DnsRecord::CNAME(target) => RData::CNAME(parse_name(target)?),
+        DnsRecord::NS(ns) => RData::NS(parse_name(ns)?),
+        DnsRecord::PTR(target) => RData::PTR(parse_name(target)?),
+        DnsRecord::SRV(crate::dns_data::SRV { prio, weight, port, target }) => {
+            let tgt = Name::from_str(&target)
+                .with_context(|| format!("srv target: '{}'", target))?;
+            RData::SRV(SRV::new(*prio, *weight, *port, tgt))
+        }
+        DnsRecord::TXT(text) => RData::TXT(TXT::new(txt_strings(text))),
+    };
+    resp.set_data(Some(data));
+    Ok(resp)
+}
+
+/// Returns the addresses of `target`, for use as glue in the additional section
+/// of a response
+///
+/// Glue is only a convenience for the client, which can always look the target
+/// up itself, so failing to find it is logged rather than failing the request.
+fn glue_records(log: &Logger, tree: &sled::Tree, target: &Name) -> Vec<Record> {
+    let key = target.to_string();
+    let key = key.trim_end_matches('.');
+    let records = match get_records(tree, key) {
+        Ok(Some(records)) => records,
+        Ok(None) => return vec![],
+        Err(e) => {
+            error!(log, "glue for {}: {:#}", key, e);
+            return vec![];
+        }
+    };
+    records
+        .iter()
+        .filter(|record| matches!(record, DnsRecord::A(_) | DnsRecord::AAAA(_)))
+        .filter_map(|record| match dns_record_to_record(target, record) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                error!(log, "glue for {}: {:#}", key, e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn nack(
     log: &Logger,
     mr: &MessageRequest,
-    socket: &UdpSocket,
     header: &Header,
-    src: &SocketAddr,
-) {
+) -> Option<Response> {
     let rb = MessageResponseBuilder::from_message_request(mr);
     let mresp = rb.error_msg(header, ResponseCode::ServFail);
-    let mut resp_data = Vec::new();
-    let mut enc = BinEncoder::new(&mut resp_data);
-    match mresp.destructive_emit(&mut enc) {
-        Ok(_) => {}
-        Err(e) => {
-            error!(log, "destructive emit: {}", e);
-            return;
-        }
-    }
-    match socket.send_to(&resp_data, &src).await {
-        Ok(_) => {}
+    match encode_response(mresp, MIN_UDP_PAYLOAD) {
+        Ok(data) => Some(Response { data, code: ResponseCode::ServFail }),
         Err(e) => {
             error!(log, "destructive emit: {}", e);
+            None
         }
     }
 }
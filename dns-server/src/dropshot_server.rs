@@ -4,8 +4,10 @@
 
 //! Dropshot server for configuring DNS namespace
 
-use crate::dns_data::{self, DnsKV, DnsRecordKey};
+use crate::dns_data::{self, DataError, DnsConfig, DnsKV, DnsRecordKey};
 use dropshot::{endpoint, RequestContext};
+use schemars::JsonSchema;
+use serde::Deserialize;
 use std::sync::Arc;
 
 pub struct Context {
@@ -21,53 +23,180 @@ impl Context {
 pub fn api() -> dropshot::ApiDescription<Arc<Context>> {
     let mut api = dropshot::ApiDescription::new();
 
+    api.register(dns_zones_list).expect("register dns_zones_list");
+    api.register(dns_zone_create).expect("register dns_zone_create");
+    api.register(dns_zone_delete).expect("register dns_zone_delete");
     api.register(dns_records_list).expect("register dns_records_list");
     api.register(dns_records_create).expect("register dns_records_create");
     api.register(dns_records_delete).expect("register dns_records_delete");
+    api.register(dns_config_get).expect("register dns_config_get");
+    api.register(dns_config_put).expect("register dns_config_put");
     api
 }
 
+/// Path parameters for requests that operate on a zone
+#[derive(Deserialize, JsonSchema)]
+struct ZonePathParam {
+    zone: String,
+}
+
+fn http_error(error: DataError) -> dropshot::HttpError {
+    let message = error.to_string();
+    match error {
+        DataError::NoSuchZone(_) => dropshot::HttpError::for_client_error(
+            Some(String::from("NoSuchZone")),
+            http::StatusCode::NOT_FOUND,
+            message,
+        ),
+        DataError::InvalidZoneName(_) | DataError::NameOutsideZone { .. } => {
+            dropshot::HttpError::for_bad_request(
+                Some(String::from("InvalidName")),
+                message,
+            )
+        }
+        DataError::StaleGeneration { .. } => {
+            dropshot::HttpError::for_client_error(
+                Some(String::from("StaleGeneration")),
+                http::StatusCode::CONFLICT,
+                message,
+            )
+        }
+        DataError::Internal(_) => {
+            dropshot::HttpError::for_internal_error(message)
+        }
+    }
+}
+
+#[endpoint(
+    method = GET,
+    path = "/zones",
+)]
+async fn dns_zones_list(
+    rqctx: RequestContext<Arc<Context>>,
+) -> Result<dropshot::HttpResponseOk<Vec<String>>, dropshot::HttpError> {
+    let apictx = rqctx.context();
+    let zones = apictx.client.list_zones().await.map_err(http_error)?;
+    Ok(dropshot::HttpResponseOk(zones))
+}
+
+/// Creates a zone with no records, if it doesn't already exist
+#[endpoint(
+    method = PUT,
+    path = "/zones/{zone}",
+)]
+async fn dns_zone_create(
+    rqctx: RequestContext<Arc<Context>>,
+    path_params: dropshot::Path<ZonePathParam>,
+) -> Result<dropshot::HttpResponseUpdatedNoContent, dropshot::HttpError> {
+    let apictx = rqctx.context();
+    let zone = path_params.into_inner().zone;
+    apictx.client.create_zone(zone).await.map_err(http_error)?;
+    Ok(dropshot::HttpResponseUpdatedNoContent())
+}
+
+/// Removes a zone along with all of its records
+#[endpoint(
+    method = DELETE,
+    path = "/zones/{zone}",
+)]
+async fn dns_zone_delete(
+    rqctx: RequestContext<Arc<Context>>,
+    path_params: dropshot::Path<ZonePathParam>,
+) -> Result<dropshot::HttpResponseDeleted, dropshot::HttpError> {
+    let apictx = rqctx.context();
+    let zone = path_params.into_inner().zone;
+    apictx.client.delete_zone(zone).await.map_err(http_error)?;
+    Ok(dropshot::HttpResponseDeleted())
+}
+
 #[endpoint(
     method = GET,
-    path = "/records",
+    path = "/zones/{zone}/records",
 )]
 async fn dns_records_list(
     rqctx: RequestContext<Arc<Context>>,
+    path_params: dropshot::Path<ZonePathParam>,
 ) -> Result<dropshot::HttpResponseOk<Vec<DnsKV>>, dropshot::HttpError> {
     let apictx = rqctx.context();
+    let zone = path_params.into_inner().zone;
     // XXX record key
-    let records = apictx.client.get_records(None).await.map_err(|e| {
-        dropshot::HttpError::for_internal_error(format!("uh oh: {:?}", e))
-    })?;
+    let records =
+        apictx.client.get_records(zone, None).await.map_err(http_error)?;
     Ok(dropshot::HttpResponseOk(records))
 }
 
 #[endpoint(
     method = POST,
-    path = "/records",
+    path = "/zones/{zone}/records",
 )]
 async fn dns_records_create(
     rqctx: RequestContext<Arc<Context>>,
+    path_params: dropshot::Path<ZonePathParam>,
     rq: dropshot::TypedBody<Vec<DnsKV>>,
 ) -> Result<dropshot::HttpResponseUpdatedNoContent, dropshot::HttpError> {
     let apictx = rqctx.context();
-    apictx.client.set_records(rq.into_inner()).await.map_err(|e| {
-        dropshot::HttpError::for_internal_error(format!("uh oh: {:?}", e))
-    })?;
+    let zone = path_params.into_inner().zone;
+    apictx
+        .client
+        .set_records(zone, rq.into_inner())
+        .await
+        .map_err(http_error)?;
     Ok(dropshot::HttpResponseUpdatedNoContent())
 }
 
 #[endpoint(
     method = DELETE,
-    path = "/records",
+    path = "/zones/{zone}/records",
 )]
 async fn dns_records_delete(
     rqctx: RequestContext<Arc<Context>>,
+    path_params: dropshot::Path<ZonePathParam>,
     rq: dropshot::TypedBody<Vec<DnsRecordKey>>,
 ) -> Result<dropshot::HttpResponseDeleted, dropshot::HttpError> {
     let apictx = rqctx.context();
-    apictx.client.delete_records(rq.into_inner()).await.map_err(|e| {
-        dropshot::HttpError::for_internal_error(format!("uh oh: {:?}", e))
-    })?;
+    let zone = path_params.into_inner().zone;
+    apictx
+        .client
+        .delete_records(zone, rq.into_inner())
+        .await
+        .map_err(http_error)?;
     Ok(dropshot::HttpResponseDeleted())
 }
+
+#[endpoint(
+    method = GET,
+    path = "/zones/{zone}/config",
+)]
+async fn dns_config_get(
+    rqctx: RequestContext<Arc<Context>>,
+    path_params: dropshot::Path<ZonePathParam>,
+) -> Result<dropshot::HttpResponseOk<DnsConfig>, dropshot::HttpError> {
+    let apictx = rqctx.context();
+    let zone = path_params.into_inner().zone;
+    let config = apictx.client.get_config(zone).await.map_err(http_error)?;
+    Ok(dropshot::HttpResponseOk(config))
+}
+
+/// Replaces all of the records in a zone with the given configuration
+///
+/// Putting the configuration that the zone already has is a no-op.  Putting
+/// an older configuration than the zone already has fails with a 409
+/// ("Conflict").
+#[endpoint(
+    method = PUT,
+    path = "/zones/{zone}/config",
+)]
+async fn dns_config_put(
+    rqctx: RequestContext<Arc<Context>>,
+    path_params: dropshot::Path<ZonePathParam>,
+    rq: dropshot::TypedBody<DnsConfig>,
+) -> Result<dropshot::HttpResponseUpdatedNoContent, dropshot::HttpError> {
+    let apictx = rqctx.context();
+    let zone = path_params.into_inner().zone;
+    apictx
+        .client
+        .put_config(zone, rq.into_inner())
+        .await
+        .map_err(http_error)?;
+    Ok(dropshot::HttpResponseUpdatedNoContent())
+}
@@ -9,18 +9,31 @@ use anyhow::anyhow;
 use serde::Deserialize;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use uuid::Uuid;
 
 pub mod dns_data;
 pub mod dns_server;
 pub mod dropshot_server;
+pub mod metrics;
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub log: dropshot::ConfigLogging,
     pub dropshot: dropshot::ConfigDropshot,
     pub data: dns_data::Config,
+    /// If present, query metrics are exported to oximeter
+    #[serde(default)]
+    pub metrics: Option<metrics::Config>,
 }
 
+/// Starts the DNS and Dropshot servers
+///
+/// `zone` is created (with no records) if it doesn't already exist.  Other
+/// zones may be added using the Dropshot server.  Records stored by a server
+/// that only served a single zone are moved into `zone`.
+///
+/// Metrics about queries are always tracked, and are exported to oximeter in
+/// the background if `config.metrics` is set.
 pub async fn start(
     log: slog::Logger,
     config: Config,
@@ -31,15 +44,33 @@ pub async fn start(
     dropshot::HttpServer<Arc<dropshot_server::Context>>,
 )> {
     let db = Arc::new(sled::open(&config.data.storage_path)?);
+    dns_data::create_zone(&db, &zone)
+        .map_err(|e| anyhow!("create zone {:?}: {}", zone, e))?;
+    dns_data::migrate_legacy_records(&db, &zone)
+        .map_err(|e| anyhow!("migrate records to zone {:?}: {}", zone, e))?;
+
+    let metrics = metrics::Metrics::new(
+        config.metrics.as_ref().map(|c| c.id).unwrap_or_else(Uuid::new_v4),
+    );
+    if let Some(metrics_config) = config.metrics.clone() {
+        let log = log.new(slog::o!("component" => "metrics"));
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(e) =
+                metrics::run_producer(log.clone(), metrics_config, metrics)
+                    .await
+            {
+                slog::error!(log, "metric producer exited: {:#}", e);
+            }
+        });
+    }
 
     let dns_server = {
         let db = db.clone();
         let log = log.clone();
-        let dns_config = dns_server::Config {
-            bind_address: dns_address.to_string(),
-            zone: zone.to_string(),
-        };
-        dns_server::run(log, db, dns_config).await?
+        let dns_config =
+            dns_server::Config { bind_address: dns_address.to_string() };
+        dns_server::run(log, db, dns_config, metrics).await?
     };
 
     let dropshot_server = start_dropshot_server(config, log, db).await?;
@@ -0,0 +1,256 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Metrics about the queries answered by the DNS server
+//!
+//! These are exported to oximeter by registering a producer server with Nexus,
+//! which assigns it a collector that stores them in ClickHouse.
+
+use omicron_common::api::internal::nexus::ProducerEndpoint;
+use omicron_common::backoff;
+use oximeter::histogram::Histogram;
+use oximeter::types::Cumulative;
+use oximeter::{Metric, MetricsError, Producer, Sample, Target};
+use serde::Deserialize;
+use slog::{info, warn, Logger};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Configuration for exporting metrics to oximeter
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    /// The producer ID under which metrics are reported, which also identifies
+    /// this server in them
+    pub id: Uuid,
+    /// The address of Nexus's internal API, with which the producer registers
+    pub nexus_address: SocketAddr,
+    /// The address on which the producer server listens for oximeter
+    pub bind_address: SocketAddr,
+    /// How often oximeter should collect metrics, in seconds
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// Whether to also serve metrics at `/metrics` on the producer server, in
+    /// the Prometheus text exposition format
+    #[serde(default)]
+    pub prometheus: bool,
+}
+
+fn default_interval_secs() -> u64 {
+    10
+}
+
+/// The DNS server that metrics are about
+#[derive(Debug, Clone, Target)]
+pub struct DnsServer {
+    pub id: Uuid,
+}
+
+/// The number of queries received for a type of record
+#[derive(Debug, Clone, Metric)]
+pub struct Queries {
+    pub record_type: String,
+    #[datum]
+    pub count: Cumulative<i64>,
+}
+
+/// The number of queries received for names in a zone
+///
+/// Queries for names that aren't in any zone served here are counted under
+/// an empty zone name.
+#[derive(Debug, Clone, Metric)]
+pub struct ZoneQueries {
+    pub zone: String,
+    #[datum]
+    pub count: Cumulative<i64>,
+}
+
+/// The number of responses sent with a response code, such as "NXDomain" or
+/// "ServFail"
+#[derive(Debug, Clone, Metric)]
+pub struct Responses {
+    pub response_code: String,
+    #[datum]
+    pub count: Cumulative<i64>,
+}
+
+/// A histogram of the time taken to answer queries, in seconds, by the
+/// transport ("udp" or "tcp") they arrived over
+#[derive(Debug, Clone, Metric)]
+pub struct QueryLatency {
+    pub transport: String,
+    #[datum]
+    pub latency: Histogram<f64>,
+}
+
+/// The outcome of a single query, as recorded in [`Metrics`]
+#[derive(Debug, Clone)]
+pub struct QueryOutcome<'a> {
+    pub record_type: String,
+    pub zone: Option<&'a str>,
+    pub response_code: Option<String>,
+    pub transport: &'static str,
+    pub latency: Duration,
+}
+
+/// Tracks metrics about the queries answered by the DNS server
+///
+/// This is cheap to clone, and all clones update the same metrics.  It's an
+/// [`oximeter::Producer`] which produces a sample for each timeseries that has
+/// been updated since the server started.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    target: DnsServer,
+    inner: Arc<Mutex<MetricsInner>>,
+}
+
+#[derive(Debug)]
+struct MetricsInner {
+    histogram: Histogram<f64>,
+    queries: BTreeMap<String, Queries>,
+    zones: BTreeMap<String, ZoneQueries>,
+    responses: BTreeMap<String, Responses>,
+    latencies: BTreeMap<&'static str, QueryLatency>,
+}
+
+impl Metrics {
+    pub fn new(id: Uuid) -> Self {
+        // Latencies range from a microsecond to ten seconds.
+        let histogram = Histogram::span_decades(-6, 1)
+            .expect("valid decades for the query latency histogram");
+        Self {
+            target: DnsServer { id },
+            inner: Arc::new(Mutex::new(MetricsInner {
+                histogram,
+                queries: BTreeMap::new(),
+                zones: BTreeMap::new(),
+                responses: BTreeMap::new(),
+                latencies: BTreeMap::new(),
+            })),
+        }
+    }
+
+    /// Returns the ID of the server that metrics are reported for
+    pub fn id(&self) -> Uuid {
+        self.target.id
+    }
+
+    /// Records the outcome of a query
+    pub fn record(&self, outcome: QueryOutcome<'_>) {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        *inner
+            .queries
+            .entry(outcome.record_type.clone())
+            .or_insert_with(|| Queries {
+                record_type: outcome.record_type,
+                count: Cumulative::default(),
+            })
+            .datum_mut() += 1;
+
+        let zone = outcome.zone.unwrap_or("");
+        *inner
+            .zones
+            .entry(zone.to_string())
+            .or_insert_with(|| ZoneQueries {
+                zone: zone.to_string(),
+                count: Cumulative::default(),
+            })
+            .datum_mut() += 1;
+
+        if let Some(response_code) = outcome.response_code {
+            *inner
+                .responses
+                .entry(response_code.clone())
+                .or_insert_with(|| Responses {
+                    response_code,
+                    count: Cumulative::default(),
+                })
+                .datum_mut() += 1;
+        }
+
+        let histogram = &inner.histogram;
+        let latency =
+            inner.latencies.entry(outcome.transport).or_insert_with(|| {
+                QueryLatency {
+                    transport: outcome.transport.to_string(),
+                    latency: histogram.clone(),
+                }
+            });
+        // Sampling only fails for NaN, which a duration can't be.
+        let _ = latency.latency.sample(outcome.latency.as_secs_f64());
+    }
+}
+
+impl Producer for Metrics {
+    fn produce(
+        &mut self,
+    ) -> Result<Box<dyn Iterator<Item = Sample> + 'static>, MetricsError> {
+        let inner = self.inner.lock().unwrap();
+        let target = &self.target;
+        let samples = inner
+            .queries
+            .values()
+            .map(|m| Sample::new(target, m))
+            .chain(inner.zones.values().map(|m| Sample::new(target, m)))
+            .chain(inner.responses.values().map(|m| Sample::new(target, m)))
+            .chain(inner.latencies.values().map(|m| Sample::new(target, m)))
+            .collect::<Vec<_>>();
+        Ok(Box::new(samples.into_iter()))
+    }
+}
+
+/// Runs an oximeter producer server which exports `metrics`
+///
+/// Nexus may not be running yet when the DNS server starts (it uses DNS to
+/// find its own dependencies), so registration is retried until it succeeds.
+/// This only returns if the producer server fails after it has started.
+pub async fn run_producer(
+    log: Logger,
+    config: Config,
+    metrics: Metrics,
+) -> Result<(), anyhow::Error> {
+    let producer_config = oximeter_producer::Config {
+        server_info: ProducerEndpoint {
+            id: config.id,
+            address: config.bind_address,
+            base_route: "/collect".to_string(),
+            interval: Duration::from_secs(config.interval_secs),
+        },
+        registration_address: config.nexus_address,
+        dropshot_config: dropshot::ConfigDropshot {
+            bind_address: config.bind_address,
+            ..Default::default()
+        },
+        logging_config: dropshot::ConfigLogging::StderrTerminal {
+            level: dropshot::ConfigLoggingLevel::Info,
+        },
+        prometheus: config.prometheus,
+    };
+    let start = || async {
+        oximeter_producer::Server::start(&producer_config)
+            .await
+            .map_err(backoff::BackoffError::transient)
+    };
+    let log_failure = |error, delay| {
+        warn!(
+            log,
+            "failed to start metric producer, will retry in {:?}", delay;
+            "error" => %error,
+        );
+    };
+    let server = backoff::retry_notify(
+        backoff::retry_policy_internal_service(),
+        start,
+        log_failure,
+    )
+    .await?;
+    server.registry().register_producer(metrics)?;
+    info!(log, "exporting metrics"; "address" => server.address());
+    server.serve_forever().await?;
+    Ok(())
+}
@@ -2,32 +2,38 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::net::Ipv6Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::Arc;
+use uuid::Uuid;
 
 use anyhow::Result;
 use dns_service_client::{
-    types::{DnsKv, DnsRecord, DnsRecordKey, Srv},
+    types::{DnsConfig, DnsKv, DnsRecord, DnsRecordKey, Srv},
     Client,
 };
 use dropshot::test_util::LogContext;
 use omicron_test_utils::dev::test_setup_log;
+use oximeter::types::{Datum, FieldValue, Sample};
+use oximeter::Producer;
 use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_resolver::proto::rr::{RData, RecordType};
 use trust_dns_resolver::TokioAsyncResolver;
 use trust_dns_resolver::{
     config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
     proto::op::ResponseCode,
 };
 
+/// The zone that each test's DNS server is created with
+const TEST_ZONE: &str = "oxide.internal";
+
 #[tokio::test]
 pub async fn aaaa_crud() -> Result<(), anyhow::Error> {
-    let test_ctx =
-        init_client_server("aaaa_crud", "oxide.internal".into()).await?;
+    let test_ctx = init_client_server("aaaa_crud").await?;
     let client = &test_ctx.client;
     let resolver = &test_ctx.resolver;
 
     // records should initially be empty
-    let records = client.dns_records_list().await?;
+    let records = client.dns_records_list(TEST_ZONE).await?;
     assert!(records.is_empty());
 
     // add an aaaa record
@@ -35,14 +41,14 @@ pub async fn aaaa_crud() -> Result<(), anyhow::Error> {
     let addr = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x1);
     let aaaa = DnsRecord::Aaaa(addr);
     client
-        .dns_records_create(&vec![DnsKv {
-            key: name.clone(),
-            records: vec![aaaa.clone()],
-        }])
+        .dns_records_create(
+            TEST_ZONE,
+            &vec![DnsKv { key: name.clone(), records: vec![aaaa.clone()] }],
+        )
         .await?;
 
     // read back the aaaa record
-    let records = client.dns_records_list().await?;
+    let records = client.dns_records_list(TEST_ZONE).await?;
     assert_eq!(1, records.len());
     assert_eq!(records[0].key.name, name.name);
 
@@ -67,13 +73,12 @@ pub async fn aaaa_crud() -> Result<(), anyhow::Error> {
 
 #[tokio::test]
 pub async fn srv_crud() -> Result<(), anyhow::Error> {
-    let test_ctx =
-        init_client_server("srv_crud", "oxide.internal".into()).await?;
+    let test_ctx = init_client_server("srv_crud").await?;
     let client = &test_ctx.client;
     let resolver = &test_ctx.resolver;
 
     // records should initially be empty
-    let records = client.dns_records_list().await?;
+    let records = client.dns_records_list(TEST_ZONE).await?;
     assert!(records.is_empty());
 
     // add a srv record
@@ -82,14 +87,14 @@ pub async fn srv_crud() -> Result<(), anyhow::Error> {
         Srv { prio: 47, weight: 74, port: 99, target: "outpost47".into() };
     let rec = DnsRecord::Srv(srv.clone());
     client
-        .dns_records_create(&vec![DnsKv {
-            key: name.clone(),
-            records: vec![rec.clone()],
-        }])
+        .dns_records_create(
+            TEST_ZONE,
+            &vec![DnsKv { key: name.clone(), records: vec![rec.clone()] }],
+        )
         .await?;
 
     // read back the srv record
-    let records = client.dns_records_list().await?;
+    let records = client.dns_records_list(TEST_ZONE).await?;
     assert_eq!(1, records.len());
     assert_eq!(records[0].key.name, name.name);
 
@@ -120,14 +125,12 @@ pub async fn srv_crud() -> Result<(), anyhow::Error> {
 
 #[tokio::test]
 pub async fn multi_record_crud() -> Result<(), anyhow::Error> {
-    let test_ctx =
-        init_client_server("multi_record_crud", "oxide.internal".into())
-            .await?;
+    let test_ctx = init_client_server("multi_record_crud").await?;
     let client = &test_ctx.client;
     let resolver = &test_ctx.resolver;
 
     // records should initially be empty
-    let records = client.dns_records_list().await?;
+    let records = client.dns_records_list(TEST_ZONE).await?;
     assert!(records.is_empty());
 
     // Add multiple AAAA records
@@ -137,14 +140,14 @@ pub async fn multi_record_crud() -> Result<(), anyhow::Error> {
     let aaaa1 = DnsRecord::Aaaa(addr1);
     let aaaa2 = DnsRecord::Aaaa(addr2);
     client
-        .dns_records_create(&vec![DnsKv {
-            key: name.clone(),
-            records: vec![aaaa1, aaaa2],
-        }])
+        .dns_records_create(
+            TEST_ZONE,
+            &vec![DnsKv { key: name.clone(), records: vec![aaaa1, aaaa2] }],
+        )
         .await?;
 
     // read back the aaaa records
-    let records = client.dns_records_list().await?;
+    let records = client.dns_records_list(TEST_ZONE).await?;
     assert_eq!(1, records.len());
     assert_eq!(records[0].key.name, name.name);
 
@@ -178,6 +181,550 @@ pub async fn multi_record_crud() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+#[tokio::test]
+pub async fn query_types_and_glue() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server("query_types_and_glue").await?;
+    let client = &test_ctx.client;
+    let resolver = &test_ctx.resolver;
+
+    // Add a service whose name has both SRV and AAAA records, and whose SRV
+    // record points at a name with an AAAA record of its own.
+    let service = DnsRecordKey { name: "nexus.oxide.internal".into() };
+    let backend = DnsRecordKey { name: "backend.oxide.internal".into() };
+    let service_addr = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x1);
+    let backend_addr = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x2);
+    let srv = Srv {
+        prio: 0,
+        weight: 0,
+        port: 12221,
+        target: "backend.oxide.internal".into(),
+    };
+    client
+        .dns_records_create(
+            TEST_ZONE,
+            &vec![
+                DnsKv {
+                    key: service.clone(),
+                    records: vec![
+                        DnsRecord::Srv(srv.clone()),
+                        DnsRecord::Aaaa(service_addr),
+                    ],
+                },
+                DnsKv {
+                    key: backend.clone(),
+                    records: vec![DnsRecord::Aaaa(backend_addr)],
+                },
+            ],
+        )
+        .await?;
+
+    // Asking for the SRV records returns only those, along with the address
+    // of their target.
+    let response = resolver.srv_lookup(service.name.clone() + ".").await?;
+    let srvs = response.iter().collect::<Vec<_>>();
+    assert_eq!(srvs.len(), 1);
+    assert_eq!(srvs[0].port(), srv.port);
+    assert_eq!(srvs[0].target().to_string(), srv.target + ".");
+    let glue = response.ip_iter().collect::<Vec<_>>();
+    assert_eq!(glue, vec![IpAddr::V6(backend_addr)]);
+
+    // Asking for the address returns only that.
+    let response = resolver.ipv6_lookup(service.name.clone() + ".").await?;
+    let addrs = response.iter().collect::<Vec<_>>();
+    assert_eq!(addrs, vec![&service_addr]);
+
+    // Asking for a type of record that the name doesn't have returns no
+    // records, but doesn't claim that the name doesn't exist.
+    match resolver.srv_lookup(backend.name.clone() + ".").await {
+        Ok(unexpected) => {
+            panic!("Expected NODATA, got record {:?}", unexpected);
+        }
+        Err(e) => match e.kind() {
+            ResolveErrorKind::NoRecordsFound { response_code, .. } => {
+                assert_eq!(*response_code, ResponseCode::NoError);
+            }
+            unexpected => {
+                panic!("Expected NODATA, got error {:?}", unexpected);
+            }
+        },
+    };
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn record_types() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server("record_types").await?;
+    let client = &test_ctx.client;
+    let resolver = &test_ctx.resolver;
+
+    // Before anything is added, the apex of the zone has only an SOA record.
+    let soa = resolver.soa_lookup("oxide.internal.").await?;
+    let soa = soa.iter().next().expect("no SOA record returned");
+    assert_eq!(soa.mname().to_string(), "oxide.internal.");
+    let initial_serial = soa.serial();
+
+    let addr4 = Ipv4Addr::new(192, 168, 1, 1);
+    let addr6 = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x1);
+    let text = "a".repeat(300);
+    client
+        .dns_records_create(
+            TEST_ZONE,
+            &vec![
+                DnsKv {
+                    key: DnsRecordKey { name: "oxide.internal".into() },
+                    records: vec![DnsRecord::Ns("ns1.oxide.internal".into())],
+                },
+                DnsKv {
+                    key: DnsRecordKey { name: "ns1.oxide.internal".into() },
+                    records: vec![DnsRecord::Aaaa(addr6)],
+                },
+                DnsKv {
+                    key: DnsRecordKey { name: "v4.oxide.internal".into() },
+                    records: vec![DnsRecord::A(addr4)],
+                },
+                DnsKv {
+                    key: DnsRecordKey { name: "alias.oxide.internal".into() },
+                    records: vec![DnsRecord::Cname(
+                        "ns1.oxide.internal".into(),
+                    )],
+                },
+                DnsKv {
+                    key: DnsRecordKey { name: "text.oxide.internal".into() },
+                    records: vec![DnsRecord::Txt(text.clone())],
+                },
+                DnsKv {
+                    key: DnsRecordKey { name: "ptr.oxide.internal".into() },
+                    records: vec![DnsRecord::Ptr("v4.oxide.internal".into())],
+                },
+            ],
+        )
+        .await?;
+
+    // The SOA record names the zone's name server, and its serial number has
+    // changed along with the data.
+    let soa = resolver.soa_lookup("oxide.internal.").await?;
+    let soa = soa.iter().next().expect("no SOA record returned");
+    assert_eq!(soa.mname().to_string(), "ns1.oxide.internal.");
+    assert_ne!(soa.serial(), initial_serial);
+
+    let ns = resolver.ns_lookup("oxide.internal.").await?;
+    let ns = ns.iter().map(|ns| ns.to_string()).collect::<Vec<_>>();
+    assert_eq!(ns, vec!["ns1.oxide.internal."]);
+
+    let response = resolver.ipv4_lookup("v4.oxide.internal.").await?;
+    assert_eq!(response.iter().collect::<Vec<_>>(), vec![&addr4]);
+
+    // Looking up an alias gets the canonical name's addresses.
+    let response = resolver.lookup_ip("alias.oxide.internal.").await?;
+    assert_eq!(response.iter().collect::<Vec<_>>(), vec![IpAddr::V6(addr6)]);
+
+    // Long text is split into several strings, which clients put back
+    // together.
+    let response = resolver.txt_lookup("text.oxide.internal.").await?;
+    let txt = response.iter().next().expect("no TXT record returned");
+    assert_eq!(txt.txt_data().len(), 2);
+    let received = txt.txt_data().concat();
+    assert_eq!(received, text.as_bytes());
+
+    let response =
+        resolver.lookup("ptr.oxide.internal.", RecordType::PTR).await?;
+    let targets = response
+        .iter()
+        .map(|rdata| match rdata {
+            RData::PTR(target) => target.to_string(),
+            other => panic!("expected PTR record, got {:?}", other),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(targets, vec!["v4.oxide.internal."]);
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn config_generations() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server("config_generations").await?;
+    let client = &test_ctx.client;
+    let resolver = &test_ctx.resolver;
+
+    // A server which has never been configured is at generation 0.
+    let config = client.dns_config_get(TEST_ZONE).await?;
+    assert_eq!(config.generation, 0);
+    assert!(config.records.is_empty());
+
+    let addr1 = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x1);
+    let addr2 = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x2);
+    let config1 = DnsConfig {
+        generation: 1,
+        records: vec![DnsKv {
+            key: DnsRecordKey { name: "gen1.oxide.internal".into() },
+            records: vec![DnsRecord::Aaaa(addr1)],
+        }],
+    };
+    client.dns_config_put(TEST_ZONE, &config1).await?;
+
+    let config = client.dns_config_get(TEST_ZONE).await?;
+    assert_eq!(config.generation, 1);
+    assert_eq!(config.records.len(), 1);
+    assert_eq!(config.records[0].key.name, "gen1.oxide.internal");
+    let response = resolver.lookup_ip("gen1.oxide.internal.").await?;
+    assert_eq!(response.iter().collect::<Vec<_>>(), vec![IpAddr::V6(addr1)]);
+
+    // The SOA serial follows the generation.
+    let soa = resolver.soa_lookup("oxide.internal.").await?;
+    let soa = soa.iter().next().expect("no SOA record returned");
+    assert_eq!(soa.serial(), 1);
+
+    // Putting the same generation again is fine.
+    client.dns_config_put(TEST_ZONE, &config1).await?;
+
+    // Putting an older one is not, and changes nothing.
+    let error = client
+        .dns_config_put(
+            TEST_ZONE,
+            &DnsConfig { generation: 0, records: vec![] },
+        )
+        .await
+        .expect_err("putting an older generation should fail");
+    assert_eq!(error.status().map(|s| s.as_u16()), Some(409));
+    let config = client.dns_config_get(TEST_ZONE).await?;
+    assert_eq!(config.generation, 1);
+
+    // A newer generation replaces all of the records at once.
+    client
+        .dns_config_put(
+            TEST_ZONE,
+            &DnsConfig {
+                generation: 2,
+                records: vec![DnsKv {
+                    key: DnsRecordKey { name: "gen2.oxide.internal".into() },
+                    records: vec![DnsRecord::Aaaa(addr2)],
+                }],
+            },
+        )
+        .await?;
+    let config = client.dns_config_get(TEST_ZONE).await?;
+    assert_eq!(config.generation, 2);
+    assert_eq!(config.records.len(), 1);
+    lookup_ip_expect_nxdomain(resolver, "gen1.oxide.internal.").await;
+    let response = resolver.lookup_ip("gen2.oxide.internal.").await?;
+    assert_eq!(response.iter().collect::<Vec<_>>(), vec![IpAddr::V6(addr2)]);
+
+    // Changing individual records leaves the generation alone, so that it
+    // can't collide with the next configuration that's put.
+    client
+        .dns_records_delete(
+            TEST_ZONE,
+            &vec![DnsRecordKey { name: "gen2.oxide.internal".into() }],
+        )
+        .await?;
+    client
+        .dns_records_create(
+            TEST_ZONE,
+            &vec![DnsKv {
+                key: DnsRecordKey { name: "extra.oxide.internal".into() },
+                records: vec![DnsRecord::Aaaa(addr1)],
+            }],
+        )
+        .await?;
+    let config = client.dns_config_get(TEST_ZONE).await?;
+    assert_eq!(config.generation, 2);
+    assert_eq!(config.records.len(), 1);
+    assert_eq!(config.records[0].key.name, "extra.oxide.internal");
+
+    // The next configuration replaces those changes too.
+    let config3 = DnsConfig {
+        generation: 3,
+        records: vec![DnsKv {
+            key: DnsRecordKey { name: "gen3.oxide.internal".into() },
+            records: vec![DnsRecord::Aaaa(addr1)],
+        }],
+    };
+    client.dns_config_put(TEST_ZONE, &config3).await?;
+    let config = client.dns_config_get(TEST_ZONE).await?;
+    assert_eq!(config.generation, 3);
+    assert_eq!(config.records.len(), 1);
+    assert_eq!(config.records[0].key.name, "gen3.oxide.internal");
+    lookup_ip_expect_nxdomain(resolver, "extra.oxide.internal.").await;
+    let response = resolver.lookup_ip("gen3.oxide.internal.").await?;
+    assert_eq!(response.iter().collect::<Vec<_>>(), vec![IpAddr::V6(addr1)]);
+
+    // A configuration at the current generation which the zone no longer
+    // has, because individual records were changed since, isn't mistaken
+    // for one that's already been applied.
+    client
+        .dns_records_delete(
+            TEST_ZONE,
+            &vec![DnsRecordKey { name: "gen3.oxide.internal".into() }],
+        )
+        .await?;
+    client.dns_config_put(TEST_ZONE, &config3).await?;
+    let config = client.dns_config_get(TEST_ZONE).await?;
+    assert_eq!(config.generation, 3);
+    assert_eq!(config.records.len(), 1);
+    let response = resolver.lookup_ip("gen3.oxide.internal.").await?;
+    assert_eq!(response.iter().collect::<Vec<_>>(), vec![IpAddr::V6(addr1)]);
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn multiple_zones() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server("multiple_zones").await?;
+    let client = &test_ctx.client;
+    let resolver = &test_ctx.resolver;
+
+    // Zone names are stored in canonical form, so creating one that already
+    // exists (under any spelling) changes nothing.
+    client.dns_zone_create("Example.com.").await?;
+    client.dns_zone_create("example.com").await?;
+    client.dns_zone_create("sub.oxide.internal").await?;
+    let zones = client.dns_zones_list().await?;
+    assert_eq!(
+        zones.into_inner(),
+        vec!["example.com", "oxide.internal", "sub.oxide.internal"]
+    );
+
+    // Each zone has its own records, which can only be for names in the zone.
+    let addr4 = Ipv4Addr::new(192, 0, 2, 1);
+    let www = DnsKv {
+        key: DnsRecordKey { name: "www.example.com".into() },
+        records: vec![DnsRecord::A(addr4)],
+    };
+    let error = client
+        .dns_records_create(TEST_ZONE, &vec![www.clone()])
+        .await
+        .expect_err("adding a record outside of the zone should fail");
+    assert_eq!(error.status().map(|s| s.as_u16()), Some(400));
+    client.dns_records_create("example.com", &vec![www]).await?;
+    assert!(client.dns_records_list(TEST_ZONE).await?.is_empty());
+    assert_eq!(client.dns_config_get(TEST_ZONE).await?.generation, 0);
+    assert_eq!(client.dns_config_get("example.com").await?.generation, 1);
+
+    let response = resolver.ipv4_lookup("www.example.com.").await?;
+    assert_eq!(response.iter().collect::<Vec<_>>(), vec![&addr4]);
+    let soa = resolver.soa_lookup("example.com.").await?;
+    let soa = soa.iter().next().expect("no SOA record returned");
+    assert_eq!(soa.mname().to_string(), "example.com.");
+    assert_eq!(soa.serial(), 1);
+
+    // Names are answered from the most specific zone that they're in.
+    let addr1 = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x1);
+    let addr2 = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x2);
+    for (zone, addr) in [(TEST_ZONE, addr1), ("sub.oxide.internal", addr2)] {
+        client
+            .dns_records_create(
+                zone,
+                &vec![DnsKv {
+                    key: DnsRecordKey {
+                        name: "host.sub.oxide.internal".into(),
+                    },
+                    records: vec![DnsRecord::Aaaa(addr)],
+                }],
+            )
+            .await?;
+    }
+    let response = resolver.ipv6_lookup("host.sub.oxide.internal.").await?;
+    assert_eq!(response.iter().collect::<Vec<_>>(), vec![&addr2]);
+    let soa = resolver.soa_lookup("sub.oxide.internal.").await?;
+    let soa = soa.iter().next().expect("no SOA record returned");
+    assert_eq!(soa.mname().to_string(), "sub.oxide.internal.");
+
+    // Removing a zone removes its records, and the server stops answering
+    // for it.
+    client.dns_zone_delete("example.com").await?;
+    let zones = client.dns_zones_list().await?;
+    assert_eq!(
+        zones.into_inner(),
+        vec!["oxide.internal", "sub.oxide.internal"]
+    );
+    let error = client
+        .dns_records_list("example.com")
+        .await
+        .expect_err("listing the records of a removed zone should fail");
+    assert_eq!(error.status().map(|s| s.as_u16()), Some(404));
+    let error = resolver
+        .lookup_ip("www.example.com.")
+        .await
+        .expect_err("looking up a name in a removed zone should fail");
+    assert!(
+        matches!(
+            error.kind(),
+            ResolveErrorKind::NoRecordsFound {
+                response_code: ResponseCode::ServFail,
+                ..
+            }
+        ),
+        "Saw error: {error}",
+    );
+
+    // A zone created again with the same name starts out empty.
+    client.dns_zone_create("example.com").await?;
+    assert!(client.dns_records_list("example.com").await?.is_empty());
+    assert_eq!(client.dns_config_get("example.com").await?.generation, 0);
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn legacy_records_migrated() -> Result<(), anyhow::Error> {
+    let (tmp, config, logctx) = test_config("legacy_records_migrated")?;
+    let log = logctx.log.clone();
+
+    // Store records the way a server that only served a single zone did: in
+    // the default tree, with their generation in a separate tree.
+    let addr = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x1);
+    {
+        let db = sled::open(&config.data.storage_path)?;
+        db.insert(
+            "old.oxide.internal",
+            serde_json::to_vec(&vec![DnsRecord::Aaaa(addr)])?,
+        )?;
+        db.open_tree("metadata")?
+            .insert("generation", 4u64.to_be_bytes().to_vec())?;
+        db.flush()?;
+    }
+
+    let (dns_server, dropshot_server) = dns_server::start(
+        log.clone(),
+        config,
+        TEST_ZONE.to_string(),
+        "[::1]:0".parse().unwrap(),
+    )
+    .await?;
+    let client =
+        Client::new(&format!("http://{}", dropshot_server.local_addr()), log);
+    let resolver = make_resolver(
+        dns_server.address,
+        &[Protocol::Udp],
+        ResolverOpts::default(),
+    );
+
+    // The records and their generation now belong to the zone.
+    let config = client.dns_config_get(TEST_ZONE).await?;
+    assert_eq!(config.generation, 4);
+    assert_eq!(config.records.len(), 1);
+    assert_eq!(config.records[0].key.name, "old.oxide.internal");
+    let response = resolver.lookup_ip("old.oxide.internal.").await?;
+    assert_eq!(response.iter().collect::<Vec<_>>(), vec![IpAddr::V6(addr)]);
+
+    drop(dns_server);
+    dropshot_server.close().await.expect("Failed to clean up server");
+    tmp.close().expect("Failed to clean up tmp directory");
+    logctx.cleanup_successful();
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn tcp() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server("tcp").await?;
+    let client = &test_ctx.client;
+    let resolver = make_resolver(
+        test_ctx.dns_server.address,
+        &[Protocol::Tcp],
+        ResolverOpts::default(),
+    );
+
+    let name = DnsRecordKey { name: "devron.oxide.internal".into() };
+    let addr = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x1);
+    client
+        .dns_records_create(
+            TEST_ZONE,
+            &vec![DnsKv {
+                key: name.clone(),
+                records: vec![DnsRecord::Aaaa(addr)],
+            }],
+        )
+        .await?;
+
+    // resolve the name over TCP
+    let response = resolver.ipv6_lookup(name.name + ".").await?;
+    let addrs = response.iter().collect::<Vec<_>>();
+    assert_eq!(addrs, vec![&addr]);
+
+    // names which don't exist are still reported as such
+    match resolver.lookup_ip("unicorn.oxide.internal.").await {
+        Ok(unexpected) => {
+            panic!("Expected NXDOMAIN, got record {:?}", unexpected);
+        }
+        Err(e) => match e.kind() {
+            ResolveErrorKind::NoRecordsFound { response_code, .. } => {
+                assert_eq!(*response_code, ResponseCode::NXDomain);
+            }
+            unexpected => {
+                panic!("Expected NXDOMAIN, got error {:?}", unexpected);
+            }
+        },
+    };
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn truncation() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server("truncation").await?;
+    let client = &test_ctx.client;
+
+    // Add enough SRV records that they don't fit in the 512 bytes that UDP
+    // clients accept by default, but do fit in what EDNS clients accept.
+    const NRECORDS: u16 = 24;
+    let name = DnsRecordKey { name: "nexus.oxide.internal".into() };
+    let records = (0..NRECORDS)
+        .map(|i| {
+            DnsRecord::Srv(Srv {
+                prio: 0,
+                weight: 0,
+                port: 12221 + i,
+                target: format!("backend-{:02}.oxide.internal", i),
+            })
+        })
+        .collect();
+    client
+        .dns_records_create(
+            TEST_ZONE,
+            &vec![DnsKv { key: name.clone(), records }],
+        )
+        .await?;
+    let fqdn = name.name + ".";
+
+    // A UDP-only client without EDNS gets a truncated response, and has
+    // nowhere else to look for the rest.
+    let resolver = make_resolver(
+        test_ctx.dns_server.address,
+        &[Protocol::Udp],
+        ResolverOpts::default(),
+    );
+    let response = resolver.srv_lookup(fqdn.clone()).await?;
+    assert!(response.iter().count() < usize::from(NRECORDS));
+
+    // A client that can also use TCP retries the truncated response there.
+    let resolver = make_resolver(
+        test_ctx.dns_server.address,
+        &[Protocol::Udp, Protocol::Tcp],
+        ResolverOpts::default(),
+    );
+    let response = resolver.srv_lookup(fqdn.clone()).await?;
+    assert_eq!(response.iter().count(), usize::from(NRECORDS));
+
+    // A UDP-only client that advertises a larger buffer using EDNS gets the
+    // whole response over UDP.
+    let resolver = make_resolver(
+        test_ctx.dns_server.address,
+        &[Protocol::Udp],
+        ResolverOpts { edns0: true, ..ResolverOpts::default() },
+    );
+    let response = resolver.srv_lookup(fqdn).await?;
+    assert_eq!(response.iter().count(), usize::from(NRECORDS));
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
 async fn lookup_ip_expect_nxdomain(resolver: &TokioAsyncResolver, name: &str) {
     match resolver.lookup_ip(name).await {
         Ok(unexpected) => {
@@ -208,23 +755,25 @@ async fn lookup_ip_expect_nxdomain(resolver: &TokioAsyncResolver, name: &str) {
 
 #[tokio::test]
 pub async fn empty_record() -> Result<(), anyhow::Error> {
-    let test_ctx =
-        init_client_server("empty_record", "oxide.internal".into()).await?;
+    let test_ctx = init_client_server("empty_record").await?;
     let client = &test_ctx.client;
     let resolver = &test_ctx.resolver;
 
     // records should initially be empty
-    let records = client.dns_records_list().await?;
+    let records = client.dns_records_list(TEST_ZONE).await?;
     assert!(records.is_empty());
 
     // Add an empty DNS record
     let name = DnsRecordKey { name: "devron.oxide.internal".into() };
     client
-        .dns_records_create(&vec![DnsKv { key: name.clone(), records: vec![] }])
+        .dns_records_create(
+            TEST_ZONE,
+            &vec![DnsKv { key: name.clone(), records: vec![] }],
+        )
         .await?;
 
     // read back the aaaa record
-    let records = client.dns_records_list().await?;
+    let records = client.dns_records_list(TEST_ZONE).await?;
     assert_eq!(1, records.len());
     assert_eq!(records[0].key.name, name.name);
     assert_eq!(0, records[0].records.len());
@@ -238,8 +787,7 @@ pub async fn empty_record() -> Result<(), anyhow::Error> {
 
 #[tokio::test]
 pub async fn nxdomain() -> Result<(), anyhow::Error> {
-    let test_ctx =
-        init_client_server("nxdomain", "oxide.internal".into()).await?;
+    let test_ctx = init_client_server("nxdomain").await?;
     let resolver = &test_ctx.resolver;
 
     // asking for a nonexistent record within the domain of the internal DNS
@@ -252,8 +800,7 @@ pub async fn nxdomain() -> Result<(), anyhow::Error> {
 
 #[tokio::test]
 pub async fn servfail() -> Result<(), anyhow::Error> {
-    let test_ctx =
-        init_client_server("servfail", "oxide.internal".into()).await?;
+    let test_ctx = init_client_server("servfail").await?;
     let resolver = &test_ctx.resolver;
 
     // asking for a record outside the domain of the internal DNS
@@ -288,6 +835,87 @@ pub async fn servfail() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+#[tokio::test]
+pub async fn query_metrics() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server("query_metrics").await?;
+    let client = &test_ctx.client;
+    let resolver = &test_ctx.resolver;
+
+    let name = DnsRecordKey { name: "devron.oxide.internal".into() };
+    let addr = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x1);
+    client
+        .dns_records_create(
+            TEST_ZONE,
+            &vec![DnsKv {
+                key: name.clone(),
+                records: vec![DnsRecord::Aaaa(addr)],
+            }],
+        )
+        .await?;
+
+    // one query that's answered, one for a name that doesn't exist, and one
+    // for a name outside of any zone
+    resolver.ipv6_lookup(name.name + ".").await?;
+    resolver
+        .ipv6_lookup("unicorn.oxide.internal.")
+        .await
+        .expect_err("unexpectedly resolved a nonexistent name");
+    resolver
+        .ipv6_lookup("oxide.computer.")
+        .await
+        .expect_err("unexpectedly resolved a name outside of the zone");
+
+    let samples = test_ctx
+        .dns_server
+        .metrics
+        .clone()
+        .produce()
+        .expect("failed to produce samples")
+        .collect::<Vec<_>>();
+    for sample in &samples {
+        assert_eq!(
+            sample.target_fields()[0].value,
+            FieldValue::Uuid(test_ctx.dns_server.metrics.id())
+        );
+    }
+
+    // Resolvers may retry a SERVFAIL, so only the other counts are exact.
+    assert!(count(&samples, "dns_server:queries", "AAAA").unwrap() >= 3);
+    assert_eq!(count(&samples, "dns_server:zone_queries", TEST_ZONE), Some(2));
+    assert!(count(&samples, "dns_server:zone_queries", "").unwrap() >= 1);
+    assert_eq!(count(&samples, "dns_server:responses", "NoError"), Some(1));
+    assert_eq!(count(&samples, "dns_server:responses", "NXDomain"), Some(1));
+    assert!(count(&samples, "dns_server:responses", "ServFail").unwrap() >= 1);
+
+    let latency = samples
+        .iter()
+        .find(|s| s.timeseries_name == "dns_server:query_latency")
+        .expect("no latency sample");
+    match latency.measurement.datum() {
+        Datum::HistogramF64(histogram) => {
+            assert!(histogram.n_samples() >= 3);
+        }
+        other => panic!("unexpected latency datum {:?}", other),
+    }
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+/// Returns the value of the counter in `samples` from the timeseries named
+/// `timeseries` with the metric field `value`
+fn count(samples: &[Sample], timeseries: &str, value: &str) -> Option<i64> {
+    let sample = samples.iter().find(|s| {
+        s.timeseries_name == timeseries
+            && s.metric_fields()[0].value
+                == FieldValue::String(value.to_string())
+    })?;
+    match sample.measurement.datum() {
+        Datum::CumulativeI64(count) => Some(count.value()),
+        other => panic!("unexpected datum {:?}", other),
+    }
+}
+
 struct TestContext {
     client: Client,
     resolver: TokioAsyncResolver,
@@ -309,7 +937,6 @@ impl TestContext {
 
 async fn init_client_server(
     test_name: &str,
-    zone: String,
 ) -> Result<TestContext, anyhow::Error> {
     // initialize dns server config
     let (tmp, config, logctx) = test_config(test_name)?;
@@ -323,25 +950,18 @@ async fn init_client_server(
     let dns_server = {
         let db = db.clone();
         let log = log.clone();
-        let dns_config = dns_server::dns_server::Config {
-            bind_address: "[::1]:0".into(),
-            zone,
-        };
+        let dns_config =
+            dns_server::dns_server::Config { bind_address: "[::1]:0".into() };
 
-        dns_server::dns_server::run(log, db, dns_config).await?
+        let metrics = dns_server::metrics::Metrics::new(Uuid::new_v4());
+        dns_server::dns_server::run(log, db, dns_config, metrics).await?
     };
 
-    let mut rc = ResolverConfig::new();
-    rc.add_name_server(NameServerConfig {
-        socket_addr: dns_server.address,
-        protocol: Protocol::Udp,
-        tls_dns_name: None,
-        trust_nx_responses: false,
-        bind_addr: None,
-    });
-
-    let resolver =
-        TokioAsyncResolver::tokio(rc, ResolverOpts::default()).unwrap();
+    let resolver = make_resolver(
+        dns_server.address,
+        &[Protocol::Udp],
+        ResolverOpts::default(),
+    );
 
     // launch a dropshot server
     let dropshot_server =
@@ -352,6 +972,7 @@ async fn init_client_server(
 
     let client =
         Client::new(&format!("http://{}", dropshot_server.local_addr()), log);
+    client.dns_zone_create(TEST_ZONE).await?;
 
     Ok(TestContext {
         client,
@@ -363,6 +984,27 @@ async fn init_client_server(
     })
 }
 
+/// Returns a resolver which talks to the DNS server at `address` using each
+/// of `protocols`
+fn make_resolver(
+    address: SocketAddr,
+    protocols: &[Protocol],
+    opts: ResolverOpts,
+) -> TokioAsyncResolver {
+    let mut rc = ResolverConfig::new();
+    for protocol in protocols {
+        rc.add_name_server(NameServerConfig {
+            socket_addr: address,
+            protocol: *protocol,
+            tls_dns_name: None,
+            trust_nx_responses: false,
+            bind_addr: None,
+        });
+    }
+
+    TokioAsyncResolver::tokio(rc, opts).unwrap()
+}
+
 fn test_config(
     test_name: &str,
 ) -> Result<(tempdir::TempDir, dns_server::Config, LogContext), anyhow::Error> {
@@ -382,6 +1024,7 @@ fn test_config(
             ..Default::default()
         },
         data: dns_server::dns_data::Config { nmax_messages: 16, storage_path },
+        metrics: None,
     };
 
     Ok((tmp_dir, config, logctx))
@@ -2,12 +2,15 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::types::{DnsKv, DnsRecord, DnsRecordKey, Srv};
+use crate::types::{DnsConfig, DnsKv, DnsRecord, DnsRecordKey, Srv};
+use futures::lock::Mutex;
 use futures::stream::{self, StreamExt, TryStreamExt};
+use internal_dns_names::DNS_ZONE;
 use omicron_common::address::{
     Ipv6Subnet, ReservedRackSubnet, AZ_PREFIX, DNS_PORT, DNS_SERVER_PORT,
 };
 use slog::{info, Logger};
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
 use trust_dns_proto::rr::record_type::RecordType;
@@ -69,19 +72,33 @@ impl DnsAddressLookup for ServerAddresses {
     }
 }
 
-/// A connection used to update multiple DNS servers.
+/// A connection used to update one zone on multiple DNS servers.
 pub struct Updater {
     log: Logger,
+    zone: String,
     clients: Vec<crate::Client>,
+    // Serializes changes to individual records, each of which reads the
+    // current configuration and puts the next generation of it.
+    update_lock: Mutex<()>,
 }
 
 impl Updater {
+    /// Creates an updater for the internal DNS zone.
     pub fn new(address_getter: &impl DnsAddressLookup, log: Logger) -> Self {
+        Self::new_for_zone(address_getter, DNS_ZONE, log)
+    }
+
+    /// Creates an updater for an arbitrary zone, such as an external one.
+    pub fn new_for_zone(
+        address_getter: &impl DnsAddressLookup,
+        zone: &str,
+        log: Logger,
+    ) -> Self {
         let addrs = address_getter.dropshot_server_addrs();
-        Self::new_from_addrs(addrs, log)
+        Self::new_from_addrs(addrs, zone, log)
     }
 
-    fn new_from_addrs(addrs: Vec<SocketAddr>, log: Logger) -> Self {
+    fn new_from_addrs(addrs: Vec<SocketAddr>, zone: &str, log: Logger) -> Self {
         let clients = addrs
             .into_iter()
             .map(|addr| {
@@ -90,7 +107,12 @@ impl Updater {
             })
             .collect::<Vec<_>>();
 
-        Self { log, clients }
+        Self {
+            log,
+            zone: zone.to_string(),
+            clients,
+            update_lock: Mutex::new(()),
+        }
     }
 
     /// Inserts all service records into the DNS server.
@@ -100,22 +122,22 @@ impl Updater {
         &self,
         records: &HashMap<internal_dns_names::SRV, Vec<AAAARecord>>,
     ) -> Result<(), DnsError> {
+        let mut body = Vec::new();
         for (srv, aaaa) in records.iter() {
             info!(self.log, "Inserting DNS record: {:?}", srv);
 
-            self.insert_dns_records_internal(aaaa, srv).await?;
+            body.extend(Self::dns_records_for_service(aaaa, srv));
         }
-        Ok(())
+        self.dns_records_set(&body).await
     }
 
-    // Utility function to insert:
+    // Utility function to create:
     // - A set of uniquely-named AAAA records, each corresponding to an address
     // - An SRV record, pointing to each of the AAAA records.
-    async fn insert_dns_records_internal(
-        &self,
+    fn dns_records_for_service(
         aaaa: &Vec<AAAARecord>,
         srv_key: &internal_dns_names::SRV,
-    ) -> Result<(), DnsError> {
+    ) -> Vec<DnsKv> {
         let mut records = Vec::with_capacity(aaaa.len() + 1);
 
         // Add one DnsKv per AAAA, each with a single record.
@@ -139,20 +161,22 @@ impl Updater {
                 })
                 .collect::<Vec<_>>(),
         });
-        self.dns_records_set(&records).await
+        records
     }
 
-    /// Sets a records on all DNS servers.
+    /// Returns the zone that this updater manages.
+    pub fn zone(&self) -> &str {
+        &self.zone
+    }
+
+    /// Creates the zone on all DNS servers that do not already serve it.
     ///
-    /// Returns an error if setting the record fails on any server.
-    pub async fn dns_records_set<'a>(
-        &'a self,
-        body: &'a Vec<crate::types::DnsKv>,
-    ) -> Result<(), DnsError> {
+    /// Returns an error if creating the zone fails on any server.
+    pub async fn dns_zone_create(&self) -> Result<(), DnsError> {
         stream::iter(&self.clients)
             .map(Ok::<_, DnsError>)
             .try_for_each_concurrent(None, |client| async move {
-                client.dns_records_create(body).await?;
+                client.dns_zone_create(&self.zone).await?;
                 Ok(())
             })
             .await?;
@@ -160,17 +184,102 @@ impl Updater {
         Ok(())
     }
 
-    /// Deletes records in all DNS servers.
+    /// Sets records in the zone on all DNS servers, replacing any records
+    /// with the same names.
     ///
-    /// Returns an error if deleting the record fails on any server.
-    pub async fn dns_records_delete<'a>(
-        &'a self,
-        body: &'a Vec<crate::types::DnsRecordKey>,
+    /// See [`Updater::dns_config_update`].  Returns an error if setting the
+    /// records fails on any server.
+    pub async fn dns_records_set(
+        &self,
+        body: &Vec<crate::types::DnsKv>,
     ) -> Result<(), DnsError> {
+        self.dns_config_update(|records| {
+            for kv in body {
+                records.insert(kv.key.name.clone(), kv.clone());
+            }
+        })
+        .await
+    }
+
+    /// Deletes records in the zone in all DNS servers.
+    ///
+    /// See [`Updater::dns_config_update`].  Returns an error if deleting the
+    /// records fails on any server.
+    pub async fn dns_records_delete(
+        &self,
+        body: &Vec<crate::types::DnsRecordKey>,
+    ) -> Result<(), DnsError> {
+        self.dns_config_update(|records| {
+            for key in body {
+                records.remove(&key.name);
+            }
+        })
+        .await
+    }
+
+    /// Changes the records of the zone with `update`, and puts the result on
+    /// all DNS servers as a new configuration.
+    ///
+    /// The records changed are those of the newest configuration on any
+    /// server, and the new configuration is the generation after that one, so
+    /// that each generation has a single set of records.  Changes made through
+    /// this updater are applied one at a time, each building on the last.
+    async fn dns_config_update<F>(&self, update: F) -> Result<(), DnsError>
+    where
+        F: FnOnce(&mut BTreeMap<String, DnsKv>),
+    {
+        let _guard = self.update_lock.lock().await;
+        let Some(newest) = self
+            .dns_config_get()
+            .await?
+            .into_iter()
+            .max_by_key(|config| config.generation)
+        else {
+            return Ok(());
+        };
+        let mut records: BTreeMap<_, _> = newest
+            .records
+            .into_iter()
+            .map(|kv| (kv.key.name.clone(), kv))
+            .collect();
+        update(&mut records);
+        self.dns_config_put(&DnsConfig {
+            generation: newest.generation + 1,
+            records: records.into_values().collect(),
+        })
+        .await
+    }
+
+    /// Fetches the current configuration of the zone from all DNS servers.
+    ///
+    /// Returns an error if fetching it fails on any server, including because
+    /// the server does not serve the zone yet.
+    pub async fn dns_config_get(&self) -> Result<Vec<DnsConfig>, DnsError> {
+        stream::iter(&self.clients)
+            .then(|client| async move {
+                Ok::<_, DnsError>(
+                    client.dns_config_get(&self.zone).await?.into_inner(),
+                )
+            })
+            .try_collect()
+            .await
+    }
+
+    /// Replaces the configuration of the zone on all DNS servers.
+    ///
+    /// Servers which already have this generation are left alone, so this
+    /// may be used to bring up to date any servers which missed an earlier
+    /// update.  Returns an error if any server fails to apply the
+    /// configuration, including because it already has a newer one.
+    pub async fn dns_config_put(
+        &self,
+        config: &DnsConfig,
+    ) -> Result<(), DnsError> {
+        info!(self.log, "Putting DNS config"; "generation" => config.generation);
         stream::iter(&self.clients)
             .map(Ok::<_, DnsError>)
             .try_for_each_concurrent(None, |client| async move {
-                client.dns_records_delete(body).await?;
+                client.dns_config_put(&self.zone, config).await?;
                 Ok(())
             })
             .await?;
@@ -329,10 +438,13 @@ mod test {
                 let log = log.clone();
                 let dns_config = dns_server::dns_server::Config {
                     bind_address: "[::1]:0".to_string(),
-                    zone: internal_dns_names::DNS_ZONE.into(),
                 };
 
-                dns_server::dns_server::run(log, db, dns_config).await.unwrap()
+                let metrics =
+                    dns_server::metrics::Metrics::new(uuid::Uuid::new_v4());
+                dns_server::dns_server::run(log, db, dns_config, metrics)
+                    .await
+                    .unwrap()
             };
 
             let config = dns_server::Config {
@@ -348,12 +460,20 @@ mod test {
                     nmax_messages: 16,
                     storage_path: storage.path().to_string_lossy().into(),
                 },
+                metrics: None,
             };
 
             let dropshot_server =
                 dns_server::start_dropshot_server(config, log.clone(), db)
                     .await
                     .unwrap();
+            crate::Client::new(
+                &format!("http://{}", dropshot_server.local_addr()),
+                log.clone(),
+            )
+            .dns_zone_create(DNS_ZONE)
+            .await
+            .unwrap();
 
             Self { _storage: storage, dns_server, dropshot_server }
         }
@@ -646,4 +766,166 @@ mod test {
 
         logctx.cleanup_successful();
     }
+
+    // Changes to records are put on every server as new configurations, one
+    // generation at a time.
+    #[tokio::test]
+    async fn record_changes_put_new_configs() {
+        let logctx = test_setup_log("record_changes_put_new_configs");
+        let dns_servers = [
+            DnsServer::create(&logctx.log).await,
+            DnsServer::create(&logctx.log).await,
+        ];
+
+        let mut address_getter = LocalAddressGetter::default();
+        for dns_server in &dns_servers {
+            address_getter.add_dns_server(
+                dns_server.dns_server_address(),
+                dns_server.dropshot_server_address(),
+            );
+        }
+        let updater = Updater::new(&address_getter, logctx.log.clone());
+
+        let records = HashMap::from([(
+            SRV::Service(ServiceName::Cockroach),
+            vec![(
+                AAAA::Zone(Uuid::new_v4()),
+                SocketAddrV6::new(
+                    Ipv6Addr::from_str("ff::01").unwrap(),
+                    12345,
+                    0,
+                    0,
+                ),
+            )],
+        )]);
+        updater.insert_dns_records(&records).await.unwrap();
+        for config in updater.dns_config_get().await.unwrap() {
+            assert_eq!(config.generation, 1);
+            assert_eq!(config.records.len(), 2);
+        }
+
+        // Changes build on the newest configuration, even if some server
+        // missed it.
+        let first = crate::Client::new(
+            &format!("http://{}", dns_servers[0].dropshot_server_address()),
+            logctx.log.clone(),
+        );
+        first
+            .dns_config_put(
+                DNS_ZONE,
+                &DnsConfig {
+                    generation: 2,
+                    records: vec![DnsKv {
+                        key: DnsRecordKey { name: "extra".to_string() },
+                        records: vec![DnsRecord::Aaaa(
+                            Ipv6Addr::from_str("ff::02").unwrap(),
+                        )],
+                    }],
+                },
+            )
+            .await
+            .unwrap();
+        updater
+            .dns_records_delete(&vec![DnsRecordKey {
+                name: "extra".to_string(),
+            }])
+            .await
+            .unwrap();
+        for config in updater.dns_config_get().await.unwrap() {
+            assert_eq!(config.generation, 3);
+            assert!(config.records.is_empty());
+        }
+
+        logctx.cleanup_successful();
+    }
+
+    // Putting a configuration brings every server up to date, including ones
+    // which missed earlier configurations.
+    #[tokio::test]
+    async fn put_config_to_stale_server() {
+        let logctx = test_setup_log("put_config_to_stale_server");
+        let dns_servers = [
+            DnsServer::create(&logctx.log).await,
+            DnsServer::create(&logctx.log).await,
+        ];
+
+        let mut address_getter = LocalAddressGetter::default();
+        for dns_server in &dns_servers {
+            address_getter.add_dns_server(
+                dns_server.dns_server_address(),
+                dns_server.dropshot_server_address(),
+            );
+        }
+        let updater = Updater::new(&address_getter, logctx.log.clone());
+
+        let srv_crdb = SRV::Service(ServiceName::Cockroach);
+        let aaaa = AAAA::Zone(Uuid::new_v4());
+        let addr = Ipv6Addr::from_str("ff::01").unwrap();
+        let config = |generation| crate::types::DnsConfig {
+            generation,
+            records: vec![
+                DnsKv {
+                    key: DnsRecordKey { name: aaaa.to_string() },
+                    records: vec![DnsRecord::Aaaa(addr)],
+                },
+                DnsKv {
+                    key: DnsRecordKey { name: srv_crdb.to_string() },
+                    records: vec![DnsRecord::Srv(Srv {
+                        prio: 0,
+                        weight: 0,
+                        port: 12345,
+                        target: aaaa.to_string(),
+                    })],
+                },
+            ],
+        };
+
+        // Only the first server sees generation 1.
+        let first = crate::Client::new(
+            &format!("http://{}", dns_servers[0].dropshot_server_address()),
+            logctx.log.clone(),
+        );
+        first.dns_config_put(DNS_ZONE, &config(1)).await.unwrap();
+
+        // Putting it again everywhere brings the second one up to date.
+        updater.dns_config_put(&config(1)).await.unwrap();
+        for dns_server in &dns_servers {
+            let resolver =
+                Resolver::new_from_addrs(vec![dns_server.dns_server_address()])
+                    .expect("Error creating localhost resolver");
+            let ip = resolver
+                .lookup_ipv6(srv_crdb.clone())
+                .await
+                .expect("Should have been able to look up IP address");
+            assert_eq!(ip, addr);
+        }
+        let configs = updater.dns_config_get().await.unwrap();
+        assert_eq!(configs.len(), dns_servers.len());
+        for config in configs {
+            assert_eq!(config.generation, 1);
+            assert_eq!(config.records.len(), 2);
+        }
+
+        // Neither server accepts an older generation.
+        let error = updater
+            .dns_config_put(&config(0))
+            .await
+            .expect_err("Putting an older generation should fail");
+        assert_eq!(
+            error.status(),
+            Some(reqwest::StatusCode::CONFLICT),
+            "Saw error: {error}",
+        );
+        for dns_server in &dns_servers {
+            let client = crate::Client::new(
+                &format!("http://{}", dns_server.dropshot_server_address()),
+                logctx.log.clone(),
+            );
+            let current =
+                client.dns_config_get(DNS_ZONE).await.unwrap().into_inner();
+            assert_eq!(current.generation, 1);
+        }
+
+        logctx.cleanup_successful();
+    }
 }
@@ -96,6 +96,7 @@ async fn instance_launch() -> Result<()> {
             network_interfaces: InstanceNetworkInterfaceAttachment::Default,
             external_ips: vec![ExternalIpCreate::Ephemeral { pool_name: None }],
             user_data: String::new(),
+            affinity_groups: Vec::new(),
             start: true,
         })
         .send()
@@ -0,0 +1,131 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::impl_enum_type;
+use crate::schema::{affinity_group, affinity_group_instance_membership};
+use db_macros::Resource;
+use nexus_types::external_api::{params, shared, views};
+use nexus_types::identity::Resource;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+impl_enum_type!(
+    #[derive(SqlType, Debug, QueryId)]
+    #[diesel(postgres_type(name = "affinity_group_kind"))]
+    pub struct AffinityGroupKindEnum;
+
+    #[derive(Clone, Copy, Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq)]
+    #[diesel(sql_type = AffinityGroupKindEnum)]
+    pub enum AffinityGroupKind;
+
+    // Enum values
+    Affinity => b"affinity"
+    AntiAffinity => b"anti_affinity"
+);
+
+impl From<shared::AffinityGroupKind> for AffinityGroupKind {
+    fn from(kind: shared::AffinityGroupKind) -> Self {
+        match kind {
+            shared::AffinityGroupKind::Affinity => Self::Affinity,
+            shared::AffinityGroupKind::AntiAffinity => Self::AntiAffinity,
+        }
+    }
+}
+
+impl From<AffinityGroupKind> for shared::AffinityGroupKind {
+    fn from(kind: AffinityGroupKind) -> Self {
+        match kind {
+            AffinityGroupKind::Affinity => Self::Affinity,
+            AffinityGroupKind::AntiAffinity => Self::AntiAffinity,
+        }
+    }
+}
+
+impl_enum_type!(
+    #[derive(SqlType, Debug, QueryId)]
+    #[diesel(postgres_type(name = "affinity_policy"))]
+    pub struct AffinityPolicyEnum;
+
+    #[derive(Clone, Copy, Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq)]
+    #[diesel(sql_type = AffinityPolicyEnum)]
+    pub enum AffinityPolicy;
+
+    // Enum values
+    Hard => b"hard"
+    Soft => b"soft"
+);
+
+impl From<shared::AffinityPolicy> for AffinityPolicy {
+    fn from(policy: shared::AffinityPolicy) -> Self {
+        match policy {
+            shared::AffinityPolicy::Hard => Self::Hard,
+            shared::AffinityPolicy::Soft => Self::Soft,
+        }
+    }
+}
+
+impl From<AffinityPolicy> for shared::AffinityPolicy {
+    fn from(policy: AffinityPolicy) -> Self {
+        match policy {
+            AffinityPolicy::Hard => Self::Hard,
+            AffinityPolicy::Soft => Self::Soft,
+        }
+    }
+}
+
+/// A group of Instances whose placement on sleds is constrained relative to
+/// one another.
+#[derive(
+    Queryable,
+    Insertable,
+    Selectable,
+    Clone,
+    Debug,
+    Resource,
+    Serialize,
+    Deserialize,
+)]
+#[diesel(table_name = affinity_group)]
+pub struct AffinityGroup {
+    #[diesel(embed)]
+    pub identity: AffinityGroupIdentity,
+
+    pub project_id: Uuid,
+    pub kind: AffinityGroupKind,
+    pub policy: AffinityPolicy,
+}
+
+impl AffinityGroup {
+    pub fn new(
+        group_id: Uuid,
+        project_id: Uuid,
+        params: params::AffinityGroupCreate,
+    ) -> Self {
+        Self {
+            identity: AffinityGroupIdentity::new(group_id, params.identity),
+            project_id,
+            kind: params.kind.into(),
+            policy: params.policy.into(),
+        }
+    }
+}
+
+impl From<AffinityGroup> for views::AffinityGroup {
+    fn from(group: AffinityGroup) -> Self {
+        Self {
+            identity: group.identity(),
+            project_id: group.project_id,
+            kind: group.kind.into(),
+            policy: group.policy.into(),
+        }
+    }
+}
+
+/// Records that an Instance is a member of an affinity group.
+#[derive(Queryable, Insertable, Selectable, Clone, Copy, Debug)]
+#[diesel(table_name = affinity_group_instance_membership)]
+pub struct AffinityGroupInstanceMembership {
+    pub group_id: Uuid,
+    pub instance_id: Uuid,
+}
@@ -0,0 +1,178 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::impl_enum_type;
+use crate::schema::{alert, alert_rule};
+use chrono::{DateTime, Utc};
+use db_macros::Resource;
+use nexus_types::external_api::{params, shared, views};
+use nexus_types::identity::Resource;
+use omicron_common::api::external::Error;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+impl_enum_type!(
+    #[derive(SqlType, Debug, QueryId)]
+    #[diesel(postgres_type(name = "alert_comparison"))]
+    pub struct AlertComparisonEnum;
+
+    #[derive(Clone, Copy, Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq)]
+    #[diesel(sql_type = AlertComparisonEnum)]
+    pub enum AlertComparison;
+
+    // Enum values
+    Above => b"above"
+    Below => b"below"
+);
+
+impl From<shared::AlertComparison> for AlertComparison {
+    fn from(comparison: shared::AlertComparison) -> Self {
+        match comparison {
+            shared::AlertComparison::Above => Self::Above,
+            shared::AlertComparison::Below => Self::Below,
+        }
+    }
+}
+
+impl From<AlertComparison> for shared::AlertComparison {
+    fn from(comparison: AlertComparison) -> Self {
+        match comparison {
+            AlertComparison::Above => Self::Above,
+            AlertComparison::Below => Self::Below,
+        }
+    }
+}
+
+impl_enum_type!(
+    #[derive(SqlType, Debug, QueryId)]
+    #[diesel(postgres_type(name = "alert_rule_state"))]
+    pub struct AlertRuleStateEnum;
+
+    #[derive(Clone, Copy, Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq)]
+    #[diesel(sql_type = AlertRuleStateEnum)]
+    pub enum AlertRuleState;
+
+    // Enum values
+    Ok => b"ok"
+    Firing => b"firing"
+);
+
+impl From<AlertRuleState> for views::AlertRuleState {
+    fn from(state: AlertRuleState) -> Self {
+        match state {
+            AlertRuleState::Ok => Self::Ok,
+            AlertRuleState::Firing => Self::Firing,
+        }
+    }
+}
+
+/// A rule which fires when an aggregation of a timeseries has been past a
+/// threshold for some time, along with the outcome of its latest evaluation.
+#[derive(Queryable, Insertable, Selectable, Clone, Debug, Resource)]
+#[diesel(table_name = alert_rule)]
+pub struct AlertRule {
+    #[diesel(embed)]
+    pub identity: AlertRuleIdentity,
+
+    pub timeseries_name: String,
+    pub criteria: Vec<String>,
+    /// The aggregation applied to the timeseries, as JSON.
+    pub aggregation: serde_json::Value,
+    pub comparison: AlertComparison,
+    pub threshold: f64,
+    pub duration_secs: i64,
+
+    pub state: AlertRuleState,
+    pub time_state_changed: DateTime<Utc>,
+    pub time_evaluated: Option<DateTime<Utc>>,
+    pub last_value: Option<f64>,
+    /// When the rule was first found past its threshold, if it still is.
+    pub time_breach_started: Option<DateTime<Utc>>,
+}
+
+impl AlertRule {
+    pub fn new(id: Uuid, params: params::AlertRuleCreate) -> Self {
+        let identity = AlertRuleIdentity::new(id, params.identity);
+        let time_state_changed = identity.time_created;
+        Self {
+            identity,
+            timeseries_name: params.timeseries_name,
+            criteria: params.criteria,
+            aggregation: serde_json::to_value(&params.aggregation)
+                .expect("Failed to serialize an aggregation"),
+            comparison: params.comparison.into(),
+            threshold: params.threshold,
+            duration_secs: params.duration_secs.get().into(),
+            state: AlertRuleState::Ok,
+            time_state_changed,
+            time_evaluated: None,
+            last_value: None,
+            time_breach_started: None,
+        }
+    }
+}
+
+impl TryFrom<AlertRule> for views::AlertRule {
+    type Error = Error;
+    fn try_from(rule: AlertRule) -> Result<Self, Error> {
+        Ok(Self {
+            identity: rule.identity(),
+            timeseries_name: rule.timeseries_name,
+            criteria: rule.criteria,
+            aggregation: serde_json::from_value(rule.aggregation).map_err(
+                |e| {
+                    Error::internal_error(&format!(
+                        "invalid aggregation for alert rule: {}",
+                        e
+                    ))
+                },
+            )?,
+            comparison: rule.comparison.into(),
+            threshold: rule.threshold,
+            duration_secs: u32::try_from(rule.duration_secs).map_err(|_| {
+                Error::internal_error("invalid duration for alert rule")
+            })?,
+            state: rule.state.into(),
+            time_state_changed: rule.time_state_changed,
+            time_evaluated: rule.time_evaluated,
+            last_value: rule.last_value,
+        })
+    }
+}
+
+/// A period during which an [`AlertRule`] was firing.
+#[derive(Queryable, Insertable, Selectable, Clone, Debug)]
+#[diesel(table_name = alert)]
+pub struct Alert {
+    pub id: Uuid,
+    pub alert_rule_id: Uuid,
+    pub time_fired: DateTime<Utc>,
+    pub value: f64,
+    pub time_resolved: Option<DateTime<Utc>>,
+}
+
+impl Alert {
+    /// Describes a rule which has just started firing.
+    pub fn new(alert_rule_id: Uuid, value: f64) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            alert_rule_id,
+            time_fired: Utc::now(),
+            value,
+            time_resolved: None,
+        }
+    }
+}
+
+impl From<Alert> for views::Alert {
+    fn from(alert: Alert) -> Self {
+        Self {
+            id: alert.id,
+            alert_rule_id: alert.alert_rule_id,
+            time_fired: alert.time_fired,
+            value: alert.value,
+            time_resolved: alert.time_resolved,
+        }
+    }
+}
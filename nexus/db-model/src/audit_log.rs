@@ -0,0 +1,114 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::IdentityType;
+use crate::schema::audit_log;
+use crate::SqlU16;
+use chrono::{DateTime, Utc};
+use nexus_types::external_api::views;
+use uuid::Uuid;
+
+/// A mutating request made to the external API, along with its outcome once
+/// the request completes.
+#[derive(Queryable, Insertable, Selectable, Clone, Debug)]
+#[diesel(table_name = audit_log)]
+pub struct AuditLogEntry {
+    pub id: Uuid,
+    pub time_started: DateTime<Utc>,
+    pub request_id: String,
+    pub operation_id: String,
+    pub http_method: String,
+    pub request_uri: String,
+    pub actor_id: Option<Uuid>,
+    pub actor_kind: Option<IdentityType>,
+    pub actor_silo_id: Option<Uuid>,
+    pub time_completed: Option<DateTime<Utc>>,
+    pub http_status_code: Option<SqlU16>,
+    pub error_message: Option<String>,
+}
+
+impl AuditLogEntry {
+    /// Describes a request which has just been received.
+    pub fn new(
+        request_id: String,
+        operation_id: String,
+        http_method: String,
+        request_uri: String,
+        actor: views::AuditLogActor,
+    ) -> Self {
+        let (actor_id, actor_kind, actor_silo_id) = match actor {
+            views::AuditLogActor::UserBuiltin { user_builtin_id } => {
+                (Some(user_builtin_id), Some(IdentityType::UserBuiltin), None)
+            }
+            views::AuditLogActor::SiloUser { silo_user_id, silo_id } => (
+                Some(silo_user_id),
+                Some(IdentityType::SiloUser),
+                Some(silo_id),
+            ),
+            views::AuditLogActor::Unauthenticated => (None, None, None),
+        };
+        Self {
+            id: Uuid::new_v4(),
+            time_started: Utc::now(),
+            request_id,
+            operation_id,
+            http_method,
+            request_uri,
+            actor_id,
+            actor_kind,
+            actor_silo_id,
+            time_completed: None,
+            http_status_code: None,
+            error_message: None,
+        }
+    }
+}
+
+impl From<AuditLogEntry> for views::AuditLogEntry {
+    fn from(entry: AuditLogEntry) -> Self {
+        let actor =
+            match (entry.actor_kind, entry.actor_id, entry.actor_silo_id) {
+                (Some(IdentityType::UserBuiltin), Some(user_builtin_id), _) => {
+                    views::AuditLogActor::UserBuiltin { user_builtin_id }
+                }
+                (
+                    Some(IdentityType::SiloUser),
+                    Some(silo_user_id),
+                    Some(silo_id),
+                ) => views::AuditLogActor::SiloUser { silo_user_id, silo_id },
+                _ => views::AuditLogActor::Unauthenticated,
+            };
+        Self {
+            id: entry.id,
+            time_started: entry.time_started,
+            request_id: entry.request_id,
+            operation_id: entry.operation_id,
+            http_method: entry.http_method,
+            request_uri: entry.request_uri,
+            actor,
+            time_completed: entry.time_completed,
+            http_status_code: entry.http_status_code.map(|code| *code),
+            error_message: entry.error_message,
+        }
+    }
+}
+
+/// The outcome of a request recorded in the audit log
+#[derive(AsChangeset, Clone, Debug)]
+#[diesel(table_name = audit_log)]
+pub struct AuditLogCompletion {
+    pub time_completed: DateTime<Utc>,
+    pub http_status_code: SqlU16,
+    pub error_message: Option<String>,
+}
+
+impl AuditLogCompletion {
+    pub fn new(http_status_code: u16, error_message: Option<String>) -> Self {
+        Self {
+            time_completed: Utc::now(),
+            http_status_code: SqlU16::new(http_status_code),
+            error_message,
+        }
+    }
+}
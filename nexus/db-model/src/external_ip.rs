@@ -7,15 +7,18 @@
 
 use crate::impl_enum_type;
 use crate::schema::external_ip;
+use crate::schema::floating_ip;
 use crate::Name;
 use crate::SqlU16;
 use chrono::DateTime;
 use chrono::Utc;
+use db_macros::Resource;
 use diesel::Queryable;
 use diesel::Selectable;
 use ipnetwork::IpNetwork;
 use nexus_types::external_api::shared;
 use nexus_types::external_api::views;
+use nexus_types::identity::Resource;
 use omicron_common::api::external::Error;
 use std::convert::TryFrom;
 use std::net::IpAddr;
@@ -68,6 +71,27 @@ pub struct ExternalIp {
     pub ip: IpNetwork,
     pub first_port: SqlU16,
     pub last_port: SqlU16,
+    // Only Some(_) for Floating IPs
+    pub project_id: Option<Uuid>,
+}
+
+/// A Floating IP, as exposed in the API.
+///
+/// Floating IPs are stored in the `external_ip` table, alongside all other
+/// kinds of external addresses. This type is read from a view over that table
+/// which contains only Floating IPs, and which can therefore describe them as
+/// a project-scoped API resource.
+#[derive(Debug, Clone, Selectable, Queryable, Resource)]
+#[diesel(table_name = floating_ip)]
+pub struct FloatingIp {
+    #[diesel(embed)]
+    pub identity: FloatingIpIdentity,
+
+    pub ip_pool_id: Uuid,
+    pub ip_pool_range_id: Uuid,
+    pub instance_id: Option<Uuid>,
+    pub ip: IpNetwork,
+    pub project_id: Uuid,
 }
 
 impl From<ExternalIp> for sled_agent_client::types::SourceNatConfig {
@@ -93,6 +117,8 @@ pub struct IncompleteExternalIp {
     pool_id: Uuid,
     // Optional address requesting that a specific IP address be allocated.
     explicit_ip: Option<IpNetwork>,
+    // Only Some(_) for Floating IPs
+    project_id: Option<Uuid>,
 }
 
 impl IncompleteExternalIp {
@@ -110,6 +136,7 @@ impl IncompleteExternalIp {
             instance_id: Some(instance_id),
             pool_id,
             explicit_ip: None,
+            project_id: None,
         }
     }
 
@@ -123,6 +150,7 @@ impl IncompleteExternalIp {
             instance_id: Some(instance_id),
             pool_id,
             explicit_ip: None,
+            project_id: None,
         }
     }
 
@@ -130,6 +158,7 @@ impl IncompleteExternalIp {
         id: Uuid,
         name: &Name,
         description: &str,
+        project_id: Uuid,
         pool_id: Uuid,
     ) -> Self {
         Self {
@@ -141,6 +170,28 @@ impl IncompleteExternalIp {
             instance_id: None,
             pool_id,
             explicit_ip: None,
+            project_id: Some(project_id),
+        }
+    }
+
+    pub fn for_floating_explicit(
+        id: Uuid,
+        name: &Name,
+        description: &str,
+        project_id: Uuid,
+        pool_id: Uuid,
+        address: IpAddr,
+    ) -> Self {
+        Self {
+            id,
+            name: Some(name.clone()),
+            description: Some(description.to_string()),
+            time_created: Utc::now(),
+            kind: IpKind::Floating,
+            instance_id: None,
+            pool_id,
+            explicit_ip: Some(IpNetwork::from(address)),
+            project_id: Some(project_id),
         }
     }
 
@@ -158,6 +209,7 @@ impl IncompleteExternalIp {
             instance_id: None,
             pool_id,
             explicit_ip: Some(IpNetwork::from(address)),
+            project_id: None,
         }
     }
 
@@ -171,6 +223,7 @@ impl IncompleteExternalIp {
             instance_id: None,
             pool_id,
             explicit_ip: None,
+            project_id: None,
         }
     }
 
@@ -205,6 +258,10 @@ impl IncompleteExternalIp {
     pub fn explicit_ip(&self) -> &Option<IpNetwork> {
         &self.explicit_ip
     }
+
+    pub fn project_id(&self) -> &Option<Uuid> {
+        &self.project_id
+    }
 }
 
 impl TryFrom<IpKind> for shared::IpKind {
@@ -229,3 +286,51 @@ impl TryFrom<ExternalIp> for views::ExternalIp {
         Ok(views::ExternalIp { kind, ip: ip.ip.ip() })
     }
 }
+
+impl TryFrom<ExternalIp> for FloatingIp {
+    type Error = Error;
+
+    fn try_from(ip: ExternalIp) -> Result<Self, Self::Error> {
+        let (name, description, project_id) =
+            match (ip.kind, ip.name, ip.description, ip.project_id) {
+                (
+                    IpKind::Floating,
+                    Some(name),
+                    Some(description),
+                    Some(project_id),
+                ) => (name, description, project_id),
+                _ => {
+                    return Err(Error::internal_error(
+                        "external IP is not a well-formed Floating IP",
+                    ))
+                }
+            };
+        let identity = FloatingIpIdentity {
+            id: ip.id,
+            name,
+            description,
+            time_created: ip.time_created,
+            time_modified: ip.time_modified,
+            time_deleted: ip.time_deleted,
+        };
+        Ok(FloatingIp {
+            identity,
+            ip_pool_id: ip.ip_pool_id,
+            ip_pool_range_id: ip.ip_pool_range_id,
+            instance_id: ip.instance_id,
+            ip: ip.ip,
+            project_id,
+        })
+    }
+}
+
+impl From<FloatingIp> for views::FloatingIp {
+    fn from(ip: FloatingIp) -> Self {
+        views::FloatingIp {
+            identity: ip.identity(),
+            ip: ip.ip.ip(),
+            project_id: ip.project_id,
+            instance_id: ip.instance_id,
+        }
+    }
+}
@@ -9,6 +9,9 @@ extern crate diesel;
 #[macro_use]
 extern crate newtype_derive;
 
+mod affinity_group;
+mod alert_rule;
+mod audit_log;
 mod block_size;
 mod bytecount;
 mod certificate;
@@ -34,6 +37,9 @@ mod ipv6;
 mod ipv6net;
 mod l4_port_range;
 mod macaddr;
+mod migration;
+mod migration_phase;
+mod migration_state;
 mod name;
 mod network_interface;
 mod nexus_service;
@@ -63,6 +69,7 @@ mod silo_group;
 mod silo_user;
 mod silo_user_password_hash;
 mod sled;
+mod sled_provision_state;
 mod snapshot;
 mod ssh_key;
 mod u16;
@@ -89,6 +96,9 @@ mod db {
 
 pub use self::macaddr::*;
 pub use self::u16::*;
+pub use affinity_group::*;
+pub use alert_rule::*;
+pub use audit_log::*;
 pub use block_size::*;
 pub use bytecount::*;
 pub use certificate::*;
@@ -113,6 +123,9 @@ pub use ipv4net::*;
 pub use ipv6::*;
 pub use ipv6net::*;
 pub use l4_port_range::*;
+pub use migration::*;
+pub use migration_phase::*;
+pub use migration_state::*;
 pub use name::*;
 pub use network_interface::*;
 pub use nexus_service::*;
@@ -135,6 +148,7 @@ pub use silo_group::*;
 pub use silo_user::*;
 pub use silo_user_password_hash::*;
 pub use sled::*;
+pub use sled_provision_state::*;
 pub use snapshot::*;
 pub use ssh_key::*;
 pub use system_update::*;
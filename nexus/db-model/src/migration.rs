@@ -0,0 +1,72 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::MigrationPhase;
+use super::MigrationState;
+use crate::schema::migration;
+use chrono::{DateTime, Utc};
+use nexus_types::external_api::views;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A live migration of an Instance from one sled to another.
+#[derive(
+    Queryable, Insertable, Debug, Clone, Selectable, Serialize, Deserialize,
+)]
+#[diesel(table_name = migration)]
+pub struct Migration {
+    pub id: Uuid,
+    pub time_created: DateTime<Utc>,
+    pub time_modified: DateTime<Utc>,
+    pub instance_id: Uuid,
+    pub state: MigrationState,
+    pub phase: MigrationPhase,
+    pub source_sled_id: Uuid,
+    pub source_propolis_id: Uuid,
+    pub target_sled_id: Uuid,
+    pub target_propolis_id: Uuid,
+    pub time_finished: Option<DateTime<Utc>>,
+}
+
+impl Migration {
+    pub fn new(
+        id: Uuid,
+        instance_id: Uuid,
+        source_sled_id: Uuid,
+        source_propolis_id: Uuid,
+        target_sled_id: Uuid,
+        target_propolis_id: Uuid,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            id,
+            time_created: now,
+            time_modified: now,
+            instance_id,
+            state: MigrationState::Pending,
+            phase: MigrationPhase::Preparing,
+            source_sled_id,
+            source_propolis_id,
+            target_sled_id,
+            target_propolis_id,
+            time_finished: None,
+        }
+    }
+}
+
+impl From<Migration> for views::Migration {
+    fn from(migration: Migration) -> Self {
+        Self {
+            id: migration.id,
+            instance_id: migration.instance_id,
+            state: migration.state.into(),
+            phase: migration.phase.into(),
+            source_sled_id: migration.source_sled_id,
+            target_sled_id: migration.target_sled_id,
+            time_created: migration.time_created,
+            time_modified: migration.time_modified,
+            time_finished: migration.time_finished,
+        }
+    }
+}
@@ -0,0 +1,47 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::impl_enum_type;
+use nexus_types::external_api::views;
+use serde::{Deserialize, Serialize};
+
+impl_enum_type!(
+    #[derive(Clone, SqlType, Debug, QueryId)]
+    #[diesel(postgres_type(name = "migration_phase"))]
+    pub struct MigrationPhaseEnum;
+
+    #[derive(Clone, Copy, Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq)]
+    #[diesel(sql_type = MigrationPhaseEnum)]
+    pub enum MigrationPhase;
+
+    // Enum values
+    Preparing => b"preparing"
+    SourceReady => b"source_ready"
+    Transferring => b"transferring"
+    TargetRunning => b"target_running"
+);
+
+impl std::fmt::Display for MigrationPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationPhase::Preparing => write!(f, "preparing"),
+            MigrationPhase::SourceReady => write!(f, "source_ready"),
+            MigrationPhase::Transferring => write!(f, "transferring"),
+            MigrationPhase::TargetRunning => write!(f, "target_running"),
+        }
+    }
+}
+
+impl From<MigrationPhase> for views::MigrationPhase {
+    fn from(phase: MigrationPhase) -> Self {
+        use views::MigrationPhase as api;
+        use MigrationPhase as db;
+        match phase {
+            db::Preparing => api::Preparing,
+            db::SourceReady => api::SourceReady,
+            db::Transferring => api::Transferring,
+            db::TargetRunning => api::TargetRunning,
+        }
+    }
+}
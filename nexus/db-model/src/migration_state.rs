@@ -0,0 +1,62 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::impl_enum_type;
+use nexus_types::external_api::views;
+use serde::{Deserialize, Serialize};
+
+impl_enum_type!(
+    #[derive(Clone, SqlType, Debug, QueryId)]
+    #[diesel(postgres_type(name = "migration_state"))]
+    pub struct MigrationStateEnum;
+
+    #[derive(Clone, Copy, Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq)]
+    #[diesel(sql_type = MigrationStateEnum)]
+    pub enum MigrationState;
+
+    // Enum values
+    Pending => b"pending"
+    InProgress => b"in_progress"
+    Completed => b"completed"
+    Failed => b"failed"
+    Cancelled => b"cancelled"
+);
+
+impl MigrationState {
+    /// Returns true if the migration has come to rest, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            MigrationState::Completed
+                | MigrationState::Failed
+                | MigrationState::Cancelled
+        )
+    }
+}
+
+impl std::fmt::Display for MigrationState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationState::Pending => write!(f, "pending"),
+            MigrationState::InProgress => write!(f, "in_progress"),
+            MigrationState::Completed => write!(f, "completed"),
+            MigrationState::Failed => write!(f, "failed"),
+            MigrationState::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl From<MigrationState> for views::MigrationState {
+    fn from(state: MigrationState) -> Self {
+        use views::MigrationState as api;
+        use MigrationState as db;
+        match state {
+            db::Pending => api::Pending,
+            db::InProgress => api::InProgress,
+            db::Completed => api::Completed,
+            db::Failed => api::Failed,
+            db::Cancelled => api::Cancelled,
+        }
+    }
+}
@@ -2,9 +2,13 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use super::{Disk, Generation, Image, Instance, Name, Snapshot, Vpc};
+use super::{
+    AffinityGroup, Disk, Generation, Image, Instance, Name, Snapshot, Vpc,
+};
 use crate::collection::DatastoreCollectionConfig;
-use crate::schema::{disk, image, instance, project, snapshot, vpc};
+use crate::schema::{
+    affinity_group, disk, image, instance, project, snapshot, vpc,
+};
 use chrono::{DateTime, Utc};
 use db_macros::Resource;
 use nexus_types::external_api::params;
@@ -83,6 +87,13 @@ impl DatastoreCollectionConfig<Vpc> for Project {
     type CollectionIdColumn = vpc::dsl::project_id;
 }
 
+impl DatastoreCollectionConfig<AffinityGroup> for Project {
+    type CollectionId = Uuid;
+    type GenerationNumberColumn = project::dsl::rcgen;
+    type CollectionTimeDeletedColumn = project::dsl::time_deleted;
+    type CollectionIdColumn = affinity_group::dsl::project_id;
+}
+
 /// Describes a set of updates for the [`Project`] model.
 #[derive(AsChangeset)]
 #[diesel(table_name = project)]
@@ -15,6 +15,7 @@ pub struct Rack {
     pub identity: RackIdentity,
     pub initialized: bool,
     pub tuf_base_url: Option<String>,
+    pub external_dns_generation: i64,
 }
 
 impl Rack {
@@ -23,6 +24,7 @@ impl Rack {
             identity: RackIdentity::new(id),
             initialized: false,
             tuf_base_url: None,
+            external_dns_generation: 0,
         }
     }
 }
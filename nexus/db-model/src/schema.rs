@@ -110,6 +110,100 @@ table! {
     }
 }
 
+table! {
+    migration (id) {
+        id -> Uuid,
+        time_created -> Timestamptz,
+        time_modified -> Timestamptz,
+        instance_id -> Uuid,
+        state -> crate::MigrationStateEnum,
+        phase -> crate::MigrationPhaseEnum,
+        source_sled_id -> Uuid,
+        source_propolis_id -> Uuid,
+        target_sled_id -> Uuid,
+        target_propolis_id -> Uuid,
+        time_finished -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    affinity_group (id) {
+        id -> Uuid,
+        name -> Text,
+        description -> Text,
+        time_created -> Timestamptz,
+        time_modified -> Timestamptz,
+        time_deleted -> Nullable<Timestamptz>,
+        project_id -> Uuid,
+        kind -> crate::AffinityGroupKindEnum,
+        policy -> crate::AffinityPolicyEnum,
+    }
+}
+
+table! {
+    affinity_group_instance_membership (group_id, instance_id) {
+        group_id -> Uuid,
+        instance_id -> Uuid,
+    }
+}
+
+allow_tables_to_appear_in_same_query!(
+    affinity_group,
+    affinity_group_instance_membership,
+    instance
+);
+
+table! {
+    audit_log (id) {
+        id -> Uuid,
+        time_started -> Timestamptz,
+        request_id -> Text,
+        operation_id -> Text,
+        http_method -> Text,
+        request_uri -> Text,
+        actor_id -> Nullable<Uuid>,
+        actor_kind -> Nullable<crate::IdentityTypeEnum>,
+        actor_silo_id -> Nullable<Uuid>,
+        time_completed -> Nullable<Timestamptz>,
+        http_status_code -> Nullable<Int4>,
+        error_message -> Nullable<Text>,
+    }
+}
+
+table! {
+    alert_rule (id) {
+        id -> Uuid,
+        name -> Text,
+        description -> Text,
+        time_created -> Timestamptz,
+        time_modified -> Timestamptz,
+        time_deleted -> Nullable<Timestamptz>,
+
+        timeseries_name -> Text,
+        criteria -> Array<Text>,
+        aggregation -> Jsonb,
+        comparison -> crate::AlertComparisonEnum,
+        threshold -> Float8,
+        duration_secs -> Int8,
+
+        state -> crate::AlertRuleStateEnum,
+        time_state_changed -> Timestamptz,
+        time_evaluated -> Nullable<Timestamptz>,
+        last_value -> Nullable<Float8>,
+        time_breach_started -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    alert (id) {
+        id -> Uuid,
+        alert_rule_id -> Uuid,
+        time_fired -> Timestamptz,
+        value -> Float8,
+        time_resolved -> Nullable<Timestamptz>,
+    }
+}
+
 table! {
     metric_producer (id) {
         id -> Uuid,
@@ -182,6 +276,24 @@ table! {
         ip -> Inet,
         first_port -> Int4,
         last_port -> Int4,
+        project_id -> Nullable<Uuid>,
+    }
+}
+
+// This is a view over the `external_ip` table, with only floating IPs.
+table! {
+    floating_ip (id) {
+        id -> Uuid,
+        name -> Text,
+        description -> Text,
+        time_created -> Timestamptz,
+        time_modified -> Timestamptz,
+        time_deleted -> Nullable<Timestamptz>,
+        ip_pool_id -> Uuid,
+        ip_pool_range_id -> Uuid,
+        instance_id -> Nullable<Uuid>,
+        ip -> Inet,
+        project_id -> Uuid,
     }
 }
 
@@ -364,6 +476,7 @@ table! {
         time_modified -> Timestamptz,
         initialized -> Bool,
         tuf_base_url -> Nullable<Text>,
+        external_dns_generation -> Int8,
     }
 }
 
@@ -393,6 +506,8 @@ table! {
         ip -> Inet,
         port -> Int4,
         last_used_address -> Inet,
+
+        provision_state -> crate::SledProvisionStateEnum,
     }
 }
 
@@ -459,6 +574,9 @@ table! {
         virtual_disk_bytes_provisioned -> Int8,
         cpus_provisioned -> Int8,
         ram_provisioned -> Int8,
+        virtual_disk_bytes_limit -> Nullable<Int8>,
+        cpus_limit -> Nullable<Int8>,
+        ram_limit -> Nullable<Int8>,
     }
 }
 
@@ -771,6 +889,8 @@ joinable!(system_update_component_update -> component_update (component_update_i
 allow_tables_to_appear_in_same_query!(ip_pool_range, ip_pool);
 joinable!(ip_pool_range -> ip_pool (ip_pool_id));
 
+allow_tables_to_appear_in_same_query!(sled, zpool);
+
 allow_tables_to_appear_in_same_query!(
     dataset,
     disk,
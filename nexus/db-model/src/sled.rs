@@ -2,7 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use super::{Generation, SqlU16};
+use super::{Generation, SledProvisionState, SqlU16};
 use crate::collection::DatastoreCollectionConfig;
 use crate::ipv6;
 use crate::schema::{physical_disk, service, sled, zpool};
@@ -35,6 +35,9 @@ pub struct Sled {
 
     /// The last IP address provided to an Oxide service on this sled
     pub last_used_address: ipv6::Ipv6Addr,
+
+    /// Whether new Instances and regions may be placed on this sled
+    pub provision_state: SledProvisionState,
 }
 
 impl Sled {
@@ -64,6 +67,7 @@ impl Sled {
             ip: ipv6::Ipv6Addr::from(addr.ip()),
             port: addr.port().into(),
             last_used_address,
+            provision_state: SledProvisionState::Active,
         }
     }
 
@@ -90,6 +94,7 @@ impl From<Sled> for views::Sled {
             identity: sled.identity(),
             service_address: sled.address(),
             rack_id: sled.rack_id,
+            provision_state: sled.provision_state.into(),
             baseboard: views::Baseboard {
                 serial: sled.serial_number,
                 part: sled.part_number,
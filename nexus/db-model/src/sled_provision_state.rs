@@ -0,0 +1,41 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::impl_enum_type;
+use nexus_types::external_api::views;
+use serde::{Deserialize, Serialize};
+
+impl_enum_type!(
+    #[derive(Clone, SqlType, Debug, QueryId)]
+    #[diesel(postgres_type(name = "sled_provision_state"))]
+    pub struct SledProvisionStateEnum;
+
+    #[derive(Clone, Copy, Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq)]
+    #[diesel(sql_type = SledProvisionStateEnum)]
+    pub enum SledProvisionState;
+
+    // Enum values
+    Active => b"active"
+    Cordoned => b"cordoned"
+    Draining => b"draining"
+);
+
+impl SledProvisionState {
+    /// Returns true if new Instances and regions may be placed on the sled.
+    pub fn is_provisionable(&self) -> bool {
+        matches!(self, SledProvisionState::Active)
+    }
+}
+
+impl From<SledProvisionState> for views::SledProvisionState {
+    fn from(state: SledProvisionState) -> Self {
+        use views::SledProvisionState as api;
+        use SledProvisionState as db;
+        match state {
+            db::Active => api::Active,
+            db::Cordoned => api::Cordoned,
+            db::Draining => api::Draining,
+        }
+    }
+}
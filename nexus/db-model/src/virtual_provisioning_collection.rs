@@ -5,6 +5,8 @@
 use crate::schema::virtual_provisioning_collection;
 use crate::ByteCount;
 use chrono::{DateTime, Utc};
+use nexus_types::external_api::params;
+use nexus_types::external_api::views;
 use omicron_common::api::external;
 use parse_display::Display;
 use uuid::Uuid;
@@ -29,6 +31,10 @@ pub struct VirtualProvisioningCollection {
     pub virtual_disk_bytes_provisioned: ByteCount,
     pub cpus_provisioned: i64,
     pub ram_provisioned: ByteCount,
+
+    pub virtual_disk_bytes_limit: Option<ByteCount>,
+    pub cpus_limit: Option<i64>,
+    pub ram_limit: Option<ByteCount>,
 }
 
 impl VirtualProvisioningCollection {
@@ -42,6 +48,9 @@ impl VirtualProvisioningCollection {
             ),
             cpus_provisioned: 0,
             ram_provisioned: ByteCount(external::ByteCount::from(0)),
+            virtual_disk_bytes_limit: None,
+            cpus_limit: None,
+            ram_limit: None,
         }
     }
 
@@ -51,3 +60,39 @@ impl VirtualProvisioningCollection {
             && self.ram_provisioned.to_bytes() == 0
     }
 }
+
+impl From<VirtualProvisioningCollection> for views::ResourceQuotas {
+    fn from(collection: VirtualProvisioningCollection) -> Self {
+        Self {
+            cpus: collection.cpus_limit,
+            memory: collection.ram_limit.map(|limit| limit.0),
+            storage: collection.virtual_disk_bytes_limit.map(|limit| limit.0),
+        }
+    }
+}
+
+/// Describes the quotas of a collection, for updating them
+///
+/// Quotas which are not set are cleared.
+#[derive(AsChangeset)]
+#[diesel(table_name = virtual_provisioning_collection)]
+#[diesel(treat_none_as_null = true)]
+pub struct VirtualProvisioningCollectionQuotas {
+    pub time_modified: Option<DateTime<Utc>>,
+    pub virtual_disk_bytes_limit: Option<ByteCount>,
+    pub cpus_limit: Option<i64>,
+    pub ram_limit: Option<ByteCount>,
+}
+
+impl From<params::ResourceQuotasUpdate>
+    for VirtualProvisioningCollectionQuotas
+{
+    fn from(params: params::ResourceQuotasUpdate) -> Self {
+        Self {
+            time_modified: Some(Utc::now()),
+            virtual_disk_bytes_limit: params.storage.map(ByteCount::from),
+            cpus_limit: params.cpus,
+            ram_limit: params.memory.map(ByteCount::from),
+        }
+    }
+}
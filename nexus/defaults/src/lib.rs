@@ -17,6 +17,14 @@ use std::net::Ipv6Addr;
 /// instance.
 pub const DEFAULT_PRIMARY_NIC_NAME: &str = "net0";
 
+/// The largest number of virtual CPUs that may be provisioned for a guest
+/// instance.
+pub const MAX_INSTANCE_CPUS: u16 = 64;
+
+/// The largest amount of memory, in bytes, that may be provisioned for a guest
+/// instance.
+pub const MAX_INSTANCE_MEMORY_BYTES: u64 = 256 * (1 << 30); // 256 GiB
+
 lazy_static! {
     /// The default IPv4 subnet range assigned to the default VPC Subnet, when
     /// the VPC is created, if one is not provided in the request. See
@@ -0,0 +1,253 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Affinity groups, and placing Instances on sleds in accordance with them
+
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::lookup;
+use crate::db::lookup::LookupPath;
+use crate::db::model::AffinityGroupKind;
+use crate::db::model::AffinityPolicy;
+use crate::db::model::Name;
+use crate::external_api::params;
+use nexus_types::identity::Asset;
+use nexus_types::identity::Resource;
+use omicron_common::api::external::http_pagination::PaginatedBy;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::NameOrId;
+use ref_cast::RefCast;
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+impl super::Nexus {
+    pub fn affinity_group_lookup<'a>(
+        &'a self,
+        opctx: &'a OpContext,
+        group_selector: &'a params::AffinityGroupSelector,
+    ) -> LookupResult<lookup::AffinityGroup<'a>> {
+        match group_selector {
+            params::AffinityGroupSelector {
+                affinity_group: NameOrId::Id(id),
+                project_selector: None,
+            } => {
+                let group = LookupPath::new(opctx, &self.db_datastore)
+                    .affinity_group_id(*id);
+                Ok(group)
+            }
+            params::AffinityGroupSelector {
+                affinity_group: NameOrId::Name(name),
+                project_selector: Some(project_selector),
+            } => {
+                let group = self
+                    .project_lookup(opctx, project_selector)?
+                    .affinity_group_name(Name::ref_cast(name));
+                Ok(group)
+            }
+            params::AffinityGroupSelector {
+                affinity_group: NameOrId::Id(_),
+                project_selector: Some(_),
+            } => Err(Error::invalid_request(
+                "when providing affinity group as an ID, project should not be specified",
+            )),
+            _ => Err(Error::invalid_request(
+                "affinity group should either be UUID or project should be specified",
+            )),
+        }
+    }
+
+    pub async fn project_create_affinity_group(
+        &self,
+        opctx: &OpContext,
+        project_lookup: &lookup::Project<'_>,
+        params: &params::AffinityGroupCreate,
+    ) -> CreateResult<db::model::AffinityGroup> {
+        let (.., authz_project) =
+            project_lookup.lookup_for(authz::Action::CreateChild).await?;
+        let group = db::model::AffinityGroup::new(
+            Uuid::new_v4(),
+            authz_project.id(),
+            params.clone(),
+        );
+        self.db_datastore
+            .project_create_affinity_group(opctx, &authz_project, group)
+            .await
+    }
+
+    pub async fn affinity_group_list(
+        &self,
+        opctx: &OpContext,
+        project_lookup: &lookup::Project<'_>,
+        pagparams: &PaginatedBy<'_>,
+    ) -> ListResultVec<db::model::AffinityGroup> {
+        let (.., authz_project) =
+            project_lookup.lookup_for(authz::Action::ListChildren).await?;
+        self.db_datastore
+            .affinity_group_list(opctx, &authz_project, pagparams)
+            .await
+    }
+
+    pub async fn affinity_group_delete(
+        &self,
+        opctx: &OpContext,
+        group_lookup: &lookup::AffinityGroup<'_>,
+    ) -> DeleteResult {
+        let (.., authz_group) =
+            group_lookup.lookup_for(authz::Action::Delete).await?;
+        self.db_datastore
+            .project_delete_affinity_group(opctx, &authz_group)
+            .await
+    }
+
+    pub async fn affinity_group_member_list(
+        &self,
+        opctx: &OpContext,
+        group_lookup: &lookup::AffinityGroup<'_>,
+    ) -> ListResultVec<db::model::Instance> {
+        let (.., authz_group) =
+            group_lookup.lookup_for(authz::Action::Read).await?;
+        self.db_datastore.affinity_group_member_list(opctx, &authz_group).await
+    }
+
+    /// Looks up the affinity groups which a new Instance in the given Project
+    /// is to join.
+    pub(crate) async fn affinity_groups_for_instance_create(
+        &self,
+        opctx: &OpContext,
+        project_id: Uuid,
+        groups: &[NameOrId],
+    ) -> ListResultVec<(authz::AffinityGroup, db::model::AffinityGroup)> {
+        let mut found = Vec::with_capacity(groups.len());
+        for group in groups {
+            let group_lookup = match group {
+                NameOrId::Id(id) => LookupPath::new(opctx, &self.db_datastore)
+                    .affinity_group_id(*id),
+                NameOrId::Name(name) => {
+                    LookupPath::new(opctx, &self.db_datastore)
+                        .project_id(project_id)
+                        .affinity_group_name(Name::ref_cast(name))
+                }
+            };
+            let (.., authz_group, db_group) =
+                group_lookup.fetch_for(authz::Action::Modify).await?;
+
+            // Groups may be provided by ID, so we need to make sure they're
+            // actually in the Instance's project.
+            if db_group.project_id != project_id {
+                return Err(Error::invalid_request(
+                    "affinity group must be in the same project as the instance",
+                ));
+            }
+            found.push((authz_group, db_group));
+        }
+        Ok(found)
+    }
+
+    /// Looks up the affinity groups of which an existing Instance is a member.
+    pub(crate) async fn instance_affinity_groups(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+    ) -> ListResultVec<(authz::AffinityGroup, db::model::AffinityGroup)> {
+        let groups = self
+            .db_datastore
+            .instance_list_affinity_groups(opctx, authz_instance)
+            .await?;
+        let mut found = Vec::with_capacity(groups.len());
+        for db_group in groups {
+            let (.., authz_group) = LookupPath::new(opctx, &self.db_datastore)
+                .affinity_group_id(db_group.id())
+                .lookup_for(authz::Action::Read)
+                .await?;
+            found.push((authz_group, db_group));
+        }
+        Ok(found)
+    }
+
+    /// Picks a sled on which to run an Instance which is a member of `groups`,
+    /// other than any of `excluded_sleds`.
+    ///
+    /// Groups with a hard policy restrict which sleds may be picked, and it's
+    /// an error if no sled is left.  Groups with a soft policy only make some
+    /// sleds preferable to others.  Among equally good sleds, one is picked at
+    /// random.  Returns `None` if there are no sleds to pick from at all.
+    ///
+    /// Instances which are placed concurrently don't see one another here.
+    /// New Instances are checked against their hard groups again when they
+    /// join them (see [`db::DataStore::affinity_group_member_add`]).
+    ///
+    /// TODO-correctness Migrations aren't rechecked that way, so a migrating
+    /// Instance can still race with the placement of another member.
+    pub(crate) async fn affinity_sled_select(
+        &self,
+        opctx: &OpContext,
+        instance_id: Uuid,
+        groups: &[(authz::AffinityGroup, db::model::AffinityGroup)],
+        excluded_sleds: &[Uuid],
+    ) -> Result<Option<Uuid>, Error> {
+        // Each candidate is paired with the number of soft groups which it
+        // satisfies.
+        let mut candidates: Vec<(Uuid, usize)> = self
+            .db_datastore
+            .sled_list_provisionable(&self.opctx_alloc, excluded_sleds)
+            .await?
+            .into_iter()
+            .map(|sled| (sled.id(), 0))
+            .collect();
+        if candidates.is_empty() {
+            return Ok(None);
+        }
+
+        for (authz_group, db_group) in groups {
+            let member_sleds: BTreeSet<Uuid> = self
+                .db_datastore
+                .affinity_group_member_sleds(opctx, authz_group, instance_id)
+                .await?
+                .into_iter()
+                .collect();
+            let satisfies = |sled_id: &Uuid| match db_group.kind {
+                AffinityGroupKind::Affinity => {
+                    member_sleds.is_empty() || member_sleds.contains(sled_id)
+                }
+                AffinityGroupKind::AntiAffinity => {
+                    !member_sleds.contains(sled_id)
+                }
+            };
+            match db_group.policy {
+                AffinityPolicy::Hard => {
+                    candidates.retain(|(sled_id, _)| satisfies(sled_id));
+                    if candidates.is_empty() {
+                        let kind = match db_group.kind {
+                            AffinityGroupKind::Affinity => "affinity",
+                            AffinityGroupKind::AntiAffinity => "anti-affinity",
+                        };
+                        return Err(Error::invalid_request(&format!(
+                            "no available sled satisfies {} group \"{}\"",
+                            kind,
+                            db_group.name(),
+                        )));
+                    }
+                }
+                AffinityPolicy::Soft => {
+                    for (sled_id, satisfied) in candidates.iter_mut() {
+                        if satisfies(sled_id) {
+                            *satisfied += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        // The candidates are in random order, so this breaks ties randomly.
+        Ok(candidates
+            .into_iter()
+            .max_by_key(|(_, satisfied)| *satisfied)
+            .map(|(sled_id, _)| sled_id))
+    }
+}
@@ -0,0 +1,312 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Alert rules evaluated over timeseries in the timeseries database
+
+use super::oximeter::map_oximeter_err;
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::lookup;
+use crate::db::lookup::LookupPath;
+use crate::db::model::AlertRuleState;
+use crate::db::model::Name;
+use crate::external_api::params;
+use crate::external_api::views;
+use chrono::DateTime;
+use chrono::Utc;
+use nexus_types::identity::Resource;
+use omicron_common::api::external::http_pagination::PaginatedBy;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DataPageParams;
+use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::LookupType;
+use omicron_common::api::external::NameOrId;
+use oximeter::types::Datum;
+use oximeter_db::query::Timestamp;
+use ref_cast::RefCast;
+use serde::Serialize;
+use std::num::NonZeroU32;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How many alert rules are fetched at a time while evaluating them.
+const ALERT_RULE_BATCH_SIZE: u32 = 100;
+
+/// How long to wait for the webhook to accept a notification.
+const ALERT_WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The body of a notification POSTed to the alert webhook when a rule starts
+/// or stops firing.
+#[derive(Serialize)]
+struct AlertNotification {
+    rule: views::AlertRule,
+    alert: views::Alert,
+}
+
+impl super::Nexus {
+    pub fn alert_rule_lookup<'a>(
+        &'a self,
+        opctx: &'a OpContext,
+        alert_rule: &'a NameOrId,
+    ) -> lookup::AlertRule<'a> {
+        match alert_rule {
+            NameOrId::Id(id) => {
+                LookupPath::new(opctx, &self.db_datastore).alert_rule_id(*id)
+            }
+            NameOrId::Name(name) => LookupPath::new(opctx, &self.db_datastore)
+                .alert_rule_name(Name::ref_cast(name)),
+        }
+    }
+
+    pub async fn alert_rule_create(
+        &self,
+        opctx: &OpContext,
+        params: params::AlertRuleCreate,
+    ) -> CreateResult<db::model::AlertRule> {
+        // Catch rules that can never be evaluated here, rather than every time
+        // they're evaluated.
+        oximeter_db::TimeseriesName::try_from(params.timeseries_name.as_str())
+            .map_err(|e| Error::invalid_request(&e.to_string()))?;
+        for criterion in &params.criteria {
+            criterion
+                .parse::<oximeter_db::query::StringFieldSelector>()
+                .map_err(|e| Error::invalid_request(&e.to_string()))?;
+        }
+        let rule = db::model::AlertRule::new(Uuid::new_v4(), params);
+        self.db_datastore.alert_rule_create(opctx, rule).await
+    }
+
+    pub async fn alert_rule_list(
+        &self,
+        opctx: &OpContext,
+        pagparams: &PaginatedBy<'_>,
+    ) -> ListResultVec<db::model::AlertRule> {
+        self.db_datastore.alert_rule_list(opctx, pagparams).await
+    }
+
+    pub async fn alert_rule_delete(
+        &self,
+        opctx: &OpContext,
+        alert_rule_lookup: &lookup::AlertRule<'_>,
+    ) -> DeleteResult {
+        let (.., authz_rule) =
+            alert_rule_lookup.lookup_for(authz::Action::Delete).await?;
+        self.db_datastore.alert_rule_delete(opctx, &authz_rule).await
+    }
+
+    pub async fn alert_list(
+        &self,
+        opctx: &OpContext,
+        alert_rule_lookup: &lookup::AlertRule<'_>,
+        pagparams: &DataPageParams<'_, (DateTime<Utc>, Uuid)>,
+    ) -> ListResultVec<db::model::Alert> {
+        let (.., authz_rule) =
+            alert_rule_lookup.lookup_for(authz::Action::ListChildren).await?;
+        self.db_datastore.alert_list(opctx, &authz_rule, pagparams).await
+    }
+
+    /// Evaluates every alert rule once, recording which rules are firing and
+    /// notifying the configured webhook of any that started or stopped.
+    ///
+    /// A rule that can't be evaluated (e.g., because its timeseries can't be
+    /// queried) is logged and left in its current state.
+    pub async fn alert_rules_evaluate(&self) -> Result<(), Error> {
+        let opctx = &self.opctx_alerts;
+        let mut marker = None;
+        loop {
+            let rules = self
+                .db_datastore
+                .alert_rule_list(
+                    opctx,
+                    &PaginatedBy::Id(DataPageParams {
+                        marker: marker.as_ref(),
+                        direction: dropshot::PaginationOrder::Ascending,
+                        limit: NonZeroU32::new(ALERT_RULE_BATCH_SIZE).unwrap(),
+                    }),
+                )
+                .await?;
+            let Some(last) = rules.last() else {
+                return Ok(());
+            };
+            marker = Some(last.id());
+
+            // Only look up the timeseries database if there are rules to
+            // evaluate.
+            let client = self.timeseries_client.get().await.map_err(|e| {
+                Error::internal_error(&format!(
+                    "Cannot access timeseries DB: {}",
+                    e
+                ))
+            })?;
+
+            for rule in rules {
+                let rule_id = rule.id();
+                if let Err(error) =
+                    self.alert_rule_evaluate(&client, rule).await
+                {
+                    warn!(self.log, "failed to evaluate alert rule";
+                        "alert_rule_id" => %rule_id,
+                        "error" => ?error);
+                }
+            }
+        }
+    }
+
+    /// Evaluates one alert rule over the last `duration_secs` of its
+    /// timeseries.
+    ///
+    /// The rule is breaching if, for any group of timeseries produced by its
+    /// aggregation, the latest aggregated value in that span is past the
+    /// threshold.  The rule fires once it has been breaching at every
+    /// evaluation for at least `duration_secs`, and is resolved as soon as
+    /// it's found not to be.  A rule whose timeseries has no data in that span
+    /// keeps its previous state.
+    async fn alert_rule_evaluate(
+        &self,
+        client: &oximeter_db::Client,
+        rule: db::model::AlertRule,
+    ) -> Result<(), Error> {
+        let opctx = &self.opctx_alerts;
+        let rule_id = rule.id();
+        let authz_rule = authz::AlertRule::new(
+            authz::FLEET,
+            rule_id,
+            LookupType::ById(rule_id),
+        );
+        let view = views::AlertRule::try_from(rule.clone())?;
+
+        let now = Utc::now();
+        let start_time = now - chrono::Duration::seconds(rule.duration_secs);
+        let criteria: Vec<&str> =
+            rule.criteria.iter().map(String::as_str).collect();
+        let timeseries = match client
+            .select_timeseries_aggregated(
+                &rule.timeseries_name,
+                &criteria,
+                Some(Timestamp::Inclusive(start_time)),
+                Some(Timestamp::Exclusive(now)),
+                view.aggregation.clone(),
+                None,
+            )
+            .await
+        {
+            Ok(timeseries) => timeseries,
+            // The timeseries may not have been reported yet.
+            Err(oximeter_db::Error::TimeseriesNotFound(_)) => vec![],
+            Err(e) => return Err(map_oximeter_err(e)),
+        };
+
+        // Report the latest value of a breaching group if there is one, or of
+        // whichever group was seen last otherwise.
+        let mut breaching = false;
+        let mut value = None;
+        for group in &timeseries {
+            let Some(measurement) = group.measurements.last() else {
+                continue;
+            };
+            let latest = match measurement.datum() {
+                Datum::F64(value) => *value,
+                datum => {
+                    return Err(Error::internal_error(&format!(
+                        "expected aggregated values to be F64, found {:?}",
+                        datum.datum_type()
+                    )))
+                }
+            };
+            value = Some(latest);
+            if view.comparison.crosses(latest, rule.threshold) {
+                breaching = true;
+                break;
+            }
+        }
+
+        let (state, time_breach_started) = match value {
+            // Without any data, there's no telling whether the rule is still
+            // breaching, or still not.
+            None => (rule.state, rule.time_breach_started),
+            Some(_) if !breaching => (AlertRuleState::Ok, None),
+            Some(_) => {
+                let started = rule.time_breach_started.unwrap_or(now);
+                let state = if now - started
+                    >= chrono::Duration::seconds(rule.duration_secs)
+                {
+                    AlertRuleState::Firing
+                } else {
+                    rule.state
+                };
+                (state, Some(started))
+            }
+        };
+        let alert = self
+            .db_datastore
+            .alert_rule_record_evaluation(
+                opctx,
+                &authz_rule,
+                rule.state,
+                state,
+                value.or(rule.last_value),
+                time_breach_started,
+            )
+            .await?;
+        if let Some(alert) = alert {
+            info!(self.log, "alert rule changed state";
+                "alert_rule_id" => %rule_id,
+                "state" => ?state,
+                "value" => ?value);
+            self.alert_notify(opctx, &authz_rule, alert).await;
+        }
+        Ok(())
+    }
+
+    /// POSTs a notification describing an alert that just fired or was
+    /// resolved to the configured webhook, if there is one.
+    ///
+    /// Failures are logged rather than retried: the alert's state is recorded
+    /// either way, and can be seen through the API.
+    async fn alert_notify(
+        &self,
+        opctx: &OpContext,
+        authz_rule: &authz::AlertRule,
+        alert: db::model::Alert,
+    ) {
+        let Some(webhook_url) = &self.alerts_webhook_url else {
+            return;
+        };
+        let rule_id = authz_rule.id();
+        let result = async {
+            let (.., db_rule) = LookupPath::new(opctx, &self.db_datastore)
+                .alert_rule_id(rule_id)
+                .fetch()
+                .await?;
+            let notification = AlertNotification {
+                rule: db_rule.try_into()?,
+                alert: alert.into(),
+            };
+            reqwest::Client::new()
+                .post(webhook_url)
+                .timeout(ALERT_WEBHOOK_TIMEOUT)
+                .json(&notification)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| {
+                    Error::unavail(&format!(
+                        "failed to notify alert webhook: {}",
+                        e
+                    ))
+                })?;
+            Ok::<(), Error>(())
+        }
+        .await;
+        if let Err(error) = result {
+            warn!(self.log, "failed to send alert notification";
+                "alert_rule_id" => %rule_id,
+                "webhook_url" => webhook_url,
+                "error" => ?error);
+        }
+    }
+}
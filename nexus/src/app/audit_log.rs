@@ -0,0 +1,100 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Recording mutating external API requests in the audit log
+
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::lookup::LookupPath;
+use crate::external_api::params;
+use crate::external_api::views;
+use chrono::DateTime;
+use chrono::Utc;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DataPageParams;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::UpdateResult;
+use uuid::Uuid;
+
+impl super::Nexus {
+    /// Records a request which has just been received.
+    ///
+    /// This happens before the request is carried out so that there's a record
+    /// of it even if Nexus never finds out how it turned out.
+    pub async fn audit_log_entry_init(
+        &self,
+        entry: db::model::AuditLogEntry,
+    ) -> CreateResult<db::model::AuditLogEntry> {
+        self.db_datastore
+            .audit_log_entry_init(&self.opctx_audit_log, entry)
+            .await
+    }
+
+    /// Records the outcome of a request previously recorded with
+    /// [`Nexus::audit_log_entry_init()`].
+    pub async fn audit_log_entry_complete(
+        &self,
+        entry: &db::model::AuditLogEntry,
+        completion: db::model::AuditLogCompletion,
+    ) -> UpdateResult<()> {
+        self.db_datastore
+            .audit_log_entry_complete(
+                &self.opctx_audit_log,
+                entry.id,
+                completion,
+            )
+            .await
+    }
+
+    /// Describes a Silo user as the actor behind a request, for requests
+    /// which act on a user's behalf without being authenticated as that user.
+    pub async fn audit_log_silo_user_actor(
+        &self,
+        silo_user_id: Uuid,
+    ) -> LookupResult<views::AuditLogActor> {
+        let (.., db_user) =
+            LookupPath::new(&self.opctx_audit_log, &self.db_datastore)
+                .silo_user_id(silo_user_id)
+                .fetch()
+                .await?;
+        Ok(views::AuditLogActor::SiloUser {
+            silo_user_id,
+            silo_id: db_user.silo_id,
+        })
+    }
+
+    /// Lists the audit log.
+    ///
+    /// Fleet viewers can see every entry.  Silo viewers can see the entries
+    /// for requests made by their Silo's users.
+    pub async fn audit_log_list(
+        &self,
+        opctx: &OpContext,
+        params: &params::AuditLogParams,
+        pagparams: &DataPageParams<'_, (DateTime<Utc>, Uuid)>,
+    ) -> ListResultVec<db::model::AuditLogEntry> {
+        let authz_silo = match opctx
+            .authorize(authz::Action::ListChildren, &authz::AUDIT_LOG)
+            .await
+        {
+            Ok(()) => None,
+            Err(Error::Forbidden) => {
+                Some(opctx.authn.silo_or_builtin()?.ok_or(Error::Forbidden)?)
+            }
+            Err(error) => return Err(error),
+        };
+        self.db_datastore
+            .audit_log_list(
+                opctx,
+                authz_silo.as_ref(),
+                params.start_time,
+                params.end_time,
+                pagparams,
+            )
+            .await
+    }
+}
@@ -13,6 +13,7 @@ use crate::db::lookup;
 use crate::db::lookup::LookupPath;
 use crate::db::model::Name;
 use crate::external_api::params;
+use omicron_common::api::external;
 use omicron_common::api::external::http_pagination::PaginatedBy;
 use omicron_common::api::external::ByteCount;
 use omicron_common::api::external::CreateResult;
@@ -22,6 +23,7 @@ use omicron_common::api::external::InternalContext;
 use omicron_common::api::external::ListResultVec;
 use omicron_common::api::external::LookupResult;
 use omicron_common::api::external::NameOrId;
+use omicron_common::api::external::UpdateResult;
 use omicron_common::api::internal::nexus::DiskRuntimeState;
 use ref_cast::RefCast;
 use sled_agent_client::Client as SledAgentClient;
@@ -172,15 +174,63 @@ impl super::Nexus {
                     });
                 }
             }
-            params::DiskSource::Image { image_id: _ } => {
-                // Until we implement project images, do not allow disks to be
-                // created from a project image.
-                return Err(Error::InvalidValue {
-                    label: String::from("image"),
-                    message: String::from(
-                        "project image are not yet supported",
-                    ),
-                });
+            params::DiskSource::Image { image_id } => {
+                let (.., db_image) = LookupPath::new(opctx, &self.db_datastore)
+                    .image_id(*image_id)
+                    .fetch()
+                    .await?;
+
+                // Reject disks where the block size doesn't evenly divide the
+                // total size
+                if (params.size.to_bytes()
+                    % db_image.block_size.to_bytes() as u64)
+                    != 0
+                {
+                    return Err(Error::InvalidValue {
+                        label: String::from("size and block_size"),
+                        message: String::from(
+                            "total size must be a multiple of image's block size",
+                        ),
+                    });
+                }
+
+                // If the size of the image is greater than the size of the
+                // disk, return an error.
+                if db_image.size.to_bytes() > params.size.to_bytes() {
+                    return Err(Error::invalid_request(
+                        &format!(
+                            "disk size {} must be greater than or equal to image size {}",
+                            params.size.to_bytes(),
+                            db_image.size.to_bytes(),
+                        ),
+                    ));
+                }
+
+                // Reject disks where the size isn't at least
+                // MIN_DISK_SIZE_BYTES
+                if params.size.to_bytes() < params::MIN_DISK_SIZE_BYTES as u64 {
+                    return Err(Error::InvalidValue {
+                        label: String::from("size"),
+                        message: format!(
+                            "total size must be at least {}",
+                            ByteCount::from(params::MIN_DISK_SIZE_BYTES)
+                        ),
+                    });
+                }
+
+                // Reject disks where the MIN_DISK_SIZE_BYTES doesn't evenly
+                // divide the size
+                if (params.size.to_bytes() % params::MIN_DISK_SIZE_BYTES as u64)
+                    != 0
+                {
+                    return Err(Error::InvalidValue {
+                        label: String::from("size"),
+                        message: format!(
+                            "total size must be a multiple of {}",
+                            ByteCount::from(params::MIN_DISK_SIZE_BYTES)
+                        ),
+                    });
+                }
             }
             params::DiskSource::GlobalImage { image_id } => {
                 let (.., db_global_image) =
@@ -270,12 +320,7 @@ impl super::Nexus {
     }
 
     /// Modifies the runtime state of the Disk as requested.  This generally
-    /// means attaching or detaching the disk.
-    // TODO(https://github.com/oxidecomputer/omicron/issues/811):
-    // This will be unused until we implement hot-plug support.
-    // However, it has been left for reference until then, as it will
-    // likely be needed once that feature is implemented.
-    #[allow(dead_code)]
+    /// means hot-plugging the disk into, or out of, a running instance.
     pub(crate) async fn disk_set_runtime(
         &self,
         opctx: &OpContext,
@@ -387,6 +432,79 @@ impl super::Nexus {
         Ok(())
     }
 
+    /// Grow a detached disk to a new total size.
+    pub async fn disk_update(
+        self: &Arc<Self>,
+        opctx: &OpContext,
+        disk_lookup: &lookup::Disk<'_>,
+        params: &params::DiskUpdate,
+    ) -> UpdateResult<db::model::Disk> {
+        let (.., authz_project, authz_disk, db_disk) =
+            disk_lookup.fetch_for(authz::Action::Modify).await?;
+
+        let disk_state = db_disk.state();
+        if !matches!(disk_state.state(), external::DiskState::Detached) {
+            return Err(Error::InvalidRequest {
+                message: format!(
+                    "disk must be detached to be resized (currently {})",
+                    disk_state.state().label()
+                ),
+            });
+        }
+
+        let old_size = db_disk.size.to_bytes();
+        let new_size = params.size.to_bytes();
+        if new_size < old_size {
+            return Err(Error::InvalidValue {
+                label: String::from("size"),
+                message: format!(
+                    "disks cannot be shrunk (current size is {})",
+                    db_disk.size.0
+                ),
+            });
+        }
+        if new_size == old_size {
+            return Ok(db_disk);
+        }
+
+        // The added space is backed by its own set of regions, so it is
+        // subject to the same constraints as the size of a new disk.
+        let size_delta = new_size - old_size;
+        if (size_delta % params::MIN_DISK_SIZE_BYTES as u64) != 0 {
+            return Err(Error::InvalidValue {
+                label: String::from("size"),
+                message: format!(
+                    "total size must grow by a multiple of {}",
+                    ByteCount::from(params::MIN_DISK_SIZE_BYTES)
+                ),
+            });
+        }
+        if (size_delta % db_disk.block_size.to_bytes() as u64) != 0 {
+            return Err(Error::InvalidValue {
+                label: String::from("size and block_size"),
+                message: String::from(
+                    "total size must be a multiple of block size",
+                ),
+            });
+        }
+
+        let saga_params = sagas::disk_resize::Params {
+            serialized_authn: authn::saga::Serialized::for_opctx(opctx),
+            project_id: authz_project.id(),
+            authz_disk,
+            disk: db_disk,
+            new_size: params.size,
+        };
+        let saga_outputs = self
+            .execute_saga::<sagas::disk_resize::SagaDiskResize>(saga_params)
+            .await?;
+        let disk_resized = saga_outputs
+            .lookup_node_output::<db::model::Disk>("resized_disk")
+            .map_err(|e| Error::internal_error(&format!("{:#}", &e)))
+            .internal_context("looking up output from disk resize saga")?;
+        Ok(disk_resized)
+    }
+
     /// Remove a read only parent from a disk.
     /// This is just a wrapper around the volume operation of the same
     /// name, but we provide this interface when all the caller has is
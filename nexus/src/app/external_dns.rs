@@ -0,0 +1,274 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Publishing Silo and Instance names in the external DNS zone
+
+use crate::db::datastore::ExternalDnsState;
+use dns_service_client::multiclient::DnsError;
+use dns_service_client::types::DnsConfig;
+use dns_service_client::types::DnsKv;
+use dns_service_client::types::DnsRecord;
+use dns_service_client::types::DnsRecordKey;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+
+impl super::Nexus {
+    /// Asks the external DNS task to bring the zone up to date with the
+    /// current Silos, Instances, and external IPs.
+    ///
+    /// This is called after anything that affects the zone's contents has
+    /// changed.  It does not wait for the update, so it never slows down or
+    /// fails the request that made the change.
+    pub(crate) fn external_dns_sync_activate(&self) {
+        self.external_dns_sync_needed.notify_one();
+    }
+
+    /// Brings the external DNS zone up to date with the current Silos,
+    /// Instances, and external IPs.
+    ///
+    /// This runs in the external DNS task, both when activated and
+    /// periodically.  Failures are logged rather than returned; the next
+    /// successful run repairs the zone, including on any DNS servers that
+    /// missed earlier updates.  If every DNS server already has the right
+    /// records, nothing is written and the generation is left alone.
+    pub(crate) async fn external_dns_sync(&self) {
+        let updater = match &self.external_dns {
+            Some(updater) => updater,
+            None => return,
+        };
+        let log = &self.log;
+        let opctx = &self.opctx_external_dns;
+
+        let state = match self.db_datastore.external_dns_state(opctx).await {
+            Ok(state) => state,
+            Err(error) => {
+                warn!(log, "failed to read external DNS state";
+                    "error" => #%error);
+                return;
+            }
+        };
+        let records = external_dns_records(updater.zone(), &state);
+        match updater.dns_config_get().await {
+            Ok(configs)
+                if configs
+                    .iter()
+                    .all(|config| records_equal(&config.records, &records)) =>
+            {
+                trace!(log, "external DNS is up to date");
+                return;
+            }
+            Ok(_) => (),
+            // The zone may not have been created on some server yet; the put
+            // below takes care of that.
+            Err(error) => {
+                debug!(log, "failed to fetch external DNS config";
+                    "error" => #%error);
+            }
+        }
+
+        // Something changed.  Publish the state under a new generation, so
+        // that it isn't confused with anything published before.
+        let generation = match self
+            .db_datastore
+            .external_dns_generation_bump(opctx, self.rack_id)
+            .await
+        {
+            Ok(generation) => generation,
+            Err(error) => {
+                warn!(log, "failed to bump external DNS generation";
+                    "error" => #%error);
+                return;
+            }
+        };
+        let state = match self.db_datastore.external_dns_state(opctx).await {
+            Ok(state) => state,
+            Err(error) => {
+                warn!(log, "failed to read external DNS state";
+                    "error" => #%error);
+                return;
+            }
+        };
+        let config = DnsConfig {
+            generation,
+            records: external_dns_records(updater.zone(), &state),
+        };
+        match external_dns_put(updater, &config).await {
+            Ok(()) => {
+                debug!(log, "updated external DNS";
+                    "generation" => generation);
+            }
+            // Another Nexus already published a newer generation.  It may
+            // have read the state before this one did, and so miss changes
+            // that this one saw.  Run again to compare the zone against the
+            // current state, and publish that under a newer generation if
+            // it differs.
+            Err(error)
+                if error.status() == Some(http::StatusCode::CONFLICT) =>
+            {
+                debug!(log, "external DNS already has a newer generation";
+                    "generation" => generation);
+                self.external_dns_sync_activate();
+            }
+            Err(error) => {
+                warn!(log, "failed to update external DNS";
+                    "generation" => generation,
+                    "error" => #%error);
+            }
+        }
+    }
+}
+
+/// Returns whether two sets of records for a zone have the same contents,
+/// regardless of the order in which names and records are listed.
+fn records_equal(a: &[DnsKv], b: &[DnsKv]) -> bool {
+    let normalize = |records: &[DnsKv]| {
+        records
+            .iter()
+            .map(|kv| {
+                let values = kv
+                    .records
+                    .iter()
+                    .map(|record| serde_json::to_string(record).unwrap())
+                    .collect::<BTreeSet<_>>();
+                (kv.key.name.clone(), values)
+            })
+            .collect::<BTreeMap<_, _>>()
+    };
+    normalize(a) == normalize(b)
+}
+
+async fn external_dns_put(
+    updater: &dns_service_client::multiclient::Updater,
+    config: &DnsConfig,
+) -> Result<(), DnsError> {
+    updater.dns_zone_create().await?;
+    updater.dns_config_put(config).await
+}
+
+/// Computes the records of the external DNS zone `zone`.
+///
+/// Each Silo is published as `<silo>.<zone>`, resolving to the external
+/// addresses of the Nexus API servers.  Each Instance with ephemeral or
+/// floating IPs is published as
+/// `<instance>.<project>.<organization>.<silo>.<zone>`, resolving to those
+/// IPs.  The organization is part of the name because Project names are only
+/// unique within an Organization.
+fn external_dns_records(zone: &str, state: &ExternalDnsState) -> Vec<DnsKv> {
+    let zone = zone.trim_end_matches('.');
+    let address_records = |ips: &[IpAddr]| {
+        ips.iter()
+            .map(|ip| match ip {
+                IpAddr::V4(ip) => DnsRecord::A(*ip),
+                IpAddr::V6(ip) => DnsRecord::Aaaa(*ip),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut records = Vec::new();
+    if !state.nexus_ips.is_empty() {
+        records.extend(state.silos.iter().map(|silo| DnsKv {
+            key: DnsRecordKey { name: format!("{}.{}", silo, zone) },
+            records: address_records(&state.nexus_ips),
+        }));
+    }
+    records.extend(state.instances.iter().filter(|i| !i.ips.is_empty()).map(
+        |instance| DnsKv {
+            key: DnsRecordKey {
+                name: format!(
+                    "{}.{}.{}.{}.{}",
+                    instance.instance,
+                    instance.project,
+                    instance.organization,
+                    instance.silo,
+                    zone
+                ),
+            },
+            records: address_records(&instance.ips),
+        },
+    ));
+    records
+}
+
+#[cfg(test)]
+mod test {
+    use super::external_dns_records;
+    use super::records_equal;
+    use crate::db::datastore::ExternalDnsInstance;
+    use crate::db::datastore::ExternalDnsState;
+    use crate::db::model::Name;
+    use dns_service_client::types::DnsRecord;
+    use std::net::IpAddr;
+
+    fn name(s: &str) -> Name {
+        Name(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_external_dns_records() {
+        let nexus_ips: Vec<IpAddr> =
+            vec!["192.168.1.20".parse().unwrap(), "fd00::20".parse().unwrap()];
+        let state = ExternalDnsState {
+            nexus_ips: nexus_ips.clone(),
+            silos: vec![name("alpha"), name("beta")],
+            instances: vec![
+                ExternalDnsInstance {
+                    silo: name("alpha"),
+                    organization: name("org"),
+                    project: name("proj"),
+                    instance: name("web"),
+                    ips: vec!["10.0.0.5".parse().unwrap()],
+                },
+                ExternalDnsInstance {
+                    silo: name("beta"),
+                    organization: name("org"),
+                    project: name("proj"),
+                    instance: name("idle"),
+                    ips: vec![],
+                },
+            ],
+        };
+
+        let records = external_dns_records("oxide.example.", &state);
+        let names: Vec<&str> =
+            records.iter().map(|kv| kv.key.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "alpha.oxide.example",
+                "beta.oxide.example",
+                "web.proj.org.alpha.oxide.example",
+            ]
+        );
+        match records[0].records.as_slice() {
+            [DnsRecord::A(a), DnsRecord::Aaaa(aaaa)] => {
+                assert_eq!(IpAddr::V4(*a), nexus_ips[0]);
+                assert_eq!(IpAddr::V6(*aaaa), nexus_ips[1]);
+            }
+            other => panic!("unexpected silo records: {:?}", other),
+        }
+        match records[2].records.as_slice() {
+            [DnsRecord::A(a)] => {
+                assert_eq!(a.to_string(), "10.0.0.5");
+            }
+            other => panic!("unexpected instance records: {:?}", other),
+        }
+
+        // The order of names and records doesn't matter when deciding whether
+        // a DNS server is up to date, but their contents do.
+        let mut reordered = records.clone();
+        reordered.reverse();
+        reordered[2].records.reverse();
+        assert!(records_equal(&records, &reordered));
+        assert!(!records_equal(&records, &reordered[1..]));
+        reordered[2].records.pop();
+        assert!(!records_equal(&records, &reordered));
+
+        // Without any Nexus addresses, Silos are not published at all.
+        let state = ExternalDnsState { nexus_ips: vec![], ..state };
+        let records = external_dns_records("oxide.example", &state);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].key.name, "web.proj.org.alpha.oxide.example");
+    }
+}
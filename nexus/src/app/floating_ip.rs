@@ -0,0 +1,237 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Floating IP addresses
+
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::lookup;
+use crate::db::lookup::LookupPath;
+use crate::db::model::IpKind;
+use crate::db::model::Name;
+use crate::external_api::params;
+use nexus_types::identity::Resource;
+use omicron_common::api::external::http_pagination::PaginatedBy;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::InstanceState;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::NameOrId;
+use omicron_common::api::external::UpdateResult;
+use ref_cast::RefCast;
+use sled_agent_client::types::InstanceExternalIpsEnsureBody;
+use uuid::Uuid;
+
+impl super::Nexus {
+    pub fn floating_ip_lookup<'a>(
+        &'a self,
+        opctx: &'a OpContext,
+        fip_selector: &'a params::FloatingIpSelector,
+    ) -> LookupResult<lookup::FloatingIp<'a>> {
+        match fip_selector {
+            params::FloatingIpSelector {
+                floating_ip: NameOrId::Id(id),
+                project_selector: None,
+            } => {
+                let floating_ip = LookupPath::new(opctx, &self.db_datastore)
+                    .floating_ip_id(*id);
+                Ok(floating_ip)
+            }
+            params::FloatingIpSelector {
+                floating_ip: NameOrId::Name(name),
+                project_selector: Some(project_selector),
+            } => {
+                let floating_ip = self
+                    .project_lookup(opctx, project_selector)?
+                    .floating_ip_name(Name::ref_cast(name));
+                Ok(floating_ip)
+            }
+            params::FloatingIpSelector {
+                floating_ip: NameOrId::Id(_),
+                project_selector: Some(_),
+            } => Err(Error::invalid_request(
+                "when providing floating IP as an ID, project should not be specified",
+            )),
+            _ => Err(Error::invalid_request(
+                "floating IP should either be UUID or project should be specified",
+            )),
+        }
+    }
+
+    pub async fn project_create_floating_ip(
+        &self,
+        opctx: &OpContext,
+        project_lookup: &lookup::Project<'_>,
+        params: &params::FloatingIpCreate,
+    ) -> CreateResult<db::model::FloatingIp> {
+        let (.., authz_project) =
+            project_lookup.lookup_for(authz::Action::CreateChild).await?;
+        self.db_datastore
+            .allocate_floating_ip(opctx, &authz_project, Uuid::new_v4(), params)
+            .await?
+            .try_into()
+    }
+
+    pub async fn floating_ip_list(
+        &self,
+        opctx: &OpContext,
+        project_lookup: &lookup::Project<'_>,
+        pagparams: &PaginatedBy<'_>,
+    ) -> ListResultVec<db::model::FloatingIp> {
+        let (.., authz_project) =
+            project_lookup.lookup_for(authz::Action::ListChildren).await?;
+        self.db_datastore
+            .floating_ip_list(opctx, &authz_project, pagparams)
+            .await
+    }
+
+    pub async fn floating_ip_delete(
+        &self,
+        opctx: &OpContext,
+        fip_lookup: &lookup::FloatingIp<'_>,
+    ) -> DeleteResult {
+        let (.., authz_fip) =
+            fip_lookup.lookup_for(authz::Action::Delete).await?;
+        self.db_datastore.floating_ip_delete(opctx, &authz_fip).await
+    }
+
+    /// Attach a Floating IP to an instance in the same project.
+    ///
+    /// The instance must be either stopped or running. If it is running, the
+    /// new address is sent to the sled on which the instance runs, and the
+    /// attachment is undone if that fails.
+    pub async fn floating_ip_attach(
+        &self,
+        opctx: &OpContext,
+        fip_lookup: &lookup::FloatingIp<'_>,
+        params: &params::FloatingIpAttach,
+    ) -> UpdateResult<db::model::FloatingIp> {
+        let (.., authz_project, authz_fip, db_fip) =
+            fip_lookup.fetch_for(authz::Action::Modify).await?;
+        let (.., authz_project_instance, authz_instance, db_instance) = self
+            .instance_lookup(
+                opctx,
+                &params::InstanceSelector::new(
+                    None,
+                    Some(authz_project.id().into()),
+                    params.instance.clone(),
+                ),
+            )?
+            .fetch_for(authz::Action::Modify)
+            .await?;
+
+        // As with disks, both resources may be provided by ID, so we need to
+        // make sure they actually share a project.
+        if authz_project.id() != authz_project_instance.id() {
+            return Err(Error::invalid_request(
+                "floating IP must be in the same project as the instance",
+            ));
+        }
+        if db_fip.instance_id == Some(authz_instance.id()) {
+            return Ok(db_fip);
+        }
+        Self::check_external_ips_change_allowed(&db_instance)?;
+
+        let fip = self
+            .db_datastore
+            .floating_ip_attach(opctx, &authz_fip, authz_instance.id())
+            .await?;
+        if let Err(e) = self
+            .instance_ensure_external_ips(opctx, &authz_instance, &db_instance)
+            .await
+        {
+            self.db_datastore.floating_ip_detach(opctx, &authz_fip).await?;
+            return Err(e);
+        }
+        self.external_dns_sync_activate();
+        Ok(fip)
+    }
+
+    /// Detach a Floating IP from the instance it's attached to, if any.
+    ///
+    /// As with attachment, the instance must be either stopped or running.
+    pub async fn floating_ip_detach(
+        &self,
+        opctx: &OpContext,
+        fip_lookup: &lookup::FloatingIp<'_>,
+    ) -> UpdateResult<db::model::FloatingIp> {
+        let (.., authz_fip, db_fip) =
+            fip_lookup.fetch_for(authz::Action::Modify).await?;
+        let instance_id = match db_fip.instance_id {
+            Some(instance_id) => instance_id,
+            None => return Ok(db_fip),
+        };
+        let (.., authz_instance, db_instance) =
+            LookupPath::new(opctx, &self.db_datastore)
+                .instance_id(instance_id)
+                .fetch_for(authz::Action::Modify)
+                .await?;
+        Self::check_external_ips_change_allowed(&db_instance)?;
+
+        let fip =
+            self.db_datastore.floating_ip_detach(opctx, &authz_fip).await?;
+        if let Err(e) = self
+            .instance_ensure_external_ips(opctx, &authz_instance, &db_instance)
+            .await
+        {
+            self.db_datastore
+                .floating_ip_attach(opctx, &authz_fip, instance_id)
+                .await?;
+            return Err(e);
+        }
+        self.external_dns_sync_activate();
+        Ok(fip)
+    }
+
+    /// Checks that the set of external IPs of an instance may be modified.
+    ///
+    /// Stopped instances pick up their external IPs when they're next started.
+    /// Running instances are updated in place. Instances in any other state
+    /// may be in the middle of handing their configuration to a sled, so
+    /// changes are refused rather than possibly lost.
+    fn check_external_ips_change_allowed(
+        db_instance: &db::model::Instance,
+    ) -> Result<(), Error> {
+        let state: InstanceState = db_instance.runtime().state.0;
+        match state {
+            InstanceState::Stopped | InstanceState::Running => Ok(()),
+            _ => Err(Error::invalid_request(&format!(
+                "cannot attach or detach floating IPs while instance is {}",
+                state.label(),
+            ))),
+        }
+    }
+
+    /// Sends the current set of external IPs of a running instance to the
+    /// sled on which it runs.
+    async fn instance_ensure_external_ips(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        db_instance: &db::model::Instance,
+    ) -> Result<(), Error> {
+        if db_instance.runtime().state.0 != InstanceState::Running {
+            return Ok(());
+        }
+        let external_ips = self
+            .db_datastore
+            .instance_lookup_external_ips(opctx, authz_instance.id())
+            .await?
+            .into_iter()
+            .filter(|ip| ip.kind != IpKind::SNat)
+            .map(|ip| ip.ip.ip())
+            .collect();
+        let sa = self.instance_sled(db_instance).await?;
+        sa.instance_external_ips_put(
+            &db_instance.id(),
+            &InstanceExternalIpsEnsureBody { external_ips },
+        )
+        .await
+        .map_err(Error::from)?;
+        Ok(())
+    }
+}
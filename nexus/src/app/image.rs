@@ -9,6 +9,7 @@ use crate::authz;
 use crate::context::OpContext;
 use crate::db;
 use crate::db::identity::Asset;
+use crate::db::identity::Resource;
 use crate::db::lookup::LookupPath;
 use crate::db::model::Name;
 use crate::external_api::params;
@@ -21,6 +22,7 @@ use omicron_common::api::external::ListResultVec;
 use omicron_common::api::external::LookupResult;
 use omicron_common::api::external::LookupType;
 use omicron_common::api::external::ResourceType;
+use sled_agent_client::types::VolumeConstructionRequest;
 use std::str::FromStr;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -33,18 +35,145 @@ impl super::Nexus {
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        _params: &params::ImageCreate,
+        params: &params::ImageCreate,
     ) -> CreateResult<db::model::Image> {
-        let _ = LookupPath::new(opctx, &self.db_datastore)
+        let (.., authz_project) = LookupPath::new(opctx, &self.db_datastore)
             .organization_name(organization_name)
             .project_name(project_name)
             .lookup_for(authz::Action::CreateChild)
             .await?;
 
-        // TODO(https://github.com/oxidecomputer/omicron/issues/1482): When
-        // we implement this, remember to insert the image within a Project
-        // using "insert_resource".
-        Err(self.unimplemented_todo(opctx, Unimpl::Public).await)
+        let db_block_size = db::model::BlockSize::try_from(params.block_size)
+            .map_err(|e| Error::InvalidValue {
+            label: String::from("block_size"),
+            message: format!("block_size is invalid: {}", e),
+        })?;
+
+        let image_id = Uuid::new_v4();
+
+        let (volume_construction_request, url, size) = match &params.source {
+            params::ImageSource::Url { url } => {
+                let size = image_size_from_url(url, params.block_size).await?;
+                let volume_construction_request =
+                    VolumeConstructionRequest::Url {
+                        id: image_id,
+                        block_size: db_block_size.to_bytes().into(),
+                        url: url.clone(),
+                    };
+                (volume_construction_request, Some(url.clone()), size)
+            }
+
+            params::ImageSource::Snapshot { id } => {
+                let (.., authz_snapshot_project, _, db_snapshot) =
+                    LookupPath::new(opctx, &self.db_datastore)
+                        .snapshot_id(*id)
+                        .fetch()
+                        .await?;
+
+                if authz_snapshot_project.id() != authz_project.id() {
+                    return Err(Error::invalid_request(
+                        "snapshot must be in the same project as the image",
+                    ));
+                }
+
+                if db_snapshot.state != db::model::SnapshotState::Ready {
+                    return Err(Error::invalid_request(&format!(
+                        "snapshot {} is not ready (state {:?})",
+                        db_snapshot.id(),
+                        db_snapshot.state,
+                    )));
+                }
+
+                if db_snapshot.block_size != db_block_size {
+                    return Err(Error::InvalidValue {
+                        label: String::from("block_size"),
+                        message: format!(
+                            "block_size must match the snapshot's block size \
+                            of {}",
+                            db_snapshot.block_size.to_bytes(),
+                        ),
+                    });
+                }
+
+                // The image's volume is a read-only view of the snapshot: the
+                // snapshot's volume construction request becomes the read-only
+                // parent of an otherwise empty volume. Creating the volume
+                // record takes a reference on each of the snapshot's region
+                // snapshots, so they outlive the snapshot itself for as long
+                // as the image exists.
+                let snapshot_volume = self
+                    .db_datastore
+                    .volume_checkout(db_snapshot.volume_id)
+                    .await?;
+                let snapshot_volume_construction_request: VolumeConstructionRequest =
+                    serde_json::from_str(snapshot_volume.data())?;
+
+                let volume_construction_request =
+                    VolumeConstructionRequest::Volume {
+                        id: image_id,
+                        block_size: db_block_size.to_bytes().into(),
+                        sub_volumes: vec![],
+                        read_only_parent: Some(Box::new(
+                            snapshot_volume_construction_request,
+                        )),
+                    };
+                (volume_construction_request, None, db_snapshot.size.into())
+            }
+
+            params::ImageSource::YouCanBootAnythingAsLongAsItsAlpine => {
+                return Err(Error::InvalidValue {
+                    label: String::from("source"),
+                    message: String::from(
+                        "the alpine image source is only available for \
+                        system-wide images",
+                    ),
+                });
+            }
+        };
+
+        let volume_data = serde_json::to_string(&volume_construction_request)?;
+        let volume = self
+            .db_datastore
+            .volume_create(db::model::Volume::new(Uuid::new_v4(), volume_data))
+            .await?;
+
+        let new_image = db::model::Image {
+            identity: db::model::ImageIdentity::new(
+                image_id,
+                params.identity.clone(),
+            ),
+            project_id: authz_project.id(),
+            volume_id: volume.id(),
+            url,
+            version: None,
+            digest: None,
+            block_size: db_block_size,
+            size: size.into(),
+        };
+
+        match self
+            .db_datastore
+            .project_image_create(opctx, &authz_project, new_image)
+            .await
+        {
+            Ok(image) => Ok(image),
+            Err(e) => {
+                // Don't leak the volume (and the region snapshot references
+                // it holds) if the image record could not be created.
+                if let Err(delete_error) =
+                    self.volume_delete(opctx, volume.id()).await
+                {
+                    warn!(
+                        opctx.log,
+                        "failed to clean up volume {} for image {}: {}",
+                        volume.id(),
+                        image_id,
+                        delete_error,
+                    );
+                }
+                Err(e)
+            }
+        }
     }
 
     pub async fn project_list_images(
@@ -52,27 +181,32 @@ impl super::Nexus {
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        _pagparams: &DataPageParams<'_, Name>,
+        pagparams: &DataPageParams<'_, Name>,
     ) -> ListResultVec<db::model::Image> {
-        let _ = LookupPath::new(opctx, &self.db_datastore)
+        let (.., authz_project) = LookupPath::new(opctx, &self.db_datastore)
             .organization_name(organization_name)
             .project_name(project_name)
             .lookup_for(authz::Action::ListChildren)
             .await?;
-        Err(self.unimplemented_todo(opctx, Unimpl::Public).await)
+        self.db_datastore
+            .project_image_list(opctx, &authz_project, pagparams)
+            .await
     }
 
     pub async fn project_image_fetch(
         &self,
         opctx: &OpContext,
-        _organization_name: &Name,
-        _project_name: &Name,
+        organization_name: &Name,
+        project_name: &Name,
         image_name: &Name,
     ) -> LookupResult<db::model::Image> {
-        let lookup_type = LookupType::ByName(image_name.to_string());
-        let not_found_error = lookup_type.into_not_found(ResourceType::Image);
-        let unimp = Unimpl::ProtectedLookup(not_found_error);
-        Err(self.unimplemented_todo(opctx, unimp).await)
+        let (.., db_image) = LookupPath::new(opctx, &self.db_datastore)
+            .organization_name(organization_name)
+            .project_name(project_name)
+            .image_name(image_name)
+            .fetch()
+            .await?;
+        Ok(db_image)
     }
 
     pub async fn project_image_fetch_by_id(
@@ -80,23 +214,33 @@ impl super::Nexus {
         opctx: &OpContext,
         image_id: &Uuid,
     ) -> LookupResult<db::model::Image> {
-        let lookup_type = LookupType::ById(*image_id);
-        let not_found_error = lookup_type.into_not_found(ResourceType::Image);
-        let unimp = Unimpl::ProtectedLookup(not_found_error);
-        Err(self.unimplemented_todo(opctx, unimp).await)
+        let (.., db_image) = LookupPath::new(opctx, &self.db_datastore)
+            .image_id(*image_id)
+            .fetch()
+            .await?;
+        Ok(db_image)
     }
 
     pub async fn project_delete_image(
         self: &Arc<Self>,
         opctx: &OpContext,
-        _organization_name: &Name,
-        _project_name: &Name,
+        organization_name: &Name,
+        project_name: &Name,
         image_name: &Name,
     ) -> DeleteResult {
-        let lookup_type = LookupType::ByName(image_name.to_string());
-        let not_found_error = lookup_type.into_not_found(ResourceType::Image);
-        let unimp = Unimpl::ProtectedLookup(not_found_error);
-        Err(self.unimplemented_todo(opctx, unimp).await)
+        let (.., authz_image, db_image) =
+            LookupPath::new(opctx, &self.db_datastore)
+                .organization_name(organization_name)
+                .project_name(project_name)
+                .image_name(image_name)
+                .fetch_for(authz::Action::Delete)
+                .await?;
+
+        self.db_datastore.project_image_delete(opctx, &authz_image).await?;
+
+        // Disks created from this image hold their own copy of its volume
+        // construction request, so the image's volume can go away now.
+        self.volume_delete(opctx, db_image.volume_id).await
     }
 
     // Globally-Scoped Images
@@ -128,79 +272,7 @@ impl super::Nexus {
                 let volume_data =
                     serde_json::to_string(&volume_construction_request)?;
 
-                // use reqwest to query url for size
-                let dur = std::time::Duration::from_secs(5);
-                let client = reqwest::ClientBuilder::new()
-                    .connect_timeout(dur)
-                    .timeout(dur)
-                    .build()
-                    .map_err(|e| {
-                        Error::internal_error(&format!(
-                            "failed to build reqwest client: {}",
-                            e
-                        ))
-                    })?;
-
-                let response = client.head(url).send().await.map_err(|e| {
-                    Error::InvalidValue {
-                        label: String::from("url"),
-                        message: format!("error querying url: {}", e),
-                    }
-                })?;
-
-                if !response.status().is_success() {
-                    return Err(Error::InvalidValue {
-                        label: String::from("url"),
-                        message: format!(
-                            "querying url returned: {}",
-                            response.status()
-                        ),
-                    });
-                }
-
-                // grab total size from content length
-                let content_length = response
-                    .headers()
-                    .get(reqwest::header::CONTENT_LENGTH)
-                    .ok_or("no content length!")
-                    .map_err(|e| Error::InvalidValue {
-                        label: String::from("url"),
-                        message: format!("error querying url: {}", e),
-                    })?;
-
-                let total_size =
-                    u64::from_str(content_length.to_str().map_err(|e| {
-                        Error::InvalidValue {
-                            label: String::from("url"),
-                            message: format!("content length invalid: {}", e),
-                        }
-                    })?)
-                    .map_err(|e| {
-                        Error::InvalidValue {
-                            label: String::from("url"),
-                            message: format!("content length invalid: {}", e),
-                        }
-                    })?;
-
-                let size: external::ByteCount = total_size.try_into().map_err(
-                    |e: external::ByteCountRangeError| Error::InvalidValue {
-                        label: String::from("size"),
-                        message: format!("total size is invalid: {}", e),
-                    },
-                )?;
-
-                // validate total size is divisible by block size
-                let block_size: u64 = params.block_size.into();
-                if (size.to_bytes() % block_size) != 0 {
-                    return Err(Error::InvalidValue {
-                        label: String::from("size"),
-                        message: format!(
-                            "total size {} must be divisible by block size {}",
-                            size.to_bytes(),
-                            block_size
-                        ),
-                    });
-                }
+                let size = image_size_from_url(url, params.block_size).await?;
 
                 let new_image_volume =
                     db::model::Volume::new(Uuid::new_v4(), volume_data);
@@ -332,3 +404,80 @@ impl super::Nexus {
             .await)
     }
 }
+
+/// Determine the size of an image served at `url` from the `Content-Length`
+/// of a HEAD request, checking that it is a whole number of `block_size`
+/// blocks.
+async fn image_size_from_url(
+    url: &str,
+    block_size: params::BlockSize,
+) -> Result<external::ByteCount, Error> {
+    let dur = std::time::Duration::from_secs(5);
+    let client = reqwest::ClientBuilder::new()
+        .connect_timeout(dur)
+        .timeout(dur)
+        .build()
+        .map_err(|e| {
+            Error::internal_error(&format!(
+                "failed to build reqwest client: {}",
+                e
+            ))
+        })?;
+
+    let response =
+        client.head(url).send().await.map_err(|e| Error::InvalidValue {
+            label: String::from("url"),
+            message: format!("error querying url: {}", e),
+        })?;
+
+    if !response.status().is_success() {
+        return Err(Error::InvalidValue {
+            label: String::from("url"),
+            message: format!("querying url returned: {}", response.status()),
+        });
+    }
+
+    // grab total size from content length
+    let content_length = response
+        .headers()
+        .get(reqwest::header::CONTENT_LENGTH)
+        .ok_or("no content length!")
+        .map_err(|e| Error::InvalidValue {
+            label: String::from("url"),
+            message: format!("error querying url: {}", e),
+        })?;
+
+    let total_size = u64::from_str(content_length.to_str().map_err(|e| {
+        Error::InvalidValue {
+            label: String::from("url"),
+            message: format!("content length invalid: {}", e),
+        }
+    })?)
+    .map_err(|e| Error::InvalidValue {
+        label: String::from("url"),
+        message: format!("content length invalid: {}", e),
+    })?;
+
+    let size: external::ByteCount =
+        total_size.try_into().map_err(|e: external::ByteCountRangeError| {
+            Error::InvalidValue {
+                label: String::from("size"),
+                message: format!("total size is invalid: {}", e),
+            }
+        })?;
+
+    // validate total size is divisible by block size
+    let block_size: u64 = block_size.into();
+    if (size.to_bytes() % block_size) != 0 {
+        return Err(Error::InvalidValue {
+            label: String::from("size"),
+            message: format!(
+                "total size {} must be divisible by block size {}",
+                size.to_bytes(),
+                block_size
+            ),
+        });
+    }
+
+    Ok(size)
+}
@@ -21,13 +21,16 @@ use futures::future::Fuse;
 use futures::{FutureExt, SinkExt, StreamExt};
 use nexus_db_model::IpKind;
 use nexus_db_model::Name;
+use nexus_defaults as defaults;
 use omicron_common::address::PROPOLIS_PORT;
 use omicron_common::api::external::http_pagination::PaginatedBy;
 use omicron_common::api::external::ByteCount;
 use omicron_common::api::external::CreateResult;
 use omicron_common::api::external::DataPageParams;
 use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::DiskState;
 use omicron_common::api::external::Error;
+use omicron_common::api::external::InstanceCpuCount;
 use omicron_common::api::external::InstanceState;
 use omicron_common::api::external::InternalContext;
 use omicron_common::api::external::ListResultVec;
@@ -42,6 +45,7 @@ use sled_agent_client::types::InstanceRuntimeStateRequested;
 use sled_agent_client::types::InstanceStateRequested;
 use sled_agent_client::types::SourceNatConfig;
 use sled_agent_client::Client as SledAgentClient;
+use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::io::{AsyncRead, AsyncWrite};
@@ -142,31 +146,7 @@ impl super::Nexus {
             }
         }
 
-        // Reject instances where the memory is not at least
-        // MIN_MEMORY_SIZE_BYTES
-        if params.memory.to_bytes() < params::MIN_MEMORY_SIZE_BYTES as u64 {
-            return Err(Error::InvalidValue {
-                label: String::from("size"),
-                message: format!(
-                    "memory must be at least {}",
-                    ByteCount::from(params::MIN_MEMORY_SIZE_BYTES)
-                ),
-            });
-        }
-
-        // Reject instances where the memory is not divisible by
-        // MIN_MEMORY_SIZE_BYTES
-        if (params.memory.to_bytes() % params::MIN_MEMORY_SIZE_BYTES as u64)
-            != 0
-        {
-            return Err(Error::InvalidValue {
-                label: String::from("size"),
-                message: format!(
-                    "memory must be divisible by {}",
-                    ByteCount::from(params::MIN_MEMORY_SIZE_BYTES)
-                ),
-            });
-        }
+        Self::validate_instance_resources(params.ncpus, params.memory)?;
 
         let saga_params = sagas::instance_create::Params {
             serialized_authn: authn::saga::Serialized::for_opctx(opctx),
@@ -224,9 +204,68 @@ impl super::Nexus {
             .instance_id(instance_id)
             .fetch()
             .await?;
+        if !params.external_ips.is_empty() {
+            self.external_dns_sync_activate();
+        }
         Ok(db_instance)
     }
 
+    /// Checks that the CPU count and memory size of an instance are within
+    /// the limits Nexus supports.
+    fn validate_instance_resources(
+        ncpus: InstanceCpuCount,
+        memory: ByteCount,
+    ) -> Result<(), Error> {
+        if ncpus.0 == 0 || ncpus.0 > defaults::MAX_INSTANCE_CPUS {
+            return Err(Error::InvalidValue {
+                label: String::from("ncpus"),
+                message: format!(
+                    "ncpus must be between 1 and {}",
+                    defaults::MAX_INSTANCE_CPUS
+                ),
+            });
+        }
+
+        // Reject instances where the memory is not at least
+        // MIN_MEMORY_SIZE_BYTES
+        if memory.to_bytes() < params::MIN_MEMORY_SIZE_BYTES as u64 {
+            return Err(Error::InvalidValue {
+                label: String::from("size"),
+                message: format!(
+                    "memory must be at least {}",
+                    ByteCount::from(params::MIN_MEMORY_SIZE_BYTES)
+                ),
+            });
+        }
+
+        // Reject instances where the memory is not divisible by
+        // MIN_MEMORY_SIZE_BYTES
+        if (memory.to_bytes() % params::MIN_MEMORY_SIZE_BYTES as u64) != 0 {
+            return Err(Error::InvalidValue {
+                label: String::from("size"),
+                message: format!(
+                    "memory must be divisible by {}",
+                    ByteCount::from(params::MIN_MEMORY_SIZE_BYTES)
+                ),
+            });
+        }
+
+        // Reject instances with more memory than
+        // MAX_INSTANCE_MEMORY_BYTES
+        if memory.to_bytes() > defaults::MAX_INSTANCE_MEMORY_BYTES {
+            return Err(Error::InvalidValue {
+                label: String::from("size"),
+                message: format!(
+                    "memory must be at most {}",
+                    ByteCount::try_from(defaults::MAX_INSTANCE_MEMORY_BYTES)
+                        .unwrap()
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
     pub async fn instance_list(
         &self,
         opctx: &OpContext,
@@ -238,6 +277,46 @@ impl super::Nexus {
         self.db_datastore.instance_list(opctx, &authz_project, pagparams).await
     }
 
+    /// Change the number of CPUs and the amount of memory of a stopped
+    /// instance.
+    pub async fn instance_update(
+        &self,
+        opctx: &OpContext,
+        instance_lookup: &lookup::Instance<'_>,
+        params: &params::InstanceUpdate,
+    ) -> UpdateResult<db::model::Instance> {
+        let (.., authz_instance, db_instance) =
+            instance_lookup.fetch_for(authz::Action::Modify).await?;
+
+        let state = db_instance.runtime().state.0;
+        if state != InstanceState::Stopped {
+            return Err(Error::InvalidRequest {
+                message: format!(
+                    "instance must be stopped to be resized (currently {})",
+                    state.label()
+                ),
+            });
+        }
+        Self::validate_instance_resources(params.ncpus, params.memory)?;
+
+        let runtime = db_instance.runtime();
+        if runtime.ncpus.0 .0 == params.ncpus.0
+            && runtime.memory.to_bytes() == params.memory.to_bytes()
+        {
+            return Ok(db_instance);
+        }
+
+        self.db_datastore
+            .instance_update_resources(
+                opctx,
+                &authz_instance,
+                &db_instance,
+                params.ncpus,
+                params.memory,
+            )
+            .await
+    }
+
     // This operation may only occur on stopped instances, which implies that
     // the attached disks do not have any running "upstairs" process running
     // within the sled.
@@ -261,6 +340,7 @@ impl super::Nexus {
             saga_params,
         )
         .await?;
+        self.external_dns_sync_activate();
         Ok(())
     }
 
@@ -270,8 +350,18 @@ impl super::Nexus {
         instance_lookup: &lookup::Instance<'_>,
         params: params::InstanceMigrate,
     ) -> UpdateResult<db::model::Instance> {
-        let (.., authz_instance) =
-            instance_lookup.lookup_for(authz::Action::Modify).await?;
+        let (.., authz_instance, db_instance) =
+            instance_lookup.fetch_for(authz::Action::Modify).await?;
+
+        // Only a running Instance can be live-migrated.  (A stopped one can
+        // simply be started elsewhere.)
+        let state = db_instance.runtime().state.state();
+        if *state != InstanceState::Running {
+            return Err(Error::invalid_request(&format!(
+                "cannot migrate instance in state \"{}\"",
+                state,
+            )));
+        }
 
         // Kick off the migration saga
         let saga_params = sagas::instance_migrate::Params {
@@ -460,12 +550,108 @@ impl super::Nexus {
             &requested,
         )?;
 
-        // Gather disk information and turn that into DiskRequests
+        // Ask the sled agent to begin the state change.  Then update the
+        // database to reflect the new intermediate state.  If this update is
+        // not the newest one, that's fine.  That might just mean the sled agent
+        // beat us to it.
+
+        let instance_hardware =
+            self.instance_hardware(opctx, authz_instance, db_instance).await?;
+
+        let sa = self.instance_sled(&db_instance).await?;
+
+        let instance_put_result = sa
+            .instance_put(
+                &db_instance.id(),
+                &sled_agent_client::types::InstanceEnsureBody {
+                    initial: instance_hardware,
+                    target: requested.clone(),
+                    migrate: None,
+                },
+            )
+            .await;
+
+        match instance_put_result {
+            Ok(new_runtime) => {
+                let new_runtime: nexus::InstanceRuntimeState =
+                    new_runtime.into_inner().into();
+
+                self.db_datastore
+                    .instance_update_runtime(
+                        &db_instance.id(),
+                        &new_runtime.into(),
+                    )
+                    .await
+                    .map(|_| ())
+            }
+
+            Err(e) => {
+                // The sled-agent has told us that it can't do what we
+                // requested, but does that mean a failure? One example would be
+                // if we try to "reboot" a stopped instance. That shouldn't
+                // transition the instance to failed. But if the sled-agent
+                // *can't* boot a stopped instance, that should transition
+                // to failed.
+                //
+                // Without a richer error type, let the sled-agent tell Nexus
+                // what to do with status codes.
+                error!(self.log, "saw {} from instance_put!", e);
+
+                // this is unfortunate, but sled_agent_client::Error doesn't
+                // implement Copy, and can't be match'ed upon below without this
+                // line.
+                let e = e.into();
+
+                match &e {
+                    // Bad request shouldn't change the instance state.
+                    Error::InvalidRequest { .. } => Err(e),
+
+                    // Internal server error (or anything else) should change
+                    // the instance state to failed, we don't know what state
+                    // the instance is in.
+                    _ => {
+                        let new_runtime = db::model::InstanceRuntimeState {
+                            state: db::model::InstanceState::new(
+                                InstanceState::Failed,
+                            ),
+                            gen: db_instance.runtime_state.gen.next().into(),
+                            ..db_instance.runtime_state.clone()
+                        };
+
+                        // XXX what if this fails?
+                        let result = self
+                            .db_datastore
+                            .instance_update_runtime(
+                                &db_instance.id(),
+                                &new_runtime,
+                            )
+                            .await;
+
+                        error!(
+                            self.log,
+                            "saw {:?} from setting InstanceState::Failed after bad instance_put",
+                            result,
+                        );
+
+                        Err(e)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Gathers everything a sled agent needs to know to run the Instance.
+    pub(crate) async fn instance_hardware(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        db_instance: &db::model::Instance,
+    ) -> Result<sled_agent_client::types::InstanceHardware, Error> {
         let disks = self
             .db_datastore
             .instance_list_disks(
-                &opctx,
-                &authz_instance,
+                opctx,
+                authz_instance,
                 &PaginatedBy::Name(DataPageParams {
                     marker: None,
                     direction: dropshot::PaginationOrder::Ascending,
@@ -492,42 +678,11 @@ impl super::Nexus {
 
         let nics = self
             .db_datastore
-            .derive_guest_network_interface_info(&opctx, &authz_instance)
+            .derive_guest_network_interface_info(opctx, authz_instance)
             .await?;
 
-        // Collect the external IPs for the instance.
-        // TODO-correctness: Handle Floating IPs, see
-        //  https://github.com/oxidecomputer/omicron/issues/1334
-        let (snat_ip, external_ips): (Vec<_>, Vec<_>) = self
-            .db_datastore
-            .instance_lookup_external_ips(&opctx, authz_instance.id())
-            .await?
-            .into_iter()
-            .partition(|ip| ip.kind == IpKind::SNat);
-
-        // Sanity checks on the number and kind of each IP address.
-        // TODO-correctness: Handle multiple IP addresses, see
-        //  https://github.com/oxidecomputer/omicron/issues/1467
-        if external_ips.len() > MAX_EXTERNAL_IPS_PER_INSTANCE {
-            return Err(Error::internal_error(
-                format!(
-                    "Expected the number of external IPs to be limited to \
-                    {}, but found {}",
-                    MAX_EXTERNAL_IPS_PER_INSTANCE,
-                    external_ips.len(),
-                )
-                .as_str(),
-            ));
-        }
-        let external_ips =
-            external_ips.into_iter().map(|model| model.ip.ip()).collect();
-        if snat_ip.len() != 1 {
-            return Err(Error::internal_error(
-                "Expected exactly one SNAT IP address for an instance",
-            ));
-        }
-        let source_nat =
-            SourceNatConfig::from(snat_ip.into_iter().next().unwrap());
+        let (source_nat, external_ips) =
+            self.instance_external_ips_for_sled(opctx, authz_instance).await?;
 
         // Gather the firewall rules for the VPC this instance is in.
         // The NIC info we gathered above doesn't have VPC information
@@ -583,12 +738,7 @@ impl super::Nexus {
             .map(|ssh_key| ssh_key.public_key)
             .collect::<Vec<String>>();
 
-        // Ask the sled agent to begin the state change.  Then update the
-        // database to reflect the new intermediate state.  If this update is
-        // not the newest one, that's fine.  That might just mean the sled agent
-        // beat us to it.
-
-        let instance_hardware = sled_agent_client::types::InstanceHardware {
+        Ok(sled_agent_client::types::InstanceHardware {
             runtime: sled_agent_client::types::InstanceRuntimeState::from(
                 db_instance.runtime().clone(),
             ),
@@ -601,88 +751,49 @@ impl super::Nexus {
                 &base64::engine::general_purpose::STANDARD,
                 db_instance.generate_cidata(&public_keys)?,
             )),
-        };
-
-        let sa = self.instance_sled(&db_instance).await?;
-
-        let instance_put_result = sa
-            .instance_put(
-                &db_instance.id(),
-                &sled_agent_client::types::InstanceEnsureBody {
-                    initial: instance_hardware,
-                    target: requested.clone(),
-                    migrate: None,
-                },
-            )
-            .await;
-
-        match instance_put_result {
-            Ok(new_runtime) => {
-                let new_runtime: nexus::InstanceRuntimeState =
-                    new_runtime.into_inner().into();
-
-                self.db_datastore
-                    .instance_update_runtime(
-                        &db_instance.id(),
-                        &new_runtime.into(),
-                    )
-                    .await
-                    .map(|_| ())
-            }
-
-            Err(e) => {
-                // The sled-agent has told us that it can't do what we
-                // requested, but does that mean a failure? One example would be
-                // if we try to "reboot" a stopped instance. That shouldn't
-                // transition the instance to failed. But if the sled-agent
-                // *can't* boot a stopped instance, that should transition
-                // to failed.
-                //
-                // Without a richer error type, let the sled-agent tell Nexus
-                // what to do with status codes.
-                error!(self.log, "saw {} from instance_put!", e);
-
-                // this is unfortunate, but sled_agent_client::Error doesn't
-                // implement Copy, and can't be match'ed upon below without this
-                // line.
-                let e = e.into();
-
-                match &e {
-                    // Bad request shouldn't change the instance state.
-                    Error::InvalidRequest { .. } => Err(e),
-
-                    // Internal server error (or anything else) should change
-                    // the instance state to failed, we don't know what state
-                    // the instance is in.
-                    _ => {
-                        let new_runtime = db::model::InstanceRuntimeState {
-                            state: db::model::InstanceState::new(
-                                InstanceState::Failed,
-                            ),
-                            gen: db_instance.runtime_state.gen.next().into(),
-                            ..db_instance.runtime_state.clone()
-                        };
-
-                        // XXX what if this fails?
-                        let result = self
-                            .db_datastore
-                            .instance_update_runtime(
-                                &db_instance.id(),
-                                &new_runtime,
-                            )
-                            .await;
+        })
+    }
 
-                        error!(
-                            self.log,
-                            "saw {:?} from setting InstanceState::Failed after bad instance_put",
-                            result,
-                        );
+    /// Returns the source NAT configuration and external IP addresses that a
+    /// sled agent should give the Instance.
+    pub(crate) async fn instance_external_ips_for_sled(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+    ) -> Result<(SourceNatConfig, Vec<IpAddr>), Error> {
+        // Collect the external IPs for the instance. Any attached Floating IPs
+        // are provided to the sled alongside Ephemeral IPs.
+        let (snat_ip, external_ips): (Vec<_>, Vec<_>) = self
+            .db_datastore
+            .instance_lookup_external_ips(opctx, authz_instance.id())
+            .await?
+            .into_iter()
+            .partition(|ip| ip.kind == IpKind::SNat);
 
-                        Err(e)
-                    }
-                }
-            }
+        // Sanity checks on the number and kind of each IP address.
+        // TODO-correctness: Handle multiple IP addresses, see
+        //  https://github.com/oxidecomputer/omicron/issues/1467
+        if external_ips.len() > MAX_EXTERNAL_IPS_PER_INSTANCE {
+            return Err(Error::internal_error(
+                format!(
+                    "Expected the number of external IPs to be limited to \
+                    {}, but found {}",
+                    MAX_EXTERNAL_IPS_PER_INSTANCE,
+                    external_ips.len(),
+                )
+                .as_str(),
+            ));
+        }
+        let external_ips =
+            external_ips.into_iter().map(|model| model.ip.ip()).collect();
+        if snat_ip.len() != 1 {
+            return Err(Error::internal_error(
+                "Expected exactly one SNAT IP address for an instance",
+            ));
         }
+        let source_nat =
+            SourceNatConfig::from(snat_ip.into_iter().next().unwrap());
+        Ok((source_nat, external_ips))
     }
 
     /// Lists disks attached to the instance.
@@ -706,8 +817,8 @@ impl super::Nexus {
         instance_lookup: &lookup::Instance<'_>,
         disk: NameOrId,
     ) -> UpdateResult<db::model::Disk> {
-        let (.., authz_project, authz_instance) =
-            instance_lookup.lookup_for(authz::Action::Modify).await?;
+        let (.., authz_project, authz_instance, db_instance) =
+            instance_lookup.fetch_for(authz::Action::Modify).await?;
         let (.., authz_project_disk, authz_disk) = self
             .disk_lookup(
                 opctx,
@@ -732,29 +843,74 @@ impl super::Nexus {
             });
         }
 
-        // TODO(https://github.com/oxidecomputer/omicron/issues/811):
-        // Disk attach is only implemented for instances that are not
-        // currently running. This operation therefore can operate exclusively
-        // on database state.
-        //
-        // To implement hot-plug support, we should do the following in a saga:
-        // - Update the state to "Attaching", rather than "Attached".
-        // - If the instance is running...
-        //   - Issue a request to "disk attach" to the associated sled agent,
-        //   using the "state generation" value from the moment we attached.
-        //   - Update the DB if the request succeeded (hopefully to "Attached").
-        // - If the instance is not running...
-        //   - Update the disk state in the DB to "Attached".
+        // If the instance is not running, the attachment only needs to be
+        // recorded in the database. The disk will be handed to the instance's
+        // sled agent the next time it starts.
+        if db_instance.runtime().state.0 != InstanceState::Running {
+            let (_instance, disk) = self
+                .db_datastore
+                .instance_attach_disk(
+                    &opctx,
+                    &authz_instance,
+                    &authz_disk,
+                    MAX_DISKS_PER_INSTANCE,
+                )
+                .await?;
+            return Ok(disk);
+        }
+
+        // Otherwise, the disk is hot-plugged: mark it as "attaching" (which
+        // fails if the instance has since stopped running), then ask the
+        // instance's sled agent to attach it. The sled agent reports the disk
+        // as "attached" once the VMM has it. Sled agents whose VMMs can't yet
+        // hot-plug disks refuse the request, and the attachment is undone.
         let (_instance, disk) = self
             .db_datastore
-            .instance_attach_disk(
+            .instance_hotplug_attach_disk(
                 &opctx,
                 &authz_instance,
                 &authz_disk,
                 MAX_DISKS_PER_INSTANCE,
             )
             .await?;
-        Ok(disk)
+        if !matches!(disk.state().into(), DiskState::Attaching(_)) {
+            // The disk was already attached by a previous request.
+            return Ok(disk);
+        }
+
+        let sa = self.instance_sled(&db_instance).await?;
+        if let Err(e) = self
+            .disk_set_runtime(
+                opctx,
+                &authz_disk,
+                &disk,
+                sa,
+                sled_agent_client::types::DiskStateRequested::Attached(
+                    authz_instance.id(),
+                ),
+            )
+            .await
+        {
+            // Give up the slot on the instance, so that the attach can be
+            // retried later. If that fails too, the error that's worth
+            // reporting is still the one from the sled agent.
+            if let Err(error) = self
+                .db_datastore
+                .disk_update_runtime(
+                    opctx,
+                    &authz_disk,
+                    &disk.runtime().detach(),
+                )
+                .await
+            {
+                warn!(self.log, "failed to undo disk hot-plug attach";
+                    "instance_id" => %authz_instance.id(),
+                    "disk_id" => %authz_disk.id(),
+                    "error" => ?error);
+            }
+            return Err(e);
+        }
+        self.db_datastore.disk_refetch(opctx, &authz_disk).await
     }
 
     /// Detach a disk from an instance.
@@ -764,8 +920,8 @@ impl super::Nexus {
         instance_lookup: &lookup::Instance<'_>,
         disk: NameOrId,
     ) -> UpdateResult<db::model::Disk> {
-        let (.., authz_project, authz_instance) =
-            instance_lookup.lookup_for(authz::Action::Modify).await?;
+        let (.., authz_project, authz_instance, db_instance) =
+            instance_lookup.fetch_for(authz::Action::Modify).await?;
         let (.., authz_disk) = self
             .disk_lookup(
                 opctx,
@@ -777,35 +933,96 @@ impl super::Nexus {
             )?
             .lookup_for(authz::Action::Modify)
             .await?;
-        // TODO(https://github.com/oxidecomputer/omicron/issues/811):
-        // Disk detach is only implemented for instances that are not
-        // currently running. This operation therefore can operate exclusively
-        // on database state.
-        //
-        // To implement hot-unplug support, we should do the following in a saga:
-        // - Update the state to "Detaching", rather than "Detached".
-        // - If the instance is running...
-        //   - Issue a request to "disk detach" to the associated sled agent,
-        //   using the "state generation" value from the moment we attached.
-        //   - Update the DB if the request succeeded (hopefully to "Detached").
-        // - If the instance is not running...
-        //   - Update the disk state in the DB to "Detached".
+        if db_instance.runtime().state.0 != InstanceState::Running {
+            let disk = self
+                .db_datastore
+                .instance_detach_disk(&opctx, &authz_instance, &authz_disk)
+                .await?;
+            return Ok(disk);
+        }
+
+        // As with hot-plug, the disk stays associated with the instance (in
+        // the "detaching" state) until its sled agent reports that the VMM
+        // has released it.
         let disk = self
             .db_datastore
-            .instance_detach_disk(&opctx, &authz_instance, &authz_disk)
+            .instance_hotplug_detach_disk(&opctx, &authz_instance, &authz_disk)
             .await?;
-        Ok(disk)
+        if !matches!(disk.state().into(), DiskState::Detaching(_)) {
+            return Ok(disk);
+        }
+
+        let sa = self.instance_sled(&db_instance).await?;
+        if let Err(e) = self
+            .disk_set_runtime(
+                opctx,
+                &authz_disk,
+                &disk,
+                sa,
+                sled_agent_client::types::DiskStateRequested::Detached,
+            )
+            .await
+        {
+            if let Err(error) = self
+                .db_datastore
+                .disk_update_runtime(
+                    opctx,
+                    &authz_disk,
+                    &disk.runtime().attach(authz_instance.id()),
+                )
+                .await
+            {
+                warn!(self.log, "failed to undo disk hot-plug detach";
+                    "instance_id" => %authz_instance.id(),
+                    "disk_id" => %authz_disk.id(),
+                    "error" => ?error);
+            }
+            return Err(e);
+        }
+        self.db_datastore.disk_refetch(opctx, &authz_disk).await
     }
 
     /// Invoked by a sled agent to publish an updated runtime state for an
     /// Instance.
     pub async fn notify_instance_updated(
         &self,
+        opctx: &OpContext,
         id: &Uuid,
         new_runtime_state: &nexus::InstanceRuntimeState,
     ) -> Result<(), Error> {
         let log = &self.log;
 
+        // Updates from the target of a migration may settle the migration
+        // one way or the other, which takes more than recording the update.
+        match LookupPath::new(opctx, &self.db_datastore)
+            .instance_id(*id)
+            .fetch_for(authz::Action::Modify)
+            .await
+        {
+            Ok((.., authz_instance, db_instance))
+                if db_instance.runtime().dst_propolis_id
+                    == Some(new_runtime_state.propolis_id) =>
+            {
+                if self
+                    .instance_migration_target_updated(
+                        opctx,
+                        &authz_instance,
+                        &db_instance,
+                        new_runtime_state,
+                    )
+                    .await?
+                {
+                    info!(log, "instance updated by migration target";
+                        "instance_id" => %id,
+                        "propolis_id" => %new_runtime_state.propolis_id,
+                        "new_state" => %new_runtime_state.run_state);
+                    return Ok(());
+                }
+            }
+            Ok(_) | Err(Error::ObjectNotFound { .. }) => {}
+            Err(error) => return Err(error),
+        }
+
         let result = self
             .db_datastore
             .instance_update_runtime(id, &(new_runtime_state.clone().into()))
@@ -9,6 +9,7 @@ use crate::context::OpContext;
 use crate::external_api::http_entrypoints::SystemMetricName;
 use crate::external_api::http_entrypoints::SystemMetricParams;
 use omicron_common::api::external::Error;
+use oximeter_db::query::Aggregation;
 use oximeter_db::Measurement;
 use std::num::NonZeroU32;
 
@@ -18,6 +19,7 @@ impl super::Nexus {
         opctx: &OpContext,
         metric_name: SystemMetricName,
         query: SystemMetricParams,
+        aggregation: Option<Aggregation>,
         limit: NonZeroU32,
     ) -> Result<dropshot::ResultsPage<Measurement>, Error> {
         let timeseries = match metric_name {
@@ -32,6 +34,7 @@ impl super::Nexus {
             &timeseries,
             &[&format!("id=={}", query.id)],
             query.pagination,
+            aggregation,
             limit,
         )
         .await
@@ -0,0 +1,358 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Live migration of Instances between sleds
+
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::lookup;
+use crate::db::model::MigrationPhase;
+use crate::db::model::MigrationState;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::InstanceState;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::UpdateResult;
+use omicron_common::api::internal::nexus;
+use sled_agent_client::types::InstanceEnsureBody;
+use sled_agent_client::types::InstanceHardware;
+use sled_agent_client::types::InstanceRuntimeStateRequested;
+use sled_agent_client::types::InstanceStateRequested;
+use uuid::Uuid;
+
+impl super::Nexus {
+    pub async fn instance_migration_list(
+        &self,
+        opctx: &OpContext,
+        instance_lookup: &lookup::Instance<'_>,
+    ) -> ListResultVec<db::model::Migration> {
+        let (.., authz_instance) =
+            instance_lookup.lookup_for(authz::Action::Read).await?;
+        self.db_datastore.instance_list_migrations(opctx, &authz_instance).await
+    }
+
+    pub async fn instance_migration_fetch(
+        &self,
+        opctx: &OpContext,
+        instance_lookup: &lookup::Instance<'_>,
+        migration_id: Uuid,
+    ) -> LookupResult<db::model::Migration> {
+        let (.., authz_instance) =
+            instance_lookup.lookup_for(authz::Action::Read).await?;
+        self.db_datastore
+            .migration_fetch(opctx, &authz_instance, migration_id)
+            .await
+    }
+
+    /// Cancels a migration that has not finished yet, leaving the Instance
+    /// running on the sled it's migrating away from.
+    pub async fn instance_migration_cancel(
+        &self,
+        opctx: &OpContext,
+        instance_lookup: &lookup::Instance<'_>,
+        migration_id: Uuid,
+    ) -> UpdateResult<db::model::Migration> {
+        let (.., authz_instance, db_instance) =
+            instance_lookup.fetch_for(authz::Action::Modify).await?;
+        let migration = self
+            .db_datastore
+            .migration_fetch(opctx, &authz_instance, migration_id)
+            .await?;
+        if migration.state.is_finished() {
+            return Err(Error::invalid_request(&format!(
+                "cannot cancel migration in state \"{}\"",
+                migration.state,
+            )));
+        }
+
+        if db_instance.runtime().migration_id == Some(migration_id) {
+            self.instance_migration_abandon(
+                opctx,
+                &authz_instance,
+                &db_instance,
+                &migration,
+                MigrationState::Cancelled,
+            )
+            .await?;
+        } else {
+            // The saga carrying out the migration hasn't involved the
+            // Instance yet.  It will notice that the migration was cancelled
+            // and unwind on its own.
+            let cancelled = self
+                .db_datastore
+                .migration_update_state(
+                    opctx,
+                    &authz_instance,
+                    migration_id,
+                    MigrationState::Cancelled,
+                )
+                .await?;
+            if !cancelled {
+                return Err(Error::invalid_request(
+                    "migration finished before it could be cancelled",
+                ));
+            }
+        }
+
+        self.db_datastore
+            .migration_fetch(opctx, &authz_instance, migration_id)
+            .await
+    }
+
+    /// Handles an update to the runtime state of an Instance reported by the
+    /// target of its migration.
+    ///
+    /// Returns true if the update has been dealt with, and false if it should
+    /// be recorded like any other update.
+    pub(crate) async fn instance_migration_target_updated(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        db_instance: &db::model::Instance,
+        new_runtime_state: &nexus::InstanceRuntimeState,
+    ) -> Result<bool, Error> {
+        let migration_id = match db_instance.runtime().migration_id {
+            Some(id) => id,
+            None => return Ok(false),
+        };
+        let migration = self
+            .db_datastore
+            .migration_fetch(opctx, authz_instance, migration_id)
+            .await?;
+
+        // If the target failed, the Instance should keep running on the
+        // source, and the failed target's state isn't interesting.
+        if new_runtime_state.run_state == InstanceState::Failed {
+            self.instance_migration_abandon(
+                opctx,
+                authz_instance,
+                db_instance,
+                &migration,
+                MigrationState::Failed,
+            )
+            .await?;
+            return Ok(true);
+        }
+
+        // Otherwise, record the update.  Once the target is running the
+        // migration is over, and the source can be cleaned up.
+        let updated = self
+            .db_datastore
+            .instance_update_runtime(
+                &db_instance.id(),
+                &new_runtime_state.clone().into(),
+            )
+            .await?;
+        if !updated {
+            return Ok(true);
+        }
+        self.instance_migration_record_phase(
+            opctx,
+            authz_instance,
+            &migration,
+            new_runtime_state,
+        )
+        .await?;
+        if new_runtime_state.run_state != InstanceState::Running
+            || new_runtime_state.migration_id.is_some()
+        {
+            return Ok(true);
+        }
+
+        // TODO-correctness If the migration was cancelled while the target
+        // finished, the source may already have been told to resume.  We
+        // should be resolving these races with the sled agents rather than
+        // after the fact.
+        let completed = self
+            .db_datastore
+            .migration_update_state(
+                opctx,
+                authz_instance,
+                migration_id,
+                MigrationState::Completed,
+            )
+            .await?;
+        if !completed {
+            warn!(self.log, "migration target finished after migration ended";
+                "instance_id" => %db_instance.id(),
+                "migration_id" => %migration_id);
+            return Ok(true);
+        }
+
+        info!(self.log, "instance migration completed";
+            "instance_id" => %db_instance.id(),
+            "migration_id" => %migration_id,
+            "sled_id" => %migration.target_sled_id);
+
+        // The source is no longer needed.  If we can't tell its sled agent
+        // that, the Instance is still fine: it's running on the target.
+        let source_runtime = nexus::InstanceRuntimeState {
+            sled_id: migration.source_sled_id,
+            propolis_id: migration.source_propolis_id,
+            ..new_runtime_state.clone()
+        };
+        if let Err(error) = self
+            .instance_migration_put(
+                opctx,
+                authz_instance,
+                migration.source_sled_id,
+                source_runtime,
+                InstanceStateRequested::Destroyed,
+            )
+            .await
+        {
+            warn!(self.log, "failed to destroy migration source";
+                "instance_id" => %db_instance.id(),
+                "migration_id" => %migration_id,
+                "error" => ?error);
+        }
+        Ok(true)
+    }
+
+    /// Records the phase a migration has reached, judging by the runtime
+    /// state of the Instance reported by the sled agent running either its
+    /// source or its target.
+    pub(crate) async fn instance_migration_record_phase(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        migration: &db::model::Migration,
+        runtime: &nexus::InstanceRuntimeState,
+    ) -> Result<(), Error> {
+        let phase = if runtime.propolis_id == migration.source_propolis_id {
+            match runtime.run_state {
+                InstanceState::Migrating
+                    if runtime.migration_id == Some(migration.id) =>
+                {
+                    MigrationPhase::SourceReady
+                }
+                _ => return Ok(()),
+            }
+        } else if runtime.propolis_id == migration.target_propolis_id {
+            match runtime.run_state {
+                InstanceState::Migrating => MigrationPhase::Transferring,
+                InstanceState::Running if runtime.migration_id.is_none() => {
+                    MigrationPhase::TargetRunning
+                }
+                _ => return Ok(()),
+            }
+        } else {
+            return Ok(());
+        };
+        self.db_datastore
+            .migration_update_phase(opctx, authz_instance, migration.id, phase)
+            .await?;
+        Ok(())
+    }
+
+    /// Gives up on a migration that has begun involving both the source and
+    /// target of the migration, leaving the Instance running on the source.
+    pub(crate) async fn instance_migration_abandon(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        db_instance: &db::model::Instance,
+        migration: &db::model::Migration,
+        state: MigrationState,
+    ) -> Result<(), Error> {
+        self.db_datastore
+            .migration_update_state(opctx, authz_instance, migration.id, state)
+            .await?;
+
+        // Tear down whatever's left of the target.  It may well be gone
+        // already, which is fine.
+        let runtime: nexus::InstanceRuntimeState =
+            db_instance.runtime().clone().into();
+        let target_runtime = nexus::InstanceRuntimeState {
+            sled_id: migration.target_sled_id,
+            propolis_id: migration.target_propolis_id,
+            ..runtime.clone()
+        };
+        if let Err(error) = self
+            .instance_migration_put(
+                opctx,
+                authz_instance,
+                migration.target_sled_id,
+                target_runtime,
+                InstanceStateRequested::Destroyed,
+            )
+            .await
+        {
+            warn!(self.log, "failed to destroy migration target";
+                "instance_id" => %db_instance.id(),
+                "migration_id" => %migration.id,
+                "error" => ?error);
+        }
+
+        // Asking the source to keep running abandons the migration there.
+        let source_runtime = nexus::InstanceRuntimeState {
+            sled_id: migration.source_sled_id,
+            propolis_id: migration.source_propolis_id,
+            ..runtime
+        };
+        let new_runtime = self
+            .instance_migration_put(
+                opctx,
+                authz_instance,
+                migration.source_sled_id,
+                source_runtime,
+                InstanceStateRequested::Running,
+            )
+            .await?;
+        self.db_datastore
+            .instance_abandon_migration(
+                &db_instance.id(),
+                &migration.id,
+                &new_runtime.into(),
+            )
+            .await?;
+        info!(self.log, "instance migration abandoned";
+            "instance_id" => %db_instance.id(),
+            "migration_id" => %migration.id,
+            "state" => %state);
+        Ok(())
+    }
+
+    /// Asks the sled agent on `sled_id` to move its copy of a migrating
+    /// Instance to the `requested` state.
+    ///
+    /// The sled agent already knows about this copy of the Instance, so it
+    /// only needs to be told which one it is by `runtime`.
+    pub(crate) async fn instance_migration_put(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        sled_id: Uuid,
+        runtime: nexus::InstanceRuntimeState,
+        requested: InstanceStateRequested,
+    ) -> Result<nexus::InstanceRuntimeState, Error> {
+        let (source_nat, external_ips) =
+            self.instance_external_ips_for_sled(opctx, authz_instance).await?;
+        let sa = self.sled_client(&sled_id).await?;
+        let new_runtime = sa
+            .instance_put(
+                &authz_instance.id(),
+                &InstanceEnsureBody {
+                    initial: InstanceHardware {
+                        runtime: runtime.into(),
+                        nics: vec![],
+                        source_nat,
+                        external_ips,
+                        firewall_rules: vec![],
+                        disks: vec![],
+                        cloud_init_bytes: None,
+                    },
+                    target: InstanceRuntimeStateRequested {
+                        run_state: requested,
+                        migration_params: None,
+                    },
+                    migrate: None,
+                },
+            )
+            .await
+            .map_err(Error::from)?;
+        Ok(new_runtime.into_inner().into())
+    }
+}
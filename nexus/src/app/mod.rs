@@ -25,20 +25,27 @@ use uuid::Uuid;
 
 // The implementation of Nexus is large, and split into a number of submodules
 // by resource.
+mod affinity_group;
+mod alert;
+mod audit_log;
 mod certificate;
 mod device_auth;
 mod disk;
+mod external_dns;
 mod external_ip;
+mod floating_ip;
 mod iam;
 mod image;
 mod instance;
 mod ip_pool;
 mod metrics;
+mod migration;
 mod network_interface;
 mod organization;
 mod oximeter;
 mod project;
 pub mod provisioning;
+mod quota;
 mod rack;
 pub mod saga;
 mod session;
@@ -66,6 +73,10 @@ pub(crate) const MAX_NICS_PER_INSTANCE: usize = 8;
 // TODO-completeness: Support multiple external IPs
 pub(crate) const MAX_EXTERNAL_IPS_PER_INSTANCE: usize = 1;
 
+/// How often Nexus checks the external DNS zone even if nothing changed
+const EXTERNAL_DNS_SYNC_PERIOD: std::time::Duration =
+    std::time::Duration::from_secs(60);
+
 pub(crate) struct ExternalServers {
     config: dropshot::ConfigDropshot,
     https_port: u16,
@@ -155,6 +166,27 @@ pub struct Nexus {
     /// Operational context used for external request authentication
     opctx_external_authn: OpContext,
 
+    /// Operational context used for moving Instances off of draining sleds
+    opctx_sled_drain: OpContext,
+
+    /// Operational context used for recording requests in the audit log
+    opctx_audit_log: OpContext,
+
+    /// Operational context used for updating the external DNS zone
+    opctx_external_dns: OpContext,
+
+    /// Operational context used for evaluating alert rules
+    opctx_alerts: OpContext,
+
+    /// URL to which alert notifications are sent, if configured
+    alerts_webhook_url: Option<String>,
+
+    /// Client to the DNS servers of the external DNS zone, if configured
+    external_dns: Option<dns_service_client::multiclient::Updater>,
+
+    /// Wakes up the task that keeps the external DNS zone up to date
+    external_dns_sync_needed: Arc<tokio::sync::Notify>,
+
     /// Max issue delay for samael crate - used only for testing
     // the samael crate has an extra check (beyond the check against the SAML
     // response NotOnOrAfter) that fails if the issue instant was too long ago.
@@ -232,6 +264,17 @@ impl Nexus {
             populate_args,
         );
 
+        let external_dns = config.pkg.external_dns.as_ref().map(|c| {
+            dns_service_client::multiclient::Updater::new_for_zone(
+                &dns_service_client::multiclient::ServerAddresses {
+                    dropshot_server_addrs: c.servers.clone(),
+                    dns_server_addrs: vec![],
+                },
+                &c.zone,
+                log.new(o!("component" => "ExternalDnsUpdater")),
+            )
+        });
+
         let nexus = Nexus {
             id: config.deployment.id,
             rack_id,
@@ -261,6 +304,37 @@ impl Nexus {
                 authn::Context::external_authn(),
                 Arc::clone(&db_datastore),
             ),
+            opctx_sled_drain: OpContext::for_background(
+                log.new(o!("component" => "SledDrain")),
+                Arc::clone(&authz),
+                authn::Context::internal_api(),
+                Arc::clone(&db_datastore),
+            ),
+            opctx_audit_log: OpContext::for_background(
+                log.new(o!("component" => "AuditLog")),
+                Arc::clone(&authz),
+                authn::Context::internal_api(),
+                Arc::clone(&db_datastore),
+            ),
+            opctx_external_dns: OpContext::for_background(
+                log.new(o!("component" => "ExternalDns")),
+                Arc::clone(&authz),
+                authn::Context::internal_api(),
+                Arc::clone(&db_datastore),
+            ),
+            opctx_alerts: OpContext::for_background(
+                log.new(o!("component" => "Alerts")),
+                Arc::clone(&authz),
+                authn::Context::internal_api(),
+                Arc::clone(&db_datastore),
+            ),
+            alerts_webhook_url: config
+                .pkg
+                .alerts
+                .as_ref()
+                .and_then(|alerts| alerts.webhook_url.clone()),
+            external_dns,
+            external_dns_sync_needed: Arc::new(tokio::sync::Notify::new()),
             samael_max_issue_delay: std::sync::Mutex::new(None),
             resolver,
         };
@@ -288,6 +362,57 @@ impl Nexus {
         );
 
         *nexus.recovery_task.lock().unwrap() = Some(recovery_task);
+
+        // Evaluate alert rules periodically for as long as Nexus is around,
+        // if alerting is configured.  The timeseries database may be found
+        // through DNS rather than configured directly, so whether there is one
+        // can't be told from its configuration.
+        if let Some(alerts) = &config.pkg.alerts {
+            let alerts_period =
+                std::time::Duration::from_secs(alerts.evaluation_period_secs);
+            let weak_nexus = Arc::downgrade(&nexus);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(alerts_period);
+                interval.set_missed_tick_behavior(
+                    tokio::time::MissedTickBehavior::Delay,
+                );
+                loop {
+                    interval.tick().await;
+                    let Some(nexus) = weak_nexus.upgrade() else {
+                        return;
+                    };
+                    if let Err(error) = nexus.alert_rules_evaluate().await {
+                        warn!(nexus.log, "failed to evaluate alert rules";
+                            "error" => ?error);
+                    }
+                }
+            });
+        }
+
+        // Keep the external DNS zone up to date, whenever something changes
+        // and periodically to repair DNS servers that missed an update.
+        if nexus.external_dns.is_some() {
+            let sync_needed = Arc::clone(&nexus.external_dns_sync_needed);
+            let weak_nexus = Arc::downgrade(&nexus);
+            tokio::spawn(async move {
+                let mut interval =
+                    tokio::time::interval(EXTERNAL_DNS_SYNC_PERIOD);
+                interval.set_missed_tick_behavior(
+                    tokio::time::MissedTickBehavior::Delay,
+                );
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => (),
+                        _ = sync_needed.notified() => (),
+                    }
+                    let Some(nexus) = weak_nexus.upgrade() else {
+                        return;
+                    };
+                    nexus.external_dns_sync().await;
+                }
+            });
+        }
+
         nexus
     }
 
@@ -77,13 +77,18 @@ impl super::Nexus {
     ) -> UpdateResult<db::model::Organization> {
         let (.., authz_organization) =
             organization_lookup.lookup_for(authz::Action::Modify).await?;
-        self.db_datastore
+        let organization = self
+            .db_datastore
             .organization_update(
                 opctx,
                 &authz_organization,
                 new_params.clone().into(),
             )
-            .await
+            .await?;
+        if new_params.identity.name.is_some() {
+            self.external_dns_sync_activate();
+        }
+        Ok(organization)
     }
 
     // Role assignments
@@ -21,6 +21,7 @@ use omicron_common::api::external::PaginationOrder;
 use omicron_common::api::internal::nexus;
 use omicron_common::backoff;
 use oximeter_client::Client as OximeterClient;
+use oximeter_db::query::Aggregation;
 use oximeter_db::query::Timestamp;
 use oximeter_db::Measurement;
 use oximeter_db::TimeseriesSchema;
@@ -231,6 +232,10 @@ impl super::Nexus {
     /// [oximeter-db::client::select_timeseries_with].
     /// * `query_params`: Pagination parameter, identifying which page of
     /// results to return.
+    /// * `aggregation`: If provided, the matching timeseries are aggregated
+    /// in the database into one, with one measurement per window (see
+    /// [oximeter_db::query::Aggregation]). Any fields to group by must have
+    /// a single value among the matching timeseries.
     /// * `limit`: The maximum number of results to return in a paginated
     /// request.
     pub async fn select_timeseries(
@@ -238,6 +243,7 @@ impl super::Nexus {
         timeseries_name: &str,
         criteria: &[&str],
         query_params: PaginationParams<ResourceMetrics, ResourceMetrics>,
+        aggregation: Option<Aggregation>,
         limit: NonZeroU32,
     ) -> Result<dropshot::ResultsPage<Measurement>, Error> {
         #[inline]
@@ -258,6 +264,15 @@ impl super::Nexus {
             // we'd duplicate the returned measurement. To return each
             // measurement exactly once, we make the start time "exclusive"
             // on all "next" pages.
+            //
+            // Aggregated measurements are stamped with the start of their
+            // window instead, and the next page starts at the following
+            // window, so that start time is inclusive.
+            dropshot::WhichPage::Next(query) if aggregation.is_some() => (
+                Timestamp::Inclusive(query.start_time),
+                Timestamp::Exclusive(query.end_time),
+                query,
+            ),
             dropshot::WhichPage::Next(query) => (
                 Timestamp::Exclusive(query.start_time),
                 Timestamp::Exclusive(query.end_time),
@@ -268,59 +283,80 @@ impl super::Nexus {
             return Ok(no_results());
         }
 
-        let timeseries_list = self
-            .timeseries_client
-            .get()
-            .await
-            .map_err(|e| {
-                Error::internal_error(&format!(
-                    "Cannot access timeseries DB: {}",
-                    e
-                ))
-            })?
-            .select_timeseries_with(
-                timeseries_name,
-                criteria,
-                Some(start_time),
-                Some(end_time),
-                Some(limit),
-            )
-            .await
-            .or_else(|err| {
-                // If the timeseries name exists in the API, but not in Clickhouse,
-                // it might just not have been populated yet.
-                match err {
-                    oximeter_db::Error::TimeseriesNotFound(_) => Ok(vec![]),
-                    _ => Err(err),
-                }
-            })
-            .map_err(map_oximeter_err)?;
+        let client = self.timeseries_client.get().await.map_err(|e| {
+            Error::internal_error(&format!(
+                "Cannot access timeseries DB: {}",
+                e
+            ))
+        })?;
+        let measurement_lists = match &aggregation {
+            None => client
+                .select_timeseries_with(
+                    timeseries_name,
+                    criteria,
+                    Some(start_time),
+                    Some(end_time),
+                    Some(limit),
+                )
+                .await
+                .map(|list| {
+                    list.into_iter().map(|t| t.measurements).collect::<Vec<_>>()
+                }),
+            Some(aggregation) => client
+                .select_timeseries_aggregated(
+                    timeseries_name,
+                    criteria,
+                    Some(start_time),
+                    Some(end_time),
+                    aggregation.clone(),
+                    Some(limit),
+                )
+                .await
+                .map(|list| {
+                    list.into_iter().map(|t| t.measurements).collect::<Vec<_>>()
+                }),
+        }
+        .or_else(|err| {
+            // If the timeseries name exists in the API, but not in Clickhouse,
+            // it might just not have been populated yet.
+            match err {
+                oximeter_db::Error::TimeseriesNotFound(_) => Ok(vec![]),
+                _ => Err(err),
+            }
+        })
+        .map_err(map_oximeter_err)?;
 
-        if timeseries_list.len() > 1 {
+        if measurement_lists.len() > 1 {
             return Err(Error::internal_error(&format!(
                 "expected 1 timeseries but got {} ({:?} {:?})",
-                timeseries_list.len(),
+                measurement_lists.len(),
                 timeseries_name,
                 criteria
             )));
         }
 
         // If we received no data, exit early.
-        let timeseries =
-            if let Some(timeseries) = timeseries_list.into_iter().next() {
-                timeseries
+        let measurements =
+            if let Some(measurements) = measurement_lists.into_iter().next() {
+                measurements
             } else {
                 return Ok(no_results());
             };
 
         Ok(dropshot::ResultsPage::new(
-            timeseries.measurements,
+            measurements,
             &query,
             |last_measurement: &Measurement, query: &ResourceMetrics| {
-                ResourceMetrics {
-                    start_time: last_measurement.timestamp(),
-                    end_time: query.end_time,
-                }
+                let start_time = match &aggregation {
+                    Some(aggregation) => {
+                        last_measurement.timestamp()
+                            + chrono::Duration::seconds(
+                                aggregation.window_secs.get().into(),
+                            )
+                    }
+                    None => last_measurement.timestamp(),
+                };
+                ResourceMetrics { start_time, end_time: query.end_time }
             },
         )
         .unwrap())
@@ -364,7 +400,7 @@ impl super::Nexus {
     }
 }
 
-fn map_oximeter_err(error: oximeter_db::Error) -> Error {
+pub(super) fn map_oximeter_err(error: oximeter_db::Error) -> Error {
     match error {
         oximeter_db::Error::DatabaseUnavailable(_) => {
             Error::ServiceUnavailable { internal_message: error.to_string() }
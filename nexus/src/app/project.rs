@@ -112,9 +112,14 @@ impl super::Nexus {
     ) -> UpdateResult<db::model::Project> {
         let (.., authz_project) =
             project_lookup.lookup_for(authz::Action::Modify).await?;
-        self.db_datastore
+        let project = self
+            .db_datastore
             .project_update(opctx, &authz_project, new_params.clone().into())
-            .await
+            .await?;
+        if new_params.identity.name.is_some() {
+            self.external_dns_sync_activate();
+        }
+        Ok(project)
     }
 
     pub async fn project_delete(
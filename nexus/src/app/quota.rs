@@ -0,0 +1,124 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Quotas on the virtual resources provisioned within silos, organizations,
+//! and projects
+
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::lookup;
+use crate::external_api::params;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::UpdateResult;
+
+impl super::Nexus {
+    // Silos
+    //
+    // Anybody who can read a Silo can see its quotas, but since they bound
+    // what the Silo may consume from the fleet, only fleet administrators may
+    // change them.
+
+    pub async fn silo_quotas_view(
+        &self,
+        opctx: &OpContext,
+        silo_lookup: &lookup::Silo<'_>,
+    ) -> LookupResult<db::model::VirtualProvisioningCollection> {
+        let (authz_silo,) = silo_lookup.lookup_for(authz::Action::Read).await?;
+        self.db_datastore
+            .virtual_provisioning_collection_get(opctx, authz_silo.id())
+            .await
+    }
+
+    pub async fn silo_quotas_update(
+        &self,
+        opctx: &OpContext,
+        silo_lookup: &lookup::Silo<'_>,
+        params: &params::ResourceQuotasUpdate,
+    ) -> UpdateResult<db::model::VirtualProvisioningCollection> {
+        let (authz_silo,) = silo_lookup.lookup_for(authz::Action::Read).await?;
+        opctx.authorize(authz::Action::Modify, &authz::FLEET).await?;
+        self.collection_quotas_update(opctx, authz_silo.id(), params).await
+    }
+
+    // Organizations
+    //
+    // Organization quotas may be changed by administrators of the Silo.
+
+    pub async fn organization_quotas_view(
+        &self,
+        opctx: &OpContext,
+        organization_lookup: &lookup::Organization<'_>,
+    ) -> LookupResult<db::model::VirtualProvisioningCollection> {
+        let (.., authz_org) =
+            organization_lookup.lookup_for(authz::Action::Read).await?;
+        self.db_datastore
+            .virtual_provisioning_collection_get(opctx, authz_org.id())
+            .await
+    }
+
+    pub async fn organization_quotas_update(
+        &self,
+        opctx: &OpContext,
+        organization_lookup: &lookup::Organization<'_>,
+        params: &params::ResourceQuotasUpdate,
+    ) -> UpdateResult<db::model::VirtualProvisioningCollection> {
+        let (authz_silo, authz_org) =
+            organization_lookup.lookup_for(authz::Action::Read).await?;
+        opctx.authorize(authz::Action::Modify, &authz_silo).await?;
+        self.collection_quotas_update(opctx, authz_org.id(), params).await
+    }
+
+    // Projects
+    //
+    // Project quotas may be changed by administrators of the Organization.
+
+    pub async fn project_quotas_view(
+        &self,
+        opctx: &OpContext,
+        project_lookup: &lookup::Project<'_>,
+    ) -> LookupResult<db::model::VirtualProvisioningCollection> {
+        let (.., authz_project) =
+            project_lookup.lookup_for(authz::Action::Read).await?;
+        self.db_datastore
+            .virtual_provisioning_collection_get(opctx, authz_project.id())
+            .await
+    }
+
+    pub async fn project_quotas_update(
+        &self,
+        opctx: &OpContext,
+        project_lookup: &lookup::Project<'_>,
+        params: &params::ResourceQuotasUpdate,
+    ) -> UpdateResult<db::model::VirtualProvisioningCollection> {
+        let (.., authz_org, authz_project) =
+            project_lookup.lookup_for(authz::Action::Read).await?;
+        opctx.authorize(authz::Action::Modify, &authz_org).await?;
+        self.collection_quotas_update(opctx, authz_project.id(), params).await
+    }
+
+    async fn collection_quotas_update(
+        &self,
+        opctx: &OpContext,
+        collection_id: uuid::Uuid,
+        params: &params::ResourceQuotasUpdate,
+    ) -> UpdateResult<db::model::VirtualProvisioningCollection> {
+        if let Some(cpus) = params.cpus {
+            if cpus < 0 {
+                return Err(Error::InvalidValue {
+                    label: String::from("cpus"),
+                    message: String::from("quota must not be negative"),
+                });
+            }
+        }
+        self.db_datastore
+            .virtual_provisioning_collection_update_quotas(
+                opctx,
+                collection_id,
+                params.clone().into(),
+            )
+            .await
+    }
+}
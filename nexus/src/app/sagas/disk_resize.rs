@@ -0,0 +1,684 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Grow a detached disk.
+//!
+//! Crucible regions cannot be resized in place, so a disk is grown by
+//! allocating an additional set of regions large enough to hold the new
+//! space, and appending them to the disk's volume as a new sub-volume.
+
+use super::{
+    common_storage::{
+        delete_crucible_regions, ensure_all_datasets_and_regions,
+    },
+    ActionRegistry, NexusActionContext, NexusSaga, SagaInitError,
+};
+use crate::app::sagas::declare_saga_actions;
+use crate::context::OpContext;
+use crate::db::identity::{Asset, Resource};
+use crate::{authn, authz, db};
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::Error;
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use serde::Deserialize;
+use serde::Serialize;
+use sled_agent_client::types::{CrucibleOpts, VolumeConstructionRequest};
+use steno::ActionError;
+use uuid::Uuid;
+
+// disk resize saga: input parameters
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Params {
+    pub serialized_authn: authn::saga::Serialized,
+    pub project_id: Uuid,
+    pub authz_disk: authz::Disk,
+    /// The disk record, as it was when the resize was requested
+    pub disk: db::model::Disk,
+    pub new_size: ByteCount,
+}
+
+// disk resize saga: actions
+
+declare_saga_actions! {
+    disk_resize;
+    START_MAINTENANCE -> "maintenance_runtime" {
+        + sdr_start_maintenance
+        - sdr_start_maintenance_undo
+    }
+    GET_EXISTING_REGIONS -> "existing_region_ids" {
+        + sdr_get_existing_regions
+    }
+    GET_VOLUME_DATA -> "old_volume_data" {
+        + sdr_get_volume_data
+    }
+    REGIONS_ALLOC -> "datasets_and_regions" {
+        + sdr_alloc_regions
+        - sdr_alloc_regions_undo
+    }
+    SPACE_ACCOUNT -> "no_result1" {
+        + sdr_account_space
+        - sdr_account_space_undo
+    }
+    REGIONS_ENSURE -> "new_volume_data" {
+        + sdr_regions_ensure
+        - sdr_regions_ensure_undo
+    }
+    UPDATE_VOLUME_RECORD -> "no_result2" {
+        + sdr_update_volume_record
+        - sdr_update_volume_record_undo
+    }
+    FINALIZE_DISK_RECORD -> "resized_disk" {
+        + sdr_finalize_disk_record
+    }
+}
+
+// disk resize saga: definition
+
+#[derive(Debug)]
+pub struct SagaDiskResize;
+impl NexusSaga for SagaDiskResize {
+    const NAME: &'static str = "disk-resize";
+    type Params = Params;
+
+    fn register_actions(registry: &mut ActionRegistry) {
+        disk_resize_register_actions(registry);
+    }
+
+    fn make_saga_dag(
+        _params: &Self::Params,
+        mut builder: steno::DagBuilder,
+    ) -> Result<steno::Dag, SagaInitError> {
+        builder.append(start_maintenance_action());
+        builder.append(get_existing_regions_action());
+        builder.append(get_volume_data_action());
+        builder.append(regions_alloc_action());
+        builder.append(space_account_action());
+        builder.append(regions_ensure_action());
+        builder.append(update_volume_record_action());
+        builder.append(finalize_disk_record_action());
+
+        Ok(builder.build()?)
+    }
+}
+
+// disk resize saga: action implementations
+
+/// Moves the disk from "detached" to "maintenance", so that it cannot be
+/// attached to an instance while its volume is being changed.
+async fn sdr_start_maintenance(
+    sagactx: NexusActionContext,
+) -> Result<db::model::DiskRuntimeState, ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+
+    let maintenance_runtime = params.disk.runtime().maintenance();
+    let updated = osagactx
+        .datastore()
+        .disk_update_runtime(&opctx, &params.authz_disk, &maintenance_runtime)
+        .await
+        .map_err(ActionError::action_failed)?;
+
+    // The update only fails to apply if the disk's runtime state changed
+    // after we looked at it, unless we already applied it ourselves.
+    if !updated {
+        let disk = osagactx
+            .datastore()
+            .disk_refetch(&opctx, &params.authz_disk)
+            .await
+            .map_err(ActionError::action_failed)?;
+        if disk.runtime().gen != maintenance_runtime.gen
+            || disk.runtime().disk_state != maintenance_runtime.disk_state
+        {
+            return Err(ActionError::action_failed(Error::conflict(
+                "disk was modified while being resized",
+            )));
+        }
+    }
+
+    Ok(maintenance_runtime)
+}
+
+async fn sdr_start_maintenance_undo(
+    sagactx: NexusActionContext,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+
+    let maintenance_runtime =
+        sagactx.lookup::<db::model::DiskRuntimeState>("maintenance_runtime")?;
+    osagactx
+        .datastore()
+        .disk_update_runtime(
+            &opctx,
+            &params.authz_disk,
+            &maintenance_runtime.detach(),
+        )
+        .await?;
+    Ok(())
+}
+
+async fn sdr_get_existing_regions(
+    sagactx: NexusActionContext,
+) -> Result<Vec<Uuid>, ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+
+    let region_ids = osagactx
+        .datastore()
+        .get_allocated_regions(params.disk.volume_id)
+        .await
+        .map_err(ActionError::action_failed)?
+        .into_iter()
+        .map(|(_, region)| region.id())
+        .collect();
+    Ok(region_ids)
+}
+
+async fn sdr_get_volume_data(
+    sagactx: NexusActionContext,
+) -> Result<String, ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+
+    let volume = osagactx
+        .datastore()
+        .volume_get(params.disk.volume_id)
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(volume.data().to_string())
+}
+
+async fn sdr_alloc_regions(
+    sagactx: NexusActionContext,
+) -> Result<Vec<(db::model::Dataset, db::model::Region)>, ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let existing_region_ids =
+        sagactx.lookup::<Vec<Uuid>>("existing_region_ids")?;
+
+    let size_delta = size_delta(&params)?;
+    let datasets_and_regions = osagactx
+        .datastore()
+        .region_allocate_additional(
+            params.disk.volume_id,
+            &params.disk.block_size,
+            size_delta,
+            &existing_region_ids,
+        )
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(datasets_and_regions)
+}
+
+async fn sdr_alloc_regions_undo(
+    sagactx: NexusActionContext,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+
+    let region_ids = sagactx
+        .lookup::<Vec<(db::model::Dataset, db::model::Region)>>(
+            "datasets_and_regions",
+        )?
+        .into_iter()
+        .map(|(_, region)| region.id())
+        .collect::<Vec<Uuid>>();
+
+    osagactx.datastore().regions_hard_delete(region_ids).await?;
+    Ok(())
+}
+
+async fn sdr_account_space(
+    sagactx: NexusActionContext,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+
+    osagactx
+        .datastore()
+        .virtual_provisioning_collection_resize_disk(
+            &opctx,
+            params.disk.id(),
+            params.project_id,
+            params.disk.size,
+            params.new_size.into(),
+        )
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(())
+}
+
+async fn sdr_account_space_undo(
+    sagactx: NexusActionContext,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+
+    osagactx
+        .datastore()
+        .virtual_provisioning_collection_resize_disk(
+            &opctx,
+            params.disk.id(),
+            params.project_id,
+            params.new_size.into(),
+            params.disk.size,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Call out to Crucible agent to create the new regions, and compute the
+/// disk's new volume construction request.
+async fn sdr_regions_ensure(
+    sagactx: NexusActionContext,
+) -> Result<String, ActionError> {
+    let log = sagactx.user_data().log();
+    let params = sagactx.saga_params::<Params>()?;
+    let old_volume_data = sagactx.lookup::<String>("old_volume_data")?;
+
+    let datasets_and_regions = ensure_all_datasets_and_regions(
+        &log,
+        sagactx.lookup::<Vec<(db::model::Dataset, db::model::Region)>>(
+            "datasets_and_regions",
+        )?,
+    )
+    .await?;
+
+    let block_size = datasets_and_regions[0].1.block_size;
+    let blocks_per_extent = datasets_and_regions[0].1.extent_size;
+    let extent_count = datasets_and_regions[0].1.extent_count;
+
+    let mut volume_construction_request: VolumeConstructionRequest =
+        serde_json::from_str(&old_volume_data).map_err(|e| {
+            ActionError::action_failed(Error::internal_error(&format!(
+                "failed to deserialize volume data: {}",
+                e,
+            )))
+        })?;
+
+    let sub_volumes = match &mut volume_construction_request {
+        VolumeConstructionRequest::Volume { sub_volumes, .. } => sub_volumes,
+        _ => {
+            return Err(ActionError::action_failed(Error::internal_error(
+                &format!(
+                    "disk {} has an unexpected volume construction request",
+                    params.disk.id(),
+                ),
+            )));
+        }
+    };
+
+    // The new space is appended to the end of the disk as another sub-volume,
+    // backed by its own set of regions.
+    let mut rng = StdRng::from_entropy();
+    sub_volumes.push(VolumeConstructionRequest::Region {
+        block_size,
+        blocks_per_extent,
+        extent_count: extent_count.try_into().unwrap(),
+        gen: 1,
+        opts: CrucibleOpts {
+            id: Uuid::new_v4(),
+            target: datasets_and_regions
+                .iter()
+                .map(|(dataset, region)| {
+                    dataset.address_with_port(region.port_number).to_string()
+                })
+                .collect(),
+
+            lossy: false,
+            flush_timeout: None,
+
+            // all downstairs will expect encrypted blocks
+            key: Some(base64::Engine::encode(
+                &base64::engine::general_purpose::STANDARD,
+                {
+                    // TODO the current encryption key
+                    // requirement is 32 bytes, what if that
+                    // changes?
+                    let mut random_bytes: [u8; 32] = [0; 32];
+                    rng.fill_bytes(&mut random_bytes);
+                    random_bytes
+                },
+            )),
+
+            // TODO TLS, which requires sending X509 stuff during
+            // downstairs region allocation too.
+            cert_pem: None,
+            key_pem: None,
+            root_cert_pem: None,
+
+            control: None,
+
+            read_only: false,
+        },
+    });
+
+    let volume_data = serde_json::to_string(&volume_construction_request)
+        .map_err(|e| {
+            ActionError::action_failed(Error::internal_error(&e.to_string()))
+        })?;
+
+    Ok(volume_data)
+}
+
+async fn sdr_regions_ensure_undo(
+    sagactx: NexusActionContext,
+) -> Result<(), anyhow::Error> {
+    let log = sagactx.user_data().log();
+    warn!(log, "sdr_regions_ensure_undo: Deleting crucible regions");
+    delete_crucible_regions(
+        sagactx.lookup::<Vec<(db::model::Dataset, db::model::Region)>>(
+            "datasets_and_regions",
+        )?,
+    )
+    .await?;
+    info!(log, "sdr_regions_ensure_undo: Deleted crucible regions");
+    Ok(())
+}
+
+async fn sdr_update_volume_record(
+    sagactx: NexusActionContext,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let new_volume_data = sagactx.lookup::<String>("new_volume_data")?;
+
+    osagactx
+        .datastore()
+        .volume_update_data(params.disk.volume_id, new_volume_data)
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(())
+}
+
+async fn sdr_update_volume_record_undo(
+    sagactx: NexusActionContext,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let old_volume_data = sagactx.lookup::<String>("old_volume_data")?;
+
+    osagactx
+        .datastore()
+        .volume_update_data(params.disk.volume_id, old_volume_data)
+        .await?;
+    Ok(())
+}
+
+async fn sdr_finalize_disk_record(
+    sagactx: NexusActionContext,
+) -> Result<db::model::Disk, ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+
+    let maintenance_runtime =
+        sagactx.lookup::<db::model::DiskRuntimeState>("maintenance_runtime")?;
+    let disk = osagactx
+        .datastore()
+        .disk_update_size(
+            &opctx,
+            &params.authz_disk,
+            params.new_size.into(),
+            &maintenance_runtime.detach(),
+        )
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(disk)
+}
+
+// helper functions
+
+/// Returns the amount of space being added to the disk
+fn size_delta(params: &Params) -> Result<ByteCount, ActionError> {
+    let old_size = params.disk.size.to_bytes();
+    let new_size = params.new_size.to_bytes();
+    new_size
+        .checked_sub(old_size)
+        .and_then(|delta| ByteCount::try_from(delta).ok())
+        .ok_or_else(|| {
+            ActionError::action_failed(Error::invalid_request(&format!(
+                "new disk size {} is smaller than the current size {}",
+                new_size, old_size,
+            )))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        app::saga::create_saga_dag, app::sagas::disk_resize::Params,
+        app::sagas::disk_resize::SagaDiskResize, authn::saga::Serialized,
+        authz, context::OpContext, db::identity::Resource,
+        db::lookup::LookupPath,
+    };
+    use dropshot::test_util::ClientTestContext;
+    use nexus_test_utils::resource_helpers::create_ip_pool;
+    use nexus_test_utils::resource_helpers::create_organization;
+    use nexus_test_utils::resource_helpers::create_project;
+    use nexus_test_utils::resource_helpers::DiskTest;
+    use nexus_test_utils_macros::nexus_test;
+    use nexus_types::external_api::params;
+    use omicron_common::api::external::ByteCount;
+    use omicron_common::api::external::DiskState;
+    use omicron_common::api::external::Name;
+    use std::num::NonZeroU32;
+    use uuid::Uuid;
+
+    type ControlPlaneTestContext =
+        nexus_test_utils::ControlPlaneTestContext<crate::Server>;
+
+    const ORG_NAME: &str = "test-org";
+    const PROJECT_NAME: &str = "springfield-squidport";
+
+    async fn create_org_and_project(client: &ClientTestContext) -> Uuid {
+        create_ip_pool(&client, "p0", None).await;
+        create_organization(&client, ORG_NAME).await;
+        let project = create_project(client, ORG_NAME, PROJECT_NAME).await;
+        project.identity.id
+    }
+
+    fn test_opctx(cptestctx: &ControlPlaneTestContext) -> OpContext {
+        OpContext::for_tests(
+            cptestctx.logctx.log.new(o!()),
+            cptestctx.server.apictx.nexus.datastore().clone(),
+        )
+    }
+
+    async fn create_disk(cptestctx: &ControlPlaneTestContext) -> Uuid {
+        let nexus = &cptestctx.server.apictx.nexus;
+        let opctx = test_opctx(&cptestctx);
+
+        let project_selector = params::ProjectSelector::new(
+            Some(Name::try_from(ORG_NAME.to_string()).unwrap().into()),
+            Name::try_from(PROJECT_NAME.to_string()).unwrap().into(),
+        );
+        let project_lookup =
+            nexus.project_lookup(&opctx, &project_selector).unwrap();
+
+        nexus
+            .project_create_disk(
+                &opctx,
+                &project_lookup,
+                &crate::app::sagas::disk_create::test::new_disk_create_params(),
+            )
+            .await
+            .expect("Failed to create disk")
+            .id()
+    }
+
+    async fn new_test_params(
+        cptestctx: &ControlPlaneTestContext,
+        project_id: Uuid,
+        disk_id: Uuid,
+    ) -> Params {
+        let opctx = test_opctx(&cptestctx);
+        let datastore = cptestctx.server.apictx.nexus.datastore();
+        let (.., authz_disk, disk) = LookupPath::new(&opctx, datastore)
+            .disk_id(disk_id)
+            .fetch_for(authz::Action::Modify)
+            .await
+            .unwrap();
+        Params {
+            serialized_authn: Serialized::for_opctx(&opctx),
+            project_id,
+            authz_disk,
+            disk,
+            new_size: ByteCount::from_gibibytes_u32(2),
+        }
+    }
+
+    async fn fetch_disk(
+        cptestctx: &ControlPlaneTestContext,
+        disk_id: Uuid,
+    ) -> crate::db::model::Disk {
+        let opctx = test_opctx(&cptestctx);
+        let datastore = cptestctx.server.apictx.nexus.datastore();
+        let (.., disk) = LookupPath::new(&opctx, datastore)
+            .disk_id(disk_id)
+            .fetch()
+            .await
+            .unwrap();
+        disk
+    }
+
+    async fn regions_for_disk(
+        cptestctx: &ControlPlaneTestContext,
+        disk: &crate::db::model::Disk,
+    ) -> usize {
+        let datastore = cptestctx.server.apictx.nexus.datastore();
+        datastore.get_allocated_regions(disk.volume_id).await.unwrap().len()
+    }
+
+    #[nexus_test(server = crate::Server)]
+    async fn test_saga_basic_usage_succeeds(
+        cptestctx: &ControlPlaneTestContext,
+    ) {
+        DiskTest::new(cptestctx).await;
+
+        let client = &cptestctx.external_client;
+        let nexus = &cptestctx.server.apictx.nexus;
+        let project_id = create_org_and_project(&client).await;
+        let disk_id = create_disk(&cptestctx).await;
+        let old_disk = fetch_disk(&cptestctx, disk_id).await;
+        let old_region_count = regions_for_disk(&cptestctx, &old_disk).await;
+
+        let params = new_test_params(&cptestctx, project_id, disk_id).await;
+        let dag = create_saga_dag::<SagaDiskResize>(params).unwrap();
+        let runnable_saga = nexus.create_runnable_saga(dag).await.unwrap();
+        nexus.run_saga(runnable_saga).await.unwrap();
+
+        let disk = fetch_disk(&cptestctx, disk_id).await;
+        assert_eq!(disk.size.to_bytes(), 2 * 1024 * 1024 * 1024);
+        assert_eq!(*disk.state().state(), DiskState::Detached);
+        assert_eq!(
+            regions_for_disk(&cptestctx, &disk).await,
+            2 * old_region_count
+        );
+    }
+
+    #[nexus_test(server = crate::Server)]
+    async fn test_action_failure_can_unwind(
+        cptestctx: &ControlPlaneTestContext,
+    ) {
+        DiskTest::new(cptestctx).await;
+        let log = &cptestctx.logctx.log;
+
+        let client = &cptestctx.external_client;
+        let nexus = &cptestctx.server.apictx.nexus;
+        let project_id = create_org_and_project(&client).await;
+        let disk_id = create_disk(&cptestctx).await;
+        let old_disk = fetch_disk(&cptestctx, disk_id).await;
+        let old_region_count = regions_for_disk(&cptestctx, &old_disk).await;
+
+        let params = new_test_params(&cptestctx, project_id, disk_id).await;
+        let dag = create_saga_dag::<SagaDiskResize>(params).unwrap();
+
+        for node in dag.get_nodes() {
+            info!(
+                log,
+                "Creating new saga which will fail at index {:?}", node.index();
+                "node_name" => node.name().as_ref(),
+                "label" => node.label(),
+            );
+            let runnable_saga =
+                nexus.create_runnable_saga(dag.clone()).await.unwrap();
+
+            nexus
+                .sec()
+                .saga_inject_error(runnable_saga.id(), node.index())
+                .await
+                .unwrap();
+            nexus
+                .run_saga(runnable_saga)
+                .await
+                .expect_err("Saga should have failed");
+
+            // The disk should be exactly as it was before the resize.
+            let disk = fetch_disk(&cptestctx, disk_id).await;
+            assert_eq!(disk.size, old_disk.size);
+            assert_eq!(*disk.state().state(), DiskState::Detached);
+            assert_eq!(
+                regions_for_disk(&cptestctx, &disk).await,
+                old_region_count
+            );
+            let volume =
+                nexus.datastore().volume_get(disk.volume_id).await.unwrap();
+            let old_volume =
+                nexus.datastore().volume_get(old_disk.volume_id).await.unwrap();
+            assert_eq!(volume.data(), old_volume.data());
+        }
+    }
+
+    #[nexus_test(server = crate::Server)]
+    async fn test_actions_succeed_idempotently(
+        cptestctx: &ControlPlaneTestContext,
+    ) {
+        DiskTest::new(cptestctx).await;
+
+        let client = &cptestctx.external_client;
+        let nexus = &cptestctx.server.apictx.nexus;
+        let project_id = create_org_and_project(&client).await;
+        let disk_id = create_disk(&cptestctx).await;
+        let old_disk = fetch_disk(&cptestctx, disk_id).await;
+        let old_region_count = regions_for_disk(&cptestctx, &old_disk).await;
+
+        let params = new_test_params(&cptestctx, project_id, disk_id).await;
+        let dag = create_saga_dag::<SagaDiskResize>(params).unwrap();
+        let runnable_saga =
+            nexus.create_runnable_saga(dag.clone()).await.unwrap();
+
+        // Cause all actions to run twice. The saga should succeed regardless!
+        for node in dag.get_nodes() {
+            nexus
+                .sec()
+                .saga_inject_repeat(
+                    runnable_saga.id(),
+                    node.index(),
+                    steno::RepeatInjected {
+                        action: NonZeroU32::new(2).unwrap(),
+                        undo: NonZeroU32::new(1).unwrap(),
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        nexus
+            .run_saga(runnable_saga)
+            .await
+            .expect("Saga should have succeeded");
+
+        let disk = fetch_disk(&cptestctx, disk_id).await;
+        assert_eq!(disk.size.to_bytes(), 2 * 1024 * 1024 * 1024);
+        assert_eq!(
+            regions_for_disk(&cptestctx, &disk).await,
+            2 * old_region_count
+        );
+    }
+}
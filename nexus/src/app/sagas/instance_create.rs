@@ -87,6 +87,10 @@ declare_saga_actions! {
         + sic_create_instance_record
         - sic_delete_instance_record
     }
+    JOIN_AFFINITY_GROUPS -> "affinity_groups_joined" {
+        + sic_join_affinity_groups
+        - sic_join_affinity_groups_undo
+    }
     CREATE_NETWORK_INTERFACE -> "output" {
         + sic_create_network_interface
         - sic_create_network_interface_undo
@@ -143,6 +147,7 @@ impl NexusSaga for SagaInstanceCreate {
         builder.append(resources_account_action());
         builder.append(alloc_propolis_ip_action());
         builder.append(create_instance_record_action());
+        builder.append(join_affinity_groups_action());
 
         // Helper function for appending subsagas to our parent saga.
         fn subsaga_append<S: Serialize>(
@@ -324,17 +329,28 @@ async fn sic_alloc_server(
     //   state, etc, we'd currently provision it here. I don't think this is a
     //   trivial fix, but it's work we'll need to account for eventually.
     //
-    // - This is selecting a random sled from all sleds in the cluster. For
+    // - This is selecting a random sled from all sleds in the cluster, other
+    //   than as constrained by the instance's affinity groups. For
     //   multi-rack, this is going to fling the sled to an arbitrary system.
     //   Maybe that's okay, but worth knowing about explicitly.
     //
-    // - This doesn't take into account anti-affinity - users will want to
-    //   schedule instances that belong to a cluster on different failure
-    //   domains. See https://github.com/oxidecomputer/omicron/issues/1705.
+    // - Affinity groups only consider the sled as a failure domain.
 
-    osagactx
-        .nexus()
-        .random_sled_id()
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+    let instance_id = sagactx.lookup::<Uuid>("instance_id")?;
+    let nexus = osagactx.nexus();
+    let groups = nexus
+        .affinity_groups_for_instance_create(
+            &opctx,
+            params.project_id,
+            &params.create_params.affinity_groups,
+        )
+        .await
+        .map_err(ActionError::action_failed)?;
+
+    nexus
+        .affinity_sled_select(&opctx, instance_id, &groups, &[])
         .await
         .map_err(ActionError::action_failed)?
         .ok_or_else(|| Error::ServiceUnavailable {
@@ -638,6 +654,8 @@ async fn sic_allocate_instance_snat_ip_undo(
 
 /// Create an external IPs for the instance, using the request parameters at
 /// index `ip_index`, and return its ID if one is created (or None).
+///
+/// Floating IPs already exist, and are attached to the instance instead.
 async fn sic_allocate_instance_external_ip(
     sagactx: NexusActionContext,
 ) -> Result<(), ActionError> {
@@ -658,16 +676,33 @@ async fn sic_allocate_instance_external_ip(
     let instance_id = repeat_saga_params.instance_id;
     let ip_id = repeat_saga_params.new_id;
 
-    // Collect the possible pool name for this IP address
-    let pool_name = match ip_params {
+    match ip_params {
         params::ExternalIpCreate::Ephemeral { ref pool_name } => {
-            pool_name.as_ref().map(|name| db::model::Name(name.clone()))
+            let pool_name =
+                pool_name.as_ref().map(|name| db::model::Name(name.clone()));
+            datastore
+                .allocate_instance_ephemeral_ip(
+                    &opctx,
+                    ip_id,
+                    instance_id,
+                    pool_name,
+                )
+                .await
+                .map_err(ActionError::action_failed)?;
         }
-    };
-    datastore
-        .allocate_instance_ephemeral_ip(&opctx, ip_id, instance_id, pool_name)
-        .await
-        .map_err(ActionError::action_failed)?;
+        params::ExternalIpCreate::Floating { ref floating_ip_name } => {
+            let (.., authz_fip) = LookupPath::new(&opctx, &datastore)
+                .project_id(saga_params.project_id)
+                .floating_ip_name(&db::model::Name(floating_ip_name.clone()))
+                .lookup_for(authz::Action::Modify)
+                .await
+                .map_err(ActionError::action_failed)?;
+            datastore
+                .floating_ip_attach(&opctx, &authz_fip, instance_id)
+                .await
+                .map_err(ActionError::action_failed)?;
+        }
+    }
     Ok(())
 }
 
@@ -679,14 +714,29 @@ async fn sic_allocate_instance_external_ip_undo(
     let repeat_saga_params = sagactx.saga_params::<NetParams>()?;
     let saga_params = repeat_saga_params.saga_params;
     let ip_index = repeat_saga_params.which;
-    if ip_index >= saga_params.create_params.external_ips.len() {
-        return Ok(());
-    }
+    let ip_params = match saga_params.create_params.external_ips.get(ip_index) {
+        None => return Ok(()),
+        Some(ip_params) => ip_params,
+    };
 
     let opctx =
         OpContext::for_saga_action(&sagactx, &saga_params.serialized_authn);
-    let ip_id = repeat_saga_params.new_id;
-    datastore.deallocate_external_ip(&opctx, ip_id).await?;
+    match ip_params {
+        params::ExternalIpCreate::Ephemeral { .. } => {
+            let ip_id = repeat_saga_params.new_id;
+            datastore.deallocate_external_ip(&opctx, ip_id).await?;
+        }
+        params::ExternalIpCreate::Floating { ref floating_ip_name } => {
+            let (.., authz_fip, db_fip) = LookupPath::new(&opctx, &datastore)
+                .project_id(saga_params.project_id)
+                .floating_ip_name(&db::model::Name(floating_ip_name.clone()))
+                .fetch_for(authz::Action::Modify)
+                .await?;
+            if db_fip.instance_id == Some(repeat_saga_params.instance_id) {
+                datastore.floating_ip_detach(&opctx, &authz_fip).await?;
+            }
+        }
+    }
     Ok(())
 }
 
@@ -880,6 +930,52 @@ async fn sic_create_instance_record(
     Ok(instance.name().clone().into())
 }
 
+async fn sic_join_affinity_groups(
+    sagactx: NexusActionContext,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+    let instance_id = sagactx.lookup::<Uuid>("instance_id")?;
+
+    let groups = osagactx
+        .nexus()
+        .affinity_groups_for_instance_create(
+            &opctx,
+            params.project_id,
+            &params.create_params.affinity_groups,
+        )
+        .await
+        .map_err(ActionError::action_failed)?;
+    for (authz_group, _) in groups {
+        osagactx
+            .datastore()
+            .affinity_group_member_add(&opctx, &authz_group, instance_id)
+            .await
+            .map_err(ActionError::action_failed)?;
+    }
+    Ok(())
+}
+
+async fn sic_join_affinity_groups_undo(
+    sagactx: NexusActionContext,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+    let instance_id = sagactx.lookup::<Uuid>("instance_id")?;
+
+    let (.., authz_instance) = LookupPath::new(&opctx, &osagactx.datastore())
+        .instance_id(instance_id)
+        .lookup_for(authz::Action::Modify)
+        .await?;
+    osagactx
+        .datastore()
+        .instance_leave_affinity_groups(&opctx, &authz_instance)
+        .await?;
+    Ok(())
+}
+
 async fn sic_delete_instance_record(
     sagactx: NexusActionContext,
 ) -> Result<(), anyhow::Error> {
@@ -1078,6 +1174,7 @@ pub mod test {
                         name: DISK_NAME.parse().unwrap(),
                     },
                 )],
+                affinity_groups: Vec::new(),
                 start: false,
             },
         }
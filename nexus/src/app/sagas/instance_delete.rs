@@ -40,6 +40,9 @@ declare_saga_actions! {
     RESOURCES_ACCOUNT -> "no_result4" {
         + sid_account_resources
     }
+    LEAVE_AFFINITY_GROUPS -> "no_result5" {
+        + sid_leave_affinity_groups
+    }
 }
 
 // instance delete saga: definition
@@ -62,6 +65,7 @@ impl NexusSaga for SagaInstanceDelete {
         builder.append(delete_network_interfaces_action());
         builder.append(deallocate_external_ip_action());
         builder.append(resources_account_action());
+        builder.append(leave_affinity_groups_action());
         Ok(builder.build()?)
     }
 }
@@ -120,6 +124,26 @@ async fn sid_deallocate_external_ip(
         )
         .await
         .map_err(ActionError::action_failed)?;
+    // Floating IPs outlive the instance, and are returned to the project.
+    osagactx
+        .datastore()
+        .detach_floating_ips_by_instance_id(&opctx, params.authz_instance.id())
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(())
+}
+
+async fn sid_leave_affinity_groups(
+    sagactx: NexusActionContext,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+    osagactx
+        .datastore()
+        .instance_leave_affinity_groups(&opctx, &params.authz_instance)
+        .await
+        .map_err(ActionError::action_failed)?;
     Ok(())
 }
 
@@ -223,6 +247,7 @@ mod test {
             disks: vec![params::InstanceDiskAttachment::Attach(
                 params::InstanceDiskAttach { name: DISK_NAME.parse().unwrap() },
             )],
+            affinity_groups: Vec::new(),
             start: false,
         }
     }
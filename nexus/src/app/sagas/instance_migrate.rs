@@ -6,22 +6,25 @@ use super::instance_create::allocate_sled_ipv6;
 use super::{NexusActionContext, NexusSaga, ACTION_GENERATE_ID};
 use crate::app::sagas::declare_saga_actions;
 use crate::authn;
+use crate::authz;
 use crate::context::OpContext;
+use crate::db;
 use crate::db::identity::Resource;
-use crate::db::model::IpKind;
+use crate::db::lookup::LookupPath;
+use crate::db::model::MigrationState;
 use crate::external_api::params;
+use omicron_common::address::PROPOLIS_PORT;
 use omicron_common::api::external::Error;
 use omicron_common::api::internal::nexus::InstanceRuntimeState;
 use serde::Deserialize;
 use serde::Serialize;
 use sled_agent_client::types::InstanceEnsureBody;
-use sled_agent_client::types::InstanceHardware;
 use sled_agent_client::types::InstanceMigrateParams;
 use sled_agent_client::types::InstanceRuntimeStateMigrateParams;
 use sled_agent_client::types::InstanceRuntimeStateRequested;
 use sled_agent_client::types::InstanceStateRequested;
-use sled_agent_client::types::SourceNatConfig;
 use std::net::Ipv6Addr;
+use std::net::SocketAddr;
 use steno::ActionError;
 use steno::Node;
 use uuid::Uuid;
@@ -39,20 +42,26 @@ pub struct Params {
 
 declare_saga_actions! {
     instance_migrate;
+    SELECT_DST_SLED -> "dst_sled_id" {
+        + sim_select_dst_sled
+    }
     ALLOCATE_PROPOLIS_IP -> "dst_propolis_ip" {
         + sim_allocate_propolis_ip
     }
+    CREATE_MIGRATION_RECORD -> "migration" {
+        + sim_create_migration_record
+        - sim_create_migration_record_undo
+    }
     MIGRATE_PREP -> "migrate_instance" {
         + sim_migrate_prep
+        - sim_migrate_prep_undo
     }
     INSTANCE_MIGRATE -> "instance_migrate" {
-        // TODO robustness: This needs an undo action
         + sim_instance_migrate
+        - sim_instance_migrate_undo
     }
-    CLEANUP_SOURCE -> "cleanup_source" {
-        // TODO robustness: This needs an undo action. Is it even possible
-        // to undo at this point?
-        + sim_cleanup_source
+    MIGRATION_START -> "migration_start" {
+        + sim_migration_start
     }
 }
 
@@ -84,15 +93,154 @@ impl NexusSaga for SagaInstanceMigrate {
             ACTION_GENERATE_ID.as_ref(),
         ));
 
+        builder.append(select_dst_sled_action());
         builder.append(allocate_propolis_ip_action());
+        builder.append(create_migration_record_action());
         builder.append(migrate_prep_action());
         builder.append(instance_migrate_action());
-        builder.append(cleanup_source_action());
+        builder.append(migration_start_action());
 
         Ok(builder.build()?)
     }
 }
 
+// Pick the sled to migrate the instance to, if the caller didn't.
+async fn sim_select_dst_sled(
+    sagactx: NexusActionContext,
+) -> Result<Uuid, ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+
+    let (.., authz_instance, db_instance) =
+        LookupPath::new(&opctx, &osagactx.datastore())
+            .instance_id(params.instance_id)
+            .fetch()
+            .await
+            .map_err(ActionError::action_failed)?;
+    let src_sled_id = db_instance.runtime().sled_id;
+
+    match params.migrate_params.dst_sled_id {
+        Some(dst_sled_id) if dst_sled_id == src_sled_id => {
+            Err(ActionError::action_failed(Error::invalid_request(
+                "instance is already running on the requested sled",
+            )))
+        }
+        Some(dst_sled_id) => {
+            let (.., sled) = LookupPath::new(&opctx, &osagactx.datastore())
+                .sled_id(dst_sled_id)
+                .fetch()
+                .await
+                .map_err(ActionError::action_failed)?;
+            if !sled.provision_state.is_provisionable() {
+                return Err(ActionError::action_failed(
+                    Error::invalid_request(
+                        "sled is not accepting new instances",
+                    ),
+                ));
+            }
+            Ok(sled.id())
+        }
+        // Choose a sled the same way we do for a new Instance, other than
+        // the one the Instance is running on.
+        None => {
+            let nexus = osagactx.nexus();
+            let groups = nexus
+                .instance_affinity_groups(&opctx, &authz_instance)
+                .await
+                .map_err(ActionError::action_failed)?;
+            nexus
+                .affinity_sled_select(
+                    &opctx,
+                    params.instance_id,
+                    &groups,
+                    &[src_sled_id],
+                )
+                .await
+                .map_err(ActionError::action_failed)?
+                .ok_or_else(|| Error::ServiceUnavailable {
+                    internal_message: String::from(
+                        "no sleds available to migrate Instance to",
+                    ),
+                })
+                .map_err(ActionError::action_failed)
+        }
+    }
+}
+
+// Allocate an IP address on the destination sled for the Propolis server.
+async fn sim_allocate_propolis_ip(
+    sagactx: NexusActionContext,
+) -> Result<Ipv6Addr, ActionError> {
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+    allocate_sled_ipv6(&opctx, sagactx, "dst_sled_id").await
+}
+
+async fn sim_create_migration_record(
+    sagactx: NexusActionContext,
+) -> Result<db::model::Migration, ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+
+    let migration_id = sagactx.lookup::<Uuid>("migrate_id")?;
+    let dst_propolis_id = sagactx.lookup::<Uuid>("dst_propolis_id")?;
+    let dst_sled_id = sagactx.lookup::<Uuid>("dst_sled_id")?;
+
+    let (.., authz_instance, db_instance) =
+        LookupPath::new(&opctx, &osagactx.datastore())
+            .instance_id(params.instance_id)
+            .fetch()
+            .await
+            .map_err(ActionError::action_failed)?;
+    let runtime = db_instance.runtime();
+
+    osagactx
+        .datastore()
+        .migration_insert(
+            &opctx,
+            &authz_instance,
+            db::model::Migration::new(
+                migration_id,
+                params.instance_id,
+                runtime.sled_id,
+                runtime.propolis_id,
+                dst_sled_id,
+                dst_propolis_id,
+            ),
+        )
+        .await
+        .map_err(ActionError::action_failed)
+}
+
+async fn sim_create_migration_record_undo(
+    sagactx: NexusActionContext,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+    let migration = sagactx.lookup::<db::model::Migration>("migration")?;
+
+    let (.., authz_instance) = LookupPath::new(&opctx, &osagactx.datastore())
+        .instance_id(params.instance_id)
+        .lookup_for(authz::Action::Modify)
+        .await?;
+
+    // Keep the record around, so that there's a trace of what happened.  If
+    // the migration was cancelled, it stays that way.
+    osagactx
+        .datastore()
+        .migration_update_state(
+            &opctx,
+            &authz_instance,
+            migration.id,
+            MigrationState::Failed,
+        )
+        .await?;
+    Ok(())
+}
+
 async fn sim_migrate_prep(
     sagactx: NexusActionContext,
 ) -> Result<(Uuid, InstanceRuntimeState), ActionError> {
@@ -102,6 +250,7 @@ async fn sim_migrate_prep(
 
     let migrate_uuid = sagactx.lookup::<Uuid>("migrate_id")?;
     let dst_propolis_uuid = sagactx.lookup::<Uuid>("dst_propolis_id")?;
+    let migration = sagactx.lookup::<db::model::Migration>("migration")?;
 
     // We have sled-agent (via Nexus) attempt to place
     // the instance in a "Migrating" state w/ the given
@@ -118,97 +267,99 @@ async fn sim_migrate_prep(
         .await
         .map_err(ActionError::action_failed)?;
     let instance_id = instance.id();
+    let runtime: InstanceRuntimeState = instance.runtime_state.into();
 
-    Ok((instance_id, instance.runtime_state.into()))
+    let (.., authz_instance) = LookupPath::new(&opctx, &osagactx.datastore())
+        .instance_id(instance_id)
+        .lookup_for(authz::Action::Modify)
+        .await
+        .map_err(ActionError::action_failed)?;
+    osagactx
+        .nexus()
+        .instance_migration_record_phase(
+            &opctx,
+            &authz_instance,
+            &migration,
+            &runtime,
+        )
+        .await
+        .map_err(ActionError::action_failed)?;
+
+    Ok((instance_id, runtime))
 }
 
-// Allocate an IP address on the destination sled for the Propolis server.
-async fn sim_allocate_propolis_ip(
+async fn sim_migrate_prep_undo(
     sagactx: NexusActionContext,
-) -> Result<Ipv6Addr, ActionError> {
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
     let params = sagactx.saga_params::<Params>()?;
     let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
-    allocate_sled_ipv6(&opctx, sagactx, "dst_sled_uuid").await
+    let migration = sagactx.lookup::<db::model::Migration>("migration")?;
+
+    let (.., authz_instance, db_instance) =
+        LookupPath::new(&opctx, &osagactx.datastore())
+            .instance_id(params.instance_id)
+            .fetch()
+            .await?;
+
+    // If the migration was cancelled in the meantime, the source may already
+    // have been told to keep running.
+    if db_instance.runtime().migration_id != Some(migration.id) {
+        return Ok(());
+    }
+
+    // Leave the instance running on the source.
+    osagactx
+        .nexus()
+        .instance_migration_abandon(
+            &opctx,
+            &authz_instance,
+            &db_instance,
+            &migration,
+            MigrationState::Failed,
+        )
+        .await?;
+    Ok(())
 }
 
 async fn sim_instance_migrate(
     sagactx: NexusActionContext,
-) -> Result<(), ActionError> {
+) -> Result<InstanceRuntimeState, ActionError> {
     let osagactx = sagactx.user_data();
     let params = sagactx.saga_params::<Params>()?;
     let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
 
     let migration_id = sagactx.lookup::<Uuid>("migrate_id")?;
-    let dst_sled_id = params.migrate_params.dst_sled_id;
+    let dst_sled_id = sagactx.lookup::<Uuid>("dst_sled_id")?;
     let dst_propolis_id = sagactx.lookup::<Uuid>("dst_propolis_id")?;
+    let dst_propolis_ip = sagactx.lookup::<Ipv6Addr>("dst_propolis_ip")?;
     let (instance_id, old_runtime) =
         sagactx.lookup::<(Uuid, InstanceRuntimeState)>("migrate_instance")?;
 
-    // Allocate an IP address the destination sled for the new Propolis server.
-    let propolis_addr = osagactx
-        .datastore()
-        .next_ipv6_address(&opctx, dst_sled_id)
-        .await
-        .map_err(ActionError::action_failed)?;
-
     let runtime = InstanceRuntimeState {
         sled_id: dst_sled_id,
         propolis_id: dst_propolis_id,
-        propolis_addr: Some(std::net::SocketAddr::new(
-            propolis_addr.into(),
-            12400,
+        propolis_addr: Some(SocketAddr::new(
+            dst_propolis_ip.into(),
+            PROPOLIS_PORT,
         )),
-        ..old_runtime
+        ..old_runtime.clone()
     };
 
-    // Collect the external IPs for the instance.
-    //  https://github.com/oxidecomputer/omicron/issues/1467
-    // TODO-correctness: Handle Floating IPs, see
-    //  https://github.com/oxidecomputer/omicron/issues/1334
-    let (snat_ip, external_ips): (Vec<_>, Vec<_>) = osagactx
-        .datastore()
-        .instance_lookup_external_ips(&opctx, instance_id)
+    // The target needs to be set up exactly like the source.
+    let (.., authz_instance, db_instance) =
+        LookupPath::new(&opctx, &osagactx.datastore())
+            .instance_id(instance_id)
+            .fetch()
+            .await
+            .map_err(ActionError::action_failed)?;
+    let mut instance_hardware = osagactx
+        .nexus()
+        .instance_hardware(&opctx, &authz_instance, &db_instance)
         .await
-        .map_err(ActionError::action_failed)?
-        .into_iter()
-        .partition(|ip| ip.kind == IpKind::SNat);
-
-    // Sanity checks on the number and kind of each IP address.
-    if external_ips.len() > crate::app::MAX_EXTERNAL_IPS_PER_INSTANCE {
-        return Err(ActionError::action_failed(Error::internal_error(
-            format!(
-                "Expected the number of external IPs to be limited to \
-                {}, but found {}",
-                crate::app::MAX_EXTERNAL_IPS_PER_INSTANCE,
-                external_ips.len(),
-            )
-            .as_str(),
-        )));
-    }
-    let external_ips =
-        external_ips.into_iter().map(|model| model.ip.ip()).collect();
-    if snat_ip.len() != 1 {
-        return Err(ActionError::action_failed(Error::internal_error(
-            "Expected exactly one SNAT IP address for an instance",
-        )));
-    }
-    let source_nat = SourceNatConfig::from(snat_ip.into_iter().next().unwrap());
-
-    // The TODO items below are tracked in
-    //   https://github.com/oxidecomputer/omicron/issues/1783
-    let instance_hardware = InstanceHardware {
-        runtime: runtime.into(),
-        // TODO: populate NICs
-        nics: vec![],
-        source_nat,
-        external_ips,
-        // TODO: populate firewall rules
-        firewall_rules: vec![],
-        // TODO: populate disks
-        disks: vec![],
-        // TODO: populate cloud init bytes
-        cloud_init_bytes: None,
-    };
+        .map_err(ActionError::action_failed)?;
+    instance_hardware.runtime = runtime.into();
+
     let target = InstanceRuntimeStateRequested {
         run_state: InstanceStateRequested::Migrating,
         migration_params: Some(InstanceRuntimeStateMigrateParams {
@@ -247,19 +398,90 @@ async fn sim_instance_migrate(
         .into_inner()
         .into();
 
+    let migration = sagactx.lookup::<db::model::Migration>("migration")?;
     osagactx
-        .datastore()
-        .instance_update_runtime(&instance_id, &new_runtime_state.into())
+        .nexus()
+        .instance_migration_record_phase(
+            &opctx,
+            &authz_instance,
+            &migration,
+            &new_runtime_state,
+        )
         .await
         .map_err(ActionError::action_failed)?;
 
+    Ok(new_runtime_state)
+}
+
+async fn sim_instance_migrate_undo(
+    sagactx: NexusActionContext,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+    let dst_sled_id = sagactx.lookup::<Uuid>("dst_sled_id")?;
+    let new_runtime_state =
+        sagactx.lookup::<InstanceRuntimeState>("instance_migrate")?;
+
+    // Tear down the target.
+    let (.., authz_instance) = LookupPath::new(&opctx, &osagactx.datastore())
+        .instance_id(params.instance_id)
+        .lookup_for(authz::Action::Modify)
+        .await?;
+    osagactx
+        .nexus()
+        .instance_migration_put(
+            &opctx,
+            &authz_instance,
+            dst_sled_id,
+            new_runtime_state,
+            InstanceStateRequested::Destroyed,
+        )
+        .await?;
     Ok(())
 }
 
-async fn sim_cleanup_source(
-    _sagactx: NexusActionContext,
+async fn sim_migration_start(
+    sagactx: NexusActionContext,
 ) -> Result<(), ActionError> {
-    // TODO: clean up the previous instance whether it's on the same sled or a
-    // different one
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+    let migration = sagactx.lookup::<db::model::Migration>("migration")?;
+    let new_runtime_state =
+        sagactx.lookup::<InstanceRuntimeState>("instance_migrate")?;
+
+    let (.., authz_instance) = LookupPath::new(&opctx, &osagactx.datastore())
+        .instance_id(params.instance_id)
+        .lookup_for(authz::Action::Modify)
+        .await
+        .map_err(ActionError::action_failed)?;
+
+    // If the migration was cancelled while the target was being set up, it
+    // stays cancelled, and we unwind.
+    let started = osagactx
+        .datastore()
+        .migration_update_state(
+            &opctx,
+            &authz_instance,
+            migration.id,
+            MigrationState::InProgress,
+        )
+        .await
+        .map_err(ActionError::action_failed)?;
+    if !started {
+        return Err(ActionError::action_failed(Error::invalid_request(
+            "migration was cancelled",
+        )));
+    }
+
+    // From here on, the target reports the progress of the migration to us
+    // directly.
+    osagactx
+        .datastore()
+        .instance_update_runtime(&params.instance_id, &new_runtime_state.into())
+        .await
+        .map_err(ActionError::action_failed)?;
+
     Ok(())
 }
@@ -21,6 +21,7 @@ use uuid::Uuid;
 
 pub mod disk_create;
 pub mod disk_delete;
+pub mod disk_resize;
 pub mod instance_create;
 pub mod instance_delete;
 pub mod instance_migrate;
@@ -95,6 +96,7 @@ fn make_action_registry() -> ActionRegistry {
 
     <disk_create::SagaDiskCreate as NexusSaga>::register_actions(&mut registry);
     <disk_delete::SagaDiskDelete as NexusSaga>::register_actions(&mut registry);
+    <disk_resize::SagaDiskResize as NexusSaga>::register_actions(&mut registry);
     <instance_create::SagaInstanceCreate as NexusSaga>::register_actions(
         &mut registry,
     );
@@ -2225,6 +2225,7 @@ mod test {
                     params::InstanceDiskAttach { name: Name::from_str(DISK_NAME).unwrap() },
                 )],
                 external_ips: vec![],
+                affinity_groups: Vec::new(),
                 start: true,
             },
         )
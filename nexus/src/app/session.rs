@@ -152,6 +152,7 @@ impl super::Nexus {
                 | Error::Unauthenticated { .. }
                 | Error::InvalidValue { .. }
                 | Error::Forbidden
+                | Error::InsufficientCapacity { .. }
                 | Error::InternalError { .. }
                 | Error::ServiceUnavailable { .. }
                 | Error::MethodNotAllowed { .. }
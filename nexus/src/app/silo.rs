@@ -41,9 +41,12 @@ impl super::Nexus {
         // create arbitrary groups in the Silo, but we allow them to create
         // this one in this case.
         let external_authn_opctx = self.opctx_external_authn();
-        self.datastore()
+        let silo = self
+            .datastore()
             .silo_create(&opctx, &external_authn_opctx, new_silo_params)
-            .await
+            .await?;
+        self.external_dns_sync_activate();
+        Ok(silo)
     }
 
     pub async fn silos_list(
@@ -88,7 +91,9 @@ impl super::Nexus {
                 .silo_name(name)
                 .fetch_for(authz::Action::Delete)
                 .await?;
-        self.db_datastore.silo_delete(opctx, &authz_silo, &db_silo).await
+        self.db_datastore.silo_delete(opctx, &authz_silo, &db_silo).await?;
+        self.external_dns_sync_activate();
+        Ok(())
     }
 
     // Role assignments
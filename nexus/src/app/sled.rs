@@ -4,22 +4,31 @@
 
 //! Sleds, and the hardware and services within them.
 
+use crate::authz;
 use crate::context::OpContext;
 use crate::db;
 use crate::db::identity::Asset;
+use crate::db::identity::Resource;
 use crate::db::lookup::LookupPath;
 use crate::db::model::DatasetKind;
 use crate::db::model::ServiceKind;
+use crate::external_api::params;
+use crate::external_api::views::SledDrainStatus;
 use crate::internal_api::params::{
     PhysicalDiskDeleteRequest, PhysicalDiskPutRequest, SledAgentStartupInfo,
     SledRole, ZpoolPutRequest,
 };
+use chrono::Utc;
 use omicron_common::api::external::DataPageParams;
 use omicron_common::api::external::Error;
+use omicron_common::api::external::InstanceState;
 use omicron_common::api::external::ListResultVec;
 use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::LookupType;
+use omicron_common::api::external::UpdateResult;
 use sled_agent_client::Client as SledAgentClient;
-use std::net::{Ipv6Addr, SocketAddrV6};
+use std::collections::BTreeSet;
+use std::net::{IpAddr, Ipv6Addr, SocketAddrV6};
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -100,12 +109,184 @@ impl super::Nexus {
         )))
     }
 
-    pub async fn random_sled_id(&self) -> Result<Option<Uuid>, Error> {
-        Ok(self
+    /// Changes whether new Instances and regions may be placed on a sled.
+    ///
+    /// Moving a sled back to `Active` stops any drain in progress, though
+    /// migrations which have already begun are left to finish.
+    pub async fn sled_set_provision_state(
+        &self,
+        opctx: &OpContext,
+        sled_id: Uuid,
+        state: db::model::SledProvisionState,
+    ) -> UpdateResult<db::model::Sled> {
+        let (.., authz_sled) = LookupPath::new(opctx, &self.db_datastore)
+            .sled_id(sled_id)
+            .lookup_for(authz::Action::Modify)
+            .await?;
+        let sled = self
+            .db_datastore
+            .sled_set_provision_state(opctx, &authz_sled, state)
+            .await?;
+        info!(self.log, "sled provision state changed";
+            "sled_id" => %sled_id,
+            "provision_state" => ?state);
+        Ok(sled)
+    }
+
+    /// Cordons a sled and moves its Instances to other sleds.
+    ///
+    /// Running Instances are live-migrated.  Stopped ones are simply assigned
+    /// to another sled, which is where they'll start next time.  Instances in
+    /// any other state (e.g., starting or already migrating) are left alone;
+    /// draining the sled again picks up any that were skipped or that failed
+    /// to move.
+    pub async fn sled_drain(
+        self: &Arc<Self>,
+        opctx: &OpContext,
+        sled_id: Uuid,
+    ) -> Result<SledDrainStatus, Error> {
+        self.sled_set_provision_state(
+            opctx,
+            sled_id,
+            db::model::SledProvisionState::Draining,
+        )
+        .await?;
+
+        // The caller may administer the fleet without being able to see into
+        // the Silos whose Instances we're about to move, so this part happens
+        // on behalf of the control plane.
+        let drain_opctx = &self.opctx_sled_drain;
+        let authz_sled =
+            authz::Sled::new(authz::FLEET, sled_id, LookupType::ById(sled_id));
+        let instances = self
+            .db_datastore
+            .sled_instance_list(drain_opctx, &authz_sled)
+            .await?;
+        for instance in instances {
+            let instance_id = instance.id();
+            let result = match instance.runtime().state.state() {
+                InstanceState::Running => {
+                    let instance_lookup =
+                        LookupPath::new(drain_opctx, &self.db_datastore)
+                            .instance_id(instance_id);
+                    self.project_instance_migrate(
+                        drain_opctx,
+                        &instance_lookup,
+                        params::InstanceMigrate { dst_sled_id: None },
+                    )
+                    .await
+                    .map(|_| ())
+                }
+                InstanceState::Stopped => {
+                    self.sled_drain_stopped_instance(
+                        drain_opctx,
+                        sled_id,
+                        &instance,
+                    )
+                    .await
+                }
+                _ => continue,
+            };
+            if let Err(error) = result {
+                warn!(self.log, "failed to move instance off of sled";
+                    "sled_id" => %sled_id,
+                    "instance_id" => %instance_id,
+                    "error" => ?error);
+            }
+        }
+
+        self.sled_drain_status(opctx, sled_id).await
+    }
+
+    /// Reports how far along moving Instances off of a sled is.
+    pub async fn sled_drain_status(
+        &self,
+        opctx: &OpContext,
+        sled_id: Uuid,
+    ) -> LookupResult<SledDrainStatus> {
+        let (.., db_sled) = LookupPath::new(opctx, &self.db_datastore)
+            .sled_id(sled_id)
+            .fetch_for(authz::Action::Read)
+            .await?;
+        let drain_opctx = &self.opctx_sled_drain;
+        let authz_sled =
+            authz::Sled::new(authz::FLEET, sled_id, LookupType::ById(sled_id));
+
+        // Once a migration is under way, the Instance is recorded as being on
+        // its target, but it hasn't left this sled until the migration is
+        // over.
+        let migrating: BTreeSet<Uuid> = self
             .db_datastore
-            .random_sled(&self.opctx_alloc)
+            .sled_list_active_migrations(drain_opctx, &authz_sled)
             .await?
-            .map(|sled| sled.id()))
+            .into_iter()
+            .map(|migration| migration.instance_id)
+            .collect();
+        let mut remaining = migrating.clone();
+        remaining.extend(
+            self.db_datastore
+                .sled_instance_list(drain_opctx, &authz_sled)
+                .await?
+                .into_iter()
+                .map(|instance| instance.id()),
+        );
+
+        Ok(SledDrainStatus {
+            sled_id,
+            provision_state: db_sled.provision_state.into(),
+            instances_remaining: remaining.len() as u64,
+            instances_migrating: migrating.len() as u64,
+        })
+    }
+
+    /// Assigns a stopped Instance on a draining sled to some other sled.
+    async fn sled_drain_stopped_instance(
+        &self,
+        opctx: &OpContext,
+        sled_id: Uuid,
+        instance: &db::model::Instance,
+    ) -> Result<(), Error> {
+        let (.., authz_instance) = LookupPath::new(opctx, &self.db_datastore)
+            .instance_id(instance.id())
+            .lookup_for(authz::Action::Modify)
+            .await?;
+        let groups =
+            self.instance_affinity_groups(opctx, &authz_instance).await?;
+        let new_sled_id = self
+            .affinity_sled_select(opctx, instance.id(), &groups, &[sled_id])
+            .await?
+            .ok_or_else(|| {
+                Error::unavail("no sleds available to move Instance to")
+            })?;
+        let propolis_ip =
+            self.db_datastore.next_ipv6_address(opctx, new_sled_id).await?;
+
+        // The sled agent on the drained sled never hears about the Instance
+        // again, which is fine while it's stopped.
+        //
+        // TODO-correctness If the Instance is started while this is going on,
+        // whichever update lands second is ignored because of the generation
+        // number, but the Instance may end up running somewhere other than
+        // where we think it is.
+        let old_runtime = instance.runtime();
+        let new_runtime = db::model::InstanceRuntimeState {
+            sled_id: new_sled_id,
+            propolis_ip: Some(IpAddr::from(propolis_ip).into()),
+            gen: old_runtime.gen.next().into(),
+            time_updated: Utc::now(),
+            ..old_runtime.clone()
+        };
+        let updated = self
+            .db_datastore
+            .instance_update_runtime(&instance.id(), &new_runtime)
+            .await?;
+        if updated {
+            info!(self.log, "moved stopped instance off of sled";
+                "instance_id" => %instance.id(),
+                "old_sled_id" => %sled_id,
+                "new_sled_id" => %new_sled_id);
+        }
+        Ok(())
     }
 
     // Physical disks
@@ -41,6 +41,7 @@ use crate::db::model::ConsoleSession;
 use crate::authz;
 use crate::db;
 use crate::db::identity::Asset;
+use crate::external_api::views;
 use omicron_common::api::external::LookupType;
 use serde::Deserialize;
 use serde::Serialize;
@@ -337,6 +338,24 @@ impl From<&Actor> for db::model::IdentityType {
     }
 }
 
+impl From<&Actor> for views::AuditLogActor {
+    fn from(actor: &Actor) -> views::AuditLogActor {
+        match actor {
+            Actor::UserBuiltin { user_builtin_id } => {
+                views::AuditLogActor::UserBuiltin {
+                    user_builtin_id: *user_builtin_id,
+                }
+            }
+            Actor::SiloUser { silo_user_id, silo_id } => {
+                views::AuditLogActor::SiloUser {
+                    silo_user_id: *silo_user_id,
+                    silo_id: *silo_id,
+                }
+            }
+        }
+    }
+}
+
 impl std::fmt::Debug for Actor {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // This `Debug` impl is approximately the same as what we'd get by
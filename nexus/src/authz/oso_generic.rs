@@ -109,6 +109,7 @@ pub fn make_omicron_oso(log: &slog::Logger) -> Result<OsoInit, anyhow::Error> {
         GlobalImageList::get_polar_class(),
         ConsoleSessionList::get_polar_class(),
         DeviceAuthRequestList::get_polar_class(),
+        AuditLog::get_polar_class(),
         SiloIdentityProviderList::get_polar_class(),
         SiloUserList::get_polar_class(),
     ];
@@ -122,6 +123,8 @@ pub fn make_omicron_oso(log: &slog::Logger) -> Result<OsoInit, anyhow::Error> {
         Project::init(),
         Disk::init(),
         Snapshot::init(),
+        FloatingIp::init(),
+        AffinityGroup::init(),
         Instance::init(),
         IpPool::init(),
         NetworkInterface::init(),
@@ -131,6 +134,7 @@ pub fn make_omicron_oso(log: &slog::Logger) -> Result<OsoInit, anyhow::Error> {
         VpcSubnet::init(),
         // Fleet-level resources
         Certificate::init(),
+        AlertRule::init(),
         ConsoleSession::init(),
         DeviceAuthRequest::init(),
         DeviceAccessToken::init(),
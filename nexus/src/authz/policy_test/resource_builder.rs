@@ -246,6 +246,7 @@ impl_dyn_authorized_resource_for_global!(authz::ConsoleSessionList);
 impl_dyn_authorized_resource_for_global!(authz::GlobalImageList);
 impl_dyn_authorized_resource_for_global!(authz::IpPoolList);
 impl_dyn_authorized_resource_for_global!(authz::DeviceAuthRequestList);
+impl_dyn_authorized_resource_for_global!(authz::AuditLog);
 
 impl DynAuthorizedResource for authz::SiloIdentityProviderList {
     fn do_authorize<'a, 'b>(
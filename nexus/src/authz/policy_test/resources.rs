@@ -68,6 +68,7 @@ pub async fn make_resources(
     builder.new_resource(authz::DEVICE_AUTH_REQUEST_LIST);
     builder.new_resource(authz::GLOBAL_IMAGE_LIST);
     builder.new_resource(authz::IP_POOL_LIST);
+    builder.new_resource(authz::AUDIT_LOG);
 
     // Silo/organization/project hierarchy
     make_silo(&mut builder, "silo1", main_silo_id, true).await;
@@ -104,6 +105,13 @@ pub async fn make_resources(
         LookupType::ById(certificate_id),
     ));
 
+    let alert_rule_id = "4e8e2ed1-c4a3-4d2b-9f5b-3d8a7b1c6e0f".parse().unwrap();
+    builder.new_resource(authz::AlertRule::new(
+        authz::FLEET,
+        alert_rule_id,
+        LookupType::ById(alert_rule_id),
+    ));
+
     let device_user_code = String::from("a-device-user-code");
     builder.new_resource(authz::DeviceAuthRequest::new(
         authz::FLEET,
@@ -287,6 +295,18 @@ async fn make_project(
         Uuid::new_v4(),
         LookupType::ByName(format!("{}-snapshot1", disk_name)),
     ));
+
+    builder.new_resource(authz::FloatingIp::new(
+        project.clone(),
+        Uuid::new_v4(),
+        LookupType::ByName(format!("{}-fip1", project_name)),
+    ));
+
+    builder.new_resource(authz::AffinityGroup::new(
+        project.clone(),
+        Uuid::new_v4(),
+        LookupType::ByName(format!("{}-affinity-group1", project_name)),
+    ));
 }
 
 /// Returns the set of authz classes exempted from the coverage test
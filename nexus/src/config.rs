@@ -7,7 +7,9 @@
 
 // TODO: Use them directly? No need for this file
 
+pub use omicron_common::nexus_config::AlertsConfig;
 pub use omicron_common::nexus_config::Config;
+pub use omicron_common::nexus_config::ExternalDnsConfig;
 pub use omicron_common::nexus_config::PackageConfig;
 pub use omicron_common::nexus_config::SchemeName;
 pub use omicron_common::nexus_config::Tunables;
@@ -12,6 +12,7 @@ use crate::authn::external::session_cookie::{Session, SessionStore};
 use crate::authn::ConsoleSessionWithSiloId;
 use crate::authz::AuthorizedResource;
 use crate::db::DataStore;
+use crate::external_api::views;
 use crate::saga_interface::SagaContext;
 use async_trait::async_trait;
 use authn::external::session_cookie::HttpAuthnSessionCookie;
@@ -19,6 +20,7 @@ use authn::external::spoof::HttpAuthnSpoof;
 use authn::external::token::HttpAuthnToken;
 use authn::external::HttpAuthnScheme;
 use chrono::{DateTime, Duration, Utc};
+use http::StatusCode;
 use internal_dns_names::{ServiceName, SRV};
 use omicron_common::address::{Ipv6Subnet, AZ_PREFIX, COCKROACH_PORT};
 use omicron_common::api::external::Error;
@@ -30,6 +32,7 @@ use slog::Logger;
 use std::collections::BTreeMap;
 use std::env;
 use std::fmt::Debug;
+use std::future::Future;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -202,6 +205,110 @@ impl ServerContext {
             },
         }))
     }
+
+    /// Runs the handler for a mutating external API request, recording the
+    /// request and its outcome in the audit log
+    ///
+    /// The handler is timed just like it would be by
+    /// [`LatencyTracker::instrument_dropshot_handler()`].  If the request
+    /// can't be recorded, it's not carried out at all.
+    pub async fn audit_and_time<H, R>(
+        &self,
+        rqctx: &dropshot::RequestContext<Arc<ServerContext>>,
+        operation_id: &str,
+        handler: H,
+    ) -> Result<R, dropshot::HttpError>
+    where
+        R: dropshot::HttpResponse,
+        H: Future<Output = Result<R, dropshot::HttpError>>,
+    {
+        // The handler authenticates the request again when it creates its
+        // OpContext, but we have no way to get at that from here, and we want
+        // to record who made the request even if the handler fails.  If
+        // authentication fails, the handler will run into the same problem and
+        // deal with it however it sees fit (e.g., logout succeeds regardless).
+        // TODO-performance we could avoid authenticating requests twice if
+        // handlers got their OpContext from here.
+        let actor = match self.external_authn.authn_request(rqctx).await {
+            Ok(authn) => authn.actor().map_or(
+                views::AuditLogActor::Unauthenticated,
+                views::AuditLogActor::from,
+            ),
+            Err(_) => views::AuditLogActor::Unauthenticated,
+        };
+
+        let entry =
+            self.audit_log_entry_init(rqctx, operation_id, actor).await?;
+        let result = self
+            .external_latencies
+            .instrument_dropshot_handler(rqctx, handler)
+            .await;
+        match &result {
+            Ok(_) => {
+                let status_code =
+                    R::response_metadata().success.unwrap_or(StatusCode::OK);
+                self.audit_log_entry_complete(rqctx, &entry, status_code, None)
+                    .await
+            }
+            Err(error) => {
+                self.audit_log_entry_complete(
+                    rqctx,
+                    &entry,
+                    error.status_code,
+                    Some(error),
+                )
+                .await
+            }
+        }
+        result
+    }
+
+    /// Records a request to the external API which has just been received in
+    /// the audit log
+    pub async fn audit_log_entry_init(
+        &self,
+        rqctx: &dropshot::RequestContext<Arc<ServerContext>>,
+        operation_id: &str,
+        actor: views::AuditLogActor,
+    ) -> Result<db::model::AuditLogEntry, dropshot::HttpError> {
+        let request = &rqctx.request;
+        let entry = db::model::AuditLogEntry::new(
+            rqctx.request_id.clone(),
+            operation_id.to_string(),
+            request.method().to_string(),
+            request.uri().to_string(),
+            actor,
+        );
+        Ok(self.nexus.audit_log_entry_init(entry).await?)
+    }
+
+    /// Records the outcome of a request previously recorded with
+    /// [`ServerContext::audit_log_entry_init()`]
+    ///
+    /// By the time we get here, the request has been carried out, so failing
+    /// to record its outcome is logged rather than reported to the client.
+    pub async fn audit_log_entry_complete(
+        &self,
+        rqctx: &dropshot::RequestContext<Arc<ServerContext>>,
+        entry: &db::model::AuditLogEntry,
+        status_code: StatusCode,
+        error: Option<&dropshot::HttpError>,
+    ) {
+        let completion = db::model::AuditLogCompletion::new(
+            status_code.as_u16(),
+            error.map(|error| error.external_message.clone()),
+        );
+        if let Err(error) =
+            self.nexus.audit_log_entry_complete(entry, completion).await
+        {
+            warn!(
+                rqctx.log,
+                "failed to record outcome of request in audit log";
+                "audit_log_entry_id" => entry.id.to_string(),
+                "error" => #%error,
+            );
+        }
+    }
 }
 
 /// Provides general facilities scoped to whatever operation Nexus is currently
@@ -0,0 +1,322 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! [`DataStore`] methods on [`AffinityGroup`]s.
+
+use super::DataStore;
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::collection_insert::AsyncInsertError;
+use crate::db::collection_insert::DatastoreCollection;
+use crate::db::error::public_error_from_diesel_pool;
+use crate::db::error::ErrorHandler;
+use crate::db::error::TransactionError;
+use crate::db::identity::Resource;
+use crate::db::model::AffinityGroup;
+use crate::db::model::AffinityGroupInstanceMembership;
+use crate::db::model::AffinityGroupKind;
+use crate::db::model::AffinityPolicy;
+use crate::db::model::Instance;
+use crate::db::model::Name;
+use crate::db::model::Project;
+use crate::db::pagination::paginated;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::Utc;
+use diesel::prelude::*;
+use omicron_common::api::external::http_pagination::PaginatedBy;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::ResourceType;
+use ref_cast::RefCast;
+use uuid::Uuid;
+
+impl DataStore {
+    pub async fn project_create_affinity_group(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        group: AffinityGroup,
+    ) -> CreateResult<AffinityGroup> {
+        opctx.authorize(authz::Action::CreateChild, authz_project).await?;
+
+        use db::schema::affinity_group::dsl;
+        let name = group.name().clone();
+        let project_id = group.project_id;
+        Project::insert_resource(
+            project_id,
+            diesel::insert_into(dsl::affinity_group).values(group),
+        )
+        .insert_and_get_result_async(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| match e {
+            AsyncInsertError::CollectionNotFound => authz_project.not_found(),
+            AsyncInsertError::DatabaseError(e) => {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(
+                        ResourceType::AffinityGroup,
+                        name.as_str(),
+                    ),
+                )
+            }
+        })
+    }
+
+    pub async fn affinity_group_list(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        pagparams: &PaginatedBy<'_>,
+    ) -> ListResultVec<AffinityGroup> {
+        opctx.authorize(authz::Action::ListChildren, authz_project).await?;
+
+        use db::schema::affinity_group::dsl;
+        match pagparams {
+            PaginatedBy::Id(pagparams) => {
+                paginated(dsl::affinity_group, dsl::id, &pagparams)
+            }
+            PaginatedBy::Name(pagparams) => paginated(
+                dsl::affinity_group,
+                dsl::name,
+                &pagparams.map_name(|n| Name::ref_cast(n)),
+            ),
+        }
+        .filter(dsl::time_deleted.is_null())
+        .filter(dsl::project_id.eq(authz_project.id()))
+        .select(AffinityGroup::as_select())
+        .load_async(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Deletes an affinity group.
+    ///
+    /// The group's members are unaffected, other than no longer belonging to
+    /// the group.
+    pub async fn project_delete_affinity_group(
+        &self,
+        opctx: &OpContext,
+        authz_group: &authz::AffinityGroup,
+    ) -> DeleteResult {
+        opctx.authorize(authz::Action::Delete, authz_group).await?;
+
+        use db::schema::affinity_group::dsl;
+        let now = Utc::now();
+        diesel::update(dsl::affinity_group)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::id.eq(authz_group.id()))
+            .set(dsl::time_deleted.eq(now))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_group),
+                )
+            })?;
+
+        use db::schema::affinity_group_instance_membership::dsl as member_dsl;
+        diesel::delete(member_dsl::affinity_group_instance_membership)
+            .filter(member_dsl::group_id.eq(authz_group.id()))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        Ok(())
+    }
+
+    /// Lists the Instances which are members of an affinity group.
+    pub async fn affinity_group_member_list(
+        &self,
+        opctx: &OpContext,
+        authz_group: &authz::AffinityGroup,
+    ) -> ListResultVec<Instance> {
+        opctx.authorize(authz::Action::Read, authz_group).await?;
+
+        use db::schema::affinity_group_instance_membership::dsl as member_dsl;
+        use db::schema::instance::dsl as instance_dsl;
+        member_dsl::affinity_group_instance_membership
+            .inner_join(
+                instance_dsl::instance
+                    .on(instance_dsl::id.eq(member_dsl::instance_id)),
+            )
+            .filter(member_dsl::group_id.eq(authz_group.id()))
+            .filter(instance_dsl::time_deleted.is_null())
+            .order(instance_dsl::id.asc())
+            .select(Instance::as_select())
+            .load_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Lists the sleds on which the members of an affinity group run, or
+    /// would run if started, other than `instance_id`.
+    pub async fn affinity_group_member_sleds(
+        &self,
+        opctx: &OpContext,
+        authz_group: &authz::AffinityGroup,
+        instance_id: Uuid,
+    ) -> ListResultVec<Uuid> {
+        opctx.authorize(authz::Action::Read, authz_group).await?;
+
+        use db::schema::affinity_group_instance_membership::dsl as member_dsl;
+        use db::schema::instance::dsl as instance_dsl;
+        member_dsl::affinity_group_instance_membership
+            .inner_join(
+                instance_dsl::instance
+                    .on(instance_dsl::id.eq(member_dsl::instance_id)),
+            )
+            .filter(member_dsl::group_id.eq(authz_group.id()))
+            .filter(member_dsl::instance_id.ne(instance_id))
+            .filter(instance_dsl::time_deleted.is_null())
+            .select(instance_dsl::active_server_id)
+            .distinct()
+            .load_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Lists the affinity groups of which an Instance is a member.
+    pub async fn instance_list_affinity_groups(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+    ) -> ListResultVec<AffinityGroup> {
+        opctx.authorize(authz::Action::Read, authz_instance).await?;
+
+        use db::schema::affinity_group::dsl as group_dsl;
+        use db::schema::affinity_group_instance_membership::dsl as member_dsl;
+        member_dsl::affinity_group_instance_membership
+            .inner_join(
+                group_dsl::affinity_group
+                    .on(group_dsl::id.eq(member_dsl::group_id)),
+            )
+            .filter(member_dsl::instance_id.eq(authz_instance.id()))
+            .filter(group_dsl::time_deleted.is_null())
+            .order(group_dsl::id.asc())
+            .select(AffinityGroup::as_select())
+            .load_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Makes an Instance a member of an affinity group.
+    ///
+    /// If the group has a hard policy, the sled which the Instance has been
+    /// assigned is checked against the group's other members in the same
+    /// transaction that records the membership, with the group locked.  This
+    /// catches Instances that were placed concurrently, which can't see one
+    /// another when their sleds are picked.
+    ///
+    /// This succeeds if the Instance is already a member.
+    pub async fn affinity_group_member_add(
+        &self,
+        opctx: &OpContext,
+        authz_group: &authz::AffinityGroup,
+        instance_id: Uuid,
+    ) -> Result<(), Error> {
+        opctx.authorize(authz::Action::Modify, authz_group).await?;
+
+        type TxnError = TransactionError<Error>;
+        let group_id = authz_group.id();
+        self.pool_authorized(opctx)
+            .await?
+            .transaction_async(|mut conn| async move {
+                use db::schema::affinity_group::dsl as group_dsl;
+                use db::schema::affinity_group_instance_membership::dsl;
+                use db::schema::instance::dsl as instance_dsl;
+
+                // Lock the group, so that members are added one at a time.
+                let group = group_dsl::affinity_group
+                    .filter(group_dsl::id.eq(group_id))
+                    .filter(group_dsl::time_deleted.is_null())
+                    .select(AffinityGroup::as_select())
+                    .for_update()
+                    .get_result_async(&mut conn)
+                    .await?;
+
+                if group.policy == AffinityPolicy::Hard {
+                    let sled_id: Uuid = instance_dsl::instance
+                        .filter(instance_dsl::id.eq(instance_id))
+                        .filter(instance_dsl::time_deleted.is_null())
+                        .select(instance_dsl::active_server_id)
+                        .get_result_async(&mut conn)
+                        .await?;
+                    let member_sleds: Vec<Uuid> =
+                        dsl::affinity_group_instance_membership
+                            .inner_join(
+                                instance_dsl::instance
+                                    .on(instance_dsl::id.eq(dsl::instance_id)),
+                            )
+                            .filter(dsl::group_id.eq(group_id))
+                            .filter(dsl::instance_id.ne(instance_id))
+                            .filter(instance_dsl::time_deleted.is_null())
+                            .select(instance_dsl::active_server_id)
+                            .distinct()
+                            .load_async(&mut conn)
+                            .await?;
+                    let satisfied = match group.kind {
+                        AffinityGroupKind::Affinity => {
+                            member_sleds.is_empty()
+                                || member_sleds.contains(&sled_id)
+                        }
+                        AffinityGroupKind::AntiAffinity => {
+                            !member_sleds.contains(&sled_id)
+                        }
+                    };
+                    if !satisfied {
+                        return Err(TxnError::CustomError(Error::unavail(
+                            &format!(
+                                "instance's sled no longer satisfies affinity \
+                                group \"{}\"",
+                                group.name(),
+                            ),
+                        )));
+                    }
+                }
+
+                diesel::insert_into(dsl::affinity_group_instance_membership)
+                    .values(AffinityGroupInstanceMembership {
+                        group_id,
+                        instance_id,
+                    })
+                    .on_conflict((dsl::group_id, dsl::instance_id))
+                    .do_nothing()
+                    .execute_async(&mut conn)
+                    .await?;
+                Ok(())
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e,
+                TxnError::Pool(e) => public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_group),
+                ),
+            })
+    }
+
+    /// Removes an Instance from every affinity group of which it's a member.
+    pub async fn instance_leave_affinity_groups(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+    ) -> DeleteResult {
+        opctx.authorize(authz::Action::Modify, authz_instance).await?;
+
+        use db::schema::affinity_group_instance_membership::dsl;
+        diesel::delete(dsl::affinity_group_instance_membership)
+            .filter(dsl::instance_id.eq(authz_instance.id()))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! [`DataStore`] methods on [`AlertRule`]s and their [`Alert`]s.
+
+use super::DataStore;
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::error::public_error_from_diesel_pool;
+use crate::db::error::ErrorHandler;
+use crate::db::error::TransactionError;
+use crate::db::identity::Resource;
+use crate::db::model::Alert;
+use crate::db::model::AlertRule;
+use crate::db::model::AlertRuleState;
+use crate::db::model::Name;
+use crate::db::pagination::paginated;
+use crate::db::pagination::paginated_multicolumn;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::DateTime;
+use chrono::Utc;
+use diesel::prelude::*;
+use omicron_common::api::external::http_pagination::PaginatedBy;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DataPageParams;
+use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::ResourceType;
+use omicron_common::api::external::UpdateResult;
+use ref_cast::RefCast;
+use uuid::Uuid;
+
+impl DataStore {
+    /// Stores a new alert rule in the database.
+    pub async fn alert_rule_create(
+        &self,
+        opctx: &OpContext,
+        rule: AlertRule,
+    ) -> CreateResult<AlertRule> {
+        use db::schema::alert_rule::dsl;
+
+        opctx.authorize(authz::Action::CreateChild, &authz::FLEET).await?;
+
+        let name = rule.name().clone();
+        diesel::insert_into(dsl::alert_rule)
+            .values(rule)
+            .returning(AlertRule::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(
+                        ResourceType::AlertRule,
+                        name.as_str(),
+                    ),
+                )
+            })
+    }
+
+    pub async fn alert_rule_list(
+        &self,
+        opctx: &OpContext,
+        pagparams: &PaginatedBy<'_>,
+    ) -> ListResultVec<AlertRule> {
+        use db::schema::alert_rule::dsl;
+
+        opctx.authorize(authz::Action::ListChildren, &authz::FLEET).await?;
+
+        match pagparams {
+            PaginatedBy::Id(params) => {
+                paginated(dsl::alert_rule, dsl::id, &params)
+            }
+            PaginatedBy::Name(params) => paginated(
+                dsl::alert_rule,
+                dsl::name,
+                &params.map_name(|n| Name::ref_cast(n)),
+            ),
+        }
+        .filter(dsl::time_deleted.is_null())
+        .select(AlertRule::as_select())
+        .load_async::<AlertRule>(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Deletes an alert rule, resolving its alert if it's firing.
+    pub async fn alert_rule_delete(
+        &self,
+        opctx: &OpContext,
+        authz_rule: &authz::AlertRule,
+    ) -> DeleteResult {
+        opctx.authorize(authz::Action::Delete, authz_rule).await?;
+
+        let rule_id = authz_rule.id();
+        self.pool_authorized(opctx)
+            .await?
+            .transaction_async(|conn| async move {
+                use db::schema::alert::dsl as alert_dsl;
+                use db::schema::alert_rule::dsl;
+
+                let now = Utc::now();
+                diesel::update(dsl::alert_rule)
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(rule_id))
+                    .set(dsl::time_deleted.eq(now))
+                    .execute_async(&conn)
+                    .await?;
+                diesel::update(alert_dsl::alert)
+                    .filter(alert_dsl::alert_rule_id.eq(rule_id))
+                    .filter(alert_dsl::time_resolved.is_null())
+                    .set(alert_dsl::time_resolved.eq(now))
+                    .execute_async(&conn)
+                    .await?;
+                Ok(())
+            })
+            .await
+            .map_err(|e| match e {
+                TransactionError::CustomError(e) => e,
+                TransactionError::Pool(e) => public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_rule),
+                ),
+            })
+    }
+
+    /// Records the outcome of evaluating an alert rule which was in state
+    /// `from` when it was fetched, including when it started breaching its
+    /// threshold, if it is.
+    ///
+    /// If the rule changed state, the alert it opened or resolved is returned.
+    /// Nothing is recorded, and `None` is returned, if the rule is no longer
+    /// in state `from` (e.g., because another Nexus evaluated it in the
+    /// meantime) or has been deleted.
+    pub async fn alert_rule_record_evaluation(
+        &self,
+        opctx: &OpContext,
+        authz_rule: &authz::AlertRule,
+        from: AlertRuleState,
+        to: AlertRuleState,
+        value: Option<f64>,
+        time_breach_started: Option<DateTime<Utc>>,
+    ) -> UpdateResult<Option<Alert>> {
+        opctx.authorize(authz::Action::Modify, authz_rule).await?;
+
+        let rule_id = authz_rule.id();
+        self.pool_authorized(opctx)
+            .await?
+            .transaction_async(|conn| async move {
+                use db::schema::alert::dsl as alert_dsl;
+                use db::schema::alert_rule::dsl;
+
+                let now = Utc::now();
+                let query = diesel::update(dsl::alert_rule)
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(rule_id))
+                    .filter(dsl::state.eq(from));
+                let updated = if from == to {
+                    query
+                        .set((
+                            dsl::time_evaluated.eq(now),
+                            dsl::last_value.eq(value),
+                            dsl::time_breach_started.eq(time_breach_started),
+                        ))
+                        .execute_async(&conn)
+                        .await?
+                } else {
+                    query
+                        .set((
+                            dsl::state.eq(to),
+                            dsl::time_state_changed.eq(now),
+                            dsl::time_evaluated.eq(now),
+                            dsl::last_value.eq(value),
+                            dsl::time_breach_started.eq(time_breach_started),
+                        ))
+                        .execute_async(&conn)
+                        .await?
+                };
+                if updated == 0 || from == to {
+                    return Ok(None);
+                }
+
+                let alert = match (to, value) {
+                    (AlertRuleState::Firing, Some(value)) => {
+                        diesel::insert_into(alert_dsl::alert)
+                            .values(Alert::new(rule_id, value))
+                            .returning(Alert::as_returning())
+                            .get_result_async(&conn)
+                            .await?
+                    }
+                    (AlertRuleState::Firing, None) => {
+                        unreachable!("alert rule fired without a value")
+                    }
+                    (AlertRuleState::Ok, _) => {
+                        diesel::update(alert_dsl::alert)
+                            .filter(alert_dsl::alert_rule_id.eq(rule_id))
+                            .filter(alert_dsl::time_resolved.is_null())
+                            .set(alert_dsl::time_resolved.eq(now))
+                            .returning(Alert::as_returning())
+                            .get_result_async(&conn)
+                            .await?
+                    }
+                };
+                Ok(Some(alert))
+            })
+            .await
+            .map_err(|e| match e {
+                TransactionError::CustomError(e) => e,
+                TransactionError::Pool(e) => public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_rule),
+                ),
+            })
+    }
+
+    /// Lists the alerts opened by a rule, in the order in which they fired.
+    pub async fn alert_list(
+        &self,
+        opctx: &OpContext,
+        authz_rule: &authz::AlertRule,
+        pagparams: &DataPageParams<'_, (DateTime<Utc>, Uuid)>,
+    ) -> ListResultVec<Alert> {
+        use db::schema::alert::dsl;
+
+        opctx.authorize(authz::Action::ListChildren, authz_rule).await?;
+
+        paginated_multicolumn(dsl::alert, (dsl::time_fired, dsl::id), pagparams)
+            .filter(dsl::alert_rule_id.eq(authz_rule.id()))
+            .select(Alert::as_select())
+            .load_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+}
@@ -0,0 +1,114 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! [`DataStore`] methods on the audit log.
+
+use super::DataStore;
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::error::public_error_from_diesel_pool;
+use crate::db::error::ErrorHandler;
+use crate::db::model::AuditLogCompletion;
+use crate::db::model::AuditLogEntry;
+use crate::db::pagination::paginated_multicolumn;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::DateTime;
+use chrono::Utc;
+use diesel::prelude::*;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DataPageParams;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::UpdateResult;
+use uuid::Uuid;
+
+impl DataStore {
+    /// Records a request which has just been received.
+    pub async fn audit_log_entry_init(
+        &self,
+        opctx: &OpContext,
+        entry: AuditLogEntry,
+    ) -> CreateResult<AuditLogEntry> {
+        opctx.authorize(authz::Action::CreateChild, &authz::AUDIT_LOG).await?;
+
+        use db::schema::audit_log::dsl;
+        diesel::insert_into(dsl::audit_log)
+            .values(entry)
+            .returning(AuditLogEntry::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Records the outcome of a request.
+    ///
+    /// Entries may only be completed once.  Completing an entry again has no
+    /// effect.
+    pub async fn audit_log_entry_complete(
+        &self,
+        opctx: &OpContext,
+        entry_id: Uuid,
+        completion: AuditLogCompletion,
+    ) -> UpdateResult<()> {
+        opctx.authorize(authz::Action::CreateChild, &authz::AUDIT_LOG).await?;
+
+        use db::schema::audit_log::dsl;
+        diesel::update(dsl::audit_log)
+            .filter(dsl::id.eq(entry_id))
+            .filter(dsl::time_completed.is_null())
+            .set(completion)
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        Ok(())
+    }
+
+    /// Lists the entries for requests received between `start_time` and
+    /// `end_time`, in the order in which they were received.
+    ///
+    /// If `authz_silo` is provided, only the requests made by that Silo's
+    /// users are listed.  Otherwise, every entry is.
+    pub async fn audit_log_list(
+        &self,
+        opctx: &OpContext,
+        authz_silo: Option<&authz::Silo>,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+        pagparams: &DataPageParams<'_, (DateTime<Utc>, Uuid)>,
+    ) -> ListResultVec<AuditLogEntry> {
+        use db::schema::audit_log::dsl;
+        let mut query = paginated_multicolumn(
+            dsl::audit_log,
+            (dsl::time_started, dsl::id),
+            pagparams,
+        );
+        match authz_silo {
+            Some(authz_silo) => {
+                opctx
+                    .authorize(authz::Action::ListChildren, authz_silo)
+                    .await?;
+                query = query.filter(dsl::actor_silo_id.eq(authz_silo.id()));
+            }
+            None => {
+                opctx
+                    .authorize(authz::Action::ListChildren, &authz::AUDIT_LOG)
+                    .await?;
+            }
+        }
+        if let Some(start_time) = start_time {
+            query = query.filter(dsl::time_started.ge(start_time));
+        }
+        if let Some(end_time) = end_time {
+            query = query.filter(dsl::time_started.lt(end_time));
+        }
+
+        query
+            .select(AuditLogEntry::as_select())
+            .load_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+}
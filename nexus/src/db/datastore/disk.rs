@@ -147,16 +147,68 @@ impl DataStore {
         .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
+    /// Attaches a disk to an instance which is not running.
+    ///
+    /// The disk moves directly to the "attached" state, since there is no
+    /// running VMM which must be told about it.
+    pub async fn instance_attach_disk(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        authz_disk: &authz::Disk,
+        max_disks: u32,
+    ) -> Result<(Instance, Disk), Error> {
+        self.instance_attach_disk_in_states(
+            opctx,
+            authz_instance,
+            authz_disk,
+            max_disks,
+            &[
+                api::external::InstanceState::Creating,
+                api::external::InstanceState::Stopped,
+            ],
+            api::external::DiskState::Attached(authz_instance.id()),
+        )
+        .await
+    }
+
+    /// Begins hot-plugging a disk into a running instance.
+    ///
+    /// The disk moves to the "attaching" state, reserving its slot on the
+    /// instance. The caller is responsible for asking the instance's sled
+    /// agent to complete the attachment.
+    pub async fn instance_hotplug_attach_disk(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        authz_disk: &authz::Disk,
+        max_disks: u32,
+    ) -> Result<(Instance, Disk), Error> {
+        self.instance_attach_disk_in_states(
+            opctx,
+            authz_instance,
+            authz_disk,
+            max_disks,
+            &[api::external::InstanceState::Running],
+            api::external::DiskState::Attaching(authz_instance.id()),
+        )
+        .await
+    }
+
     /// Attaches a disk to an instance, if both objects:
     /// - Exist
     /// - Are in valid states
     /// - Are under the maximum "attach count" threshold
-    pub async fn instance_attach_disk(
+    ///
+    /// On success, the disk moves to `attach_state`.
+    async fn instance_attach_disk_in_states(
         &self,
         opctx: &OpContext,
         authz_instance: &authz::Instance,
         authz_disk: &authz::Disk,
         max_disks: u32,
+        ok_to_attach_instance_states: &[api::external::InstanceState],
+        attach_state: api::external::DiskState,
     ) -> Result<(Instance, Disk), Error> {
         use db::schema::{disk, instance};
 
@@ -170,32 +222,27 @@ impl DataStore {
         let ok_to_attach_disk_state_labels: Vec<_> =
             ok_to_attach_disk_states.iter().map(|s| s.label()).collect();
 
-        // TODO(https://github.com/oxidecomputer/omicron/issues/811):
-        // This list of instance attach states is more restrictive than it
-        // plausibly could be.
-        //
-        // We currently only permit attaching disks to stopped instances.
-        let ok_to_attach_instance_states = vec![
-            db::model::InstanceState(api::external::InstanceState::Creating),
-            db::model::InstanceState(api::external::InstanceState::Stopped),
-        ];
+        let ok_to_attach_instance_state_values: Vec<_> =
+            ok_to_attach_instance_states
+                .iter()
+                .map(|s| db::model::InstanceState(*s))
+                .collect();
 
-        let attached_label =
-            api::external::DiskState::Attached(authz_instance.id()).label();
+        let attach_label = attach_state.label();
 
         let (instance, disk) = Instance::attach_resource(
             authz_instance.id(),
             authz_disk.id(),
             instance::table
                 .into_boxed()
-                .filter(instance::dsl::state.eq_any(ok_to_attach_instance_states)),
+                .filter(instance::dsl::state.eq_any(ok_to_attach_instance_state_values)),
             disk::table
                 .into_boxed()
                 .filter(disk::dsl::disk_state.eq_any(ok_to_attach_disk_state_labels)),
             max_disks,
             diesel::update(disk::dsl::disk)
                 .set((
-                    disk::dsl::disk_state.eq(attached_label),
+                    disk::dsl::disk_state.eq(attach_label),
                     disk::dsl::attach_instance_id.eq(authz_instance.id())
                 ))
         )
@@ -230,10 +277,11 @@ impl DataStore {
                         // why we did not attach.
                         api::external::DiskState::Creating |
                         api::external::DiskState::Detached => {
-                            match collection.runtime_state.state.state() {
+                            let instance_state =
+                                collection.runtime_state.state.state();
+                            match instance_state {
                                 // Ok-to-be-attached instance states:
-                                api::external::InstanceState::Creating |
-                                api::external::InstanceState::Stopped => {
+                                s if ok_to_attach_instance_states.contains(s) => {
                                     // The disk is ready to be attached, and the
                                     // instance is ready to be attached. Perhaps
                                     // we are at attachment capacity?
@@ -254,7 +302,7 @@ impl DataStore {
                                 _ => {
                                     Err(Error::invalid_request(&format!(
                                         "cannot attach disk to instance in {} state",
-                                        collection.runtime_state.state.state(),
+                                        instance_state,
                                     )))
                                 }
                             }
@@ -286,11 +334,54 @@ impl DataStore {
         Ok((instance, disk))
     }
 
+    /// Detaches a disk from an instance which is not running.
     pub async fn instance_detach_disk(
         &self,
         opctx: &OpContext,
         authz_instance: &authz::Instance,
         authz_disk: &authz::Disk,
+    ) -> Result<Disk, Error> {
+        self.instance_detach_disk_in_states(
+            opctx,
+            authz_instance,
+            authz_disk,
+            &[
+                api::external::InstanceState::Creating,
+                api::external::InstanceState::Stopped,
+                api::external::InstanceState::Failed,
+            ],
+            api::external::DiskState::Detached,
+        )
+        .await
+    }
+
+    /// Begins hot-unplugging a disk from a running instance.
+    ///
+    /// The disk moves to the "detaching" state, and remains associated with
+    /// the instance until its sled agent reports that the disk is detached.
+    pub async fn instance_hotplug_detach_disk(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        authz_disk: &authz::Disk,
+    ) -> Result<Disk, Error> {
+        self.instance_detach_disk_in_states(
+            opctx,
+            authz_instance,
+            authz_disk,
+            &[api::external::InstanceState::Running],
+            api::external::DiskState::Detaching(authz_instance.id()),
+        )
+        .await
+    }
+
+    async fn instance_detach_disk_in_states(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        authz_disk: &authz::Disk,
+        ok_to_detach_instance_states: &[api::external::InstanceState],
+        detach_state: api::external::DiskState,
     ) -> Result<Disk, Error> {
         use db::schema::{disk, instance};
 
@@ -302,32 +393,28 @@ impl DataStore {
         let ok_to_detach_disk_state_labels: Vec<_> =
             ok_to_detach_disk_states.iter().map(|s| s.label()).collect();
 
-        // TODO(https://github.com/oxidecomputer/omicron/issues/811):
-        // This list of instance detach states is more restrictive than it
-        // plausibly could be.
-        //
-        // We currently only permit detaching disks from stopped instances.
-        let ok_to_detach_instance_states = vec![
-            db::model::InstanceState(api::external::InstanceState::Creating),
-            db::model::InstanceState(api::external::InstanceState::Stopped),
-            db::model::InstanceState(api::external::InstanceState::Failed),
-        ];
+        let ok_to_detach_instance_state_values: Vec<_> =
+            ok_to_detach_instance_states
+                .iter()
+                .map(|s| db::model::InstanceState(*s))
+                .collect();
 
-        let detached_label = api::external::DiskState::Detached.label();
+        let detach_label = detach_state.label();
+        let detach_instance_id = detach_state.attached_instance_id().copied();
 
         let disk = Instance::detach_resource(
             authz_instance.id(),
             authz_disk.id(),
             instance::table
                 .into_boxed()
-                .filter(instance::dsl::state.eq_any(ok_to_detach_instance_states)),
+                .filter(instance::dsl::state.eq_any(ok_to_detach_instance_state_values)),
             disk::table
                 .into_boxed()
                 .filter(disk::dsl::disk_state.eq_any(ok_to_detach_disk_state_labels)),
             diesel::update(disk::dsl::disk)
                 .set((
-                    disk::dsl::disk_state.eq(detached_label),
-                    disk::dsl::attach_instance_id.eq(Option::<Uuid>::None)
+                    disk::dsl::disk_state.eq(detach_label),
+                    disk::dsl::attach_instance_id.eq(detach_instance_id)
                 ))
         )
         .detach_and_get_result_async(self.pool_authorized(opctx).await?)
@@ -360,10 +447,11 @@ impl DataStore {
                         // Ok-to-detach disk states: Inspect the state to infer
                         // why we did not detach.
                         api::external::DiskState::Attached(id) if id == authz_instance.id() => {
-                            match collection.runtime_state.state.state() {
+                            let instance_state =
+                                collection.runtime_state.state.state();
+                            match instance_state {
                                 // Ok-to-be-detached instance states:
-                                api::external::InstanceState::Creating |
-                                api::external::InstanceState::Stopped => {
+                                s if ok_to_detach_instance_states.contains(s) => {
                                     // We can't detach, but the error hasn't
                                     // helped us infer why.
                                     return Err(Error::internal_error(
@@ -374,7 +462,7 @@ impl DataStore {
                                 _ => {
                                     Err(Error::invalid_request(&format!(
                                         "cannot detach disk from instance in {} state",
-                                        collection.runtime_state.state.state(),
+                                        instance_state,
                                     )))
                                 }
                             }
@@ -453,6 +541,40 @@ impl DataStore {
         Ok(updated)
     }
 
+    /// Records a new size for a disk, along with the runtime state that ends
+    /// the resize.
+    ///
+    /// Like [`DataStore::disk_update_runtime`], the update is only applied if
+    /// `new_runtime` has a newer generation than the disk's current runtime
+    /// state. The (possibly already-updated) disk is returned either way.
+    pub async fn disk_update_size(
+        &self,
+        opctx: &OpContext,
+        authz_disk: &authz::Disk,
+        new_size: db::model::ByteCount,
+        new_runtime: &DiskRuntimeState,
+    ) -> Result<Disk, Error> {
+        opctx.authorize(authz::Action::Modify, authz_disk).await?;
+
+        let disk_id = authz_disk.id();
+        use db::schema::disk::dsl;
+        diesel::update(dsl::disk)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::id.eq(disk_id))
+            .filter(dsl::state_generation.lt(new_runtime.gen))
+            .set((dsl::size_bytes.eq(new_size), new_runtime.clone()))
+            .check_if_exists::<Disk>(disk_id)
+            .execute_and_check(self.pool_authorized(opctx).await?)
+            .await
+            .map(|r| r.found)
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_disk),
+                )
+            })
+    }
+
     /// Fetches information about a Disk that the caller has previously fetched
     ///
     /// The only difference between this function and a new fetch by id is that
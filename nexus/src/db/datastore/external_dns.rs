@@ -0,0 +1,222 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! [`DataStore`] methods used to compute the external DNS zone.
+
+use super::DataStore;
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::error::public_error_from_diesel_pool;
+use crate::db::error::ErrorHandler;
+use crate::db::model::IpKind;
+use crate::db::model::Name;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use diesel::prelude::*;
+use ipnetwork::IpNetwork;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::LookupType;
+use omicron_common::api::external::ResourceType;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+/// The state of the control plane that Nexus publishes in external DNS.
+#[derive(Clone, Debug)]
+pub struct ExternalDnsState {
+    /// External addresses of the Nexus API servers.
+    pub nexus_ips: Vec<IpAddr>,
+    /// Names of all Silos.
+    pub silos: Vec<Name>,
+    /// All Instances with at least one ephemeral or floating IP.
+    pub instances: Vec<ExternalDnsInstance>,
+}
+
+/// An Instance reachable from outside the rack.
+#[derive(Clone, Debug)]
+pub struct ExternalDnsInstance {
+    pub silo: Name,
+    pub organization: Name,
+    pub project: Name,
+    pub instance: Name,
+    pub ips: Vec<IpAddr>,
+}
+
+impl DataStore {
+    /// Bumps the rack's external DNS generation, returning the new value.
+    ///
+    /// Each call returns a distinct generation, so no two configurations
+    /// published under these generations can differ in contents.  That is
+    /// all the generation guarantees.  In particular, it does not order the
+    /// state that callers read and publish: a Nexus that bumps the generation
+    /// first may read the state with [`DataStore::external_dns_state`] after
+    /// one that bumps it later, and then have its newer view rejected by DNS
+    /// servers that already have the higher generation.  Callers that see
+    /// such a rejection must read the state again and publish it under a new
+    /// generation.
+    pub async fn external_dns_generation_bump(
+        &self,
+        opctx: &OpContext,
+        rack_id: Uuid,
+    ) -> Result<u64, Error> {
+        opctx.authorize(authz::Action::Modify, &authz::FLEET).await?;
+        let pool = self.pool_authorized(opctx).await?;
+
+        let generation = {
+            use db::schema::rack::dsl;
+            diesel::update(dsl::rack)
+                .filter(dsl::id.eq(rack_id))
+                .set(
+                    dsl::external_dns_generation
+                        .eq(dsl::external_dns_generation + 1),
+                )
+                .returning(dsl::external_dns_generation)
+                .get_result_async::<i64>(pool)
+                .await
+                .map_err(|e| {
+                    public_error_from_diesel_pool(
+                        e,
+                        ErrorHandler::NotFoundByLookup(
+                            ResourceType::Rack,
+                            LookupType::ById(rack_id),
+                        ),
+                    )
+                })?
+        };
+        u64::try_from(generation).map_err(|_| {
+            Error::internal_error(&format!(
+                "invalid external DNS generation: {}",
+                generation
+            ))
+        })
+    }
+
+    /// Reads the state that should be published in external DNS.
+    pub async fn external_dns_state(
+        &self,
+        opctx: &OpContext,
+    ) -> Result<ExternalDnsState, Error> {
+        opctx.authorize(authz::Action::Read, &authz::FLEET).await?;
+        let pool = self.pool_authorized(opctx).await?;
+        let server_error =
+            |e| public_error_from_diesel_pool(e, ErrorHandler::Server);
+
+        // TODO-scalability Everything below is loaded in one go rather than
+        // paginated.  The number of Silos and externally-reachable Instances
+        // in a rack is expected to be modest.
+        let nexus_ips = {
+            use db::schema::external_ip::dsl;
+            use db::schema::nexus_service::dsl as nexus_dsl;
+            let ids = nexus_dsl::nexus_service
+                .select(nexus_dsl::external_ip_id)
+                .load_async::<Uuid>(pool)
+                .await
+                .map_err(server_error)?;
+            dsl::external_ip
+                .filter(dsl::id.eq_any(ids))
+                .filter(dsl::time_deleted.is_null())
+                .select(dsl::ip)
+                .load_async::<IpNetwork>(pool)
+                .await
+                .map_err(server_error)?
+                .into_iter()
+                .map(|ip| ip.ip())
+                .collect()
+        };
+
+        let silos: BTreeMap<Uuid, Name> = {
+            use db::schema::silo::dsl;
+            dsl::silo
+                .filter(dsl::time_deleted.is_null())
+                .select((dsl::id, dsl::name))
+                .load_async::<(Uuid, Name)>(pool)
+                .await
+                .map_err(server_error)?
+                .into_iter()
+                .collect()
+        };
+
+        let mut instance_ips: BTreeMap<Uuid, Vec<IpAddr>> = BTreeMap::new();
+        {
+            use db::schema::external_ip::dsl;
+            let ips = dsl::external_ip
+                .filter(dsl::instance_id.is_not_null())
+                .filter(dsl::kind.ne(IpKind::SNat))
+                .filter(dsl::time_deleted.is_null())
+                .select((dsl::instance_id, dsl::ip))
+                .load_async::<(Option<Uuid>, IpNetwork)>(pool)
+                .await
+                .map_err(server_error)?;
+            for (instance_id, ip) in ips {
+                if let Some(instance_id) = instance_id {
+                    instance_ips.entry(instance_id).or_default().push(ip.ip());
+                }
+            }
+        }
+
+        let instances = {
+            use db::schema::instance::dsl;
+            dsl::instance
+                .filter(dsl::id.eq_any(instance_ips.keys().copied()))
+                .filter(dsl::time_deleted.is_null())
+                .select((dsl::id, dsl::name, dsl::project_id))
+                .load_async::<(Uuid, Name, Uuid)>(pool)
+                .await
+                .map_err(server_error)?
+        };
+        let projects: BTreeMap<Uuid, (Name, Uuid)> = {
+            use db::schema::project::dsl;
+            dsl::project
+                .filter(dsl::id.eq_any(instances.iter().map(|(.., id)| *id)))
+                .filter(dsl::time_deleted.is_null())
+                .select((dsl::id, dsl::name, dsl::organization_id))
+                .load_async::<(Uuid, Name, Uuid)>(pool)
+                .await
+                .map_err(server_error)?
+                .into_iter()
+                .map(|(id, name, organization_id)| {
+                    (id, (name, organization_id))
+                })
+                .collect()
+        };
+        let organizations: BTreeMap<Uuid, (Name, Uuid)> = {
+            use db::schema::organization::dsl;
+            dsl::organization
+                .filter(dsl::id.eq_any(projects.values().map(|(_, id)| *id)))
+                .filter(dsl::time_deleted.is_null())
+                .select((dsl::id, dsl::name, dsl::silo_id))
+                .load_async::<(Uuid, Name, Uuid)>(pool)
+                .await
+                .map_err(server_error)?
+                .into_iter()
+                .map(|(id, name, silo_id)| (id, (name, silo_id)))
+                .collect()
+        };
+
+        // Anything whose parent was deleted while we were reading is dropped;
+        // a later generation will not include it either.
+        let instances = instances
+            .into_iter()
+            .filter_map(|(id, instance, project_id)| {
+                let (project, organization_id) = projects.get(&project_id)?;
+                let (organization, silo_id) =
+                    organizations.get(organization_id)?;
+                let silo = silos.get(silo_id)?;
+                Some(ExternalDnsInstance {
+                    silo: silo.clone(),
+                    organization: organization.clone(),
+                    project: project.clone(),
+                    instance,
+                    ips: instance_ips.remove(&id).unwrap_or_default(),
+                })
+            })
+            .collect();
+
+        Ok(ExternalDnsState {
+            nexus_ips,
+            silos: silos.into_values().collect(),
+            instances,
+        })
+    }
+}
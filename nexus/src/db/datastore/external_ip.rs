@@ -5,15 +5,19 @@
 //! [`DataStore`] methods on [`ExternalIp`]s.
 
 use super::DataStore;
+use crate::app::MAX_EXTERNAL_IPS_PER_INSTANCE;
 use crate::authz;
 use crate::context::OpContext;
 use crate::db;
 use crate::db::error::public_error_from_diesel_pool;
 use crate::db::error::ErrorHandler;
+use crate::db::error::TransactionError;
 use crate::db::model::ExternalIp;
+use crate::db::model::FloatingIp;
 use crate::db::model::IncompleteExternalIp;
 use crate::db::model::IpKind;
 use crate::db::model::Name;
+use crate::db::pagination::paginated;
 use crate::db::pool::DbConnection;
 use crate::db::queries::external_ip::NextExternalIp;
 use crate::db::update_and_check::UpdateAndCheck;
@@ -21,11 +25,18 @@ use crate::db::update_and_check::UpdateStatus;
 use async_bb8_diesel::{AsyncRunQueryDsl, PoolError};
 use chrono::Utc;
 use diesel::prelude::*;
+use nexus_types::external_api::params;
 use nexus_types::identity::Resource;
+use omicron_common::api::external::http_pagination::PaginatedBy;
 use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DeleteResult;
 use omicron_common::api::external::Error;
+use omicron_common::api::external::ListResultVec;
 use omicron_common::api::external::LookupResult;
 use omicron_common::api::external::Name as ExternalName;
+use omicron_common::api::external::ResourceType;
+use omicron_common::api::external::UpdateResult;
+use ref_cast::RefCast;
 use std::net::IpAddr;
 use std::str::FromStr;
 use uuid::Uuid;
@@ -68,6 +79,50 @@ impl DataStore {
         self.allocate_external_ip(opctx, data).await
     }
 
+    /// Allocate a Floating IP address in the provided project.
+    ///
+    /// The address is taken from the named IP Pool, or the default pool if
+    /// none is provided. If the parameters request a specific address, that
+    /// exact address is reserved, and it must be part of the selected pool.
+    pub async fn allocate_floating_ip(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        ip_id: Uuid,
+        params: &params::FloatingIpCreate,
+    ) -> CreateResult<ExternalIp> {
+        opctx.authorize(authz::Action::CreateChild, authz_project).await?;
+
+        let name = params.pool.clone().map(Name).unwrap_or_else(|| {
+            Name(ExternalName::from_str("default").unwrap())
+        });
+        let (.., pool) = self
+            .ip_pools_fetch_for(opctx, authz::Action::CreateChild, &name)
+            .await?;
+        let pool_id = pool.identity.id;
+
+        let name = Name(params.identity.name.clone());
+        let description = &params.identity.description;
+        let data = match params.address {
+            Some(address) => IncompleteExternalIp::for_floating_explicit(
+                ip_id,
+                &name,
+                description,
+                authz_project.id(),
+                pool_id,
+                address,
+            ),
+            None => IncompleteExternalIp::for_floating(
+                ip_id,
+                &name,
+                description,
+                authz_project.id(),
+                pool_id,
+            ),
+        };
+        self.allocate_external_ip(opctx, data).await
+    }
+
     /// Allocates an IP address for internal service usage.
     pub async fn allocate_service_ip(
         &self,
@@ -101,6 +156,9 @@ impl DataStore {
         PoolError: From<ConnErr>,
     {
         let explicit_ip = data.explicit_ip().is_some();
+        // Only Floating IPs are named, and their names must be unique within
+        // their project.
+        let name = data.name().clone();
         NextExternalIp::new(data).get_result_async(conn).await.map_err(|e| {
             use async_bb8_diesel::ConnectionError::Query;
             use async_bb8_diesel::PoolError::Connection;
@@ -118,7 +176,16 @@ impl DataStore {
                         )
                     }
                 }
-                _ => crate::db::queries::external_ip::from_pool(e),
+                _ => {
+                    let handler = match name {
+                        Some(ref name) => ErrorHandler::Conflict(
+                            ResourceType::FloatingIp,
+                            name.as_str(),
+                        ),
+                        None => ErrorHandler::Server,
+                    };
+                    crate::db::queries::external_ip::from_pool(e, handler)
+                }
             }
         })
     }
@@ -175,8 +242,9 @@ impl DataStore {
     /// This method returns the number of records deleted, rather than the usual
     /// `DeleteResult`. That's mostly useful for tests, but could be important
     /// if callers have some invariants they'd like to check.
-    // TODO-correctness: This can't be used for Floating IPs, we'll need a
-    // _detatch_ method for that.
+    ///
+    /// Floating IPs outlive the instances they're attached to, and so are not
+    /// deleted here. See [`Self::detach_floating_ips_by_instance_id`].
     pub async fn deallocate_external_ip_by_instance_id(
         &self,
         opctx: &OpContext,
@@ -194,6 +262,223 @@ impl DataStore {
             .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
+    /// Detach all Floating IPs attached to the provided instance ID.
+    ///
+    /// Like [`Self::deallocate_external_ip_by_instance_id`], this returns the
+    /// number of records modified.
+    pub async fn detach_floating_ips_by_instance_id(
+        &self,
+        opctx: &OpContext,
+        instance_id: Uuid,
+    ) -> Result<usize, Error> {
+        use db::schema::external_ip::dsl;
+        diesel::update(dsl::external_ip)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::instance_id.eq(instance_id))
+            .filter(dsl::kind.eq(IpKind::Floating))
+            .set((
+                dsl::instance_id.eq(Option::<Uuid>::None),
+                dsl::time_modified.eq(Utc::now()),
+            ))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// List the Floating IPs in the provided project.
+    pub async fn floating_ip_list(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        pagparams: &PaginatedBy<'_>,
+    ) -> ListResultVec<FloatingIp> {
+        opctx.authorize(authz::Action::ListChildren, authz_project).await?;
+
+        use db::schema::floating_ip::dsl;
+        match pagparams {
+            PaginatedBy::Id(pagparams) => {
+                paginated(dsl::floating_ip, dsl::id, &pagparams)
+            }
+            PaginatedBy::Name(pagparams) => paginated(
+                dsl::floating_ip,
+                dsl::name,
+                &pagparams.map_name(|n| Name::ref_cast(n)),
+            ),
+        }
+        .filter(dsl::time_deleted.is_null())
+        .filter(dsl::project_id.eq(authz_project.id()))
+        .select(FloatingIp::as_select())
+        .load_async::<FloatingIp>(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Attach a Floating IP to an instance.
+    ///
+    /// This only records the attachment in the database. Callers are
+    /// responsible for checking that they may modify the instance, and for
+    /// propagating the change to the sled on which the instance runs. The
+    /// instance need not exist yet, which allows attaching Floating IPs while
+    /// an instance is being created. Attaching a Floating IP to the instance
+    /// it is already attached to succeeds, to support idempotency.
+    ///
+    /// Attachment fails if the instance already has as many external addresses
+    /// as it may have. That's checked in the same transaction that records the
+    /// attachment, so concurrent attachments can't exceed the limit.
+    pub async fn floating_ip_attach(
+        &self,
+        opctx: &OpContext,
+        authz_fip: &authz::FloatingIp,
+        instance_id: Uuid,
+    ) -> UpdateResult<FloatingIp> {
+        opctx.authorize(authz::Action::Modify, authz_fip).await?;
+
+        type TxnError = TransactionError<Error>;
+        let fip_id = authz_fip.id();
+        let result = self
+            .pool_authorized(opctx)
+            .await?
+            .transaction_async(|mut conn| async move {
+                use db::schema::external_ip::dsl;
+
+                let n_external_ips: i64 = dsl::external_ip
+                    .filter(dsl::instance_id.eq(instance_id))
+                    .filter(dsl::kind.ne(IpKind::SNat))
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.ne(fip_id))
+                    .count()
+                    .get_result_async(&mut conn)
+                    .await?;
+                if n_external_ips >= MAX_EXTERNAL_IPS_PER_INSTANCE as i64 {
+                    return Err(TxnError::CustomError(Error::invalid_request(
+                        &format!(
+                            "An instance may not have more than {} external \
+                            IP addresses",
+                            MAX_EXTERNAL_IPS_PER_INSTANCE,
+                        ),
+                    )));
+                }
+
+                let result = diesel::update(dsl::external_ip)
+                    .filter(dsl::id.eq(fip_id))
+                    .filter(dsl::kind.eq(IpKind::Floating))
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::instance_id.is_null())
+                    .set((
+                        dsl::instance_id.eq(Some(instance_id)),
+                        dsl::time_modified.eq(Utc::now()),
+                    ))
+                    .check_if_exists::<ExternalIp>(fip_id)
+                    .execute_and_check(&mut conn)
+                    .await?;
+                Ok(result)
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e,
+                TxnError::Pool(e) => public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_fip),
+                ),
+            })?;
+        match result.status {
+            UpdateStatus::Updated => FloatingIp::try_from(result.found),
+            UpdateStatus::NotUpdatedButExists => {
+                let found = result.found;
+                if found.time_deleted.is_some() {
+                    Err(authz_fip.not_found())
+                } else if found.instance_id == Some(instance_id) {
+                    FloatingIp::try_from(found)
+                } else {
+                    Err(Error::invalid_request(
+                        "Floating IP is already attached to an instance",
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Detach a Floating IP from the instance it is attached to.
+    ///
+    /// As with [`Self::floating_ip_attach`], checking that the instance may be
+    /// modified and propagating the change to the instance's sled are the
+    /// caller's responsibility. Detaching a Floating IP which is not attached
+    /// to any instance succeeds.
+    pub async fn floating_ip_detach(
+        &self,
+        opctx: &OpContext,
+        authz_fip: &authz::FloatingIp,
+    ) -> UpdateResult<FloatingIp> {
+        opctx.authorize(authz::Action::Modify, authz_fip).await?;
+
+        use db::schema::external_ip::dsl;
+        let fip_id = authz_fip.id();
+        let result = diesel::update(dsl::external_ip)
+            .filter(dsl::id.eq(fip_id))
+            .filter(dsl::kind.eq(IpKind::Floating))
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::instance_id.is_not_null())
+            .set((
+                dsl::instance_id.eq(Option::<Uuid>::None),
+                dsl::time_modified.eq(Utc::now()),
+            ))
+            .check_if_exists::<ExternalIp>(fip_id)
+            .execute_and_check(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_fip),
+                )
+            })?;
+        if result.found.time_deleted.is_some() {
+            return Err(authz_fip.not_found());
+        }
+        FloatingIp::try_from(result.found)
+    }
+
+    /// Delete a Floating IP, returning its address to the IP Pool.
+    ///
+    /// Floating IPs which are attached to an instance cannot be deleted.
+    pub async fn floating_ip_delete(
+        &self,
+        opctx: &OpContext,
+        authz_fip: &authz::FloatingIp,
+    ) -> DeleteResult {
+        opctx.authorize(authz::Action::Delete, authz_fip).await?;
+
+        use db::schema::external_ip::dsl;
+        let fip_id = authz_fip.id();
+        let result = diesel::update(dsl::external_ip)
+            .filter(dsl::id.eq(fip_id))
+            .filter(dsl::kind.eq(IpKind::Floating))
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::instance_id.is_null())
+            .set(dsl::time_deleted.eq(Utc::now()))
+            .check_if_exists::<ExternalIp>(fip_id)
+            .execute_and_check(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_fip),
+                )
+            })?;
+        match result.status {
+            UpdateStatus::Updated => Ok(()),
+            UpdateStatus::NotUpdatedButExists => {
+                if result.found.time_deleted.is_some() {
+                    Err(authz_fip.not_found())
+                } else {
+                    Err(Error::invalid_request(
+                        "Floating IP cannot be deleted while attached to an \
+                        instance",
+                    ))
+                }
+            }
+        }
+    }
+
     /// Fetch all external IP addresses of any kind for the provided instance
     pub async fn instance_lookup_external_ips(
         &self,
@@ -0,0 +1,109 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! [`DataStore`] methods on project-scoped [`Image`]s.
+
+use super::DataStore;
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::collection_insert::AsyncInsertError;
+use crate::db::collection_insert::DatastoreCollection;
+use crate::db::error::public_error_from_diesel_pool;
+use crate::db::error::ErrorHandler;
+use crate::db::identity::Resource;
+use crate::db::model::Image;
+use crate::db::model::Name;
+use crate::db::model::Project;
+use crate::db::pagination::paginated;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::Utc;
+use diesel::prelude::*;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DataPageParams;
+use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::LookupType;
+use omicron_common::api::external::ResourceType;
+
+impl DataStore {
+    pub async fn project_image_list(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<Image> {
+        opctx.authorize(authz::Action::ListChildren, authz_project).await?;
+
+        use db::schema::image::dsl;
+        paginated(dsl::image, dsl::name, pagparams)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::project_id.eq(authz_project.id()))
+            .select(Image::as_select())
+            .load_async::<Image>(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    pub async fn project_image_create(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        image: Image,
+    ) -> CreateResult<Image> {
+        opctx.authorize(authz::Action::CreateChild, authz_project).await?;
+
+        use db::schema::image::dsl;
+        let project_id = image.project_id;
+        let name = image.name().clone();
+        Project::insert_resource(
+            project_id,
+            diesel::insert_into(dsl::image).values(image),
+        )
+        .insert_and_get_result_async(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| match e {
+            AsyncInsertError::CollectionNotFound => Error::ObjectNotFound {
+                type_name: ResourceType::Project,
+                lookup_type: LookupType::ById(project_id),
+            },
+            AsyncInsertError::DatabaseError(e) => {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(ResourceType::Image, name.as_str()),
+                )
+            }
+        })
+    }
+
+    /// Soft-delete a project image.
+    ///
+    /// This only removes the image record. The caller is responsible for
+    /// cleaning up the image's volume.
+    pub async fn project_image_delete(
+        &self,
+        opctx: &OpContext,
+        authz_image: &authz::Image,
+    ) -> DeleteResult {
+        opctx.authorize(authz::Action::Delete, authz_image).await?;
+
+        use db::schema::image::dsl;
+        let now = Utc::now();
+        let image_id = authz_image.id();
+        diesel::update(dsl::image)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::id.eq(image_id))
+            .set(dsl::time_deleted.eq(now))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_image),
+                )
+            })?;
+        Ok(())
+    }
+}
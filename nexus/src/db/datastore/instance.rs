@@ -15,6 +15,7 @@ use crate::db::collection_insert::AsyncInsertError;
 use crate::db::collection_insert::DatastoreCollection;
 use crate::db::error::public_error_from_diesel_pool;
 use crate::db::error::ErrorHandler;
+use crate::db::error::TransactionError;
 use crate::db::identity::Resource;
 use crate::db::lookup::LookupPath;
 use crate::db::model::Instance;
@@ -24,6 +25,7 @@ use crate::db::model::Project;
 use crate::db::pagination::paginated;
 use crate::db::update_and_check::UpdateAndCheck;
 use crate::db::update_and_check::UpdateStatus;
+use async_bb8_diesel::AsyncConnection;
 use async_bb8_diesel::AsyncRunQueryDsl;
 use chrono::Utc;
 use diesel::prelude::*;
@@ -36,6 +38,7 @@ use omicron_common::api::external::ListResultVec;
 use omicron_common::api::external::LookupResult;
 use omicron_common::api::external::LookupType;
 use omicron_common::api::external::ResourceType;
+use omicron_common::api::external::UpdateResult;
 use omicron_common::bail_unless;
 use ref_cast::RefCast;
 use uuid::Uuid;
@@ -181,9 +184,12 @@ impl DataStore {
             .filter(dsl::time_deleted.is_null())
             .filter(dsl::id.eq(*instance_id))
             .filter(dsl::state_generation.lt(new_runtime.gen))
+            // While the Instance is migrating, only its migration target may
+            // update it; otherwise only its active Propolis server may do so.
             .filter(
                 dsl::migration_id
                     .is_null()
+                    .and(dsl::active_propolis_id.eq(new_runtime.propolis_id))
                     .or(dsl::target_propolis_id.eq(new_runtime.propolis_id)),
             )
             .set(new_runtime.clone())
@@ -207,6 +213,133 @@ impl DataStore {
         Ok(updated)
     }
 
+    /// Abandons the given in-progress migration of an Instance, recording
+    /// `new_runtime` as reported by the sled agent on the migration's source.
+    ///
+    /// The generation number of `new_runtime` is not compared to the one in
+    /// the database: the latter may have been advanced by the migration's
+    /// target, but it's the source's lineage of runtime states that remains
+    /// authoritative from here on.
+    ///
+    /// Returns false if the Instance is no longer migrating as part of
+    /// `migration_id`.
+    pub async fn instance_abandon_migration(
+        &self,
+        instance_id: &Uuid,
+        migration_id: &Uuid,
+        new_runtime: &InstanceRuntimeState,
+    ) -> Result<bool, Error> {
+        use db::schema::instance::dsl;
+
+        let updated = diesel::update(dsl::instance)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::id.eq(*instance_id))
+            .filter(dsl::migration_id.eq(*migration_id))
+            .set(new_runtime.clone())
+            .check_if_exists::<Instance>(*instance_id)
+            .execute_and_check(self.pool())
+            .await
+            .map(|r| match r.status {
+                UpdateStatus::Updated => true,
+                UpdateStatus::NotUpdatedButExists => false,
+            })
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::Instance,
+                        LookupType::ById(*instance_id),
+                    ),
+                )
+            })?;
+
+        Ok(updated)
+    }
+
+    /// Changes the number of CPUs and the amount of memory of a stopped
+    /// Instance.
+    ///
+    /// The Instance and the CPU and RAM provisioning counters of every
+    /// collection containing it are updated in a single transaction. The
+    /// update is refused if the Instance is no longer stopped, or if its
+    /// runtime state changed since `db_instance` was fetched.
+    pub async fn instance_update_resources(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        db_instance: &Instance,
+        ncpus: api::external::InstanceCpuCount,
+        memory: api::external::ByteCount,
+    ) -> UpdateResult<Instance> {
+        opctx.authorize(authz::Action::Modify, authz_instance).await?;
+
+        use db::schema::instance::dsl;
+
+        let stopped = db::model::InstanceState::new(
+            api::external::InstanceState::Stopped,
+        );
+        let instance_id = authz_instance.id();
+        let project_id = db_instance.project_id;
+        let old_runtime = db_instance.runtime().clone();
+        let old_cpus = i64::from(&old_runtime.ncpus.0);
+        let new_cpus = i64::from(&ncpus);
+        let new_memory = db::model::ByteCount::from(memory);
+
+        type TxnError = TransactionError<Error>;
+        self.pool_authorized(opctx)
+            .await?
+            .transaction_async(|conn| async move {
+                let updated: Vec<Instance> = diesel::update(dsl::instance)
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(instance_id))
+                    .filter(dsl::state.eq(stopped))
+                    .filter(dsl::state_generation.eq(old_runtime.gen))
+                    .set((
+                        dsl::ncpus.eq(db::model::InstanceCpuCount(ncpus)),
+                        dsl::memory.eq(new_memory),
+                        dsl::state_generation.eq(db::model::Generation::from(
+                            old_runtime.gen.next(),
+                        )),
+                        dsl::time_modified.eq(Utc::now()),
+                    ))
+                    .returning(Instance::as_returning())
+                    .get_results_async(&conn)
+                    .await?;
+                let instance = match updated.into_iter().next() {
+                    Some(instance) => instance,
+                    None => {
+                        return Err(TxnError::CustomError(
+                            Error::invalid_request(
+                                "instance must be stopped to be resized, \
+                                and may not be modified concurrently",
+                            ),
+                        ))
+                    }
+                };
+
+                self.virtual_provisioning_collection_resize_instance_on_connection(
+                    &conn,
+                    instance_id,
+                    project_id,
+                    old_cpus,
+                    old_runtime.memory,
+                    new_cpus,
+                    new_memory,
+                )
+                .await?;
+
+                Ok(instance)
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e,
+                TxnError::Pool(e) => public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_instance),
+                ),
+            })
+    }
+
     pub async fn project_delete_instance(
         &self,
         opctx: &OpContext,
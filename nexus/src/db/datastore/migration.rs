@@ -0,0 +1,184 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! [`DataStore`] methods on [`Migration`]s.
+
+use super::DataStore;
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::error::public_error_from_diesel_pool;
+use crate::db::error::ErrorHandler;
+use crate::db::model::Migration;
+use crate::db::model::MigrationPhase;
+use crate::db::model::MigrationState;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::Utc;
+use diesel::prelude::*;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::LookupType;
+use omicron_common::api::external::ResourceType;
+use omicron_common::api::external::UpdateResult;
+use uuid::Uuid;
+
+impl DataStore {
+    /// Records the start of a migration of the given Instance.
+    pub async fn migration_insert(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        migration: Migration,
+    ) -> CreateResult<Migration> {
+        opctx.authorize(authz::Action::Modify, authz_instance).await?;
+        use db::schema::migration::dsl;
+        // The record may already exist if this is a replay of the saga
+        // action that created it, in which case it's returned unchanged.
+        let migration_id = migration.id;
+        diesel::insert_into(dsl::migration)
+            .values(migration)
+            .on_conflict(dsl::id)
+            .do_nothing()
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        self.migration_fetch(opctx, authz_instance, migration_id).await
+    }
+
+    /// Lists the migrations of the given Instance, oldest first, including
+    /// finished ones.
+    pub async fn instance_list_migrations(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+    ) -> ListResultVec<Migration> {
+        opctx.authorize(authz::Action::Read, authz_instance).await?;
+        use db::schema::migration::dsl;
+        dsl::migration
+            .filter(dsl::instance_id.eq(authz_instance.id()))
+            .order(dsl::time_created.asc())
+            .select(Migration::as_select())
+            .load_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Fetches a migration of the given Instance.
+    pub async fn migration_fetch(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        migration_id: Uuid,
+    ) -> LookupResult<Migration> {
+        opctx.authorize(authz::Action::Read, authz_instance).await?;
+        use db::schema::migration::dsl;
+        dsl::migration
+            .filter(dsl::id.eq(migration_id))
+            .filter(dsl::instance_id.eq(authz_instance.id()))
+            .select(Migration::as_select())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::Migration,
+                        LookupType::ById(migration_id),
+                    ),
+                )
+            })
+    }
+
+    /// Moves a migration which has not yet finished into `state`.
+    ///
+    /// Returns false if the migration had already finished, in which case it
+    /// is left as it was.
+    pub async fn migration_update_state(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        migration_id: Uuid,
+        state: MigrationState,
+    ) -> UpdateResult<bool> {
+        opctx.authorize(authz::Action::Modify, authz_instance).await?;
+        use db::schema::migration::dsl;
+        let now = Utc::now();
+        let time_finished = if state.is_finished() { Some(now) } else { None };
+        let updated = diesel::update(dsl::migration)
+            .filter(dsl::id.eq(migration_id))
+            .filter(dsl::instance_id.eq(authz_instance.id()))
+            .filter(
+                dsl::state
+                    .eq(MigrationState::Pending)
+                    .or(dsl::state.eq(MigrationState::InProgress)),
+            )
+            .set((
+                dsl::state.eq(state),
+                dsl::time_modified.eq(now),
+                dsl::time_finished.eq(time_finished),
+            ))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        Ok(updated != 0)
+    }
+
+    /// Records that a migration which has not yet finished has reached
+    /// `phase`.
+    ///
+    /// The phase of a finished migration is left as the last one it reached.
+    pub async fn migration_update_phase(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        migration_id: Uuid,
+        phase: MigrationPhase,
+    ) -> UpdateResult<bool> {
+        opctx.authorize(authz::Action::Modify, authz_instance).await?;
+        use db::schema::migration::dsl;
+        let updated = diesel::update(dsl::migration)
+            .filter(dsl::id.eq(migration_id))
+            .filter(dsl::instance_id.eq(authz_instance.id()))
+            .filter(
+                dsl::state
+                    .eq(MigrationState::Pending)
+                    .or(dsl::state.eq(MigrationState::InProgress)),
+            )
+            .filter(dsl::phase.ne(phase))
+            .set((dsl::phase.eq(phase), dsl::time_modified.eq(Utc::now())))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        Ok(updated != 0)
+    }
+
+    /// Lists the unfinished migrations away from a sled.
+    pub async fn sled_list_active_migrations(
+        &self,
+        opctx: &OpContext,
+        authz_sled: &authz::Sled,
+    ) -> ListResultVec<Migration> {
+        opctx.authorize(authz::Action::ListChildren, authz_sled).await?;
+        use db::schema::migration::dsl;
+        dsl::migration
+            .filter(dsl::source_sled_id.eq(authz_sled.id()))
+            .filter(
+                dsl::state
+                    .eq(MigrationState::Pending)
+                    .or(dsl::state.eq(MigrationState::InProgress)),
+            )
+            .order(dsl::time_created.asc())
+            .select(Migration::as_select())
+            .load_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+}
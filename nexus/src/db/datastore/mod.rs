@@ -41,16 +41,22 @@ use std::net::Ipv6Addr;
 use std::sync::Arc;
 use uuid::Uuid;
 
+mod affinity_group;
+mod alert_rule;
+mod audit_log;
 mod certificate;
 mod console_session;
 mod dataset;
 mod device_auth;
 mod disk;
+mod external_dns;
 mod external_ip;
 mod global_image;
 mod identity_provider;
+mod image;
 mod instance;
 mod ip_pool;
+mod migration;
 mod network_interface;
 mod organization;
 mod oximeter;
@@ -74,6 +80,8 @@ mod volume;
 mod vpc;
 mod zpool;
 
+pub use external_dns::ExternalDnsInstance;
+pub use external_dns::ExternalDnsState;
 pub use virtual_provisioning_collection::StorageType;
 pub use volume::CrucibleResources;
 
@@ -275,7 +283,7 @@ mod test {
     use crate::db::model::{
         BlockSize, ComponentUpdate, ComponentUpdateIdentity, ConsoleSession,
         Dataset, DatasetKind, ExternalIp, Project, Rack, Region, Service,
-        ServiceKind, SiloUser, Sled, SshKey, SystemUpdate,
+        ServiceKind, SiloUser, Sled, SledProvisionState, SshKey, SystemUpdate,
         UpdateableComponentType, VpcSubnet, Zpool,
     };
     use crate::db::queries::vpc_subnet::FilterConflictingVpcSubnetRangesQuery;
@@ -761,6 +769,100 @@ mod test {
         logctx.cleanup_successful();
     }
 
+    #[tokio::test]
+    async fn test_region_allocation_skips_cordoned_sleds() {
+        let logctx =
+            dev::test_setup_log("test_region_allocation_skips_cordoned_sleds");
+        let mut db = test_setup_database(&logctx.log).await;
+        let cfg = db::Config { url: db.pg_config().clone() };
+        let pool = db::Pool::new(&cfg);
+        let datastore = Arc::new(DataStore::new(Arc::new(pool)));
+        let opctx =
+            OpContext::for_tests(logctx.log.new(o!()), datastore.clone());
+
+        // Create two sleds, each with a zpool full of datasets, and cordon
+        // one of them.
+        let bogus_addr = SocketAddrV6::new(Ipv6Addr::LOCALHOST, 8080, 0, 0);
+        let mut zpool_ids = vec![];
+        for _ in 0..2 {
+            let sled_id = create_test_sled(&datastore).await;
+            let zpool_id = create_test_zpool(&datastore, sled_id).await;
+            for _ in 0..REGION_REDUNDANCY_THRESHOLD {
+                let dataset = Dataset::new(
+                    Uuid::new_v4(),
+                    zpool_id,
+                    bogus_addr,
+                    DatasetKind::Crucible,
+                );
+                datastore.dataset_upsert(dataset).await.unwrap();
+            }
+            zpool_ids.push((sled_id, zpool_id));
+        }
+        let (cordoned_sled_id, _) = zpool_ids[0];
+        let (_, active_zpool_id) = zpool_ids[1];
+        let authz_sled = authz::Sled::new(
+            authz::FLEET,
+            cordoned_sled_id,
+            LookupType::ById(cordoned_sled_id),
+        );
+        datastore
+            .sled_set_provision_state(
+                &opctx,
+                &authz_sled,
+                SledProvisionState::Cordoned,
+            )
+            .await
+            .unwrap();
+
+        // All the regions land on the sled which is still active.
+        let params = create_test_disk_create_params(
+            "disk1",
+            ByteCount::from_mebibytes_u32(500),
+        );
+        let dataset_and_regions = datastore
+            .region_allocate(
+                &opctx,
+                Uuid::new_v4(),
+                &params.disk_source,
+                params.size,
+            )
+            .await
+            .unwrap();
+        assert_eq!(REGION_REDUNDANCY_THRESHOLD, dataset_and_regions.len());
+        for (dataset, _) in dataset_and_regions {
+            assert_eq!(dataset.pool_id, active_zpool_id);
+        }
+
+        // With the remaining sled cordoned too, there's nowhere to put them.
+        let (active_sled_id, _) = zpool_ids[1];
+        let authz_sled = authz::Sled::new(
+            authz::FLEET,
+            active_sled_id,
+            LookupType::ById(active_sled_id),
+        );
+        datastore
+            .sled_set_provision_state(
+                &opctx,
+                &authz_sled,
+                SledProvisionState::Draining,
+            )
+            .await
+            .unwrap();
+        let err = datastore
+            .region_allocate(
+                &opctx,
+                Uuid::new_v4(),
+                &params.disk_source,
+                params.size,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::ServiceUnavailable { .. }));
+
+        let _ = db.cleanup().await;
+        logctx.cleanup_successful();
+    }
+
     #[tokio::test]
     async fn test_region_allocation_out_of_space_fails() {
         let logctx =
@@ -1165,6 +1267,7 @@ mod test {
                 ))),
                 first_port: crate::db::model::SqlU16(0),
                 last_port: crate::db::model::SqlU16(10),
+                project_id: None,
             })
             .collect::<Vec<_>>();
         diesel::insert_into(dsl::external_ip)
@@ -1223,6 +1326,7 @@ mod test {
             ))),
             first_port: crate::db::model::SqlU16(0),
             last_port: crate::db::model::SqlU16(10),
+            project_id: None,
         };
         diesel::insert_into(dsl::external_ip)
             .values(ip.clone())
@@ -1291,6 +1395,7 @@ mod test {
             ip: addresses.next().unwrap().into(),
             first_port: crate::db::model::SqlU16(0),
             last_port: crate::db::model::SqlU16(10),
+            project_id: Some(Uuid::new_v4()),
         };
 
         // Combinations of NULL and non-NULL for:
@@ -1314,6 +1419,8 @@ mod test {
                         description: description.clone(),
                         ip: addresses.next().unwrap().into(),
                         instance_id: *instance_id,
+                        // Floating IP names are unique within a project.
+                        project_id: Some(Uuid::new_v4()),
                         ..ip
                     };
                     let res = diesel::insert_into(dsl::external_ip)
@@ -1361,6 +1468,7 @@ mod test {
                             kind,
                             ip: addresses.next().unwrap().into(),
                             instance_id: *instance_id,
+                            project_id: None,
                             ..ip
                         };
                         let res = diesel::insert_into(dsl::external_ip)
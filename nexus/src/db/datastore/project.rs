@@ -166,6 +166,8 @@ impl DataStore {
     generate_fn_to_ensure_none_in_project!(image, name, String);
     generate_fn_to_ensure_none_in_project!(snapshot, name, String);
     generate_fn_to_ensure_none_in_project!(vpc, name, String);
+    generate_fn_to_ensure_none_in_project!(affinity_group, name, String);
+    generate_fn_to_ensure_none_in_project!(floating_ip, name, String);
 
     /// Delete a project
     pub async fn project_delete(
@@ -182,6 +184,8 @@ impl DataStore {
         self.ensure_no_images_in_project(opctx, authz_project).await?;
         self.ensure_no_snapshots_in_project(opctx, authz_project).await?;
         self.ensure_no_vpcs_in_project(opctx, authz_project).await?;
+        self.ensure_no_affinity_groups_in_project(opctx, authz_project).await?;
+        self.ensure_no_floating_ips_in_project(opctx, authz_project).await?;
 
         use db::schema::project::dsl;
 
@@ -11,6 +11,7 @@ use crate::db;
 use crate::db::error::public_error_from_diesel_pool;
 use crate::db::error::ErrorHandler;
 use crate::db::error::TransactionError;
+use crate::db::identity::Asset;
 use crate::db::lookup::LookupPath;
 use crate::db::model::Dataset;
 use crate::db::model::Region;
@@ -71,15 +72,13 @@ impl DataStore {
 
                 Ok(db_snapshot.block_size)
             }
-            params::DiskSource::Image { image_id: _ } => {
-                // Until we implement project images, do not allow disks to be
-                // created from a project image.
-                return Err(Error::InvalidValue {
-                    label: String::from("image"),
-                    message: String::from(
-                        "project image are not yet supported",
-                    ),
-                });
+            params::DiskSource::Image { image_id } => {
+                let (.., db_image) = LookupPath::new(opctx, &self)
+                    .image_id(*image_id)
+                    .fetch()
+                    .await?;
+
+                Ok(db_image.block_size)
             }
             params::DiskSource::GlobalImage { image_id } => {
                 let (.., db_global_image) = LookupPath::new(opctx, &self)
@@ -156,6 +155,7 @@ impl DataStore {
                 block_size.into(),
                 blocks_per_extent,
                 extent_count,
+                0,
             )
             .get_results_async(self.pool())
             .await
@@ -164,6 +164,40 @@ impl DataStore {
         Ok(dataset_and_regions)
     }
 
+    /// Idempotently allocates an additional set of regions to back `size`
+    /// more bytes of an existing volume.
+    ///
+    /// `existing_region_ids` are the regions backing the volume before it was
+    /// grown. Only the newly-allocated regions (and the datasets to which they
+    /// belong) are returned, regardless of how many times this is called.
+    pub async fn region_allocate_additional(
+        &self,
+        volume_id: Uuid,
+        block_size: &db::model::BlockSize,
+        size: external::ByteCount,
+        existing_region_ids: &[Uuid],
+    ) -> Result<Vec<(Dataset, Region)>, Error> {
+        let (blocks_per_extent, extent_count) =
+            Self::get_crucible_allocation(block_size, size);
+
+        let dataset_and_regions: Vec<(Dataset, Region)> =
+            crate::db::queries::region_allocation::RegionAllocate::new(
+                volume_id,
+                (*block_size).into(),
+                blocks_per_extent,
+                extent_count,
+                existing_region_ids.len().try_into().unwrap(),
+            )
+            .get_results_async(self.pool())
+            .await
+            .map_err(|e| crate::db::queries::region_allocation::from_pool(e))?;
+
+        Ok(dataset_and_regions
+            .into_iter()
+            .filter(|(_, region)| !existing_region_ids.contains(&region.id()))
+            .collect())
+    }
+
     /// Deletes a set of regions.
     ///
     /// Also updates the storage usage on their corresponding datasets.
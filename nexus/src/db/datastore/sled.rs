@@ -11,7 +11,9 @@ use crate::db;
 use crate::db::error::public_error_from_diesel_pool;
 use crate::db::error::ErrorHandler;
 use crate::db::identity::Asset;
+use crate::db::model::Instance;
 use crate::db::model::Sled;
+use crate::db::model::SledProvisionState;
 use crate::db::pagination::paginated;
 use async_bb8_diesel::AsyncRunQueryDsl;
 use chrono::Utc;
@@ -19,8 +21,9 @@ use diesel::prelude::*;
 use omicron_common::api::external::CreateResult;
 use omicron_common::api::external::DataPageParams;
 use omicron_common::api::external::ListResultVec;
-use omicron_common::api::external::OptionalLookupResult;
+use omicron_common::api::external::LookupType;
 use omicron_common::api::external::ResourceType;
+use omicron_common::api::external::UpdateResult;
 use uuid::Uuid;
 
 impl DataStore {
@@ -66,24 +69,75 @@ impl DataStore {
             .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn random_sled(
+    /// Lists the sleds which accept new Instances, other than those in
+    /// `excluded_sleds`, in random order.
+    pub async fn sled_list_provisionable(
         &self,
         opctx: &OpContext,
-    ) -> OptionalLookupResult<Sled> {
+        excluded_sleds: &[Uuid],
+    ) -> ListResultVec<Sled> {
         opctx.authorize(authz::Action::Read, &authz::FLEET).await?;
         use db::schema::sled::dsl;
 
         sql_function!(fn random() -> diesel::sql_types::Float);
-        Ok(dsl::sled
+        dsl::sled
             .filter(dsl::time_deleted.is_null())
+            .filter(dsl::provision_state.eq(SledProvisionState::Active))
+            .filter(dsl::id.ne_all(excluded_sleds.to_vec()))
             .order(random())
-            .limit(1)
             .select(Sled::as_select())
             .load_async::<Sled>(self.pool_authorized(opctx).await?)
             .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Changes whether new Instances and regions may be placed on a sled.
+    pub async fn sled_set_provision_state(
+        &self,
+        opctx: &OpContext,
+        authz_sled: &authz::Sled,
+        state: SledProvisionState,
+    ) -> UpdateResult<Sled> {
+        opctx.authorize(authz::Action::Modify, authz_sled).await?;
+        use db::schema::sled::dsl;
+        diesel::update(dsl::sled)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::id.eq(authz_sled.id()))
+            .set((
+                dsl::provision_state.eq(state),
+                dsl::time_modified.eq(Utc::now()),
+            ))
+            .returning(Sled::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
             .map_err(|e| {
-                public_error_from_diesel_pool(e, ErrorHandler::Server)
-            })?
-            .pop())
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::Sled,
+                        LookupType::ById(authz_sled.id()),
+                    ),
+                )
+            })
+    }
+
+    /// Lists the Instances which are running, or would run, on a sled.
+    ///
+    /// This spans every Silo, so it's only for use by the control plane.
+    pub async fn sled_instance_list(
+        &self,
+        opctx: &OpContext,
+        authz_sled: &authz::Sled,
+    ) -> ListResultVec<Instance> {
+        opctx.authorize(authz::Action::ListChildren, authz_sled).await?;
+        use db::schema::instance::dsl;
+        dsl::instance
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::active_server_id.eq(authz_sled.id()))
+            .order(dsl::id.asc())
+            .select(Instance::as_select())
+            .load_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 }
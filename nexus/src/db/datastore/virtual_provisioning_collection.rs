@@ -11,11 +11,13 @@ use crate::db::error::public_error_from_diesel_pool;
 use crate::db::error::ErrorHandler;
 use crate::db::model::ByteCount;
 use crate::db::model::VirtualProvisioningCollection;
+use crate::db::model::VirtualProvisioningCollectionQuotas;
 use crate::db::pool::DbConnection;
+use crate::db::queries::virtual_provisioning_collection_update::from_pool;
 use crate::db::queries::virtual_provisioning_collection_update::VirtualProvisioningCollectionUpdate;
 use async_bb8_diesel::{AsyncRunQueryDsl, PoolError};
 use diesel::prelude::*;
-use omicron_common::api::external::{DeleteResult, Error};
+use omicron_common::api::external::{DeleteResult, Error, UpdateResult};
 use uuid::Uuid;
 
 /// The types of resources which can consume storage space.
@@ -104,6 +106,27 @@ impl DataStore {
         Ok(virtual_provisioning_collection)
     }
 
+    /// Replaces the quotas of a [`VirtualProvisioningCollection`] object.
+    ///
+    /// Quotas may be set below the resources already provisioned within the
+    /// collection, in which case only further provisioning is refused.
+    pub async fn virtual_provisioning_collection_update_quotas(
+        &self,
+        opctx: &OpContext,
+        id: Uuid,
+        quotas: VirtualProvisioningCollectionQuotas,
+    ) -> UpdateResult<VirtualProvisioningCollection> {
+        use db::schema::virtual_provisioning_collection::dsl;
+
+        diesel::update(dsl::virtual_provisioning_collection)
+            .filter(dsl::id.eq(id))
+            .set(quotas)
+            .returning(VirtualProvisioningCollection::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
     /// Delete a [`VirtualProvisioningCollection`] object.
     pub async fn virtual_provisioning_collection_delete(
         &self,
@@ -211,9 +234,7 @@ impl DataStore {
             )
             .get_results_async(self.pool_authorized(opctx).await?)
             .await
-            .map_err(|e| {
-                public_error_from_diesel_pool(e, ErrorHandler::Server)
-            })?;
+            .map_err(from_pool)?;
         self.virtual_provisioning_collection_producer
             .append_disk_metrics(&provisions);
         Ok(provisions)
@@ -275,6 +296,34 @@ impl DataStore {
         Ok(provisions)
     }
 
+    /// Transitively replaces the provisioned size of a disk from project ->
+    /// fleet.
+    ///
+    /// This is idempotent: the change is only applied while the disk is still
+    /// accounted at `old_disk_bytes`.
+    pub async fn virtual_provisioning_collection_resize_disk(
+        &self,
+        opctx: &OpContext,
+        id: Uuid,
+        project_id: Uuid,
+        old_disk_bytes: ByteCount,
+        new_disk_bytes: ByteCount,
+    ) -> Result<Vec<VirtualProvisioningCollection>, Error> {
+        let provisions =
+            VirtualProvisioningCollectionUpdate::new_resize_storage(
+                id,
+                old_disk_bytes,
+                new_disk_bytes,
+                project_id,
+            )
+            .get_results_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(from_pool)?;
+        self.virtual_provisioning_collection_producer
+            .append_disk_metrics(&provisions);
+        Ok(provisions)
+    }
+
     /// Transitively updates all CPU/RAM provisions from project -> fleet.
     pub async fn virtual_provisioning_collection_insert_instance(
         &self,
@@ -290,9 +339,41 @@ impl DataStore {
             )
             .get_results_async(self.pool_authorized(opctx).await?)
             .await
-            .map_err(|e| {
-                public_error_from_diesel_pool(e, ErrorHandler::Server)
-            })?;
+            .map_err(from_pool)?;
+        self.virtual_provisioning_collection_producer
+            .append_cpu_metrics(&provisions);
+        Ok(provisions)
+    }
+
+    /// Transitively replaces the provisioned CPU/RAM of an instance from
+    /// project -> fleet.
+    ///
+    /// This is idempotent: the change is only applied while the instance is
+    /// still accounted at `old_cpus` and `old_ram`.
+    pub(crate) async fn virtual_provisioning_collection_resize_instance_on_connection<
+        ConnErr,
+    >(
+        &self,
+        conn: &(impl async_bb8_diesel::AsyncConnection<DbConnection, ConnErr>
+              + Sync),
+        id: Uuid,
+        project_id: Uuid,
+        old_cpus: i64,
+        old_ram: ByteCount,
+        new_cpus: i64,
+        new_ram: ByteCount,
+    ) -> Result<Vec<VirtualProvisioningCollection>, Error>
+    where
+        ConnErr: From<diesel::result::Error> + Send + 'static,
+        PoolError: From<ConnErr>,
+    {
+        let provisions =
+            VirtualProvisioningCollectionUpdate::new_resize_instance(
+                id, old_cpus, old_ram, new_cpus, new_ram, project_id,
+            )
+            .get_results_async(conn)
+            .await
+            .map_err(|e| from_pool(PoolError::from(e)))?;
         self.virtual_provisioning_collection_producer
             .append_cpu_metrics(&provisions);
         Ok(provisions)
@@ -26,6 +26,7 @@ use omicron_common::api::external::ListResultVec;
 use omicron_common::api::external::LookupResult;
 use omicron_common::api::external::LookupType;
 use omicron_common::api::external::ResourceType;
+use omicron_common::api::external::UpdateResult;
 use serde::Deserialize;
 use serde::Serialize;
 use sled_agent_client::types::VolumeConstructionRequest;
@@ -166,6 +167,61 @@ impl DataStore {
         Ok(())
     }
 
+    /// Fetch a Volume without modifying it.
+    ///
+    /// Unlike [`DataStore::volume_checkout`], this does not bump the
+    /// generation numbers of the volume's regions, so the result must not be
+    /// handed to an Upstairs.
+    pub async fn volume_get(&self, volume_id: Uuid) -> LookupResult<Volume> {
+        use db::schema::volume::dsl;
+
+        dsl::volume
+            .filter(dsl::id.eq(volume_id))
+            .filter(dsl::time_deleted.is_null())
+            .select(Volume::as_select())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::Volume,
+                        LookupType::ById(volume_id),
+                    ),
+                )
+            })
+    }
+
+    /// Replace the volume construction request stored for a Volume.
+    ///
+    /// This does not adjust the usage counts of any Crucible resources, so
+    /// callers must only use it for changes that add or remove read-write
+    /// regions owned by the volume itself.
+    pub async fn volume_update_data(
+        &self,
+        volume_id: Uuid,
+        data: String,
+    ) -> UpdateResult<Volume> {
+        use db::schema::volume::dsl;
+
+        diesel::update(dsl::volume)
+            .filter(dsl::id.eq(volume_id))
+            .filter(dsl::time_deleted.is_null())
+            .set((dsl::data.eq(data), dsl::time_modified.eq(Utc::now())))
+            .returning(Volume::as_returning())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::Volume,
+                        LookupType::ById(volume_id),
+                    ),
+                )
+            })
+    }
+
     /// Checkout a copy of the Volume from the database.
     /// This action (getting a copy) will increase the generation number
     /// of Volumes of the VolumeConstructionRequest::Volume type that have
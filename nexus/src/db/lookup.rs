@@ -251,6 +251,16 @@ impl<'a> LookupPath<'a> {
         Snapshot::PrimaryKey(Root { lookup_root: self }, id)
     }
 
+    /// Select a resource of type FloatingIp, identified by its id
+    pub fn floating_ip_id(self, id: Uuid) -> FloatingIp<'a> {
+        FloatingIp::PrimaryKey(Root { lookup_root: self }, id)
+    }
+
+    /// Select a resource of type AffinityGroup, identified by its id
+    pub fn affinity_group_id(self, id: Uuid) -> AffinityGroup<'a> {
+        AffinityGroup::PrimaryKey(Root { lookup_root: self }, id)
+    }
+
     /// Select a resource of type NetworkInterface, identified by its id
     pub fn network_interface_id(self, id: Uuid) -> NetworkInterface<'a> {
         NetworkInterface::PrimaryKey(Root { lookup_root: self }, id)
@@ -446,6 +456,23 @@ impl<'a> LookupPath<'a> {
     {
         Certificate::PrimaryKey(Root { lookup_root: self }, id)
     }
+
+    /// Select a resource of type AlertRule, identified by its name
+    pub fn alert_rule_name<'b, 'c>(self, name: &'b Name) -> AlertRule<'c>
+    where
+        'a: 'c,
+        'b: 'c,
+    {
+        AlertRule::Name(Root { lookup_root: self }, name)
+    }
+
+    /// Select a resource of type AlertRule, identified by its id
+    pub fn alert_rule_id<'b>(self, id: Uuid) -> AlertRule<'b>
+    where
+        'a: 'b,
+    {
+        AlertRule::PrimaryKey(Root { lookup_root: self }, id)
+    }
 }
 
 /// Represents the head of the selection path for a resource
@@ -548,7 +575,14 @@ lookup_resource! {
 lookup_resource! {
     name = "Project",
     ancestors = [ "Silo", "Organization" ],
-    children = [ "Disk", "Instance", "Vpc", "Snapshot" ],
+    children = [
+        "Disk",
+        "Instance",
+        "Vpc",
+        "Snapshot",
+        "FloatingIp",
+        "AffinityGroup"
+    ],
     lookup_by_name = true,
     soft_deletes = true,
     primary_key_columns = [ { column_name = "id", rust_type = Uuid } ]
@@ -581,6 +615,24 @@ lookup_resource! {
     primary_key_columns = [ { column_name = "id", rust_type = Uuid } ]
 }
 
+lookup_resource! {
+    name = "FloatingIp",
+    ancestors = [ "Silo", "Organization", "Project" ],
+    children = [],
+    lookup_by_name = true,
+    soft_deletes = true,
+    primary_key_columns = [ { column_name = "id", rust_type = Uuid } ]
+}
+
+lookup_resource! {
+    name = "AffinityGroup",
+    ancestors = [ "Silo", "Organization", "Project" ],
+    children = [],
+    lookup_by_name = true,
+    soft_deletes = true,
+    primary_key_columns = [ { column_name = "id", rust_type = Uuid } ]
+}
+
 lookup_resource! {
     name = "Instance",
     ancestors = [ "Silo", "Organization", "Project" ],
@@ -767,6 +819,15 @@ lookup_resource! {
     primary_key_columns = [ { column_name = "id", rust_type = Uuid } ]
 }
 
+lookup_resource! {
+    name = "AlertRule",
+    ancestors = [],
+    children = [],
+    lookup_by_name = true,
+    soft_deletes = true,
+    primary_key_columns = [ { column_name = "id", rust_type = Uuid } ]
+}
+
 #[cfg(test)]
 mod test {
     use super::Instance;
@@ -1588,6 +1588,7 @@ mod tests {
             network_interfaces: InstanceNetworkInterfaceAttachment::None,
             external_ips: vec![],
             disks: vec![],
+            affinity_groups: Vec::new(),
             start: true,
         };
         let runtime = InstanceRuntimeState {
@@ -6,7 +6,7 @@
 
 use crate::db::alias::ExpressionAlias;
 use crate::db::datastore::REGION_REDUNDANCY_THRESHOLD;
-use crate::db::model::{Dataset, DatasetKind, Region};
+use crate::db::model::{Dataset, DatasetKind, Region, SledProvisionState};
 use crate::db::pool::DbConnection;
 use crate::db::subquery::{AsQuerySource, Cte, CteBuilder, CteQuery};
 use crate::db::true_or_cast_error::{matches_sentinel, TrueOrCastError};
@@ -83,13 +83,27 @@ struct CandidateDatasets {
 impl CandidateDatasets {
     fn new() -> Self {
         use crate::db::schema::dataset::dsl as dataset_dsl;
+        use crate::db::schema::sled::dsl as sled_dsl;
+        use crate::db::schema::zpool::dsl as zpool_dsl;
 
+        // Datasets on sleds which have been cordoned don't get new regions.
         Self {
             query: Box::new(
                 dataset_dsl::dataset
+                    .inner_join(
+                        zpool_dsl::zpool
+                            .on(zpool_dsl::id.eq(dataset_dsl::pool_id)),
+                    )
+                    .inner_join(
+                        sled_dsl::sled.on(sled_dsl::id.eq(zpool_dsl::sled_id)),
+                    )
                     .filter(dataset_dsl::time_deleted.is_null())
                     .filter(dataset_dsl::size_used.is_not_null())
                     .filter(dataset_dsl::kind.eq(DatasetKind::Crucible))
+                    .filter(
+                        sled_dsl::provision_state
+                            .eq(SledProvisionState::Active),
+                    )
                     .order(dataset_dsl::size_used.asc())
                     .limit(REGION_REDUNDANCY_THRESHOLD.try_into().unwrap())
                     .select((dataset_dsl::id, dataset_dsl::pool_id)),
@@ -334,6 +348,7 @@ impl DoInsert {
         old_regions: &OldRegions,
         candidate_regions: &CandidateRegions,
         proposed_datasets_fit: &ProposedDatasetsFit,
+        existing_region_count: i64,
     ) -> Self {
         let redundancy = REGION_REDUNDANCY_THRESHOLD as i64;
         let not_allocated_yet = old_regions
@@ -341,7 +356,7 @@ impl DoInsert {
             .count()
             .single_value()
             .assume_not_null()
-            .lt(redundancy);
+            .lt(existing_region_count + redundancy);
         let enough_candidates = candidate_regions
             .query_source()
             .count()
@@ -450,6 +465,12 @@ impl UpdateDatasets {
 
 /// Constructs a CTE for allocating new regions, and updating the datasets to
 /// which those regions belong.
+///
+/// Each allocation adds one set of [`REGION_REDUNDANCY_THRESHOLD`] regions to
+/// the volume. `existing_region_count` is the number of regions the volume had
+/// before this allocation: the CTE only inserts regions if the volume has no
+/// more than that, which keeps the allocation idempotent both for new volumes
+/// (where it is zero) and for volumes being grown by an additional region set.
 #[derive(QueryId)]
 pub struct RegionAllocate {
     cte: Cte,
@@ -461,6 +482,7 @@ impl RegionAllocate {
         block_size: ByteCount,
         blocks_per_extent: i64,
         extent_count: i64,
+        existing_region_count: i64,
     ) -> Self {
         let old_regions = OldRegions::new(volume_id);
         let candidate_datasets = CandidateDatasets::new();
@@ -481,6 +503,7 @@ impl RegionAllocate {
             &old_regions,
             &candidate_regions,
             &proposed_datasets_fit,
+            existing_region_count,
         );
         let insert_regions = InsertRegions::new(&do_insert, &candidate_regions);
         let updated_datasets =
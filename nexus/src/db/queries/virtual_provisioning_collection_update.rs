@@ -13,16 +13,58 @@ use crate::db::pool::DbConnection;
 use crate::db::schema::virtual_provisioning_collection;
 use crate::db::schema::virtual_provisioning_resource;
 use crate::db::subquery::{AsQuerySource, Cte, CteBuilder, CteQuery};
+use crate::db::true_or_cast_error::{matches_sentinel, TrueOrCastError};
 use db_macros::Subquery;
 use diesel::pg::Pg;
 use diesel::query_builder::{AstPass, Query, QueryFragment, QueryId};
 use diesel::{
-    sql_types, CombineDsl, ExpressionMethods, IntoSql,
+    sql_types, BoolExpressionMethods, CombineDsl, ExpressionMethods, IntoSql,
     NullableExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper,
 };
 use nexus_db_model::queries::virtual_provisioning_collection_update::{
-    all_collections, do_update, parent_org, parent_silo,
+    all_collections, do_update, parent_org, parent_silo, quotas_check,
 };
+use omicron_common::api::external;
+
+const NOT_ENOUGH_STORAGE_QUOTA_SENTINEL: &'static str =
+    "Not enough storage quota";
+const NOT_ENOUGH_CPUS_QUOTA_SENTINEL: &'static str = "Not enough cpus quota";
+const NOT_ENOUGH_RAM_QUOTA_SENTINEL: &'static str = "Not enough ram quota";
+
+/// Translates a generic pool error to an external error based
+/// on messages which may be emitted when provisioning exceeds a quota.
+pub fn from_pool(e: async_bb8_diesel::PoolError) -> external::Error {
+    use crate::db::error;
+
+    let sentinels = [
+        NOT_ENOUGH_STORAGE_QUOTA_SENTINEL,
+        NOT_ENOUGH_CPUS_QUOTA_SENTINEL,
+        NOT_ENOUGH_RAM_QUOTA_SENTINEL,
+    ];
+    if let Some(sentinel) = matches_sentinel(&e, &sentinels) {
+        match sentinel {
+            NOT_ENOUGH_STORAGE_QUOTA_SENTINEL => {
+                return external::Error::insufficient_capacity(
+                    "Not enough storage quota remaining to provision disks",
+                );
+            }
+            NOT_ENOUGH_CPUS_QUOTA_SENTINEL => {
+                return external::Error::insufficient_capacity(
+                    "Not enough CPU quota remaining to provision instances",
+                );
+            }
+            NOT_ENOUGH_RAM_QUOTA_SENTINEL => {
+                return external::Error::insufficient_capacity(
+                    "Not enough memory quota remaining to provision instances",
+                );
+            }
+            // Fall-through to the generic error conversion.
+            _ => {}
+        }
+    }
+
+    error::public_error_from_diesel_pool(e, error::ErrorHandler::Server)
+}
 
 #[derive(Subquery, QueryId)]
 #[subquery(name = parent_org)]
@@ -130,6 +172,54 @@ impl DoUpdate {
         }
     }
 
+    fn new_for_resize_storage(
+        id: uuid::Uuid,
+        old_disk_bytes: ByteCount,
+    ) -> Self {
+        use virtual_provisioning_resource::dsl;
+
+        // Only apply the resize if the resource is still accounted at its old
+        // size, so that repeating it does not charge the difference twice.
+        let not_resized_yet = dsl::virtual_provisioning_resource
+            .filter(dsl::id.eq(id))
+            .filter(dsl::virtual_disk_bytes_provisioned.eq(old_disk_bytes))
+            .count()
+            .single_value()
+            .assume_not_null()
+            .eq(1);
+
+        Self {
+            query: Box::new(diesel::select((ExpressionAlias::new::<
+                do_update::update,
+            >(not_resized_yet),))),
+        }
+    }
+
+    fn new_for_resize_instance(
+        id: uuid::Uuid,
+        old_cpus: i64,
+        old_ram: ByteCount,
+    ) -> Self {
+        use virtual_provisioning_resource::dsl;
+
+        // As with storage, only apply the resize if the resource is still
+        // accounted at its old size.
+        let not_resized_yet = dsl::virtual_provisioning_resource
+            .filter(dsl::id.eq(id))
+            .filter(dsl::cpus_provisioned.eq(old_cpus))
+            .filter(dsl::ram_provisioned.eq(old_ram))
+            .count()
+            .single_value()
+            .assume_not_null()
+            .eq(1);
+
+        Self {
+            query: Box::new(diesel::select((ExpressionAlias::new::<
+                do_update::update,
+            >(not_resized_yet),))),
+        }
+    }
+
     fn new_for_delete(id: uuid::Uuid) -> Self {
         use virtual_provisioning_resource::dsl;
 
@@ -148,6 +238,108 @@ impl DoUpdate {
     }
 }
 
+/// The amount by which an update grows the resources provisioned within each
+/// collection.
+///
+/// Quotas are only checked for the resources which grow, so that resources can
+/// always be freed up, even within a collection which is already over quota.
+#[derive(Default)]
+struct ProvisionIncrease {
+    virtual_disk_bytes: i64,
+    cpus: i64,
+    ram: i64,
+}
+
+#[derive(Subquery, QueryId)]
+#[subquery(name = quotas_check)]
+struct QuotasCheck {
+    query: Box<dyn CteQuery<SqlType = quotas_check::SqlType>>,
+}
+
+impl QuotasCheck {
+    fn new(
+        all_collections: &AllCollections,
+        do_update: &DoUpdate,
+        increase: ProvisionIncrease,
+    ) -> Self {
+        use virtual_provisioning_collection::dsl;
+
+        // Selects the collections containing the resource which would be
+        // modified by this update. If the update is not going to be applied
+        // (e.g., because it already was), no collections are selected, so
+        // that repeating an update never fails on account of quotas.
+        let updated_collections = || {
+            dsl::virtual_provisioning_collection
+                .filter(dsl::id.eq_any(
+                    all_collections.query_source().select(all_collections::id),
+                ))
+                .filter(
+                    do_update
+                        .query_source()
+                        .select(do_update::update)
+                        .single_value()
+                        .assume_not_null(),
+                )
+        };
+
+        let storage_fits = updated_collections()
+            .filter(
+                (increase.virtual_disk_bytes > 0).into_sql::<sql_types::Bool>(),
+            )
+            .filter(dsl::virtual_disk_bytes_limit.is_not_null())
+            .filter(
+                (dsl::virtual_disk_bytes_provisioned
+                    + increase.virtual_disk_bytes)
+                    .gt(dsl::virtual_disk_bytes_limit.assume_not_null()),
+            )
+            .count()
+            .single_value()
+            .assume_not_null()
+            .eq(0);
+        let cpus_fit = updated_collections()
+            .filter((increase.cpus > 0).into_sql::<sql_types::Bool>())
+            .filter(dsl::cpus_limit.is_not_null())
+            .filter(
+                (dsl::cpus_provisioned + increase.cpus)
+                    .gt(dsl::cpus_limit.assume_not_null()),
+            )
+            .count()
+            .single_value()
+            .assume_not_null()
+            .eq(0);
+        let ram_fits = updated_collections()
+            .filter((increase.ram > 0).into_sql::<sql_types::Bool>())
+            .filter(dsl::ram_limit.is_not_null())
+            .filter(
+                (dsl::ram_provisioned + increase.ram)
+                    .gt(dsl::ram_limit.assume_not_null()),
+            )
+            .count()
+            .single_value()
+            .assume_not_null()
+            .eq(0);
+
+        Self {
+            query: Box::new(diesel::select((ExpressionAlias::new::<
+                quotas_check::ok,
+            >(
+                TrueOrCastError::new(
+                    storage_fits,
+                    NOT_ENOUGH_STORAGE_QUOTA_SENTINEL,
+                )
+                .and(TrueOrCastError::new(
+                    cpus_fit,
+                    NOT_ENOUGH_CPUS_QUOTA_SENTINEL,
+                ))
+                .and(TrueOrCastError::new(
+                    ram_fits,
+                    NOT_ENOUGH_RAM_QUOTA_SENTINEL,
+                )),
+            ),))),
+        }
+    }
+}
+
 #[derive(Subquery, QueryId)]
 #[subquery(name = virtual_provisioning_collection)]
 struct UpdatedProvisions {
@@ -159,6 +351,7 @@ impl UpdatedProvisions {
     fn new<V>(
         all_collections: &AllCollections,
         do_update: &DoUpdate,
+        quotas_check: &QuotasCheck,
         values: V,
     ) -> Self
     where
@@ -186,6 +379,13 @@ impl UpdatedProvisions {
                             .single_value()
                             .assume_not_null(),
                     )
+                    .filter(
+                        quotas_check
+                            .query_source()
+                            .select(quotas_check::ok)
+                            .single_value()
+                            .assume_not_null(),
+                    )
                     .returning(virtual_provisioning_collection::all_columns),
             ),
         }
@@ -249,12 +449,15 @@ impl VirtualProvisioningCollectionUpdate {
     // - update: A SQL query to actually modify the resource record. Generally
     // this is an "INSERT", "UPDATE", or "DELETE".
     // - project_id: The project to which the resource belongs.
+    // - increase: The amount by which the resources provisioned within each
+    // collection grow, which must fit within the quotas of all of them.
     // - values: The updated values to propagate through collections (iff
-    // "do_update" evaluates to "true").
+    // "do_update" evaluates to "true", and the quotas are respected).
     fn apply_update<U, V>(
         do_update: DoUpdate,
         update: U,
         project_id: uuid::Uuid,
+        increase: ProvisionIncrease,
         values: V,
     ) -> Self
     where
@@ -271,8 +474,14 @@ impl VirtualProvisioningCollectionUpdate {
             &parent_silo,
             *crate::db::fixed_data::FLEET_ID,
         );
-        let updated_collections =
-            UpdatedProvisions::new(&all_collections, &do_update, values);
+        let quotas_check =
+            QuotasCheck::new(&all_collections, &do_update, increase);
+        let updated_collections = UpdatedProvisions::new(
+            &all_collections,
+            &do_update,
+            &quotas_check,
+            values,
+        );
 
         // TODO: Do we want to select from "all_collections" instead? Seems more
         // idempotent; it'll work even when we don't update anything...
@@ -287,6 +496,7 @@ impl VirtualProvisioningCollectionUpdate {
             .add_subquery(parent_silo)
             .add_subquery(all_collections)
             .add_subquery(do_update)
+            .add_subquery(quotas_check)
             .add_subquery(update)
             .add_subquery(updated_collections)
             .build(final_select);
@@ -321,6 +531,11 @@ impl VirtualProvisioningCollectionUpdate {
             ),
             // Within this project, org, silo, fleet...
             project_id,
+            // ... If the disk usage fits within all quotas...
+            ProvisionIncrease {
+                virtual_disk_bytes: i64::from(disk_byte_diff.0),
+                ..Default::default()
+            },
             // ... We add the disk usage.
             (
                 collection_dsl::time_modified.eq(diesel::dsl::now),
@@ -350,6 +565,8 @@ impl VirtualProvisioningCollectionUpdate {
             ),
             // Within this project, org, silo, fleet...
             project_id,
+            // ... Which is always allowed...
+            ProvisionIncrease::default(),
             // ... We subtract the disk usage.
             (
                 collection_dsl::time_modified.eq(diesel::dsl::now),
@@ -360,6 +577,52 @@ impl VirtualProvisioningCollectionUpdate {
         )
     }
 
+    pub fn new_resize_storage(
+        id: uuid::Uuid,
+        old_disk_bytes: ByteCount,
+        new_disk_bytes: ByteCount,
+        project_id: uuid::Uuid,
+    ) -> Self {
+        use virtual_provisioning_collection::dsl as collection_dsl;
+        use virtual_provisioning_resource::dsl as resource_dsl;
+
+        Self::apply_update(
+            // We should resize the record if it still has its old size.
+            DoUpdate::new_for_resize_storage(id, old_disk_bytes),
+            // The query to actually resize the record.
+            UnreferenceableSubquery(
+                diesel::update(resource_dsl::virtual_provisioning_resource)
+                    .filter(resource_dsl::id.eq(id))
+                    .filter(
+                        resource_dsl::virtual_disk_bytes_provisioned
+                            .eq(old_disk_bytes),
+                    )
+                    .set(
+                        resource_dsl::virtual_disk_bytes_provisioned
+                            .eq(new_disk_bytes),
+                    )
+                    .returning(virtual_provisioning_resource::all_columns),
+            ),
+            // Within this project, org, silo, fleet...
+            project_id,
+            // ... If any growth fits within all quotas...
+            ProvisionIncrease {
+                virtual_disk_bytes: i64::from(new_disk_bytes.0)
+                    - i64::from(old_disk_bytes.0),
+                ..Default::default()
+            },
+            // ... We swap the old disk usage for the new one.
+            (
+                collection_dsl::time_modified.eq(diesel::dsl::now),
+                collection_dsl::virtual_disk_bytes_provisioned.eq(
+                    collection_dsl::virtual_disk_bytes_provisioned
+                        - old_disk_bytes
+                        + new_disk_bytes,
+                ),
+            ),
+        )
+    }
+
     pub fn new_insert_instance(
         id: uuid::Uuid,
         cpus_diff: i64,
@@ -390,6 +653,12 @@ impl VirtualProvisioningCollectionUpdate {
             ),
             // Within this project, org, silo, fleet...
             project_id,
+            // ... If the resource usage fits within all quotas...
+            ProvisionIncrease {
+                cpus: cpus_diff,
+                ram: i64::from(ram_diff.0),
+                ..Default::default()
+            },
             // ... We update the resource usage.
             (
                 collection_dsl::time_modified.eq(diesel::dsl::now),
@@ -401,6 +670,53 @@ impl VirtualProvisioningCollectionUpdate {
         )
     }
 
+    pub fn new_resize_instance(
+        id: uuid::Uuid,
+        old_cpus: i64,
+        old_ram: ByteCount,
+        new_cpus: i64,
+        new_ram: ByteCount,
+        project_id: uuid::Uuid,
+    ) -> Self {
+        use virtual_provisioning_collection::dsl as collection_dsl;
+        use virtual_provisioning_resource::dsl as resource_dsl;
+
+        Self::apply_update(
+            // We should resize the record if it still has its old size.
+            DoUpdate::new_for_resize_instance(id, old_cpus, old_ram),
+            // The query to actually resize the record.
+            UnreferenceableSubquery(
+                diesel::update(resource_dsl::virtual_provisioning_resource)
+                    .filter(resource_dsl::id.eq(id))
+                    .filter(resource_dsl::cpus_provisioned.eq(old_cpus))
+                    .filter(resource_dsl::ram_provisioned.eq(old_ram))
+                    .set((
+                        resource_dsl::cpus_provisioned.eq(new_cpus),
+                        resource_dsl::ram_provisioned.eq(new_ram),
+                    ))
+                    .returning(virtual_provisioning_resource::all_columns),
+            ),
+            // Within this project, org, silo, fleet...
+            project_id,
+            // ... If any growth fits within all quotas...
+            ProvisionIncrease {
+                cpus: new_cpus - old_cpus,
+                ram: i64::from(new_ram.0) - i64::from(old_ram.0),
+                ..Default::default()
+            },
+            // ... We swap the old resource usage for the new one.
+            (
+                collection_dsl::time_modified.eq(diesel::dsl::now),
+                collection_dsl::cpus_provisioned.eq(
+                    collection_dsl::cpus_provisioned - old_cpus + new_cpus,
+                ),
+                collection_dsl::ram_provisioned.eq(
+                    collection_dsl::ram_provisioned - old_ram + new_ram,
+                ),
+            ),
+        )
+    }
+
     pub fn new_delete_instance(
         id: uuid::Uuid,
         cpus_diff: i64,
@@ -421,6 +737,8 @@ impl VirtualProvisioningCollectionUpdate {
             ),
             // Within this project, org, silo, fleet...
             project_id,
+            // ... Which is always allowed...
+            ProvisionIncrease::default(),
             // ... We update the resource usage.
             (
                 collection_dsl::time_modified.eq(diesel::dsl::now),
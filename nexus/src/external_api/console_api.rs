@@ -108,7 +108,7 @@ pub async fn login_spoof(
         };
         Ok(response)
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "login_spoof", handler).await
 }
 
 // Silos have one or more identity providers, and an unauthenticated user will
@@ -390,7 +390,7 @@ pub async fn login_saml(
         login_finish(&opctx, apictx, user, relay_state.and_then(|r| r.referer))
             .await
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "login_saml", handler).await
 }
 
 #[derive(Deserialize, JsonSchema)]
@@ -424,7 +424,7 @@ pub async fn login_local(
             .await?;
         login_finish(&opctx, apictx, user, None).await
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "login_local", handler).await
 }
 
 async fn login_finish(
@@ -523,7 +523,7 @@ pub async fn logout(
         Ok(response)
     };
 
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "logout", handler).await
 }
 
 #[derive(Deserialize, JsonSchema)]
@@ -185,7 +185,7 @@ pub async fn device_auth_confirm(
             .await?;
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "device_auth_confirm", handler).await
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
@@ -202,6 +202,25 @@ pub enum DeviceAccessTokenResponse {
     Denied,
 }
 
+/// Records the grant of a device access token in the audit log
+///
+/// Polling for a token isn't authenticated, and most polls don't change
+/// anything, so rather than recording every poll, we record the one which hands
+/// out a token on behalf of the user who confirmed the grant.
+async fn audit_token_grant(
+    rqctx: &RequestContext<Arc<ServerContext>>,
+    token: &DeviceAccessToken,
+) -> Result<(), HttpError> {
+    let apictx = rqctx.context();
+    let actor =
+        apictx.nexus.audit_log_silo_user_actor(token.silo_user_id).await?;
+    let entry = apictx
+        .audit_log_entry_init(rqctx, "device_access_token", actor)
+        .await?;
+    apictx.audit_log_entry_complete(rqctx, &entry, StatusCode::OK, None).await;
+    Ok(())
+}
+
 /// Request a device access token
 ///
 /// This endpoint should be polled by the client until the user code
@@ -242,10 +261,13 @@ pub async fn device_access_token(
             .await
         {
             Ok(response) => match response {
-                Granted(token) => build_oauth_response(
-                    StatusCode::OK,
-                    &DeviceAccessTokenGrant::from(token),
-                ),
+                Granted(token) => {
+                    audit_token_grant(&rqctx, &token).await?;
+                    build_oauth_response(
+                        StatusCode::OK,
+                        &DeviceAccessTokenGrant::from(token),
+                    )
+                }
                 Pending => build_oauth_response(
                     StatusCode::BAD_REQUEST,
                     &serde_json::json!({
@@ -7,9 +7,11 @@
 use super::{
     console_api, device_auth, params,
     views::{
-        self, Certificate, GlobalImage, Group, IdentityProvider, Image, IpPool,
-        IpPoolRange, Organization, PhysicalDisk, Project, Rack, Role, Silo,
-        Sled, Snapshot, SshKey, User, UserBuiltin, Vpc, VpcRouter, VpcSubnet,
+        self, AffinityGroup, Alert, AlertRule, AuditLogEntry, Certificate,
+        FloatingIp, GlobalImage, Group, IdentityProvider, Image, IpPool,
+        IpPoolRange, Organization, PhysicalDisk, Project, Rack, ResourceQuotas,
+        Role, Silo, Sled, Snapshot, SshKey, User, UserBuiltin, Vpc, VpcRouter,
+        VpcSubnet,
     },
 };
 use crate::authz;
@@ -18,6 +20,7 @@ use crate::db;
 use crate::db::model::Name;
 use crate::external_api::shared;
 use crate::ServerContext;
+use chrono::DateTime;
 use chrono::Utc;
 use dropshot::ApiDescription;
 use dropshot::EmptyScanParams;
@@ -71,6 +74,7 @@ use ref_cast::RefCast;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
+use std::num::NonZeroU32;
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -107,6 +111,8 @@ pub fn external_api() -> NexusApiDescription {
         api.register(organization_update_v1)?;
         api.register(organization_policy_view_v1)?;
         api.register(organization_policy_update_v1)?;
+        api.register(organization_quotas_view_v1)?;
+        api.register(organization_quotas_update_v1)?;
 
         api.register(project_list)?;
         api.register(project_create)?;
@@ -124,6 +130,8 @@ pub fn external_api() -> NexusApiDescription {
         api.register(project_update_v1)?;
         api.register(project_policy_view_v1)?;
         api.register(project_policy_update_v1)?;
+        api.register(project_quotas_view_v1)?;
+        api.register(project_quotas_update_v1)?;
 
         // Operator-Accessible IP Pools API
         api.register(ip_pool_list)?;
@@ -154,6 +162,7 @@ pub fn external_api() -> NexusApiDescription {
         api.register(disk_list_v1)?;
         api.register(disk_create_v1)?;
         api.register(disk_view_v1)?;
+        api.register(disk_update_v1)?;
         api.register(disk_delete_v1)?;
 
         api.register(instance_list)?;
@@ -174,8 +183,12 @@ pub fn external_api() -> NexusApiDescription {
         api.register(instance_list_v1)?;
         api.register(instance_view_v1)?;
         api.register(instance_create_v1)?;
+        api.register(instance_update_v1)?;
         api.register(instance_delete_v1)?;
         api.register(instance_migrate_v1)?;
+        api.register(instance_migration_list_v1)?;
+        api.register(instance_migration_view_v1)?;
+        api.register(instance_migration_cancel_v1)?;
         api.register(instance_reboot_v1)?;
         api.register(instance_start_v1)?;
         api.register(instance_stop_v1)?;
@@ -203,6 +216,19 @@ pub fn external_api() -> NexusApiDescription {
         api.register(snapshot_view_v1)?;
         api.register(snapshot_delete_v1)?;
 
+        api.register(floating_ip_list_v1)?;
+        api.register(floating_ip_create_v1)?;
+        api.register(floating_ip_view_v1)?;
+        api.register(floating_ip_delete_v1)?;
+        api.register(floating_ip_attach_v1)?;
+        api.register(floating_ip_detach_v1)?;
+
+        api.register(affinity_group_list_v1)?;
+        api.register(affinity_group_create_v1)?;
+        api.register(affinity_group_view_v1)?;
+        api.register(affinity_group_delete_v1)?;
+        api.register(affinity_group_member_list_v1)?;
+
         api.register(vpc_list)?;
         api.register(vpc_create)?;
         api.register(vpc_view)?;
@@ -290,6 +316,10 @@ pub fn external_api() -> NexusApiDescription {
         api.register(sled_list_v1)?;
         api.register(sled_view_v1)?;
         api.register(sled_physical_disk_list_v1)?;
+        api.register(sled_cordon_v1)?;
+        api.register(sled_uncordon_v1)?;
+        api.register(sled_drain_v1)?;
+        api.register(sled_drain_view_v1)?;
         api.register(physical_disk_list_v1)?;
 
         api.register(saga_list)?;
@@ -303,6 +333,8 @@ pub fn external_api() -> NexusApiDescription {
 
         api.register(timeseries_schema_get)?;
 
+        api.register(audit_log_list)?;
+
         api.register(role_list)?;
         api.register(role_view)?;
 
@@ -320,6 +352,8 @@ pub fn external_api() -> NexusApiDescription {
         api.register(silo_identity_provider_list)?;
         api.register(silo_policy_view)?;
         api.register(silo_policy_update)?;
+        api.register(silo_quotas_view)?;
+        api.register(silo_quotas_update)?;
 
         api.register(saml_identity_provider_create)?;
         api.register(saml_identity_provider_view)?;
@@ -338,6 +372,12 @@ pub fn external_api() -> NexusApiDescription {
         api.register(certificate_view_v1)?;
         api.register(certificate_delete_v1)?;
 
+        api.register(alert_rule_list_v1)?;
+        api.register(alert_rule_create_v1)?;
+        api.register(alert_rule_view_v1)?;
+        api.register(alert_rule_delete_v1)?;
+        api.register(alert_list_v1)?;
+
         api.register(system_image_list)?;
         api.register(system_image_create)?;
         api.register(system_image_view)?;
@@ -502,7 +542,7 @@ async fn system_policy_update_v1(
         let policy = nexus.fleet_update_policy(&opctx, &new_policy).await?;
         Ok(HttpResponseOk(policy))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "system_policy_update_v1", handler).await
 }
 
 /// Update the top-level IAM policy
@@ -528,7 +568,7 @@ async fn system_policy_update(
         let policy = nexus.fleet_update_policy(&opctx, &new_policy).await?;
         Ok(HttpResponseOk(policy))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "system_policy_update", handler).await
 }
 
 /// Fetch the current silo's IAM policy
@@ -610,7 +650,7 @@ async fn policy_update_v1(
             nexus.silo_update_policy(&opctx, lookup, &new_policy).await?;
         Ok(HttpResponseOk(policy))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "policy_update_v1", handler).await
 }
 
 /// Update the current silo's IAM policy
@@ -643,7 +683,7 @@ async fn policy_update(
             nexus.silo_update_policy(&opctx, lookup, &new_policy).await?;
         Ok(HttpResponseOk(policy))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "policy_update", handler).await
 }
 
 /// List silos
@@ -699,7 +739,7 @@ async fn silo_create(
             nexus.silo_create(&opctx, new_silo_params.into_inner()).await?;
         Ok(HttpResponseCreated(silo.try_into()?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "silo_create", handler).await
 }
 
 /// Path parameters for Silo requests
@@ -776,7 +816,7 @@ async fn silo_delete(
         nexus.silo_delete(&opctx, &silo_name).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "silo_delete", handler).await
 }
 
 /// Fetch a silo's IAM policy
@@ -830,9 +870,60 @@ async fn silo_policy_update(
             nexus.silo_update_policy(&opctx, lookup, &new_policy).await?;
         Ok(HttpResponseOk(policy))
     };
+    apictx.audit_and_time(&rqctx, "silo_policy_update", handler).await
+}
+
+/// Fetch a silo's resource quotas
+#[endpoint {
+    method = GET,
+    path = "/system/silos/{silo_name}/quotas",
+    tags = ["system"],
+}]
+async fn silo_quotas_view(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<SiloPathParam>,
+) -> Result<HttpResponseOk<ResourceQuotas>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let silo_name = &path.silo_name;
+
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let lookup = nexus.db_lookup(&opctx).silo_name(silo_name);
+        let quotas = nexus.silo_quotas_view(&opctx, &lookup).await?;
+        Ok(HttpResponseOk(quotas.into()))
+    };
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+/// Update a silo's resource quotas
+#[endpoint {
+    method = PUT,
+    path = "/system/silos/{silo_name}/quotas",
+    tags = ["system"],
+}]
+async fn silo_quotas_update(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<SiloPathParam>,
+    new_quotas: TypedBody<params::ResourceQuotasUpdate>,
+) -> Result<HttpResponseOk<ResourceQuotas>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let new_quotas = new_quotas.into_inner();
+    let silo_name = &path.silo_name;
+
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let lookup = nexus.db_lookup(&opctx).silo_name(silo_name);
+        let quotas =
+            nexus.silo_quotas_update(&opctx, &lookup, &new_quotas).await?;
+        Ok(HttpResponseOk(quotas.into()))
+    };
+    apictx.audit_and_time(&rqctx, "silo_quotas_update", handler).await
+}
+
 // Silo-specific user endpoints
 
 /// List users in a silo
@@ -968,7 +1059,9 @@ async fn saml_identity_provider_create(
             .await?;
         Ok(HttpResponseCreated(provider.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .audit_and_time(&rqctx, "saml_identity_provider_create", handler)
+        .await
 }
 
 /// Path parameters for Silo SAML identity provider requests
@@ -1043,7 +1136,7 @@ async fn local_idp_user_create(
             .await?;
         Ok(HttpResponseCreated(user.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "local_idp_user_create", handler).await
 }
 
 /// Delete a user
@@ -1070,7 +1163,7 @@ async fn local_idp_user_delete(
             .await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "local_idp_user_delete", handler).await
 }
 
 /// Set or invalidate a user's password
@@ -1102,7 +1195,7 @@ async fn local_idp_user_set_password(
             .await?;
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "local_idp_user_set_password", handler).await
 }
 
 /// List organizations
@@ -1192,7 +1285,7 @@ async fn organization_create_v1(
             .await?;
         Ok(HttpResponseCreated(organization.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "organization_create_v1", handler).await
 }
 
 /// Create an organization
@@ -1216,7 +1309,7 @@ async fn organization_create(
             .await?;
         Ok(HttpResponseCreated(organization.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "organization_create", handler).await
 }
 
 /// Fetch an organization
@@ -1337,7 +1430,7 @@ async fn organization_delete_v1(
         nexus.organization_delete(&opctx, &organization_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "organization_delete_v1", handler).await
 }
 
 /// Delete an organization
@@ -1365,7 +1458,7 @@ async fn organization_delete(
         nexus.organization_delete(&opctx, &organization_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "organization_delete", handler).await
 }
 
 /// Update an organization
@@ -1397,7 +1490,7 @@ async fn organization_update_v1(
             .await?;
         Ok(HttpResponseOk(new_organization.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "organization_update_v1", handler).await
 }
 
 /// Update an organization
@@ -1437,7 +1530,7 @@ async fn organization_update(
             .await?;
         Ok(HttpResponseOk(new_organization.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "organization_update", handler).await
 }
 
 /// Fetch an organization's IAM policy
@@ -1534,7 +1627,9 @@ async fn organization_policy_update_v1(
             .await?;
         Ok(HttpResponseOk(policy))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .audit_and_time(&rqctx, "organization_policy_update_v1", handler)
+        .await
 }
 
 /// Update an organization's IAM policy
@@ -1574,9 +1669,71 @@ async fn organization_policy_update(
             .await?;
         Ok(HttpResponseOk(policy))
     };
+    apictx.audit_and_time(&rqctx, "organization_policy_update", handler).await
+}
+
+/// Fetch an organization's resource quotas
+#[endpoint {
+    method = GET,
+    path = "/v1/organizations/{organization}/quotas",
+    tags = ["organizations"],
+}]
+async fn organization_quotas_view_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::OrganizationPath>,
+) -> Result<HttpResponseOk<ResourceQuotas>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let organization_selector =
+            &params::OrganizationSelector { organization: path.organization };
+        let organization_lookup =
+            nexus.organization_lookup(&opctx, &organization_selector)?;
+        let quotas = nexus
+            .organization_quotas_view(&opctx, &organization_lookup)
+            .await?;
+        Ok(HttpResponseOk(quotas.into()))
+    };
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+/// Update an organization's resource quotas
+#[endpoint {
+    method = PUT,
+    path = "/v1/organizations/{organization}/quotas",
+    tags = ["organizations"],
+}]
+async fn organization_quotas_update_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::OrganizationPath>,
+    new_quotas: TypedBody<params::ResourceQuotasUpdate>,
+) -> Result<HttpResponseOk<ResourceQuotas>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let new_quotas = new_quotas.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let organization_selector =
+            &params::OrganizationSelector { organization: path.organization };
+        let organization_lookup =
+            nexus.organization_lookup(&opctx, &organization_selector)?;
+        let quotas = nexus
+            .organization_quotas_update(
+                &opctx,
+                &organization_lookup,
+                &new_quotas,
+            )
+            .await?;
+        Ok(HttpResponseOk(quotas.into()))
+    };
+    apictx
+        .audit_and_time(&rqctx, "organization_quotas_update_v1", handler)
+        .await
+}
+
 /// List projects
 #[endpoint {
     method = GET,
@@ -1683,7 +1840,7 @@ async fn project_create_v1(
             .await?;
         Ok(HttpResponseCreated(project.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "project_create_v1", handler).await
 }
 
 /// Create a project
@@ -1718,7 +1875,7 @@ async fn project_create(
             .await?;
         Ok(HttpResponseCreated(project.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "project_create", handler).await
 }
 
 /// Fetch a project
@@ -1837,7 +1994,7 @@ async fn project_delete_v1(
         nexus.project_delete(&opctx, &project_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "project_delete_v1", handler).await
 }
 
 /// Delete a project
@@ -1865,7 +2022,7 @@ async fn project_delete(
         nexus.project_delete(&opctx, &project_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "project_delete", handler).await
 }
 
 /// Update a project
@@ -1897,7 +2054,7 @@ async fn project_update_v1(
             .await?;
         Ok(HttpResponseOk(project.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "project_update_v1", handler).await
 }
 
 /// Update a project
@@ -1937,7 +2094,7 @@ async fn project_update(
             .await?;
         Ok(HttpResponseOk(new_project.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "project_update", handler).await
 }
 
 /// Fetch a project's IAM policy
@@ -2027,7 +2184,7 @@ async fn project_policy_update_v1(
             .await?;
         Ok(HttpResponseOk(new_policy))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "project_policy_update_v1", handler).await
 }
 
 /// Update a project's IAM policy
@@ -2060,9 +2217,69 @@ async fn project_policy_update(
             .await?;
         Ok(HttpResponseOk(policy))
     };
+    apictx.audit_and_time(&rqctx, "project_policy_update", handler).await
+}
+
+/// Fetch a project's resource quotas
+#[endpoint {
+    method = GET,
+    path = "/v1/projects/{project}/quotas",
+    tags = ["projects"],
+}]
+async fn project_quotas_view_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::ProjectPath>,
+    query_params: Query<params::OptionalOrganizationSelector>,
+) -> Result<HttpResponseOk<ResourceQuotas>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let query = query_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let project_selector = params::ProjectSelector {
+            organization_selector: query.organization_selector,
+            project: path.project,
+        };
+        let project_lookup = nexus.project_lookup(&opctx, &project_selector)?;
+        let quotas = nexus.project_quotas_view(&opctx, &project_lookup).await?;
+        Ok(HttpResponseOk(quotas.into()))
+    };
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+/// Update a project's resource quotas
+#[endpoint {
+    method = PUT,
+    path = "/v1/projects/{project}/quotas",
+    tags = ["projects"],
+}]
+async fn project_quotas_update_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::ProjectPath>,
+    query_params: Query<params::OptionalOrganizationSelector>,
+    new_quotas: TypedBody<params::ResourceQuotasUpdate>,
+) -> Result<HttpResponseOk<ResourceQuotas>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let query = query_params.into_inner();
+    let new_quotas = new_quotas.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let project_selector = params::ProjectSelector {
+            organization_selector: query.organization_selector,
+            project: path.project,
+        };
+        let project_lookup = nexus.project_lookup(&opctx, &project_selector)?;
+        let quotas = nexus
+            .project_quotas_update(&opctx, &project_lookup, &new_quotas)
+            .await?;
+        Ok(HttpResponseOk(quotas.into()))
+    };
+    apictx.audit_and_time(&rqctx, "project_quotas_update_v1", handler).await
+}
+
 // IP Pools
 
 #[derive(Deserialize, JsonSchema)]
@@ -2121,7 +2338,7 @@ async fn ip_pool_create(
         let pool = nexus.ip_pool_create(&opctx, &pool_params).await?;
         Ok(HttpResponseCreated(IpPool::from(pool)))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "ip_pool_create", handler).await
 }
 
 /// Fetch an IP pool
@@ -2187,7 +2404,7 @@ async fn ip_pool_delete(
         nexus.ip_pool_delete(&opctx, pool_name).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "ip_pool_delete", handler).await
 }
 
 /// Update an IP Pool
@@ -2211,7 +2428,7 @@ async fn ip_pool_update(
         let pool = nexus.ip_pool_update(&opctx, pool_name, &updates).await?;
         Ok(HttpResponseOk(pool.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "ip_pool_update", handler).await
 }
 
 /// Fetch the IP pool used for Oxide services.
@@ -2302,7 +2519,7 @@ async fn ip_pool_range_add(
         let out = nexus.ip_pool_add_range(&opctx, pool_name, &range).await?;
         Ok(HttpResponseCreated(out.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "ip_pool_range_add", handler).await
 }
 
 /// Remove a range from an IP pool
@@ -2326,7 +2543,7 @@ async fn ip_pool_range_remove(
         nexus.ip_pool_delete_range(&opctx, pool_name, &range).await?;
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "ip_pool_range_remove", handler).await
 }
 
 /// List ranges for the IP pool used for Oxide services.
@@ -2390,7 +2607,7 @@ async fn ip_pool_service_range_add(
         let out = nexus.ip_pool_service_add_range(&opctx, &range).await?;
         Ok(HttpResponseCreated(out.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "ip_pool_service_range_add", handler).await
 }
 
 /// Remove a range from an IP pool used for Oxide services.
@@ -2411,7 +2628,7 @@ async fn ip_pool_service_range_remove(
         nexus.ip_pool_service_delete_range(&opctx, &range).await?;
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "ip_pool_service_range_remove", handler).await
 }
 
 // Disks
@@ -2516,7 +2733,7 @@ async fn disk_create_v1(
             nexus.project_create_disk(&opctx, &project_lookup, &params).await?;
         Ok(HttpResponseCreated(disk.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "disk_create_v1", handler).await
 }
 
 // TODO-correctness See note about instance create.  This should be async.
@@ -2548,7 +2765,7 @@ async fn disk_create(
             .await?;
         Ok(HttpResponseCreated(disk.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "disk_create", handler).await
 }
 
 /// Fetch a disk
@@ -2579,6 +2796,39 @@ async fn disk_view_v1(
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+/// Update a disk
+///
+/// Currently, the only supported update is growing a detached disk.
+#[endpoint {
+    method = PUT,
+    path = "/v1/disks/{disk}",
+    tags = ["disks"]
+}]
+async fn disk_update_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::DiskPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+    updated_disk: TypedBody<params::DiskUpdate>,
+) -> Result<HttpResponseOk<Disk>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let query = query_params.into_inner();
+    let disk_selector = params::DiskSelector {
+        disk: path.disk,
+        project_selector: query.project_selector,
+    };
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let disk_lookup = nexus.disk_lookup(&opctx, &disk_selector)?;
+        let disk = nexus
+            .disk_update(&opctx, &disk_lookup, &updated_disk.into_inner())
+            .await?;
+        Ok(HttpResponseOk(disk.into()))
+    };
+    apictx.audit_and_time(&rqctx, "disk_update_v1", handler).await
+}
+
 /// Path parameters for Disk requests
 #[derive(Deserialize, JsonSchema)]
 struct DiskPathParam {
@@ -2666,7 +2916,7 @@ async fn disk_delete_v1(
         nexus.project_delete_disk(&opctx, &disk_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "disk_delete_v1", handler).await
 }
 
 /// Use `DELETE /v1/disks/{disk}` instead
@@ -2694,7 +2944,7 @@ async fn disk_delete(
         nexus.project_delete_disk(&opctx, &disk_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "disk_delete", handler).await
 }
 
 #[derive(Display, Deserialize, JsonSchema)]
@@ -2718,16 +2968,16 @@ pub enum DiskMetricName {
 async fn disk_metrics_list(
     rqctx: RequestContext<Arc<ServerContext>>,
     path_params: Path<MetricsPathParam<DiskPathParam, DiskMetricName>>,
-    query_params: Query<
-        PaginationParams<params::ResourceMetrics, params::ResourceMetrics>,
-    >,
+    query_params: Query<ResourceMetricsParams>,
 ) -> Result<HttpResponseOk<ResultsPage<oximeter_db::Measurement>>, HttpError> {
     let apictx = rqctx.context();
     let handler = async {
         let nexus = &apictx.nexus;
         let path = path_params.into_inner();
         let query = query_params.into_inner();
-        let limit = rqctx.page_limit(&query)?;
+        let limit = rqctx.page_limit(&query.pagination)?;
+        let aggregation =
+            metrics_aggregation(query.aggregation, query.window_secs)?;
         let disk_selector = params::DiskSelector::new(
             Some(path.inner.organization_name.into()),
             Some(path.inner.project_name.into()),
@@ -2743,7 +2993,8 @@ async fn disk_metrics_list(
             .select_timeseries(
                 &format!("crucible_upstairs:{}", path.metric_name),
                 &[&format!("upstairs_uuid=={}", authz_disk.id())],
-                query,
+                query.pagination,
+                aggregation,
                 limit,
             )
             .await?;
@@ -2858,7 +3109,7 @@ async fn instance_create_v1(
             .await?;
         Ok(HttpResponseCreated(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_create_v1", handler).await
 }
 
 /// Create an instance
@@ -2901,7 +3152,7 @@ async fn instance_create(
             .await?;
         Ok(HttpResponseCreated(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_create", handler).await
 }
 
 /// Fetch an instance
@@ -3000,6 +3251,45 @@ async fn instance_view_by_id(
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+/// Update an instance
+///
+/// Currently, the only supported update is changing the number of CPUs and the
+/// amount of memory of a stopped instance.
+#[endpoint {
+    method = PUT,
+    path = "/v1/instances/{instance}",
+    tags = ["instances"],
+}]
+async fn instance_update_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<params::OptionalProjectSelector>,
+    path_params: Path<params::InstancePath>,
+    updated_instance: TypedBody<params::InstanceUpdate>,
+) -> Result<HttpResponseOk<Instance>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let query = query_params.into_inner();
+    let instance_selector = params::InstanceSelector {
+        project_selector: query.project_selector,
+        instance: path.instance,
+    };
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let instance_lookup =
+            nexus.instance_lookup(&opctx, &instance_selector)?;
+        let instance = nexus
+            .instance_update(
+                &opctx,
+                &instance_lookup,
+                &updated_instance.into_inner(),
+            )
+            .await?;
+        Ok(HttpResponseOk(instance.into()))
+    };
+    apictx.audit_and_time(&rqctx, "instance_update_v1", handler).await
+}
+
 /// Delete an instance
 #[endpoint {
     method = DELETE,
@@ -3026,7 +3316,7 @@ async fn instance_delete_v1(
         nexus.project_destroy_instance(&opctx, &instance_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_delete_v1", handler).await
 }
 
 /// Delete an instance
@@ -3055,7 +3345,7 @@ async fn instance_delete(
         nexus.project_destroy_instance(&opctx, &instance_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_delete", handler).await
 }
 
 // TODO should this be in the public API?
@@ -3093,49 +3383,147 @@ async fn instance_migrate_v1(
             .await?;
         Ok(HttpResponseOk(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_migrate_v1", handler).await
 }
 
-// TODO should this be in the public API?
-/// Migrate an instance
-/// Use `POST /v1/instances/{instance}/migrate` instead
+/// List an instance's migrations
 #[endpoint {
-    method = POST,
-    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/migrate",
+    method = GET,
+    path = "/v1/instances/{instance}/migrations",
     tags = ["instances"],
-    deprecated = true,
 }]
-async fn instance_migrate(
+async fn instance_migration_list_v1(
     rqctx: RequestContext<Arc<ServerContext>>,
-    path_params: Path<InstancePathParam>,
-    migrate_params: TypedBody<params::InstanceMigrate>,
-) -> Result<HttpResponseOk<Instance>, HttpError> {
+    query_params: Query<params::OptionalProjectSelector>,
+    path_params: Path<params::InstancePath>,
+) -> Result<HttpResponseOk<ResultsPage<views::Migration>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
-    let migrate_instance_params = migrate_params.into_inner();
-    let instance_selector = params::InstanceSelector::new(
-        Some(path.organization_name.into()),
-        Some(path.project_name.into()),
-        path.instance_name.into(),
-    );
+    let query = query_params.into_inner();
+    let instance_selector = params::InstanceSelector {
+        project_selector: query.project_selector,
+        instance: path.instance,
+    };
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
         let instance_lookup =
             nexus.instance_lookup(&opctx, &instance_selector)?;
-        let instance = nexus
-            .project_instance_migrate(
-                &opctx,
-                &instance_lookup,
-                migrate_instance_params,
-            )
-            .await?;
-        Ok(HttpResponseOk(instance.into()))
+        let migrations = nexus
+            .instance_migration_list(&opctx, &instance_lookup)
+            .await?
+            .into_iter()
+            .map(|m| m.into())
+            .collect();
+        Ok(HttpResponseOk(ResultsPage { items: migrations, next_page: None }))
     };
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
-/// Reboot an instance
+/// Fetch an instance migration
+#[endpoint {
+    method = GET,
+    path = "/v1/instances/{instance}/migrations/{migration}",
+    tags = ["instances"],
+}]
+async fn instance_migration_view_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<params::OptionalProjectSelector>,
+    path_params: Path<params::InstanceMigrationPath>,
+) -> Result<HttpResponseOk<views::Migration>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let query = query_params.into_inner();
+    let instance_selector = params::InstanceSelector {
+        project_selector: query.project_selector,
+        instance: path.instance,
+    };
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let instance_lookup =
+            nexus.instance_lookup(&opctx, &instance_selector)?;
+        let migration = nexus
+            .instance_migration_fetch(&opctx, &instance_lookup, path.migration)
+            .await?;
+        Ok(HttpResponseOk(migration.into()))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+/// Cancel an instance migration
+///
+/// The instance keeps running on the sled it was migrating away from.
+#[endpoint {
+    method = POST,
+    path = "/v1/instances/{instance}/migrations/{migration}/cancel",
+    tags = ["instances"],
+}]
+async fn instance_migration_cancel_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<params::OptionalProjectSelector>,
+    path_params: Path<params::InstanceMigrationPath>,
+) -> Result<HttpResponseOk<views::Migration>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let query = query_params.into_inner();
+    let instance_selector = params::InstanceSelector {
+        project_selector: query.project_selector,
+        instance: path.instance,
+    };
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let instance_lookup =
+            nexus.instance_lookup(&opctx, &instance_selector)?;
+        let migration = nexus
+            .instance_migration_cancel(&opctx, &instance_lookup, path.migration)
+            .await?;
+        Ok(HttpResponseOk(migration.into()))
+    };
+    apictx.audit_and_time(&rqctx, "instance_migration_cancel_v1", handler).await
+}
+
+// TODO should this be in the public API?
+/// Migrate an instance
+/// Use `POST /v1/instances/{instance}/migrate` instead
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/migrate",
+    tags = ["instances"],
+    deprecated = true,
+}]
+async fn instance_migrate(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<InstancePathParam>,
+    migrate_params: TypedBody<params::InstanceMigrate>,
+) -> Result<HttpResponseOk<Instance>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let migrate_instance_params = migrate_params.into_inner();
+    let instance_selector = params::InstanceSelector::new(
+        Some(path.organization_name.into()),
+        Some(path.project_name.into()),
+        path.instance_name.into(),
+    );
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let instance_lookup =
+            nexus.instance_lookup(&opctx, &instance_selector)?;
+        let instance = nexus
+            .project_instance_migrate(
+                &opctx,
+                &instance_lookup,
+                migrate_instance_params,
+            )
+            .await?;
+        Ok(HttpResponseOk(instance.into()))
+    };
+    apictx.audit_and_time(&rqctx, "instance_migrate", handler).await
+}
+
+/// Reboot an instance
 #[endpoint {
     method = POST,
     path = "/v1/instances/{instance}/reboot",
@@ -3161,7 +3549,7 @@ async fn instance_reboot_v1(
         let instance = nexus.instance_reboot(&opctx, &instance_lookup).await?;
         Ok(HttpResponseAccepted(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_reboot_v1", handler).await
 }
 
 /// Reboot an instance
@@ -3191,7 +3579,7 @@ async fn instance_reboot(
         let instance = nexus.instance_reboot(&opctx, &instance_lookup).await?;
         Ok(HttpResponseAccepted(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_reboot", handler).await
 }
 
 /// Boot an instance
@@ -3220,7 +3608,7 @@ async fn instance_start_v1(
         let instance = nexus.instance_start(&opctx, &instance_lookup).await?;
         Ok(HttpResponseAccepted(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_start_v1", handler).await
 }
 
 /// Boot an instance
@@ -3250,7 +3638,7 @@ async fn instance_start(
         let instance = nexus.instance_start(&opctx, &instance_lookup).await?;
         Ok(HttpResponseAccepted(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_start", handler).await
 }
 
 /// Stop an instance
@@ -3279,7 +3667,7 @@ async fn instance_stop_v1(
         let instance = nexus.instance_stop(&opctx, &instance_lookup).await?;
         Ok(HttpResponseAccepted(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_stop_v1", handler).await
 }
 
 /// Halt an instance
@@ -3309,7 +3697,7 @@ async fn instance_stop(
         let instance = nexus.instance_stop(&opctx, &instance_lookup).await?;
         Ok(HttpResponseAccepted(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_stop", handler).await
 }
 
 /// Fetch an instance's serial console
@@ -3550,7 +3938,7 @@ async fn instance_disk_attach_v1(
             nexus.instance_attach_disk(&opctx, &instance_lookup, disk).await?;
         Ok(HttpResponseAccepted(disk.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_disk_attach_v1", handler).await
 }
 
 /// Attach a disk to an instance
@@ -3584,7 +3972,7 @@ async fn instance_disk_attach(
             .await?;
         Ok(HttpResponseAccepted(disk.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_disk_attach", handler).await
 }
 
 /// Detach a disk from an instance
@@ -3616,7 +4004,7 @@ async fn instance_disk_detach_v1(
             nexus.instance_detach_disk(&opctx, &instance_lookup, disk).await?;
         Ok(HttpResponseAccepted(disk.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_disk_detach_v1", handler).await
 }
 
 /// Detach a disk from an instance
@@ -3650,7 +4038,7 @@ async fn instance_disk_detach(
             .await?;
         Ok(HttpResponseAccepted(disk.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "instance_disk_detach", handler).await
 }
 
 // Certificates
@@ -3752,7 +4140,7 @@ async fn certificate_create_v1(
         let cert = nexus.certificate_create(&opctx, new_cert_params).await?;
         Ok(HttpResponseCreated(cert.try_into()?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "certificate_create_v1", handler).await
 }
 
 /// Create a new system-wide x.509 certificate.
@@ -3778,7 +4166,7 @@ async fn certificate_create(
         let cert = nexus.certificate_create(&opctx, new_cert_params).await?;
         Ok(HttpResponseCreated(cert.try_into()?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "certificate_create", handler).await
 }
 
 /// Path parameters for Certificate requests
@@ -3862,7 +4250,7 @@ async fn certificate_delete_v1(
             .await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "certificate_delete_v1", handler).await
 }
 
 /// Delete a certificate
@@ -3892,7 +4280,7 @@ async fn certificate_delete(
             .await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "certificate_delete", handler).await
 }
 
 // Images
@@ -3955,7 +4343,7 @@ async fn system_image_create(
         let image = nexus.global_image_create(&opctx, new_image_params).await?;
         Ok(HttpResponseCreated(image.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "system_image_create", handler).await
 }
 
 /// Path parameters for Image requests
@@ -4033,7 +4421,7 @@ async fn system_image_delete(
         nexus.global_image_delete(&opctx, &image_name).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "system_image_delete", handler).await
 }
 
 /// List images
@@ -4110,7 +4498,7 @@ async fn image_create(
             .await?;
         Ok(HttpResponseCreated(image.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "image_create", handler).await
 }
 
 /// Path parameters for Image requests
@@ -4208,7 +4596,7 @@ async fn image_delete(
             .await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "image_delete", handler).await
 }
 
 /// List network interfaces
@@ -4316,7 +4704,9 @@ async fn instance_network_interface_create_v1(
             .await?;
         Ok(HttpResponseCreated(iface.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .audit_and_time(&rqctx, "instance_network_interface_create_v1", handler)
+        .await
 }
 
 /// Create a network interface
@@ -4353,7 +4743,9 @@ async fn instance_network_interface_create(
             .await?;
         Ok(HttpResponseCreated(iface.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .audit_and_time(&rqctx, "instance_network_interface_create", handler)
+        .await
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
@@ -4395,7 +4787,9 @@ async fn instance_network_interface_delete_v1(
         nexus.network_interface_delete(&opctx, &interface_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .audit_and_time(&rqctx, "instance_network_interface_delete_v1", handler)
+        .await
 }
 
 /// Delete a network interface
@@ -4432,7 +4826,9 @@ async fn instance_network_interface_delete(
         nexus.network_interface_delete(&opctx, &interface_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .audit_and_time(&rqctx, "instance_network_interface_delete", handler)
+        .await
 }
 
 /// Fetch a network interface
@@ -4562,7 +4958,9 @@ async fn instance_network_interface_update_v1(
             .await?;
         Ok(HttpResponseOk(NetworkInterface::from(interface)))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .audit_and_time(&rqctx, "instance_network_interface_update_v1", handler)
+        .await
 }
 
 /// Update a network interface
@@ -4597,7 +4995,9 @@ async fn instance_network_interface_update(
             .await?;
         Ok(HttpResponseOk(interface.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .audit_and_time(&rqctx, "instance_network_interface_update", handler)
+        .await
 }
 
 // External IP addresses for instances
@@ -4633,6 +5033,359 @@ async fn instance_external_ip_list(
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+// Floating IPs
+
+/// List floating IPs
+#[endpoint {
+    method = GET,
+    path = "/v1/floating-ips",
+    tags = ["floating-ips"],
+}]
+async fn floating_ip_list_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<PaginatedByNameOrId<params::ProjectSelector>>,
+) -> Result<HttpResponseOk<ResultsPage<FloatingIp>>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let query = query_params.into_inner();
+        let pag_params = data_page_params_for(&rqctx, &query)?;
+        let scan_params = ScanByNameOrId::from_query(&query)?;
+        let paginated_by = name_or_id_pagination(&pag_params, scan_params)?;
+        let project_lookup =
+            nexus.project_lookup(&opctx, &scan_params.selector)?;
+        let ips = nexus
+            .floating_ip_list(&opctx, &project_lookup, &paginated_by)
+            .await?
+            .into_iter()
+            .map(|ip| ip.into())
+            .collect();
+        Ok(HttpResponseOk(ScanByNameOrId::results_page(
+            &query,
+            ips,
+            &marker_for_name_or_id,
+        )?))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+/// Create a floating IP
+///
+/// Reserves an address from an IP pool for use by instances in a project.
+#[endpoint {
+    method = POST,
+    path = "/v1/floating-ips",
+    tags = ["floating-ips"],
+}]
+async fn floating_ip_create_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<params::ProjectSelector>,
+    floating_params: TypedBody<params::FloatingIpCreate>,
+) -> Result<HttpResponseCreated<FloatingIp>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let query = query_params.into_inner();
+        let floating_params = floating_params.into_inner();
+        let project_lookup = nexus.project_lookup(&opctx, &query)?;
+        let ip = nexus
+            .project_create_floating_ip(
+                &opctx,
+                &project_lookup,
+                &floating_params,
+            )
+            .await?;
+        Ok(HttpResponseCreated(ip.into()))
+    };
+    apictx.audit_and_time(&rqctx, "floating_ip_create_v1", handler).await
+}
+
+/// Fetch a floating IP
+#[endpoint {
+    method = GET,
+    path = "/v1/floating-ips/{floating_ip}",
+    tags = ["floating-ips"],
+}]
+async fn floating_ip_view_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::FloatingIpPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+) -> Result<HttpResponseOk<FloatingIp>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let floating_ip_selector = params::FloatingIpSelector {
+            project_selector: query.project_selector,
+            floating_ip: path.floating_ip,
+        };
+        let (.., fip) = nexus
+            .floating_ip_lookup(&opctx, &floating_ip_selector)?
+            .fetch()
+            .await?;
+        Ok(HttpResponseOk(fip.into()))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+/// Delete a floating IP
+///
+/// Floating IPs must be detached from any instance before they are deleted.
+#[endpoint {
+    method = DELETE,
+    path = "/v1/floating-ips/{floating_ip}",
+    tags = ["floating-ips"],
+}]
+async fn floating_ip_delete_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::FloatingIpPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let floating_ip_selector = params::FloatingIpSelector {
+            project_selector: query.project_selector,
+            floating_ip: path.floating_ip,
+        };
+        let fip_lookup =
+            nexus.floating_ip_lookup(&opctx, &floating_ip_selector)?;
+        nexus.floating_ip_delete(&opctx, &fip_lookup).await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx.audit_and_time(&rqctx, "floating_ip_delete_v1", handler).await
+}
+
+/// Attach a floating IP to an instance
+#[endpoint {
+    method = POST,
+    path = "/v1/floating-ips/{floating_ip}/attach",
+    tags = ["floating-ips"],
+}]
+async fn floating_ip_attach_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::FloatingIpPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+    target: TypedBody<params::FloatingIpAttach>,
+) -> Result<HttpResponseAccepted<FloatingIp>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let floating_ip_selector = params::FloatingIpSelector {
+            project_selector: query.project_selector,
+            floating_ip: path.floating_ip,
+        };
+        let fip_lookup =
+            nexus.floating_ip_lookup(&opctx, &floating_ip_selector)?;
+        let fip = nexus
+            .floating_ip_attach(&opctx, &fip_lookup, &target.into_inner())
+            .await?;
+        Ok(HttpResponseAccepted(fip.into()))
+    };
+    apictx.audit_and_time(&rqctx, "floating_ip_attach_v1", handler).await
+}
+
+/// Detach a floating IP from an instance
+#[endpoint {
+    method = POST,
+    path = "/v1/floating-ips/{floating_ip}/detach",
+    tags = ["floating-ips"],
+}]
+async fn floating_ip_detach_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::FloatingIpPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+) -> Result<HttpResponseAccepted<FloatingIp>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let floating_ip_selector = params::FloatingIpSelector {
+            project_selector: query.project_selector,
+            floating_ip: path.floating_ip,
+        };
+        let fip_lookup =
+            nexus.floating_ip_lookup(&opctx, &floating_ip_selector)?;
+        let fip = nexus.floating_ip_detach(&opctx, &fip_lookup).await?;
+        Ok(HttpResponseAccepted(fip.into()))
+    };
+    apictx.audit_and_time(&rqctx, "floating_ip_detach_v1", handler).await
+}
+
+// Affinity groups
+
+/// List affinity groups
+#[endpoint {
+    method = GET,
+    path = "/v1/affinity-groups",
+    tags = ["affinity"],
+}]
+async fn affinity_group_list_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<PaginatedByNameOrId<params::ProjectSelector>>,
+) -> Result<HttpResponseOk<ResultsPage<AffinityGroup>>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let query = query_params.into_inner();
+        let pag_params = data_page_params_for(&rqctx, &query)?;
+        let scan_params = ScanByNameOrId::from_query(&query)?;
+        let paginated_by = name_or_id_pagination(&pag_params, scan_params)?;
+        let project_lookup =
+            nexus.project_lookup(&opctx, &scan_params.selector)?;
+        let groups = nexus
+            .affinity_group_list(&opctx, &project_lookup, &paginated_by)
+            .await?
+            .into_iter()
+            .map(|group| group.into())
+            .collect();
+        Ok(HttpResponseOk(ScanByNameOrId::results_page(
+            &query,
+            groups,
+            &marker_for_name_or_id,
+        )?))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+/// Create an affinity group
+///
+/// Instances join affinity groups when they're created, which constrains the
+/// sleds on which they're placed.
+#[endpoint {
+    method = POST,
+    path = "/v1/affinity-groups",
+    tags = ["affinity"],
+}]
+async fn affinity_group_create_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<params::ProjectSelector>,
+    new_group: TypedBody<params::AffinityGroupCreate>,
+) -> Result<HttpResponseCreated<AffinityGroup>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let query = query_params.into_inner();
+        let new_group = new_group.into_inner();
+        let project_lookup = nexus.project_lookup(&opctx, &query)?;
+        let group = nexus
+            .project_create_affinity_group(&opctx, &project_lookup, &new_group)
+            .await?;
+        Ok(HttpResponseCreated(group.into()))
+    };
+    apictx.audit_and_time(&rqctx, "affinity_group_create_v1", handler).await
+}
+
+/// Fetch an affinity group
+#[endpoint {
+    method = GET,
+    path = "/v1/affinity-groups/{affinity_group}",
+    tags = ["affinity"],
+}]
+async fn affinity_group_view_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::AffinityGroupPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+) -> Result<HttpResponseOk<AffinityGroup>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let group_selector = params::AffinityGroupSelector {
+            project_selector: query.project_selector,
+            affinity_group: path.affinity_group,
+        };
+        let (.., group) = nexus
+            .affinity_group_lookup(&opctx, &group_selector)?
+            .fetch()
+            .await?;
+        Ok(HttpResponseOk(group.into()))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+/// Delete an affinity group
+///
+/// The group's members are unaffected, other than no longer being placed
+/// according to the group.
+#[endpoint {
+    method = DELETE,
+    path = "/v1/affinity-groups/{affinity_group}",
+    tags = ["affinity"],
+}]
+async fn affinity_group_delete_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::AffinityGroupPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let group_selector = params::AffinityGroupSelector {
+            project_selector: query.project_selector,
+            affinity_group: path.affinity_group,
+        };
+        let group_lookup =
+            nexus.affinity_group_lookup(&opctx, &group_selector)?;
+        nexus.affinity_group_delete(&opctx, &group_lookup).await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx.audit_and_time(&rqctx, "affinity_group_delete_v1", handler).await
+}
+
+/// List the instances in an affinity group
+#[endpoint {
+    method = GET,
+    path = "/v1/affinity-groups/{affinity_group}/members",
+    tags = ["affinity"],
+}]
+async fn affinity_group_member_list_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::AffinityGroupPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+) -> Result<HttpResponseOk<ResultsPage<Instance>>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let group_selector = params::AffinityGroupSelector {
+            project_selector: query.project_selector,
+            affinity_group: path.affinity_group,
+        };
+        let group_lookup =
+            nexus.affinity_group_lookup(&opctx, &group_selector)?;
+        let members = nexus
+            .affinity_group_member_list(&opctx, &group_lookup)
+            .await?
+            .into_iter()
+            .map(|instance| instance.into())
+            .collect();
+        Ok(HttpResponseOk(ResultsPage { items: members, next_page: None }))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
 // Snapshots
 
 /// List snapshots
@@ -4738,7 +5491,7 @@ async fn snapshot_create_v1(
             .await?;
         Ok(HttpResponseCreated(snapshot.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "snapshot_create_v1", handler).await
 }
 
 /// Create a snapshot
@@ -4770,7 +5523,7 @@ async fn snapshot_create(
             .await?;
         Ok(HttpResponseCreated(snapshot.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "snapshot_create", handler).await
 }
 
 /// Path parameters for Snapshot requests
@@ -4892,7 +5645,7 @@ async fn snapshot_delete_v1(
         nexus.snapshot_delete(&opctx, &snapshot_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "snapshot_delete_v1", handler).await
 }
 
 /// Delete a snapshot
@@ -4922,7 +5675,7 @@ async fn snapshot_delete(
         nexus.snapshot_delete(&opctx, &snapshot_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "snapshot_delete", handler).await
 }
 
 // VPCs
@@ -5030,7 +5783,7 @@ async fn vpc_create_v1(
             .await?;
         Ok(HttpResponseCreated(vpc.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_create_v1", handler).await
 }
 
 /// Create a VPC
@@ -5062,7 +5815,7 @@ async fn vpc_create(
             .await?;
         Ok(HttpResponseCreated(vpc.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_create", handler).await
 }
 
 /// Fetch a VPC
@@ -5184,7 +5937,7 @@ async fn vpc_update_v1(
             .await?;
         Ok(HttpResponseOk(vpc.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_update_v1", handler).await
 }
 
 /// Update a VPC
@@ -5216,7 +5969,7 @@ async fn vpc_update(
             .await?;
         Ok(HttpResponseOk(newvpc.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_update", handler).await
 }
 
 /// Delete a VPC
@@ -5244,7 +5997,7 @@ async fn vpc_delete_v1(
         nexus.project_delete_vpc(&opctx, &vpc_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_delete_v1", handler).await
 }
 
 /// Delete a VPC
@@ -5273,7 +6026,7 @@ async fn vpc_delete(
         nexus.project_delete_vpc(&opctx, &vpc_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_delete", handler).await
 }
 
 /// Fetch a subnet
@@ -5376,7 +6129,7 @@ async fn vpc_subnet_create_v1(
             nexus.vpc_create_subnet(&opctx, &vpc_lookup, &create).await?;
         Ok(HttpResponseCreated(subnet.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_subnet_create_v1", handler).await
 }
 
 /// Create a subnet
@@ -5408,7 +6161,7 @@ async fn vpc_subnet_create(
             .await?;
         Ok(HttpResponseCreated(subnet.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_subnet_create", handler).await
 }
 
 /// Fetch a subnet
@@ -5532,7 +6285,7 @@ async fn vpc_subnet_delete_v1(
         nexus.vpc_delete_subnet(&opctx, &subnet_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_subnet_delete_v1", handler).await
 }
 
 /// Delete a subnet
@@ -5563,7 +6316,7 @@ async fn vpc_subnet_delete(
         nexus.vpc_delete_subnet(&opctx, &subnet_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_subnet_delete", handler).await
 }
 
 /// Update a subnet
@@ -5596,7 +6349,7 @@ async fn vpc_subnet_update_v1(
             .await?;
         Ok(HttpResponseOk(subnet.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_subnet_update_v1", handler).await
 }
 
 /// Update a subnet
@@ -5634,7 +6387,7 @@ async fn vpc_subnet_update(
             .await?;
         Ok(HttpResponseOk(subnet.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_subnet_update", handler).await
 }
 
 /// List network interfaces
@@ -5768,7 +6521,7 @@ async fn vpc_firewall_rules_update_v1(
             rules: rules.into_iter().map(|rule| rule.into()).collect(),
         }))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_firewall_rules_update_v1", handler).await
 }
 
 /// Replace firewall rules
@@ -5808,7 +6561,7 @@ async fn vpc_firewall_rules_update(
             rules: rules.into_iter().map(|rule| rule.into()).collect(),
         }))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_firewall_rules_update", handler).await
 }
 
 // VPC Routers
@@ -6017,7 +6770,7 @@ async fn vpc_router_create_v1(
             .await?;
         Ok(HttpResponseCreated(router.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_router_create_v1", handler).await
 }
 
 /// Create a router
@@ -6054,7 +6807,7 @@ async fn vpc_router_create(
             .await?;
         Ok(HttpResponseCreated(router.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_router_create", handler).await
 }
 
 /// Delete a router
@@ -6083,7 +6836,7 @@ async fn vpc_router_delete_v1(
         nexus.vpc_delete_router(&opctx, &router_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_router_delete_v1", handler).await
 }
 
 /// Delete a router
@@ -6114,7 +6867,7 @@ async fn vpc_router_delete(
         nexus.vpc_delete_router(&opctx, &router_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_router_delete", handler).await
 }
 
 /// Update a router
@@ -6147,7 +6900,7 @@ async fn vpc_router_update_v1(
             .await?;
         Ok(HttpResponseOk(router.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_router_update_v1", handler).await
 }
 
 /// Update a router
@@ -6184,7 +6937,7 @@ async fn vpc_router_update(
             .await?;
         Ok(HttpResponseOk(router.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_router_update", handler).await
 }
 
 /// List routes
@@ -6405,7 +7158,7 @@ async fn vpc_router_route_create_v1(
             .await?;
         Ok(HttpResponseCreated(route.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_router_route_create_v1", handler).await
 }
 
 /// Create a router
@@ -6444,7 +7197,7 @@ async fn vpc_router_route_create(
             .await?;
         Ok(HttpResponseCreated(route.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_router_route_create", handler).await
 }
 
 /// Delete a route
@@ -6473,7 +7226,7 @@ async fn vpc_router_route_delete_v1(
         nexus.router_delete_route(&opctx, &route_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_router_route_delete_v1", handler).await
 }
 
 /// Delete a route
@@ -6505,7 +7258,7 @@ async fn vpc_router_route_delete(
         nexus.router_delete_route(&opctx, &route_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_router_route_delete", handler).await
 }
 /// Update a route
 #[endpoint {
@@ -6537,7 +7290,7 @@ async fn vpc_router_route_update_v1(
             .await?;
         Ok(HttpResponseOk(route.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_router_route_update_v1", handler).await
 }
 
 /// Update a route
@@ -6576,7 +7329,7 @@ async fn vpc_router_route_update(
             .await?;
         Ok(HttpResponseOk(route.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "vpc_router_route_update", handler).await
 }
 
 // Racks
@@ -6809,6 +7562,109 @@ async fn sled_view(
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+/// Cordon a sled
+///
+/// No new instances or regions are placed on a cordoned sled, but whatever is
+/// already running there is left alone.
+#[endpoint {
+    method = POST,
+    path = "/v1/system/hardware/sleds/{sled_id}/cordon",
+    tags = ["system"],
+}]
+async fn sled_cordon_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<SledPathParam>,
+) -> Result<HttpResponseOk<Sled>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let sled = nexus
+            .sled_set_provision_state(
+                &opctx,
+                path.sled_id,
+                db::model::SledProvisionState::Cordoned,
+            )
+            .await?;
+        Ok(HttpResponseOk(sled.into()))
+    };
+    apictx.audit_and_time(&rqctx, "sled_cordon_v1", handler).await
+}
+
+/// Return a cordoned or draining sled to service
+#[endpoint {
+    method = POST,
+    path = "/v1/system/hardware/sleds/{sled_id}/uncordon",
+    tags = ["system"],
+}]
+async fn sled_uncordon_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<SledPathParam>,
+) -> Result<HttpResponseOk<Sled>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let sled = nexus
+            .sled_set_provision_state(
+                &opctx,
+                path.sled_id,
+                db::model::SledProvisionState::Active,
+            )
+            .await?;
+        Ok(HttpResponseOk(sled.into()))
+    };
+    apictx.audit_and_time(&rqctx, "sled_uncordon_v1", handler).await
+}
+
+/// Drain a sled
+///
+/// Cordons the sled and moves its instances to other sleds: running instances
+/// are live-migrated, and stopped ones will start elsewhere.  Draining a sled
+/// which is already draining retries any instances which couldn't be moved.
+#[endpoint {
+    method = POST,
+    path = "/v1/system/hardware/sleds/{sled_id}/drain",
+    tags = ["system"],
+}]
+async fn sled_drain_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<SledPathParam>,
+) -> Result<HttpResponseOk<views::SledDrainStatus>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let status = nexus.sled_drain(&opctx, path.sled_id).await?;
+        Ok(HttpResponseOk(status))
+    };
+    apictx.audit_and_time(&rqctx, "sled_drain_v1", handler).await
+}
+
+/// Fetch the progress of draining a sled
+#[endpoint {
+    method = GET,
+    path = "/v1/system/hardware/sleds/{sled_id}/drain",
+    tags = ["system"],
+}]
+async fn sled_drain_view_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<SledPathParam>,
+) -> Result<HttpResponseOk<views::SledDrainStatus>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let status = nexus.sled_drain_status(&opctx, path.sled_id).await?;
+        Ok(HttpResponseOk(status))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
 // Physical disks
 
 /// List physical disks
@@ -6949,6 +7805,28 @@ async fn sled_physical_disk_list(
 
 // Metrics
 
+// The aggregation parameters are repeated in each of these rather than
+// flattened in from a common struct: serde can't parse numbers out of a query
+// string in a struct that is itself flattened.
+
+/// Query parameters for the metrics of a single resource
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ResourceMetricsParams {
+    #[serde(flatten)]
+    pub pagination: dropshot::PaginationParams<
+        params::ResourceMetrics,
+        params::ResourceMetrics,
+    >,
+
+    /// If given, the measurements are combined with this function into one
+    /// per window of `window_secs` seconds.  It must be given on every page.
+    pub aggregation: Option<oximeter_db::query::Reducer>,
+
+    /// The length of the windows over which measurements are aggregated, in
+    /// seconds.  Required with `aggregation`.
+    pub window_secs: Option<NonZeroU32>,
+}
+
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct SystemMetricParams {
     #[serde(flatten)]
@@ -6960,6 +7838,37 @@ pub struct SystemMetricParams {
     /// The UUID of the container being queried
     // TODO: I might want to force the caller to specify type here?
     pub id: Uuid,
+
+    /// If given, the measurements are combined with this function into one
+    /// per window of `window_secs` seconds.  It must be given on every page.
+    pub aggregation: Option<oximeter_db::query::Reducer>,
+
+    /// The length of the windows over which measurements are aggregated, in
+    /// seconds.  Required with `aggregation`.
+    pub window_secs: Option<NonZeroU32>,
+}
+
+/// Returns how to aggregate the measurements requested from a metrics
+/// endpoint, given its `aggregation` and `window_secs` query parameters.
+fn metrics_aggregation(
+    reducer: Option<oximeter_db::query::Reducer>,
+    window_secs: Option<NonZeroU32>,
+) -> Result<Option<oximeter_db::query::Aggregation>, Error> {
+    match (reducer, window_secs) {
+        (None, None) => Ok(None),
+        (Some(reducer), Some(window_secs)) => {
+            Ok(Some(oximeter_db::query::Aggregation {
+                reducer,
+                group_by: Vec::new(),
+                window_secs,
+                rate: false,
+                statistic: None,
+            }))
+        }
+        _ => Err(Error::invalid_request(
+            "\"aggregation\" and \"window_secs\" must be given together",
+        )),
+    }
 }
 
 #[derive(Display, Deserialize, JsonSchema)]
@@ -6996,8 +7905,16 @@ async fn system_metric(
 
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
+        let aggregation =
+            metrics_aggregation(query.aggregation, query.window_secs)?;
         let result = nexus
-            .system_metric_lookup(&opctx, metric_name, query, limit)
+            .system_metric_lookup(
+                &opctx,
+                metric_name,
+                query,
+                aggregation,
+                limit,
+            )
             .await?;
 
         Ok(HttpResponseOk(result))
@@ -7023,7 +7940,7 @@ async fn system_update_refresh(
         nexus.updates_refresh_metadata(&opctx).await?;
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "system_update_refresh", handler).await
 }
 
 /// View system version and update status
@@ -7218,7 +8135,7 @@ async fn system_update_start(
             status: views::UpdateStatus::Updating,
         }))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "system_update_start", handler).await
 }
 
 /// Stop system update
@@ -7255,7 +8172,7 @@ async fn system_update_stop(
 
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "system_update_stop", handler).await
 }
 
 /// List all update deployments
@@ -7572,6 +8489,244 @@ async fn timeseries_schema_get(
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+// Alerts
+
+/// List alert rules
+///
+/// Returns a list of all the alert rules, along with the outcome of their
+/// latest evaluation.
+#[endpoint {
+    method = GET,
+    path = "/v1/system/alert-rules",
+    tags = ["system"],
+}]
+async fn alert_rule_list_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<PaginatedByNameOrId>,
+) -> Result<HttpResponseOk<ResultsPage<AlertRule>>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let query = query_params.into_inner();
+        let pag_params = data_page_params_for(&rqctx, &query)?;
+        let scan_params = ScanByNameOrId::from_query(&query)?;
+        let paginated_by = name_or_id_pagination(&pag_params, scan_params)?;
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let rules = nexus
+            .alert_rule_list(&opctx, &paginated_by)
+            .await?
+            .into_iter()
+            .map(|r| r.try_into())
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(HttpResponseOk(ScanByNameOrId::results_page(
+            &query,
+            rules,
+            &marker_for_name_or_id,
+        )?))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+/// Create an alert rule
+///
+/// The rule fires when an aggregation of a timeseries has been past the
+/// threshold for `duration_secs`, and is resolved once it no longer is.  A rule
+/// whose timeseries has no recent data keeps its state.
+#[endpoint {
+    method = POST,
+    path = "/v1/system/alert-rules",
+    tags = ["system"],
+}]
+async fn alert_rule_create_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    new_rule: TypedBody<params::AlertRuleCreate>,
+) -> Result<HttpResponseCreated<AlertRule>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let new_rule_params = new_rule.into_inner();
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let rule = nexus.alert_rule_create(&opctx, new_rule_params).await?;
+        Ok(HttpResponseCreated(rule.try_into()?))
+    };
+    apictx.audit_and_time(&rqctx, "alert_rule_create_v1", handler).await
+}
+
+/// Path parameters for alert rule requests
+#[derive(Deserialize, JsonSchema)]
+struct AlertRulePathParam {
+    alert_rule: NameOrId,
+}
+
+/// Fetch an alert rule
+#[endpoint {
+    method = GET,
+    path = "/v1/system/alert-rules/{alert_rule}",
+    tags = ["system"],
+}]
+async fn alert_rule_view_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<AlertRulePathParam>,
+) -> Result<HttpResponseOk<AlertRule>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let (.., rule) =
+            nexus.alert_rule_lookup(&opctx, &path.alert_rule).fetch().await?;
+        Ok(HttpResponseOk(rule.try_into()?))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+/// Delete an alert rule
+///
+/// Resolves the rule's alert if it's firing.  Its past alerts are kept.
+#[endpoint {
+    method = DELETE,
+    path = "/v1/system/alert-rules/{alert_rule}",
+    tags = ["system"],
+}]
+async fn alert_rule_delete_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<AlertRulePathParam>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let rule_lookup = nexus.alert_rule_lookup(&opctx, &path.alert_rule);
+        nexus.alert_rule_delete(&opctx, &rule_lookup).await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx.audit_and_time(&rqctx, "alert_rule_delete_v1", handler).await
+}
+
+// Alerts are paginated by the time at which they fired, with the alert's id
+// breaking ties.
+#[derive(Deserialize, JsonSchema, Serialize)]
+struct AlertPage {
+    last_seen_time: DateTime<Utc>,
+    last_seen_id: Uuid,
+}
+
+/// List an alert rule's alerts
+///
+/// Lists the periods during which the rule was firing, in the order in which
+/// they started.
+#[endpoint {
+    method = GET,
+    path = "/v1/system/alert-rules/{alert_rule}/alerts",
+    tags = ["system"],
+}]
+async fn alert_list_v1(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<AlertRulePathParam>,
+    query_params: Query<PaginationParams<EmptyScanParams, AlertPage>>,
+) -> Result<HttpResponseOk<ResultsPage<Alert>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let query = query_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let marker = match &query.page {
+            WhichPage::First(_) => None,
+            WhichPage::Next(AlertPage { last_seen_time, last_seen_id }) => {
+                Some((*last_seen_time, *last_seen_id))
+            }
+        };
+        let pagparams = DataPageParams {
+            limit: rqctx.page_limit(&query)?,
+            direction: PaginationOrder::Ascending,
+            marker: marker.as_ref(),
+        };
+        let rule_lookup = nexus.alert_rule_lookup(&opctx, &path.alert_rule);
+        let alerts = nexus
+            .alert_list(&opctx, &rule_lookup, &pagparams)
+            .await?
+            .into_iter()
+            .map(|a| a.into())
+            .collect();
+        Ok(HttpResponseOk(ResultsPage::new(
+            alerts,
+            &EmptyScanParams {},
+            |alert: &Alert, _| AlertPage {
+                last_seen_time: alert.time_fired,
+                last_seen_id: alert.id,
+            },
+        )?))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+// Audit log
+
+// Audit log entries are paginated by the time at which the request was
+// received, with the entry's id breaking ties.
+#[derive(Deserialize, JsonSchema, Serialize)]
+struct AuditLogPage {
+    #[serde(flatten)]
+    scan: params::AuditLogParams,
+    last_seen_time: DateTime<Utc>,
+    last_seen_id: Uuid,
+}
+
+/// List audit log entries
+///
+/// Lists the mutating requests made to the external API, in the order in
+/// which they were received.  Fleet viewers can see every entry, while Silo
+/// viewers can see the entries for requests made by their Silo's users.
+#[endpoint {
+    method = GET,
+    path = "/v1/audit-log",
+    tags = ["audit-log"],
+}]
+async fn audit_log_list(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<PaginationParams<params::AuditLogParams, AuditLogPage>>,
+) -> Result<HttpResponseOk<ResultsPage<AuditLogEntry>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let (scan, marker) = match &query.page {
+            WhichPage::First(scan) => (scan, None),
+            WhichPage::Next(AuditLogPage {
+                scan,
+                last_seen_time,
+                last_seen_id,
+            }) => (scan, Some((*last_seen_time, *last_seen_id))),
+        };
+        let pagparams = DataPageParams {
+            limit: rqctx.page_limit(&query)?,
+            direction: PaginationOrder::Ascending,
+            marker: marker.as_ref(),
+        };
+        let entries = nexus
+            .audit_log_list(&opctx, scan, &pagparams)
+            .await?
+            .into_iter()
+            .map(|e| e.into())
+            .collect();
+        Ok(HttpResponseOk(ResultsPage::new(
+            entries,
+            scan,
+            |entry: &AuditLogEntry, scan: &params::AuditLogParams| {
+                AuditLogPage {
+                    scan: scan.clone(),
+                    last_seen_time: entry.time_started,
+                    last_seen_id: entry.id,
+                }
+            },
+        )?))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
 // Built-in roles
 
 // Roles have their own pagination scheme because they do not use the usual "id"
@@ -7722,7 +8877,7 @@ async fn session_sshkey_create(
             .await?;
         Ok(HttpResponseCreated(ssh_key.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "session_sshkey_create", handler).await
 }
 
 /// Path parameters for SSH key requests by name
@@ -7785,7 +8940,7 @@ async fn session_sshkey_delete(
         nexus.ssh_key_delete(&opctx, actor.actor_id(), ssh_key_name).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.audit_and_time(&rqctx, "session_sshkey_delete", handler).await
 }
 
 /// Path parameters for metrics requests where `/metrics/{metric_name}` is
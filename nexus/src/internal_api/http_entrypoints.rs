@@ -27,7 +27,7 @@ use omicron_common::api::internal::nexus::InstanceRuntimeState;
 use omicron_common::api::internal::nexus::ProducerEndpoint;
 use omicron_common::api::internal::nexus::UpdateArtifactId;
 use oximeter::types::ProducerResults;
-use oximeter_producer::{collect, ProducerIdPathParams};
+use oximeter_producer::{collect, prometheus_metrics, ProducerIdPathParams};
 use schemars::JsonSchema;
 use serde::Deserialize;
 use std::sync::Arc;
@@ -36,8 +36,14 @@ use uuid::Uuid;
 type NexusApiDescription = ApiDescription<Arc<ServerContext>>;
 
 /// Returns a description of the internal nexus API
-pub fn internal_api() -> NexusApiDescription {
-    fn register_endpoints(api: &mut NexusApiDescription) -> Result<(), String> {
+///
+/// If `prometheus` is set, this includes an endpoint serving Nexus's own
+/// metrics in the Prometheus text exposition format.
+pub fn internal_api(prometheus: bool) -> NexusApiDescription {
+    fn register_endpoints(
+        api: &mut NexusApiDescription,
+        prometheus: bool,
+    ) -> Result<(), String> {
         api.register(sled_agent_put)?;
         api.register(rack_initialization_complete)?;
         api.register(physical_disk_put)?;
@@ -50,12 +56,15 @@ pub fn internal_api() -> NexusApiDescription {
         api.register(cpapi_producers_post)?;
         api.register(cpapi_collectors_post)?;
         api.register(cpapi_metrics_collect)?;
+        if prometheus {
+            api.register(cpapi_metrics_prometheus)?;
+        }
         api.register(cpapi_artifact_download)?;
         Ok(())
     }
 
     let mut api = NexusApiDescription::new();
-    if let Err(err) = register_endpoints(&mut api) {
+    if let Err(err) = register_endpoints(&mut api, prometheus) {
         panic!("failed to register entrypoints: {}", err);
     }
     api
@@ -205,7 +214,10 @@ async fn cpapi_instances_put(
     let path = path_params.into_inner();
     let new_state = new_runtime_state.into_inner();
     let handler = async {
-        nexus.notify_instance_updated(&path.instance_id, &new_state).await?;
+        let opctx = OpContext::for_internal_api(&rqctx).await;
+        nexus
+            .notify_instance_updated(&opctx, &path.instance_id, &new_state)
+            .await?;
         Ok(HttpResponseUpdatedNoContent())
     };
     apictx.internal_latencies.instrument_dropshot_handler(&rqctx, handler).await
@@ -361,6 +373,20 @@ async fn cpapi_metrics_collect(
         .await
 }
 
+/// Endpoint for Prometheus to scrape nexus server metrics, during development.
+#[endpoint {
+    method = GET,
+    path = "/metrics",
+    unpublished = true,
+}]
+async fn cpapi_metrics_prometheus(
+    request_context: RequestContext<Arc<ServerContext>>,
+) -> Result<http::Response<Body>, HttpError> {
+    // This isn't instrumented, since the latency instruments need a known
+    // success status code, which raw responses don't have.
+    prometheus_metrics(&request_context.context().producer_registry).await
+}
+
 /// Endpoint used by Sled Agents to download cached artifacts.
 #[endpoint {
     method = GET,
@@ -58,7 +58,7 @@ pub fn run_openapi_external() -> Result<(), String> {
 }
 
 pub fn run_openapi_internal() -> Result<(), String> {
-    internal_api()
+    internal_api(false)
         .openapi("Nexus internal API", "0.0.1")
         .description("Nexus internal API")
         .contact_url("https://oxide.computer")
@@ -97,7 +97,7 @@ impl<'a> InternalServer<'a> {
         // Launch the internal server.
         let server_starter_internal = dropshot::HttpServerStarter::new(
             &config.deployment.dropshot_internal,
-            internal_api(),
+            internal_api(config.pkg.prometheus),
             Arc::clone(&apictx),
             &log.new(o!("component" => "dropshot_internal")),
         )
@@ -160,6 +160,10 @@ impl Server {
             .await
             .map_err(|e| e.to_string())?;
 
+        // Publish the external DNS zone, in case anything changed while no
+        // Nexus was running.
+        apictx.nexus.external_dns_sync_activate();
+
         let server = Server { apictx: apictx.clone() };
         Ok(server)
     }
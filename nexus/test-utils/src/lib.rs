@@ -21,6 +21,7 @@ use slog::o;
 use slog::Logger;
 use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
 use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 use uuid::Uuid;
 
@@ -33,6 +34,9 @@ pub const RACK_UUID: &str = "c19a698f-c6f9-4a17-ae30-20d711b8f7dc";
 pub const OXIMETER_UUID: &str = "39e6175b-4df2-4730-b11d-cbc1e60a2e78";
 pub const PRODUCER_UUID: &str = "a6458b7d-87c3-4483-be96-854d814c20de";
 
+/// Zone in which the test Nexus publishes external DNS names.
+pub const EXTERNAL_DNS_ZONE: &str = "oxide-dev.test";
+
 pub struct ControlPlaneTestContext<N> {
     pub external_client: ClientTestContext,
     pub internal_client: ClientTestContext,
@@ -43,11 +47,13 @@ pub struct ControlPlaneTestContext<N> {
     pub sled_agent: sim::Server,
     pub oximeter: Oximeter,
     pub producer: ProducerServer,
+    pub external_dns: ExternalDnsServer,
 }
 
 impl<N: NexusServer> ControlPlaneTestContext<N> {
     pub async fn teardown(mut self) {
         self.server.close().await;
+        self.external_dns.dropshot_server.close().await.unwrap();
         self.database.cleanup().await.unwrap();
         self.clickhouse.cleanup().await.unwrap();
         self.sled_agent.http_server.close().await.unwrap();
@@ -87,6 +93,52 @@ impl<N: NexusServer> ControlPlaneTestContext<N> {
     }
 }
 
+/// The DNS server holding the external DNS zone maintained by Nexus.
+pub struct ExternalDnsServer {
+    pub storage_dir: tempfile::TempDir,
+    pub dns_server: dns_server::dns_server::Server,
+    pub dropshot_server:
+        dropshot::HttpServer<Arc<dns_server::dropshot_server::Context>>,
+}
+
+impl ExternalDnsServer {
+    pub async fn start(log: &Logger) -> Result<Self, String> {
+        let storage_dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+        let config = dns_server::Config {
+            log: ConfigLogging::StderrTerminal {
+                level: ConfigLoggingLevel::Debug,
+            },
+            dropshot: ConfigDropshot {
+                bind_address: "[::1]:0".parse().unwrap(),
+                request_body_max_bytes: 1024 * 1024,
+                ..Default::default()
+            },
+            data: dns_server::dns_data::Config {
+                nmax_messages: 16,
+                storage_path: storage_dir.path().to_string_lossy().to_string(),
+            },
+            metrics: None,
+        };
+        let (dns_server, dropshot_server) = dns_server::start(
+            log.new(o!("component" => "external_dns")),
+            config,
+            EXTERNAL_DNS_ZONE.to_string(),
+            "[::1]:0".parse().unwrap(),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+        Ok(Self { storage_dir, dns_server, dropshot_server })
+    }
+
+    /// Returns a client to the server's HTTP API.
+    pub fn client(&self, log: &Logger) -> dns_service_client::Client {
+        dns_service_client::Client::new(
+            &format!("http://{}", self.dropshot_server.local_addr()),
+            log.new(o!("component" => "external_dns_client")),
+        )
+    }
+}
+
 pub fn load_test_config() -> omicron_common::nexus_config::Config {
     // We load as much configuration as we can from the test suite configuration
     // file.  In practice, TestContext requires that:
@@ -142,6 +194,13 @@ pub async fn test_setup_with_config<N: NexusServer>(
         .expect("Tests expect to set a port of Clickhouse")
         .set_port(clickhouse.port());
 
+    // Start the DNS server for the external DNS zone.
+    let external_dns = ExternalDnsServer::start(log).await.unwrap();
+    config.pkg.external_dns = Some(nexus_config::ExternalDnsConfig {
+        zone: EXTERNAL_DNS_ZONE.to_string(),
+        servers: vec![external_dns.dropshot_server.local_addr()],
+    });
+
     let server = N::start_and_populate(&config, &logctx.log).await;
 
     let external_server_addr =
@@ -210,6 +269,7 @@ pub async fn test_setup_with_config<N: NexusServer>(
         sled_agent,
         oximeter,
         producer,
+        external_dns,
         logctx,
     }
 }
@@ -234,6 +294,7 @@ pub async fn start_sled_agent(
             zpools: vec![],
             ip: IpAddr::from(Ipv6Addr::LOCALHOST),
         },
+        prometheus: false,
     };
 
     let (server, _rack_init_request) =
@@ -251,10 +312,12 @@ pub async fn start_oximeter(
         address: Some(SocketAddr::new(Ipv6Addr::LOCALHOST.into(), db_port)),
         batch_size: 10,
         batch_interval: 1,
+        retention: None,
     };
     let config = oximeter_collector::Config {
         nexus_address: Some(nexus_address),
         db,
+        producers: oximeter_collector::ProducerConfig::default(),
         log: ConfigLogging::StderrTerminal { level: ConfigLoggingLevel::Error },
     };
     let args = oximeter_collector::OximeterArguments {
@@ -325,6 +388,7 @@ pub async fn start_producer_server(
         logging_config: ConfigLogging::StderrTerminal {
             level: ConfigLoggingLevel::Error,
         },
+        prometheus: false,
     };
     let server =
         ProducerServer::start(&config).await.map_err(|e| e.to_string())?;
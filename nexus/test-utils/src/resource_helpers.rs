@@ -390,6 +390,7 @@ pub async fn create_instance_with(
             network_interfaces: nics.clone(),
             external_ips: vec![],
             disks,
+            affinity_groups: Vec::new(),
             start: true,
         },
     )
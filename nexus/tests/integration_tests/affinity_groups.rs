@@ -0,0 +1,407 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests affinity groups and their effect on instance placement
+
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use dropshot::ResultsPage;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_interface::NexusServer;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::object_create;
+use nexus_test_utils::resource_helpers::object_delete;
+use nexus_test_utils::resource_helpers::objects_list_page_authz;
+use nexus_test_utils::resource_helpers::populate_ip_pool;
+use nexus_test_utils::start_sled_agent;
+use nexus_test_utils::SLED_AGENT_UUID;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::Instance;
+use omicron_common::api::external::InstanceCpuCount;
+use omicron_common::api::external::NameOrId;
+use omicron_nexus::authz;
+use omicron_nexus::context::OpContext;
+use omicron_nexus::db::lookup::LookupPath;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::shared::AffinityGroupKind;
+use omicron_nexus::external_api::shared::AffinityPolicy;
+use omicron_nexus::external_api::views::AffinityGroup;
+use omicron_nexus::external_api::views::SledDrainStatus;
+use omicron_sled_agent::sim;
+use std::str::FromStr;
+use uuid::Uuid;
+
+type ControlPlaneTestContext =
+    nexus_test_utils::ControlPlaneTestContext<omicron_nexus::Server>;
+
+const ORGANIZATION_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "affinity-project";
+const PROJECT_SELECTOR: &str = "organization=test-org&project=affinity-project";
+
+fn get_affinity_groups_url() -> String {
+    format!("/v1/affinity-groups?{}", PROJECT_SELECTOR)
+}
+
+fn get_affinity_group_url(name: &str) -> String {
+    format!("/v1/affinity-groups/{}?{}", name, PROJECT_SELECTOR)
+}
+
+fn get_affinity_group_members_url(name: &str) -> String {
+    format!("/v1/affinity-groups/{}/members?{}", name, PROJECT_SELECTOR)
+}
+
+/// Creates the project used by these tests, along with a second sled agent
+/// so that there's more than one sled to choose from.
+async fn setup(cptestctx: &ControlPlaneTestContext) -> (Uuid, sim::Server) {
+    let client = &cptestctx.external_client;
+    populate_ip_pool(&client, "default", None).await;
+    create_organization(&client, ORGANIZATION_NAME).await;
+    create_project(&client, ORGANIZATION_NAME, PROJECT_NAME).await;
+
+    let sa_id = Uuid::new_v4();
+    let log = cptestctx.logctx.log.new(o!( "sled_id" => sa_id.to_string() ));
+    let addr = cptestctx.server.get_http_server_internal_address().await;
+    let sa = start_sled_agent(log, addr, sa_id).await.unwrap();
+    (sa_id, sa)
+}
+
+async fn create_affinity_group(
+    client: &ClientTestContext,
+    name: &str,
+    kind: AffinityGroupKind,
+    policy: AffinityPolicy,
+) -> AffinityGroup {
+    object_create(
+        client,
+        &get_affinity_groups_url(),
+        &params::AffinityGroupCreate {
+            identity: IdentityMetadataCreateParams {
+                name: name.parse().unwrap(),
+                description: format!("affinity group {:?}", name),
+            },
+            kind,
+            policy,
+        },
+    )
+    .await
+}
+
+fn instance_create_params(
+    name: &str,
+    affinity_groups: &[&str],
+) -> params::InstanceCreate {
+    params::InstanceCreate {
+        identity: IdentityMetadataCreateParams {
+            name: name.parse().unwrap(),
+            description: format!("instance {:?}", name),
+        },
+        ncpus: InstanceCpuCount(4),
+        memory: ByteCount::from_gibibytes_u32(1),
+        hostname: String::from("the_host"),
+        user_data: vec![],
+        network_interfaces: params::InstanceNetworkInterfaceAttachment::None,
+        external_ips: vec![],
+        disks: vec![],
+        affinity_groups: affinity_groups
+            .iter()
+            .map(|group| NameOrId::Name(group.parse().unwrap()))
+            .collect(),
+        start: true,
+    }
+}
+
+async fn create_instance_in_groups(
+    client: &ClientTestContext,
+    name: &str,
+    affinity_groups: &[&str],
+) -> Instance {
+    object_create(
+        client,
+        &format!("/v1/instances?{}", PROJECT_SELECTOR),
+        &instance_create_params(name, affinity_groups),
+    )
+    .await
+}
+
+async fn affinity_group_members(
+    client: &ClientTestContext,
+    name: &str,
+) -> Vec<Instance> {
+    NexusRequest::object_get(client, &get_affinity_group_members_url(name))
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body::<ResultsPage<Instance>>()
+        .unwrap()
+        .items
+}
+
+/// Returns the number of Instances on a sled.
+async fn sled_instance_count(client: &ClientTestContext, sled_id: Uuid) -> u64 {
+    NexusRequest::object_get(
+        client,
+        &format!("/v1/system/hardware/sleds/{}/drain", sled_id),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body::<SledDrainStatus>()
+    .unwrap()
+    .instances_remaining
+}
+
+#[nexus_test]
+async fn test_affinity_group_create_and_delete(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let (_, sa) = setup(cptestctx).await;
+
+    // No groups to start with.
+    let groups = objects_list_page_authz::<AffinityGroup>(
+        client,
+        &get_affinity_groups_url(),
+    )
+    .await
+    .items;
+    assert!(groups.is_empty());
+
+    let group = create_affinity_group(
+        client,
+        "spread",
+        AffinityGroupKind::AntiAffinity,
+        AffinityPolicy::Soft,
+    )
+    .await;
+    assert_eq!(group.kind, AffinityGroupKind::AntiAffinity);
+    assert_eq!(group.policy, AffinityPolicy::Soft);
+
+    // The group can be fetched by name or by ID, and is listed in the
+    // project.
+    let fetched: AffinityGroup =
+        NexusRequest::object_get(client, &get_affinity_group_url("spread"))
+            .authn_as(AuthnMode::PrivilegedUser)
+            .execute()
+            .await
+            .unwrap()
+            .parsed_body()
+            .unwrap();
+    assert_eq!(fetched.identity.id, group.identity.id);
+    let fetched: AffinityGroup = NexusRequest::object_get(
+        client,
+        &format!("/v1/affinity-groups/{}", group.identity.id),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(fetched.identity.name, group.identity.name);
+    let groups = objects_list_page_authz::<AffinityGroup>(
+        client,
+        &get_affinity_groups_url(),
+    )
+    .await
+    .items;
+    assert_eq!(groups.len(), 1);
+
+    // Names must be unique within a project.
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        &get_affinity_groups_url(),
+        &params::AffinityGroupCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "spread".parse().unwrap(),
+                description: String::from("a duplicate"),
+            },
+            kind: AffinityGroupKind::Affinity,
+            policy: AffinityPolicy::Hard,
+        },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(error.message, "already exists: affinity-group \"spread\"");
+
+    // An Instance which is created in the group is listed as a member.
+    let instance = create_instance_in_groups(client, "inst", &["spread"]).await;
+    let members = affinity_group_members(client, "spread").await;
+    assert_eq!(members.len(), 1);
+    assert_eq!(members[0].identity.id, instance.identity.id);
+
+    // Deleting the group leaves its members alone.
+    object_delete(client, &get_affinity_group_url("spread")).await;
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        &get_affinity_group_url("spread"),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    NexusRequest::object_get(
+        client,
+        &format!("/v1/instances/inst?{}", PROJECT_SELECTOR),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Instances can't join groups that don't exist.
+    NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::POST,
+        &format!("/v1/instances?{}", PROJECT_SELECTOR),
+        &instance_create_params("orphan", &["spread"]),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    sa.http_server.close().await.unwrap();
+}
+
+#[nexus_test]
+async fn test_hard_anti_affinity(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let sled_id = Uuid::from_str(&SLED_AGENT_UUID).unwrap();
+    let (other_sled_id, sa) = setup(cptestctx).await;
+
+    create_affinity_group(
+        client,
+        "spread",
+        AffinityGroupKind::AntiAffinity,
+        AffinityPolicy::Hard,
+    )
+    .await;
+
+    // The first two members land on different sleds.
+    create_instance_in_groups(client, "inst1", &["spread"]).await;
+    create_instance_in_groups(client, "inst2", &["spread"]).await;
+    assert_eq!(sled_instance_count(client, sled_id).await, 1);
+    assert_eq!(sled_instance_count(client, other_sled_id).await, 1);
+
+    // There's nowhere left to put a third, and the failed Instance doesn't
+    // linger as a member of the group.
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        &format!("/v1/instances?{}", PROJECT_SELECTOR),
+        &instance_create_params("inst3", &["spread"]),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "no available sled satisfies anti-affinity group \"spread\""
+    );
+    assert_eq!(affinity_group_members(client, "spread").await.len(), 2);
+
+    // Instances outside the group can go anywhere.
+    create_instance_in_groups(client, "inst3", &[]).await;
+
+    sa.http_server.close().await.unwrap();
+}
+
+#[nexus_test]
+async fn test_hard_affinity(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let sled_id = Uuid::from_str(&SLED_AGENT_UUID).unwrap();
+    let (other_sled_id, sa) = setup(cptestctx).await;
+
+    create_affinity_group(
+        client,
+        "together",
+        AffinityGroupKind::Affinity,
+        AffinityPolicy::Hard,
+    )
+    .await;
+
+    // Every member lands on the sled where the first one did.
+    for name in ["inst1", "inst2", "inst3"] {
+        create_instance_in_groups(client, name, &["together"]).await;
+    }
+    let mut counts = vec![
+        sled_instance_count(client, sled_id).await,
+        sled_instance_count(client, other_sled_id).await,
+    ];
+    counts.sort();
+    assert_eq!(counts, vec![0, 3]);
+    assert_eq!(affinity_group_members(client, "together").await.len(), 3);
+
+    sa.http_server.close().await.unwrap();
+}
+
+#[nexus_test]
+async fn test_hard_anti_affinity_checked_on_join(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx().nexus;
+    let datastore = nexus.datastore();
+    let opctx =
+        OpContext::for_tests(cptestctx.logctx.log.new(o!()), datastore.clone());
+    populate_ip_pool(&client, "default", None).await;
+    create_organization(&client, ORGANIZATION_NAME).await;
+    create_project(&client, ORGANIZATION_NAME, PROJECT_NAME).await;
+
+    // With only one sled, these Instances necessarily share it, as if they
+    // had been placed concurrently.
+    let group = create_affinity_group(
+        client,
+        "spread",
+        AffinityGroupKind::AntiAffinity,
+        AffinityPolicy::Hard,
+    )
+    .await;
+    let inst1 = create_instance_in_groups(client, "inst1", &[]).await;
+    let inst2 = create_instance_in_groups(client, "inst2", &[]).await;
+
+    // Only the first of them may join the group.
+    let (.., authz_group) = LookupPath::new(&opctx, datastore)
+        .affinity_group_id(group.identity.id)
+        .lookup_for(authz::Action::Modify)
+        .await
+        .unwrap();
+    datastore
+        .affinity_group_member_add(&opctx, &authz_group, inst1.identity.id)
+        .await
+        .unwrap();
+    let error = datastore
+        .affinity_group_member_add(&opctx, &authz_group, inst2.identity.id)
+        .await
+        .unwrap_err();
+    assert!(
+        error.to_string().contains("no longer satisfies affinity group"),
+        "unexpected error: {}",
+        error
+    );
+    let members = affinity_group_members(client, "spread").await;
+    assert_eq!(members.len(), 1);
+    assert_eq!(members[0].identity.id, inst1.identity.id);
+}
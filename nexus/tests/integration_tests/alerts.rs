@@ -0,0 +1,224 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests alert rules evaluated over timeseries
+
+use httptest::{matchers::*, responders::*, Expectation, Server};
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::load_test_config;
+use nexus_test_utils::resource_helpers::object_create;
+use nexus_test_utils::resource_helpers::object_delete;
+use nexus_test_utils::resource_helpers::objects_list_page_authz;
+use nexus_test_utils::test_setup_with_config;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::nexus_config::AlertsConfig;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::shared::AlertComparison;
+use omicron_nexus::external_api::views::Alert;
+use omicron_nexus::external_api::views::AlertRule;
+use omicron_nexus::external_api::views::AlertRuleState;
+use omicron_test_utils::dev::poll::{wait_for_condition, CondCheckError};
+use oximeter::types::Sample;
+use oximeter_db::query::Aggregation;
+use oximeter_db::query::Reducer;
+use oximeter_db::DbWrite;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
+use std::time::Duration;
+use uuid::Uuid;
+
+const ALERT_RULES_URL: &str = "/v1/system/alert-rules";
+
+#[derive(Debug, Clone, oximeter::Target)]
+struct AlertTarget {
+    id: Uuid,
+}
+
+#[derive(Debug, Clone, oximeter::Metric)]
+struct RamUsed {
+    datum: f64,
+}
+
+#[tokio::test]
+async fn test_alert_rule_fires_and_resolves() {
+    // Stand in for the webhook alerts are sent to.
+    let webhook = Server::run();
+    webhook.expect(
+        Expectation::matching(all_of![
+            request::method_path("POST", "/alerts"),
+            request::body(json_decoded(|body: &serde_json::Value| {
+                body["rule"]["name"] == "ram-used"
+                    && body["rule"]["state"] == "firing"
+                    && body["alert"]["time_resolved"].is_null()
+            })),
+        ])
+        .times(1)
+        .respond_with(status_code(200)),
+    );
+    webhook.expect(
+        Expectation::matching(all_of![
+            request::method_path("POST", "/alerts"),
+            request::body(json_decoded(|body: &serde_json::Value| {
+                body["rule"]["name"] == "ram-used"
+                    && body["rule"]["state"] == "ok"
+                    && !body["alert"]["time_resolved"].is_null()
+            })),
+        ])
+        .times(1)
+        .respond_with(status_code(200)),
+    );
+
+    let mut config = load_test_config();
+    // Rules are evaluated by the test itself, so the background task only
+    // evaluates them once, at startup.
+    config.pkg.alerts = Some(AlertsConfig {
+        evaluation_period_secs: 3600,
+        webhook_url: Some(webhook.url_str("/alerts")),
+    });
+    let cptestctx = test_setup_with_config::<omicron_nexus::Server>(
+        "test_alert_rule_fires_and_resolves",
+        &mut config,
+    )
+    .await;
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx().nexus;
+
+    let target = AlertTarget { id: Uuid::new_v4() };
+    let rule: AlertRule = object_create(
+        client,
+        ALERT_RULES_URL,
+        &params::AlertRuleCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "ram-used".parse().unwrap(),
+                description: String::from("too much RAM in use"),
+            },
+            timeseries_name: String::from("alert_target:ram_used"),
+            criteria: vec![format!("id=={}", target.id)],
+            aggregation: Aggregation {
+                reducer: Reducer::Max,
+                group_by: vec![],
+                window_secs: NonZeroU32::new(1).unwrap(),
+                rate: false,
+                statistic: None,
+            },
+            comparison: AlertComparison::Above,
+            threshold: 100.0,
+            duration_secs: NonZeroU32::new(2).unwrap(),
+        },
+    )
+    .await;
+    assert_eq!(rule.state, AlertRuleState::Ok);
+    assert_eq!(rule.time_evaluated, None);
+    let rule_url = format!("{}/{}", ALERT_RULES_URL, rule.identity.name);
+    let alerts_url = format!("{}/alerts", rule_url);
+
+    // A rule whose timeseries hasn't been reported yet keeps its state.
+    nexus.alert_rules_evaluate().await.unwrap();
+    let rule: AlertRule = NexusRequest::object_get(client, &rule_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body()
+        .unwrap();
+    assert_eq!(rule.state, AlertRuleState::Ok);
+    assert!(rule.time_evaluated.is_some());
+    assert_eq!(rule.last_value, None);
+
+    // A value past the threshold doesn't fire the rule until it has been past
+    // it for the rule's duration.
+    let ch_address =
+        SocketAddr::new("::1".parse().unwrap(), cptestctx.clickhouse.port());
+    let ch_client = oximeter_db::Client::new(ch_address, &cptestctx.logctx.log);
+    ch_client.init_db().await.unwrap();
+    report_ram_used(&ch_client, &target, 200.0).await;
+    nexus.alert_rules_evaluate().await.unwrap();
+    let rules =
+        objects_list_page_authz::<AlertRule>(client, ALERT_RULES_URL).await;
+    assert_eq!(rules.items.len(), 1);
+    assert_eq!(rules.items[0].state, AlertRuleState::Ok);
+    assert_eq!(rules.items[0].last_value, Some(200.0));
+    let alerts = objects_list_page_authz::<Alert>(client, &alerts_url).await;
+    assert!(alerts.items.is_empty());
+
+    // Keep reporting values past the threshold until the rule fires.
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+    const POLL_DURATION: Duration = Duration::from_secs(30);
+    wait_for_condition(
+        || async {
+            report_ram_used(&ch_client, &target, 200.0).await;
+            nexus.alert_rules_evaluate().await.unwrap();
+            let rules =
+                objects_list_page_authz::<AlertRule>(client, ALERT_RULES_URL)
+                    .await;
+            if rules.items[0].state == AlertRuleState::Firing {
+                Ok(())
+            } else {
+                Err(CondCheckError::<Infallible>::NotYet)
+            }
+        },
+        &POLL_INTERVAL,
+        &POLL_DURATION,
+    )
+    .await
+    .expect("alert rule never fired");
+    let rules =
+        objects_list_page_authz::<AlertRule>(client, ALERT_RULES_URL).await;
+    let rule = &rules.items[0];
+    assert_eq!(rule.last_value, Some(200.0));
+    let alerts = objects_list_page_authz::<Alert>(client, &alerts_url).await;
+    assert_eq!(alerts.items.len(), 1);
+    assert_eq!(alerts.items[0].alert_rule_id, rule.identity.id);
+    assert_eq!(alerts.items[0].value, 200.0);
+    assert_eq!(alerts.items[0].time_resolved, None);
+
+    // Evaluating a rule that's still firing doesn't raise another alert.
+    report_ram_used(&ch_client, &target, 200.0).await;
+    nexus.alert_rules_evaluate().await.unwrap();
+    let alerts = objects_list_page_authz::<Alert>(client, &alerts_url).await;
+    assert_eq!(alerts.items.len(), 1);
+
+    // Nor does a lack of data resolve it.
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    nexus.alert_rules_evaluate().await.unwrap();
+    let rules =
+        objects_list_page_authz::<AlertRule>(client, ALERT_RULES_URL).await;
+    assert_eq!(rules.items[0].state, AlertRuleState::Firing);
+    assert_eq!(rules.items[0].last_value, Some(200.0));
+
+    // The alert is resolved as soon as the value is back within the
+    // threshold.
+    report_ram_used(&ch_client, &target, 50.0).await;
+    nexus.alert_rules_evaluate().await.unwrap();
+    let rules =
+        objects_list_page_authz::<AlertRule>(client, ALERT_RULES_URL).await;
+    assert_eq!(rules.items[0].state, AlertRuleState::Ok);
+    assert_eq!(rules.items[0].last_value, Some(50.0));
+    let alerts = objects_list_page_authz::<Alert>(client, &alerts_url).await;
+    assert_eq!(alerts.items.len(), 1);
+    assert!(alerts.items[0].time_resolved.is_some());
+
+    // Deleted rules are no longer listed or evaluated.
+    object_delete(client, &rule_url).await;
+    let rules =
+        objects_list_page_authz::<AlertRule>(client, ALERT_RULES_URL).await;
+    assert!(rules.items.is_empty());
+    nexus.alert_rules_evaluate().await.unwrap();
+
+    cptestctx.teardown().await;
+    webhook.verify_and_clear();
+}
+
+async fn report_ram_used(
+    client: &oximeter_db::Client,
+    target: &AlertTarget,
+    value: f64,
+) {
+    client
+        .insert_samples(&[Sample::new(target, &RamUsed { datum: value })])
+        .await
+        .unwrap();
+}
@@ -0,0 +1,160 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests the audit log of mutating external API requests
+
+use chrono::SecondsFormat;
+use chrono::Utc;
+use dropshot::ResultsPage;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::grant_iam;
+use nexus_test_utils::resource_helpers::objects_list_page_authz;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_nexus::authn::USER_TEST_PRIVILEGED;
+use omicron_nexus::authn::USER_TEST_UNPRIVILEGED;
+use omicron_nexus::authz::SiloRole;
+use omicron_nexus::db::fixed_data::silo::DEFAULT_SILO;
+use omicron_nexus::db::identity::Asset;
+use omicron_nexus::db::identity::Resource;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::views::AuditLogActor;
+use omicron_nexus::external_api::views::AuditLogEntry;
+
+type ControlPlaneTestContext =
+    nexus_test_utils::ControlPlaneTestContext<omicron_nexus::Server>;
+
+const AUDIT_LOG_URL: &str = "/v1/audit-log";
+
+#[nexus_test]
+async fn test_audit_log(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let t0 = Utc::now();
+
+    // A successful request is recorded along with who made it.
+    create_organization(&client, "audited-org").await;
+    let entries =
+        objects_list_page_authz::<AuditLogEntry>(client, AUDIT_LOG_URL)
+            .await
+            .items;
+    let entry = entries
+        .iter()
+        .find(|e| e.operation_id == "organization_create_v1")
+        .expect("missing audit log entry for organization create");
+    assert_eq!(entry.http_method, "POST");
+    assert_eq!(entry.request_uri, "/v1/organizations");
+    assert_eq!(
+        entry.actor,
+        AuditLogActor::SiloUser {
+            silo_user_id: USER_TEST_PRIVILEGED.id(),
+            silo_id: DEFAULT_SILO.id(),
+        }
+    );
+    assert!(entry.time_started >= t0);
+    assert!(entry.time_completed.unwrap() >= entry.time_started);
+    assert_eq!(entry.http_status_code, Some(201));
+    assert_eq!(entry.error_message, None);
+
+    // So is a failed one, along with the error it produced.
+    let t1 = Utc::now();
+    NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        "/v1/organizations",
+        &params::OrganizationCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "audited-org".parse().unwrap(),
+                description: String::from("a duplicate"),
+            },
+        },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Only that request is listed when asking for requests received since it
+    // was made.
+    let url = format!(
+        "{}?start_time={}",
+        AUDIT_LOG_URL,
+        t1.to_rfc3339_opts(SecondsFormat::Micros, true)
+    );
+    let entries = objects_list_page_authz::<AuditLogEntry>(client, &url).await;
+    assert_eq!(entries.items.len(), 1);
+    let entry = &entries.items[0];
+    assert_eq!(entry.operation_id, "organization_create_v1");
+    assert_eq!(entry.http_status_code, Some(400));
+    assert_eq!(
+        entry.error_message.as_deref(),
+        Some("already exists: organization \"audited-org\"")
+    );
+
+    // An empty range of times lists nothing.
+    let url = format!(
+        "{}?start_time={}&end_time={}",
+        AUDIT_LOG_URL,
+        t1.to_rfc3339_opts(SecondsFormat::Micros, true),
+        t1.to_rfc3339_opts(SecondsFormat::Micros, true),
+    );
+    let entries = objects_list_page_authz::<AuditLogEntry>(client, &url).await;
+    assert!(entries.items.is_empty());
+
+    // Reading the audit log is not itself recorded.
+    let entries =
+        objects_list_page_authz::<AuditLogEntry>(client, AUDIT_LOG_URL)
+            .await
+            .items;
+    assert!(entries.iter().all(|e| e.http_method != "GET"));
+}
+
+#[nexus_test]
+async fn test_audit_log_authz(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+
+    // Users who can't see the Silo's configuration can't see its audit log.
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::FORBIDDEN,
+        Method::GET,
+        AUDIT_LOG_URL,
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Silo viewers can see the requests made by their Silo's users.
+    let silo_url = format!("/system/silos/{}", DEFAULT_SILO.identity().name);
+    grant_iam(
+        client,
+        &silo_url,
+        SiloRole::Viewer,
+        USER_TEST_UNPRIVILEGED.id(),
+        AuthnMode::PrivilegedUser,
+    )
+    .await;
+    let entries: ResultsPage<AuditLogEntry> =
+        NexusRequest::object_get(client, AUDIT_LOG_URL)
+            .authn_as(AuthnMode::UnprivilegedUser)
+            .execute()
+            .await
+            .unwrap()
+            .parsed_body()
+            .unwrap();
+    assert!(entries
+        .items
+        .iter()
+        .any(|e| e.operation_id == "silo_policy_update"));
+    assert!(entries.items.iter().all(|e| e.actor
+        == AuditLogActor::SiloUser {
+            silo_user_id: USER_TEST_PRIVILEGED.id(),
+            silo_id: DEFAULT_SILO.id(),
+        }));
+}
@@ -166,6 +166,11 @@ async fn instance_simulate(nexus: &Arc<Nexus>, id: &Uuid) {
     sa.instance_finish_transition(*id).await;
 }
 
+async fn disk_simulate(nexus: &Arc<Nexus>, id: &Uuid) {
+    let sa = nexus.disk_sled_by_id(id).await.unwrap();
+    sa.disk_finish_transition(*id).await;
+}
+
 #[nexus_test]
 async fn test_disk_create_attach_detach_delete(
     cptestctx: &ControlPlaneTestContext,
@@ -210,10 +215,8 @@ async fn test_disk_create_attach_detach_delete(
     let instance =
         create_instance(&client, ORG_NAME, PROJECT_NAME, INSTANCE_NAME).await;
 
-    // TODO(https://github.com/oxidecomputer/omicron/issues/811):
-    //
-    // Instances must be stopped before disks can be attached - this
-    // is an artificial limitation without hotplug support.
+    // Stop the instance, so that disks are attached and detached without
+    // involving its sled agent.  See `test_disk_hotplug` for the running case.
     let instance_next =
         set_instance_state(&client, INSTANCE_NAME, "stop").await;
     instance_simulate(nexus, &instance_next.identity.id).await;
@@ -296,6 +299,76 @@ async fn test_disk_create_attach_detach_delete(
     );
 }
 
+#[nexus_test]
+async fn test_disk_hotplug(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    DiskTest::new(&cptestctx).await;
+    create_org_and_project(client).await;
+    let nexus = &cptestctx.server.apictx().nexus;
+
+    let disk_url = get_disk_url(DISK_NAME);
+    let disk = create_disk(&client, ORG_NAME, PROJECT_NAME, DISK_NAME).await;
+
+    // Create an instance and let it finish booting.
+    let instance =
+        create_instance(&client, ORG_NAME, PROJECT_NAME, INSTANCE_NAME).await;
+    let instance_id = instance.identity.id;
+    instance_simulate(nexus, &instance_id).await;
+
+    let url_instance_disks = get_instance_disks_url(INSTANCE_NAME);
+    let url_instance_attach_disk = get_disk_attach_url(INSTANCE_NAME);
+    let url_instance_detach_disk = get_disk_detach_url(INSTANCE_NAME);
+
+    // Attaching a disk to the running instance leaves it "attaching" until
+    // the sled agent reports that the instance has it.
+    let disk = disk_post(
+        client,
+        &url_instance_attach_disk,
+        disk.identity.name.clone(),
+    )
+    .await;
+    assert_eq!(disk.state, DiskState::Attaching(instance_id));
+    let disks = disks_list(&client, &url_instance_disks).await;
+    assert_eq!(disks.len(), 1);
+
+    // Attaching it again is fine.
+    let disk = disk_post(
+        client,
+        &url_instance_attach_disk,
+        disk.identity.name.clone(),
+    )
+    .await;
+    assert_eq!(disk.state, DiskState::Attaching(instance_id));
+
+    disk_simulate(nexus, &disk.identity.id).await;
+    let disk = disk_get(&client, &disk_url).await;
+    assert_eq!(disk.state, DiskState::Attached(instance_id));
+
+    // Detaching the disk works the same way.
+    let disk = disk_post(
+        client,
+        &url_instance_detach_disk,
+        disk.identity.name.clone(),
+    )
+    .await;
+    assert_eq!(disk.state, DiskState::Detaching(instance_id));
+
+    disk_simulate(nexus, &disk.identity.id).await;
+    let disk = disk_get(&client, &disk_url).await;
+    assert_eq!(disk.state, DiskState::Detached);
+    let disks = disks_list(&client, &url_instance_disks).await;
+    assert_eq!(disks.len(), 0);
+
+    // Once the instance has stopped, attaching the disk once more only
+    // involves the database.
+    let instance_next =
+        set_instance_state(&client, INSTANCE_NAME, "stop").await;
+    instance_simulate(nexus, &instance_next.identity.id).await;
+    let disk =
+        disk_post(client, &url_instance_attach_disk, disk.identity.name).await;
+    assert_eq!(disk.state, DiskState::Attached(instance_id));
+}
+
 #[nexus_test]
 async fn test_disk_create_disk_that_already_exists_fails(
     cptestctx: &ControlPlaneTestContext,
@@ -358,10 +431,8 @@ async fn test_disk_move_between_instances(cptestctx: &ControlPlaneTestContext) {
     // Create an instance to attach the disk.
     let instance =
         create_instance(&client, ORG_NAME, PROJECT_NAME, INSTANCE_NAME).await;
-    // TODO(https://github.com/oxidecomputer/omicron/issues/811):
-    //
-    // Instances must be stopped before disks can be attached - this
-    // is an artificial limitation without hotplug support.
+    // Stop the instance, so that disks are attached and detached without
+    // involving its sled agent.
     let instance_next =
         set_instance_state(&client, INSTANCE_NAME, "stop").await;
     instance_simulate(nexus, &instance_next.identity.id).await;
@@ -1298,6 +1369,166 @@ async fn test_disk_size_accounting(cptestctx: &ControlPlaneTestContext) {
     }
 }
 
+#[nexus_test]
+async fn test_disk_grow(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx().nexus;
+    let datastore = nexus.datastore();
+
+    // Create three 10 GiB zpools, each with one dataset.
+    let test = DiskTest::new(&cptestctx).await;
+
+    let project_id = create_org_and_project(client).await;
+    let opctx =
+        OpContext::for_tests(cptestctx.logctx.log.new(o!()), datastore.clone());
+
+    // Create a 2 GiB disk.
+    let disks_url = get_disks_url();
+    let new_disk = params::DiskCreate {
+        identity: IdentityMetadataCreateParams {
+            name: DISK_NAME.parse().unwrap(),
+            description: String::from("sells rainsticks"),
+        },
+        disk_source: params::DiskSource::Blank {
+            block_size: params::BlockSize::try_from(512).unwrap(),
+        },
+        size: ByteCount::from_gibibytes_u32(2),
+    };
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &disks_url)
+            .body(Some(&new_disk))
+            .expect_status(Some(StatusCode::CREATED)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("unexpected failure creating 2 GiB disk");
+
+    // Shrinking the disk is not allowed.
+    let disk_url = get_disk_url(DISK_NAME);
+    let error = NexusRequest::new(
+        RequestBuilder::new(client, Method::PUT, &disk_url)
+            .body(Some(&params::DiskUpdate {
+                size: ByteCount::from_gibibytes_u32(1),
+            }))
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body::<HttpErrorResponseBody>()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "unsupported value for \"size\": disks cannot be shrunk (current \
+        size is 2 GiB)"
+    );
+
+    // Grow the disk to 5 GiB.
+    let disk: Disk = NexusRequest::object_put(
+        client,
+        &disk_url,
+        Some(&params::DiskUpdate { size: ByteCount::from_gibibytes_u32(5) }),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("unexpected failure growing disk")
+    .parsed_body()
+    .unwrap();
+    assert_eq!(disk.size, ByteCount::from_gibibytes_u32(5));
+    assert_eq!(disk.state, DiskState::Detached);
+    let disk = disk_get(&client, &disk_url).await;
+    assert_eq!(disk.size, ByteCount::from_gibibytes_u32(5));
+
+    // The added space is charged to the project.
+    let virtual_provisioning_collection = datastore
+        .virtual_provisioning_collection_get(&opctx, project_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        virtual_provisioning_collection.virtual_disk_bytes_provisioned.0,
+        ByteCount::from_gibibytes_u32(5),
+    );
+
+    // The disk is now backed by two region sets, which together occupy the
+    // full size of the disk on every dataset.
+    for zpool in &test.zpools {
+        for dataset in &zpool.datasets {
+            assert_eq!(
+                datastore
+                    .regions_total_occupied_size(dataset.id)
+                    .await
+                    .unwrap(),
+                ByteCount::from_gibibytes_u32(5).to_bytes(),
+            );
+        }
+    }
+
+    // The simulated Crucible agent on every dataset has created a region for
+    // each set: the original 2 GiB region, and a 3 GiB region for the space
+    // that was added.
+    for zpool in &test.zpools {
+        for dataset in &zpool.datasets {
+            let crucible = test
+                .sled_agent
+                .get_crucible_dataset(zpool.id, dataset.id)
+                .await;
+            let mut region_sizes = crucible
+                .list()
+                .await
+                .into_iter()
+                .map(|region| {
+                    assert_eq!(region.state, RegionState::Created);
+                    region.block_size * region.extent_size * region.extent_count
+                })
+                .collect::<Vec<_>>();
+            region_sizes.sort();
+            assert_eq!(
+                region_sizes,
+                vec![
+                    ByteCount::from_gibibytes_u32(2).to_bytes(),
+                    ByteCount::from_gibibytes_u32(3).to_bytes(),
+                ],
+            );
+        }
+    }
+
+    // Growing the disk past what the datasets can hold fails, and leaves the
+    // disk untouched.
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::PUT, &disk_url)
+            .body(Some(&params::DiskUpdate {
+                size: ByteCount::from_gibibytes_u32(12),
+            }))
+            .expect_status(Some(StatusCode::SERVICE_UNAVAILABLE)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    let disk = disk_get(&client, &disk_url).await;
+    assert_eq!(disk.size, ByteCount::from_gibibytes_u32(5));
+    assert_eq!(disk.state, DiskState::Detached);
+    let virtual_provisioning_collection = datastore
+        .virtual_provisioning_collection_get(&opctx, project_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        virtual_provisioning_collection.virtual_disk_bytes_provisioned.0,
+        ByteCount::from_gibibytes_u32(5),
+    );
+
+    // The disk can be deleted, along with all of its regions.
+    NexusRequest::object_delete(client, &disk_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .expect("failed to delete disk");
+    assert!(test.crucible_resources_deleted().await);
+}
+
 // Test creating two disks across six zpools
 #[nexus_test]
 async fn test_multiple_disks_multiple_zpools(
@@ -1521,6 +1752,97 @@ async fn test_disk_metrics_paginated(cptestctx: &ControlPlaneTestContext) {
     }
 }
 
+#[nexus_test]
+async fn test_disk_metrics_aggregated(cptestctx: &ControlPlaneTestContext) {
+    // Normally, Nexus is not registered as a producer for tests.
+    // Turn this bit on so we can also test some metrics from Nexus itself.
+    cptestctx.server.register_as_producer().await;
+
+    let oximeter = &cptestctx.oximeter;
+    let client = &cptestctx.external_client;
+    DiskTest::new(&cptestctx).await;
+    let project_id = create_org_and_project(client).await;
+    let disk = create_disk(&client, ORG_NAME, PROJECT_NAME, DISK_NAME).await;
+    create_instance_with_disk(client).await;
+    oximeter.force_collect().await;
+
+    const WINDOW_SECS: i64 = 60;
+    let disk_url = format!(
+        "/organizations/{ORG_NAME}/projects/{PROJECT_NAME}/disks/{DISK_NAME}"
+    );
+    let params = format!(
+        "start_time={:?}&end_time={:?}&aggregation=max&window_secs={}",
+        Utc::now() - chrono::Duration::seconds(2 * WINDOW_SECS),
+        Utc::now() + chrono::Duration::seconds(2 * WINDOW_SECS),
+        WINDOW_SECS,
+    );
+
+    // Aggregated measurements are reported once per window, stamped with the
+    // start of the window.
+    for metric in &ALL_METRICS {
+        let measurements = query_for_metrics_until_they_exist(
+            client,
+            &format!("{disk_url}/metrics/{metric}?{params}"),
+        )
+        .await;
+        let mut last_timestamp = None;
+        for item in &measurements.items {
+            match item.datum() {
+                Datum::F64(_) => (),
+                _ => panic!("Unexpected datum type {:?}", item.datum()),
+            }
+            assert_eq!(item.timestamp().timestamp() % WINDOW_SECS, 0);
+            assert_eq!(item.timestamp().timestamp_subsec_nanos(), 0);
+            if let Some(last_timestamp) = last_timestamp {
+                assert!(last_timestamp < item.timestamp());
+            }
+            last_timestamp = Some(item.timestamp());
+        }
+    }
+
+    // System metrics can be aggregated too.  The window may include samples
+    // from before the disk was created.
+    let measurements = query_for_metrics_until_they_exist(
+        client,
+        &format!(
+            "/system/metrics/virtual_disk_space_provisioned?{params}&id={}",
+            project_id
+        ),
+    )
+    .await;
+    let item = &measurements.items[measurements.items.len() - 1];
+    match item.datum() {
+        Datum::F64(size) => {
+            assert!(*size > 0.0);
+            assert!(*size <= i64::from(disk.size) as f64);
+        }
+        _ => panic!("Unexpected datum type {:?}", item.datum()),
+    }
+
+    // Aggregating requires a window.
+    let error = NexusRequest::expect_failure(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::GET,
+        &format!(
+            "{disk_url}/metrics/read?start_time={:?}&end_time={:?}\
+            &aggregation=max",
+            Utc::now() - chrono::Duration::seconds(10),
+            Utc::now() + chrono::Duration::seconds(10),
+        ),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body::<HttpErrorResponseBody>()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "\"aggregation\" and \"window_secs\" must be given together"
+    );
+}
+
 async fn disk_get(client: &ClientTestContext, disk_url: &str) -> Disk {
     NexusRequest::object_get(client, disk_url)
         .authn_as(AuthnMode::PrivilegedUser)
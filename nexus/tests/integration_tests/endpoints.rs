@@ -33,10 +33,16 @@ use omicron_nexus::external_api::params;
 use omicron_nexus::external_api::shared;
 use omicron_nexus::external_api::shared::IpRange;
 use omicron_nexus::external_api::shared::Ipv4Range;
+use oximeter_db::query::Aggregation;
+use oximeter_db::query::Reducer;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
+use std::num::NonZeroU32;
 use std::str::FromStr;
 
+// The demo instance is never migrated, so this migration doesn't exist.
+const DEMO_INSTANCE_MIGRATION_ID: &str = "ba6d2a87-9bb7-4bbc-8a7c-8f6b7e3c1a42";
+
 lazy_static! {
     pub static ref HARDWARE_RACK_URL: String =
         format!("/v1/system/hardware/racks/{}", RACK_UUID);
@@ -46,6 +52,12 @@ lazy_static! {
         format!("/v1/system/hardware/disks");
     pub static ref HARDWARE_SLED_DISK_URL: String =
         format!("/v1/system/hardware/sleds/{}/disks", SLED_AGENT_UUID);
+    pub static ref HARDWARE_SLED_CORDON_URL: String =
+        format!("/v1/system/hardware/sleds/{}/cordon", SLED_AGENT_UUID);
+    pub static ref HARDWARE_SLED_UNCORDON_URL: String =
+        format!("/v1/system/hardware/sleds/{}/uncordon", SLED_AGENT_UUID);
+    pub static ref HARDWARE_SLED_DRAIN_URL: String =
+        format!("/v1/system/hardware/sleds/{}/drain", SLED_AGENT_UUID);
 
     // Global policy
     pub static ref SYSTEM_POLICY_URL: &'static str = "/v1/system/policy";
@@ -56,6 +68,8 @@ lazy_static! {
         format!("/system/silos/{}", *DEMO_SILO_NAME);
     pub static ref DEMO_SILO_POLICY_URL: String =
         format!("/system/silos/{}/policy", *DEMO_SILO_NAME);
+    pub static ref DEMO_SILO_QUOTAS_URL: String =
+        format!("/system/silos/{}/quotas", *DEMO_SILO_NAME);
     pub static ref DEMO_SILO_CREATE: params::SiloCreate =
         params::SiloCreate {
             identity: IdentityMetadataCreateParams {
@@ -66,6 +80,13 @@ lazy_static! {
             identity_mode: shared::SiloIdentityMode::SamlJit,
             admin_group_name: None,
         };
+    // Quotas applied to the demo Silo, Organization, and Project
+    pub static ref DEMO_QUOTAS_UPDATE: params::ResourceQuotasUpdate =
+        params::ResourceQuotasUpdate {
+            cpus: Some(16),
+            memory: Some(ByteCount::from_gibibytes_u32(64)),
+            storage: Some(ByteCount::from_gibibytes_u32(1024)),
+        };
     // Use the default Silo for testing the local IdP
     pub static ref DEMO_SILO_USERS_CREATE_URL: String = format!(
         "/system/silos/{}/identity-providers/local/users",
@@ -94,6 +115,8 @@ lazy_static! {
         format!("/v1/organizations/{}", *DEMO_ORG_NAME);
     pub static ref DEMO_ORG_POLICY_URL: String =
         format!("/v1/organizations/{}/policy", *DEMO_ORG_NAME);
+    pub static ref DEMO_ORG_QUOTAS_URL: String =
+        format!("/v1/organizations/{}/quotas", *DEMO_ORG_NAME);
     pub static ref DEMO_ORG_PROJECTS_URL: String = format!("/v1/projects?organization={}", *DEMO_ORG_NAME);
     pub static ref DEMO_ORG_CREATE: params::OrganizationCreate =
         params::OrganizationCreate {
@@ -111,8 +134,14 @@ lazy_static! {
         format!("organization={}&project={}", *DEMO_ORG_NAME, *DEMO_PROJECT_NAME);
     pub static ref DEMO_PROJECT_POLICY_URL: String =
         format!("/v1/projects/{}/policy?organization={}", *DEMO_PROJECT_NAME, *DEMO_ORG_NAME);
+    pub static ref DEMO_PROJECT_QUOTAS_URL: String =
+        format!("/v1/projects/{}/quotas?organization={}", *DEMO_PROJECT_NAME, *DEMO_ORG_NAME);
+    pub static ref DEMO_PROJECT_URL_AFFINITY_GROUPS: String =
+        format!("/v1/affinity-groups?organization={}&project={}", *DEMO_ORG_NAME, *DEMO_PROJECT_NAME);
     pub static ref DEMO_PROJECT_URL_DISKS: String =
         format!("/v1/disks?organization={}&project={}", *DEMO_ORG_NAME, *DEMO_PROJECT_NAME);
+    pub static ref DEMO_PROJECT_URL_FLOATING_IPS: String =
+        format!("/v1/floating-ips?organization={}&project={}", *DEMO_ORG_NAME, *DEMO_PROJECT_NAME);
     pub static ref DEMO_PROJECT_URL_IMAGES: String =
         format!("/organizations/{}/projects/{}/images", *DEMO_ORG_NAME, *DEMO_PROJECT_NAME);
     pub static ref DEMO_PROJECT_URL_INSTANCES: String = format!("/v1/instances?organization={}&project={}", *DEMO_ORG_NAME, *DEMO_PROJECT_NAME);
@@ -216,6 +245,10 @@ lazy_static! {
                 DiskTest::DEFAULT_ZPOOL_SIZE_GIB / 2
             ),
         };
+    pub static ref DEMO_DISK_UPDATE: params::DiskUpdate =
+        params::DiskUpdate {
+            size: DEMO_DISK_CREATE.size,
+        };
     pub static ref DEMO_DISK_METRICS_URL: String =
         format!(
             "/organizations/{}/projects/{}/disks/{}/metrics/activated?start_time={:?}&end_time={:?}",
@@ -243,6 +276,12 @@ lazy_static! {
         format!("/v1/instances/{}/reboot?{}", *DEMO_INSTANCE_NAME, *DEMO_PROJECT_SELECTOR);
     pub static ref DEMO_INSTANCE_MIGRATE_URL: String =
         format!("/v1/instances/{}/migrate?{}", *DEMO_INSTANCE_NAME, *DEMO_PROJECT_SELECTOR);
+    pub static ref DEMO_INSTANCE_MIGRATIONS_URL: String =
+        format!("/v1/instances/{}/migrations?{}", *DEMO_INSTANCE_NAME, *DEMO_PROJECT_SELECTOR);
+    pub static ref DEMO_INSTANCE_MIGRATION_URL: String =
+        format!("/v1/instances/{}/migrations/{}?{}", *DEMO_INSTANCE_NAME, DEMO_INSTANCE_MIGRATION_ID, *DEMO_PROJECT_SELECTOR);
+    pub static ref DEMO_INSTANCE_MIGRATION_CANCEL_URL: String =
+        format!("/v1/instances/{}/migrations/{}/cancel?{}", *DEMO_INSTANCE_NAME, DEMO_INSTANCE_MIGRATION_ID, *DEMO_PROJECT_SELECTOR);
     pub static ref DEMO_INSTANCE_SERIAL_URL: String =
         format!("/v1/instances/{}/serial-console?{}", *DEMO_INSTANCE_NAME, *DEMO_PROJECT_SELECTOR);
     pub static ref DEMO_INSTANCE_SERIAL_STREAM_URL: String =
@@ -276,8 +315,14 @@ lazy_static! {
                 params::ExternalIpCreate::Ephemeral { pool_name: Some(DEMO_IP_POOL_NAME.clone()) }
             ],
             disks: vec![],
+            affinity_groups: Vec::new(),
             start: true,
         };
+    pub static ref DEMO_INSTANCE_UPDATE: params::InstanceUpdate =
+        params::InstanceUpdate {
+            ncpus: InstanceCpuCount(2),
+            memory: ByteCount::from_gibibytes_u32(16),
+        };
 
     // The instance needs a network interface, too.
     pub static ref DEMO_INSTANCE_NIC_NAME: Name =
@@ -325,6 +370,38 @@ lazy_static! {
         };
 }
 
+lazy_static! {
+    pub static ref DEMO_ALERT_RULE_NAME: Name =
+        "demo-alert-rule".parse().unwrap();
+    pub static ref DEMO_ALERT_RULES_URL: String =
+        format!("/v1/system/alert-rules");
+    pub static ref DEMO_ALERT_RULE_URL: String =
+        format!("/v1/system/alert-rules/demo-alert-rule");
+    pub static ref DEMO_ALERT_RULE_ALERTS_URL: String =
+        format!("/v1/system/alert-rules/demo-alert-rule/alerts");
+    pub static ref DEMO_ALERT_RULE_CREATE: params::AlertRuleCreate =
+        params::AlertRuleCreate {
+            identity: IdentityMetadataCreateParams {
+                name: DEMO_ALERT_RULE_NAME.clone(),
+                description: String::from(""),
+            },
+            timeseries_name: String::from(
+                "collection_target:virtual_disk_space_provisioned"
+            ),
+            criteria: vec![],
+            aggregation: Aggregation {
+                reducer: Reducer::Sum,
+                group_by: vec![String::from("id")],
+                window_secs: NonZeroU32::new(60).unwrap(),
+                rate: false,
+                statistic: None,
+            },
+            comparison: shared::AlertComparison::Above,
+            threshold: 1e12,
+            duration_secs: NonZeroU32::new(300).unwrap(),
+        };
+}
+
 lazy_static! {
     // Project Images
     pub static ref DEMO_IMAGE_NAME: Name = "demo-image".parse().unwrap();
@@ -403,6 +480,40 @@ lazy_static! {
             disk: DEMO_DISK_NAME.clone(),
         };
 
+    // Floating IPs
+    pub static ref DEMO_FLOATING_IP_NAME: Name = "demo-floating-ip".parse().unwrap();
+    pub static ref DEMO_FLOATING_IP_URL: String =
+        format!("/v1/floating-ips/{}?{}", *DEMO_FLOATING_IP_NAME, *DEMO_PROJECT_SELECTOR);
+    pub static ref DEMO_FLOATING_IP_ATTACH_URL: String =
+        format!("/v1/floating-ips/{}/attach?{}", *DEMO_FLOATING_IP_NAME, *DEMO_PROJECT_SELECTOR);
+    pub static ref DEMO_FLOATING_IP_DETACH_URL: String =
+        format!("/v1/floating-ips/{}/detach?{}", *DEMO_FLOATING_IP_NAME, *DEMO_PROJECT_SELECTOR);
+    pub static ref DEMO_FLOATING_IP_CREATE: params::FloatingIpCreate =
+        params::FloatingIpCreate {
+            identity: IdentityMetadataCreateParams {
+                name: DEMO_FLOATING_IP_NAME.clone(),
+                description: String::from("a new IP"),
+            },
+            address: None,
+            pool: None,
+        };
+
+    // Affinity groups
+    pub static ref DEMO_AFFINITY_GROUP_NAME: Name = "demo-affinity-group".parse().unwrap();
+    pub static ref DEMO_AFFINITY_GROUP_URL: String =
+        format!("/v1/affinity-groups/{}?{}", *DEMO_AFFINITY_GROUP_NAME, *DEMO_PROJECT_SELECTOR);
+    pub static ref DEMO_AFFINITY_GROUP_MEMBERS_URL: String =
+        format!("/v1/affinity-groups/{}/members?{}", *DEMO_AFFINITY_GROUP_NAME, *DEMO_PROJECT_SELECTOR);
+    pub static ref DEMO_AFFINITY_GROUP_CREATE: params::AffinityGroupCreate =
+        params::AffinityGroupCreate {
+            identity: IdentityMetadataCreateParams {
+                name: DEMO_AFFINITY_GROUP_NAME.clone(),
+                description: String::from(""),
+            },
+            kind: shared::AffinityGroupKind::AntiAffinity,
+            policy: shared::AffinityPolicy::Soft,
+        };
+
     // SSH keys
     pub static ref DEMO_SSHKEYS_URL: &'static str = "/session/me/sshkeys";
     pub static ref DEMO_SSHKEY_NAME: Name = "aaaaa-ssh-key".parse().unwrap();
@@ -775,6 +886,17 @@ lazy_static! {
                 ),
             ],
         },
+        VerifyEndpoint {
+            url: &DEMO_SILO_QUOTAS_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Put(
+                    serde_json::to_value(&*DEMO_QUOTAS_UPDATE).unwrap()
+                ),
+            ],
+        },
         VerifyEndpoint {
             url: "/v1/policy",
             visibility: Visibility::Public,
@@ -906,6 +1028,18 @@ lazy_static! {
             ],
         },
 
+        VerifyEndpoint {
+            url: &DEMO_ORG_QUOTAS_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Put(
+                    serde_json::to_value(&*DEMO_QUOTAS_UPDATE).unwrap()
+                ),
+            ],
+        },
+
         /* Projects */
 
         // TODO-security TODO-correctness One thing that's a little strange
@@ -962,6 +1096,18 @@ lazy_static! {
             ],
         },
 
+        VerifyEndpoint {
+            url: &DEMO_PROJECT_QUOTAS_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Put(
+                    serde_json::to_value(&*DEMO_QUOTAS_UPDATE).unwrap()
+                ),
+            ],
+        },
+
         /* VPCs */
         VerifyEndpoint {
             url: &DEMO_PROJECT_URL_VPCS,
@@ -1173,6 +1319,9 @@ lazy_static! {
             unprivileged_access: UnprivilegedAccess::None,
             allowed_methods: vec![
                 AllowedMethod::Get,
+                AllowedMethod::Put(
+                    serde_json::to_value(&*DEMO_DISK_UPDATE).unwrap()
+                ),
                 AllowedMethod::Delete,
             ],
         },
@@ -1228,7 +1377,7 @@ lazy_static! {
             visibility: Visibility::Protected,
             unprivileged_access: UnprivilegedAccess::None,
             allowed_methods: vec![
-                AllowedMethod::GetUnimplemented,
+                AllowedMethod::Get,
                 AllowedMethod::Post(
                     serde_json::to_value(&*DEMO_IMAGE_CREATE).unwrap()
                 ),
@@ -1240,7 +1389,7 @@ lazy_static! {
             visibility: Visibility::Protected,
             unprivileged_access: UnprivilegedAccess::None,
             allowed_methods: vec![
-                AllowedMethod::GetUnimplemented,
+                AllowedMethod::Get,
             ],
         },
 
@@ -1249,7 +1398,7 @@ lazy_static! {
             visibility: Visibility::Protected,
             unprivileged_access: UnprivilegedAccess::None,
             allowed_methods: vec![
-                AllowedMethod::GetUnimplemented,
+                AllowedMethod::Get,
                 AllowedMethod::Delete,
             ],
         },
@@ -1278,6 +1427,85 @@ lazy_static! {
             ]
         },
 
+        /* Floating IPs */
+
+        VerifyEndpoint {
+            url: &DEMO_PROJECT_URL_FLOATING_IPS,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Post(
+                    serde_json::to_value(DEMO_FLOATING_IP_CREATE.clone()).unwrap(),
+                )
+            ]
+        },
+
+        VerifyEndpoint {
+            url: &DEMO_FLOATING_IP_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Delete,
+            ]
+        },
+
+        VerifyEndpoint {
+            url: &DEMO_FLOATING_IP_ATTACH_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Post(
+                    serde_json::to_value(params::FloatingIpAttach {
+                        instance: DEMO_INSTANCE_NAME.clone().into()
+                    }).unwrap()
+                )
+            ],
+        },
+
+        VerifyEndpoint {
+            url: &DEMO_FLOATING_IP_DETACH_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Post(serde_json::Value::Null)
+            ],
+        },
+
+        /* Affinity groups */
+
+        VerifyEndpoint {
+            url: &DEMO_PROJECT_URL_AFFINITY_GROUPS,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Post(
+                    serde_json::to_value(DEMO_AFFINITY_GROUP_CREATE.clone()).unwrap(),
+                )
+            ]
+        },
+
+        VerifyEndpoint {
+            url: &DEMO_AFFINITY_GROUP_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Delete,
+            ]
+        },
+
+        VerifyEndpoint {
+            url: &DEMO_AFFINITY_GROUP_MEMBERS_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+            ]
+        },
+
         /* Instances */
         VerifyEndpoint {
             url: &DEMO_PROJECT_URL_INSTANCES,
@@ -1297,6 +1525,9 @@ lazy_static! {
             unprivileged_access: UnprivilegedAccess::None,
             allowed_methods: vec![
                 AllowedMethod::Get,
+                AllowedMethod::Put(
+                    serde_json::to_value(&*DEMO_INSTANCE_UPDATE).unwrap()
+                ),
                 AllowedMethod::Delete,
             ],
         },
@@ -1332,11 +1563,35 @@ lazy_static! {
             allowed_methods: vec![
                 AllowedMethod::Post(serde_json::to_value(
                     params::InstanceMigrate {
-                        dst_sled_id: uuid::Uuid::new_v4(),
+                        dst_sled_id: Some(uuid::Uuid::new_v4()),
                     }
                 ).unwrap()),
             ],
         },
+        VerifyEndpoint {
+            url: &DEMO_INSTANCE_MIGRATIONS_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get
+            ],
+        },
+        VerifyEndpoint {
+            url: &DEMO_INSTANCE_MIGRATION_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::GetNonexistent
+            ],
+        },
+        VerifyEndpoint {
+            url: &DEMO_INSTANCE_MIGRATION_CANCEL_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Post(serde_json::Value::Null)
+            ],
+        },
         VerifyEndpoint {
             url: &DEMO_INSTANCE_SERIAL_URL,
             visibility: Visibility::Protected,
@@ -1460,6 +1715,34 @@ lazy_static! {
             allowed_methods: vec![AllowedMethod::Get],
         },
 
+        VerifyEndpoint {
+            url: &HARDWARE_SLED_CORDON_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Post(serde_json::Value::Null)
+            ],
+        },
+
+        VerifyEndpoint {
+            url: &HARDWARE_SLED_UNCORDON_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Post(serde_json::Value::Null)
+            ],
+        },
+
+        VerifyEndpoint {
+            url: &HARDWARE_SLED_DRAIN_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Post(serde_json::Value::Null)
+            ],
+        },
+
         /* Sagas */
 
         VerifyEndpoint {
@@ -1485,6 +1768,15 @@ lazy_static! {
             allowed_methods: vec![AllowedMethod::Get],
         },
 
+        /* Audit log */
+
+        VerifyEndpoint {
+            url: "/v1/audit-log",
+            visibility: Visibility::Public,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![AllowedMethod::Get],
+        },
+
         /* Updates */
 
         VerifyEndpoint {
@@ -1693,6 +1985,34 @@ lazy_static! {
             ],
         },
 
+        /* Alerts */
+        VerifyEndpoint {
+            url: &DEMO_ALERT_RULES_URL,
+            visibility: Visibility::Public,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Post(
+                    serde_json::to_value(&*DEMO_ALERT_RULE_CREATE).unwrap(),
+                ),
+            ],
+        },
+        VerifyEndpoint {
+            url: &DEMO_ALERT_RULE_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Delete,
+            ],
+        },
+        VerifyEndpoint {
+            url: &DEMO_ALERT_RULE_ALERTS_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![AllowedMethod::Get],
+        },
+
         /* Certificates */
         VerifyEndpoint {
             url: &DEMO_CERTIFICATES_URL,
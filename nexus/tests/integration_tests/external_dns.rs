@@ -0,0 +1,224 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests that Nexus keeps the external DNS zone up to date
+
+use super::instances::instance_simulate;
+use dns_service_client::types::DnsRecord;
+use dropshot::test_util::ClientTestContext;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::object_create;
+use nexus_test_utils::resource_helpers::object_delete;
+use nexus_test_utils::resource_helpers::populate_ip_pool;
+use nexus_test_utils::EXTERNAL_DNS_ZONE;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::IdentityMetadataUpdateParams;
+use omicron_common::api::external::Instance;
+use omicron_common::api::external::InstanceCpuCount;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::shared::IpRange;
+use omicron_nexus::external_api::shared::Ipv4Range;
+use omicron_nexus::external_api::views::Project;
+use omicron_test_utils::dev::poll::{wait_for_condition, CondCheckError};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+type ControlPlaneTestContext =
+    nexus_test_utils::ControlPlaneTestContext<omicron_nexus::Server>;
+
+const ORGANIZATION_NAME: &str = "rainforest";
+const PROJECT_NAME: &str = "carcosa";
+
+/// Returns the names and addresses in the external DNS zone.
+async fn external_dns_records(
+    cptestctx: &ControlPlaneTestContext,
+) -> BTreeMap<String, Vec<IpAddr>> {
+    let client = cptestctx.external_dns.client(&cptestctx.logctx.log);
+    client
+        .dns_records_list(EXTERNAL_DNS_ZONE)
+        .await
+        .unwrap()
+        .into_inner()
+        .into_iter()
+        .map(|kv| {
+            let ips = kv
+                .records
+                .into_iter()
+                .filter_map(|record| match record {
+                    DnsRecord::A(ip) => Some(IpAddr::V4(ip)),
+                    DnsRecord::Aaaa(ip) => Some(IpAddr::V6(ip)),
+                    _ => None,
+                })
+                .collect();
+            (kv.key.name, ips)
+        })
+        .collect()
+}
+
+/// Waits for Nexus to publish names and addresses in the external DNS zone
+/// that satisfy `check`, and returns them.
+///
+/// Nexus updates the zone in the background, so changes made through the API
+/// show up some time after the request that made them completes.
+async fn wait_for_external_dns_records<F>(
+    cptestctx: &ControlPlaneTestContext,
+    check: F,
+) -> BTreeMap<String, Vec<IpAddr>>
+where
+    F: Fn(&BTreeMap<String, Vec<IpAddr>>) -> bool,
+{
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+    const POLL_DURATION: Duration = Duration::from_secs(30);
+    wait_for_condition(
+        || async {
+            let records = external_dns_records(cptestctx).await;
+            if check(&records) {
+                Ok(records)
+            } else {
+                Err(CondCheckError::<Infallible>::NotYet)
+            }
+        },
+        &POLL_INTERVAL,
+        &POLL_DURATION,
+    )
+    .await
+    .expect("external DNS zone was not updated")
+}
+
+async fn create_instance_with_ephemeral_ip(
+    client: &ClientTestContext,
+    project_name: &str,
+    name: &str,
+) -> Instance {
+    object_create(
+        client,
+        &format!(
+            "/v1/instances?organization={}&project={}",
+            ORGANIZATION_NAME, project_name
+        ),
+        &params::InstanceCreate {
+            identity: IdentityMetadataCreateParams {
+                name: name.parse().unwrap(),
+                description: format!("instance {:?}", name),
+            },
+            ncpus: InstanceCpuCount(4),
+            memory: ByteCount::from_gibibytes_u32(1),
+            hostname: String::from("the_host"),
+            user_data: vec![],
+            network_interfaces:
+                params::InstanceNetworkInterfaceAttachment::Default,
+            external_ips: vec![params::ExternalIpCreate::Ephemeral {
+                pool_name: None,
+            }],
+            disks: vec![],
+            affinity_groups: Vec::new(),
+            start: true,
+        },
+    )
+    .await
+}
+
+#[nexus_test]
+async fn test_external_dns_instance_names(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx().nexus;
+    let range = IpRange::V4(
+        Ipv4Range::new(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 5))
+            .unwrap(),
+    );
+    populate_ip_pool(&client, "default", Some(range)).await;
+    create_organization(&client, ORGANIZATION_NAME).await;
+    create_project(client, ORGANIZATION_NAME, PROJECT_NAME).await;
+
+    // Nothing is published until an instance has an external address.  The
+    // test rack has no Nexus services with external addresses, so Silos
+    // aren't published either.
+    assert!(external_dns_records(cptestctx).await.is_empty());
+
+    // An instance with an ephemeral IP gets a name under its Project,
+    // Organization, and Silo.
+    let instance =
+        create_instance_with_ephemeral_ip(client, PROJECT_NAME, "web").await;
+    let records =
+        wait_for_external_dns_records(cptestctx, |r| !r.is_empty()).await;
+    let name = format!(
+        "web.{}.{}.default-silo.{}",
+        PROJECT_NAME, ORGANIZATION_NAME, EXTERNAL_DNS_ZONE
+    );
+    assert_eq!(records.keys().collect::<Vec<_>>(), vec![&name]);
+    let ips = &records[&name];
+    assert_eq!(ips.len(), 1);
+    assert!(ips[0] >= range.first_address() && ips[0] <= range.last_address());
+
+    // Renaming the Project renames the instance's record.
+    let project: Project = NexusRequest::object_put(
+        client,
+        &format!(
+            "/v1/projects/{}?organization={}",
+            PROJECT_NAME, ORGANIZATION_NAME
+        ),
+        Some(&params::ProjectUpdate {
+            identity: IdentityMetadataUpdateParams {
+                name: Some("ys".parse().unwrap()),
+                description: None,
+            },
+        }),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(project.identity.name.as_str(), "ys");
+    let renamed = format!(
+        "web.ys.{}.default-silo.{}",
+        ORGANIZATION_NAME, EXTERNAL_DNS_ZONE
+    );
+    let records =
+        wait_for_external_dns_records(cptestctx, |r| r.contains_key(&renamed))
+            .await;
+    assert_eq!(records.keys().collect::<Vec<_>>(), vec![&renamed]);
+    assert_eq!(&records[&renamed], ips);
+
+    // Deleting the instance removes its record.
+    instance_simulate(nexus, &instance.identity.id).await;
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &format!(
+                "/v1/instances/web/stop?organization={}&project=ys",
+                ORGANIZATION_NAME
+            ),
+        )
+        .body(None as Option<&serde_json::Value>)
+        .expect_status(Some(StatusCode::ACCEPTED)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    instance_simulate(nexus, &instance.identity.id).await;
+    object_delete(
+        client,
+        &format!(
+            "/v1/instances/web?organization={}&project=ys",
+            ORGANIZATION_NAME
+        ),
+    )
+    .await;
+    wait_for_external_dns_records(cptestctx, |r| r.is_empty()).await;
+}
@@ -0,0 +1,437 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests Floating IP support in the API
+
+use super::instances::instance_simulate;
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use dropshot::ResultsPage;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::object_create;
+use nexus_test_utils::resource_helpers::object_delete;
+use nexus_test_utils::resource_helpers::objects_list_page_authz;
+use nexus_test_utils::resource_helpers::populate_ip_pool;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::Instance;
+use omicron_common::api::external::InstanceCpuCount;
+use omicron_common::api::external::NameOrId;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::shared::IpKind;
+use omicron_nexus::external_api::shared::IpRange;
+use omicron_nexus::external_api::shared::Ipv4Range;
+use omicron_nexus::external_api::views;
+use omicron_nexus::external_api::views::FloatingIp;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+
+type ControlPlaneTestContext =
+    nexus_test_utils::ControlPlaneTestContext<omicron_nexus::Server>;
+
+const ORGANIZATION_NAME: &str = "rainforest";
+const PROJECT_NAME: &str = "carcosa";
+const PROJECT_SELECTOR: &str = "organization=rainforest&project=carcosa";
+
+fn get_floating_ips_url() -> String {
+    format!("/v1/floating-ips?{}", PROJECT_SELECTOR)
+}
+
+fn get_floating_ip_url(name: &str) -> String {
+    format!("/v1/floating-ips/{}?{}", name, PROJECT_SELECTOR)
+}
+
+async fn create_org_and_project(client: &ClientTestContext) -> IpRange {
+    let range = IpRange::V4(
+        Ipv4Range::new(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 5))
+            .unwrap(),
+    );
+    populate_ip_pool(&client, "default", Some(range)).await;
+    create_organization(&client, ORGANIZATION_NAME).await;
+    create_project(client, ORGANIZATION_NAME, PROJECT_NAME).await;
+    range
+}
+
+async fn create_floating_ip(
+    client: &ClientTestContext,
+    name: &str,
+    address: Option<IpAddr>,
+) -> FloatingIp {
+    object_create(
+        client,
+        &get_floating_ips_url(),
+        &params::FloatingIpCreate {
+            identity: IdentityMetadataCreateParams {
+                name: name.parse().unwrap(),
+                description: format!("floating IP {:?}", name),
+            },
+            address,
+            pool: None,
+        },
+    )
+    .await
+}
+
+async fn create_instance_with_ips(
+    client: &ClientTestContext,
+    name: &str,
+    external_ips: Vec<params::ExternalIpCreate>,
+) -> Instance {
+    object_create(
+        client,
+        &format!("/v1/instances?{}", PROJECT_SELECTOR),
+        &params::InstanceCreate {
+            identity: IdentityMetadataCreateParams {
+                name: name.parse().unwrap(),
+                description: format!("instance {:?}", name),
+            },
+            ncpus: InstanceCpuCount(4),
+            memory: ByteCount::from_gibibytes_u32(1),
+            hostname: String::from("the_host"),
+            user_data: vec![],
+            network_interfaces:
+                params::InstanceNetworkInterfaceAttachment::Default,
+            external_ips,
+            disks: vec![],
+            affinity_groups: Vec::new(),
+            start: true,
+        },
+    )
+    .await
+}
+
+async fn floating_ip_get(client: &ClientTestContext, url: &str) -> FloatingIp {
+    NexusRequest::object_get(client, url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body()
+        .unwrap()
+}
+
+async fn floating_ip_post(
+    client: &ClientTestContext,
+    url: &str,
+    body: Option<&params::FloatingIpAttach>,
+    status: StatusCode,
+) -> dropshot::test_util::TestResponse {
+    let builder = RequestBuilder::new(client, Method::POST, url)
+        .body(body)
+        .expect_status(Some(status));
+    NexusRequest::new(builder)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+}
+
+/// Starts or stops the instance "inst", depending on `action`.
+async fn instance_post(client: &ClientTestContext, action: &str) {
+    floating_ip_post(
+        client,
+        &format!("/v1/instances/inst/{}?{}", action, PROJECT_SELECTOR),
+        None,
+        StatusCode::ACCEPTED,
+    )
+    .await;
+}
+
+async fn instance_external_ips(
+    client: &ClientTestContext,
+    instance_name: &str,
+) -> Vec<views::ExternalIp> {
+    NexusRequest::object_get(
+        client,
+        &format!(
+            "/organizations/{}/projects/{}/instances/{}/external-ips",
+            ORGANIZATION_NAME, PROJECT_NAME, instance_name
+        ),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body::<ResultsPage<views::ExternalIp>>()
+    .unwrap()
+    .items
+}
+
+#[nexus_test]
+async fn test_floating_ip_create_and_delete(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let range = create_org_and_project(client).await;
+
+    // No Floating IPs to start with.
+    let fips =
+        objects_list_page_authz::<FloatingIp>(client, &get_floating_ips_url())
+            .await
+            .items;
+    assert!(fips.is_empty());
+
+    // Create one Floating IP with an automatically-assigned address, and one
+    // with an explicit address.
+    let fip = create_floating_ip(client, "fip-auto", None).await;
+    assert!(fip.ip >= range.first_address() && fip.ip <= range.last_address());
+    assert_eq!(fip.instance_id, None);
+    let address = IpAddr::from(Ipv4Addr::new(10, 0, 0, 5));
+    let explicit_fip =
+        create_floating_ip(client, "fip-explicit", Some(address)).await;
+    assert_eq!(explicit_fip.ip, address);
+    assert_eq!(explicit_fip.project_id, fip.project_id);
+
+    // Both can be fetched by name or by ID, and are listed in the project.
+    let fetched =
+        floating_ip_get(client, &get_floating_ip_url("fip-auto")).await;
+    assert_eq!(fetched.identity.id, fip.identity.id);
+    let fetched = floating_ip_get(
+        client,
+        &format!("/v1/floating-ips/{}", explicit_fip.identity.id),
+    )
+    .await;
+    assert_eq!(fetched.ip, address);
+    let fips =
+        objects_list_page_authz::<FloatingIp>(client, &get_floating_ips_url())
+            .await
+            .items;
+    assert_eq!(fips.len(), 2);
+
+    // Names must be unique within a project.
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        &get_floating_ips_url(),
+        &params::FloatingIpCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "fip-auto".parse().unwrap(),
+                description: String::from("a duplicate"),
+            },
+            address: None,
+            pool: None,
+        },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(error.message, "already exists: floating-ip \"fip-auto\"");
+
+    // An address may only be reserved once.
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        &get_floating_ips_url(),
+        &params::FloatingIpCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "fip-taken".parse().unwrap(),
+                description: String::from("an address that's in use"),
+            },
+            address: Some(address),
+            pool: None,
+        },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(error.message, "Requested external IP address not available");
+
+    // Deleting a Floating IP returns its address to the pool.
+    object_delete(client, &get_floating_ip_url("fip-explicit")).await;
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        &get_floating_ip_url("fip-explicit"),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    let fip = create_floating_ip(client, "fip-again", Some(address)).await;
+    assert_eq!(fip.ip, address);
+}
+
+#[nexus_test]
+async fn test_floating_ip_attach_and_detach(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx().nexus;
+    let sled_agent = &cptestctx.sled_agent.sled_agent;
+    create_org_and_project(client).await;
+
+    // Create a running instance without any external addresses.
+    let instance = create_instance_with_ips(client, "inst", vec![]).await;
+    let instance_id = instance.identity.id;
+    instance_simulate(nexus, &instance_id).await;
+    assert!(instance_external_ips(client, "inst").await.is_empty());
+    assert_eq!(
+        sled_agent.instance_external_ips(instance_id).await,
+        Some(vec![])
+    );
+
+    // Attach a Floating IP to it, which should be propagated to the sled.
+    let fip = create_floating_ip(client, "fip", None).await;
+    let attach = params::FloatingIpAttach {
+        instance: NameOrId::Name("inst".parse().unwrap()),
+    };
+    let attach_url =
+        format!("/v1/floating-ips/fip/attach?{}", PROJECT_SELECTOR);
+    let attached: FloatingIp = floating_ip_post(
+        client,
+        &attach_url,
+        Some(&attach),
+        StatusCode::ACCEPTED,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(attached.instance_id, Some(instance_id));
+    let ips = instance_external_ips(client, "inst").await;
+    assert_eq!(ips.len(), 1);
+    assert_eq!(ips[0].kind, IpKind::Floating);
+    assert_eq!(ips[0].ip, fip.ip);
+    assert_eq!(
+        sled_agent.instance_external_ips(instance_id).await,
+        Some(vec![fip.ip])
+    );
+
+    // Attaching it again is fine, but another Floating IP won't fit.
+    floating_ip_post(client, &attach_url, Some(&attach), StatusCode::ACCEPTED)
+        .await;
+    create_floating_ip(client, "fip2", None).await;
+    let error: HttpErrorResponseBody = floating_ip_post(
+        client,
+        &format!("/v1/floating-ips/fip2/attach?{}", PROJECT_SELECTOR),
+        Some(&attach),
+        StatusCode::BAD_REQUEST,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "An instance may not have more than 1 external IP addresses"
+    );
+
+    // An attached Floating IP can't be deleted.
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::DELETE,
+        &get_floating_ip_url("fip"),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "Floating IP cannot be deleted while attached to an instance"
+    );
+
+    // Detach it, which also removes it from the sled.
+    let detach_url =
+        format!("/v1/floating-ips/fip/detach?{}", PROJECT_SELECTOR);
+    let detached: FloatingIp =
+        floating_ip_post(client, &detach_url, None, StatusCode::ACCEPTED)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(detached.instance_id, None);
+    assert!(instance_external_ips(client, "inst").await.is_empty());
+    assert_eq!(
+        sled_agent.instance_external_ips(instance_id).await,
+        Some(vec![])
+    );
+
+    // Changes are refused while the instance is moving between states.
+    instance_post(client, "stop").await;
+    let error: HttpErrorResponseBody = floating_ip_post(
+        client,
+        &attach_url,
+        Some(&attach),
+        StatusCode::BAD_REQUEST,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "cannot attach or detach floating IPs while instance is stopping"
+    );
+
+    // A stopped instance is given the address when it's next started.
+    instance_simulate(nexus, &instance_id).await;
+    floating_ip_post(client, &attach_url, Some(&attach), StatusCode::ACCEPTED)
+        .await;
+    instance_post(client, "start").await;
+    instance_simulate(nexus, &instance_id).await;
+    assert_eq!(
+        sled_agent.instance_external_ips(instance_id).await,
+        Some(vec![fip.ip])
+    );
+    floating_ip_post(client, &detach_url, None, StatusCode::ACCEPTED).await;
+    object_delete(client, &get_floating_ip_url("fip")).await;
+}
+
+#[nexus_test]
+async fn test_instance_create_with_floating_ip(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx().nexus;
+    let sled_agent = &cptestctx.sled_agent.sled_agent;
+    create_org_and_project(client).await;
+
+    // Create an instance using an existing Floating IP.
+    let fip = create_floating_ip(client, "fip", None).await;
+    let instance = create_instance_with_ips(
+        client,
+        "inst",
+        vec![params::ExternalIpCreate::Floating {
+            floating_ip_name: "fip".parse().unwrap(),
+        }],
+    )
+    .await;
+    let instance_id = instance.identity.id;
+    let fetched = floating_ip_get(client, &get_floating_ip_url("fip")).await;
+    assert_eq!(fetched.instance_id, Some(instance_id));
+    let ips = instance_external_ips(client, "inst").await;
+    assert_eq!(ips.len(), 1);
+    assert_eq!(ips[0].kind, IpKind::Floating);
+    assert_eq!(ips[0].ip, fip.ip);
+    assert_eq!(
+        sled_agent.instance_external_ips(instance_id).await,
+        Some(vec![fip.ip])
+    );
+
+    // Stop and delete the instance. The Floating IP outlives it.
+    let instance_url = format!("/v1/instances/inst?{}", PROJECT_SELECTOR);
+    instance_simulate(nexus, &instance_id).await;
+    instance_post(client, "stop").await;
+    instance_simulate(nexus, &instance_id).await;
+    object_delete(client, &instance_url).await;
+    let fetched = floating_ip_get(client, &get_floating_ip_url("fip")).await;
+    assert_eq!(fetched.instance_id, None);
+    object_delete(client, &get_floating_ip_url("fip")).await;
+}
@@ -9,14 +9,20 @@ use http::StatusCode;
 use nexus_test_utils::http_testing::AuthnMode;
 use nexus_test_utils::http_testing::NexusRequest;
 use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::create_disk;
 use nexus_test_utils::resource_helpers::create_organization;
 use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::object_create;
 use nexus_test_utils::resource_helpers::DiskTest;
 use nexus_test_utils_macros::nexus_test;
 
-use omicron_common::api::external::{ByteCount, IdentityMetadataCreateParams};
+use omicron_common::api::external::{
+    ByteCount, Disk, IdentityMetadataCreateParams,
+};
 use omicron_nexus::external_api::params;
 use omicron_nexus::external_api::views::GlobalImage;
+use omicron_nexus::external_api::views::Image;
+use omicron_nexus::external_api::views::Snapshot;
 
 use httptest::{matchers::*, responders::*, Expectation, ServerBuilder};
 
@@ -425,3 +431,295 @@ async fn test_make_disk_from_global_image_too_small(
         )
     );
 }
+
+#[nexus_test]
+async fn test_project_image_create_list_delete(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    DiskTest::new(&cptestctx).await;
+
+    let server = ServerBuilder::new().run().unwrap();
+    server.expect(
+        Expectation::matching(request::method_path("HEAD", "/image.raw"))
+            .times(1..)
+            .respond_with(
+                status_code(200).append_header(
+                    "Content-Length",
+                    format!("{}", 4096 * 1000),
+                ),
+            ),
+    );
+
+    create_organization(&client, "myorg").await;
+    create_project(client, "myorg", "myproj").await;
+
+    let images_url = "/organizations/myorg/projects/myproj/images";
+
+    // No project images yet
+    let images: Vec<Image> =
+        NexusRequest::iter_collection_authn(client, images_url, "", None)
+            .await
+            .expect("failed to list images")
+            .all_items;
+    assert_eq!(images.len(), 0);
+
+    // Create one!
+    let image_create_params = params::ImageCreate {
+        identity: IdentityMetadataCreateParams {
+            name: "my-os".parse().unwrap(),
+            description: String::from("our own OS build"),
+        },
+        source: params::ImageSource::Url {
+            url: server.url("/image.raw").to_string(),
+        },
+        block_size: params::BlockSize::try_from(512).unwrap(),
+    };
+
+    let image: Image =
+        NexusRequest::objects_post(client, images_url, &image_create_params)
+            .authn_as(AuthnMode::PrivilegedUser)
+            .execute()
+            .await
+            .unwrap()
+            .parsed_body()
+            .unwrap();
+    assert_eq!(image.identity.name, "my-os");
+    assert_eq!(image.size, ByteCount::from(4096 * 1000));
+
+    // Creating it again conflicts
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, images_url)
+            .body(Some(&image_create_params))
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("unexpected success");
+
+    // It can be fetched by name and by id, and is listed
+    let image_url = format!("{}/my-os", images_url);
+    let fetched: Image = NexusRequest::object_get(client, &image_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body()
+        .unwrap();
+    assert_eq!(fetched.identity.id, image.identity.id);
+
+    let fetched: Image = NexusRequest::object_get(
+        client,
+        &format!("/by-id/images/{}", image.identity.id),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(fetched.identity.name, "my-os");
+
+    let images: Vec<Image> =
+        NexusRequest::iter_collection_authn(client, images_url, "", None)
+            .await
+            .expect("failed to list images")
+            .all_items;
+    assert_eq!(images.len(), 1);
+
+    // Delete it
+    NexusRequest::object_delete(client, &image_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+
+    let images: Vec<Image> =
+        NexusRequest::iter_collection_authn(client, images_url, "", None)
+            .await
+            .expect("failed to list images")
+            .all_items;
+    assert_eq!(images.len(), 0);
+
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::GET, &image_url)
+            .expect_status(Some(StatusCode::NOT_FOUND)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("unexpected success");
+}
+
+#[nexus_test]
+async fn test_make_disk_from_project_image(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    DiskTest::new(&cptestctx).await;
+
+    let server = ServerBuilder::new().run().unwrap();
+    server.expect(
+        Expectation::matching(request::method_path("HEAD", "/my-os.raw"))
+            .times(1..)
+            .respond_with(
+                status_code(200).append_header(
+                    "Content-Length",
+                    format!("{}", 4096 * 1000),
+                ),
+            ),
+    );
+
+    create_organization(&client, "myorg").await;
+    create_project(client, "myorg", "myproj").await;
+
+    let image_create_params = params::ImageCreate {
+        identity: IdentityMetadataCreateParams {
+            name: "my-os".parse().unwrap(),
+            description: String::from("our own OS build"),
+        },
+        source: params::ImageSource::Url {
+            url: server.url("/my-os.raw").to_string(),
+        },
+        block_size: params::BlockSize::try_from(512).unwrap(),
+    };
+
+    let image: Image = NexusRequest::objects_post(
+        client,
+        "/organizations/myorg/projects/myproj/images",
+        &image_create_params,
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+
+    let new_disk = params::DiskCreate {
+        identity: IdentityMetadataCreateParams {
+            name: "disk".parse().unwrap(),
+            description: String::from("booted from our own image"),
+        },
+        disk_source: params::DiskSource::Image { image_id: image.identity.id },
+        size: ByteCount::from_gibibytes_u32(1),
+    };
+
+    NexusRequest::objects_post(
+        client,
+        "/organizations/myorg/projects/myproj/disks",
+        &new_disk,
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // A disk smaller than the image is rejected
+    let new_disk = params::DiskCreate {
+        identity: IdentityMetadataCreateParams {
+            name: "tiny-disk".parse().unwrap(),
+            description: String::from("too small"),
+        },
+        disk_source: params::DiskSource::Image { image_id: image.identity.id },
+        size: ByteCount::from(4096 * 500),
+    };
+
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &"/organizations/myorg/projects/myproj/disks",
+        )
+        .body(Some(&new_disk))
+        .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("unexpected success");
+}
+
+#[nexus_test]
+async fn test_make_disk_from_project_image_from_snapshot(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    DiskTest::new(&cptestctx).await;
+
+    create_organization(&client, "myorg").await;
+    create_project(client, "myorg", "myproj").await;
+
+    // Snapshot a disk, and make an image of the snapshot.
+    let base_disk = create_disk(client, "myorg", "myproj", "base-disk").await;
+    let snapshot: Snapshot = object_create(
+        client,
+        "/v1/snapshots?organization=myorg&project=myproj",
+        &params::SnapshotCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "base-snapshot".parse().unwrap(),
+                description: String::from("a snapshot of our own OS"),
+            },
+            disk: base_disk.identity.name.clone(),
+        },
+    )
+    .await;
+
+    let image: Image = object_create(
+        client,
+        "/organizations/myorg/projects/myproj/images",
+        &params::ImageCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "my-os".parse().unwrap(),
+                description: String::from("our own OS build"),
+            },
+            source: params::ImageSource::Snapshot { id: snapshot.identity.id },
+            block_size: params::BlockSize::try_from(512).unwrap(),
+        },
+    )
+    .await;
+    assert_eq!(image.url, None);
+    assert_eq!(image.size, snapshot.size);
+
+    // A disk can be made from the image.
+    let new_disk = params::DiskCreate {
+        identity: IdentityMetadataCreateParams {
+            name: "disk".parse().unwrap(),
+            description: String::from("booted from our own image"),
+        },
+        disk_source: params::DiskSource::Image { image_id: image.identity.id },
+        size: base_disk.size,
+    };
+    let disk: Disk = object_create(
+        client,
+        "/organizations/myorg/projects/myproj/disks",
+        &new_disk,
+    )
+    .await;
+    assert_eq!(disk.image_id, Some(image.identity.id));
+    assert_eq!(disk.size, base_disk.size);
+
+    // The image's block size must match the snapshot's.
+    let bad_image_create_params = params::ImageCreate {
+        identity: IdentityMetadataCreateParams {
+            name: "bad-block-size".parse().unwrap(),
+            description: String::from("mismatched block size"),
+        },
+        source: params::ImageSource::Snapshot { id: snapshot.identity.id },
+        block_size: params::BlockSize::try_from(4096).unwrap(),
+    };
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &"/organizations/myorg/projects/myproj/images",
+        )
+        .body(Some(&bad_image_create_params))
+        .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("unexpected success");
+}
@@ -0,0 +1,277 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests live migration of instances between sleds
+
+use super::instances::instance_simulate;
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use dropshot::ResultsPage;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_interface::NexusServer;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::create_instance;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::populate_ip_pool;
+use nexus_test_utils::start_sled_agent;
+use nexus_test_utils::SLED_AGENT_UUID;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::Instance;
+use omicron_common::api::external::InstanceState;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::views::Migration;
+use omicron_nexus::external_api::views::MigrationPhase;
+use omicron_nexus::external_api::views::MigrationState;
+use omicron_sled_agent::sim;
+use std::str::FromStr;
+use uuid::Uuid;
+
+type ControlPlaneTestContext =
+    nexus_test_utils::ControlPlaneTestContext<omicron_nexus::Server>;
+
+const ORGANIZATION_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "migration-project";
+const PROJECT_SELECTOR: &str =
+    "organization=test-org&project=migration-project";
+const INSTANCE_NAME: &str = "migrant";
+
+fn get_instance_url() -> String {
+    format!("/v1/instances/{}?{}", INSTANCE_NAME, PROJECT_SELECTOR)
+}
+
+fn get_migrate_url() -> String {
+    format!("/v1/instances/{}/migrate?{}", INSTANCE_NAME, PROJECT_SELECTOR)
+}
+
+fn get_migrations_url() -> String {
+    format!("/v1/instances/{}/migrations?{}", INSTANCE_NAME, PROJECT_SELECTOR)
+}
+
+fn get_migration_cancel_url(migration_id: Uuid) -> String {
+    format!(
+        "/v1/instances/{}/migrations/{}/cancel?{}",
+        INSTANCE_NAME, migration_id, PROJECT_SELECTOR
+    )
+}
+
+/// Creates a running instance on the default sled agent, along with a second
+/// sled agent it can be migrated to.
+async fn setup(
+    cptestctx: &ControlPlaneTestContext,
+) -> (Instance, Uuid, sim::Server) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx().nexus;
+    populate_ip_pool(&client, "default", None).await;
+    create_organization(&client, ORGANIZATION_NAME).await;
+    create_project(&client, ORGANIZATION_NAME, PROJECT_NAME).await;
+
+    let instance =
+        create_instance(client, ORGANIZATION_NAME, PROJECT_NAME, INSTANCE_NAME)
+            .await;
+    instance_simulate(nexus, &instance.identity.id).await;
+
+    let sa_id = Uuid::new_v4();
+    let log = cptestctx.logctx.log.new(o!( "sled_id" => sa_id.to_string() ));
+    let addr = cptestctx.server.get_http_server_internal_address().await;
+    let sa = start_sled_agent(log, addr, sa_id).await.unwrap();
+    (instance, sa_id, sa)
+}
+
+async fn instance_get(client: &ClientTestContext) -> Instance {
+    NexusRequest::object_get(client, &get_instance_url())
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body()
+        .unwrap()
+}
+
+async fn instance_migrate(
+    client: &ClientTestContext,
+    dst_sled_id: Option<Uuid>,
+    status: StatusCode,
+) -> dropshot::test_util::TestResponse {
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &get_migrate_url())
+            .body(Some(&params::InstanceMigrate { dst_sled_id }))
+            .expect_status(Some(status)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+}
+
+async fn migrations_list(client: &ClientTestContext) -> Vec<Migration> {
+    NexusRequest::object_get(client, &get_migrations_url())
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body::<ResultsPage<Migration>>()
+        .unwrap()
+        .items
+}
+
+async fn migration_cancel(
+    client: &ClientTestContext,
+    migration_id: Uuid,
+    status: StatusCode,
+) -> dropshot::test_util::TestResponse {
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &get_migration_cancel_url(migration_id),
+        )
+        .expect_status(Some(status)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+}
+
+#[nexus_test]
+async fn test_instance_migrate_chooses_sled(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx().nexus;
+    let (instance, dst_sled_id, sa) = setup(cptestctx).await;
+    let instance_id = instance.identity.id;
+    let src_sled_id = Uuid::from_str(SLED_AGENT_UUID).unwrap();
+
+    // There's only one other sled, so that's where the instance goes.
+    let migrated: Instance = instance_migrate(client, None, StatusCode::OK)
+        .await
+        .parsed_body()
+        .unwrap();
+    assert_eq!(migrated.runtime.run_state, InstanceState::Migrating);
+
+    let migrations = migrations_list(client).await;
+    assert_eq!(migrations.len(), 1);
+    let migration = &migrations[0];
+    assert_eq!(migration.instance_id, instance_id);
+    assert_eq!(migration.state, MigrationState::InProgress);
+    assert_eq!(migration.phase, MigrationPhase::Transferring);
+    assert_eq!(migration.source_sled_id, src_sled_id);
+    assert_eq!(migration.target_sled_id, dst_sled_id);
+    assert!(migration.time_finished.is_none());
+
+    // Once the target is running, the migration is over.
+    instance_simulate(nexus, &instance_id).await;
+    let instance = instance_get(client).await;
+    assert_eq!(instance.runtime.run_state, InstanceState::Running);
+
+    let fetched: Migration = NexusRequest::object_get(
+        client,
+        &format!(
+            "/v1/instances/{}/migrations/{}?{}",
+            INSTANCE_NAME, migration.id, PROJECT_SELECTOR
+        ),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(fetched.id, migration.id);
+    assert_eq!(fetched.state, MigrationState::Completed);
+    assert_eq!(fetched.phase, MigrationPhase::TargetRunning);
+    assert!(fetched.time_finished.is_some());
+
+    // A finished migration can't be cancelled.
+    let error: HttpErrorResponseBody =
+        migration_cancel(client, migration.id, StatusCode::BAD_REQUEST)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(error.message, "cannot cancel migration in state \"completed\"");
+
+    // Migrating the instance to the sled it's already on fails up front.
+    let error: HttpErrorResponseBody =
+        instance_migrate(client, Some(dst_sled_id), StatusCode::BAD_REQUEST)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(
+        error.message,
+        "instance is already running on the requested sled"
+    );
+    assert_eq!(migrations_list(client).await.len(), 1);
+
+    sa.http_server.close().await.unwrap();
+}
+
+#[nexus_test]
+async fn test_instance_migration_cancel(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let (_instance, dst_sled_id, sa) = setup(cptestctx).await;
+
+    instance_migrate(client, Some(dst_sled_id), StatusCode::OK).await;
+    let migration = migrations_list(client).await.remove(0);
+    assert_eq!(migration.state, MigrationState::InProgress);
+    assert_eq!(migration.phase, MigrationPhase::Transferring);
+
+    // Cancelling the migration leaves the instance running where it was.
+    let cancelled: Migration =
+        migration_cancel(client, migration.id, StatusCode::OK)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(cancelled.state, MigrationState::Cancelled);
+    assert_eq!(cancelled.phase, MigrationPhase::Transferring);
+    assert!(cancelled.time_finished.is_some());
+
+    let instance = instance_get(client).await;
+    assert_eq!(instance.runtime.run_state, InstanceState::Running);
+
+    // The migration stays cancelled.
+    let error: HttpErrorResponseBody =
+        migration_cancel(client, migration.id, StatusCode::BAD_REQUEST)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(error.message, "cannot cancel migration in state \"cancelled\"");
+    assert_eq!(
+        migrations_list(client).await[0].state,
+        MigrationState::Cancelled
+    );
+
+    sa.http_server.close().await.unwrap();
+}
+
+#[nexus_test]
+async fn test_instance_migration_failure(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let (_instance, dst_sled_id, sa) = setup(cptestctx).await;
+
+    // The destination sled is known to Nexus, but its sled agent is gone, so
+    // the migration fails after the source is ready, and the instance keeps
+    // running on the source.
+    sa.http_server.close().await.unwrap();
+    instance_migrate(
+        client,
+        Some(dst_sled_id),
+        StatusCode::INTERNAL_SERVER_ERROR,
+    )
+    .await;
+
+    let instance = instance_get(client).await;
+    assert_eq!(instance.runtime.run_state, InstanceState::Running);
+
+    let migrations = migrations_list(client).await;
+    assert_eq!(migrations.len(), 1);
+    assert_eq!(migrations[0].state, MigrationState::Failed);
+    assert_eq!(migrations[0].phase, MigrationPhase::SourceReady);
+    assert_eq!(migrations[0].target_sled_id, dst_sled_id);
+    assert!(migrations[0].time_finished.is_some());
+}
@@ -242,6 +242,7 @@ async fn test_instances_create_reboot_halt(
                     params::InstanceNetworkInterfaceAttachment::Default,
                 external_ips: vec![],
                 disks: vec![],
+                affinity_groups: Vec::new(),
                 start: true,
             }))
             .expect_status(Some(StatusCode::BAD_REQUEST)),
@@ -698,6 +699,119 @@ async fn test_instance_metrics(cptestctx: &ControlPlaneTestContext) {
     }
 }
 
+#[nexus_test]
+async fn test_instance_update(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let apictx = &cptestctx.server.apictx();
+    let nexus = &apictx.nexus;
+    let datastore = nexus.datastore();
+    let opctx =
+        OpContext::for_tests(cptestctx.logctx.log.new(o!()), datastore.clone());
+    let project_id = create_org_and_project(&client).await;
+
+    // Create an instance, with 4 CPUs and 1 GiB of memory.
+    let instance_name = "just-rainsticks";
+    let instance_url = get_instance_url(instance_name);
+    let instance =
+        create_instance(client, ORGANIZATION_NAME, PROJECT_NAME, instance_name)
+            .await;
+    let instance_id = instance.identity.id;
+    instance_simulate(nexus, &instance_id).await;
+
+    // Running instances can't be resized.
+    let update = params::InstanceUpdate {
+        ncpus: InstanceCpuCount(8),
+        memory: ByteCount::from_gibibytes_u32(4),
+    };
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::PUT,
+        &instance_url,
+        &update,
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "instance must be stopped to be resized (currently running)"
+    );
+
+    // Stop it, after which it can be.
+    instance_post(&client, instance_name, InstanceOp::Stop).await;
+    instance_simulate(nexus, &instance_id).await;
+    let instance: Instance =
+        NexusRequest::object_put(client, &instance_url, Some(&update))
+            .authn_as(AuthnMode::PrivilegedUser)
+            .execute()
+            .await
+            .unwrap()
+            .parsed_body()
+            .unwrap();
+    assert_eq!(instance.ncpus.0, 8);
+    assert_eq!(instance.memory, ByteCount::from_gibibytes_u32(4));
+    assert_eq!(instance.runtime.run_state, InstanceState::Stopped);
+    let instance = instance_get(&client, &instance_url).await;
+    assert_eq!(instance.ncpus.0, 8);
+    assert_eq!(instance.memory, ByteCount::from_gibibytes_u32(4));
+
+    // The project's provisioned resources reflect the new size.
+    let virtual_provisioning_collection = datastore
+        .virtual_provisioning_collection_get(&opctx, project_id)
+        .await
+        .unwrap();
+    assert_eq!(virtual_provisioning_collection.cpus_provisioned, 8);
+    assert_eq!(
+        virtual_provisioning_collection.ram_provisioned.0,
+        ByteCount::from_gibibytes_u32(4),
+    );
+
+    // Sizes outside of the supported limits are rejected.
+    for (ncpus, memory) in [
+        (0, ByteCount::from_gibibytes_u32(4)),
+        (u16::MAX, ByteCount::from_gibibytes_u32(4)),
+        (8, ByteCount::from_mebibytes_u32(1536)),
+        (8, ByteCount::from_gibibytes_u32(4096)),
+    ] {
+        NexusRequest::expect_failure_with_body(
+            client,
+            StatusCode::BAD_REQUEST,
+            Method::PUT,
+            &instance_url,
+            &params::InstanceUpdate { ncpus: InstanceCpuCount(ncpus), memory },
+        )
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    }
+
+    // The instance can be started with its new size, and its resources are
+    // returned on deletion.
+    instance_post(&client, instance_name, InstanceOp::Start).await;
+    instance_simulate(nexus, &instance_id).await;
+    let instance = instance_get(&client, &instance_url).await;
+    assert_eq!(instance.runtime.run_state, InstanceState::Running);
+    assert_eq!(instance.ncpus.0, 8);
+    instance_post(&client, instance_name, InstanceOp::Stop).await;
+    instance_simulate(nexus, &instance_id).await;
+    NexusRequest::object_delete(client, &instance_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    let virtual_provisioning_collection = datastore
+        .virtual_provisioning_collection_get(&opctx, project_id)
+        .await
+        .unwrap();
+    assert_eq!(virtual_provisioning_collection.cpus_provisioned, 0);
+    assert_eq!(virtual_provisioning_collection.ram_provisioned.to_bytes(), 0);
+}
+
 #[nexus_test]
 async fn test_instances_create_stopped_start(
     cptestctx: &ControlPlaneTestContext,
@@ -730,6 +844,7 @@ async fn test_instances_create_stopped_start(
                 params::InstanceNetworkInterfaceAttachment::Default,
             external_ips: vec![],
             disks: vec![],
+            affinity_groups: Vec::new(),
             start: false,
         },
     )
@@ -912,6 +1027,7 @@ async fn test_instance_create_saga_removes_instance_database_record(
         network_interfaces: interface_params.clone(),
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: Vec::new(),
         start: true,
     };
     let response =
@@ -936,6 +1052,7 @@ async fn test_instance_create_saga_removes_instance_database_record(
         network_interfaces: interface_params,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: Vec::new(),
         start: true,
     };
     let _ =
@@ -1022,6 +1139,7 @@ async fn test_instance_with_single_explicit_ip_address(
         network_interfaces: interface_params,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: Vec::new(),
         start: true,
     };
     let response =
@@ -1139,6 +1257,7 @@ async fn test_instance_with_new_custom_network_interfaces(
         network_interfaces: interface_params,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: Vec::new(),
         start: true,
     };
     let response =
@@ -1254,6 +1373,7 @@ async fn test_instance_create_delete_network_interface(
         network_interfaces: params::InstanceNetworkInterfaceAttachment::None,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: Vec::new(),
         start: true,
     };
     let response =
@@ -1496,6 +1616,7 @@ async fn test_instance_update_network_interfaces(
         network_interfaces: params::InstanceNetworkInterfaceAttachment::None,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: Vec::new(),
         start: true,
     };
     let response =
@@ -1897,6 +2018,7 @@ async fn test_instance_with_multiple_nics_unwinds_completely(
         network_interfaces: interface_params,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: Vec::new(),
         start: true,
     };
     let builder =
@@ -1974,6 +2096,7 @@ async fn test_attach_one_disk_to_instance(cptestctx: &ControlPlaneTestContext) {
                 name: Name::try_from(String::from("probablydata")).unwrap(),
             },
         )],
+        affinity_groups: Vec::new(),
         start: true,
     };
 
@@ -2064,6 +2187,7 @@ async fn test_instance_fails_to_boot_with_disk(
                 name: Name::try_from(String::from("probablydata")).unwrap(),
             },
         )],
+        affinity_groups: Vec::new(),
         start: true,
     };
 
@@ -2149,6 +2273,7 @@ async fn test_instance_create_attach_disks(
                 },
             ),
         ],
+        affinity_groups: Vec::new(),
         start: true,
     };
 
@@ -2270,6 +2395,7 @@ async fn test_instance_create_attach_disks_undo(
                 params::InstanceDiskAttach { name: faulted_disk.identity.name },
             ),
         ],
+        affinity_groups: Vec::new(),
         start: true,
     };
 
@@ -2368,6 +2494,7 @@ async fn test_attach_eight_disks_to_instance(
                 )
             })
             .collect(),
+        affinity_groups: Vec::new(),
         start: true,
     };
 
@@ -2474,6 +2601,7 @@ async fn test_cannot_attach_nine_disks_to_instance(
                 )
             })
             .collect(),
+        affinity_groups: Vec::new(),
         start: true,
     };
 
@@ -2596,6 +2724,7 @@ async fn test_cannot_attach_faulted_disks(cptestctx: &ControlPlaneTestContext) {
                 )
             })
             .collect(),
+        affinity_groups: Vec::new(),
         start: true,
     };
 
@@ -2705,6 +2834,7 @@ async fn test_disks_detached_when_instance_destroyed(
                 )
             })
             .collect(),
+        affinity_groups: Vec::new(),
         start: true,
     };
 
@@ -2808,6 +2938,7 @@ async fn test_instances_memory_rejected_less_than_min_memory_size(
         network_interfaces: params::InstanceNetworkInterfaceAttachment::Default,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: Vec::new(),
         start: true,
     };
 
@@ -2858,6 +2989,7 @@ async fn test_instances_memory_not_divisible_by_min_memory_size(
         network_interfaces: params::InstanceNetworkInterfaceAttachment::Default,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: Vec::new(),
         start: true,
     };
 
@@ -3024,6 +3156,7 @@ async fn test_instance_ephemeral_ip_from_correct_pool(
             ),
         }],
         disks: vec![],
+        affinity_groups: Vec::new(),
         start: true,
     };
     let response =
@@ -3134,6 +3267,7 @@ async fn test_instance_create_in_silo(cptestctx: &ControlPlaneTestContext) {
             pool_name: Some(Name::try_from(String::from("default")).unwrap()),
         }],
         disks: vec![],
+        affinity_groups: Vec::new(),
         start: true,
     };
     let url_instances = format!(
@@ -3,6 +3,9 @@
 //! See the driver in the parent directory for how and why this is structured
 //! the way it is.
 
+mod affinity_groups;
+mod alerts;
+mod audit_log;
 mod authn_http;
 mod authz;
 mod basic;
@@ -11,7 +14,10 @@ mod commands;
 mod console_api;
 mod device_auth;
 mod disks;
+mod external_dns;
+mod floating_ips;
 mod images;
+mod instance_migrations;
 mod instances;
 mod ip_pools;
 mod metrics;
@@ -19,6 +25,7 @@ mod organizations;
 mod oximeter;
 mod password_login;
 mod projects;
+mod quotas;
 mod rack;
 mod role_assignments;
 mod roles_builtin;
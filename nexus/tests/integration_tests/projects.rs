@@ -19,6 +19,7 @@ use omicron_common::api::external::IdentityMetadataCreateParams;
 use omicron_common::api::external::Instance;
 use omicron_common::api::external::InstanceCpuCount;
 use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::shared;
 use omicron_nexus::external_api::views;
 use omicron_nexus::external_api::views::Project;
 
@@ -192,6 +193,7 @@ async fn test_project_deletion_with_instance(
                 params::InstanceNetworkInterfaceAttachment::None,
             external_ips: vec![],
             disks: vec![],
+            affinity_groups: Vec::new(),
             start: false,
         },
     )
@@ -388,3 +390,98 @@ async fn test_project_deletion_with_vpc(cptestctx: &ControlPlaneTestContext) {
         .unwrap();
     delete_project(&url, &client).await;
 }
+
+#[nexus_test]
+async fn test_project_deletion_with_affinity_group(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+
+    let org_name = "test-org";
+    create_organization(&client, &org_name).await;
+
+    // Create a project that we'll use for testing.
+    let name = "springfield-squidport";
+    let url = format!("/organizations/{}/projects/{}", org_name, name);
+
+    create_project(&client, &org_name, &name).await;
+    delete_project_default_subnet(&url, &client).await;
+    delete_project_default_vpc(&url, &client).await;
+
+    let group_url = format!(
+        "/v1/affinity-groups/my-group?organization={org_name}&project={name}"
+    );
+    let _: views::AffinityGroup = object_create(
+        client,
+        &format!("/v1/affinity-groups?organization={org_name}&project={name}"),
+        &params::AffinityGroupCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "my-group".parse().unwrap(),
+                description: "description".to_string(),
+            },
+            kind: shared::AffinityGroupKind::AntiAffinity,
+            policy: shared::AffinityPolicy::Hard,
+        },
+    )
+    .await;
+
+    assert_eq!(
+        "project to be deleted contains an affinity group: my-group",
+        delete_project_expect_fail(&url, &client).await,
+    );
+
+    NexusRequest::object_delete(client, &group_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    delete_project(&url, &client).await;
+}
+
+#[nexus_test]
+async fn test_project_deletion_with_floating_ip(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+
+    let org_name = "test-org";
+    populate_ip_pool(&client, "default", None).await;
+    create_organization(&client, &org_name).await;
+
+    // Create a project that we'll use for testing.
+    let name = "springfield-squidport";
+    let url = format!("/organizations/{}/projects/{}", org_name, name);
+
+    create_project(&client, &org_name, &name).await;
+    delete_project_default_subnet(&url, &client).await;
+    delete_project_default_vpc(&url, &client).await;
+
+    let fip_url = format!(
+        "/v1/floating-ips/my-fip?organization={org_name}&project={name}"
+    );
+    let _: views::FloatingIp = object_create(
+        client,
+        &format!("/v1/floating-ips?organization={org_name}&project={name}"),
+        &params::FloatingIpCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "my-fip".parse().unwrap(),
+                description: "description".to_string(),
+            },
+            address: None,
+            pool: None,
+        },
+    )
+    .await;
+
+    assert_eq!(
+        "project to be deleted contains a floating ip: my-fip",
+        delete_project_expect_fail(&url, &client).await,
+    );
+
+    NexusRequest::object_delete(client, &fip_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    delete_project(&url, &client).await;
+}
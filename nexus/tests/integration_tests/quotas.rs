@@ -0,0 +1,266 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests resource quotas on Silos, Organizations, and Projects
+
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::create_disk;
+use nexus_test_utils::resource_helpers::create_instance;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::object_put;
+use nexus_test_utils::resource_helpers::populate_ip_pool;
+use nexus_test_utils::resource_helpers::DiskTest;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::InstanceCpuCount;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::views::ResourceQuotas;
+
+type ControlPlaneTestContext =
+    nexus_test_utils::ControlPlaneTestContext<omicron_nexus::Server>;
+
+const ORGANIZATION_NAME: &str = "springfield-squidport";
+const PROJECT_NAME: &str = "carcosa";
+
+fn get_organization_quotas_url() -> String {
+    format!("/v1/organizations/{}/quotas", ORGANIZATION_NAME)
+}
+
+fn get_project_quotas_url() -> String {
+    format!(
+        "/v1/projects/{}/quotas?organization={}",
+        PROJECT_NAME, ORGANIZATION_NAME
+    )
+}
+
+async fn create_org_and_project(client: &ClientTestContext) {
+    populate_ip_pool(&client, "default", None).await;
+    create_organization(&client, ORGANIZATION_NAME).await;
+    create_project(client, ORGANIZATION_NAME, PROJECT_NAME).await;
+}
+
+async fn quotas_get(client: &ClientTestContext, url: &str) -> ResourceQuotas {
+    NexusRequest::object_get(client, url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body()
+        .unwrap()
+}
+
+async fn expect_insufficient_capacity(
+    client: &ClientTestContext,
+    url: &str,
+    body: &impl serde::Serialize,
+) -> HttpErrorResponseBody {
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, url)
+            .body(Some(body))
+            .expect_status(Some(StatusCode::INSUFFICIENT_STORAGE)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
+
+#[nexus_test]
+async fn test_quotas_view_and_update(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_org_and_project(&client).await;
+
+    // Nothing is limited until a quota is set.
+    let unlimited = ResourceQuotas { cpus: None, memory: None, storage: None };
+    let url = get_project_quotas_url();
+    assert_eq!(quotas_get(client, &url).await, unlimited);
+    assert_eq!(
+        quotas_get(client, &get_organization_quotas_url()).await,
+        unlimited
+    );
+
+    let quotas: ResourceQuotas = object_put(
+        client,
+        &url,
+        &params::ResourceQuotasUpdate {
+            cpus: Some(8),
+            memory: Some(ByteCount::from_gibibytes_u32(16)),
+            storage: None,
+        },
+    )
+    .await;
+    let expected = ResourceQuotas {
+        cpus: Some(8),
+        memory: Some(ByteCount::from_gibibytes_u32(16)),
+        storage: None,
+    };
+    assert_eq!(quotas, expected);
+    assert_eq!(quotas_get(client, &url).await, expected);
+
+    // Quotas left out of an update are removed.
+    let quotas: ResourceQuotas = object_put(
+        client,
+        &url,
+        &params::ResourceQuotasUpdate {
+            cpus: None,
+            memory: None,
+            storage: None,
+        },
+    )
+    .await;
+    assert_eq!(quotas, unlimited);
+
+    // Negative quotas are rejected.
+    let error: HttpErrorResponseBody = NexusRequest::new(
+        RequestBuilder::new(client, Method::PUT, &url)
+            .body(Some(&params::ResourceQuotasUpdate {
+                cpus: Some(-1),
+                memory: None,
+                storage: None,
+            }))
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "unsupported value for \"cpus\": quota must not be negative"
+    );
+}
+
+#[nexus_test]
+async fn test_instance_create_exceeding_quota(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    create_org_and_project(&client).await;
+
+    // Each instance created by `create_instance` has 4 CPUs and 1 GiB of
+    // memory, so the project has room for exactly one.
+    let _: ResourceQuotas = object_put(
+        client,
+        &get_project_quotas_url(),
+        &params::ResourceQuotasUpdate {
+            cpus: Some(6),
+            memory: None,
+            storage: None,
+        },
+    )
+    .await;
+    create_instance(client, ORGANIZATION_NAME, PROJECT_NAME, "inst1").await;
+
+    let instance_params = params::InstanceCreate {
+        identity: IdentityMetadataCreateParams {
+            name: "inst2".parse().unwrap(),
+            description: String::from("over quota"),
+        },
+        ncpus: InstanceCpuCount(4),
+        memory: ByteCount::from_gibibytes_u32(1),
+        hostname: String::from("inst2"),
+        user_data: vec![],
+        network_interfaces: params::InstanceNetworkInterfaceAttachment::None,
+        external_ips: vec![],
+        disks: vec![],
+        affinity_groups: Vec::new(),
+        start: false,
+    };
+    let instances_url = format!(
+        "/v1/instances?organization={}&project={}",
+        ORGANIZATION_NAME, PROJECT_NAME
+    );
+    let error =
+        expect_insufficient_capacity(client, &instances_url, &instance_params)
+            .await;
+    assert_eq!(
+        error.message,
+        "Not enough CPU quota remaining to provision instances"
+    );
+
+    // Quotas on the Organization apply to every Project within it.
+    let _: ResourceQuotas = object_put(
+        client,
+        &get_project_quotas_url(),
+        &params::ResourceQuotasUpdate {
+            cpus: None,
+            memory: None,
+            storage: None,
+        },
+    )
+    .await;
+    let _: ResourceQuotas = object_put(
+        client,
+        &get_organization_quotas_url(),
+        &params::ResourceQuotasUpdate {
+            cpus: None,
+            memory: Some(ByteCount::from_gibibytes_u32(1)),
+            storage: None,
+        },
+    )
+    .await;
+    let error =
+        expect_insufficient_capacity(client, &instances_url, &instance_params)
+            .await;
+    assert_eq!(
+        error.message,
+        "Not enough memory quota remaining to provision instances"
+    );
+}
+
+#[nexus_test]
+async fn test_disk_create_exceeding_quota(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    DiskTest::new(&cptestctx).await;
+    create_org_and_project(&client).await;
+
+    // `create_disk` creates 1 GiB disks.
+    let _: ResourceQuotas = object_put(
+        client,
+        &get_project_quotas_url(),
+        &params::ResourceQuotasUpdate {
+            cpus: None,
+            memory: None,
+            storage: Some(ByteCount::from_gibibytes_u32(1)),
+        },
+    )
+    .await;
+    create_disk(client, ORGANIZATION_NAME, PROJECT_NAME, "disk1").await;
+
+    let disks_url = format!(
+        "/v1/disks?organization={}&project={}",
+        ORGANIZATION_NAME, PROJECT_NAME
+    );
+    let error = expect_insufficient_capacity(
+        client,
+        &disks_url,
+        &params::DiskCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "disk2".parse().unwrap(),
+                description: String::from("over quota"),
+            },
+            disk_source: params::DiskSource::Blank {
+                block_size: params::BlockSize::try_from(512).unwrap(),
+            },
+            size: ByteCount::from_gibibytes_u32(1),
+        },
+    )
+    .await;
+    assert_eq!(
+        error.message,
+        "Not enough storage quota remaining to provision disks"
+    );
+}
@@ -4,16 +4,26 @@
 
 //! Tests for APIs against sled-based endpoints.
 
+use super::instances::instance_simulate;
 use dropshot::test_util::ClientTestContext;
+use http::method::Method;
+use http::StatusCode;
 use nexus_test_interface::NexusServer;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::create_instance;
+use nexus_test_utils::resource_helpers::create_organization;
 use nexus_test_utils::resource_helpers::create_physical_disk;
+use nexus_test_utils::resource_helpers::create_project;
 use nexus_test_utils::resource_helpers::delete_physical_disk;
 use nexus_test_utils::resource_helpers::objects_list_page_authz;
+use nexus_test_utils::resource_helpers::populate_ip_pool;
 use nexus_test_utils::start_sled_agent;
 use nexus_test_utils::SLED_AGENT_UUID;
 use nexus_test_utils_macros::nexus_test;
 use omicron_nexus::external_api::views::{
-    PhysicalDisk, PhysicalDiskType, Sled,
+    PhysicalDisk, PhysicalDiskType, Sled, SledDrainStatus, SledProvisionState,
 };
 use omicron_nexus::internal_api::params as internal_params;
 use std::str::FromStr;
@@ -105,3 +115,104 @@ async fn test_physical_disk_create_list_delete(
     delete_physical_disk(&internal_client, "v", "s", "m", sled_id).await;
     assert!(physical_disks_list(&external_client, &disks_url).await.is_empty());
 }
+
+async fn sled_post(
+    client: &ClientTestContext,
+    sled_id: Uuid,
+    action: &str,
+) -> dropshot::test_util::TestResponse {
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &format!("/v1/system/hardware/sleds/{}/{}", sled_id, action),
+        )
+        .expect_status(Some(StatusCode::OK)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+}
+
+async fn sled_drain_status(
+    client: &ClientTestContext,
+    sled_id: Uuid,
+) -> SledDrainStatus {
+    NexusRequest::object_get(
+        client,
+        &format!("/v1/system/hardware/sleds/{}/drain", sled_id),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
+
+#[nexus_test]
+async fn test_sled_cordon_and_drain(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx().nexus;
+    let sled_id = Uuid::from_str(&SLED_AGENT_UUID).unwrap();
+
+    // Start a second sled, and cordon it so that the instance we create has
+    // to land on the first one.
+    let other_sled_id = Uuid::new_v4();
+    let log =
+        cptestctx.logctx.log.new(o!( "sled_id" => other_sled_id.to_string() ));
+    let addr = cptestctx.server.get_http_server_internal_address().await;
+    let sa = start_sled_agent(log, addr, other_sled_id).await.unwrap();
+    let sled: Sled =
+        sled_post(client, other_sled_id, "cordon").await.parsed_body().unwrap();
+    assert_eq!(sled.provision_state, SledProvisionState::Cordoned);
+
+    populate_ip_pool(&client, "default", None).await;
+    create_organization(&client, "test-org").await;
+    create_project(&client, "test-org", "test-project").await;
+    let instance =
+        create_instance(client, "test-org", "test-project", "inst").await;
+    let instance_id = instance.identity.id;
+    instance_simulate(nexus, &instance_id).await;
+
+    let status = sled_drain_status(client, sled_id).await;
+    assert_eq!(status.provision_state, SledProvisionState::Active);
+    assert_eq!(status.instances_remaining, 1);
+    assert_eq!(status.instances_migrating, 0);
+    assert_eq!(
+        sled_drain_status(client, other_sled_id).await.instances_remaining,
+        0
+    );
+
+    // Return the second sled to service and drain the first.  The instance
+    // starts migrating to the second sled.
+    let sled: Sled = sled_post(client, other_sled_id, "uncordon")
+        .await
+        .parsed_body()
+        .unwrap();
+    assert_eq!(sled.provision_state, SledProvisionState::Active);
+    let status: SledDrainStatus =
+        sled_post(client, sled_id, "drain").await.parsed_body().unwrap();
+    assert_eq!(status.provision_state, SledProvisionState::Draining);
+    assert_eq!(status.instances_remaining, 1);
+    assert_eq!(status.instances_migrating, 1);
+
+    // Once the migration finishes, the sled is empty.
+    instance_simulate(nexus, &instance_id).await;
+    let status = sled_drain_status(client, sled_id).await;
+    assert_eq!(status.provision_state, SledProvisionState::Draining);
+    assert_eq!(status.instances_remaining, 0);
+    assert_eq!(status.instances_migrating, 0);
+    assert_eq!(
+        sled_drain_status(client, other_sled_id).await.instances_remaining,
+        1
+    );
+
+    // The drained sled can be returned to service.
+    let sled: Sled =
+        sled_post(client, sled_id, "uncordon").await.parsed_body().unwrap();
+    assert_eq!(sled.provision_state, SledProvisionState::Active);
+
+    sa.http_server.close().await.unwrap();
+}
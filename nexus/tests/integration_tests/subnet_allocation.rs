@@ -61,6 +61,7 @@ async fn create_instance_expect_failure(
         network_interfaces,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: Vec::new(),
         start: true,
     };
 
@@ -272,6 +272,24 @@ lazy_static! {
             body: serde_json::to_value(&*DEMO_SNAPSHOT_CREATE).unwrap(),
             id_routes: vec!["/by-id/snapshots/{id}"],
         },
+        // Create a Floating IP in the Project
+        SetupReq::Post {
+            url: &DEMO_PROJECT_URL_FLOATING_IPS,
+            body: serde_json::to_value(&*DEMO_FLOATING_IP_CREATE).unwrap(),
+            id_routes: vec!["/v1/floating-ips/{id}"],
+        },
+        // Create an affinity group in the Project
+        SetupReq::Post {
+            url: &DEMO_PROJECT_URL_AFFINITY_GROUPS,
+            body: serde_json::to_value(&*DEMO_AFFINITY_GROUP_CREATE).unwrap(),
+            id_routes: vec!["/v1/affinity-groups/{id}"],
+        },
+        // Create an Image in the Project
+        SetupReq::Post {
+            url: &DEMO_PROJECT_URL_IMAGES,
+            body: serde_json::to_value(&*DEMO_IMAGE_CREATE).unwrap(),
+            id_routes: vec!["/by-id/images/{id}"],
+        },
         // Create a GlobalImage
         SetupReq::Post {
             url: "/system/images",
@@ -296,6 +314,12 @@ lazy_static! {
             body: serde_json::to_value(&*DEMO_CERTIFICATE_CREATE).unwrap(),
             id_routes: vec![],
         },
+        // Create an alert rule
+        SetupReq::Post {
+            url: &DEMO_ALERT_RULES_URL,
+            body: serde_json::to_value(&*DEMO_ALERT_RULE_CREATE).unwrap(),
+            id_routes: vec![],
+        },
     ];
 }
 
@@ -17,7 +17,7 @@ use serde::{
     de::{self, Visitor},
     Deserialize, Deserializer, Serialize, Serializer,
 };
-use std::{net::IpAddr, str::FromStr};
+use std::{net::IpAddr, num::NonZeroU32, str::FromStr};
 use uuid::Uuid;
 
 // TODO-v1: Post migration rename `*Path` to `*Identifier`
@@ -36,6 +36,12 @@ pub struct InstancePath {
     pub instance: NameOrId,
 }
 
+#[derive(Deserialize, JsonSchema)]
+pub struct InstanceMigrationPath {
+    pub instance: NameOrId,
+    pub migration: Uuid,
+}
+
 #[derive(Deserialize, JsonSchema)]
 pub struct NetworkInterfacePath {
     pub interface: NameOrId,
@@ -71,6 +77,16 @@ pub struct SnapshotPath {
     pub snapshot: NameOrId,
 }
 
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct FloatingIpPath {
+    pub floating_ip: NameOrId,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AffinityGroupPath {
+    pub affinity_group: NameOrId,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct OrganizationSelector {
     pub organization: NameOrId,
@@ -154,6 +170,48 @@ impl SnapshotSelector {
     }
 }
 
+#[derive(Deserialize, JsonSchema)]
+pub struct FloatingIpSelector {
+    #[serde(flatten)]
+    pub project_selector: Option<ProjectSelector>,
+    pub floating_ip: NameOrId,
+}
+
+impl FloatingIpSelector {
+    pub fn new(
+        organization: Option<NameOrId>,
+        project: Option<NameOrId>,
+        floating_ip: NameOrId,
+    ) -> Self {
+        FloatingIpSelector {
+            project_selector: project
+                .map(|p| ProjectSelector::new(organization, p)),
+            floating_ip,
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct AffinityGroupSelector {
+    #[serde(flatten)]
+    pub project_selector: Option<ProjectSelector>,
+    pub affinity_group: NameOrId,
+}
+
+impl AffinityGroupSelector {
+    pub fn new(
+        organization: Option<NameOrId>,
+        project: Option<NameOrId>,
+        affinity_group: NameOrId,
+    ) -> Self {
+        AffinityGroupSelector {
+            project_selector: project
+                .map(|p| ProjectSelector::new(organization, p)),
+            affinity_group,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct InstanceSelector {
     #[serde(flatten)]
@@ -734,6 +792,21 @@ pub struct ProjectUpdate {
     pub identity: IdentityMetadataUpdateParams,
 }
 
+// RESOURCE QUOTAS
+
+/// Updateable quotas of a Silo, Organization, or Project
+///
+/// Each quota left unset is removed, making that resource unlimited.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ResourceQuotasUpdate {
+    /// The number of virtual CPUs which may be provisioned
+    pub cpus: Option<i64>,
+    /// The amount of memory which may be provisioned
+    pub memory: Option<ByteCount>,
+    /// The amount of disk and snapshot storage which may be provisioned
+    pub storage: Option<ByteCount>,
+}
+
 // NETWORK INTERFACES
 
 /// Create-time parameters for a
@@ -891,7 +964,32 @@ pub enum ExternalIpCreate {
     /// automatically-assigned from the provided IP Pool, or all available pools
     /// if not specified.
     Ephemeral { pool_name: Option<Name> },
-    // TODO: Add floating IPs: https://github.com/oxidecomputer/omicron/issues/1334
+    /// An existing Floating IP, in the same project as the instance, which is
+    /// attached to the instance when it is created.
+    Floating { floating_ip_name: Name },
+}
+
+/// Create-time parameters for a Floating IP
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct FloatingIpCreate {
+    #[serde(flatten)]
+    pub identity: IdentityMetadataCreateParams,
+
+    /// An IP address to reserve for use as a Floating IP. If not provided, an
+    /// address is automatically-assigned from the IP Pool.
+    pub address: Option<IpAddr>,
+
+    /// The IP Pool from which to allocate the address. If not specified, the
+    /// default pool is used.
+    pub pool: Option<Name>,
+}
+
+/// Parameters for attaching a Floating IP to an instance
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct FloatingIpAttach {
+    /// Name or ID of the instance to which the Floating IP is attached. The
+    /// instance must be in the same project as the Floating IP.
+    pub instance: NameOrId,
 }
 
 /// Create-time parameters for an [`Instance`](omicron_common::api::external::Instance)
@@ -933,11 +1031,29 @@ pub struct InstanceCreate {
     #[serde(default)]
     pub disks: Vec<InstanceDiskAttachment>,
 
+    /// The affinity groups, in the same project, which this instance joins.
+    ///
+    /// The instance is placed on a sled in accordance with the groups it
+    /// joins. Membership lasts for the lifetime of the instance.
+    #[serde(default)]
+    pub affinity_groups: Vec<NameOrId>,
+
     /// Should this instance be started upon creation; true by default.
     #[serde(default = "bool_true")]
     pub start: bool,
 }
 
+/// Updateable properties of an [`Instance`](omicron_common::api::external::Instance)
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct InstanceUpdate {
+    /// The number of CPUs in the Instance
+    ///
+    /// Instances may only be resized while stopped.
+    pub ncpus: InstanceCpuCount,
+    /// The memory allocated to the Instance, in bytes
+    pub memory: ByteCount,
+}
+
 #[inline]
 fn bool_true() -> bool {
     true
@@ -1011,7 +1127,10 @@ impl JsonSchema for UserData {
 /// Migration parameters for an [`Instance`](omicron_common::api::external::Instance)
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct InstanceMigrate {
-    pub dst_sled_id: Uuid,
+    /// The sled to migrate the instance to.  If omitted, a sled is chosen
+    /// the same way as for a newly created instance.
+    #[serde(default)]
+    pub dst_sled_id: Option<Uuid>,
 }
 
 /// Forwarded to a sled agent to request the contents of an Instance's serial console.
@@ -1217,6 +1336,15 @@ pub struct DiskCreate {
     pub size: ByteCount,
 }
 
+/// Updateable properties of a [`Disk`](omicron_common::api::external::Disk)
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct DiskUpdate {
+    /// new total size of the Disk in bytes
+    ///
+    /// Disks may only grow, and only while detached from any instance.
+    pub size: ByteCount,
+}
+
 /// TODO-v1: Delete this
 /// Parameters for the [`Disk`](omicron_common::api::external::Disk) to be
 /// attached or detached to an instance
@@ -1307,6 +1435,55 @@ pub struct SnapshotCreate {
     pub disk: Name,
 }
 
+// AFFINITY GROUPS
+
+/// Create-time parameters for an [`AffinityGroup`](crate::external_api::views::AffinityGroup)
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AffinityGroupCreate {
+    #[serde(flatten)]
+    pub identity: IdentityMetadataCreateParams,
+
+    /// Whether members of the group are placed on the same sled or on
+    /// different sleds.
+    pub kind: shared::AffinityGroupKind,
+
+    /// Whether an instance which can't be placed according to the group isn't
+    /// placed at all, or is placed elsewhere.
+    pub policy: shared::AffinityPolicy,
+}
+
+// ALERTS
+
+/// Create-time parameters for an [`AlertRule`](crate::external_api::views::AlertRule)
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AlertRuleCreate {
+    #[serde(flatten)]
+    pub identity: IdentityMetadataCreateParams,
+
+    /// The name of the timeseries evaluated, e.g.,
+    /// "collection_target:ram_provisioned".
+    pub timeseries_name: String,
+
+    /// Filters on the fields of the timeseries, e.g., "id==<uuid>".  Only the
+    /// timeseries matching every filter are evaluated.
+    #[serde(default)]
+    pub criteria: Vec<String>,
+
+    /// How the matching timeseries are aggregated into the values compared
+    /// with the threshold.
+    pub aggregation: oximeter_db::query::Aggregation,
+
+    /// Whether the rule fires when the aggregated value is above or below the
+    /// threshold.
+    pub comparison: shared::AlertComparison,
+
+    pub threshold: f64,
+
+    /// How long, in seconds, the aggregated value must have been past the
+    /// threshold before the rule fires.
+    pub duration_secs: NonZeroU32,
+}
+
 // BUILT-IN USERS
 //
 // These cannot be created via the external API, but we use the same interfaces
@@ -1347,6 +1524,17 @@ pub struct ResourceMetrics {
     pub end_time: DateTime<Utc>,
 }
 
+// AUDIT LOG
+
+/// Query parameters for listing the audit log
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct AuditLogParams {
+    /// An inclusive start time of requests to list
+    pub start_time: Option<DateTime<Utc>>,
+    /// An exclusive end time of requests to list
+    pub end_time: Option<DateTime<Utc>>,
+}
+
 // SYSTEM UPDATE
 
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
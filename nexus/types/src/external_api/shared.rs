@@ -159,6 +159,58 @@ pub enum ServiceUsingCertificate {
     ExternalApi,
 }
 
+/// Whether the members of an affinity group are placed together or apart.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AffinityGroupKind {
+    /// Members of the group are placed on the same sled.
+    Affinity,
+
+    /// Members of the group are placed on different sleds.
+    AntiAffinity,
+}
+
+/// What happens when an instance can't be placed in accordance with an
+/// affinity group.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AffinityPolicy {
+    /// The instance isn't placed at all, and the request which would have
+    /// placed it fails.
+    Hard,
+
+    /// The group is honoured where possible, but the instance is placed
+    /// elsewhere rather than not at all.
+    Soft,
+}
+
+/// How an alert rule compares the value it evaluates with its threshold.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertComparison {
+    /// The rule fires when the value is greater than the threshold.
+    Above,
+
+    /// The rule fires when the value is less than the threshold.
+    Below,
+}
+
+impl AlertComparison {
+    /// Returns true if `value` is on the firing side of `threshold`.
+    pub fn crosses(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            AlertComparison::Above => value > threshold,
+            AlertComparison::Below => value < threshold,
+        }
+    }
+}
+
 /// An IP Range is a contiguous range of IP addresses, usually within an IP
 /// Pool.
 ///
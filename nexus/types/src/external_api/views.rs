@@ -5,7 +5,8 @@
 //! Views are response bodies, most of which are public lenses onto DB models.
 
 use crate::external_api::shared::{
-    self, IpKind, IpRange, ServiceUsingCertificate,
+    self, AffinityGroupKind, AffinityPolicy, IpKind, IpRange,
+    ServiceUsingCertificate,
 };
 use crate::identity::AssetIdentityMetadata;
 use api_identity::ObjectIdentity;
@@ -102,6 +103,24 @@ pub struct Project {
     pub organization_id: Uuid,
 }
 
+// RESOURCE QUOTAS
+
+/// Client view of the quotas of a Silo, Organization, or Project
+///
+/// Provisioning an instance, disk, or snapshot fails if it would exceed the
+/// quota of any collection containing it.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct ResourceQuotas {
+    /// The number of virtual CPUs which may be provisioned, or unlimited if
+    /// not set
+    pub cpus: Option<i64>,
+    /// The amount of memory which may be provisioned, or unlimited if not set
+    pub memory: Option<ByteCount>,
+    /// The amount of disk and snapshot storage which may be provisioned, or
+    /// unlimited if not set
+    pub storage: Option<ByteCount>,
+}
+
 // CERTIFICATES
 
 /// Client view of a [`Certificate`]
@@ -265,6 +284,77 @@ pub struct IpPoolRange {
     pub range: IpRange,
 }
 
+// INSTANCE MIGRATIONS
+
+/// The state of a live migration of an instance between sleds
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationState {
+    /// The destination Propolis server is being set up.
+    Pending,
+    /// The instance's state is being transferred to the destination.
+    InProgress,
+    /// The instance is running on the destination sled.
+    Completed,
+    /// The migration failed; the instance kept running on the source sled.
+    Failed,
+    /// The migration was cancelled; the instance kept running on the source
+    /// sled.
+    Cancelled,
+}
+
+/// How far a live migration has progressed, as reported by the sleds involved
+///
+/// Once a migration has finished, this is the last step it reached.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationPhase {
+    /// Neither sled has been told about the migration yet.
+    Preparing,
+    /// The source sled is ready to hand the instance over.
+    SourceReady,
+    /// The destination Propolis server is pulling the instance's state from
+    /// the source.
+    Transferring,
+    /// The instance is running on the destination sled.
+    TargetRunning,
+}
+
+/// Client view of a live migration of an instance between sleds
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Migration {
+    pub id: Uuid,
+    pub instance_id: Uuid,
+    pub state: MigrationState,
+    pub phase: MigrationPhase,
+    pub source_sled_id: Uuid,
+    pub target_sled_id: Uuid,
+    pub time_created: DateTime<Utc>,
+    pub time_modified: DateTime<Utc>,
+    /// The time at which the migration completed, failed, or was cancelled.
+    pub time_finished: Option<DateTime<Utc>>,
+}
+
+// AFFINITY GROUPS
+
+/// Client view of an affinity group, which constrains the sleds on which its
+/// member instances are placed relative to one another
+#[derive(ObjectIdentity, Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct AffinityGroup {
+    #[serde(flatten)]
+    pub identity: IdentityMetadata,
+    /// The project this affinity group exists within.
+    pub project_id: Uuid,
+    /// Whether members are placed together or apart.
+    pub kind: AffinityGroupKind,
+    /// What happens when a member can't be placed according to the group.
+    pub policy: AffinityPolicy,
+}
+
 // INSTANCE EXTERNAL IP ADDRESSES
 
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
@@ -274,6 +364,23 @@ pub struct ExternalIp {
     pub kind: IpKind,
 }
 
+// FLOATING IP ADDRESSES
+
+/// A Floating IP is a well-known IP address which can be attached
+/// and detached from instances.
+#[derive(ObjectIdentity, Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct FloatingIp {
+    #[serde(flatten)]
+    pub identity: IdentityMetadata,
+    /// The IP address held by this resource.
+    pub ip: IpAddr,
+    /// The project this resource exists within.
+    pub project_id: Uuid,
+    /// The ID of the instance that this Floating IP is attached to,
+    /// if it is presently in use.
+    pub instance_id: Option<Uuid>,
+}
+
 // RACKS
 
 /// Client view of an [`Rack`]
@@ -293,6 +400,22 @@ pub struct Baseboard {
     pub revision: i64,
 }
 
+/// Whether new resources may be placed on a sled
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SledProvisionState {
+    /// New instances and regions may be placed on the sled.
+    Active,
+    /// No new instances or regions are placed on the sled, but what's
+    /// already there keeps running.
+    Cordoned,
+    /// No new instances or regions are placed on the sled, and its instances
+    /// are being moved to other sleds.
+    Draining,
+}
+
 /// Client view of a [`Sled`]
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Sled {
@@ -301,6 +424,19 @@ pub struct Sled {
     pub service_address: SocketAddrV6,
     pub baseboard: Baseboard,
     pub rack_id: Uuid,
+    pub provision_state: SledProvisionState,
+}
+
+/// Progress of moving instances off of a sled
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct SledDrainStatus {
+    pub sled_id: Uuid,
+    pub provision_state: SledProvisionState,
+    /// The number of instances still on the sled, including those migrating
+    /// away from it.  The sled is empty once this reaches zero.
+    pub instances_remaining: u64,
+    /// The number of instances currently migrating away from the sled.
+    pub instances_migrating: u64,
 }
 
 // PHYSICAL DISKS
@@ -490,3 +626,99 @@ pub struct UpdateDeployment {
     pub version: SemverVersion,
     pub status: UpdateStatus,
 }
+
+// AUDIT LOG
+
+/// Who made a request recorded in the audit log
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuditLogActor {
+    /// One of the built-in users which the control plane uses internally
+    UserBuiltin { user_builtin_id: Uuid },
+    /// A user of a Silo
+    SiloUser { silo_user_id: Uuid, silo_id: Uuid },
+    /// The request was not authenticated
+    Unauthenticated,
+}
+
+/// A mutating request made to the API, and its outcome
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AuditLogEntry {
+    /// unique, immutable, system-controlled identifier for the entry
+    pub id: Uuid,
+    /// when the request was received
+    pub time_started: DateTime<Utc>,
+    /// identifier assigned to the request, which also appears in server logs
+    pub request_id: String,
+    /// the API operation which was requested
+    pub operation_id: String,
+    pub http_method: String,
+    pub request_uri: String,
+    pub actor: AuditLogActor,
+    /// when the request completed
+    ///
+    /// This is unset if the outcome of the request isn't known.
+    pub time_completed: Option<DateTime<Utc>>,
+    /// HTTP status code of the response to the request
+    pub http_status_code: Option<u16>,
+    /// error message returned in response to the request, if it failed
+    pub error_message: Option<String>,
+}
+
+// ALERTS
+
+/// Whether an alert rule is firing
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertRuleState {
+    /// The rule's condition wasn't met when it was last evaluated, or it
+    /// hasn't been evaluated yet
+    Ok,
+    /// The rule's condition was met when it was last evaluated
+    Firing,
+}
+
+/// Client view of an alert rule, which fires when an aggregation of a
+/// timeseries has been past a threshold for some time
+#[derive(ObjectIdentity, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AlertRule {
+    #[serde(flatten)]
+    pub identity: IdentityMetadata,
+    /// The name of the timeseries evaluated
+    pub timeseries_name: String,
+    /// Filters on the fields of the timeseries
+    pub criteria: Vec<String>,
+    /// How the matching timeseries are aggregated
+    pub aggregation: oximeter_db::query::Aggregation,
+    /// Whether the rule fires above or below the threshold
+    pub comparison: shared::AlertComparison,
+    pub threshold: f64,
+    /// How long, in seconds, the aggregated value must have been past the
+    /// threshold before the rule fires
+    pub duration_secs: u32,
+    pub state: AlertRuleState,
+    /// when the rule last started or stopped firing
+    pub time_state_changed: DateTime<Utc>,
+    /// when the rule was last evaluated, if it has been
+    pub time_evaluated: Option<DateTime<Utc>>,
+    /// the most recent aggregated value seen when the rule was last
+    /// evaluated, if there was one
+    pub last_value: Option<f64>,
+}
+
+/// A period during which an alert rule was firing
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Alert {
+    /// unique, immutable, system-controlled identifier for the alert
+    pub id: Uuid,
+    /// the rule which fired
+    pub alert_rule_id: Uuid,
+    /// when the rule started firing
+    pub time_fired: DateTime<Utc>,
+    /// the aggregated value which was past the threshold when the rule fired
+    pub value: f64,
+    /// when the rule stopped firing
+    ///
+    /// This is unset while the rule is still firing.
+    pub time_resolved: Option<DateTime<Utc>>,
+}
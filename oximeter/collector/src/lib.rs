@@ -6,22 +6,29 @@
 
 // Copyright 2021 Oxide Computer Company
 
+use chrono::{DateTime, Utc};
 use dns_service_client::multiclient::{ResolveError, Resolver};
 use dropshot::{
-    endpoint, ApiDescription, ConfigDropshot, ConfigLogging, HttpError,
+    endpoint, ApiDescription, ConfigDropshot, ConfigLogging, EmptyScanParams,
+    HttpError, HttpResponseDeleted, HttpResponseOk,
     HttpResponseUpdatedNoContent, HttpServer, HttpServerStarter,
-    RequestContext, TypedBody,
+    PaginationParams, Path as TypedPath, Query, RequestContext, ResultsPage,
+    TypedBody, WhichPage,
 };
 use internal_dns_names::{ServiceName, SRV};
 use omicron_common::address::{CLICKHOUSE_PORT, NEXUS_INTERNAL_PORT};
 use omicron_common::api::internal::nexus::ProducerEndpoint;
 use omicron_common::backoff;
-use oximeter::types::{ProducerResults, ProducerResultsItem};
+use oximeter::types::{ProducerResults, ProducerResultsItem, Sample};
+use oximeter_db::retention::RetentionPolicy;
 use oximeter_db::{Client, DbWrite};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use slog::{debug, error, info, o, trace, warn, Drain, Logger};
 use std::collections::{btree_map::Entry, BTreeMap};
+use std::convert::TryFrom;
 use std::net::{SocketAddr, SocketAddrV6};
+use std::ops::Bound;
 use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
@@ -31,6 +38,10 @@ use tokio::{
 };
 use uuid::Uuid;
 
+mod spool;
+pub use spool::SpoolConfig;
+use spool::{CollectorStats, DropReason, Spool};
+
 /// Errors collecting metric data
 #[derive(Debug, Clone, Error)]
 pub enum Error {
@@ -45,6 +56,9 @@ pub enum Error {
 
     #[error(transparent)]
     ResolveError(#[from] ResolveError),
+
+    #[error("Error spooling samples: {0}")]
+    Spool(String),
 }
 
 type CollectionToken = oneshot::Sender<()>;
@@ -61,17 +75,18 @@ enum CollectionMessage {
     // from its producer.
     Update(ProducerEndpoint),
     // Request that the task exit
-    #[allow(dead_code)]
     Shutdown,
 }
 
+// Collect metrics from a producer, returning the number of samples collected or a description of
+// the error.
 async fn perform_collection(
     log: &Logger,
     client: &reqwest::Client,
     producer: &ProducerEndpoint,
     outbox: &mpsc::Sender<(Option<CollectionToken>, ProducerResults)>,
     token: Option<CollectionToken>,
-) {
+) -> Result<usize, String> {
     info!(log, "collecting from producer");
     let res = client
         .get(format!(
@@ -91,7 +106,17 @@ async fn perform_collection(
                             "collected {} total results",
                             results.len();
                         );
+                        let n_samples = results
+                            .iter()
+                            .map(|item| match item {
+                                ProducerResultsItem::Ok(samples) => {
+                                    samples.len()
+                                }
+                                ProducerResultsItem::Err(_) => 0,
+                            })
+                            .sum();
                         outbox.send((token, results)).await.unwrap();
+                        Ok(n_samples)
                     }
                     Err(e) => {
                         warn!(
@@ -99,6 +124,7 @@ async fn perform_collection(
                             "failed to collect results from producer: {}",
                             e.to_string();
                         );
+                        Err(format!("invalid results from producer: {}", e))
                     }
                 }
             } else {
@@ -107,6 +133,7 @@ async fn perform_collection(
                     "failed to receive metric results from producer";
                     "status_code" => res.status().as_u16(),
                 );
+                Err(format!("producer responded with {}", res.status()))
             }
         }
         Err(e) => {
@@ -115,6 +142,92 @@ async fn perform_collection(
                 "failed to send collection request to producer: {}",
                 e.to_string();
             );
+            Err(format!("failed to contact producer: {}", e))
+        }
+    }
+}
+
+/// The state of collection from a single producer
+#[derive(Debug, Clone, JsonSchema, Serialize, Deserialize)]
+pub struct ProducerStatus {
+    /// The information the producer registered with
+    pub endpoint: ProducerEndpoint,
+    /// When the producer was last registered
+    pub time_registered: DateTime<Utc>,
+    /// When metrics were last collected from the producer
+    pub last_success: Option<DateTime<Utc>>,
+    /// When collecting metrics from the producer last failed
+    pub last_failure: Option<DateTime<Utc>>,
+    /// The error from the last failed collection
+    pub last_error: Option<String>,
+    /// The number of collections that have failed since the last successful one
+    pub consecutive_failures: u64,
+    /// The total number of successful collections
+    pub n_collections: u64,
+    /// The total number of samples collected
+    pub n_samples: u64,
+}
+
+impl ProducerStatus {
+    fn new(endpoint: ProducerEndpoint) -> Self {
+        Self {
+            endpoint,
+            time_registered: Utc::now(),
+            last_success: None,
+            last_failure: None,
+            last_error: None,
+            consecutive_failures: 0,
+            n_collections: 0,
+            n_samples: 0,
+        }
+    }
+
+    // Update the registration information, such as after the producer restarts. The producer is
+    // presumably reachable again, so any failures so far are forgiven.
+    fn reregister(&mut self, endpoint: ProducerEndpoint) {
+        self.endpoint = endpoint;
+        self.time_registered = Utc::now();
+        self.consecutive_failures = 0;
+    }
+
+    // Record the outcome of a collection.
+    fn record(&mut self, result: Result<usize, String>) {
+        match result {
+            Ok(n_samples) => {
+                self.last_success = Some(Utc::now());
+                self.consecutive_failures = 0;
+                self.n_collections += 1;
+                self.n_samples += n_samples as u64;
+            }
+            Err(e) => {
+                self.last_failure = Some(Utc::now());
+                self.last_error = Some(e);
+                self.consecutive_failures += 1;
+            }
+        }
+    }
+
+    // Return the interval until the next collection, which backs off exponentially from the
+    // producer's own interval while collections are failing.
+    fn collection_interval(&self, max_backoff: Duration) -> Duration {
+        let interval = self.endpoint.interval;
+        if self.consecutive_failures == 0 {
+            return interval;
+        }
+        let exponent =
+            u32::try_from(self.consecutive_failures.min(16)).unwrap();
+        interval.saturating_mul(1 << exponent).min(max_backoff.max(interval))
+    }
+
+    // Return `true` if nothing has been collected from the producer for longer than `timeout`.
+    fn is_expired(&self, now: DateTime<Utc>, timeout: Duration) -> bool {
+        if self.consecutive_failures == 0 {
+            return false;
+        }
+        let last_seen = self.last_success.unwrap_or(self.time_registered);
+        match (now - last_seen).to_std() {
+            Ok(elapsed) => elapsed > timeout,
+            Err(_) => false,
         }
     }
 }
@@ -122,17 +235,25 @@ async fn perform_collection(
 // Background task used to collect metrics from one producer on an interval.
 //
 // This function is started by the `OximeterAgent`, when a producer is registered. The task loops
-// endlessly, and collects metrics from the assigned producer on a timeout. The assigned agent can
-// also send a `CollectionMessage`, for example to update the collection interval. This is not
-// currently used, but will likely be exposed via control plane interfaces in the future.
+// until the producer is deleted, and collects metrics from the assigned producer on a timeout. The
+// assigned agent can also send a `CollectionMessage`, for example to update the collection
+// interval.
+//
+// While collections fail, the interval between them backs off. The task exits, evicting the
+// producer, once nothing has been collected for longer than the configured eviction timeout.
 async fn collection_task(
     log: Logger,
-    mut producer: ProducerEndpoint,
+    config: ProducerConfig,
+    status: Arc<Mutex<ProducerStatus>>,
     mut inbox: mpsc::Receiver<CollectionMessage>,
     outbox: mpsc::Sender<(Option<CollectionToken>, ProducerResults)>,
 ) {
     let client = reqwest::Client::new();
-    let mut collection_timer = interval(producer.interval);
+    let mut producer = status.lock().await.endpoint.clone();
+    let max_backoff = Duration::from_secs(config.max_backoff_interval);
+    let eviction_timeout = config.eviction_timeout.map(Duration::from_secs);
+    let mut current_interval = producer.interval;
+    let mut collection_timer = interval(current_interval);
     collection_timer.tick().await; // completes immediately
     debug!(
         log,
@@ -141,7 +262,7 @@ async fn collection_task(
     );
 
     loop {
-        tokio::select! {
+        let token = tokio::select! {
             message = inbox.recv() => {
                 match message {
                     None => {
@@ -154,7 +275,7 @@ async fn collection_task(
                     },
                     Some(CollectionMessage::Collect(token)) => {
                         debug!(log, "collection task received explicit request to collect");
-                        perform_collection(&log, &client, &producer, &outbox, Some(token)).await;
+                        Some(token)
                     },
                     Some(CollectionMessage::Update(new_info)) => {
                         producer = new_info;
@@ -164,15 +285,44 @@ async fn collection_task(
                             "interval" => ?producer.interval,
                             "address" => producer.address,
                         );
-                        collection_timer = interval(producer.interval);
+                        status.lock().await.reregister(producer.clone());
+                        current_interval = producer.interval;
+                        collection_timer = interval(current_interval);
                         collection_timer.tick().await; // completes immediately
+                        continue;
                     }
                 }
             }
-            _ = collection_timer.tick() => {
-                perform_collection(&log, &client, &producer, &outbox, None).await;
+            _ = collection_timer.tick() => None,
+        };
+
+        let result =
+            perform_collection(&log, &client, &producer, &outbox, token).await;
+        let mut state = status.lock().await;
+        state.record(result);
+        if let Some(timeout) = eviction_timeout {
+            if state.is_expired(Utc::now(), timeout) {
+                warn!(
+                    log,
+                    "evicting unreachable producer";
+                    "last_success" => ?state.last_success,
+                    "last_error" => ?state.last_error,
+                );
+                return;
             }
         }
+        let next_interval = state.collection_interval(max_backoff);
+        drop(state);
+        if next_interval != current_interval {
+            debug!(
+                log,
+                "changing collection interval";
+                "interval" => ?next_interval,
+            );
+            current_interval = next_interval;
+            collection_timer = interval(current_interval);
+            collection_timer.tick().await; // completes immediately
+        }
     }
 }
 
@@ -182,8 +332,11 @@ struct CollectionTask {
     // Channel used to send messages from the agent to the actual task. The task owns the other
     // side.
     pub inbox: mpsc::Sender<CollectionMessage>,
+    // The state of collection from the producer, which the task updates.
+    pub status: Arc<Mutex<ProducerStatus>>,
     // Handle to the actual tokio task running the collection loop.
-    #[allow(dead_code)]
+    //
+    // The task exits when the producer is deleted or evicted.
     pub task: JoinHandle<()>,
 }
 
@@ -193,6 +346,8 @@ async fn results_sink(
     client: Client,
     batch_size: usize,
     batch_interval: Duration,
+    mut spool: Option<Spool>,
+    mut stats: CollectorStats,
     mut rx: mpsc::Receiver<(Option<CollectionToken>, ProducerResults)>,
 ) {
     let mut timer = interval(batch_interval);
@@ -200,8 +355,10 @@ async fn results_sink(
     let mut batch = Vec::with_capacity(batch_size);
     loop {
         let mut collection_token = None;
+        let mut replay = false;
         let insert = tokio::select! {
             _ = timer.tick() => {
+                replay = true;
                 if batch.is_empty() {
                     trace!(log, "batch interval expired, but no samples to insert");
                     false
@@ -245,21 +402,19 @@ async fn results_sink(
             }
         };
 
-        if insert {
-            debug!(log, "inserting {} samples into database", batch.len());
-            match client.insert_samples(&batch).await {
-                Ok(()) => trace!(log, "successfully inserted samples"),
-                Err(e) => {
-                    warn!(
-                        log,
-                        "failed to insert some results into metric DB: {}",
-                        e.to_string()
-                    );
-                }
+        // Anything spooled goes into the database first, so that samples are inserted in the order
+        // they were collected. Only part of a large spool is replayed at a time, so that results
+        // from the collection tasks keep being received while it's worked through.
+        if let Some(spool) = spool.as_mut() {
+            if (insert || replay) && !spool.is_empty() {
+                replay_spool(&log, &client, spool, &mut stats).await;
             }
-            // TODO-correctness The `insert_samples` call above may fail. The method itself needs
-            // better handling of partially-inserted results in that case, but we may need to retry
-            // or otherwise handle an error here as well.
+        }
+
+        if insert {
+            batch.extend(stats.samples(spool.as_ref()));
+            insert_batch(&log, &client, spool.as_mut(), &mut stats, &batch)
+                .await;
             batch.clear();
         }
 
@@ -269,8 +424,84 @@ async fn results_sink(
     }
 }
 
+// Insert a batch of samples into the database, spooling them if that fails.
+async fn insert_batch(
+    log: &Logger,
+    client: &Client,
+    mut spool: Option<&mut Spool>,
+    stats: &mut CollectorStats,
+    batch: &[Sample],
+) {
+    // If earlier batches are still spooled, the database is presumably still unavailable, and this
+    // batch must wait behind them anyway.
+    if let Some(spool) = spool.as_deref_mut() {
+        if !spool.is_empty() {
+            debug!(
+                log,
+                "spooling {} samples behind earlier batches",
+                batch.len()
+            );
+            spool_batch(log, spool, stats, batch).await;
+            return;
+        }
+    }
+    debug!(log, "inserting {} samples into database", batch.len());
+    match client.insert_samples(batch).await {
+        Ok(()) => trace!(log, "successfully inserted samples"),
+        Err(e) => {
+            // TODO-correctness The `insert_samples` call may fail after inserting some of the
+            // samples, in which case those are inserted again when the batch is replayed.
+            warn!(
+                log,
+                "failed to insert some results into metric DB: {}",
+                e.to_string()
+            );
+            match spool {
+                Some(spool) => spool_batch(log, spool, stats, batch).await,
+                None => stats.dropped(DropReason::InsertFailed, batch.len()),
+            }
+        }
+    }
+}
+
+// Append a batch of samples to the spool, recording any that are discarded.
+async fn spool_batch(
+    log: &Logger,
+    spool: &mut Spool,
+    stats: &mut CollectorStats,
+    batch: &[Sample],
+) {
+    match spool.push(batch).await {
+        Ok(n_evicted) => stats.dropped(DropReason::SpoolFull, n_evicted),
+        Err(e) => {
+            error!(log, "failed to spool samples"; "error" => %e);
+            stats.dropped(DropReason::SpoolError, batch.len());
+        }
+    }
+}
+
+// Replay spooled batches into the database, oldest first, once it's reachable again.
+async fn replay_spool(
+    log: &Logger,
+    client: &Client,
+    spool: &mut Spool,
+    stats: &mut CollectorStats,
+) {
+    if let Err(e) = client.ping().await {
+        debug!(log, "metric DB still unavailable, not replaying spool"; "error" => %e);
+        return;
+    }
+    debug!(log, "replaying {} spooled samples", spool.n_samples());
+    let replayed = spool
+        .replay(|batch| async move { client.insert_samples(&batch).await })
+        .await;
+    trace!(log, "replayed spooled samples"; "n_samples" => replayed.inserted);
+    stats.dropped(DropReason::SpoolError, replayed.unreadable);
+    stats.dropped(DropReason::Rejected, replayed.rejected);
+}
+
 /// Configuration for interacting with the metric database.
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DbConfig {
     /// Optional address of the ClickHouse server.
     ///
@@ -284,6 +515,56 @@ pub struct DbConfig {
     /// Interval on which to insert data into the database, regardless of the number of collected
     /// samples. Value is in seconds.
     pub batch_interval: u64,
+
+    /// Optional policy for how long measurements are kept in the database.
+    ///
+    /// If "None", the retention policy last applied to the database is left in place. A database
+    /// which has never had a policy applied keeps measurements indefinitely.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<RetentionPolicy>,
+
+    /// Optional configuration for spooling samples to disk while the database is unavailable.
+    ///
+    /// If "None", samples that can't be inserted are discarded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spool: Option<SpoolConfig>,
+}
+
+/// Configuration for collecting metrics from producers.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct ProducerConfig {
+    /// The longest interval between collections from a producer, while collections from it are
+    /// failing and backing off. Value is in seconds.
+    #[serde(default = "ProducerConfig::default_max_backoff_interval")]
+    pub max_backoff_interval: u64,
+
+    /// Optional time after which a producer that metrics can't be collected from is evicted. Value
+    /// is in seconds.
+    ///
+    /// If "None", producers are collected from until they're deleted.
+    ///
+    /// Nexus isn't told about evictions, and keeps the producer assigned to this collector. The
+    /// producer is collected from again once Nexus registers it here anew, which happens when the
+    /// producer registers with Nexus after it restarts, or when this collector restarts and
+    /// registers with Nexus. Until then, the producer is missing from the collector's list of
+    /// producers, and its metrics are stale.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eviction_timeout: Option<u64>,
+}
+
+impl ProducerConfig {
+    fn default_max_backoff_interval() -> u64 {
+        300
+    }
+}
+
+impl Default for ProducerConfig {
+    fn default() -> Self {
+        Self {
+            max_backoff_interval: Self::default_max_backoff_interval(),
+            eviction_timeout: None,
+        }
+    }
 }
 
 /// The internal agent the oximeter server uses to collect metrics from producers.
@@ -296,6 +577,8 @@ pub struct OximeterAgent {
     result_sender: mpsc::Sender<(Option<CollectionToken>, ProducerResults)>,
     // The actual tokio tasks running the collection on a timer.
     collection_tasks: Arc<Mutex<BTreeMap<Uuid, CollectionTask>>>,
+    // Configuration for the collection tasks
+    producer_config: ProducerConfig,
 }
 
 impl OximeterAgent {
@@ -303,6 +586,7 @@ impl OximeterAgent {
     pub async fn with_id(
         id: Uuid,
         db_config: DbConfig,
+        producer_config: ProducerConfig,
         resolver: &Resolver,
         log: &Logger,
     ) -> Result<Self, Error> {
@@ -324,6 +608,13 @@ impl OximeterAgent {
         };
         let client = Client::new(db_address, &log);
         client.init_db().await?;
+        if let Some(policy) = &db_config.retention {
+            client.apply_retention_policy(policy).await?;
+        }
+        let spool = match &db_config.spool {
+            Some(config) => Some(Spool::open(&insertion_log, config).await?),
+            None => None,
+        };
 
         // Spawn the task for aggregating and inserting all metrics
         tokio::spawn(async move {
@@ -332,6 +623,8 @@ impl OximeterAgent {
                 client,
                 db_config.batch_size,
                 Duration::from_secs(db_config.batch_interval),
+                spool,
+                CollectorStats::new(id),
                 result_receiver,
             )
             .await
@@ -341,6 +634,7 @@ impl OximeterAgent {
             log,
             result_sender,
             collection_tasks: Arc::new(Mutex::new(BTreeMap::new())),
+            producer_config,
         })
     }
 
@@ -350,23 +644,17 @@ impl OximeterAgent {
         info: ProducerEndpoint,
     ) -> Result<(), Error> {
         let id = info.id;
-        match self.collection_tasks.lock().await.entry(id) {
+        let mut collection_tasks = self.collection_tasks.lock().await;
+        self.remove_evicted_producers(&mut collection_tasks);
+        match collection_tasks.entry(id) {
             Entry::Vacant(value) => {
                 info!(self.log, "registered new metric producer";
                       "producer_id" => id.to_string(),
                       "address" => info.address,
                 );
-
-                // Build channel to control the task and receive results.
-                let (tx, rx) = mpsc::channel(4);
-                let q = self.result_sender.clone();
-                let log = self.log.new(o!("component" => "collection-task", "producer_id" => id.to_string()));
-                let task = tokio::spawn(async move {
-                    collection_task(log, info, rx, q).await;
-                });
-                value.insert(CollectionTask { inbox: tx, task });
+                value.insert(self.spawn_collection_task(info));
             }
-            Entry::Occupied(value) => {
+            Entry::Occupied(mut value) => {
                 info!(
                     self.log,
                     "received request to register existing metric producer, updating collection information";
@@ -374,17 +662,98 @@ impl OximeterAgent {
                    "interval" => ?info.interval,
                    "address" => info.address,
                 );
-                value
-                    .get()
-                    .inbox
-                    .send(CollectionMessage::Update(info))
-                    .await
-                    .unwrap();
+                let update = CollectionMessage::Update(info.clone());
+                if value.get().inbox.send(update).await.is_err() {
+                    // The task exited after the check above, because the producer was evicted.
+                    value.insert(self.spawn_collection_task(info));
+                }
             }
         }
         Ok(())
     }
 
+    // Start a task collecting from a producer.
+    fn spawn_collection_task(&self, info: ProducerEndpoint) -> CollectionTask {
+        // Build channel to control the task and receive results.
+        let (tx, rx) = mpsc::channel(4);
+        let q = self.result_sender.clone();
+        let log = self.log.new(o!("component" => "collection-task", "producer_id" => info.id.to_string()));
+        let config = self.producer_config;
+        let status = Arc::new(Mutex::new(ProducerStatus::new(info)));
+        let task_status = Arc::clone(&status);
+        let task = tokio::spawn(async move {
+            collection_task(log, config, task_status, rx, q).await;
+        });
+        CollectionTask { inbox: tx, status, task }
+    }
+
+    // Remove producers whose collection tasks have exited, because they were evicted.
+    fn remove_evicted_producers(
+        &self,
+        collection_tasks: &mut BTreeMap<Uuid, CollectionTask>,
+    ) {
+        collection_tasks.retain(|id, task| {
+            let evicted = task.task.is_finished();
+            if evicted {
+                info!(self.log, "removed evicted metric producer";
+                      "producer_id" => id.to_string());
+            }
+            !evicted
+        });
+    }
+
+    /// List the producers this oximeter instance collects from, sorted by ID.
+    pub async fn list_producers(
+        &self,
+        start_id: Option<Uuid>,
+        limit: usize,
+    ) -> Vec<ProducerStatus> {
+        let mut collection_tasks = self.collection_tasks.lock().await;
+        self.remove_evicted_producers(&mut collection_tasks);
+        let start = match start_id {
+            Some(id) => Bound::Excluded(id),
+            None => Bound::Unbounded,
+        };
+        let mut producers = Vec::new();
+        for task in
+            collection_tasks.range((start, Bound::Unbounded)).take(limit)
+        {
+            producers.push(task.1.status.lock().await.clone());
+        }
+        producers
+    }
+
+    /// Return the state of collection from a single producer.
+    pub async fn producer_status(&self, id: Uuid) -> Option<ProducerStatus> {
+        let mut collection_tasks = self.collection_tasks.lock().await;
+        self.remove_evicted_producers(&mut collection_tasks);
+        match collection_tasks.get(&id) {
+            Some(task) => Some(task.status.lock().await.clone()),
+            None => None,
+        }
+    }
+
+    /// Stop collecting from a producer.
+    ///
+    /// As with evictions, Nexus isn't told, and may register the producer with this oximeter
+    /// instance again. See [`ProducerConfig::eviction_timeout`].
+    ///
+    /// Returns `false` if the producer isn't registered with this oximeter instance.
+    pub async fn delete_producer(&self, id: Uuid) -> bool {
+        let mut collection_tasks = self.collection_tasks.lock().await;
+        self.remove_evicted_producers(&mut collection_tasks);
+        match collection_tasks.remove(&id) {
+            Some(task) => {
+                info!(self.log, "deleted metric producer";
+                      "producer_id" => id.to_string());
+                // The task may have exited already, if it was just evicted.
+                let _ = task.inbox.send(CollectionMessage::Shutdown).await;
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Forces a collection from all producers.
     ///
     /// Returns once all those values have been inserted into Clickhouse,
@@ -395,7 +764,10 @@ impl OximeterAgent {
         for task in collection_tasks.iter() {
             let (tx, rx) = oneshot::channel();
             // Scrape from each producer, into oximeter...
-            task.1.inbox.send(CollectionMessage::Collect(tx)).await.unwrap();
+            //
+            // The send fails if the producer has been evicted, in which case the token is dropped
+            // and there's nothing to wait for.
+            let _ = task.1.inbox.send(CollectionMessage::Collect(tx)).await;
             // ... and keep track of the token that indicates once the metric
             // has made it into Clickhouse.
             collection_oneshots.push(rx);
@@ -423,6 +795,10 @@ pub struct Config {
     /// Configuration for working with ClickHouse
     pub db: DbConfig,
 
+    /// Configuration for collecting from producers
+    #[serde(default)]
+    pub producers: ProducerConfig,
+
     /// Logging configuration
     pub log: ConfigLogging,
 }
@@ -489,8 +865,14 @@ impl Oximeter {
         let make_agent = || async {
             debug!(log, "creating ClickHouse client");
             Ok(Arc::new(
-                OximeterAgent::with_id(args.id, config.db, &resolver, &log)
-                    .await?,
+                OximeterAgent::with_id(
+                    args.id,
+                    config.db.clone(),
+                    config.producers,
+                    &resolver,
+                    &log,
+                )
+                .await?,
             ))
         };
         let log_client_failure = |error, delay| {
@@ -596,6 +978,12 @@ pub fn oximeter_api() -> ApiDescription<Arc<OximeterAgent>> {
     let mut api = ApiDescription::new();
     api.register(producers_post)
         .expect("Could not register producers_post API handler");
+    api.register(producers_list)
+        .expect("Could not register producers_list API handler");
+    api.register(producer_view)
+        .expect("Could not register producer_view API handler");
+    api.register(producer_delete)
+        .expect("Could not register producer_delete API handler");
     api
 }
 
@@ -616,3 +1004,318 @@ async fn producers_post(
         .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
     Ok(HttpResponseUpdatedNoContent())
 }
+
+// List the producers this collector collects from, and the state of collection from each.
+#[endpoint {
+    method = GET,
+    path = "/producers",
+}]
+async fn producers_list(
+    request_context: RequestContext<Arc<OximeterAgent>>,
+    query: Query<PaginationParams<EmptyScanParams, Uuid>>,
+) -> Result<HttpResponseOk<ResultsPage<ProducerStatus>>, HttpError> {
+    let agent = request_context.context();
+    let query = query.into_inner();
+    let limit = request_context.page_limit(&query)?.get() as usize;
+    let start_id = match query.page {
+        WhichPage::First(_) => None,
+        WhichPage::Next(id) => Some(id),
+    };
+    let producers = agent.list_producers(start_id, limit).await;
+    Ok(HttpResponseOk(ResultsPage::new(
+        producers,
+        &EmptyScanParams {},
+        |producer: &ProducerStatus, _| producer.endpoint.id,
+    )?))
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct ProducerIdPathParams {
+    producer_id: Uuid,
+}
+
+// Fetch the state of collection from a single producer.
+#[endpoint {
+    method = GET,
+    path = "/producers/{producer_id}",
+}]
+async fn producer_view(
+    request_context: RequestContext<Arc<OximeterAgent>>,
+    path: TypedPath<ProducerIdPathParams>,
+) -> Result<HttpResponseOk<ProducerStatus>, HttpError> {
+    let agent = request_context.context();
+    let producer_id = path.into_inner().producer_id;
+    agent.producer_status(producer_id).await.map(HttpResponseOk).ok_or_else(
+        || {
+            HttpError::for_not_found(
+                None,
+                format!("No producer with ID {}", producer_id),
+            )
+        },
+    )
+}
+
+// Stop collecting from a producer.
+#[endpoint {
+    method = DELETE,
+    path = "/producers/{producer_id}",
+}]
+async fn producer_delete(
+    request_context: RequestContext<Arc<OximeterAgent>>,
+    path: TypedPath<ProducerIdPathParams>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let agent = request_context.context();
+    let producer_id = path.into_inner().producer_id;
+    if agent.delete_producer(producer_id).await {
+        Ok(HttpResponseDeleted())
+    } else {
+        Err(HttpError::for_not_found(
+            None,
+            format!("No producer with ID {}", producer_id),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use omicron_test_utils::dev::clickhouse::ClickHouseInstance;
+    use omicron_test_utils::dev::poll::{wait_for_condition, CondCheckError};
+    use omicron_test_utils::dev::test_setup_log;
+
+    fn producer_status(interval: Duration) -> ProducerStatus {
+        ProducerStatus::new(ProducerEndpoint {
+            id: Uuid::new_v4(),
+            address: "[::1]:12345".parse().unwrap(),
+            base_route: "/collect".to_string(),
+            interval,
+        })
+    }
+
+    #[test]
+    fn test_producer_status_backoff() {
+        let max_backoff = Duration::from_secs(60);
+        let mut status = producer_status(Duration::from_secs(10));
+        assert_eq!(status.collection_interval(max_backoff).as_secs(), 10);
+
+        status.record(Err("unreachable".to_string()));
+        assert_eq!(status.collection_interval(max_backoff).as_secs(), 20);
+        status.record(Err("unreachable".to_string()));
+        assert_eq!(status.collection_interval(max_backoff).as_secs(), 40);
+        for _ in 0..100 {
+            status.record(Err("unreachable".to_string()));
+        }
+        assert_eq!(status.collection_interval(max_backoff).as_secs(), 60);
+        assert_eq!(status.consecutive_failures, 102);
+        assert_eq!(status.last_error.as_deref(), Some("unreachable"));
+
+        status.record(Ok(5));
+        assert_eq!(status.collection_interval(max_backoff).as_secs(), 10);
+        assert_eq!(status.consecutive_failures, 0);
+        assert_eq!(status.n_collections, 1);
+        assert_eq!(status.n_samples, 5);
+
+        // The backoff never makes collection more frequent than the producer asked for.
+        let mut status = producer_status(Duration::from_secs(120));
+        status.record(Err("unreachable".to_string()));
+        assert_eq!(status.collection_interval(max_backoff).as_secs(), 120);
+    }
+
+    #[test]
+    fn test_producer_status_expiry() {
+        let timeout = Duration::from_secs(60);
+        let mut status = producer_status(Duration::from_secs(10));
+        let later = status.time_registered + chrono::Duration::seconds(120);
+        assert!(!status.is_expired(later, timeout));
+
+        status.record(Err("unreachable".to_string()));
+        assert!(status.is_expired(later, timeout));
+        assert!(!status.is_expired(status.time_registered, timeout));
+
+        status.record(Ok(1));
+        let last_success = status.last_success.unwrap();
+        status.record(Err("unreachable".to_string()));
+        assert!(!status.is_expired(last_success, timeout));
+        assert!(status
+            .is_expired(last_success + chrono::Duration::seconds(61), timeout));
+    }
+
+    // Start an agent inserting into `db`, and an HTTP server for its API.
+    async fn start_agent(
+        log: &Logger,
+        db: &ClickHouseInstance,
+        producer_config: ProducerConfig,
+    ) -> HttpServer<Arc<OximeterAgent>> {
+        let db_config = DbConfig {
+            address: Some(SocketAddr::new("::1".parse().unwrap(), db.port())),
+            batch_size: 1000,
+            batch_interval: 10,
+            retention: None,
+            spool: None,
+        };
+        let resolver = Resolver::new_from_ip("::1".parse().unwrap()).unwrap();
+        let agent = OximeterAgent::with_id(
+            Uuid::new_v4(),
+            db_config,
+            producer_config,
+            &resolver,
+            log,
+        )
+        .await
+        .unwrap();
+        HttpServerStarter::new(
+            &ConfigDropshot {
+                bind_address: "[::1]:0".parse().unwrap(),
+                ..Default::default()
+            },
+            oximeter_api(),
+            Arc::new(agent),
+            log,
+        )
+        .unwrap()
+        .start()
+    }
+
+    async fn fetch_producer(
+        client: &reqwest::Client,
+        base: &str,
+        id: Uuid,
+    ) -> Option<ProducerStatus> {
+        let response =
+            client.get(format!("{}/{}", base, id)).send().await.unwrap();
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return None;
+        }
+        Some(response.error_for_status().unwrap().json().await.unwrap())
+    }
+
+    async fn list_producers(
+        client: &reqwest::Client,
+        base: &str,
+    ) -> Vec<ProducerStatus> {
+        client
+            .get(base)
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json::<ResultsPage<ProducerStatus>>()
+            .await
+            .unwrap()
+            .items
+    }
+
+    #[tokio::test]
+    async fn test_producer_endpoints() {
+        let logctx = test_setup_log("test_producer_endpoints");
+        let log = &logctx.log;
+        let mut db = ClickHouseInstance::new(0)
+            .await
+            .expect("Failed to start ClickHouse");
+        let producer_config = ProducerConfig {
+            max_backoff_interval: 1,
+            eviction_timeout: Some(3),
+        };
+        let server = start_agent(log, &db, producer_config).await;
+        let base = format!("http://{}/producers", server.local_addr());
+        let client = reqwest::Client::new();
+
+        // Register two producers that can't be reached, because nothing listens on their address.
+        let address = std::net::TcpListener::bind("[::1]:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut ids = [Uuid::new_v4(), Uuid::new_v4()];
+        ids.sort();
+        let endpoint = |id| ProducerEndpoint {
+            id,
+            address,
+            base_route: "/collect".to_string(),
+            interval: Duration::from_millis(100),
+        };
+        for id in ids {
+            let response =
+                client.post(&base).json(&endpoint(id)).send().await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        }
+        let producers = list_producers(&client, &base).await;
+        assert_eq!(
+            producers.iter().map(|p| p.endpoint.id).collect::<Vec<_>>(),
+            ids
+        );
+
+        // Deleting a producer removes it, and it can't be deleted twice.
+        let response =
+            client.delete(format!("{}/{}", base, ids[1])).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        assert!(fetch_producer(&client, &base, ids[1]).await.is_none());
+        let response =
+            client.delete(format!("{}/{}", base, ids[1])).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        // Failed collections are recorded, and back off. Without backing off, the third failure
+        // would come 300ms after registering rather than 700ms.
+        const POLL_INTERVAL: Duration = Duration::from_millis(50);
+        const POLL_DURATION: Duration = Duration::from_secs(30);
+        let status = wait_for_condition(
+            || async {
+                match fetch_producer(&client, &base, ids[0]).await {
+                    Some(status) if status.consecutive_failures >= 3 => {
+                        Ok(status)
+                    }
+                    _ => Err(CondCheckError::<reqwest::Error>::NotYet),
+                }
+            },
+            &POLL_INTERVAL,
+            &POLL_DURATION,
+        )
+        .await
+        .expect("producer never failed");
+        assert!(status.last_success.is_none());
+        assert_eq!(status.n_collections, 0);
+        assert!(status
+            .last_error
+            .as_deref()
+            .unwrap()
+            .starts_with("failed to contact producer"));
+        let elapsed = status.last_failure.unwrap() - status.time_registered;
+        assert!(
+            elapsed >= chrono::Duration::milliseconds(600),
+            "collections did not back off: {} failures in {}",
+            status.consecutive_failures,
+            elapsed
+        );
+
+        // The producer is evicted once nothing has been collected from it for the eviction
+        // timeout.
+        wait_for_condition(
+            || async {
+                match fetch_producer(&client, &base, ids[0]).await {
+                    Some(_) => Err(CondCheckError::<reqwest::Error>::NotYet),
+                    None => Ok(()),
+                }
+            },
+            &POLL_INTERVAL,
+            &POLL_DURATION,
+        )
+        .await
+        .expect("producer was never evicted");
+        assert!(list_producers(&client, &base).await.is_empty());
+
+        // Registering the producer again, as Nexus does when the producer itself registers again,
+        // starts collecting from it afresh.
+        let response =
+            client.post(&base).json(&endpoint(ids[0])).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        let producers = list_producers(&client, &base).await;
+        assert_eq!(producers.len(), 1);
+        assert_eq!(producers[0].endpoint.id, ids[0]);
+        assert_eq!(producers[0].n_collections, 0);
+        assert!(producers[0].time_registered > status.time_registered);
+
+        server.close().await.unwrap();
+        db.cleanup().await.expect("Failed to cleanup ClickHouse server");
+        logctx.cleanup_successful();
+    }
+}
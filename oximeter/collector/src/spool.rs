@@ -0,0 +1,599 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Spooling of samples that could not be inserted into the metric database.
+//!
+//! When ClickHouse is unavailable, batches of samples are written to files in a local directory,
+//! and replayed in the order they were spooled once the database is reachable again. The total
+//! size of the spool is bounded, with the oldest batches evicted first. A batch that the database
+//! keeps rejecting is eventually discarded, rather than holding up those behind it.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::Error;
+use oximeter::types::{Cumulative, Sample};
+use oximeter::{Metric, Target};
+use serde::{Deserialize, Serialize};
+use slog::{debug, error, warn, Logger};
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use uuid::Uuid;
+
+/// The most batches replayed from the spool at once.
+///
+/// Replaying happens in the task that receives collected samples, so a large spool is worked
+/// through a bit at a time rather than holding up everything else until it's empty.
+pub(crate) const MAX_REPLAY_BATCHES: usize = 16;
+
+/// The number of times the database may reject a spooled batch before it's discarded.
+///
+/// Failures because the database is unavailable don't count towards this, only those where it was
+/// reached and refused the batch, which retrying is unlikely to fix.
+pub(crate) const MAX_REPLAY_REJECTIONS: u32 = 3;
+
+/// Configuration for spooling samples to disk when the metric database is unavailable.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpoolConfig {
+    /// The directory in which batches of samples are spooled.
+    pub directory: PathBuf,
+
+    /// The maximum total size of the spooled batches, in bytes.
+    ///
+    /// The oldest batches are discarded to stay within this limit.
+    pub max_bytes: u64,
+}
+
+// A batch of samples in the spool.
+//
+// Each batch is stored in its own file, named by its sequence number and the number of samples it
+// contains, so that the spool can be reconstructed from the directory alone. The number of times
+// the database rejected the batch is only kept in memory, and starts over if the collector
+// restarts.
+#[derive(Debug, Clone, Copy)]
+struct SpoolEntry {
+    seq: u64,
+    n_samples: usize,
+    bytes: u64,
+    rejections: u32,
+}
+
+impl SpoolEntry {
+    fn file_name(&self) -> String {
+        format!("{:020}-{}.json", self.seq, self.n_samples)
+    }
+
+    fn from_file_name(name: &str, bytes: u64) -> Option<Self> {
+        let (seq, n_samples) = name.strip_suffix(".json")?.split_once('-')?;
+        Some(Self {
+            seq: seq.parse().ok()?,
+            n_samples: n_samples.parse().ok()?,
+            bytes,
+            rejections: 0,
+        })
+    }
+}
+
+/// The outcome of replaying part of the spool.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Replayed {
+    /// The number of samples inserted into the database.
+    pub inserted: usize,
+    /// The number of samples discarded because their batch couldn't be read from the spool.
+    pub unreadable: usize,
+    /// The number of samples discarded because the database rejected their batch too many times.
+    pub rejected: usize,
+}
+
+/// A bounded, on-disk queue of batches of samples.
+#[derive(Debug)]
+pub(crate) struct Spool {
+    log: Logger,
+    directory: PathBuf,
+    max_bytes: u64,
+    // The spooled batches, oldest first.
+    entries: VecDeque<SpoolEntry>,
+    next_seq: u64,
+}
+
+impl Spool {
+    /// Open the spool in the configured directory, creating it if needed.
+    ///
+    /// Batches spooled by a previous instance of the collector are picked up, so that they're
+    /// replayed along with any new ones.
+    pub async fn open(
+        log: &Logger,
+        config: &SpoolConfig,
+    ) -> Result<Self, Error> {
+        let directory = config.directory.clone();
+        fs::create_dir_all(&directory)
+            .await
+            .map_err(|e| spool_error(&directory, e))?;
+        let mut entries = Vec::new();
+        let mut dirents = fs::read_dir(&directory)
+            .await
+            .map_err(|e| spool_error(&directory, e))?;
+        while let Some(dirent) = dirents
+            .next_entry()
+            .await
+            .map_err(|e| spool_error(&directory, e))?
+        {
+            let path = dirent.path();
+            let metadata =
+                dirent.metadata().await.map_err(|e| spool_error(&path, e))?;
+            let entry = dirent.file_name().to_str().and_then(|name| {
+                SpoolEntry::from_file_name(name, metadata.len())
+            });
+            match entry {
+                Some(entry) if metadata.is_file() => entries.push(entry),
+                // Leftovers from a write that didn't complete.
+                _ if path.extension().map_or(false, |ext| ext == "tmp") => {
+                    let _ = fs::remove_file(&path).await;
+                }
+                _ => warn!(
+                    log,
+                    "ignoring unexpected file in spool";
+                    "path" => %path.display(),
+                ),
+            }
+        }
+        entries.sort_by_key(|entry| entry.seq);
+        let next_seq = entries.last().map_or(0, |entry| entry.seq + 1);
+        let spool = Self {
+            log: log.new(slog::o!("component" => "spool")),
+            directory,
+            max_bytes: config.max_bytes,
+            entries: entries.into(),
+            next_seq,
+        };
+        if !spool.is_empty() {
+            debug!(
+                spool.log,
+                "found spooled samples";
+                "n_batches" => spool.entries.len(),
+                "n_samples" => spool.n_samples(),
+            );
+        }
+        Ok(spool)
+    }
+
+    /// Return `true` if there are no spooled batches.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Return the total number of spooled samples.
+    pub fn n_samples(&self) -> usize {
+        self.entries.iter().map(|entry| entry.n_samples).sum()
+    }
+
+    /// Return the total size of the spooled batches, in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.bytes).sum()
+    }
+
+    /// Append a batch of samples to the spool.
+    ///
+    /// Returns the number of samples discarded to make room for the batch, which includes the
+    /// batch itself if it's larger than the spool.
+    pub async fn push(&mut self, samples: &[Sample]) -> Result<usize, Error> {
+        let contents =
+            serde_json::to_vec(samples).expect("Failed to serialize samples");
+        let entry = SpoolEntry {
+            seq: self.next_seq,
+            n_samples: samples.len(),
+            bytes: contents.len() as u64,
+            rejections: 0,
+        };
+        if entry.bytes > self.max_bytes {
+            warn!(
+                self.log,
+                "batch is larger than the spool, discarding it";
+                "n_samples" => entry.n_samples,
+                "bytes" => entry.bytes,
+            );
+            return Ok(entry.n_samples);
+        }
+
+        // Write to a temporary file first, so that a crash never leaves a partial batch behind.
+        let path = self.directory.join(entry.file_name());
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, contents)
+            .await
+            .map_err(|e| spool_error(&tmp_path, e))?;
+        fs::rename(&tmp_path, &path)
+            .await
+            .map_err(|e| spool_error(&path, e))?;
+        self.next_seq += 1;
+        self.entries.push_back(entry);
+
+        let mut n_evicted = 0;
+        while self.bytes() > self.max_bytes {
+            n_evicted += self
+                .pop_front()
+                .await
+                .expect("spool exceeds its limit, so can't be empty");
+        }
+        if n_evicted > 0 {
+            warn!(
+                self.log,
+                "spool is full, discarded oldest samples";
+                "n_samples" => n_evicted,
+            );
+        }
+        Ok(n_evicted)
+    }
+
+    /// Read the oldest batch in the spool, without removing it.
+    pub async fn front(&self) -> Option<Result<Vec<Sample>, Error>> {
+        let entry = self.entries.front()?;
+        let path = self.directory.join(entry.file_name());
+        Some(fs::read(&path).await.map_err(|e| spool_error(&path, e)).and_then(
+            |contents| {
+                serde_json::from_slice(&contents)
+                    .map_err(|e| spool_error(&path, e))
+            },
+        ))
+    }
+
+    /// Remove the oldest batch from the spool, returning the number of samples it contained.
+    pub async fn pop_front(&mut self) -> Option<usize> {
+        let entry = self.entries.pop_front()?;
+        let path = self.directory.join(entry.file_name());
+        if let Err(e) = fs::remove_file(&path).await {
+            // The batch will be found and replayed again if the collector restarts.
+            warn!(
+                self.log,
+                "failed to remove spooled batch";
+                "path" => %path.display(),
+                "error" => %e,
+            );
+        }
+        Some(entry.n_samples)
+    }
+
+    /// Replay up to [`MAX_REPLAY_BATCHES`] of the oldest batches, inserting each with `insert`.
+    ///
+    /// Replaying stops early if the database is unavailable, or rejects a batch fewer than
+    /// [`MAX_REPLAY_REJECTIONS`] times, leaving that batch at the front of the spool to be tried
+    /// again. A batch rejected that many times is discarded, so that it can't hold up those
+    /// behind it forever.
+    pub async fn replay<F, Fut>(&mut self, mut insert: F) -> Replayed
+    where
+        F: FnMut(Vec<Sample>) -> Fut,
+        Fut: Future<Output = Result<(), oximeter_db::Error>>,
+    {
+        let mut replayed = Replayed::default();
+        for _ in 0..MAX_REPLAY_BATCHES {
+            let batch = match self.front().await {
+                Some(Ok(batch)) => batch,
+                Some(Err(e)) => {
+                    error!(
+                        self.log,
+                        "discarding unreadable spooled samples";
+                        "error" => %e,
+                    );
+                    replayed.unreadable += self.pop_front().await.unwrap_or(0);
+                    continue;
+                }
+                None => break,
+            };
+            match insert(batch).await {
+                Ok(()) => {
+                    replayed.inserted += self.pop_front().await.unwrap_or(0);
+                }
+                Err(e @ oximeter_db::Error::DatabaseUnavailable(_)) => {
+                    warn!(
+                        self.log,
+                        "failed to replay spooled samples";
+                        "error" => %e,
+                    );
+                    break;
+                }
+                Err(e) => {
+                    let entry = self
+                        .entries
+                        .front_mut()
+                        .expect("a batch was just read from the spool");
+                    entry.rejections += 1;
+                    if entry.rejections < MAX_REPLAY_REJECTIONS {
+                        warn!(
+                            self.log,
+                            "database rejected spooled samples";
+                            "rejections" => entry.rejections,
+                            "error" => %e,
+                        );
+                        break;
+                    }
+                    error!(
+                        self.log,
+                        "database keeps rejecting spooled samples, discarding them";
+                        "rejections" => entry.rejections,
+                        "error" => %e,
+                    );
+                    replayed.rejected += self.pop_front().await.unwrap_or(0);
+                }
+            }
+        }
+        replayed
+    }
+}
+
+fn spool_error(path: &Path, error: impl std::fmt::Display) -> Error {
+    Error::Spool(format!("{}: {}", path.display(), error))
+}
+
+/// The collector itself, as the target of metrics about its own operation
+#[derive(Debug, Clone, Target)]
+pub struct OximeterCollector {
+    pub collector_id: Uuid,
+}
+
+/// The number of samples waiting in the spool to be inserted into the database
+#[derive(Debug, Clone, Metric)]
+pub struct SpooledSamples {
+    #[datum]
+    pub count: i64,
+}
+
+/// The size of the batches waiting in the spool, in bytes
+#[derive(Debug, Clone, Metric)]
+pub struct SpooledBytes {
+    #[datum]
+    pub bytes: i64,
+}
+
+/// The number of samples discarded without being inserted into the database, by the reason they
+/// were discarded
+#[derive(Debug, Clone, Metric)]
+pub struct DroppedSamples {
+    pub reason: String,
+    #[datum]
+    pub count: Cumulative<i64>,
+}
+
+/// Why samples were discarded without being inserted into the database
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum DropReason {
+    /// The insertion failed, and there's no spool.
+    InsertFailed,
+    /// The batch couldn't be written to, or read back from, the spool.
+    SpoolError,
+    /// The batch was evicted from, or never fit into, the spool.
+    SpoolFull,
+    /// The database rejected the spooled batch too many times.
+    Rejected,
+}
+
+impl DropReason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DropReason::InsertFailed => "insert_failed",
+            DropReason::SpoolError => "spool_error",
+            DropReason::SpoolFull => "spool_full",
+            DropReason::Rejected => "rejected",
+        }
+    }
+}
+
+/// Tracks metrics about the collector's handling of samples, which it inserts alongside the
+/// samples it collects.
+#[derive(Debug)]
+pub(crate) struct CollectorStats {
+    target: OximeterCollector,
+    dropped: BTreeMap<&'static str, DroppedSamples>,
+}
+
+impl CollectorStats {
+    pub fn new(collector_id: Uuid) -> Self {
+        Self {
+            target: OximeterCollector { collector_id },
+            dropped: BTreeMap::new(),
+        }
+    }
+
+    /// Record that `n_samples` were discarded.
+    pub fn dropped(&mut self, reason: DropReason, n_samples: usize) {
+        if n_samples == 0 {
+            return;
+        }
+        let reason = reason.as_str();
+        *self
+            .dropped
+            .entry(reason)
+            .or_insert_with(|| DroppedSamples {
+                reason: reason.to_string(),
+                count: Cumulative::default(),
+            })
+            .datum_mut() += n_samples as i64;
+    }
+
+    /// Return the current samples of the collector's metrics.
+    pub fn samples(&self, spool: Option<&Spool>) -> Vec<Sample> {
+        let mut samples = self
+            .dropped
+            .values()
+            .map(|metric| Sample::new(&self.target, metric))
+            .collect::<Vec<_>>();
+        if let Some(spool) = spool {
+            samples.push(Sample::new(
+                &self.target,
+                &SpooledSamples { count: spool.n_samples() as i64 },
+            ));
+            samples.push(Sample::new(
+                &self.target,
+                &SpooledBytes { bytes: spool.bytes() as i64 },
+            ));
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oximeter::test_util;
+    use slog::o;
+
+    fn spool_config(directory: &Path, max_bytes: u64) -> SpoolConfig {
+        SpoolConfig { directory: directory.to_path_buf(), max_bytes }
+    }
+
+    // `Sample`'s `PartialEq` ignores the measured data, so compare that separately.
+    fn assert_samples_eq(actual: &[Sample], expected: &[Sample]) {
+        assert_eq!(actual, expected);
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert_eq!(actual.measurement, expected.measurement);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spool_push_and_replay() {
+        let log = Logger::root(slog::Discard, o!());
+        let dir = tempfile::tempdir().unwrap();
+        let config = spool_config(dir.path(), u64::MAX);
+        let mut spool = Spool::open(&log, &config).await.unwrap();
+        assert!(spool.is_empty());
+        assert!(spool.front().await.is_none());
+
+        let first = vec![test_util::make_sample()];
+        let second =
+            vec![test_util::make_sample(), test_util::make_hist_sample()];
+        assert_eq!(spool.push(&first).await.unwrap(), 0);
+        assert_eq!(spool.push(&second).await.unwrap(), 0);
+        assert_eq!(spool.n_samples(), 3);
+
+        // Batches survive reopening the spool, and come back in order.
+        drop(spool);
+        let mut spool = Spool::open(&log, &config).await.unwrap();
+        assert_eq!(spool.n_samples(), 3);
+        assert_samples_eq(&spool.front().await.unwrap().unwrap(), &first);
+        assert_eq!(spool.pop_front().await, Some(1));
+        assert_samples_eq(&spool.front().await.unwrap().unwrap(), &second);
+        assert_eq!(spool.pop_front().await, Some(2));
+        assert!(spool.is_empty());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_spool_evicts_oldest() {
+        let log = Logger::root(slog::Discard, o!());
+        let dir = tempfile::tempdir().unwrap();
+        let batch = vec![test_util::make_sample()];
+        let batch_bytes = serde_json::to_vec(&batch).unwrap().len() as u64;
+        let mut spool =
+            Spool::open(&log, &spool_config(dir.path(), batch_bytes * 2))
+                .await
+                .unwrap();
+
+        assert_eq!(spool.push(&batch).await.unwrap(), 0);
+        assert_eq!(spool.push(&batch).await.unwrap(), 0);
+        assert_eq!(spool.push(&batch).await.unwrap(), 1);
+        assert_eq!(spool.n_samples(), 2);
+        assert_eq!(spool.bytes(), batch_bytes * 2);
+        assert_eq!(spool.entries.front().unwrap().seq, 1);
+
+        // A batch that could never fit is discarded outright.
+        let big = vec![test_util::make_sample(); 3];
+        assert_eq!(spool.push(&big).await.unwrap(), 3);
+        assert_eq!(spool.n_samples(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_spool_replay_is_bounded() {
+        let log = Logger::root(slog::Discard, o!());
+        let dir = tempfile::tempdir().unwrap();
+        let mut spool = Spool::open(&log, &spool_config(dir.path(), u64::MAX))
+            .await
+            .unwrap();
+        let batch = vec![test_util::make_sample()];
+        for _ in 0..=MAX_REPLAY_BATCHES {
+            spool.push(&batch).await.unwrap();
+        }
+
+        let replayed = spool.replay(|_| async { Ok(()) }).await;
+        assert_eq!(replayed.inserted, MAX_REPLAY_BATCHES);
+        assert_eq!(spool.n_samples(), 1);
+        let replayed = spool.replay(|_| async { Ok(()) }).await;
+        assert_eq!(replayed.inserted, 1);
+        assert!(spool.is_empty());
+    }
+
+    // Replay the spool into a database which rejects batches of two samples, and is unavailable
+    // if `unavailable` is set, collecting the batches it accepts in `inserted`.
+    async fn replay(
+        spool: &mut Spool,
+        unavailable: bool,
+        inserted: &mut Vec<Vec<Sample>>,
+    ) -> Replayed {
+        spool
+            .replay(|batch| {
+                let result = if unavailable {
+                    Err(oximeter_db::Error::DatabaseUnavailable(String::from(
+                        "down",
+                    )))
+                } else if batch.len() == 2 {
+                    Err(oximeter_db::Error::Database(String::from("rejected")))
+                } else {
+                    inserted.push(batch);
+                    Ok(())
+                };
+                async move { result }
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_spool_replay_discards_rejected_batch() {
+        let log = Logger::root(slog::Discard, o!());
+        let dir = tempfile::tempdir().unwrap();
+        let mut spool = Spool::open(&log, &spool_config(dir.path(), u64::MAX))
+            .await
+            .unwrap();
+        let first = vec![test_util::make_sample()];
+        let poisoned =
+            vec![test_util::make_sample(), test_util::make_hist_sample()];
+        let last = vec![test_util::make_hist_sample()];
+        spool.push(&first).await.unwrap();
+        spool.push(&poisoned).await.unwrap();
+        spool.push(&last).await.unwrap();
+
+        let mut inserted = Vec::new();
+        // The first batch goes in, and replaying stops at the poisoned one.
+        let replayed = replay(&mut spool, false, &mut inserted).await;
+        assert_eq!(
+            replayed,
+            Replayed { inserted: 1, unreadable: 0, rejected: 0 }
+        );
+        assert_eq!(spool.n_samples(), 3);
+
+        // Failures while the database is unavailable don't count as rejections.
+        for _ in 0..MAX_REPLAY_REJECTIONS {
+            assert_eq!(
+                replay(&mut spool, true, &mut inserted).await,
+                Replayed::default()
+            );
+        }
+        assert_eq!(spool.n_samples(), 3);
+
+        // Once rejected enough times, the poisoned batch is discarded and the ones behind it are
+        // replayed. It was rejected once above already.
+        for _ in 2..MAX_REPLAY_REJECTIONS {
+            assert_eq!(
+                replay(&mut spool, false, &mut inserted).await,
+                Replayed::default()
+            );
+        }
+        let replayed = replay(&mut spool, false, &mut inserted).await;
+        assert_eq!(
+            replayed,
+            Replayed { inserted: 1, unreadable: 0, rejected: 2 }
+        );
+        assert!(spool.is_empty());
+        assert_eq!(inserted.len(), 2);
+        assert_samples_eq(&inserted[0], &first);
+        assert_samples_eq(&inserted[1], &last);
+    }
+}
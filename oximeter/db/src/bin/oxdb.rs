@@ -12,10 +12,11 @@ use oximeter::{
     types::{Cumulative, Sample},
     Metric, Target,
 };
-use oximeter_db::{query, Client, DbWrite};
+use oximeter_db::{query, Client, DbWrite, TimeseriesName};
 use slog::{debug, info, o, Drain, Level, Logger};
 use std::net::IpAddr;
 use std::net::SocketAddr;
+use std::num::NonZeroU32;
 use uuid::Uuid;
 
 // Samples are inserted in chunks of this size, to avoid large allocations when inserting huge
@@ -111,6 +112,16 @@ enum Subcommand {
     /// Wipe the database and any data in it. CAREFUL.
     Wipe,
 
+    /// Show the retention policy applied to the measurements in the database
+    Retention,
+
+    /// Show every version of the schema of a timeseries, oldest first
+    Schema {
+        /// The name of the timeseries whose schema is shown.
+        #[clap(action)]
+        timeseries_name: String,
+    },
+
     /// Run a query against the database, assuming it is populated with data.
     Query {
         /// The name of the timeseries to search for. (Currently only `virtual_machine:cpu_busy`
@@ -137,6 +148,29 @@ enum Subcommand {
         /// The start time to which the search is constrained, exclusive.
         #[clap(long, conflicts_with("end"), action)]
         end_exclusive: Option<DateTime<Utc>>,
+
+        /// Aggregate the matching timeseries with this reducer (`sum`, `mean`, `min`, `max`, or
+        /// `count`), rather than printing their raw measurements.
+        #[clap(long, requires("window"), action)]
+        reducer: Option<query::Reducer>,
+
+        /// The width, in seconds, of the windows into which measurements are bucketed when
+        /// aggregating.
+        #[clap(long, requires("reducer"), action)]
+        window: Option<NonZeroU32>,
+
+        /// A field by which timeseries are grouped when aggregating. May be given more than once.
+        #[clap(long, requires("reducer"), action)]
+        group_by: Vec<String>,
+
+        /// Aggregate the rate of change of cumulative timeseries, rather than their values.
+        #[clap(long, requires("reducer"), action)]
+        rate: bool,
+
+        /// Summarize aggregated histograms with this statistic (`mean`, `variance`, `std_dev`, or
+        /// a percentile such as `p99`), rather than printing the merged histograms.
+        #[clap(long, requires("reducer"), action)]
+        statistic: Option<query::HistogramStatistic>,
     },
 }
 
@@ -270,6 +304,46 @@ async fn wipe_db(
     client.wipe_db().await.context("Failed to wipe database")
 }
 
+async fn show_retention_policy(
+    address: IpAddr,
+    port: u16,
+    log: Logger,
+) -> Result<(), anyhow::Error> {
+    let client = make_client(address, port, &log).await?;
+    match client
+        .retention_policy()
+        .await
+        .context("Failed to fetch retention policy")?
+    {
+        Some(policy) => {
+            println!("{}", serde_json::to_string(&policy).unwrap())
+        }
+        None => println!("No retention policy, measurements are kept forever"),
+    }
+    Ok(())
+}
+
+async fn show_schema(
+    address: IpAddr,
+    port: u16,
+    log: Logger,
+    timeseries_name: String,
+) -> Result<(), anyhow::Error> {
+    let client = make_client(address, port, &log).await?;
+    let timeseries_name = TimeseriesName::try_from(timeseries_name)?;
+    let versions = client
+        .timeseries_schema_history(&timeseries_name)
+        .await
+        .context("Failed to fetch timeseries schema")?;
+    if versions.is_empty() {
+        bail!("No such timeseries: \"{}\"", timeseries_name);
+    }
+    for schema in versions.iter() {
+        println!("{}", serde_json::to_string(schema).unwrap());
+    }
+    Ok(())
+}
+
 async fn query(
     address: IpAddr,
     port: u16,
@@ -278,19 +352,34 @@ async fn query(
     filters: Vec<String>,
     start: Option<query::Timestamp>,
     end: Option<query::Timestamp>,
+    aggregation: Option<query::Aggregation>,
 ) -> Result<(), anyhow::Error> {
     let client = make_client(address, port, &log).await?;
     let filters = filters.iter().map(|s| s.as_str()).collect::<Vec<_>>();
-    let timeseries = client
-        .select_timeseries_with(
-            &timeseries_name,
-            filters.as_slice(),
-            start,
-            end,
-            None,
-        )
-        .await?;
-    println!("{}", serde_json::to_string(&timeseries).unwrap());
+    if let Some(aggregation) = aggregation {
+        let timeseries = client
+            .select_timeseries_aggregated(
+                &timeseries_name,
+                filters.as_slice(),
+                start,
+                end,
+                aggregation,
+                None,
+            )
+            .await?;
+        println!("{}", serde_json::to_string(&timeseries).unwrap());
+    } else {
+        let timeseries = client
+            .select_timeseries_with(
+                &timeseries_name,
+                filters.as_slice(),
+                start,
+                end,
+                None,
+            )
+            .await?;
+        println!("{}", serde_json::to_string(&timeseries).unwrap());
+    }
     Ok(())
 }
 
@@ -314,6 +403,14 @@ async fn main() {
         Subcommand::Wipe => {
             wipe_db(args.address, args.port, log).await.unwrap()
         }
+        Subcommand::Retention => {
+            show_retention_policy(args.address, args.port, log).await.unwrap()
+        }
+        Subcommand::Schema { timeseries_name } => {
+            show_schema(args.address, args.port, log, timeseries_name)
+                .await
+                .unwrap()
+        }
         Subcommand::Query {
             timeseries_name,
             filters,
@@ -321,6 +418,11 @@ async fn main() {
             start_exclusive,
             end,
             end_exclusive,
+            reducer,
+            window,
+            group_by,
+            rate,
+            statistic,
         } => {
             let start = match (start, start_exclusive) {
                 (Some(start), _) => Some(query::Timestamp::Inclusive(start)),
@@ -332,6 +434,18 @@ async fn main() {
                 (_, Some(end)) => Some(query::Timestamp::Exclusive(end)),
                 (None, None) => None,
             };
+            let aggregation = match (reducer, window) {
+                (Some(reducer), Some(window_secs)) => {
+                    Some(query::Aggregation {
+                        reducer,
+                        group_by,
+                        window_secs,
+                        rate,
+                        statistic,
+                    })
+                }
+                _ => None,
+            };
             query(
                 args.address,
                 args.port,
@@ -340,6 +454,7 @@ async fn main() {
                 filters,
                 start,
                 end,
+                aggregation,
             )
             .await
             .unwrap();
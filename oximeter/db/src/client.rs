@@ -5,15 +5,17 @@
 //! Rust client to ClickHouse database
 // Copyright 2021 Oxide Computer Company
 
+use crate::retention::RetentionPolicy;
 use crate::{
-    model, query, Error, Metric, Target, Timeseries, TimeseriesPageSelector,
-    TimeseriesScanParams, TimeseriesSchema,
+    model, query, AggregatedTimeseries, Error, Field, Metric, Target,
+    Timeseries, TimeseriesPageSelector, TimeseriesScanParams, TimeseriesSchema,
 };
 use crate::{TimeseriesKey, TimeseriesName};
 use async_trait::async_trait;
+use chrono::Utc;
 use dropshot::{EmptyScanParams, ResultsPage, WhichPage};
-use oximeter::types::Sample;
-use slog::{debug, error, trace, Logger};
+use oximeter::types::{Datum, DatumType, Measurement, Sample};
+use slog::{debug, error, info, trace, Logger};
 use std::collections::{btree_map::Entry, BTreeMap, BTreeSet};
 use std::convert::TryFrom;
 use std::net::SocketAddr;
@@ -28,7 +30,9 @@ pub struct Client {
     log: Logger,
     url: String,
     client: reqwest::Client,
-    schema: Mutex<BTreeMap<TimeseriesName, TimeseriesSchema>>,
+    // Every known version of the schema of each timeseries, oldest first. Versions are identified
+    // by both their number and their fields, see `insert_schema_version`.
+    schema: Mutex<BTreeMap<TimeseriesName, Vec<TimeseriesSchema>>>,
 }
 
 impl Client {
@@ -79,38 +83,135 @@ impl Client {
         //  values from the measurement rows, we avoid transferring the data from those columns
         //  to/from the database, as well as the cost of parsing them for each measurement, only to
         //  promptly throw away almost all of them (except for the first).
-        let timeseries_name = TimeseriesName::try_from(timeseries_name)?;
-        let schema =
-            self.schema_for_timeseries(&timeseries_name).await?.ok_or_else(
-                || Error::TimeseriesNotFound(format!("{timeseries_name}")),
-            )?;
-        let query_builder = query::SelectQueryBuilder::new(&schema)
-            .start_time(start_time)
-            .end_time(end_time);
-
-        let mut query_builder = if let Some(limit) = limit {
-            query_builder.limit(limit)
-        } else {
-            query_builder
-        };
-
-        for criterion in criteria.iter() {
-            query_builder = query_builder.filter_raw(criterion)?;
-        }
-
+        let (versions, criteria, query_builder) = self
+            .select_query_builder(
+                timeseries_name,
+                criteria,
+                start_time,
+                end_time,
+                limit,
+            )
+            .await?;
         let query = query_builder.build();
-        let info = match query.field_query() {
-            Some(field_query) => {
-                self.select_matching_timeseries_info(&field_query, &schema)
-                    .await?
-            }
-            None => BTreeMap::new(),
-        };
+        let info =
+            self.select_matching_timeseries_info(&versions, &criteria).await?;
         if info.is_empty() {
             Ok(vec![])
         } else {
-            self.select_timeseries_with_keys(&query, &info, &schema).await
+            self.select_timeseries_with_keys(&query, &info).await
+        }
+    }
+
+    /// Select timeseries from criteria on the fields and start/end timestamps, and aggregate them
+    /// in the database as described by `aggregation`.
+    ///
+    /// One [`AggregatedTimeseries`] is returned for each distinct combination of values of the
+    /// `group_by` fields that has any measurements in the time range. The `limit` applies to the
+    /// total number of aggregated measurements.
+    pub async fn select_timeseries_aggregated(
+        &self,
+        timeseries_name: &str,
+        criteria: &[&str],
+        start_time: Option<query::Timestamp>,
+        end_time: Option<query::Timestamp>,
+        aggregation: query::Aggregation,
+        limit: Option<NonZeroU32>,
+    ) -> Result<Vec<AggregatedTimeseries>, Error> {
+        let (versions, criteria, query_builder) = self
+            .select_query_builder(
+                timeseries_name,
+                criteria,
+                start_time,
+                end_time,
+                limit,
+            )
+            .await?;
+        let query = query_builder.aggregate(aggregation)?.build();
+        let schema = query.schema();
+        let info =
+            self.select_matching_timeseries_info(&versions, &criteria).await?;
+        if info.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // Assign each timeseries to the group identified by its values of the fields it's grouped
+        // by. This mapping is sent along with the query, so that the database can aggregate each
+        // group separately.
+        //
+        // Timeseries recorded with a version of the schema without one of those fields are
+        // grouped as though it were null, by leaving it out of their group.
+        let group_by = &query.aggregation().unwrap().group_by;
+        let mut groups: Vec<Vec<Field>> = Vec::new();
+        let mut group_ids = BTreeMap::new();
+        for (key, (target, metric)) in info.iter() {
+            let group = group_by
+                .iter()
+                .filter_map(|name| {
+                    target
+                        .fields
+                        .iter()
+                        .chain(metric.fields.iter())
+                        .find(|field| &field.name == name)
+                        .cloned()
+                })
+                .collect::<Vec<_>>();
+            let group_id = match groups.iter().position(|g| g == &group) {
+                Some(index) => index,
+                None => {
+                    groups.push(group);
+                    groups.len() - 1
+                }
+            };
+            group_ids.insert(*key, group_id as u64);
+        }
+
+        let aggregated_query = query
+            .aggregated_measurement_query(&group_ids)
+            .expect("Query should have an aggregation");
+        let statistic = query.aggregation().unwrap().statistic;
+        let mut timeseries_by_group = BTreeMap::new();
+        for line in self.execute_with_body(&aggregated_query).await?.lines() {
+            let (group_id, measurement) =
+                model::parse_aggregated_measurement_from_row(
+                    line,
+                    schema.datum_type,
+                )
+                .ok_or_else(|| {
+                    Error::HistogramBinMismatch(
+                        schema.timeseries_name.to_string(),
+                    )
+                })?;
+
+            // Summarize merged histograms if requested, skipping those without any samples.
+            let measurement = match statistic {
+                Some(statistic) => {
+                    let value = match measurement.datum() {
+                        Datum::HistogramI64(hist) => statistic.evaluate(hist),
+                        Datum::HistogramF64(hist) => statistic.evaluate(hist),
+                        _ => unreachable!(
+                            "Statistics are only computed for histograms"
+                        ),
+                    };
+                    match value {
+                        Some(value) => Measurement::new(
+                            measurement.timestamp(),
+                            Datum::from(value),
+                        ),
+                        None => continue,
+                    }
+                }
+                None => measurement,
+            };
+            let timeseries = timeseries_by_group
+                .entry(group_id)
+                .or_insert_with(|| AggregatedTimeseries {
+                    timeseries_name: schema.timeseries_name.to_string(),
+                    group: groups[group_id as usize].clone(),
+                    measurements: Vec::new(),
+                });
+            timeseries.measurements.push(measurement);
         }
+        Ok(timeseries_by_group.into_values().collect())
     }
 
     pub async fn list_timeseries(
@@ -122,12 +223,8 @@ impl Client {
             WhichPage::First(ref params) => (params, 0),
             WhichPage::Next(ref sel) => (&sel.params, sel.offset.get()),
         };
-        let schema = self
-            .schema_for_timeseries(&params.timeseries_name)
-            .await?
-            .ok_or_else(|| {
-                Error::TimeseriesNotFound(format!("{}", params.timeseries_name))
-            })?;
+        let versions = self.schema_versions(&params.timeseries_name).await?;
+        let schema = merge_schema_versions(&versions);
         // TODO: Handle inclusive/exclusive timestamps in general.
         //
         // These come from a query parameter, so it's not obvious what format they should have.
@@ -143,17 +240,13 @@ impl Client {
         }
 
         let query = query_builder.build();
-        let info = match query.field_query() {
-            Some(field_query) => {
-                self.select_matching_timeseries_info(&field_query, &schema)
-                    .await?
-            }
-            None => BTreeMap::new(),
-        };
+        let info = self
+            .select_matching_timeseries_info(&versions, &params.criteria)
+            .await?;
         let results = if info.is_empty() {
             vec![]
         } else {
-            self.select_timeseries_with_keys(&query, &info, &schema).await?
+            self.select_timeseries_with_keys(&query, &info).await?
         };
         Ok(ResultsPage::new(results, &params, |_, _| {
             NonZeroU32::try_from(limit.get() + offset).unwrap()
@@ -161,7 +254,7 @@ impl Client {
         .unwrap())
     }
 
-    /// Return the schema for a timeseries by name.
+    /// Return the latest version of the schema for a timeseries by name.
     ///
     /// Note
     /// ----
@@ -173,17 +266,63 @@ impl Client {
     ) -> Result<Option<TimeseriesSchema>, Error> {
         {
             let map = self.schema.lock().unwrap();
-            if let Some(s) = map.get(name) {
+            if let Some(s) = map.get(name).and_then(|v| v.last()) {
                 return Ok(Some(s.clone()));
             }
         }
         // `get_schema` acquires the lock internally, so the above scope is required to avoid
         // deadlock.
         self.get_schema().await?;
-        Ok(self.schema.lock().unwrap().get(name).map(Clone::clone))
+        Ok(self
+            .schema
+            .lock()
+            .unwrap()
+            .get(name)
+            .and_then(|versions| versions.last())
+            .cloned())
+    }
+
+    /// Return every version of the schema for a timeseries by name, oldest first.
+    ///
+    /// This always queries the database, so it includes versions recorded by other clients. An
+    /// empty list is returned if the timeseries doesn't exist.
+    pub async fn timeseries_schema_history(
+        &self,
+        name: &TimeseriesName,
+    ) -> Result<Vec<TimeseriesSchema>, Error> {
+        let sql = format!(
+            concat!(
+                "SELECT * ",
+                "FROM {db_name}.timeseries_schema ",
+                "WHERE timeseries_name = '{timeseries_name}' ",
+                "ORDER BY version, created ",
+                "FORMAT JSONEachRow;",
+            ),
+            db_name = crate::DATABASE_NAME,
+            timeseries_name = name,
+        );
+        let body = self.execute_with_body(sql).await?;
+        let mut map = self.schema.lock().unwrap();
+        let versions = map.entry(name.clone()).or_default();
+        for line in body.lines() {
+            insert_schema_version(
+                versions,
+                TimeseriesSchema::from(
+                    serde_json::from_str::<model::DbTimeseriesSchema>(line)
+                        .expect(
+                        "Failed to deserialize TimeseriesSchema from database",
+                    ),
+                ),
+            );
+        }
+        let versions = versions.clone();
+        if versions.is_empty() {
+            map.remove(name);
+        }
+        Ok(versions)
     }
 
-    /// List timeseries schema, paginated.
+    /// List the latest version of each timeseries schema, paginated.
     pub async fn timeseries_schema_list(
         &self,
         page: &WhichPage<EmptyScanParams, TimeseriesName>,
@@ -195,6 +334,8 @@ impl Client {
                     concat!(
                         "SELECT * ",
                         "FROM {}.timeseries_schema ",
+                        "ORDER BY timeseries_name, version DESC, created DESC ",
+                        "LIMIT 1 BY timeseries_name ",
                         "LIMIT {} ",
                         "FORMAT JSONEachRow;",
                     ),
@@ -207,6 +348,8 @@ impl Client {
                     concat!(
                         "SELECT * FROM {}.timeseries_schema ",
                         "WHERE timeseries_name > '{}' ",
+                        "ORDER BY timeseries_name, version DESC, created DESC ",
+                        "LIMIT 1 BY timeseries_name ",
                         "LIMIT {} ",
                         "FORMAT JSONEachRow;",
                     ),
@@ -234,57 +377,196 @@ impl Client {
         .map_err(|e| Error::Database(e.to_string()))
     }
 
-    // Verifies that the schema for a sample matches the schema in the database.
+    /// Return the retention policy most recently applied to the database, if any.
+    ///
+    /// If no policy has been applied, measurements are kept indefinitely.
+    pub async fn retention_policy(
+        &self,
+    ) -> Result<Option<RetentionPolicy>, Error> {
+        let sql = format!(
+            concat!(
+                "SELECT * ",
+                "FROM {}.retention_policy ",
+                "ORDER BY applied DESC ",
+                "LIMIT 1 ",
+                "FORMAT JSONEachRow;",
+            ),
+            crate::DATABASE_NAME,
+        );
+        let body = self.execute_with_body(sql).await?;
+        let Some(line) = body.lines().next() else {
+            return Ok(None);
+        };
+        let row = serde_json::from_str::<model::DbRetentionPolicy>(line)
+            .map_err(|e| Error::Database(e.to_string()))?;
+        serde_json::from_str(&row.policy)
+            .map(Some)
+            .map_err(|e| Error::Database(e.to_string()))
+    }
+
+    // Look up every version of the schema for a timeseries, failing if there are none.
+    async fn schema_versions(
+        &self,
+        timeseries_name: &TimeseriesName,
+    ) -> Result<Vec<TimeseriesSchema>, Error> {
+        let versions = self.timeseries_schema_history(timeseries_name).await?;
+        if versions.is_empty() {
+            return Err(Error::TimeseriesNotFound(format!(
+                "{timeseries_name}"
+            )));
+        }
+        Ok(versions)
+    }
+
+    // Look up the schema for a timeseries, and start building a query selecting it with the given
+    // field criteria and time range.
+    //
+    // The query spans all versions of the schema, so it's built from the fields of all of them.
+    // The versions and parsed criteria are also returned, to select the matching timeseries with
+    // `select_matching_timeseries_info`.
+    async fn select_query_builder(
+        &self,
+        timeseries_name: &str,
+        criteria: &[&str],
+        start_time: Option<query::Timestamp>,
+        end_time: Option<query::Timestamp>,
+        limit: Option<NonZeroU32>,
+    ) -> Result<
+        (
+            Vec<TimeseriesSchema>,
+            Vec<query::StringFieldSelector>,
+            query::SelectQueryBuilder,
+        ),
+        Error,
+    > {
+        let timeseries_name = TimeseriesName::try_from(timeseries_name)?;
+        let versions = self.schema_versions(&timeseries_name).await?;
+        let schema = merge_schema_versions(&versions);
+        let query_builder = query::SelectQueryBuilder::new(&schema)
+            .start_time(start_time)
+            .end_time(end_time);
+
+        let mut query_builder = if let Some(limit) = limit {
+            query_builder.limit(limit)
+        } else {
+            query_builder
+        };
+
+        let criteria = criteria
+            .iter()
+            .map(|criterion| criterion.parse())
+            .collect::<Result<Vec<_>, _>>()?;
+        for criterion in criteria.iter() {
+            query_builder = query_builder.filter_str(criterion)?;
+        }
+        Ok((versions, criteria, query_builder))
+    }
+
+    // Verifies that the schema for a sample matches a version of the schema in the database.
     //
-    // If the schema exists in the database, and the sample matches that schema, `None` is
-    // returned. If the schema does not match, an Err is returned (the caller skips the sample in
-    // this case). If the schema does not _exist_ in the database, Some(schema) is returned, so
-    // that the caller can insert it into the database at the appropriate time.
+    // If the sample matches any version of the schema, `None` is returned. If it matches none,
+    // but is compatible with all of them, it's recorded as the next version of the schema. (A
+    // sample from a timeseries without any schema is the first version.) In that case,
+    // Some(schema) is returned, so that the caller can insert it into the database at the
+    // appropriate time. If the schema is incompatible, an Err is returned (the caller skips the
+    // sample in this case).
+    //
+    // This only consults the internal cache of schema, which callers should refresh from the
+    // database first, with `timeseries_schema_history`.
     async fn verify_sample_schema(
         &self,
         sample: &Sample,
     ) -> Result<Option<String>, Error> {
-        let schema = model::schema_for(sample);
-        let name = schema.timeseries_name.clone();
-        let maybe_new_schema = match self.schema.lock().unwrap().entry(name) {
-            Entry::Vacant(entry) => Ok(Some(entry.insert(schema).clone())),
-            Entry::Occupied(entry) => {
-                let existing_schema = entry.get();
-                if existing_schema == &schema {
-                    Ok(None)
-                } else {
-                    let err =
-                        error_for_schema_mismatch(&schema, &existing_schema);
+        let mut schema = model::schema_for(sample);
+        let mut map = self.schema.lock().unwrap();
+        let versions = map.entry(schema.timeseries_name.clone()).or_default();
+        if versions.iter().any(|version| version == &schema) {
+            return Ok(None);
+        }
+        if let Some(latest) = versions.last() {
+            let next = latest.version.checked_add(1);
+            match next {
+                Some(next)
+                    if versions
+                        .iter()
+                        .all(|version| schema.is_compatible_with(version)) =>
+                {
+                    schema.version = next;
+                    info!(
+                        self.log,
+                        "new version of timeseries schema";
+                        "timeseries_name" => %schema.timeseries_name,
+                        "version" => schema.version.get(),
+                    );
+                }
+                _ => {
+                    let err = error_for_schema_mismatch(&schema, latest);
                     error!(
                         self.log,
                         "timeseries schema mismatch, sample will be skipped: {}",
                         err
                     );
-                    Err(err)
+                    return Err(err);
                 }
             }
-        }?;
-        Ok(maybe_new_schema.map(|schema| {
+        }
+        versions.push(schema.clone());
+        Ok(Some(
             serde_json::to_string(&model::DbTimeseriesSchema::from(schema))
-                .expect("Failed to convert schema to DB model")
-        }))
+                .expect("Failed to convert schema to DB model"),
+        ))
     }
 
-    // Select the timeseries, including keys and field values, that match the given field-selection
-    // query.
+    // Select the timeseries, including keys and field values, that match the given criteria on
+    // their fields.
+    //
+    // The field query joins the records of every field in a schema, so it selects the timeseries
+    // that have at least those fields. The timeseries are selected with each version of the
+    // schema separately, and the fields of those selected by several versions are merged. A
+    // timeseries can't match a criterion on a field it doesn't have, so versions without a field
+    // in the criteria are skipped.
     async fn select_matching_timeseries_info(
         &self,
-        field_query: &str,
-        schema: &TimeseriesSchema,
+        versions: &[TimeseriesSchema],
+        criteria: &[query::StringFieldSelector],
     ) -> Result<BTreeMap<TimeseriesKey, (Target, Metric)>, Error> {
-        let body = self.execute_with_body(field_query).await?;
         let mut results = BTreeMap::new();
-        for line in body.lines() {
-            let row: model::FieldSelectRow = serde_json::from_str(line)
-                .expect("Unable to deserialize an expected row");
-            let (id, target, metric) =
-                model::parse_field_select_row(&row, schema);
-            results.insert(id, (target, metric));
+        'versions: for schema in versions.iter() {
+            let mut query_builder = query::SelectQueryBuilder::new(schema);
+            for criterion in criteria.iter() {
+                query_builder = match query_builder.filter_str(criterion) {
+                    Ok(query_builder) => query_builder,
+                    Err(Error::NoSuchField { .. }) => continue 'versions,
+                    Err(e) => return Err(e),
+                };
+            }
+            let Some(field_query) = query_builder.build().field_query() else {
+                continue;
+            };
+            let body = self.execute_with_body(field_query).await?;
+            for line in body.lines() {
+                let row: model::FieldSelectRow = serde_json::from_str(line)
+                    .expect("Unable to deserialize an expected row");
+                let (id, target, metric) =
+                    model::parse_field_select_row(&row, schema);
+                match results.entry(id) {
+                    Entry::Vacant(entry) => {
+                        entry.insert((target, metric));
+                    }
+                    Entry::Occupied(mut entry) => {
+                        let (existing_target, existing_metric) =
+                            entry.get_mut();
+                        merge_fields(
+                            &mut existing_target.fields,
+                            target.fields,
+                        );
+                        merge_fields(
+                            &mut existing_metric.fields,
+                            metric.fields,
+                        );
+                    }
+                }
+            }
         }
         Ok(results)
     }
@@ -295,8 +577,8 @@ impl Client {
         &self,
         query: &query::SelectQuery,
         info: &BTreeMap<TimeseriesKey, (Target, Metric)>,
-        schema: &TimeseriesSchema,
     ) -> Result<Vec<Timeseries>, Error> {
+        let schema = query.schema();
         let mut timeseries_by_key = BTreeMap::new();
         let keys = info.keys().copied().collect::<Vec<_>>();
         let measurement_query = query.measurement_query(&keys);
@@ -392,16 +674,19 @@ impl Client {
             trace!(self.log, "no new timeseries schema in database");
         } else {
             trace!(self.log, "extracting new timeseries schema");
-            let new = body.lines().map(|line| {
+            let mut map = self.schema.lock().unwrap();
+            for line in body.lines() {
                 let schema = TimeseriesSchema::from(
                     serde_json::from_str::<model::DbTimeseriesSchema>(line)
                         .expect(
                         "Failed to deserialize TimeseriesSchema from database",
                     ),
                 );
-                (schema.timeseries_name.clone(), schema)
-            });
-            self.schema.lock().unwrap().extend(new);
+                insert_schema_version(
+                    map.entry(schema.timeseries_name.clone()).or_default(),
+                    schema,
+                );
+            }
         }
         Ok(())
     }
@@ -423,6 +708,12 @@ pub trait DbWrite {
 
     /// Wipe the ClickHouse database entirely.
     async fn wipe_db(&self) -> Result<(), Error>;
+
+    /// Apply a retention policy to the measurements in the database.
+    async fn apply_retention_policy(
+        &self,
+        policy: &RetentionPolicy,
+    ) -> Result<(), Error>;
 }
 
 #[async_trait]
@@ -434,6 +725,26 @@ impl DbWrite for Client {
         let mut rows = BTreeMap::new();
         let mut new_schema = Vec::new();
 
+        // Refresh the schema of timeseries with samples that match no known version, which may
+        // have been recorded by another client, or before this one was created.
+        let unknown_timeseries = samples
+            .iter()
+            .map(model::schema_for)
+            .filter(|schema| {
+                !self
+                    .schema
+                    .lock()
+                    .unwrap()
+                    .get(&schema.timeseries_name)
+                    .map(|versions| versions.contains(schema))
+                    .unwrap_or(false)
+            })
+            .map(|schema| schema.timeseries_name)
+            .collect::<BTreeSet<_>>();
+        for timeseries_name in unknown_timeseries.iter() {
+            self.timeseries_schema_history(timeseries_name).await?;
+        }
+
         for sample in samples.iter() {
             match self.verify_sample_schema(sample).await {
                 Err(_) => {
@@ -485,7 +796,10 @@ impl DbWrite for Client {
         // data between nodes.
         //
         // NOTE: This is an issue even in the case where the schema don't conflict. Two clients may
-        // receive a sample with a new schema, and both would then try to insert that schema.
+        // receive a sample with a new schema, and both would then try to insert that schema. Or
+        // they may receive samples with different new sets of fields, and record both as the same
+        // version. Versions are identified by their fields as well as their number, so every such
+        // version is still known to readers, see `insert_schema_version`.
         if !new_schema.is_empty() {
             debug!(
                 self.log,
@@ -542,6 +856,53 @@ impl DbWrite for Client {
         let sql = include_str!("./db-wipe.sql").to_string();
         self.execute(sql).await
     }
+
+    /// Apply a retention policy to the measurements in the database.
+    ///
+    /// This replaces the `TTL` of each measurement table, and records the policy so that it can be
+    /// viewed later. Nothing is done if the policy is already in effect, since ClickHouse rewrites
+    /// existing data when a table's `TTL` changes.
+    async fn apply_retention_policy(
+        &self,
+        policy: &RetentionPolicy,
+    ) -> Result<(), Error> {
+        if self.retention_policy().await?.as_ref() == Some(policy) {
+            debug!(self.log, "retention policy is already in effect");
+            return Ok(());
+        }
+        debug!(self.log, "applying retention policy"; "policy" => ?policy);
+        let ttl = policy.ttl_expression();
+        for datum_type in [
+            DatumType::Bool,
+            DatumType::I64,
+            DatumType::F64,
+            DatumType::String,
+            DatumType::Bytes,
+            DatumType::CumulativeI64,
+            DatumType::CumulativeF64,
+            DatumType::HistogramI64,
+            DatumType::HistogramF64,
+        ] {
+            self.execute(format!(
+                "ALTER TABLE {db_name}.{table_name} MODIFY TTL {ttl};",
+                db_name = crate::DATABASE_NAME,
+                table_name = query::measurement_table_name(datum_type),
+                ttl = ttl,
+            ))
+            .await?;
+        }
+        let row = model::DbRetentionPolicy {
+            policy: serde_json::to_string(policy)
+                .expect("Failed to serialize retention policy"),
+            applied: Utc::now(),
+        };
+        self.execute(format!(
+            "INSERT INTO {db_name}.retention_policy FORMAT JSONEachRow\n{row_data}\n",
+            db_name = crate::DATABASE_NAME,
+            row_data = serde_json::to_string(&row).unwrap(),
+        ))
+        .await
+    }
 }
 
 // Return Ok if the response indicates success, otherwise return either the reqwest::Error, if this
@@ -561,6 +922,49 @@ async fn handle_db_response(
     }
 }
 
+// Add a version of a timeseries schema to the known versions, keeping them sorted, unless a
+// version with the same fields is already known.
+//
+// Clients assign the next version number from the versions they know about, and ClickHouse has no
+// transactions, so two clients recording different sets of fields at the same time may both
+// assign them the same number. A version is therefore identified by its number and its fields,
+// and versions sharing a number are ordered by when they were created.
+fn insert_schema_version(
+    versions: &mut Vec<TimeseriesSchema>,
+    schema: TimeseriesSchema,
+) {
+    if versions.iter().any(|v| v.version == schema.version && v == &schema) {
+        return;
+    }
+    let index = versions.partition_point(|v| {
+        (v.version, v.created) <= (schema.version, schema.created)
+    });
+    versions.insert(index, schema);
+}
+
+// Merge the versions of a timeseries schema into one with the fields of all of them, for building
+// queries that span all versions. The merged schema is otherwise the latest version.
+fn merge_schema_versions(versions: &[TimeseriesSchema]) -> TimeseriesSchema {
+    let (latest, older) =
+        versions.split_last().expect("A timeseries has at least one schema");
+    let mut schema = latest.clone();
+    for field in older.iter().rev().flat_map(|v| v.field_schema.iter()) {
+        if schema.field_schema(&field.name).is_none() {
+            schema.field_schema.push(field.clone());
+        }
+    }
+    schema
+}
+
+// Add the fields in `other` that aren't already in `fields`.
+fn merge_fields(fields: &mut Vec<Field>, other: Vec<Field>) {
+    for field in other {
+        if !fields.iter().any(|f| f.name == field.name) {
+            fields.push(field);
+        }
+    }
+}
+
 // Generate an error describing a schema mismatch
 fn error_for_schema_mismatch(
     schema: &TimeseriesSchema,
@@ -643,7 +1047,7 @@ mod tests {
     }
 
     // This is a target with the same name as that in `lib.rs` used for other tests, but with a
-    // different set of fields. This is intentionally used to test schema mismatches.
+    // different set of fields. This is intentionally used to test new versions of a schema.
     mod name_mismatch {
         #[derive(oximeter::Target)]
         pub struct TestTarget {
@@ -662,6 +1066,16 @@ mod tests {
         }
     }
 
+    mod extra_field {
+        #[derive(oximeter::Target)]
+        pub struct TestTarget {
+            pub name1: String,
+            pub name2: String,
+            pub num: i64,
+            pub name3: String,
+        }
+    }
+
     #[tokio::test]
     async fn test_schema_mismatch() {
         let log = slog::Logger::root(slog::Discard, o!());
@@ -692,10 +1106,216 @@ mod tests {
         };
         let sample = Sample::new(&bad_name, &metric);
         let result = client.verify_sample_schema(&sample).await;
+        assert!(
+            matches!(result, Ok(Some(_))),
+            "A sample with different fields should create a new version of the schema"
+        );
+
+        // Changing the type of an existing field is not allowed.
+        let bad_type = type_mismatch::TestTarget {
+            name1: uuid::Uuid::new_v4(),
+            name2: "second_name".into(),
+            num: 2,
+        };
+        let sample = Sample::new(&bad_type, &metric);
+        let result = client.verify_sample_schema(&sample).await;
         assert!(matches!(result, Err(Error::SchemaMismatch { .. })));
         db.cleanup().await.expect("Failed to cleanup ClickHouse server");
     }
 
+    #[tokio::test]
+    async fn test_schema_versions() {
+        let log = slog::Logger::root(slog::Discard, o!());
+
+        // Let the OS assign a port and discover it after ClickHouse starts
+        let mut db = ClickHouseInstance::new(0)
+            .await
+            .expect("Failed to start ClickHouse");
+        let address = SocketAddr::new("::1".parse().unwrap(), db.port());
+
+        let client = Client::new(address, &log);
+        client
+            .init_db()
+            .await
+            .expect("Failed to initialize timeseries database");
+        let first = test_util::make_sample();
+        client.insert_samples(&[first.clone()]).await.unwrap();
+
+        let target = name_mismatch::TestTarget {
+            name: "first_name".into(),
+            name2: "second_name".into(),
+            num: 2,
+        };
+        let metric = test_util::TestMetric {
+            id: uuid::Uuid::new_v4(),
+            good: true,
+            datum: 1,
+        };
+        let second = Sample::new(&target, &metric);
+        client.insert_samples(&[second.clone()]).await.unwrap();
+
+        // A new client should see both versions, oldest first.
+        let client = Client::new(address, &log);
+        let timeseries_name =
+            TimeseriesName::try_from(first.timeseries_name.as_str()).unwrap();
+        let versions =
+            client.timeseries_schema_history(&timeseries_name).await.unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0], model::schema_for(&first));
+        assert_eq!(versions[0].version.get(), 1);
+        assert_eq!(versions[1], model::schema_for(&second));
+        assert_eq!(versions[1].version.get(), 2);
+        assert_eq!(
+            client
+                .schema_for_timeseries(&timeseries_name)
+                .await
+                .unwrap()
+                .unwrap()
+                .version
+                .get(),
+            2,
+        );
+
+        // Inserting a sample matching the first version shouldn't create another.
+        client.insert_samples(&[first.clone()]).await.unwrap();
+        assert_eq!(
+            client
+                .timeseries_schema_history(&timeseries_name)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // Timeseries from both versions are selected, unless filtered by a field only one of them
+        // has.
+        let timeseries = client
+            .select_timeseries_with(
+                &first.timeseries_name,
+                &[],
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(timeseries.len(), 2);
+        let timeseries = client
+            .select_timeseries_with(
+                &first.timeseries_name,
+                &["name2==second_name"],
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(timeseries.len(), 2);
+        let timeseries = client
+            .select_timeseries_with(
+                &first.timeseries_name,
+                &["name==first_name"],
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(timeseries.len(), 1);
+        assert!(timeseries[0]
+            .target
+            .fields
+            .iter()
+            .any(|field| field.name == "name"));
+
+        db.cleanup().await.expect("Failed to cleanup ClickHouse server");
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_schema_versions() {
+        let log = slog::Logger::root(slog::Discard, o!());
+
+        // Let the OS assign a port and discover it after ClickHouse starts
+        let mut db = ClickHouseInstance::new(0)
+            .await
+            .expect("Failed to start ClickHouse");
+        let address = SocketAddr::new("::1".parse().unwrap(), db.port());
+
+        let client = Client::new(address, &log);
+        client
+            .init_db()
+            .await
+            .expect("Failed to initialize timeseries database");
+        let first = test_util::make_sample();
+        client.insert_samples(&[first.clone()]).await.unwrap();
+        let timeseries_name =
+            TimeseriesName::try_from(first.timeseries_name.as_str()).unwrap();
+
+        // Another client learns about the first version of the schema.
+        let other = Client::new(address, &log);
+        other.timeseries_schema_history(&timeseries_name).await.unwrap();
+
+        // The first client records a second version, which the other client doesn't see before it
+        // assigns a version to a different set of fields.
+        let metric = test_util::TestMetric {
+            id: uuid::Uuid::new_v4(),
+            good: true,
+            datum: 1,
+        };
+        let second = Sample::new(
+            &name_mismatch::TestTarget {
+                name: "first_name".into(),
+                name2: "second_name".into(),
+                num: 2,
+            },
+            &metric,
+        );
+        client.insert_samples(&[second.clone()]).await.unwrap();
+        let third = Sample::new(
+            &extra_field::TestTarget {
+                name1: "first_name".into(),
+                name2: "second_name".into(),
+                num: 3,
+                name3: "third_name".into(),
+            },
+            &metric,
+        );
+        let row =
+            other.verify_sample_schema(&third).await.unwrap().expect(
+                "A sample with different fields should be a new version",
+            );
+        other
+            .execute(format!(
+                "INSERT INTO {}.timeseries_schema FORMAT JSONEachRow\n{}\n",
+                crate::DATABASE_NAME,
+                row,
+            ))
+            .await
+            .unwrap();
+
+        // Both versions share a number, but neither is dropped.
+        let client = Client::new(address, &log);
+        let versions =
+            client.timeseries_schema_history(&timeseries_name).await.unwrap();
+        assert_eq!(versions.len(), 3);
+        assert_eq!(versions[1], model::schema_for(&second));
+        assert_eq!(versions[1].version.get(), 2);
+        assert_eq!(versions[2], model::schema_for(&third));
+        assert_eq!(versions[2].version.get(), 2);
+
+        // Samples with either set of fields match a known version.
+        client.insert_samples(&[second, third]).await.unwrap();
+        assert_eq!(
+            client
+                .timeseries_schema_history(&timeseries_name)
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+        db.cleanup().await.expect("Failed to cleanup ClickHouse server");
+    }
+
     #[tokio::test]
     async fn test_schema_update() {
         let log = slog::Logger::root(slog::Discard, o!());
@@ -736,6 +1356,7 @@ mod tests {
             .lock()
             .unwrap()
             .get(&timeseries_name)
+            .and_then(|versions| versions.last())
             .expect(
                 "After inserting a new sample, its schema should be included",
             )
@@ -876,6 +1497,153 @@ mod tests {
         db.cleanup().await.expect("Failed to cleanup ClickHouse server");
     }
 
+    #[tokio::test]
+    async fn test_client_select_timeseries_aggregated() {
+        let (mut db, client, samples) = setup_filter_testcase().await;
+        let timeseries_name = &samples[0].timeseries_name;
+
+        // Use a single window covering all samples, and sum the last value of each CPU in each
+        // project. Each project has 2 instances with 2 CPUs, whose last value is 1.
+        let aggregation = query::Aggregation {
+            reducer: query::Reducer::Sum,
+            group_by: vec![String::from("project_id")],
+            window_secs: NonZeroU32::new(u32::MAX).unwrap(),
+            rate: false,
+            statistic: None,
+        };
+        let results = client
+            .select_timeseries_aggregated(
+                timeseries_name,
+                &[],
+                None,
+                None,
+                aggregation.clone(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2, "Expected one timeseries per project");
+        let mut project_ids = Vec::new();
+        for timeseries in results.iter() {
+            assert_eq!(&timeseries.timeseries_name, timeseries_name);
+            assert_eq!(timeseries.group.len(), 1);
+            assert_eq!(timeseries.group[0].name, "project_id");
+            project_ids.push(timeseries.group[0].value.clone());
+            assert_eq!(timeseries.measurements.len(), 1);
+            assert_eq!(
+                timeseries.measurements[0].datum(),
+                &oximeter::Datum::from(4.0)
+            );
+        }
+        project_ids.dedup();
+        assert_eq!(project_ids.len(), 2);
+
+        // Without any fields to group by, all timeseries are counted together.
+        let results = client
+            .select_timeseries_aggregated(
+                timeseries_name,
+                &[],
+                None,
+                None,
+                query::Aggregation {
+                    reducer: query::Reducer::Count,
+                    group_by: vec![],
+                    ..aggregation
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].group.is_empty());
+        assert_eq!(
+            results[0].measurements[0].datum(),
+            &oximeter::Datum::from(8.0)
+        );
+        db.cleanup().await.expect("Failed to cleanup ClickHouse server");
+    }
+
+    #[tokio::test]
+    async fn test_client_select_histogram_statistic() {
+        let log = slog::Logger::root(slog_dtrace::Dtrace::new().0, o!());
+        let mut db = ClickHouseInstance::new(0)
+            .await
+            .expect("Failed to start ClickHouse");
+        let address = SocketAddr::new("::1".parse().unwrap(), db.port());
+        let client = Client::new(address, &log);
+        client
+            .init_db()
+            .await
+            .expect("Failed to initialize timeseries database");
+
+        // Two timeseries with different histograms, one with samples 1.0, 2.0 and 6.0 and the other
+        // with samples 7.0, 11.0 and 12.0, in bins starting at 0, 5 and 10.
+        let first = test_util::make_hist_sample();
+        let mut hist =
+            oximeter::histogram::Histogram::new(&[0.0, 5.0, 10.0]).unwrap();
+        for value in [7.0, 11.0, 12.0] {
+            hist.sample(value).unwrap();
+        }
+        let second = Sample::new(
+            &test_util::TestTarget::default(),
+            &test_util::TestHistogram {
+                id: Uuid::new_v4(),
+                good: true,
+                datum: hist,
+            },
+        );
+        let samples = vec![first, second];
+        client.insert_samples(&samples).await.unwrap();
+        let timeseries_name = &samples[0].timeseries_name;
+
+        let aggregation = query::Aggregation {
+            reducer: query::Reducer::Sum,
+            group_by: vec![],
+            window_secs: NonZeroU32::new(u32::MAX).unwrap(),
+            rate: false,
+            statistic: Some(query::HistogramStatistic::Mean),
+        };
+        let results = client
+            .select_timeseries_aggregated(
+                timeseries_name,
+                &[],
+                None,
+                None,
+                aggregation.clone(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].measurements.len(), 1);
+        assert_eq!(
+            results[0].measurements[0].datum(),
+            &oximeter::Datum::from(6.5)
+        );
+
+        // Two of the six merged samples are below 5 and two are in `5..10`, so the median is
+        // interpolated halfway through that bin.
+        let results = client
+            .select_timeseries_aggregated(
+                timeseries_name,
+                &[],
+                None,
+                None,
+                query::Aggregation {
+                    statistic: Some(query::HistogramStatistic::Quantile(0.5)),
+                    ..aggregation
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            results[0].measurements[0].datum(),
+            &oximeter::Datum::from(7.5)
+        );
+        db.cleanup().await.expect("Failed to cleanup ClickHouse server");
+    }
+
     #[tokio::test]
     async fn test_field_record_count() {
         // This test verifies that the number of records in the field tables is as expected.
@@ -1456,6 +2224,54 @@ mod tests {
         db.cleanup().await.expect("Failed to cleanup database");
     }
 
+    #[tokio::test]
+    async fn test_apply_retention_policy() {
+        let (mut db, client, _) = setup_filter_testcase().await;
+        assert!(client.retention_policy().await.unwrap().is_none());
+
+        let mut policy = RetentionPolicy::new(NonZeroU32::new(30).unwrap());
+        policy.overrides.insert(
+            TimeseriesName::try_from("service:request_latency").unwrap(),
+            NonZeroU32::new(7).unwrap(),
+        );
+        client.apply_retention_policy(&policy).await.unwrap();
+        assert_eq!(
+            client.retention_policy().await.unwrap(),
+            Some(policy.clone())
+        );
+
+        // The TTL should be set on every measurement table.
+        let body = client
+            .execute_with_body(format!(
+                concat!(
+                    "SELECT count() FROM system.tables ",
+                    "WHERE database = '{}' ",
+                    "AND name LIKE 'measurements_%' ",
+                    "AND create_table_query LIKE '%TTL%' ",
+                    "FORMAT CSV;",
+                ),
+                crate::DATABASE_NAME,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(body.trim(), "9");
+
+        // Applying the same policy again is a no-op, while a new one replaces it.
+        client.apply_retention_policy(&policy).await.unwrap();
+        let policy = RetentionPolicy::new(NonZeroU32::new(90).unwrap());
+        client.apply_retention_policy(&policy).await.unwrap();
+        assert_eq!(client.retention_policy().await.unwrap(), Some(policy));
+        let body = client
+            .execute_with_body(format!(
+                "SELECT count() FROM {}.retention_policy FORMAT CSV;",
+                crate::DATABASE_NAME,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(body.trim(), "2");
+        db.cleanup().await.expect("Failed to cleanup database");
+    }
+
     #[tokio::test]
     async fn test_timeseries_schema_list() {
         use std::convert::TryInto;
@@ -79,11 +79,17 @@ CREATE TABLE IF NOT EXISTS oximeter.measurements_histogrami64
     start_time DateTime64(9, 'UTC'),
     timestamp DateTime64(9, 'UTC'),
     bins Array(Int64),
-    counts Array(UInt64)
+    counts Array(UInt64),
+    sum Nullable(Float64),
+    sum_of_squares Nullable(Float64)
 )
 ENGINE = MergeTree()
 ORDER BY (timeseries_name, timeseries_key, start_time, timestamp);
 --
+ALTER TABLE oximeter.measurements_histogrami64
+    ADD COLUMN IF NOT EXISTS sum Nullable(Float64) AFTER counts,
+    ADD COLUMN IF NOT EXISTS sum_of_squares Nullable(Float64) AFTER sum;
+--
 CREATE TABLE IF NOT EXISTS oximeter.measurements_histogramf64
 (
     timeseries_name String,
@@ -91,11 +97,17 @@ CREATE TABLE IF NOT EXISTS oximeter.measurements_histogramf64
     start_time DateTime64(9, 'UTC'),
     timestamp DateTime64(9, 'UTC'),
     bins Array(Float64),
-    counts Array(UInt64)
+    counts Array(UInt64),
+    sum Nullable(Float64),
+    sum_of_squares Nullable(Float64)
 )
 ENGINE = MergeTree()
 ORDER BY (timeseries_name, timeseries_key, start_time, timestamp);
 --
+ALTER TABLE oximeter.measurements_histogramf64
+    ADD COLUMN IF NOT EXISTS sum Nullable(Float64) AFTER counts,
+    ADD COLUMN IF NOT EXISTS sum_of_squares Nullable(Float64) AFTER sum;
+--
 CREATE TABLE IF NOT EXISTS oximeter.fields_bool
 (
     timeseries_name String,
@@ -149,6 +161,7 @@ ORDER BY (timeseries_name, field_name, field_value, timeseries_key);
 CREATE TABLE IF NOT EXISTS oximeter.timeseries_schema
 (
     timeseries_name String,
+    version UInt8 DEFAULT 1,
     fields Nested(
         name String,
         type Enum(
@@ -178,3 +191,14 @@ CREATE TABLE IF NOT EXISTS oximeter.timeseries_schema
 )
 ENGINE = MergeTree()
 ORDER BY (timeseries_name, fields.name);
+--
+ALTER TABLE oximeter.timeseries_schema
+    ADD COLUMN IF NOT EXISTS version UInt8 DEFAULT 1 AFTER timeseries_name;
+--
+CREATE TABLE IF NOT EXISTS oximeter.retention_policy
+(
+    policy String,
+    applied DateTime64(9, 'UTC')
+)
+ENGINE = MergeTree()
+ORDER BY applied;
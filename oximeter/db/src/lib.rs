@@ -14,12 +14,13 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::convert::TryFrom;
-use std::num::NonZeroU32;
+use std::num::{NonZeroU32, NonZeroU8};
 use thiserror::Error;
 
 mod client;
 pub mod model;
 pub mod query;
+pub mod retention;
 pub use client::{Client, DbWrite};
 
 #[derive(Clone, Debug, Error)]
@@ -79,6 +80,18 @@ pub enum Error {
 
     #[error("Invalid timeseries name")]
     InvalidTimeseriesName,
+
+    #[error("Unknown reducer '{0}'")]
+    UnknownReducer(String),
+
+    #[error("Unknown histogram statistic '{0}'")]
+    UnknownStatistic(String),
+
+    #[error("Cannot aggregate timeseries '{timeseries_name}': {reason}")]
+    InvalidAggregation { timeseries_name: String, reason: String },
+
+    #[error("Histograms from timeseries '{0}' have different bins, and cannot be merged")]
+    HistogramBinMismatch(String),
 }
 
 /// A timeseries name.
@@ -171,9 +184,15 @@ fn validate_timeseries_name(s: &str) -> Result<&str, Error> {
 ///
 /// This includes the name of the timeseries, as well as the datum type of its metric and the
 /// schema for each field.
+///
+/// The fields of a timeseries may change over time, as its target or metric are modified. Each
+/// distinct set of fields is recorded as a new version of the schema. Versions may add or remove
+/// fields, but a field's type can't change, nor can the datum type of the timeseries.
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct TimeseriesSchema {
     pub timeseries_name: TimeseriesName,
+    /// The version of the schema, starting from 1.
+    pub version: NonZeroU8,
     pub field_schema: Vec<FieldSchema>,
     pub datum_type: DatumType,
     pub created: DateTime<Utc>,
@@ -194,8 +213,25 @@ impl TimeseriesSchema {
             .split_once(':')
             .expect("Incorrectly formatted timseries name")
     }
+
+    /// Return true if this schema can be another version of the same timeseries as `other`.
+    ///
+    /// The schema must have the same datum type, and any field they share must have the same
+    /// type and source.
+    pub fn is_compatible_with(&self, other: &TimeseriesSchema) -> bool {
+        self.timeseries_name == other.timeseries_name
+            && self.datum_type == other.datum_type
+            && self.field_schema.iter().all(|field| {
+                other
+                    .field_schema(&field.name)
+                    .map(|other| other == field)
+                    .unwrap_or(true)
+            })
+    }
 }
 
+// Two schema are equal if they describe the same timeseries, regardless of their version or when
+// they were created.
 impl PartialEq for TimeseriesSchema {
     fn eq(&self, other: &TimeseriesSchema) -> bool {
         self.timeseries_name == other.timeseries_name
@@ -211,6 +247,7 @@ impl From<model::DbTimeseriesSchema> for TimeseriesSchema {
                 schema.timeseries_name.as_str(),
             )
             .expect("Invalid timeseries name in database"),
+            version: schema.version,
             field_schema: schema.field_schema.into(),
             datum_type: schema.datum_type.into(),
             created: schema.created,
@@ -234,6 +271,9 @@ pub struct Metric {
 }
 
 /// A list of timestamped measurements from a single timeseries.
+///
+/// The target and metric only have the fields of the version of the schema with which the
+/// timeseries was recorded. Fields from other versions are missing.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Timeseries {
     pub timeseries_name: String,
@@ -242,6 +282,18 @@ pub struct Timeseries {
     pub measurements: Vec<Measurement>,
 }
 
+/// A list of timestamped measurements aggregated from one or more timeseries.
+///
+/// See [`query::Aggregation`] for how the measurements are computed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AggregatedTimeseries {
+    pub timeseries_name: String,
+    /// The values of the fields by which timeseries were grouped, which are shared by all
+    /// timeseries in this aggregate.
+    pub group: Vec<Field>,
+    pub measurements: Vec<Measurement>,
+}
+
 /// The source from which a field is derived, the target or metric.
 #[derive(
     Clone,
@@ -21,6 +21,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::net::{IpAddr, Ipv6Addr};
+use std::num::NonZeroU8;
 use uuid::Uuid;
 
 // Wrapper type to represent a boolean in the database.
@@ -120,6 +121,7 @@ impl From<Vec<FieldSchema>> for DbFieldList {
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub(crate) struct DbTimeseriesSchema {
     pub timeseries_name: String,
+    pub version: NonZeroU8,
     #[serde(flatten)]
     pub field_schema: DbFieldList,
     pub datum_type: DbDatumType,
@@ -131,6 +133,7 @@ impl From<TimeseriesSchema> for DbTimeseriesSchema {
     fn from(schema: TimeseriesSchema) -> DbTimeseriesSchema {
         DbTimeseriesSchema {
             timeseries_name: schema.timeseries_name.to_string(),
+            version: schema.version,
             field_schema: schema.field_schema.into(),
             datum_type: schema.datum_type.into(),
             created: schema.created,
@@ -138,6 +141,15 @@ impl From<TimeseriesSchema> for DbTimeseriesSchema {
     }
 }
 
+// The `DbRetentionPolicy` type models the `oximeter.retention_policy` table, which records each
+// retention policy applied to the measurement tables. The policy itself is stored as JSON.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct DbRetentionPolicy {
+    pub policy: String,
+    #[serde(with = "serde_timestamp")]
+    pub applied: DateTime<Utc>,
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
 pub enum DbFieldType {
     String,
@@ -333,12 +345,15 @@ declare_cumulative_measurement_row! { CumulativeF64MeasurementRow, f64, "cumulat
 // Representation of a histogram in ClickHouse.
 //
 // The tables storing measurements of a histogram metric use a pair of arrays to represent them,
-// for the bins and counts, respectively. This handles conversion between the type used to
-// represent histograms in Rust, [`Histogram`], and this in-database representation.
+// for the bins and counts, respectively, along with the sum and sum of squares of the samples.
+// Those are NULL for rows inserted before they were stored. This handles conversion between the
+// type used to represent histograms in Rust, [`Histogram`], and this in-database representation.
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 struct DbHistogram<T> {
     pub bins: Vec<T>,
     pub counts: Vec<u64>,
+    pub sum: Option<f64>,
+    pub sum_of_squares: Option<f64>,
 }
 
 impl<T> From<&Histogram<T>> for DbHistogram<T>
@@ -347,7 +362,12 @@ where
 {
     fn from(hist: &Histogram<T>) -> Self {
         let (bins, counts) = hist.to_arrays();
-        Self { bins, counts }
+        Self {
+            bins,
+            counts,
+            sum: hist.sum(),
+            sum_of_squares: hist.sum_of_squares(),
+        }
     }
 }
 
@@ -561,6 +581,9 @@ pub(crate) fn unroll_measurement_row(sample: &Sample) -> (String, String) {
 }
 
 /// Return the schema for a `Sample`.
+///
+/// The schema always has version 1. Its actual version is assigned when the client records it in
+/// the database.
 pub(crate) fn schema_for(sample: &Sample) -> TimeseriesSchema {
     let created = Utc::now();
     let field_schema = sample
@@ -582,6 +605,7 @@ pub(crate) fn schema_for(sample: &Sample) -> TimeseriesSchema {
             sample.timeseries_name.as_str(),
         )
         .expect("Failed to parse timeseries name"),
+        version: NonZeroU8::new(1).unwrap(),
         field_schema,
         datum_type: sample.measurement.datum_type(),
         created,
@@ -616,6 +640,7 @@ where
             target, metric,
         ))
         .expect("Failed to parse timeseries name"),
+        version: NonZeroU8::new(1).unwrap(),
         field_schema,
         datum_type: metric.datum_type(),
         created: Utc::now(),
@@ -653,6 +678,8 @@ struct DbTimeseriesHistogramSample<T> {
     timestamp: DateTime<Utc>,
     bins: Vec<T>,
     counts: Vec<u64>,
+    sum: Option<f64>,
+    sum_of_squares: Option<f64>,
 }
 
 impl<T> From<DbTimeseriesScalarGaugeSample<T>> for Measurement
@@ -685,10 +712,12 @@ where
 {
     fn from(sample: DbTimeseriesHistogramSample<T>) -> Measurement {
         let datum = Datum::from(
-            Histogram::from_arrays(
+            Histogram::from_parts(
                 sample.start_time,
                 sample.bins,
                 sample.counts,
+                sample.sum,
+                sample.sum_of_squares,
             )
             .unwrap(),
         );
@@ -770,6 +799,83 @@ pub(crate) fn parse_measurement_from_row(
     }
 }
 
+// A scalar measurement aggregated from one or more timeseries, as extracted from a query to the
+// database.
+#[derive(Debug, Clone, Deserialize)]
+struct DbAggregatedScalarSample {
+    group_id: u64,
+    #[serde(with = "serde_timestamp")]
+    timestamp: DateTime<Utc>,
+    datum: f64,
+}
+
+// A histogram merged from one or more timeseries, as extracted from a query to the database.
+//
+// `n_bin_layouts` is the number of distinct sets of bins among the merged histograms, which can
+// only be merged if that's 1.
+#[derive(Debug, Clone, Deserialize)]
+struct DbAggregatedHistogramSample<T> {
+    group_id: u64,
+    #[serde(with = "serde_timestamp")]
+    start_time: DateTime<Utc>,
+    #[serde(with = "serde_timestamp")]
+    timestamp: DateTime<Utc>,
+    bins: Vec<T>,
+    counts: Vec<u64>,
+    sum: Option<f64>,
+    sum_of_squares: Option<f64>,
+    n_bin_layouts: u64,
+}
+
+fn parse_aggregated_histogram_measurement<T>(
+    line: &str,
+) -> Option<(u64, Measurement)>
+where
+    T: Into<Datum> + traits::HistogramSupport,
+    Datum: From<Histogram<T>>,
+{
+    let sample =
+        serde_json::from_str::<DbAggregatedHistogramSample<T>>(line).unwrap();
+    if sample.n_bin_layouts > 1 {
+        return None;
+    }
+    let datum = Datum::from(
+        Histogram::from_parts(
+            sample.start_time,
+            sample.bins,
+            sample.counts,
+            sample.sum,
+            sample.sum_of_squares,
+        )
+        .unwrap(),
+    );
+    Some((sample.group_id, Measurement::new(sample.timestamp, datum)))
+}
+
+// Parse a line of JSON from the database resulting from `aggregated_measurement_query`, into an
+// aggregated measurement. Also returns the group ID from the line.
+//
+// `None` is returned if the line is a histogram merged from histograms with different bins.
+pub(crate) fn parse_aggregated_measurement_from_row(
+    line: &str,
+    datum_type: DatumType,
+) -> Option<(u64, Measurement)> {
+    match datum_type {
+        DatumType::HistogramI64 => {
+            parse_aggregated_histogram_measurement::<i64>(line)
+        }
+        DatumType::HistogramF64 => {
+            parse_aggregated_histogram_measurement::<f64>(line)
+        }
+        _ => {
+            let sample =
+                serde_json::from_str::<DbAggregatedScalarSample>(line).unwrap();
+            let datum = Datum::from(sample.datum);
+            Some((sample.group_id, Measurement::new(sample.timestamp, datum)))
+        }
+    }
+}
+
 // A single row from a query selecting timeseries with matching fields.
 //
 // This is used during querying for timeseries. Given a list of criteria on a timeseries's fields,
@@ -990,10 +1096,12 @@ mod tests {
         assert_eq!(table_name, "oximeter.measurements_histogramf64");
         let unpacked: HistogramF64MeasurementRow =
             serde_json::from_str(&row).unwrap();
-        let unpacked_hist = Histogram::from_arrays(
+        let unpacked_hist = Histogram::from_parts(
             unpacked.start_time,
             unpacked.datum.bins,
             unpacked.datum.counts,
+            unpacked.datum.sum,
+            unpacked.datum.sum_of_squares,
         )
         .unwrap();
         let measurement = &sample.measurement;
@@ -1100,7 +1208,7 @@ mod tests {
             .with_nanosecond(123_456_789)
             .unwrap();
 
-        let line = r#"{"timeseries_key": 12, "start_time": "2021-01-01 00:00:00.123456789", "timestamp": "2021-01-01 01:00:00.123456789", "bins": [0, 1], "counts": [1, 1] }"#;
+        let line = r#"{"timeseries_key": 12, "start_time": "2021-01-01 00:00:00.123456789", "timestamp": "2021-01-01 01:00:00.123456789", "bins": [0, 1], "counts": [1, 1], "sum": 1.0, "sum_of_squares": 1.0 }"#;
         let (key, measurement) =
             parse_measurement_from_row(line, DatumType::HistogramI64);
         assert_eq!(key, 12);
@@ -1109,11 +1217,60 @@ mod tests {
         if let Datum::HistogramI64(hist) = measurement.datum() {
             assert_eq!(hist.n_bins(), 3);
             assert_eq!(hist.n_samples(), 2);
+            assert_eq!(hist.mean(), Some(0.5));
+        } else {
+            panic!("Expected a histogram sample");
+        }
+
+        // Rows inserted before the sums of the samples were stored have no mean.
+        let line = r#"{"timeseries_key": 12, "start_time": "2021-01-01 00:00:00.123456789", "timestamp": "2021-01-01 01:00:00.123456789", "bins": [0, 1], "counts": [1, 1], "sum": null, "sum_of_squares": null }"#;
+        let (_, measurement) =
+            parse_measurement_from_row(line, DatumType::HistogramI64);
+        if let Datum::HistogramI64(hist) = measurement.datum() {
+            assert_eq!(hist.n_samples(), 2);
+            assert_eq!(hist.mean(), None);
         } else {
             panic!("Expected a histogram sample");
         }
     }
 
+    #[test]
+    fn test_parse_aggregated_measurement() {
+        use chrono::TimeZone;
+        let timestamp = Utc.with_ymd_and_hms(2021, 1, 1, 1, 0, 0).unwrap();
+
+        let line = r#"{"group_id": 3, "timestamp": "2021-01-01 01:00:00.000000000", "datum": 2.5 }"#;
+        let (group_id, measurement) =
+            parse_aggregated_measurement_from_row(line, DatumType::I64)
+                .unwrap();
+        assert_eq!(group_id, 3);
+        assert!(measurement.start_time().is_none());
+        assert_eq!(measurement.timestamp(), timestamp);
+        assert_eq!(measurement.datum(), &Datum::from(2.5));
+
+        let line = r#"{"group_id": 1, "start_time": "2021-01-01 00:00:00.123456789", "timestamp": "2021-01-01 01:00:00.000000000", "bins": [0, 1], "counts": [1, 3], "sum": 3.0, "sum_of_squares": 3.0, "n_bin_layouts": 1 }"#;
+        let (group_id, measurement) = parse_aggregated_measurement_from_row(
+            line,
+            DatumType::HistogramI64,
+        )
+        .unwrap();
+        assert_eq!(group_id, 1);
+        assert_eq!(measurement.timestamp(), timestamp);
+        if let Datum::HistogramI64(hist) = measurement.datum() {
+            assert_eq!(hist.n_samples(), 4);
+            assert_eq!(hist.mean(), Some(0.75));
+        } else {
+            panic!("Expected a histogram sample");
+        }
+
+        let line = r#"{"group_id": 1, "start_time": "2021-01-01 00:00:00.123456789", "timestamp": "2021-01-01 01:00:00.000000000", "bins": [0, 1], "counts": [1, 3], "sum": 3.0, "sum_of_squares": 3.0, "n_bin_layouts": 2 }"#;
+        assert!(parse_aggregated_measurement_from_row(
+            line,
+            DatumType::HistogramI64
+        )
+        .is_none());
+    }
+
     #[test]
     fn test_parse_string_datum_requiring_escape() {
         let line = "{\"timeseries_key\": 0, \"timestamp\": \"2021-01-01 01:00:00.123456789\", \"datum\": \"\\/some\\/path\"}";
@@ -1141,8 +1298,14 @@ mod tests {
         );
         assert_eq!(counts, &[0, 1, 1, 0], "Paired-array counts are incorrect");
 
-        let rebuilt =
-            Histogram::from_arrays(hist.start_time(), bins, counts).unwrap();
+        let rebuilt = Histogram::from_parts(
+            hist.start_time(),
+            bins,
+            counts,
+            hist.sum(),
+            hist.sum_of_squares(),
+        )
+        .unwrap();
         assert_eq!(
             hist, rebuilt,
             "Histogram reconstructed from paired arrays is not correct"
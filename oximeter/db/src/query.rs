@@ -10,6 +10,7 @@ use crate::{
     DATABASE_NAME, DATABASE_SELECT_FORMAT,
 };
 use chrono::{DateTime, Utc};
+use oximeter::histogram::{Histogram, HistogramSupport};
 use oximeter::types::{DatumType, FieldType, FieldValue};
 use oximeter::{Metric, Target};
 use regex::Regex;
@@ -37,6 +38,7 @@ pub struct SelectQueryBuilder {
     time_range: TimeRange,
     limit: Option<NonZeroU32>,
     offset: Option<u32>,
+    aggregation: Option<Aggregation>,
 }
 
 impl SelectQueryBuilder {
@@ -48,6 +50,7 @@ impl SelectQueryBuilder {
             time_range: TimeRange { start: None, end: None },
             limit: None,
             offset: None,
+            aggregation: None,
         }
     }
 
@@ -75,6 +78,71 @@ impl SelectQueryBuilder {
         self
     }
 
+    /// Aggregate the selected timeseries in the database, rather than returning their raw
+    /// measurements.
+    ///
+    /// An error is returned if a field to group by is not part of the timeseries, or if the
+    /// aggregation can't be applied to the timeseries's datum type. See [`Aggregation`] for
+    /// details.
+    pub fn aggregate(
+        mut self,
+        aggregation: Aggregation,
+    ) -> Result<Self, Error> {
+        let timeseries_name = &self.timeseries_schema.timeseries_name;
+        for field_name in aggregation.group_by.iter() {
+            if self.timeseries_schema.field_schema(field_name).is_none() {
+                return Err(Error::NoSuchField {
+                    timeseries_name: timeseries_name.to_string(),
+                    field_name: field_name.clone(),
+                });
+            }
+        }
+        let invalid = |reason: &str| Error::InvalidAggregation {
+            timeseries_name: timeseries_name.to_string(),
+            reason: reason.to_string(),
+        };
+        let is_histogram = matches!(
+            self.timeseries_schema.datum_type,
+            DatumType::HistogramI64 | DatumType::HistogramF64
+        );
+        match aggregation.statistic {
+            Some(_) if !is_histogram => {
+                return Err(invalid(
+                    "statistics can only be computed for histograms",
+                ));
+            }
+            Some(HistogramStatistic::Quantile(q))
+                if !(0.0..=1.0).contains(&q) =>
+            {
+                return Err(invalid("quantiles must be in the range [0, 1]"));
+            }
+            _ => {}
+        }
+        match self.timeseries_schema.datum_type {
+            DatumType::Bool | DatumType::String | DatumType::Bytes => {
+                return Err(invalid("only numeric data can be aggregated"));
+            }
+            DatumType::I64 | DatumType::F64 if aggregation.rate => {
+                return Err(invalid(
+                    "rates can only be computed for cumulative data",
+                ));
+            }
+            DatumType::HistogramI64 | DatumType::HistogramF64 => {
+                if aggregation.rate {
+                    return Err(invalid(
+                        "rates can't be computed for histograms",
+                    ));
+                }
+                if aggregation.reducer != Reducer::Sum {
+                    return Err(invalid("histograms can only be summed"));
+                }
+            }
+            _ => {}
+        }
+        self.aggregation.replace(aggregation);
+        Ok(self)
+    }
+
     /// Add a filter for a field with the given name, comparison operator, and value.
     ///
     /// An error is returned if the field cannot be found or the field value is not of the correct
@@ -253,11 +321,12 @@ impl SelectQueryBuilder {
             time_range: self.time_range,
             limit: self.limit,
             offset: self.offset,
+            aggregation: self.aggregation,
         }
     }
 }
 
-fn measurement_table_name(ty: DatumType) -> String {
+pub(crate) fn measurement_table_name(ty: DatumType) -> String {
     format!("measurements_{}", ty.to_string().to_lowercase())
 }
 
@@ -432,6 +501,130 @@ impl fmt::Display for FieldCmp {
     }
 }
 
+/// A function used to combine values when aggregating timeseries.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Reducer {
+    Sum,
+    Mean,
+    Min,
+    Max,
+    Count,
+}
+
+impl FromStr for Reducer {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sum" => Ok(Reducer::Sum),
+            "mean" => Ok(Reducer::Mean),
+            "min" => Ok(Reducer::Min),
+            "max" => Ok(Reducer::Max),
+            "count" => Ok(Reducer::Count),
+            _ => Err(Error::UnknownReducer(s.to_string())),
+        }
+    }
+}
+
+impl Reducer {
+    // Return the name of the ClickHouse aggregate function implementing this reducer.
+    fn as_db_str(&self) -> &'static str {
+        match self {
+            Reducer::Sum => "sum",
+            Reducer::Mean => "avg",
+            Reducer::Min => "min",
+            Reducer::Max => "max",
+            Reducer::Count => "count",
+        }
+    }
+}
+
+/// A summary statistic computed from histograms.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum HistogramStatistic {
+    Mean,
+    Variance,
+    StdDev,
+    /// The quantile with the given value, in `[0, 1]`.
+    Quantile(f64),
+}
+
+impl FromStr for HistogramStatistic {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mean" => Ok(HistogramStatistic::Mean),
+            "variance" => Ok(HistogramStatistic::Variance),
+            "std_dev" => Ok(HistogramStatistic::StdDev),
+            // Quantiles are given as percentiles, e.g., `p99` or `p99.9`.
+            _ => s
+                .strip_prefix('p')
+                .and_then(|p| p.parse::<f64>().ok())
+                .filter(|p| (0.0..=100.0).contains(p))
+                .map(|p| HistogramStatistic::Quantile(p / 100.0))
+                .ok_or_else(|| Error::UnknownStatistic(s.to_string())),
+        }
+    }
+}
+
+impl HistogramStatistic {
+    /// Compute this statistic from a histogram.
+    ///
+    /// `None` is returned if the histogram has no samples, for a quantile outside of `[0, 1]`, or
+    /// for a moment of a histogram whose sums of samples are unknown.
+    pub fn evaluate<T>(&self, histogram: &Histogram<T>) -> Option<f64>
+    where
+        T: HistogramSupport,
+    {
+        match self {
+            HistogramStatistic::Mean => histogram.mean(),
+            HistogramStatistic::Variance => histogram.variance(),
+            HistogramStatistic::StdDev => histogram.std_dev(),
+            HistogramStatistic::Quantile(q) => {
+                histogram.quantile(*q).ok().flatten()
+            }
+        }
+    }
+}
+
+/// Describes how the timeseries selected by a query are aggregated in the database.
+///
+/// Measurements are bucketed into windows of `window_secs` seconds, aligned to the Unix epoch,
+/// and each timeseries is first reduced to a single value per window:
+///
+/// - Gauges are averaged over the window.
+/// - Cumulative scalars take their last value in the window or, if `rate` is set, the average
+///   per-second rate of change between the first and last samples in the window. Windows with
+///   fewer than two samples of a timeseries have no rate for it, and a counter which is reset
+///   within a window isn't accounted for.
+/// - Histograms take their last value in the window.
+///
+/// The values of all timeseries with the same values of the `group_by` fields are then combined
+/// with `reducer`, giving one aggregated timeseries per group. The `count` reducer counts the
+/// timeseries with data in each window. Histograms can only be summed, which merges them by adding
+/// the counts in each bin, and the sums and sums of squares of their samples. Timeseries recorded
+/// with a version of the schema without one of the `group_by` fields are grouped by the others.
+///
+/// If a `statistic` is given, each merged histogram is then summarized by it, using the same
+/// methods as [`Histogram`]. Windows whose merged histogram has no samples are omitted.
+///
+/// Scalar results, including histogram statistics, are always reported as `F64` data, stamped with
+/// the start of their window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Aggregation {
+    pub reducer: Reducer,
+    #[serde(default)]
+    pub group_by: Vec<String>,
+    pub window_secs: NonZeroU32,
+    #[serde(default)]
+    pub rate: bool,
+    #[serde(default)]
+    pub statistic: Option<HistogramStatistic>,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct TimeRange {
     pub start: Option<Timestamp>,
@@ -479,6 +672,7 @@ pub struct SelectQuery {
     time_range: TimeRange,
     limit: Option<NonZeroU32>,
     offset: Option<u32>,
+    aggregation: Option<Aggregation>,
 }
 
 fn create_join_on_condition(columns: &[&str], current: usize) -> String {
@@ -516,6 +710,11 @@ impl SelectQuery {
         &self.timeseries_schema
     }
 
+    /// Return the aggregation applied by the query, if any.
+    pub fn aggregation(&self) -> Option<&Aggregation> {
+        self.aggregation.as_ref()
+    }
+
     pub fn field_selector<S>(
         &self,
         source: FieldSource,
@@ -594,6 +793,17 @@ impl SelectQuery {
         }
     }
 
+    fn pagination_clause(&self) -> String {
+        let mut clause = String::new();
+        if let Some(limit) = self.limit {
+            clause.push_str(&format!("LIMIT {} ", limit));
+        }
+        if let Some(offset) = self.offset {
+            clause.push_str(&format!("OFFSET {} ", offset));
+        };
+        clause
+    }
+
     /// Construct and return the query used to select the measurements, using the associated
     /// timeseries keys. If no keys are specified, then a query selecting the all timeseries with
     /// the given name will be returned. (This is probably not what you want.)
@@ -610,16 +820,6 @@ impl SelectQuery {
                     .join(", "),
             )
         };
-        let pagination_clause = {
-            let mut clause = String::new();
-            if let Some(limit) = self.limit {
-                clause.push_str(&format!("LIMIT {} ", limit));
-            }
-            if let Some(offset) = self.offset {
-                clause.push_str(&format!("OFFSET {} ", offset));
-            };
-            clause
-        };
         format!(
             concat!(
                 "SELECT * ",
@@ -638,10 +838,123 @@ impl SelectQuery {
             timeseries_name = self.timeseries_schema.timeseries_name,
             key_clause = key_clause,
             timestamp_clause = self.time_range.as_query(),
-            pagination_clause = pagination_clause,
+            pagination_clause = self.pagination_clause(),
             fmt = DATABASE_SELECT_FORMAT,
         )
     }
+
+    /// Construct and return the query used to aggregate the measurements of the timeseries with
+    /// the given keys, or `None` if the query has no aggregation.
+    ///
+    /// `groups` maps each timeseries key to the ID of the group it's aggregated into, which is
+    /// returned in the `group_id` column of each result row. The limit and offset apply to the
+    /// aggregated rows, which are ordered by group ID and then by time.
+    pub fn aggregated_measurement_query(
+        &self,
+        groups: &BTreeMap<TimeseriesKey, u64>,
+    ) -> Option<String> {
+        let aggregation = self.aggregation.as_ref()?;
+        let datum_type = self.timeseries_schema.datum_type;
+
+        // Reduce each timeseries to one value per window...
+        let (series_columns, series_having) = match datum_type {
+            DatumType::HistogramI64 | DatumType::HistogramF64 => (
+                String::from(concat!(
+                    "min(start_time) AS start_time, ",
+                    "argMax(bins, timestamp) AS bins, ",
+                    "argMax(counts, timestamp) AS counts, ",
+                    // `argMax` skips NULLs, so wrap the sums in a tuple to take
+                    // them from the latest sample even when they're unknown.
+                    "tupleElement(argMax(tuple(sum), timestamp), 1) AS sum, ",
+                    "tupleElement(argMax(tuple(sum_of_squares), timestamp), 1) AS sum_of_squares",
+                )),
+                "",
+            ),
+            _ if aggregation.rate => (
+                String::from(concat!(
+                    "toFloat64((argMax(datum, timestamp) - argMin(datum, timestamp)) / ",
+                    "((max(toUnixTimestamp64Nano(timestamp)) - ",
+                    "min(toUnixTimestamp64Nano(timestamp))) / 1e9)) AS value",
+                )),
+                "HAVING max(timestamp) > min(timestamp) ",
+            ),
+            _ if datum_type.is_cumulative() => {
+                (String::from("toFloat64(argMax(datum, timestamp)) AS value"), "")
+            }
+            _ => (String::from("toFloat64(avg(datum)) AS value"), ""),
+        };
+
+        // ... then combine the values of the timeseries in each group.
+        let group_columns = match datum_type {
+            DatumType::HistogramI64 | DatumType::HistogramF64 => {
+                String::from(concat!(
+                    "min(start_time) AS start_time, ",
+                    "any(bins) AS bins, ",
+                    "sumForEach(counts) AS counts, ",
+                    // The sums are unknown if they're unknown for any of the
+                    // histograms being merged.
+                    "if(count(sum) = count(), sum(sum), NULL) AS sum, ",
+                    "if(count(sum_of_squares) = count(), sum(sum_of_squares), NULL) ",
+                    "AS sum_of_squares, ",
+                    "length(groupUniqArray(bins)) AS n_bin_layouts",
+                ))
+            }
+            _ => format!(
+                "toFloat64({reducer}(value)) AS datum",
+                reducer = aggregation.reducer.as_db_str(),
+            ),
+        };
+        let (keys, group_ids): (Vec<_>, Vec<_>) = groups
+            .iter()
+            .map(|(key, group_id)| (key.to_string(), group_id.to_string()))
+            .unzip();
+        let keys = keys.join(", ");
+        Some(format!(
+            concat!(
+                "SELECT ",
+                "transform(timeseries_key, CAST([{keys}] AS Array(UInt64)), ",
+                "CAST([{group_ids}] AS Array(UInt64)), toUInt64(0)) AS group_id, ",
+                "window_start AS timestamp, ",
+                "{group_columns} ",
+                "FROM (",
+                "SELECT ",
+                "timeseries_key, ",
+                "toDateTime64(toStartOfInterval(timestamp, INTERVAL {window_secs} SECOND), 9, 'UTC') AS window_start, ",
+                "{series_columns} ",
+                "FROM {db_name}.{table_name} ",
+                "WHERE ",
+                "timeseries_name = '{timeseries_name}' ",
+                "AND timeseries_key IN ({keys})",
+                "{timestamp_clause}",
+                "GROUP BY (timeseries_key, window_start) ",
+                "{series_having}",
+                ") ",
+                "GROUP BY (group_id, window_start) ",
+                "ORDER BY (group_id, window_start) ",
+                "{pagination_clause}",
+                "FORMAT {fmt};",
+            ),
+            keys = keys,
+            group_ids = group_ids.join(", "),
+            group_columns = group_columns,
+            window_secs = aggregation.window_secs,
+            series_columns = series_columns,
+            db_name = DATABASE_NAME,
+            table_name = measurement_table_name(datum_type),
+            timeseries_name = self.timeseries_schema.timeseries_name,
+            timestamp_clause = {
+                let clause = self.time_range.as_query();
+                if clause.is_empty() {
+                    String::from(" ")
+                } else {
+                    clause
+                }
+            },
+            series_having = series_having,
+            pagination_clause = self.pagination_clause(),
+            fmt = DATABASE_SELECT_FORMAT,
+        ))
+    }
 }
 
 // Format the value for use in a query to the database, e.g., `... WHERE field_value = {}`.
@@ -671,6 +984,7 @@ mod tests {
     use crate::TimeseriesName;
     use chrono::TimeZone;
     use std::convert::TryFrom;
+    use std::num::NonZeroU8;
 
     #[test]
     fn test_field_value_as_db_str() {
@@ -723,6 +1037,7 @@ mod tests {
     fn test_select_query_builder_filter_raw() {
         let schema = TimeseriesSchema {
             timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
             field_schema: vec![
                 FieldSchema {
                     name: "f0".to_string(),
@@ -850,6 +1165,7 @@ mod tests {
     fn test_select_query_builder_no_fields() {
         let schema = TimeseriesSchema {
             timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
             field_schema: vec![],
             datum_type: DatumType::I64,
             created: Utc::now(),
@@ -872,6 +1188,7 @@ mod tests {
     fn test_select_query_builder_limit_offset() {
         let schema = TimeseriesSchema {
             timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
             field_schema: vec![],
             datum_type: DatumType::I64,
             created: Utc::now(),
@@ -941,6 +1258,7 @@ mod tests {
     fn test_select_query_builder_no_selectors() {
         let schema = TimeseriesSchema {
             timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
             field_schema: vec![
                 FieldSchema {
                     name: "f0".to_string(),
@@ -1002,6 +1320,7 @@ mod tests {
     fn test_select_query_builder_field_selectors() {
         let schema = TimeseriesSchema {
             timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
             field_schema: vec![
                 FieldSchema {
                     name: "f0".to_string(),
@@ -1051,6 +1370,7 @@ mod tests {
     fn test_select_query_builder_full() {
         let schema = TimeseriesSchema {
             timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
             field_schema: vec![
                 FieldSchema {
                     name: "f0".to_string(),
@@ -1120,4 +1440,234 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_select_query_builder_aggregate() {
+        let schema = |datum_type| TimeseriesSchema {
+            timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
+            field_schema: vec![FieldSchema {
+                name: "f0".to_string(),
+                ty: FieldType::I64,
+                source: FieldSource::Target,
+            }],
+            datum_type,
+            created: Utc::now(),
+        };
+        let aggregation = Aggregation {
+            reducer: Reducer::Sum,
+            group_by: vec!["f0".to_string()],
+            window_secs: NonZeroU32::try_from(60).unwrap(),
+            rate: false,
+            statistic: None,
+        };
+
+        let query = SelectQueryBuilder::new(&schema(DatumType::F64))
+            .aggregate(aggregation.clone())
+            .expect("Failed to aggregate a gauge")
+            .build();
+        assert_eq!(query.aggregation(), Some(&aggregation));
+
+        assert!(matches!(
+            SelectQueryBuilder::new(&schema(DatumType::F64)).aggregate(
+                Aggregation {
+                    group_by: vec!["f1".to_string()],
+                    ..aggregation.clone()
+                }
+            ),
+            Err(Error::NoSuchField { .. })
+        ));
+        assert!(matches!(
+            SelectQueryBuilder::new(&schema(DatumType::String))
+                .aggregate(aggregation.clone()),
+            Err(Error::InvalidAggregation { .. })
+        ));
+        assert!(matches!(
+            SelectQueryBuilder::new(&schema(DatumType::I64))
+                .aggregate(Aggregation { rate: true, ..aggregation.clone() }),
+            Err(Error::InvalidAggregation { .. })
+        ));
+        assert!(SelectQueryBuilder::new(&schema(DatumType::CumulativeI64))
+            .aggregate(Aggregation { rate: true, ..aggregation.clone() })
+            .is_ok());
+        assert!(SelectQueryBuilder::new(&schema(DatumType::HistogramF64))
+            .aggregate(aggregation.clone())
+            .is_ok());
+        assert!(matches!(
+            SelectQueryBuilder::new(&schema(DatumType::HistogramF64))
+                .aggregate(Aggregation {
+                    reducer: Reducer::Max,
+                    ..aggregation.clone()
+                }),
+            Err(Error::InvalidAggregation { .. })
+        ));
+        assert!(SelectQueryBuilder::new(&schema(DatumType::HistogramI64))
+            .aggregate(Aggregation {
+                statistic: Some(HistogramStatistic::Quantile(0.99)),
+                ..aggregation.clone()
+            })
+            .is_ok());
+        assert!(matches!(
+            SelectQueryBuilder::new(&schema(DatumType::HistogramI64))
+                .aggregate(Aggregation {
+                    statistic: Some(HistogramStatistic::Quantile(1.5)),
+                    ..aggregation.clone()
+                }),
+            Err(Error::InvalidAggregation { .. })
+        ));
+        assert!(matches!(
+            SelectQueryBuilder::new(&schema(DatumType::F64)).aggregate(
+                Aggregation {
+                    statistic: Some(HistogramStatistic::Mean),
+                    ..aggregation.clone()
+                }
+            ),
+            Err(Error::InvalidAggregation { .. })
+        ));
+    }
+
+    #[test]
+    fn test_histogram_statistic() {
+        assert_eq!(
+            "mean".parse::<HistogramStatistic>().unwrap(),
+            HistogramStatistic::Mean
+        );
+        assert_eq!(
+            "std_dev".parse::<HistogramStatistic>().unwrap(),
+            HistogramStatistic::StdDev
+        );
+        assert_eq!(
+            "p50".parse::<HistogramStatistic>().unwrap(),
+            HistogramStatistic::Quantile(0.5)
+        );
+        assert_eq!(
+            "p99".parse::<HistogramStatistic>().unwrap(),
+            HistogramStatistic::Quantile(0.99)
+        );
+        assert!("p101".parse::<HistogramStatistic>().is_err());
+        assert!("median".parse::<HistogramStatistic>().is_err());
+
+        let mut histogram = Histogram::new(&[0, 10]).unwrap();
+        assert_eq!(HistogramStatistic::Mean.evaluate(&histogram), None);
+        histogram.sample(2).unwrap();
+        histogram.sample(4).unwrap();
+        assert_eq!(HistogramStatistic::Mean.evaluate(&histogram), Some(3.0));
+        assert_eq!(
+            HistogramStatistic::Variance.evaluate(&histogram),
+            Some(1.0)
+        );
+        assert_eq!(
+            HistogramStatistic::Quantile(0.5).evaluate(&histogram),
+            Some(5.0)
+        );
+    }
+
+    #[test]
+    fn test_aggregated_measurement_query() {
+        let schema = |datum_type| TimeseriesSchema {
+            timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
+            field_schema: vec![FieldSchema {
+                name: "f0".to_string(),
+                ty: FieldType::I64,
+                source: FieldSource::Target,
+            }],
+            datum_type,
+            created: Utc::now(),
+        };
+        let groups = [(7, 0), (8, 1), (9, 0)].into_iter().collect();
+
+        let query = SelectQueryBuilder::new(&schema(DatumType::I64)).build();
+        assert!(query.aggregated_measurement_query(&groups).is_none());
+
+        let start_time = Utc::now();
+        let query = SelectQueryBuilder::new(&schema(DatumType::CumulativeF64))
+            .start_time(Some(Timestamp::Inclusive(start_time)))
+            .limit(NonZeroU32::try_from(10).unwrap())
+            .aggregate(Aggregation {
+                reducer: Reducer::Mean,
+                group_by: vec!["f0".to_string()],
+                window_secs: NonZeroU32::try_from(300).unwrap(),
+                rate: true,
+                statistic: None,
+            })
+            .unwrap()
+            .build();
+        assert_eq!(
+            query.aggregated_measurement_query(&groups).unwrap(),
+            format!(
+                concat!(
+                    "SELECT ",
+                    "transform(timeseries_key, CAST([7, 8, 9] AS Array(UInt64)), ",
+                    "CAST([0, 1, 0] AS Array(UInt64)), toUInt64(0)) AS group_id, ",
+                    "window_start AS timestamp, ",
+                    "toFloat64(avg(value)) AS datum ",
+                    "FROM (",
+                    "SELECT ",
+                    "timeseries_key, ",
+                    "toDateTime64(toStartOfInterval(timestamp, INTERVAL 300 SECOND), 9, 'UTC') AS window_start, ",
+                    "toFloat64((argMax(datum, timestamp) - argMin(datum, timestamp)) / ",
+                    "((max(toUnixTimestamp64Nano(timestamp)) - ",
+                    "min(toUnixTimestamp64Nano(timestamp))) / 1e9)) AS value ",
+                    "FROM oximeter.measurements_cumulativef64 ",
+                    "WHERE timeseries_name = 'foo:bar' ",
+                    "AND timeseries_key IN (7, 8, 9) ",
+                    "AND timestamp >= '{start_time}' ",
+                    "GROUP BY (timeseries_key, window_start) ",
+                    "HAVING max(timestamp) > min(timestamp) ",
+                    ") ",
+                    "GROUP BY (group_id, window_start) ",
+                    "ORDER BY (group_id, window_start) ",
+                    "LIMIT 10 ",
+                    "FORMAT JSONEachRow;",
+                ),
+                start_time =
+                    start_time.format(crate::DATABASE_TIMESTAMP_FORMAT),
+            )
+        );
+
+        let query = SelectQueryBuilder::new(&schema(DatumType::HistogramI64))
+            .aggregate(Aggregation {
+                reducer: Reducer::Sum,
+                group_by: vec![],
+                window_secs: NonZeroU32::try_from(60).unwrap(),
+                rate: false,
+                statistic: None,
+            })
+            .unwrap()
+            .build();
+        assert_eq!(
+            query.aggregated_measurement_query(&groups).unwrap(),
+            concat!(
+                "SELECT ",
+                "transform(timeseries_key, CAST([7, 8, 9] AS Array(UInt64)), ",
+                "CAST([0, 1, 0] AS Array(UInt64)), toUInt64(0)) AS group_id, ",
+                "window_start AS timestamp, ",
+                "min(start_time) AS start_time, ",
+                "any(bins) AS bins, ",
+                "sumForEach(counts) AS counts, ",
+                "if(count(sum) = count(), sum(sum), NULL) AS sum, ",
+                "if(count(sum_of_squares) = count(), sum(sum_of_squares), NULL) ",
+                "AS sum_of_squares, ",
+                "length(groupUniqArray(bins)) AS n_bin_layouts ",
+                "FROM (",
+                "SELECT ",
+                "timeseries_key, ",
+                "toDateTime64(toStartOfInterval(timestamp, INTERVAL 60 SECOND), 9, 'UTC') AS window_start, ",
+                "min(start_time) AS start_time, ",
+                "argMax(bins, timestamp) AS bins, ",
+                "argMax(counts, timestamp) AS counts, ",
+                "tupleElement(argMax(tuple(sum), timestamp), 1) AS sum, ",
+                "tupleElement(argMax(tuple(sum_of_squares), timestamp), 1) AS sum_of_squares ",
+                "FROM oximeter.measurements_histogrami64 ",
+                "WHERE timeseries_name = 'foo:bar' ",
+                "AND timeseries_key IN (7, 8, 9) ",
+                "GROUP BY (timeseries_key, window_start) ",
+                ") ",
+                "GROUP BY (group_id, window_start) ",
+                "ORDER BY (group_id, window_start) ",
+                "FORMAT JSONEachRow;",
+            )
+        );
+    }
 }
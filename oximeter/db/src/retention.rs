@@ -0,0 +1,174 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Retention policies for the measurements in the telemetry database.
+//!
+//! Measurements are deleted by ClickHouse itself, using a `TTL` clause on each
+//! measurement table. The clause is derived from a [`RetentionPolicy`], which
+//! has a default retention period and optional per-timeseries overrides.
+//!
+//! Only measurements are deleted. The field tables hold a single row per
+//! timeseries and field, so they stay small even for long-lived timeseries.
+//!
+//! TODO-completeness: Coarse rollups of measurements that outlive the raw
+//! retention period are not yet implemented.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::TimeseriesName;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::num::NonZeroU32;
+
+/// How long measurements are kept in the database.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RetentionPolicy {
+    /// The number of days measurements are kept, unless overridden for their
+    /// timeseries.
+    pub default_days: NonZeroU32,
+    /// The number of days measurements are kept for specific timeseries.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_overrides",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub overrides: BTreeMap<TimeseriesName, NonZeroU32>,
+}
+
+impl RetentionPolicy {
+    /// Construct a policy that keeps all measurements for `default_days`.
+    pub fn new(default_days: NonZeroU32) -> Self {
+        Self { default_days, overrides: BTreeMap::new() }
+    }
+
+    /// Return the number of days measurements from a timeseries are kept.
+    pub fn days_for(&self, timeseries_name: &TimeseriesName) -> NonZeroU32 {
+        self.overrides
+            .get(timeseries_name)
+            .copied()
+            .unwrap_or(self.default_days)
+    }
+
+    /// Return the `TTL` expression that enforces this policy on a
+    /// measurement table.
+    ///
+    /// Timeseries names are validated when the policy is constructed or
+    /// deserialized, so they're safe to quote directly into the expression.
+    pub fn ttl_expression(&self) -> String {
+        let expiry = |days: NonZeroU32| {
+            format!("toDateTime(timestamp) + INTERVAL {} DAY", days)
+        };
+        if self.overrides.is_empty() {
+            return expiry(self.default_days);
+        }
+        let mut rules = self
+            .overrides
+            .iter()
+            .map(|(name, days)| {
+                format!(
+                    "{} DELETE WHERE timeseries_name = '{}'",
+                    expiry(*days),
+                    name
+                )
+            })
+            .collect::<Vec<_>>();
+        rules.push(format!(
+            "{} DELETE WHERE timeseries_name NOT IN ({})",
+            expiry(self.default_days),
+            self.overrides
+                .keys()
+                .map(|name| format!("'{}'", name))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ));
+        rules.join(", ")
+    }
+}
+
+// Timeseries names borrow from the input when deserialized, which not all
+// formats support for map keys, so the overrides are parsed from owned strings.
+fn deserialize_overrides<'de, D>(
+    deserializer: D,
+) -> Result<BTreeMap<TimeseriesName, NonZeroU32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    BTreeMap::<String, NonZeroU32>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(name, days)| {
+            TimeseriesName::try_from(name.as_str())
+                .map(|name| (name, days))
+                .map_err(|_| {
+                    D::Error::custom(format!(
+                        "invalid timeseries name '{}'",
+                        name
+                    ))
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ttl_expression_default_only() {
+        let policy = RetentionPolicy::new(NonZeroU32::new(30).unwrap());
+        assert_eq!(
+            policy.ttl_expression(),
+            "toDateTime(timestamp) + INTERVAL 30 DAY"
+        );
+    }
+
+    #[test]
+    fn test_ttl_expression_with_overrides() {
+        let mut policy = RetentionPolicy::new(NonZeroU32::new(30).unwrap());
+        let queries = TimeseriesName::try_from("dns_server:queries").unwrap();
+        let latency = TimeseriesName::try_from("service:latency").unwrap();
+        policy.overrides.insert(queries.clone(), NonZeroU32::new(7).unwrap());
+        policy.overrides.insert(latency, NonZeroU32::new(90).unwrap());
+        assert_eq!(
+            policy.ttl_expression(),
+            concat!(
+                "toDateTime(timestamp) + INTERVAL 7 DAY ",
+                "DELETE WHERE timeseries_name = 'dns_server:queries', ",
+                "toDateTime(timestamp) + INTERVAL 90 DAY ",
+                "DELETE WHERE timeseries_name = 'service:latency', ",
+                "toDateTime(timestamp) + INTERVAL 30 DAY ",
+                "DELETE WHERE timeseries_name NOT IN ",
+                "('dns_server:queries', 'service:latency')",
+            )
+        );
+        assert_eq!(policy.days_for(&queries).get(), 7);
+        assert_eq!(
+            policy
+                .days_for(&TimeseriesName::try_from("foo:bar").unwrap())
+                .get(),
+            30
+        );
+    }
+
+    #[test]
+    fn test_deserialize_retention_policy() {
+        let policy: RetentionPolicy = serde_json::from_str(
+            r#"{"default_days": 14, "overrides": {"foo:bar": 2}}"#,
+        )
+        .unwrap();
+        assert_eq!(policy.default_days.get(), 14);
+        assert_eq!(policy.overrides.len(), 1);
+
+        // Names are validated, which keeps them from being used to inject SQL.
+        assert!(serde_json::from_str::<RetentionPolicy>(
+            r#"{"default_days": 14, "overrides": {"foo:bar' OR 1": 2}}"#,
+        )
+        .is_err());
+        assert!(serde_json::from_str::<RetentionPolicy>(
+            r#"{"default_days": 0}"#,
+        )
+        .is_err());
+    }
+}
@@ -26,6 +26,7 @@ pub trait HistogramSupport:
     + Clone
     + num_traits::Zero
     + num_traits::One
+    + num_traits::ToPrimitive
     + 'static
 {
     fn is_finite(&self) -> bool;
@@ -43,7 +44,7 @@ impl HistogramSupport for f64 {
     }
 }
 
-/// Errors related to constructing histograms or adding samples into them.
+/// Errors related to constructing histograms, adding samples into them, or summarizing them.
 #[derive(Debug, Clone, Error, JsonSchema, Serialize, Deserialize)]
 #[serde(tag = "type", content = "content", rename_all = "snake_case")]
 pub enum HistogramError {
@@ -66,6 +67,20 @@ pub enum HistogramError {
     /// Bin and count arrays are of different sizes.
     #[error("Bin and count arrays must have the same size, found {n_bins} and {n_counts}")]
     ArraySizeMismatch { n_bins: usize, n_counts: usize },
+
+    /// An attempt to merge histograms with different bins.
+    #[error("Histograms with different bins cannot be merged")]
+    BinMismatch,
+
+    /// A quantile outside of the range `[0, 1]` was requested.
+    #[error("Quantiles must be in the range [0, 1], found: {0}")]
+    InvalidQuantile(f64),
+
+    /// The counts in the bins of a histogram don't add up to its number of samples.
+    #[error(
+        "Histogram has {n_samples} samples, but its bins count {bin_total}"
+    )]
+    SampleCountMismatch { n_samples: u64, bin_total: u64 },
 }
 
 /// A type storing a range over `T`.
@@ -200,6 +215,10 @@ pub struct Bin<T> {
 ///
 /// Note that any gaps, unsorted bins, or non-finite values will result in an error.
 ///
+/// The histogram also tracks the sum and sum of squares of its samples, so that their mean and
+/// variance are exact, rather than estimated from the bins. Quantiles can only be estimated from
+/// the bins, see [`Histogram::quantile`].
+///
 /// Example
 /// -------
 /// ```rust
@@ -242,6 +261,12 @@ pub struct Histogram<T> {
     start_time: DateTime<Utc>,
     bins: Vec<Bin<T>>,
     n_samples: u64,
+    // The sum and sum of squares of the samples are unknown for histograms from producers, or
+    // stored in the database by versions of the collector, which predate them.
+    #[serde(default)]
+    sum: Option<f64>,
+    #[serde(default)]
+    sum_of_squares: Option<f64>,
 }
 
 impl<T> Histogram<T>
@@ -340,7 +365,13 @@ where
         if let Bound::Excluded(end) = bins_.last().unwrap().range.end_bound() {
             ensure_finite(*end)?;
         }
-        Ok(Self { start_time: Utc::now(), bins: bins_, n_samples: 0 })
+        Ok(Self {
+            start_time: Utc::now(),
+            bins: bins_,
+            n_samples: 0,
+            sum: Some(0.0),
+            sum_of_squares: Some(0.0),
+        })
     }
 
     /// Construct a new histogram from left bin edges.
@@ -385,7 +416,13 @@ where
         if current < <T as Bounded>::max_value() {
             bins.push(Bin { range: BinRange::from(current), count: 0 });
         }
-        Ok(Self { start_time: Utc::now(), bins, n_samples: 0 })
+        Ok(Self {
+            start_time: Utc::now(),
+            bins,
+            n_samples: 0,
+            sum: Some(0.0),
+            sum_of_squares: Some(0.0),
+        })
     }
 
     /// Add a new sample into the histogram.
@@ -400,6 +437,11 @@ where
             .unwrap(); // The `ensure_finite` call above catches values that don't end up in a bin
         self.bins[index].count += 1;
         self.n_samples += 1;
+        let value = value.to_f64().unwrap();
+        self.sum = self.sum.map(|sum| sum + value);
+        self.sum_of_squares = self
+            .sum_of_squares
+            .map(|sum_of_squares| sum_of_squares + value * value);
         Ok(())
     }
 
@@ -408,6 +450,130 @@ where
         self.n_samples
     }
 
+    /// Return the sum of the samples contained in the histogram, if it's known.
+    pub fn sum(&self) -> Option<f64> {
+        self.sum
+    }
+
+    /// Return the sum of the squares of the samples contained in the histogram, if it's known.
+    pub fn sum_of_squares(&self) -> Option<f64> {
+        self.sum_of_squares
+    }
+
+    /// Return the mean of the samples in the histogram, or `None` if there are no samples or
+    /// their sum is unknown.
+    pub fn mean(&self) -> Option<f64> {
+        if self.n_samples == 0 {
+            None
+        } else {
+            Some(self.sum? / self.n_samples as f64)
+        }
+    }
+
+    /// Return the (population) variance of the samples in the histogram, or `None` if there are
+    /// no samples or their sums are unknown.
+    pub fn variance(&self) -> Option<f64> {
+        let mean = self.mean()?;
+        // Rounding can make this slightly negative when the samples are all (nearly) equal.
+        Some(
+            (self.sum_of_squares? / self.n_samples as f64 - mean * mean)
+                .max(0.0),
+        )
+    }
+
+    /// Return the (population) standard deviation of the samples in the histogram, or `None` if
+    /// there are no samples or their sums are unknown.
+    pub fn std_dev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    /// Estimate the `q`-th quantile of the samples in the histogram, where `q` is in `[0, 1]`.
+    ///
+    /// The bin containing the quantile is found from the cumulative counts of the bins, and the
+    /// quantile is linearly interpolated within it, assuming its samples are spread uniformly over
+    /// the bin. The bins at either end of the support have no useful bound on that side, so no
+    /// interpolation is done in them: a quantile in the first bin is estimated as its right edge,
+    /// and one in a bin extending to the end of the support as its left edge.
+    ///
+    /// `None` is returned if there are no samples. An error is returned if `q` is not in `[0, 1]`,
+    /// or if the counts in the bins don't add up to the number of samples.
+    ///
+    /// Example
+    /// -------
+    /// ```rust
+    /// use oximeter::histogram::Histogram;
+    ///
+    /// let mut hist = Histogram::new(&[0.0, 10.0, 20.0]).unwrap();
+    /// for value in [1.0, 2.0, 11.0, 12.0] {
+    ///     hist.sample(value).unwrap();
+    /// }
+    /// assert_eq!(hist.quantile(0.5).unwrap(), Some(10.0));
+    /// assert_eq!(hist.quantile(0.75).unwrap(), Some(15.0));
+    /// assert_eq!(hist.mean(), Some(6.5));
+    /// ```
+    pub fn quantile(&self, q: f64) -> Result<Option<f64>, HistogramError> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(HistogramError::InvalidQuantile(q));
+        }
+        if self.n_samples == 0 {
+            return Ok(None);
+        }
+        let rank = q * self.n_samples as f64;
+        let min = <T as Bounded>::min_value();
+        let mut below = 0;
+        for bin in self.bins.iter().filter(|bin| bin.count > 0) {
+            if ((below + bin.count) as f64) < rank {
+                below += bin.count;
+                continue;
+            }
+            let (start, end) = match bin.range {
+                BinRange::Range { start, end } if start == min => (end, end),
+                BinRange::Range { start, end } => (start, end),
+                BinRange::RangeFrom { start } => (start, start),
+                BinRange::RangeTo { end } => (end, end),
+            };
+            let start = start.to_f64().unwrap();
+            let end = end.to_f64().unwrap();
+            let fraction = (rank - below as f64) / bin.count as f64;
+            return Ok(Some(start + (end - start) * fraction.clamp(0.0, 1.0)));
+        }
+        Err(HistogramError::SampleCountMismatch {
+            n_samples: self.n_samples,
+            bin_total: below,
+        })
+    }
+
+    /// Merge the samples of another histogram into this one.
+    ///
+    /// The histograms must have identical bins, or an error is returned. The start time of the
+    /// merged histogram is the earlier of the two. The sums of the merged samples are unknown if
+    /// they're unknown for either histogram.
+    pub fn merge(
+        &mut self,
+        other: &Histogram<T>,
+    ) -> Result<(), HistogramError> {
+        if self.bins.len() != other.bins.len()
+            || self
+                .bins
+                .iter()
+                .zip(other.bins.iter())
+                .any(|(bin, other)| bin.range != other.range)
+        {
+            return Err(HistogramError::BinMismatch);
+        }
+        for (bin, other) in self.bins.iter_mut().zip(other.bins.iter()) {
+            bin.count += other.count;
+        }
+        self.start_time = self.start_time.min(other.start_time);
+        self.n_samples += other.n_samples;
+        self.sum = self.sum.zip(other.sum).map(|(sum, other)| sum + other);
+        self.sum_of_squares = self
+            .sum_of_squares
+            .zip(other.sum_of_squares)
+            .map(|(sum_of_squares, other)| sum_of_squares + other);
+        Ok(())
+    }
+
     /// Return the number of bins in the histogram.
     pub fn n_bins(&self) -> usize {
         self.bins.len()
@@ -442,10 +608,25 @@ where
     }
 
     /// Construct a histogram from a start time and paired arrays with the left bin-edge and counts.
+    ///
+    /// The sum and sum of squares of the samples can't be recovered from the bins, and are
+    /// unknown. Use [`Histogram::from_parts`] to provide them.
     pub fn from_arrays(
         start_time: DateTime<Utc>,
         bins: Vec<T>,
         counts: Vec<u64>,
+    ) -> Result<Self, HistogramError> {
+        Self::from_parts(start_time, bins, counts, None, None)
+    }
+
+    /// Construct a histogram from a start time, paired arrays with the left bin-edge and counts,
+    /// and the sum and sum of squares of its samples, if they're known.
+    pub fn from_parts(
+        start_time: DateTime<Utc>,
+        bins: Vec<T>,
+        counts: Vec<u64>,
+        sum: Option<f64>,
+        sum_of_squares: Option<f64>,
     ) -> Result<Self, HistogramError> {
         if bins.len() != counts.len() {
             return Err(HistogramError::ArraySizeMismatch {
@@ -461,6 +642,8 @@ where
             n_samples += count;
         }
         hist.n_samples = n_samples;
+        hist.sum = sum;
+        hist.sum_of_squares = sum_of_squares;
         Ok(hist)
     }
 
@@ -824,14 +1007,113 @@ mod tests {
         );
         assert_eq!(counts, &[0, 1, 1, 0], "Paired-array counts are incorrect");
 
-        let rebuilt =
-            Histogram::from_arrays(hist.start_time(), bins, counts).unwrap();
+        let rebuilt = Histogram::from_parts(
+            hist.start_time(),
+            bins,
+            counts,
+            hist.sum(),
+            hist.sum_of_squares(),
+        )
+        .unwrap();
         assert_eq!(
             hist, rebuilt,
             "Histogram reconstructed from paired arrays is not correct"
         );
     }
 
+    #[test]
+    fn test_histogram_mean_and_variance() {
+        let mut hist = Histogram::new(&[0, 10, 20]).unwrap();
+        assert_eq!(hist.mean(), None);
+        assert_eq!(hist.variance(), None);
+        for sample in [2i64, 4, 4, 4, 5, 5, 7, 9] {
+            hist.sample(sample).unwrap();
+        }
+        assert_eq!(hist.sum(), Some(40.0));
+        assert_eq!(hist.sum_of_squares(), Some(232.0));
+        assert_eq!(hist.mean(), Some(5.0));
+        assert_eq!(hist.variance(), Some(4.0));
+        assert_eq!(hist.std_dev(), Some(2.0));
+
+        // Without the sums of the samples, only their count is known.
+        let (bins, counts) = hist.to_arrays();
+        let hist =
+            Histogram::from_arrays(hist.start_time(), bins, counts).unwrap();
+        assert_eq!(hist.n_samples(), 8);
+        assert_eq!(hist.mean(), None);
+        assert_eq!(hist.variance(), None);
+        assert_eq!(hist.std_dev(), None);
+    }
+
+    #[test]
+    fn test_histogram_quantile() {
+        let mut hist = Histogram::new(&[0, 10, 20]).unwrap();
+        assert_eq!(hist.quantile(0.5).unwrap(), None);
+        assert!(hist.quantile(-0.1).is_err());
+        assert!(hist.quantile(1.1).is_err());
+        assert!(hist.quantile(f64::NAN).is_err());
+
+        for sample in [1, 3, 5, 7, 11, 13] {
+            hist.sample(sample).unwrap();
+        }
+        // Four samples are in `0..10`, and two in `10..20`.
+        assert_eq!(hist.quantile(0.0).unwrap(), Some(0.0));
+        assert_eq!(hist.quantile(0.5).unwrap(), Some(7.5));
+        assert_eq!(hist.quantile(0.75).unwrap(), Some(12.5));
+        assert_eq!(hist.quantile(1.0).unwrap(), Some(20.0));
+
+        // No interpolation is done in the bins at the ends of the support.
+        hist.sample(-100).unwrap();
+        hist.sample(1000).unwrap();
+        assert_eq!(hist.quantile(0.0).unwrap(), Some(0.0));
+        assert_eq!(hist.quantile(1.0).unwrap(), Some(20.0));
+
+        // A histogram whose bins don't account for all of its samples can't be summarized.
+        hist.n_samples += 1;
+        assert!(matches!(
+            hist.quantile(1.0),
+            Err(HistogramError::SampleCountMismatch {
+                n_samples: 9,
+                bin_total: 8
+            })
+        ));
+    }
+
+    #[test]
+    fn test_histogram_merge() {
+        let mut hist = Histogram::new(&[0.0, 1.0]).unwrap();
+        hist.sample(0.5).unwrap();
+        let mut other = Histogram::new(&[0.0, 1.0]).unwrap();
+        other.sample(-1.0).unwrap();
+        other.sample(1.5).unwrap();
+        let start_time = hist.start_time();
+        hist.merge(&other).unwrap();
+        assert_eq!(hist.n_samples(), 3);
+        assert_eq!(hist.start_time(), start_time.min(other.start_time()));
+        assert_eq!(
+            hist.iter().map(|bin| bin.count).collect::<Vec<_>>(),
+            &[1, 1, 1]
+        );
+        assert_eq!(hist.sum(), Some(1.0));
+        assert_eq!(hist.sum_of_squares(), Some(3.5));
+
+        let other = Histogram::new(&[0.0, 2.0]).unwrap();
+        assert!(
+            matches!(hist.merge(&other), Err(HistogramError::BinMismatch)),
+            "Histograms with different bins should not be merged"
+        );
+        assert_eq!(hist.n_samples(), 3);
+
+        // Merging in samples whose sums are unknown makes the sums of the merged samples unknown.
+        let (bins, counts) = hist.to_arrays();
+        let other =
+            Histogram::from_arrays(hist.start_time(), bins, counts).unwrap();
+        hist.merge(&other).unwrap();
+        assert_eq!(hist.n_samples(), 6);
+        assert_eq!(hist.sum(), None);
+        assert_eq!(hist.mean(), None);
+    }
+
     #[test]
     fn test_span_decades() {
         let hist = Histogram::span_decades(0i8, 3i8).unwrap();
@@ -104,6 +104,7 @@ async fn main() {
         registration_address: "127.0.0.1:12221".parse().unwrap(),
         dropshot_config,
         logging_config,
+        prometheus: true,
     };
     let server = Server::start(&config).await.unwrap();
     let producer = CpuBusyProducer::new(4);
@@ -10,6 +10,8 @@ use dropshot::{
     endpoint, ApiDescription, ConfigDropshot, ConfigLogging, HttpError,
     HttpResponseOk, HttpServer, HttpServerStarter, Path, RequestContext,
 };
+use http::{header, Response, StatusCode};
+use hyper::Body;
 use omicron_common::api::internal::nexus::ProducerEndpoint;
 use oximeter::types::{ProducerRegistry, ProducerResults};
 use schemars::JsonSchema;
@@ -20,6 +22,8 @@ use std::net::SocketAddr;
 use thiserror::Error;
 use uuid::Uuid;
 
+pub mod prometheus;
+
 #[derive(Debug, Clone, Error)]
 pub enum Error {
     #[error("Error running producer HTTP server: {0}")]
@@ -36,6 +40,9 @@ pub struct Config {
     pub registration_address: SocketAddr,
     pub dropshot_config: ConfigDropshot,
     pub logging_config: ConfigLogging,
+    /// If true, the server also serves the produced samples in the Prometheus text exposition
+    /// format at `/metrics`, so they can be scraped by Prometheus directly.
+    pub prometheus: bool,
 }
 
 /// A Dropshot server used to expose metrics to be collected over the network.
@@ -73,7 +80,7 @@ impl Server {
         let dropshot_log = log.new(o!("component" => "dropshot"));
         let server = HttpServerStarter::new(
             &config.dropshot_config,
-            metric_server_api(),
+            metric_server_api(config.prometheus),
             registry.clone(),
             &dropshot_log,
         )
@@ -138,10 +145,14 @@ impl Server {
 }
 
 // Register API endpoints of the `Server`.
-fn metric_server_api() -> ApiDescription<ProducerRegistry> {
+fn metric_server_api(prometheus: bool) -> ApiDescription<ProducerRegistry> {
     let mut api = ApiDescription::new();
     api.register(collect_endpoint)
         .expect("Failed to register handler for collect_endpoint");
+    if prometheus {
+        api.register(prometheus_endpoint)
+            .expect("Failed to register handler for prometheus_endpoint");
+    }
     api
 }
 
@@ -164,6 +175,18 @@ async fn collect_endpoint(
     collect(registry, producer_id).await
 }
 
+// Serve the samples in the Prometheus text exposition format.
+#[endpoint {
+    method = GET,
+    path = "/metrics",
+    unpublished = true,
+}]
+async fn prometheus_endpoint(
+    request_context: RequestContext<ProducerRegistry>,
+) -> Result<Response<Body>, HttpError> {
+    prometheus_metrics(request_context.context()).await
+}
+
 // TODO this seems misplaced.
 /// Register a metric server to be polled for metric data.
 ///
@@ -201,3 +224,17 @@ pub async fn collect(
         ))
     }
 }
+
+/// Handle a request to scrape the metric data from a [`ProducerRegistry`] in the Prometheus text
+/// exposition format.
+///
+/// See the [`prometheus`] module for how samples are represented.
+pub async fn prometheus_metrics(
+    registry: &ProducerRegistry,
+) -> Result<Response<Body>, HttpError> {
+    let body = prometheus::render_results(&registry.collect());
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, prometheus::CONTENT_TYPE)
+        .body(body.into())?)
+}
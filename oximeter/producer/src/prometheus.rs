@@ -0,0 +1,236 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Rendering samples in the Prometheus text exposition format.
+//!
+//! Each timeseries becomes a Prometheus metric named by its timeseries name, with the `:` replaced
+//! by `_`, and labeled with its target and metric fields. Scalar data are exported as gauges,
+//! cumulative data as counters (with a `_total` suffix), and histograms as Prometheus histograms.
+//! Strings and bytes have no Prometheus equivalent, and are skipped.
+//!
+//! Oximeter bins exclude their upper edge, while Prometheus buckets include it, so samples falling
+//! exactly on the edge of a floating-point bin are counted in the next bucket up.
+
+// Copyright 2023 Oxide Computer Company
+
+use oximeter::histogram::{BinRange, Histogram, HistogramSupport};
+use oximeter::types::{Datum, ProducerResults, ProducerResultsItem, Sample};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// The content type of the Prometheus text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// The samples of one Prometheus metric, which all share its type.
+#[derive(Debug, Default)]
+struct Family {
+    kind: &'static str,
+    lines: Vec<String>,
+}
+
+/// Render the samples from a set of producer results, skipping any errors.
+pub fn render_results(results: &ProducerResults) -> String {
+    render(results.iter().flat_map(|item| match item {
+        ProducerResultsItem::Ok(samples) => samples.as_slice(),
+        ProducerResultsItem::Err(_) => &[],
+    }))
+}
+
+/// Render samples in the Prometheus text exposition format.
+pub fn render<'a>(samples: impl IntoIterator<Item = &'a Sample>) -> String {
+    let mut families: BTreeMap<String, Family> = BTreeMap::new();
+    for sample in samples {
+        let name = sample.timeseries_name.replace(':', "_");
+        let labels = sample
+            .target_fields()
+            .iter()
+            .chain(sample.metric_fields().iter())
+            .map(|field| {
+                format!(
+                    "{}=\"{}\"",
+                    field.name,
+                    escape(&field.value.to_string())
+                )
+            })
+            .collect::<Vec<_>>();
+        let (family_name, kind, lines) = match sample.measurement.datum() {
+            Datum::Bool(x) => {
+                let lines = vec![line(&name, &labels, u8::from(*x))];
+                (name, "gauge", lines)
+            }
+            Datum::I64(x) => {
+                let lines = vec![line(&name, &labels, x)];
+                (name, "gauge", lines)
+            }
+            Datum::F64(x) => {
+                let lines = vec![line(&name, &labels, float(*x))];
+                (name, "gauge", lines)
+            }
+            Datum::String(_) | Datum::Bytes(_) => continue,
+            Datum::CumulativeI64(x) => {
+                let name = format!("{}_total", name);
+                let lines = vec![line(&name, &labels, x.value())];
+                (name, "counter", lines)
+            }
+            Datum::CumulativeF64(x) => {
+                let name = format!("{}_total", name);
+                let lines = vec![line(&name, &labels, float(x.value()))];
+                (name, "counter", lines)
+            }
+            // Integer bins exclude their upper edge, so the largest value they contain is one less.
+            Datum::HistogramI64(x) => {
+                let lines =
+                    histogram(&name, &labels, x, |end| (end - 1).to_string());
+                (name, "histogram", lines)
+            }
+            Datum::HistogramF64(x) => {
+                let lines = histogram(&name, &labels, x, float);
+                (name, "histogram", lines)
+            }
+        };
+        let family = families.entry(family_name).or_default();
+        family.kind = kind;
+        family.lines.extend(lines);
+    }
+
+    let mut out = String::new();
+    for (name, family) in families {
+        writeln!(out, "# TYPE {} {}", name, family.kind).unwrap();
+        for line in family.lines {
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn histogram<T: HistogramSupport>(
+    name: &str,
+    labels: &[String],
+    histogram: &Histogram<T>,
+    upper_bound: impl Fn(T) -> String,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    let bucket = format!("{}_bucket", name);
+    let mut count = 0;
+    for bin in histogram.iter() {
+        count += bin.count;
+        let le = match bin.range {
+            BinRange::RangeTo { end } | BinRange::Range { end, .. } => {
+                upper_bound(end)
+            }
+            BinRange::RangeFrom { .. } => String::from("+Inf"),
+        };
+        let mut labels = labels.to_vec();
+        labels.push(format!("le=\"{}\"", le));
+        lines.push(line(&bucket, &labels, count));
+    }
+    // The last bin is bounded above if it ends at the largest value of the support, but Prometheus
+    // requires a `+Inf` bucket.
+    if !matches!(
+        histogram.iter().last().map(|bin| bin.range),
+        Some(BinRange::RangeFrom { .. })
+    ) {
+        let mut labels = labels.to_vec();
+        labels.push(String::from("le=\"+Inf\""));
+        lines.push(line(&bucket, &labels, count));
+    }
+    if let Some(sum) = histogram.sum() {
+        lines.push(line(&format!("{}_sum", name), labels, float(sum)));
+    }
+    lines.push(line(&format!("{}_count", name), labels, count));
+    lines
+}
+
+fn line(
+    name: &str,
+    labels: &[String],
+    value: impl std::fmt::Display,
+) -> String {
+    if labels.is_empty() {
+        format!("{} {}", name, value)
+    } else {
+        format!("{}{{{}}} {}", name, labels.join(","), value)
+    }
+}
+
+// Format a float the way Prometheus expects, which differs from Rust for infinities.
+fn float(x: f64) -> String {
+    if x == f64::INFINITY {
+        String::from("+Inf")
+    } else if x == f64::NEG_INFINITY {
+        String::from("-Inf")
+    } else {
+        x.to_string()
+    }
+}
+
+// Escape a label value.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oximeter::types::Cumulative;
+    use oximeter::{Metric, Target};
+
+    #[derive(Target)]
+    struct Server {
+        name: String,
+    }
+
+    #[derive(Metric)]
+    struct Requests {
+        route: String,
+        #[datum]
+        count: Cumulative<i64>,
+    }
+
+    #[derive(Metric)]
+    struct Load {
+        #[datum]
+        value: f64,
+    }
+
+    #[derive(Metric)]
+    struct Latency {
+        #[datum]
+        latency: Histogram<i64>,
+    }
+
+    #[test]
+    fn test_render() {
+        let target = Server { name: String::from("a \"quoted\"\nname") };
+        let requests =
+            Requests { route: String::from("/"), count: Cumulative::new(3) };
+        let load = Load { value: 0.5 };
+        let mut latency =
+            Latency { latency: Histogram::new(&[0, 10]).unwrap() };
+        latency.latency.sample(-1).unwrap();
+        latency.latency.sample(5).unwrap();
+        latency.latency.sample(50).unwrap();
+        let samples = [
+            Sample::new(&target, &requests),
+            Sample::new(&target, &load),
+            Sample::new(&target, &latency),
+        ];
+
+        let labels = "name=\"a \\\"quoted\\\"\\nname\"";
+        let expected = [
+            String::from("# TYPE server_latency histogram"),
+            format!("server_latency_bucket{{{},le=\"-1\"}} 1", labels),
+            format!("server_latency_bucket{{{},le=\"9\"}} 2", labels),
+            format!("server_latency_bucket{{{},le=\"+Inf\"}} 3", labels),
+            format!("server_latency_sum{{{}}} 54", labels),
+            format!("server_latency_count{{{}}} 3", labels),
+            String::from("# TYPE server_load gauge"),
+            format!("server_load{{{}}} 0.5", labels),
+            String::from("# TYPE server_requests_total counter"),
+            format!("server_requests_total{{{},route=\"/\"}} 3", labels),
+        ];
+        assert_eq!(render(&samples), expected.join("\n") + "\n");
+    }
+}
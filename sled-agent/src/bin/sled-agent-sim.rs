@@ -46,6 +46,13 @@ struct Args {
 
     #[clap(name = "NEXUS_IP:PORT", action)]
     nexus_addr: SocketAddr,
+
+    #[clap(
+        long = "prometheus",
+        action,
+        help = "Serve simulated disks' metrics in the Prometheus format"
+    )]
+    prometheus: bool,
 }
 
 #[tokio::main]
@@ -73,6 +80,7 @@ async fn do_run() -> Result<(), CmdError> {
             zpools: vec![ConfigZpool { size: 1 << 40 }; 10],
             ip: (*args.sled_agent_addr.ip()).into(),
         },
+        prometheus: args.prometheus,
     };
 
     run_server(&config).await.map_err(CmdError::Failure)
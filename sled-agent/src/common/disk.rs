@@ -128,7 +128,7 @@ impl DiskStates {
         match self.current.disk_state {
             // Currently attached - only legal to attach to current ID
             // (which is a no-op anyway).
-            DiskState::Attaching(id) | DiskState::Attached(id) => {
+            DiskState::Attached(id) => {
                 if uuid != id {
                     return Err(Error::InvalidRequest {
                         message: "disk is already attached".to_string(),
@@ -136,6 +136,24 @@ impl DiskStates {
                 }
                 return Ok(None);
             }
+            // Currently attaching to the same ID - this happens when Nexus
+            // records that a disk is being hot-plugged before asking us to
+            // attach it, so continue on through attaching.
+            DiskState::Attaching(id) => {
+                if uuid != id {
+                    return Err(Error::InvalidRequest {
+                        message: "disk is already attached".to_string(),
+                    });
+                }
+                if self.desired.is_some() {
+                    return Ok(None);
+                }
+                self.transition(
+                    DiskState::Attaching(uuid),
+                    Some(DiskStateRequested::Attached(uuid)),
+                );
+                return Ok(Some(Action::Attach(uuid)));
+            }
             // Not attached - enter attached through attaching.
             DiskState::Creating | DiskState::Detached => {
                 self.transition(
@@ -120,6 +120,15 @@ impl InstanceStates {
             .as_ref()
             .and_then(|s| get_next_desired_state(&observed, s.run_state));
 
+        // Once the target of a migration is running, the migration is over.
+        if self.current.run_state == State::Migrating
+            && current == State::Running
+            && self.is_migration_target()
+        {
+            self.current.migration_id = None;
+            self.current.dst_propolis_id = None;
+        }
+
         self.transition(current, desired);
 
         // Most commands to update Propolis are triggered via requests (from
@@ -151,6 +160,12 @@ impl InstanceStates {
         }
     }
 
+    // Returns true if this is the Propolis server to which the instance is
+    // being migrated, rather than the one from which it's being migrated.
+    pub(crate) fn is_migration_target(&self) -> bool {
+        self.current.dst_propolis_id == Some(self.current.propolis_id)
+    }
+
     // Transitions to a new InstanceState value, updating the timestamp and
     // generation number.
     //
@@ -172,9 +187,18 @@ impl InstanceStates {
     fn request_running(&mut self) -> Result<Option<Action>, Error> {
         match self.current.run_state {
             // Early exit: Running request is no-op
-            InstanceState::Running
-            | InstanceState::Rebooting
-            | InstanceState::Migrating => return Ok(None),
+            InstanceState::Running | InstanceState::Rebooting => {
+                return Ok(None)
+            }
+            // Asking the source of a migration to keep running abandons the
+            // migration: the instance stays where it is.
+            InstanceState::Migrating if !self.is_migration_target() => {
+                self.current.migration_id = None;
+                self.current.dst_propolis_id = None;
+                self.transition(InstanceState::Running, None);
+                return Ok(None);
+            }
+            InstanceState::Migrating => return Ok(None),
             // Valid states for a running request
             InstanceState::Creating
             | InstanceState::Starting
@@ -779,4 +803,55 @@ mod test {
                 if internal_message.contains("migrating but no migration id present")
         );
     }
+
+    #[test]
+    fn test_running_from_migrating_source_abandons_migration() {
+        let mut instance = make_instance();
+        instance.current_mut().run_state = State::Running;
+
+        assert_matches!(
+            instance.request_transition(&migrating_req()),
+            Ok(None)
+        );
+        verify_state(&instance, State::Migrating, Some(Requested::Running));
+
+        // Asking the source to keep running leaves the instance running where
+        // it is, with no trace of the migration.
+        assert_matches!(
+            instance.request_transition(&runtime_state(Requested::Running)),
+            Ok(None)
+        );
+        verify_state(&instance, State::Running, None);
+        assert_eq!(None, instance.current().migration_id);
+        assert_eq!(None, instance.current().dst_propolis_id);
+    }
+
+    #[test]
+    fn test_migration_target_finishes_running() {
+        let mut instance = make_instance();
+        let migrating_req = migrating_req();
+        let params = migrating_req.migration_params.unwrap();
+
+        // The target of a migration is created with the propolis ID that the
+        // source was told to migrate to.
+        let current = instance.current_mut();
+        current.run_state = State::Migrating;
+        current.propolis_id = params.dst_propolis_id;
+        current.dst_propolis_id = Some(params.dst_propolis_id);
+        current.migration_id = Some(params.migration_id);
+
+        // Asking the target to keep running doesn't abandon the migration.
+        assert_matches!(
+            instance.request_transition(&runtime_state(Requested::Running)),
+            Ok(None)
+        );
+        verify_state(&instance, State::Migrating, None);
+        assert_eq!(Some(params.migration_id), instance.current().migration_id);
+
+        // Once it's running, the migration is over.
+        assert_eq!(None, instance.observe_transition(&Observed::Running));
+        verify_state(&instance, State::Running, None);
+        assert_eq!(None, instance.current().migration_id);
+        assert_eq!(None, instance.current().dst_propolis_id);
+    }
 }
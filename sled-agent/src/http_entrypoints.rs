@@ -6,7 +6,8 @@
 
 use crate::params::{
     DatasetEnsureBody, DiskEnsureBody, InstanceEnsureBody,
-    InstanceSerialConsoleData, InstanceSerialConsoleRequest, ServiceEnsureBody,
+    InstanceExternalIpsEnsureBody, InstanceSerialConsoleData,
+    InstanceSerialConsoleRequest, ServiceEnsureBody,
     VpcFirewallRulesEnsureBody, Zpool,
 };
 use crate::serial::ByteOffset;
@@ -33,6 +34,7 @@ pub fn api() -> SledApiDescription {
         api.register(zpools_get)?;
         api.register(filesystem_put)?;
         api.register(instance_put)?;
+        api.register(instance_external_ips_put)?;
         api.register(disk_put)?;
         api.register(update_artifact)?;
         api.register(instance_serial_get)?;
@@ -124,6 +126,23 @@ async fn instance_put(
     ))
 }
 
+#[endpoint {
+    method = PUT,
+    path = "/instances/{instance_id}/external-ips",
+}]
+async fn instance_external_ips_put(
+    rqctx: RequestContext<SledAgent>,
+    path_params: Path<InstancePathParam>,
+    body: TypedBody<InstanceExternalIpsEnsureBody>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let sa = rqctx.context();
+    let instance_id = path_params.into_inner().instance_id;
+    let body_args = body.into_inner();
+    sa.instance_external_ips_ensure(instance_id, body_args.external_ips)
+        .await?;
+    Ok(HttpResponseUpdatedNoContent())
+}
+
 /// Path parameters for Disk requests (sled agent API)
 #[derive(Deserialize, JsonSchema)]
 struct DiskPathParam {
@@ -94,6 +94,9 @@ pub enum Error {
 
     #[error("Instance {0} not running!")]
     InstanceNotRunning(Uuid),
+
+    #[error("Cannot modify the external IP addresses of running instance {0}")]
+    ExternalIpsModificationUnsupported(Uuid),
 }
 
 // Issues read-only, idempotent HTTP requests at propolis until it responds with
@@ -454,6 +457,10 @@ mockall::mock! {
             disk_id: Uuid,
             snapshot_name: Uuid,
         ) -> Result<(), Error>;
+        pub async fn external_ips_ensure(
+            &self,
+            external_ips: Vec<IpAddr>,
+        ) -> Result<(), Error>;
     }
     impl Clone for Instance {
         fn clone(&self) -> Self;
@@ -864,6 +871,32 @@ impl Instance {
             Err(Error::InstanceNotRunning(inner.properties.id))
         }
     }
+
+    /// Replaces the external IP addresses provided to this instance.
+    ///
+    /// The new addresses are used the next time the instance's OPTE ports are
+    /// created.
+    pub async fn external_ips_ensure(
+        &self,
+        external_ips: Vec<IpAddr>,
+    ) -> Result<(), Error> {
+        let mut inner = self.inner.lock().await;
+        if inner.external_ips == external_ips {
+            return Ok(());
+        }
+
+        // TODO-completeness: OPTE does not yet provide a way to change the
+        // external addresses of an existing port, so changes to a running
+        // instance can't be applied until the port can be updated in place.
+        // See https://github.com/oxidecomputer/opte/issues/196
+        if inner.running_state.is_some() {
+            return Err(Error::ExternalIpsModificationUnsupported(
+                inner.properties.id,
+            ));
+        }
+        inner.external_ips = external_ips;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -17,7 +17,7 @@ use macaddr::MacAddr6;
 use omicron_common::api::internal::nexus::InstanceRuntimeState;
 use slog::Logger;
 use std::collections::BTreeMap;
-use std::net::Ipv6Addr;
+use std::net::{IpAddr, Ipv6Addr};
 use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 
@@ -213,6 +213,22 @@ impl InstanceManager {
             .map_err(Error::from)
     }
 
+    pub async fn instance_external_ips_ensure(
+        &self,
+        instance_id: Uuid,
+        external_ips: Vec<IpAddr>,
+    ) -> Result<(), Error> {
+        let instance = {
+            let instances = self.inner.instances.lock().unwrap();
+            let (_, instance) = instances
+                .get(&instance_id)
+                .ok_or(Error::NoSuchInstance(instance_id))?;
+            instance.clone()
+        };
+
+        instance.external_ips_ensure(external_ips).await.map_err(Error::from)
+    }
+
     pub async fn firewall_rules_ensure(
         &self,
         rules: &[VpcFirewallRule],
@@ -108,6 +108,14 @@ pub struct InstanceHardware {
     pub cloud_init_bytes: Option<String>,
 }
 
+/// Sent to a sled agent to replace the external IP addresses of an Instance
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct InstanceExternalIpsEnsureBody {
+    /// Zero or more external IP addresses (either floating or ephemeral),
+    /// provided to an instance to allow inbound connectivity.
+    pub external_ips: Vec<IpAddr>,
+}
+
 /// Sent to a sled agent to establish the runtime state of an Instance
 #[derive(Serialize, Deserialize, JsonSchema)]
 pub struct InstanceEnsureBody {
@@ -56,4 +56,8 @@ pub struct Config {
     pub log: ConfigLogging,
     /// configuration for the sled agent's storage
     pub storage: ConfigStorage,
+    /// whether the metric producer servers of simulated disks also serve
+    /// metrics at `/metrics`, in the Prometheus text exposition format
+    #[serde(default)]
+    pub prometheus: bool,
 }
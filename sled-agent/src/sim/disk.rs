@@ -158,6 +158,7 @@ impl SimDisk {
         &mut self,
         nexus_address: SocketAddr,
         id: Uuid,
+        prometheus: bool,
     ) -> Result<(), String> {
         // Set up a producer server.
         //
@@ -180,6 +181,7 @@ impl SimDisk {
             logging_config: ConfigLogging::StderrTerminal {
                 level: ConfigLoggingLevel::Error,
             },
+            prometheus,
         };
         let server =
             ProducerServer::start(&config).await.map_err(|e| e.to_string())?;
@@ -198,7 +200,7 @@ impl SimDisk {
 impl Simulatable for SimDisk {
     type CurrentState = DiskRuntimeState;
     type RequestedState = DiskStateRequested;
-    type ProducerArgs = (std::net::SocketAddr, Uuid);
+    type ProducerArgs = (std::net::SocketAddr, Uuid, bool);
     type Action = DiskAction;
 
     fn new(current: DiskRuntimeState) -> Self {
@@ -209,9 +211,9 @@ impl Simulatable for SimDisk {
         &mut self,
         args: Self::ProducerArgs,
     ) -> Result<(), Error> {
-        self.start_producer_server(args.0, args.1).await.map_err(|e| {
-            Error::internal_error(&format!("Setting producer server: {e}"))
-        })?;
+        self.start_producer_server(args.0, args.1, args.2).await.map_err(
+            |e| Error::internal_error(&format!("Setting producer server: {e}")),
+        )?;
         Ok(())
     }
 
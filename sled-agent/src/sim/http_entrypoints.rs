@@ -5,8 +5,9 @@
 //! HTTP entrypoint functions for the sled agent's exposed API
 
 use crate::params::{
-    DiskEnsureBody, InstanceEnsureBody, InstanceSerialConsoleData,
-    InstanceSerialConsoleRequest, VpcFirewallRulesEnsureBody,
+    DiskEnsureBody, InstanceEnsureBody, InstanceExternalIpsEnsureBody,
+    InstanceSerialConsoleData, InstanceSerialConsoleRequest,
+    VpcFirewallRulesEnsureBody,
 };
 use crate::serial::ByteOffset;
 use dropshot::endpoint;
@@ -34,6 +35,7 @@ type SledApiDescription = ApiDescription<Arc<SledAgent>>;
 pub fn api() -> SledApiDescription {
     fn register_endpoints(api: &mut SledApiDescription) -> Result<(), String> {
         api.register(instance_put)?;
+        api.register(instance_external_ips_put)?;
         api.register(instance_poke_post)?;
         api.register(disk_put)?;
         api.register(disk_poke_post)?;
@@ -71,11 +73,33 @@ async fn instance_put(
     let instance_id = path_params.into_inner().instance_id;
     let body_args = body.into_inner();
     Ok(HttpResponseOk(
-        sa.instance_ensure(instance_id, body_args.initial, body_args.target)
-            .await?,
+        sa.instance_ensure(
+            instance_id,
+            body_args.initial,
+            body_args.target,
+            body_args.migrate,
+        )
+        .await?,
     ))
 }
 
+#[endpoint {
+    method = PUT,
+    path = "/instances/{instance_id}/external-ips",
+}]
+async fn instance_external_ips_put(
+    rqctx: RequestContext<Arc<SledAgent>>,
+    path_params: Path<InstancePathParam>,
+    body: TypedBody<InstanceExternalIpsEnsureBody>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let sa = rqctx.context();
+    let instance_id = path_params.into_inner().instance_id;
+    let body_args = body.into_inner();
+    sa.instance_external_ips_ensure(instance_id, body_args.external_ips)
+        .await?;
+    Ok(HttpResponseUpdatedNoContent())
+}
+
 #[endpoint {
     method = POST,
     path = "/instances/{instance_id}/poke",
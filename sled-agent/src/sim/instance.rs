@@ -49,12 +49,34 @@ impl Simulatable for SimInstance {
         &mut self,
         target: &InstanceRuntimeStateRequested,
     ) -> Result<Option<InstanceAction>, Error> {
+        // A real Propolis server at the target of a migration pulls the
+        // instance's state from the source on its own.  Here, we pretend that
+        // this has started, and finish the migration (with the instance
+        // running) when the next transition is simulated.
+        if target.run_state == InstanceStateRequested::Migrating
+            && self.state.is_migration_target()
+            && self.state.current().run_state == InstanceState::Migrating
+            && self.state.desired().is_none()
+        {
+            self.state.transition(
+                InstanceState::Migrating,
+                Some(InstanceStateRequested::Running),
+            );
+            return Ok(None);
+        }
         self.state.request_transition(target)
     }
 
     fn execute_desired_transition(&mut self) -> Option<InstanceAction> {
         if matches!(self.state.current().run_state, InstanceState::Rebooting) {
             self.state.observe_transition(&PropolisInstanceState::Starting)
+        } else if self.state.current().run_state == InstanceState::Migrating
+            && !self.state.is_migration_target()
+        {
+            // The source of a migration keeps running until Nexus either
+            // destroys it, once the target is running, or asks it to keep
+            // running after abandoning the migration.
+            None
         } else if let Some(desired) = self.state.desired() {
             // These operations would typically be triggered via responses from
             // Propolis, but for a simulated sled agent, this does not exist.
@@ -168,6 +168,7 @@ impl Server {
                     .to_string_lossy()
                     .to_string(),
             },
+            metrics: None,
         };
         let dns_log = log.new(o!("kind" => "dns"));
         let zone = "control-plane.oxide.internal".to_string();
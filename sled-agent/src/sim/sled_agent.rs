@@ -6,8 +6,9 @@
 
 use crate::nexus::NexusClient;
 use crate::params::{
-    DiskStateRequested, InstanceHardware, InstanceRuntimeStateRequested,
-    InstanceSerialConsoleData,
+    DiskStateRequested, InstanceHardware, InstanceMigrateParams,
+    InstanceRuntimeStateRequested, InstanceSerialConsoleData,
+    InstanceStateRequested,
 };
 use crate::serial::ByteOffset;
 use futures::lock::Mutex;
@@ -15,6 +16,7 @@ use omicron_common::api::external::{Error, InstanceState, ResourceType};
 use omicron_common::api::internal::nexus::DiskRuntimeState;
 use omicron_common::api::internal::nexus::InstanceRuntimeState;
 use slog::Logger;
+use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -46,8 +48,12 @@ pub struct SledAgent {
     disks: Arc<SimCollection<SimDisk>>,
     storage: Mutex<Storage>,
     nexus_address: SocketAddr,
+    /// whether simulated disks' producer servers serve Prometheus metrics
+    prometheus: bool,
     pub nexus_client: Arc<NexusClient>,
     disk_id_to_region_ids: Mutex<HashMap<String, Vec<Uuid>>>,
+    /// external IP addresses of simulated instances, indexed by instance uuid
+    instance_external_ips: Mutex<HashMap<Uuid, Vec<IpAddr>>>,
 }
 
 fn extract_targets_from_volume_construction_request(
@@ -131,8 +137,10 @@ impl SledAgent {
                 storage_log,
             )),
             nexus_address,
+            prometheus: config.prometheus,
             nexus_client,
             disk_id_to_region_ids: Mutex::new(HashMap::new()),
+            instance_external_ips: Mutex::new(HashMap::new()),
         })
     }
 
@@ -202,6 +210,7 @@ impl SledAgent {
         instance_id: Uuid,
         initial_hardware: InstanceHardware,
         target: InstanceRuntimeStateRequested,
+        migrate: Option<InstanceMigrateParams>,
     ) -> Result<InstanceRuntimeState, Error> {
         // respond with a fake 500 level failure if asked to ensure an instance
         // with more than 16 CPUs.
@@ -212,6 +221,17 @@ impl SledAgent {
             ));
         };
 
+        // An instance can only be migrated to this sled from a Propolis
+        // server elsewhere.
+        if target.run_state == InstanceStateRequested::Migrating
+            && !self.instances.sim_contains(&instance_id).await
+            && migrate.is_none()
+        {
+            return Err(Error::invalid_request(
+                "cannot migrate instance without a source Propolis server",
+            ));
+        }
+
         for disk in &initial_hardware.disks {
             let initial_state = DiskRuntimeState {
                 disk_state: omicron_common::api::external::DiskState::Attached(
@@ -228,7 +248,10 @@ impl SledAgent {
             };
             self.disks.sim_ensure(&id, initial_state, target).await?;
             self.disks
-                .sim_ensure_producer(&id, (self.nexus_address, id))
+                .sim_ensure_producer(
+                    &id,
+                    (self.nexus_address, id, self.prometheus),
+                )
                 .await?;
         }
 
@@ -237,6 +260,11 @@ impl SledAgent {
             .sim_ensure(&instance_id, initial_hardware.runtime, target)
             .await?;
 
+        self.instance_external_ips
+            .lock()
+            .await
+            .insert(instance_id, initial_hardware.external_ips);
+
         for disk_request in &initial_hardware.disks {
             // disk_request.volume_construction_request is of type
             // propolis_client::instance_spec::VolumeConstructionRequest, where
@@ -255,16 +283,73 @@ impl SledAgent {
         Ok(instance_run_time_state)
     }
 
+    /// Replaces the external IP addresses of an instance, such as when a
+    /// Floating IP is attached to or detached from it.
+    pub async fn instance_external_ips_ensure(
+        &self,
+        instance_id: Uuid,
+        external_ips: Vec<IpAddr>,
+    ) -> Result<(), Error> {
+        if !self.instances.sim_contains(&instance_id).await {
+            return Err(Error::not_found_by_id(
+                ResourceType::Instance,
+                &instance_id,
+            ));
+        }
+        self.instance_external_ips
+            .lock()
+            .await
+            .insert(instance_id, external_ips);
+        Ok(())
+    }
+
+    /// Returns the external IP addresses most recently provided for an
+    /// instance, if any.
+    pub async fn instance_external_ips(
+        &self,
+        instance_id: Uuid,
+    ) -> Option<Vec<IpAddr>> {
+        self.instance_external_ips.lock().await.get(&instance_id).cloned()
+    }
+
     /// Idempotently ensures that the given API Disk (described by `api_disk`)
     /// is attached (or not) as specified.  This simulates disk attach and
     /// detach, similar to instance boot and halt.
+    ///
+    /// Disks may only be hot-plugged into instances which are running on
+    /// this sled.
     pub async fn disk_ensure(
         self: &Arc<Self>,
         disk_id: Uuid,
         initial_state: DiskRuntimeState,
         target: DiskStateRequested,
     ) -> Result<DiskRuntimeState, Error> {
-        self.disks.sim_ensure(&disk_id, initial_state, target).await
+        if let DiskStateRequested::Attached(instance_id) = target {
+            let instance_state = self
+                .instances
+                .sim_get_current_state(&instance_id)
+                .await?
+                .run_state;
+            if instance_state != InstanceState::Running {
+                return Err(Error::invalid_request(&format!(
+                    "cannot hot-plug disk into instance in {} state",
+                    instance_state,
+                )));
+            }
+        }
+
+        let is_new = !self.disks.sim_contains(&disk_id).await;
+        let disk_state =
+            self.disks.sim_ensure(&disk_id, initial_state, target).await?;
+        if is_new {
+            self.disks
+                .sim_ensure_producer(
+                    &disk_id,
+                    (self.nexus_address, disk_id, self.prometheus),
+                )
+                .await?;
+        }
+        Ok(disk_state)
     }
 
     pub async fn instance_count(&self) -> usize {
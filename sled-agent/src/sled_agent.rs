@@ -30,7 +30,7 @@ use omicron_common::backoff::{
     retry_notify, retry_policy_internal_service_aggressive, BackoffError,
 };
 use slog::Logger;
-use std::net::{Ipv6Addr, SocketAddrV6};
+use std::net::{IpAddr, Ipv6Addr, SocketAddrV6};
 use std::process::Command;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -87,12 +87,20 @@ pub enum Error {
 
     #[error("Error resolving DNS name: {0}")]
     ResolveError(#[from] dns_service_client::multiclient::ResolveError),
+
+    #[error("Cannot attach or detach disk {0}: hot-plug is not supported")]
+    DiskHotplugUnsupported(Uuid),
 }
 
 impl From<Error> for omicron_common::api::external::Error {
     fn from(err: Error) -> Self {
-        omicron_common::api::external::Error::InternalError {
-            internal_message: err.to_string(),
+        match err {
+            Error::DiskHotplugUnsupported(_) => {
+                omicron_common::api::external::Error::unavail(&err.to_string())
+            }
+            _ => omicron_common::api::external::Error::InternalError {
+                internal_message: err.to_string(),
+            },
         }
     }
 }
@@ -518,14 +526,16 @@ impl SledAgent {
     /// Idempotently ensures that the given virtual disk is attached (or not) as
     /// specified.
     ///
-    /// NOTE: Not yet implemented.
+    /// TODO-completeness: Propolis can't yet add or remove disks from a
+    /// running VMM, so this always fails. Disks attached to a stopped instance
+    /// are handed to Propolis when the instance starts.
     pub async fn disk_ensure(
         &self,
-        _disk_id: Uuid,
+        disk_id: Uuid,
         _initial_state: DiskRuntimeState,
         _target: DiskStateRequested,
     ) -> Result<DiskRuntimeState, Error> {
-        todo!("Disk attachment not yet implemented");
+        Err(Error::DiskHotplugUnsupported(disk_id))
     }
 
     /// Downloads and applies an artifact.
@@ -573,6 +583,19 @@ impl SledAgent {
             .map_err(Error::from)
     }
 
+    /// Replaces the external IP addresses of an instance.
+    pub async fn instance_external_ips_ensure(
+        &self,
+        instance_id: Uuid,
+        external_ips: Vec<IpAddr>,
+    ) -> Result<(), Error> {
+        self.inner
+            .instances
+            .instance_external_ips_ensure(instance_id, external_ips)
+            .await
+            .map_err(Error::from)
+    }
+
     pub async fn firewall_rules_ensure(
         &self,
         _vpc_id: Uuid,